@@ -0,0 +1,91 @@
+//! Emit benchmark: peak heap used while emitting generated pipelines.
+//!
+//! Run with: cargo bench --bench emit
+//!
+//! Compares `emit_to` (builds the whole JSON document first) against
+//! `emit_streaming_to` (serializes one task at a time). The pipeline itself
+//! is built before measuring, so the numbers are the extra memory emit needs.
+//! What remains for the streaming path is validation state (task names and
+//! cycle-detection bookkeeping), a few pointers per task.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use sykli::{K8sOptions, Pipeline};
+
+struct CountingAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn build(task_count: usize) -> Pipeline {
+    let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+        memory: Some("2Gi".into()),
+        ..Default::default()
+    });
+    let src = p.dir(".");
+    for i in 0..task_count {
+        let _ = p
+            .task(&format!("test-{}", i))
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .env("SHARD", &i.to_string())
+            .run("cargo test");
+    }
+    p
+}
+
+/// Returns (extra peak bytes, elapsed ms) for one emit.
+fn measure(p: &Pipeline, emit: fn(&Pipeline, &mut io::Sink) -> io::Result<()>) -> (usize, u128) {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let start = Instant::now();
+    emit(p, &mut io::sink()).expect("emit failed");
+    let elapsed = start.elapsed().as_millis();
+    (PEAK.load(Ordering::Relaxed) - baseline, elapsed)
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>14} {:>10} {:>14} {:>10}",
+        "tasks", "emit_to peak", "ms", "streaming peak", "ms"
+    );
+    for &count in &[1_000, 5_000, 20_000] {
+        let p = build(count);
+        let (full, full_ms) = measure(&p, |p, w| p.emit_to(w));
+        let (streamed, streamed_ms) = measure(&p, |p, w| p.emit_streaming_to(w));
+        println!(
+            "{:>8} {:>14} {:>10} {:>14} {:>10}",
+            count, full, full_ms, streamed, streamed_ms
+        );
+        assert!(
+            streamed * 5 < full,
+            "streaming emit used {} bytes vs {} for emit_to at {} tasks",
+            streamed,
+            full,
+            count
+        );
+    }
+}
@@ -60,12 +60,12 @@ fn main() {
     // === ARTIFACT PASSING ===
     // input_from automatically:
     // 1. Adds dependency on "build"
-    // 2. Makes the artifact available at "./app"
+    // 2. Makes the artifact available at "/work/app"
     p.task("package")
         .container("docker:24")
         .mount_cwd()
         .run("docker build -t myapp:latest .")
-        .input_from("build", "binary", "./app");
+        .input_from("build", "binary", "/work/app");
 
     // === CHAIN ===
     // Sequential dependencies using task names
@@ -0,0 +1,1085 @@
+//! Condition expressions: parsing, evaluation and display.
+//!
+//! Everything that needs to understand a `when` condition goes through this
+//! module, so explain, validation and exporters can't drift apart:
+//!
+//! ```rust
+//! use sykli::condition::{self, Decision};
+//! use sykli::ExplainContext;
+//!
+//! let expr = condition::parse("(branch == 'main') || (tag != '')").unwrap();
+//! let ctx = ExplainContext::default().with_branch("feature/x");
+//! assert!(matches!(expr.evaluate(&ctx), Decision::Skip(_)));
+//! ```
+//!
+//! The grammar is the one the [`Condition`](crate::Condition) builder emits:
+//!
+//! ```text
+//! expr       := and ( "||" and )*
+//! and        := unary ( "&&" unary )*
+//! unary      := "!" unary | "(" expr ")" | comparison
+//! comparison := ident ( "==" | "!=" | "matches" ) ( 'string' | true | false )
+//!             | ident ":" word
+//! ident      := [A-Za-z_][A-Za-z0-9_.]*
+//! word       := any characters but whitespace, "(", ")", "&" and "|"
+//! ```
+//!
+//! Inside a `'string'`, `\'` stands for a quote and `\\` for a backslash;
+//! [`quote`] writes a value that way.
+//!
+//! `field:value` is the legacy shorthand every SDK accepts, e.g.
+//! `branch:main`; it means `field == 'value'`.
+//!
+//! `matches` takes a glob, as the engine reads it: `*` matches any run of
+//! characters but `/`, `**` any run including `/`, and `?` exactly one
+//! character; see [`glob_matches`].
+//!
+//! `changed` is the list of files changed by the commit, not a single value:
+//! `changed matches 'web/**'` holds if any changed file matches,
+//! `changed == 'x'` if `x` changed, and `changed != 'x'` if it didn't.
+
+use std::fmt;
+
+use crate::validate::{EmitError, Issues};
+use crate::{ExplainContext, Pipeline};
+
+/// Identifiers the engine resolves, besides `env.NAME`.
+pub const KNOWN_FIELDS: &[&str] = &[
+    "branch",
+    "tag",
+    "event",
+    "ci",
+    "pr_number",
+    "platform",
+    "runner",
+    "pr_target",
+    "changed",
+    "cron",
+];
+
+/// Whether the engine resolves `field`: one of [`KNOWN_FIELDS`] or `env.NAME`.
+pub fn is_known_field(field: &str) -> bool {
+    KNOWN_FIELDS.contains(&field)
+        || field
+            .strip_prefix("env.")
+            .is_some_and(|name| !name.is_empty())
+}
+
+/// A parsed condition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// `field op value`
+    Compare(Comparison),
+    /// `!expr`
+    Not(Box<Expr>),
+    /// `lhs && rhs`
+    And(Box<Expr>, Box<Expr>),
+    /// `lhs || rhs`
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A single `field op value` comparison.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    /// Context field, e.g. `branch` or `ci`.
+    pub field: String,
+    /// Comparison operator.
+    pub op: Op,
+    /// Right-hand side.
+    pub value: Literal,
+}
+
+/// Comparison operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `matches` (glob)
+    Matches,
+}
+
+impl Op {
+    /// Returns the operator as written in conditions.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Matches => "matches",
+        }
+    }
+}
+
+/// A literal value in a comparison.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Literal {
+    /// `'quoted string'`
+    Str(String),
+    /// `true` / `false`
+    Bool(bool),
+}
+
+/// Quotes `value` as a condition string literal, escaping `'` and `\`.
+///
+/// ```rust
+/// assert_eq!(sykli::condition::quote(r"it's a\b"), r"'it\'s a\\b'");
+/// ```
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Str(s) => write!(f, "{}", quote(s)),
+            Literal::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.field, self.op.as_str(), self.value)
+    }
+}
+
+/// Renders in the same shape the `Condition` builder produces.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Compare(c) => write!(f, "{}", c),
+            Expr::Not(e) => write!(f, "!({})", e),
+            Expr::And(l, r) => write!(f, "({}) && ({})", l, r),
+            Expr::Or(l, r) => write!(f, "({}) || ({})", l, r),
+        }
+    }
+}
+
+/// Error returned by [`parse`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// Byte offset of the problem in the input.
+    pub offset: usize,
+    /// What went wrong.
+    pub message: String,
+    /// Tokens that would have been accepted at `offset`.
+    pub expected: &'static [&'static str],
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a condition string.
+pub fn parse(input: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser { input, pos: 0 };
+    let expr = parser.or()?;
+    parser.skip_ws();
+    if parser.pos < input.len() {
+        return Err(parser.error("unexpected trailing input", &["&&", "||"]));
+    }
+    Ok(expr)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str, expected: &'static [&'static str]) -> ParseError {
+        ParseError {
+            offset: self.pos,
+            message: message.to_string(),
+            expected,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.and()?;
+        while self.eat("||") {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        while self.eat("&&") {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        self.skip_ws();
+        if self.rest().starts_with('!') && !self.rest().starts_with("!=") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err(self.error("expected ')'", &[")"]));
+            }
+            return Ok(expr);
+        }
+        self.comparison().map(Expr::Compare)
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_ws();
+        let start = self.pos;
+        let input = self.input;
+        let rest = &input[start..];
+        let mut chars = rest.char_indices();
+        match chars.next() {
+            Some((_, c)) if c.is_ascii_alphabetic() || c == '_' => {}
+            _ => return None,
+        }
+        let len = chars
+            .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .map_or(rest.len(), |(i, _)| i);
+        self.pos += len;
+        Some(&input[start..self.pos])
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, ParseError> {
+        let Some(field) = self.ident() else {
+            return Err(self.error("expected identifier", &["identifier", "!", "("]));
+        };
+        let field = field.to_string();
+
+        if self.rest().starts_with(':') {
+            self.pos += 1;
+            let word = self
+                .rest()
+                .find(|c: char| c.is_whitespace() || "()&|".contains(c))
+                .unwrap_or(self.rest().len());
+            if word == 0 {
+                return Err(self.error("expected value after ':'", &["value"]));
+            }
+            let value = self.rest()[..word].to_string();
+            self.pos += word;
+            return Ok(Comparison {
+                field,
+                op: Op::Eq,
+                value: Literal::Str(value),
+            });
+        }
+
+        let op = if self.eat("==") {
+            Op::Eq
+        } else if self.eat("!=") {
+            Op::Ne
+        } else if self.ident() == Some("matches") {
+            Op::Matches
+        } else {
+            return Err(self.error("expected '==', '!=' or 'matches'", &["==", "!=", "matches"]));
+        };
+
+        self.skip_ws();
+        let open = self.pos;
+        let value = if self.eat("'") {
+            let mut value = String::new();
+            let mut chars = self.rest().char_indices();
+            loop {
+                match chars.next() {
+                    Some((i, '\'')) => {
+                        self.pos += i + 1;
+                        break;
+                    }
+                    Some((i, '\\')) => match chars.next() {
+                        Some((_, c @ ('\'' | '\\'))) => value.push(c),
+                        _ => {
+                            self.pos += i;
+                            return Err(self.error("invalid escape", &["\\'", "\\\\"]));
+                        }
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => {
+                        self.pos = open;
+                        return Err(self.error("unterminated string", &["'"]));
+                    }
+                }
+            }
+            Literal::Str(value)
+        } else {
+            let start = self.pos;
+            match self.ident() {
+                Some("true") => Literal::Bool(true),
+                Some("false") => Literal::Bool(false),
+                _ => {
+                    self.pos = start;
+                    return Err(self.error(
+                        "expected quoted string, true or false",
+                        &["'string'", "true", "false"],
+                    ));
+                }
+            }
+        };
+
+        Ok(Comparison { field, op, value })
+    }
+}
+
+/// Outcome of evaluating a condition against a context.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decision {
+    /// The condition holds; the task runs.
+    Run,
+    /// The condition doesn't hold; the task is skipped, for this reason.
+    Skip(String),
+    /// The condition can't be decided from the context.
+    Unknown(String),
+}
+
+impl Expr {
+    /// Evaluates the condition against an explain context.
+    ///
+    /// A condition using an identifier the engine doesn't resolve is
+    /// [`Decision::Unknown`]. `pr_number`, `platform`, `runner` and
+    /// `env.NAME` are looked up in [`ExplainContext::vars`].
+    pub fn evaluate(&self, ctx: &ExplainContext) -> Decision {
+        if let Some(field) = self.fields().into_iter().find(|f| !is_known_field(f)) {
+            return Decision::Unknown(format!("unknown identifier {}", field));
+        }
+        self.decide(&Lookup {
+            value: &|field| match field {
+                "branch" => Some(Literal::Str(ctx.branch.clone())),
+                "tag" => Some(Literal::Str(ctx.tag.clone())),
+                "event" => Some(Literal::Str(ctx.event.clone())),
+                "pr_target" => Some(Literal::Str(ctx.pr_target.clone())),
+                "cron" => Some(Literal::Str(ctx.cron.clone())),
+                "ci" => Some(Literal::Bool(ctx.ci)),
+                _ => {
+                    let name = field.strip_prefix("env.").unwrap_or(field);
+                    ctx.vars.get(name).map(|v| Literal::Str(v.clone()))
+                }
+            },
+            changed: Some(&ctx.changed_files),
+            globs: true,
+        })
+    }
+
+    /// Returns false if no context can make the condition true, e.g.
+    /// `branch == 'main' && branch == 'dev'`.
+    pub fn is_satisfiable(&self) -> bool {
+        // Every field can take each value it is compared against, or something else.
+        let mut fields: Vec<(&str, Vec<Literal>)> = Vec::new();
+        self.collect_values(&mut fields);
+        for (_, values) in &mut fields {
+            if values.iter().all(|v| matches!(v, Literal::Bool(_))) {
+                *values = vec![Literal::Bool(true), Literal::Bool(false)];
+            } else {
+                values.push(Literal::Str("\u{0}other".to_string()));
+            }
+        }
+
+        let worlds = fields
+            .iter()
+            .try_fold(1usize, |n, (_, values)| n.checked_mul(values.len()));
+        if worlds.map_or(true, |n| n > 4096) {
+            return true;
+        }
+        let mut choice = vec![0; fields.len()];
+        loop {
+            let lookup = |field: &str| {
+                fields
+                    .iter()
+                    .zip(&choice)
+                    .find(|((name, _), _)| *name == field)
+                    .map(|((_, values), &i)| values[i].clone())
+            };
+            // Sampled values can't stand in for every string a glob matches,
+            // so `matches` stays unknown here
+            if !matches!(
+                self.decide(&Lookup {
+                    value: &lookup,
+                    changed: None,
+                    globs: false
+                }),
+                Decision::Skip(_)
+            ) {
+                return true;
+            }
+            // Next combination
+            let mut i = 0;
+            loop {
+                if i == fields.len() {
+                    return false;
+                }
+                choice[i] += 1;
+                if choice[i] < fields[i].1.len() {
+                    break;
+                }
+                choice[i] = 0;
+                i += 1;
+            }
+        }
+    }
+
+    /// Fields compared anywhere in the condition, in order of appearance.
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Expr::Compare(c) => vec![c.field.as_str()],
+            Expr::Not(e) => e.fields(),
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                let mut fields = l.fields();
+                fields.extend(r.fields());
+                fields
+            }
+        }
+    }
+
+    fn collect_values<'a>(&'a self, fields: &mut Vec<(&'a str, Vec<Literal>)>) {
+        match self {
+            Expr::Compare(c) => {
+                let idx = match fields.iter().position(|(f, _)| *f == c.field) {
+                    Some(idx) => idx,
+                    None => {
+                        fields.push((&c.field, Vec::new()));
+                        fields.len() - 1
+                    }
+                };
+                if !fields[idx].1.contains(&c.value) {
+                    fields[idx].1.push(c.value.clone());
+                }
+            }
+            Expr::Not(e) => e.collect_values(fields),
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                l.collect_values(fields);
+                r.collect_values(fields);
+            }
+        }
+    }
+
+    /// Three-valued evaluation: unknown only propagates when it could change the result.
+    fn decide(&self, lookup: &Lookup) -> Decision {
+        match self {
+            Expr::Compare(c) => c.decide(lookup),
+            Expr::Not(e) => match e.decide(lookup) {
+                Decision::Run => Decision::Skip(format!("{} holds", e)),
+                Decision::Skip(_) => Decision::Run,
+                unknown => unknown,
+            },
+            Expr::And(l, r) => match (l.decide(lookup), r.decide(lookup)) {
+                (skip @ Decision::Skip(_), _) | (_, skip @ Decision::Skip(_)) => skip,
+                (unknown @ Decision::Unknown(_), _) | (_, unknown @ Decision::Unknown(_)) => {
+                    unknown
+                }
+                _ => Decision::Run,
+            },
+            Expr::Or(l, r) => match (l.decide(lookup), r.decide(lookup)) {
+                (Decision::Run, _) | (_, Decision::Run) => Decision::Run,
+                (unknown @ Decision::Unknown(_), _) | (_, unknown @ Decision::Unknown(_)) => {
+                    unknown
+                }
+                (Decision::Skip(a), Decision::Skip(b)) => {
+                    Decision::Skip(format!("{} and {}", a, b))
+                }
+            },
+        }
+    }
+}
+
+/// Field values for [`Expr::decide`].
+struct Lookup<'a> {
+    value: &'a dyn Fn(&str) -> Option<Literal>,
+    // Files for `changed`, or None to leave it unknown
+    changed: Option<&'a [String]>,
+    // Whether `matches` is evaluated or left unknown
+    globs: bool,
+}
+
+impl Comparison {
+    fn decide(&self, lookup: &Lookup) -> Decision {
+        if self.field == "changed" {
+            return self.decide_changed(lookup);
+        }
+        let Some(actual) = (lookup.value)(&self.field) else {
+            return Decision::Unknown(format!("{} not set in context", self.field));
+        };
+        let holds = match (self.op, &actual, &self.value) {
+            (Op::Matches, _, _) if !lookup.globs => {
+                return Decision::Unknown(format!("{} matches is not evaluated", self.field))
+            }
+            (Op::Matches, Literal::Str(a), Literal::Str(pattern)) => glob_matches(pattern, a),
+            (Op::Eq, Literal::Str(a), Literal::Str(b)) => a == b,
+            (Op::Ne, Literal::Str(a), Literal::Str(b)) => a != b,
+            (Op::Eq, Literal::Bool(a), Literal::Bool(b)) => a == b,
+            (Op::Ne, Literal::Bool(a), Literal::Bool(b)) => a != b,
+            _ => return Decision::Unknown(format!("{} compared with the wrong type", self.field)),
+        };
+        if holds {
+            return Decision::Run;
+        }
+
+        let reason = match (self.field.as_str(), self.op, &actual) {
+            ("ci", _, _) => "not running in CI".to_string(),
+            ("tag", Op::Ne, Literal::Str(a)) if a.is_empty() => "no tag present".to_string(),
+            (field, Op::Matches, actual) => {
+                format!("{} {} does not match {}", field, actual, self.value)
+            }
+            (field, Op::Eq, actual) => format!("{} is {}, not {}", field, actual, self.value),
+            (field, _, actual) => format!("{} is {}", field, actual),
+        };
+        Decision::Skip(reason)
+    }
+
+    /// `changed` compares against every changed file rather than one value.
+    fn decide_changed(&self, lookup: &Lookup) -> Decision {
+        let Some(files) = lookup.changed else {
+            return Decision::Unknown("changed not set in context".to_string());
+        };
+        let Literal::Str(ref value) = self.value else {
+            return Decision::Unknown("changed compared with the wrong type".to_string());
+        };
+        let (holds, reason) = match self.op {
+            Op::Matches if !lookup.globs => {
+                return Decision::Unknown("changed matches is not evaluated".to_string())
+            }
+            Op::Matches => (
+                files.iter().any(|f| glob_matches(value, f)),
+                format!("no changed file matches {}", self.value),
+            ),
+            Op::Eq => (files.contains(value), format!("{} not changed", self.value)),
+            Op::Ne => (!files.contains(value), format!("{} changed", self.value)),
+        };
+        if holds {
+            Decision::Run
+        } else {
+            Decision::Skip(reason)
+        }
+    }
+}
+
+impl Pipeline {
+    /// Skips emit-time checking of `when()` strings, for engines that
+    /// resolve identifiers beyond [`KNOWN_FIELDS`] and `env.NAME`.
+    pub fn allow_unchecked_conditions(&mut self) -> &mut Self {
+        self.unchecked_conditions = true;
+        self
+    }
+
+    /// Checks `when()` and `unless()` strings against the grammar and known
+    /// identifiers.
+    ///
+    /// Builder conditions are generated by the SDK, and [`Condition::var`]
+    /// exists for custom identifiers, so only raw strings are checked.
+    ///
+    /// [`Condition::var`]: crate::Condition::var
+    pub(crate) fn check_conditions(&self, issues: &mut Issues) {
+        if self.unchecked_conditions {
+            return;
+        }
+        for t in &self.tasks {
+            let when = t.condition.iter().map(|c| ("when", c));
+            for (field, cond) in when.chain(t.unless.iter().map(|c| ("unless", c))) {
+                let mut invalid = |message: String| {
+                    issues.error(EmitError::InvalidCondition {
+                        task: t.name.clone(),
+                        field,
+                        condition: cond.clone(),
+                        message,
+                    })
+                };
+                let expr = match parse(cond) {
+                    Ok(expr) => expr,
+                    Err(err) => {
+                        let token = cond[err.offset..].split_whitespace().next().unwrap_or("");
+                        if token.is_empty() {
+                            invalid(format!("{} at end of input", err.message));
+                        } else {
+                            invalid(format!("{} at {:?}", err.message, token));
+                        }
+                        continue;
+                    }
+                };
+                for field in expr.fields() {
+                    if is_known_field(field) {
+                        continue;
+                    }
+                    // Only suggest near-misses, not any identifier that scores well
+                    let suggestion = crate::suggest_task_name(field, KNOWN_FIELDS)
+                        .filter(|known| known.len().abs_diff(field.len()) <= 1);
+                    match suggestion {
+                        Some(known) => invalid(format!(
+                            "unknown identifier {:?} (did you mean {:?}?)",
+                            field, known
+                        )),
+                        None => invalid(format!("unknown identifier {:?}", field)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if `text` matches the glob `pattern`, as `matches` does.
+///
+/// Same rules as the engine: `*` matches any run of characters but `/`,
+/// including the empty one; `**` matches any run, `/` included; `?` matches
+/// exactly one character. Everything else matches itself.
+///
+/// ```rust
+/// use sykli::condition::glob_matches;
+///
+/// assert!(glob_matches("release/*", "release/1.2"));
+/// assert!(!glob_matches("release/*", "release/1.2/hotfix"));
+/// assert!(glob_matches("release/**", "release/1.2/hotfix"));
+/// assert!(glob_matches("v?.*", "v1.2.3"));
+/// assert!(!glob_matches("v*", ""));
+/// ```
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    // reachable[t]: the pattern read so far matches the first t characters
+    let mut reachable = vec![false; text.len() + 1];
+    reachable[0] = true;
+    let mut pattern = pattern.chars().peekable();
+    while let Some(c) = pattern.next() {
+        let mut next = vec![false; text.len() + 1];
+        if c == '*' {
+            let any = pattern.next_if_eq(&'*').is_some();
+            for t in 0..=text.len() {
+                next[t] = reachable[t] || (t > 0 && next[t - 1] && (any || text[t - 1] != '/'));
+            }
+        } else {
+            for t in 1..=text.len() {
+                next[t] = reachable[t - 1] && (c == '?' || c == text[t - 1]);
+            }
+        }
+        reachable = next;
+    }
+    reachable[text.len()]
+}
+
+/// Translates a glob into an anchored regex with the same rules as
+/// [`glob_matches`], for exporters whose targets match regexes.
+pub(crate) fn glob_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.next_if_eq(&'*').is_some() => re.push_str(".*"),
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push('.'),
+            c if "\\.+()[]{}|^$".contains(c) => {
+                re.push('\\');
+                re.push(c);
+            }
+            c => re.push(c),
+        }
+    }
+    re.push('$');
+    re
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Condition, Pipeline};
+
+    fn ctx(branch: &str, tag: &str, event: &str, ci: bool) -> ExplainContext {
+        ExplainContext {
+            branch: branch.to_string(),
+            tag: tag.to_string(),
+            event: event.to_string(),
+            ci,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_builder_output_round_trips() {
+        let conditions = [
+            Condition::branch("main"),
+            Condition::branch("feature/*"),
+            Condition::tag(""),
+            Condition::tag("v*"),
+            Condition::event("push"),
+            Condition::in_ci(),
+            Condition::negate(Condition::branch("wip/*")),
+            Condition::branch("main").or(Condition::tag("v*")),
+            Condition::branch("main")
+                .and(Condition::in_ci())
+                .or(Condition::negate(Condition::event("pull_request"))),
+        ];
+        for c in conditions {
+            let s = c.to_string();
+            assert_eq!(parse(&s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr = parse("branch == 'a' || branch == 'b' && !ci == true").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(branch == 'a') || ((branch == 'b') && (!(ci == true)))"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("branch = 'main'", 7, "expected '==', '!=' or 'matches'"),
+            ("branch == 'main", 10, "unterminated string"),
+            (r"branch == 'a\b'", 12, "invalid escape"),
+            (r"branch == 'a\'", 10, "unterminated string"),
+            ("(branch == 'main'", 17, "expected ')'"),
+            (
+                "branch == main",
+                10,
+                "expected quoted string, true or false",
+            ),
+            ("branch == 'main' tag", 17, "unexpected trailing input"),
+            ("", 0, "expected identifier"),
+            ("branch: main", 7, "expected value after ':'"),
+        ];
+        for (input, offset, message) in cases {
+            let err = parse(input).unwrap_err();
+            assert_eq!(
+                (err.offset, err.message.as_str()),
+                (offset, message),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_legacy_shorthand() {
+        assert_eq!(
+            parse("branch:main").unwrap().to_string(),
+            "branch == 'main'"
+        );
+        assert_eq!(
+            parse("branch:release/v1 || (tag:v1.0)")
+                .unwrap()
+                .to_string(),
+            "(branch == 'release/v1') || (tag == 'v1.0')"
+        );
+        let c = ctx("dev", "", "push", false);
+        assert_eq!(
+            parse("branch:main").unwrap().evaluate(&c),
+            Decision::Skip("branch is 'dev', not 'main'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_satisfiability() {
+        let satisfiable = [
+            "branch == 'main'",
+            "branch == 'main' || branch == 'dev'",
+            "branch != 'main' && branch != 'dev'",
+            "ci == true && tag != ''",
+            "!(ci == true)",
+            "branch matches 'release/*' && branch == 'main'",
+        ];
+        for s in satisfiable {
+            assert!(parse(s).unwrap().is_satisfiable(), "{}", s);
+        }
+
+        let unsatisfiable = [
+            "branch == 'main' && branch == 'dev'",
+            "ci == true && ci == false",
+            "(tag != '') && !(tag != '')",
+            "event == 'push' && (event == 'schedule' || event == 'pull_request')",
+        ];
+        for s in unsatisfiable {
+            assert!(!parse(s).unwrap().is_satisfiable(), "{}", s);
+        }
+    }
+
+    /// Every (condition, context) pair must get the same answer from explain
+    /// and from the condition module.
+    #[test]
+    fn test_explain_agrees_with_evaluate() {
+        let conditions = [
+            "branch == 'main'",
+            "branch != 'main'",
+            "tag != ''",
+            "tag == 'v1.0.0'",
+            "event == 'push'",
+            "ci == true",
+            "(branch == 'main') || (tag != '')",
+            "(branch == 'main') && (ci == true)",
+            "!(branch == 'main')",
+            "branch matches 'release/*'",
+            "env.DEPLOY == 'yes'",
+        ];
+        let contexts = [
+            ctx("main", "", "push", true),
+            ctx("dev", "v1.0.0", "push", false),
+            ctx("", "", "", false),
+        ];
+
+        for cond in conditions {
+            for c in &contexts {
+                let mut p = Pipeline::new();
+                let _ = p.task("t").run("true").when(cond);
+                let mut buf = Vec::new();
+                p.explain_to(&mut buf, Some(c));
+                let out = String::from_utf8(buf).unwrap();
+
+                let expected = match parse(cond).unwrap().evaluate(c) {
+                    Decision::Skip(reason) => Some(reason),
+                    _ => None,
+                };
+                let shown = out
+                    .split_once("[SKIPPED: ")
+                    .map(|(_, rest)| rest.split_once(']').unwrap().0.to_string());
+                assert_eq!(shown, expected, "{} on branch={:?}", cond, c.branch);
+            }
+        }
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        let cases = [
+            ("v*", "v1.2.3", true),
+            ("v*", "", false),
+            ("*", "", true),
+            ("release/*", "release/1.2", true),
+            ("release/*", "release", false),
+            ("feature/*", "feature/a/b", false),
+            ("feature/**", "feature/a/b", true),
+            ("feature/**", "feature/", true),
+            ("web/**/*.ts", "web/src/app/main.ts", true),
+            ("web/*.ts", "web/src/main.ts", false),
+            ("*", "a/b", false),
+            ("**", "a/b", true),
+            ("a?c", "a/c", true),
+            ("v?.?", "v1.2", true),
+            ("v?.?", "v1.23", false),
+            ("*-rc*", "v1.0-rc1", true),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("main", "main", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                glob_matches(pattern, text),
+                expected,
+                "{} ~ {}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_glob_regex() {
+        assert_eq!(glob_regex("release/*"), "^release/[^/]*$");
+        assert_eq!(glob_regex("web/**"), "^web/.*$");
+        assert_eq!(glob_regex("v?.*"), "^v.\\.[^/]*$");
+    }
+
+    #[test]
+    fn test_evaluate_globs_and_events() {
+        let cases = [
+            (
+                Condition::tag("v*"),
+                ctx("main", "v1.2.3", "push", true),
+                None,
+            ),
+            (
+                Condition::tag("v*"),
+                ctx("main", "", "push", true),
+                Some("tag '' does not match 'v*'"),
+            ),
+            (
+                Condition::tag("v?.*"),
+                ctx("main", "release-1", "push", true),
+                Some("tag 'release-1' does not match 'v?.*'"),
+            ),
+            (
+                Condition::branch("feature/*"),
+                ctx("feature/login", "", "push", false),
+                None,
+            ),
+            (
+                Condition::branch("feature/*"),
+                ctx("main", "", "push", false),
+                Some("branch 'main' does not match 'feature/*'"),
+            ),
+            (
+                Condition::branch("release/*.?"),
+                ctx("release/1.2", "", "", false),
+                None,
+            ),
+            (
+                Condition::event("push"),
+                ctx("main", "", "push", false),
+                None,
+            ),
+            (
+                Condition::event("pull_request"),
+                ctx("main", "", "push", false),
+                Some("event is 'push', not 'pull_request'"),
+            ),
+            (
+                Condition::negate(Condition::branch("wip/*")),
+                ctx("wip/x", "", "push", false),
+                Some("branch matches 'wip/*' holds"),
+            ),
+            (
+                Condition::negate(Condition::branch("wip/*")),
+                ctx("main", "", "", false),
+                None,
+            ),
+            (
+                Condition::branch("main").or(Condition::tag("v*")),
+                ctx("dev", "v2.0.0", "push", true),
+                None,
+            ),
+            (
+                Condition::branch("release/*").and(Condition::event("push")),
+                ctx("release/1", "", "schedule", true),
+                Some("event is 'schedule', not 'push'"),
+            ),
+        ];
+
+        for (cond, c, expected) in cases {
+            let mut p = Pipeline::new();
+            let _ = p.task("t").run("true").when_cond(cond.clone());
+            let mut buf = Vec::new();
+            p.explain_to(&mut buf, Some(&c));
+            let out = String::from_utf8(buf).unwrap();
+
+            let shown = out
+                .split_once("[SKIPPED: ")
+                .map(|(_, rest)| rest.split_once(']').unwrap().0);
+            assert_eq!(shown, expected, "{} on {:?}/{:?}", cond, c.branch, c.tag);
+        }
+    }
+
+    #[test]
+    fn test_paths_changed() {
+        let changed = |files: &[&str]| ExplainContext {
+            changed_files: files.iter().map(|f| f.to_string()).collect(),
+            ..Default::default()
+        };
+        let web = Condition::paths_changed(&["web/**", "package.json"]);
+        let decide =
+            |c: &Condition, ctx: &ExplainContext| parse(&c.to_string()).unwrap().evaluate(ctx);
+
+        assert_eq!(decide(&web, &changed(&["web/src/app.ts"])), Decision::Run);
+        assert_eq!(
+            decide(&web, &changed(&["README.md", "package.json"])),
+            Decision::Run
+        );
+        assert_eq!(
+            decide(&web, &changed(&["api/main.go"])),
+            Decision::Skip(
+                "no changed file matches 'web/**' and no changed file matches 'package.json'"
+                    .to_string()
+            )
+        );
+        assert!(matches!(decide(&web, &changed(&[])), Decision::Skip(_)));
+
+        let not_web = Condition::negate(Condition::paths_changed(&["web/**"]));
+        assert_eq!(decide(&not_web, &changed(&["api/main.go"])), Decision::Run);
+        assert_eq!(decide(&not_web, &changed(&[])), Decision::Run);
+        assert!(matches!(
+            decide(&not_web, &changed(&["web/index.html"])),
+            Decision::Skip(_)
+        ));
+
+        assert_eq!(decide(&web, &changed(&["package.json"])), Decision::Run);
+        assert!(parse("changed == 'package.json'").unwrap().is_satisfiable());
+    }
+
+    fn emit_err(cond: &str) -> String {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh").when(cond);
+        p.emit_to(&mut Vec::new()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_emit_rejects_bad_conditions() {
+        assert_eq!(
+            emit_err("branch = 'main'"),
+            "task \"deploy\": invalid condition \"branch = 'main'\": \
+             expected '==', '!=' or 'matches' at \"=\""
+        );
+        assert_eq!(
+            emit_err("brach == 'main'"),
+            "task \"deploy\": invalid condition \"brach == 'main'\": \
+             unknown identifier \"brach\" (did you mean \"branch\"?)"
+        );
+        assert_eq!(
+            emit_err("(tag != '') && stage == 'prod'"),
+            "task \"deploy\": invalid condition \"(tag != '') && stage == 'prod'\": \
+             unknown identifier \"stage\""
+        );
+        assert!(emit_err("branch == 'main' &&").ends_with("expected identifier at end of input"));
+    }
+
+    #[test]
+    fn test_emit_accepts_known_conditions() {
+        for cond in [
+            "branch == 'main'",
+            "(tag matches 'v*') || (event == 'pull_request')",
+            "!(ci == true) && env.DEPLOY == 'yes'",
+            "pr_target == 'main' && changed matches 'web/**'",
+            "branch:main",
+        ] {
+            let mut p = Pipeline::new();
+            let _ = p.task("t").run("true").when(cond);
+            assert!(p.emit_to(&mut Vec::new()).is_ok(), "{}", cond);
+        }
+
+        let mut p = Pipeline::new();
+        p.allow_unchecked_conditions();
+        let _ = p.task("t").run("true").when("stage == 'prod'");
+        let _ = p
+            .task("u")
+            .run("true")
+            .when_cond(Condition::var("stage").eq("prod"));
+        assert!(p.emit_to(&mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_skip_reasons() {
+        let c = ctx("dev", "", "push", false);
+        let reason = |s: &str| match parse(s).unwrap().evaluate(&c) {
+            Decision::Skip(r) => r,
+            other => panic!("{} -> {:?}", s, other),
+        };
+        assert_eq!(reason("branch == 'main'"), "branch is 'dev', not 'main'");
+        assert_eq!(reason("branch != 'dev'"), "branch is 'dev'");
+        assert_eq!(reason("tag != ''"), "no tag present");
+        assert_eq!(reason("ci == true"), "not running in CI");
+        assert_eq!(
+            reason("(branch == 'main') || (tag != '')"),
+            "branch is 'dev', not 'main' and no tag present"
+        );
+        assert_eq!(reason("!(event == 'push')"), "event == 'push' holds");
+    }
+}
@@ -0,0 +1,202 @@
+//! The `.env` format read by [`Task::env_file`](crate::Task::env_file).
+//!
+//! One `KEY=value` per line. Blank lines and lines starting with `#` are
+//! skipped, and a leading `export ` is ignored. Values may be:
+//!
+//! - unquoted: surrounding whitespace and a trailing ` # comment` are dropped
+//! - single-quoted: taken literally
+//! - double-quoted: `\n`, `\r`, `\t`, `\"` and `\\` are unescaped
+//!
+//! A later line wins over an earlier one with the same key. Runners loading
+//! `env_files` at run time follow the same rules:
+//!
+//! ```rust
+//! let contents = "# local\nexport DB_HOST=localhost\nGREETING=\"hi\\nthere\"\n";
+//! let vars = sykli::dotenv::parse(contents).unwrap();
+//! assert_eq!(
+//!     vars,
+//!     [
+//!         ("DB_HOST".to_string(), "localhost".to_string()),
+//!         ("GREETING".to_string(), "hi\nthere".to_string()),
+//!     ]
+//! );
+//! ```
+
+use std::fmt;
+use std::fs;
+
+/// A line that isn't `KEY=value`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    /// What's wrong with it.
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses `.env` contents into `(key, value)` pairs, in file order.
+///
+/// # Errors
+/// Returns the first line without a key, `=` or closing quote.
+pub fn parse(contents: &str) -> Result<Vec<(String, String)>, ParseError> {
+    let mut vars = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let err = |message: &str| ParseError {
+            line: i + 1,
+            message: message.to_string(),
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = match line.strip_prefix("export") {
+            Some(rest) if rest.starts_with([' ', '\t']) => rest.trim_start(),
+            _ => line,
+        };
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(err("expected KEY=value"));
+        };
+        let key = key.trim_end();
+        if !is_key(key) {
+            return Err(err(&format!("invalid key {:?}", key)));
+        }
+        let value = parse_value(value.trim_start()).map_err(err)?;
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// Reads and parses the file at `path`, for emit.
+pub(crate) fn read(path: &str) -> Result<Vec<(String, String)>, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(&contents).map_err(|e| e.to_string())
+}
+
+/// Letters, digits, `_` and `.`, not starting with a digit.
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_value(raw: &str) -> Result<String, &'static str> {
+    if let Some(rest) = raw.strip_prefix('\'') {
+        let (value, tail) = rest
+            .split_once('\'')
+            .ok_or("unterminated single-quoted value")?;
+        check_tail(tail)?;
+        return Ok(value.to_string());
+    }
+    if let Some(rest) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    check_tail(&rest[i + 1..])?;
+                    return Ok(value);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some(c @ ('"' | '\\')) => value.push(c),
+                    Some(c) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        return Err("unterminated double-quoted value");
+    }
+    let value = match raw.find(" #").or_else(|| raw.find("\t#")) {
+        Some(i) => &raw[..i],
+        None => raw,
+    };
+    Ok(value.trim_end().to_string())
+}
+
+/// Only whitespace or a comment may follow a closing quote.
+fn check_tail(tail: &str) -> Result<(), &'static str> {
+    let tail = tail.trim_start();
+    if tail.is_empty() || tail.starts_with('#') {
+        Ok(())
+    } else {
+        Err("unexpected text after closing quote")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(contents: &str) -> Vec<(String, String)> {
+        parse(contents).unwrap()
+    }
+
+    fn pair(k: &str, v: &str) -> (String, String) {
+        (k.to_string(), v.to_string())
+    }
+
+    #[test]
+    fn test_quotes_and_comments() {
+        let contents = r#"
+# database
+DB_HOST = localhost   # trailing comment
+DB_URL=postgres://u:p@h/db#frag
+PASSWORD='p@ss #1 \n'
+GREETING="say \"hi\"\n" # greeting
+export	TOKEN=abc
+"#;
+        assert_eq!(
+            vars(contents),
+            [
+                pair("DB_HOST", "localhost"),
+                pair("DB_URL", "postgres://u:p@h/db#frag"),
+                pair("PASSWORD", "p@ss #1 \\n"),
+                pair("GREETING", "say \"hi\"\n"),
+                pair("TOKEN", "abc"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_values_and_crlf() {
+        assert_eq!(
+            vars("EMPTY=\r\nQUOTED=\"\"\r\nexport=1\r\nLAST=x\r\n"),
+            [
+                pair("EMPTY", ""),
+                pair("QUOTED", ""),
+                pair("export", "1"),
+                pair("LAST", "x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |contents: &str| parse(contents).unwrap_err().to_string();
+        assert_eq!(err("A=1\nnot a pair\n"), "line 2: expected KEY=value");
+        assert_eq!(err("=1"), "line 1: invalid key \"\"");
+        assert_eq!(err("1A=1"), "line 1: invalid key \"1A\"");
+        assert_eq!(err("A=\"open"), "line 1: unterminated double-quoted value");
+        assert_eq!(err("A='open"), "line 1: unterminated single-quoted value");
+        assert_eq!(
+            err("A=\"x\" y"),
+            "line 1: unexpected text after closing quote"
+        );
+    }
+}
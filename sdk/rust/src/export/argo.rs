@@ -0,0 +1,1025 @@
+//! Argo Workflows export.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+use serde_json::{Map, Value};
+
+use super::{yaml_quote, ExportError, ExportWarning, Ids};
+use crate::condition::{self, Comparison, Expr, Literal, Op};
+use crate::matrix::env_alias;
+use crate::{NodeKind, OutputKind, Pipeline, RunWhen, TaskData, TaskInput};
+
+/// Name of the DAG template every task hangs off.
+const ENTRYPOINT: &str = "main";
+
+/// Workflow parameters conditions can test.
+const PARAMETERS: [&str; 2] = ["branch", "tag"];
+
+pub(super) fn render(
+    p: &Pipeline,
+    name: &str,
+) -> Result<(String, Vec<ExportWarning>), ExportError> {
+    p.validate_for_emit()
+        .map_err(|e| ExportError::Invalid(e.to_string()))?;
+    if !is_dns_label(name) {
+        return Err(ExportError::Invalid(format!(
+            "workflow name {:?} must be at most 57 lowercase letters, digits and '-', \
+             starting and ending with a letter or digit",
+            name
+        )));
+    }
+
+    let mut template_ids = Ids::new('-', template_id);
+    // The DAG template itself is called `main`.
+    template_ids.reserve(ENTRYPOINT);
+    let ids: HashMap<&str, String> = p
+        .tasks
+        .iter()
+        .filter(|t| t.kind != NodeKind::Review)
+        .map(|t| (t.name.as_str(), template_ids.alloc(&t.name)))
+        .collect();
+
+    let mut warnings = Vec::new();
+    let mut dag = String::new();
+    let mut templates = String::new();
+    for t in &p.tasks {
+        let t = &*p.with_defaults(t);
+        let t = &*t.interpolate_matrix(|key| Some(format!("{{{{item.{}}}}}", key)));
+        let mut tpl = Template {
+            task: t,
+            id: ids.get(t.name.as_str()).map(String::as_str).unwrap_or(""),
+            notes: Vec::new(),
+        };
+        if t.kind == NodeKind::Review {
+            tpl.note("review node", "Argo Workflows has no equivalent; skipped");
+        } else {
+            tpl.write_dag_task(&mut dag, &ids);
+            tpl.write(&mut templates, p, &ids)?;
+        }
+        warnings.extend(tpl.notes);
+    }
+
+    let mut out = String::from(
+        "# Generated by sykli. Submit with: argo submit --watch <file> -p branch=main\n",
+    );
+    writeln!(out, "apiVersion: argoproj.io/v1alpha1").unwrap();
+    writeln!(out, "kind: Workflow").unwrap();
+    writeln!(out, "metadata:").unwrap();
+    writeln!(out, "  generateName: {}", yaml_quote(&format!("{}-", name))).unwrap();
+    writeln!(out, "spec:").unwrap();
+    writeln!(out, "  entrypoint: {}", ENTRYPOINT).unwrap();
+    if let Some(n) = p.max_parallel {
+        writeln!(out, "  parallelism: {}", n).unwrap();
+    }
+    writeln!(out, "  arguments:").unwrap();
+    writeln!(out, "    parameters:").unwrap();
+    for param in PARAMETERS {
+        writeln!(out, "      - name: {}", param).unwrap();
+        writeln!(out, "        value: \"\"").unwrap();
+    }
+    writeln!(out, "  templates:").unwrap();
+    writeln!(out, "    - name: {}", ENTRYPOINT).unwrap();
+    writeln!(out, "      dag:").unwrap();
+    writeln!(out, "        tasks:").unwrap();
+    out.push_str(&dag);
+    out.push_str(&templates);
+    Ok((out, warnings))
+}
+
+/// One task being written as a DAG task and template, with what couldn't be
+/// exported.
+struct Template<'a> {
+    task: &'a TaskData,
+    id: &'a str,
+    notes: Vec<ExportWarning>,
+}
+
+impl Template<'_> {
+    fn note(&mut self, what: &str, why: &str) {
+        self.notes
+            .push(ExportWarning::new(&self.task.name, what, why));
+    }
+
+    /// Writes the task's entry in the `main` DAG.
+    fn write_dag_task(&mut self, out: &mut String, ids: &HashMap<&str, String>) {
+        let t = self.task;
+        writeln!(out, "          - name: {}", self.id).unwrap();
+        writeln!(out, "            template: {}", self.id).unwrap();
+
+        let mut deps = Vec::new();
+        for dep in &t.depends_on {
+            match ids.get(dep.as_str()) {
+                Some(id) => deps.push((id.as_str(), t.run_when.get(dep))),
+                None => self.note(
+                    &format!("dependency on review node {:?}", dep),
+                    "the review is skipped",
+                ),
+            }
+        }
+        if t.run_when.is_empty() {
+            if !deps.is_empty() {
+                let ids: Vec<&str> = deps.iter().map(|(id, _)| *id).collect();
+                writeln!(out, "            dependencies: [{}]", ids.join(", ")).unwrap();
+            }
+        } else {
+            // Hooks need the `depends` expression; plain names mean success.
+            let terms: Vec<String> = deps
+                .iter()
+                .map(|(id, when)| match when {
+                    None | Some(RunWhen::Success) => id.to_string(),
+                    Some(RunWhen::Failure) => format!("{}.Failed", id),
+                    Some(RunWhen::Always) => format!("({0}.Succeeded || {0}.Failed)", id),
+                })
+                .collect();
+            writeln!(
+                out,
+                "            depends: {}",
+                yaml_quote(&terms.join(" && "))
+            )
+            .unwrap();
+        }
+        if t.allow_failure {
+            writeln!(out, "            continueOn:").unwrap();
+            writeln!(out, "              failed: true").unwrap();
+        }
+
+        if let Some(cond) = t.effective_condition() {
+            let when = condition::parse(&cond)
+                .ok()
+                .and_then(|expr| when_expr(&expr));
+            match when {
+                Some(Some(expr)) => {
+                    writeln!(out, "            when: {}", yaml_quote(&expr)).unwrap()
+                }
+                Some(None) => {}
+                None => {
+                    // Never run a task whose condition we couldn't translate.
+                    self.note(
+                        &format!("condition {:?}", cond),
+                        "no Argo when expression; the task is disabled",
+                    );
+                    writeln!(out, "            when: \"false\"").unwrap();
+                }
+            }
+        }
+
+        let inputs = self.inputs(ids);
+        if !inputs.is_empty() {
+            writeln!(out, "            arguments:").unwrap();
+            writeln!(out, "              artifacts:").unwrap();
+            for (ti, from) in inputs {
+                writeln!(
+                    out,
+                    "                - name: {}",
+                    input_name(from, &ti.output)
+                )
+                .unwrap();
+                let source = format!(
+                    "{{{{tasks.{}.outputs.artifacts.{}}}}}",
+                    from,
+                    artifact_name(&ti.output)
+                );
+                writeln!(out, "                  from: {}", yaml_quote(&source)).unwrap();
+            }
+        }
+
+        let variants = t.matrix.variants();
+        if !variants.is_empty() {
+            writeln!(out, "            withItems:").unwrap();
+            for v in &variants {
+                let item: Map<String, Value> = v
+                    .values()
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+                    .collect();
+                writeln!(out, "              - {}", Value::Object(item)).unwrap();
+            }
+        }
+    }
+
+    /// Inputs with the template producing them. Inputs from review nodes
+    /// are left out.
+    fn inputs<'b>(&self, ids: &'b HashMap<&str, String>) -> Vec<(&TaskInput, &'b String)> {
+        self.task
+            .task_inputs
+            .iter()
+            .filter_map(|ti| Some((ti, ids.get(ti.from_task.as_str())?)))
+            .collect()
+    }
+
+    /// Writes the task's template.
+    fn write(
+        &mut self,
+        out: &mut String,
+        p: &Pipeline,
+        ids: &HashMap<&str, String>,
+    ) -> Result<(), ExportError> {
+        let t = self.task;
+        writeln!(out, "    - name: {}", self.id).unwrap();
+        if let Some(ref gate) = t.gate {
+            if gate.strategy != "prompt" {
+                self.note(
+                    &format!("{} gate strategy", gate.strategy),
+                    "the workflow suspends; resume it with argo resume",
+                );
+            }
+            writeln!(out, "      suspend: {{}}").unwrap();
+            return Ok(());
+        }
+        let Some(ref image) = t.container else {
+            return Err(if t.container_build.is_some() {
+                ExportError::unsupported(
+                    &t.name,
+                    "container_from_dockerfile",
+                    "build and push the image first, then use container()",
+                )
+            } else {
+                ExportError::unsupported(
+                    &t.name,
+                    "task without a container",
+                    "every Argo template runs in a container; set one with container()",
+                )
+            });
+        };
+        self.collect_notes();
+
+        let pod = self.pod_spec(p);
+        let metadata: Vec<_> = ["labels", "annotations"]
+            .into_iter()
+            .filter_map(|key| Some((key, pod.get(key)?)))
+            .collect();
+        if !metadata.is_empty() {
+            writeln!(out, "      metadata:").unwrap();
+            for (key, map) in metadata {
+                writeln!(out, "        {}:", key).unwrap();
+                write_map(out, "          ", map);
+            }
+        }
+
+        let inputs = self.inputs(ids);
+        if !inputs.is_empty() {
+            writeln!(out, "      inputs:").unwrap();
+            writeln!(out, "        artifacts:").unwrap();
+            for (ti, from) in inputs {
+                writeln!(out, "          - name: {}", input_name(from, &ti.output)).unwrap();
+                writeln!(out, "            path: {}", yaml_quote(&ti.dest_path)).unwrap();
+                let producer = p.tasks.iter().find(|d| d.name == ti.from_task);
+                if producer.is_some_and(|d| !output_required(d, &ti.output)) {
+                    writeln!(out, "            optional: true").unwrap();
+                }
+            }
+        }
+        if !t.outputs.is_empty() {
+            let outputs: BTreeMap<&String, &String> = t.outputs.iter().collect();
+            writeln!(out, "      outputs:").unwrap();
+            writeln!(out, "        artifacts:").unwrap();
+            for (name, path) in outputs {
+                let path = match t.output_kinds.get(name) {
+                    Some(OutputKind::Glob) => {
+                        let dir = glob_dir(path);
+                        self.note(
+                            &format!("glob output {:?}", name),
+                            &format!("Argo artifacts have no globs; all of {:?} is exported", dir),
+                        );
+                        dir
+                    }
+                    _ => path,
+                };
+                let path = match t.workdir {
+                    Some(ref dir) if !path.starts_with('/') => {
+                        format!("{}/{}", dir.trim_end_matches('/'), path)
+                    }
+                    _ => path.to_string(),
+                };
+                writeln!(out, "          - name: {}", artifact_name(name)).unwrap();
+                writeln!(out, "            path: {}", yaml_quote(&path)).unwrap();
+                if !output_required(t, name) {
+                    writeln!(out, "            optional: true").unwrap();
+                }
+            }
+        }
+
+        if let Some(selector) = pod.get("nodeSelector") {
+            writeln!(out, "      nodeSelector:").unwrap();
+            write_map(out, "        ", selector);
+        }
+        if let Some(Value::Array(tolerations)) = pod.get("tolerations") {
+            writeln!(out, "      tolerations:").unwrap();
+            for tol in tolerations {
+                writeln!(out, "        - {}", tol).unwrap();
+            }
+        }
+        for key in ["serviceAccountName", "priorityClassName"] {
+            if let Some(value) = pod.get(key) {
+                writeln!(out, "      {}: {}", key, value).unwrap();
+            }
+        }
+        if let Some(ctx) = pod.get("securityContext") {
+            writeln!(out, "      securityContext:").unwrap();
+            write_map(out, "        ", ctx);
+        }
+        if let Some(ref retry) = t.retry {
+            writeln!(out, "      retryStrategy:").unwrap();
+            writeln!(out, "        limit: {}", retry.max).unwrap();
+        }
+        if let Some(secs) = t.timeout {
+            writeln!(out, "      activeDeadlineSeconds: {}", secs).unwrap();
+        }
+
+        writeln!(out, "      container:").unwrap();
+        writeln!(out, "        image: {}", yaml_quote(image)).unwrap();
+        if t.args.is_empty() {
+            writeln!(out, "        command: [\"sh\", \"-c\"]").unwrap();
+            writeln!(out, "        args: [{}]", yaml_quote(&t.script())).unwrap();
+        } else {
+            let argv: Vec<String> = t.args.iter().map(|a| yaml_quote(a)).collect();
+            writeln!(out, "        command: [{}]", argv.join(", ")).unwrap();
+        }
+        if let Some(ref workdir) = t.workdir {
+            writeln!(out, "        workingDir: {}", yaml_quote(workdir)).unwrap();
+        }
+        for path in &t.runtime_env_files {
+            self.note(
+                &format!("env_file_lazy {:?}", path),
+                "use env_file() to set its variables at emit, or a ConfigMap with envFrom",
+            );
+        }
+        let env = self.env();
+        if !env.is_empty() {
+            writeln!(out, "        env:").unwrap();
+            for (k, v) in &env {
+                writeln!(out, "          - name: {}", k).unwrap();
+                writeln!(out, "            value: {}", yaml_quote(v)).unwrap();
+            }
+        }
+        if let Some(resources) = pod.get("resources") {
+            writeln!(out, "        resources:").unwrap();
+            for (key, map) in resources.as_object().into_iter().flatten() {
+                writeln!(out, "          {}:", key).unwrap();
+                write_map(out, "            ", map);
+            }
+        }
+        if let Some(ctx) = pod.get("containerSecurityContext") {
+            writeln!(out, "        securityContext:").unwrap();
+            write_map(out, "          ", ctx);
+        }
+        Ok(())
+    }
+
+    /// Builds the pod-level settings from the merged `K8sOptions` and
+    /// `k8s_raw`, keyed by their Argo names. `K8sOptions` win where both set
+    /// the same thing.
+    fn pod_spec(&mut self, p: &Pipeline) -> Map<String, Value> {
+        let t = self.task;
+        let mut pod = Map::new();
+        if let Some(ref raw) = t.k8s_raw {
+            match serde_json::from_str::<Map<String, Value>>(raw) {
+                Ok(raw) => {
+                    for (key, value) in raw {
+                        self.raw_option(&mut pod, &key, value);
+                    }
+                }
+                Err(e) => self.note("k8s_raw", &format!("not a JSON object: {}", e)),
+            }
+        }
+
+        let opts = p.merged_k8s(t).unwrap_or_default();
+        let mut requests = Map::new();
+        let mut limits = Map::new();
+        for (key, value) in [("memory", &opts.memory), ("cpu", &opts.cpu)] {
+            if let Some(value) = value {
+                requests.insert(key.to_string(), Value::String(value.clone()));
+                limits.insert(key.to_string(), Value::String(value.clone()));
+            }
+        }
+        if let Some(gpu) = opts.gpu {
+            limits.insert("nvidia.com/gpu".to_string(), gpu.into());
+        }
+        let mut resources = Map::new();
+        if !requests.is_empty() {
+            resources.insert("requests".to_string(), requests.into());
+        }
+        if !limits.is_empty() {
+            resources.insert("limits".to_string(), limits.into());
+        }
+        if !resources.is_empty() {
+            pod.insert("resources".to_string(), resources.into());
+        }
+        pod
+    }
+
+    /// Maps one `k8s_raw` key, in the engine's snake_case or Kubernetes'
+    /// camelCase, onto `pod`.
+    fn raw_option(&mut self, pod: &mut Map<String, Value>, key: &str, value: Value) {
+        let name = match key {
+            "node_selector" | "nodeSelector" => "nodeSelector",
+            "tolerations" => "tolerations",
+            "service_account" | "serviceAccount" | "serviceAccountName" => "serviceAccountName",
+            "priority_class_name" | "priorityClassName" => "priorityClassName",
+            "labels" => "labels",
+            "annotations" => "annotations",
+            "security_context" | "securityContext" => {
+                self.security_context(pod, value);
+                return;
+            }
+            _ => {
+                self.note(
+                    &format!("k8s_raw {:?}", key),
+                    "Argo templates have no equivalent; add it with podSpecPatch",
+                );
+                return;
+            }
+        };
+        pod.insert(name.to_string(), value);
+    }
+
+    /// Splits a security context into the pod's and the container's.
+    fn security_context(&mut self, pod: &mut Map<String, Value>, value: Value) {
+        let Value::Object(ctx) = value else {
+            self.note("k8s_raw \"security_context\"", "not a JSON object");
+            return;
+        };
+        let mut pod_ctx = Map::new();
+        let mut container_ctx = Map::new();
+        let mut capabilities = Map::new();
+        for (key, value) in ctx {
+            let (target, name) = match key.as_str() {
+                "run_as_user" | "runAsUser" => (&mut pod_ctx, "runAsUser"),
+                "run_as_group" | "runAsGroup" => (&mut pod_ctx, "runAsGroup"),
+                "run_as_non_root" | "runAsNonRoot" => (&mut pod_ctx, "runAsNonRoot"),
+                "fs_group" | "fsGroup" => (&mut pod_ctx, "fsGroup"),
+                "privileged" => (&mut container_ctx, "privileged"),
+                "read_only_root_filesystem" | "readOnlyRootFilesystem" => {
+                    (&mut container_ctx, "readOnlyRootFilesystem")
+                }
+                "allow_privilege_escalation" | "allowPrivilegeEscalation" => {
+                    (&mut container_ctx, "allowPrivilegeEscalation")
+                }
+                "add_capabilities" => (&mut capabilities, "add"),
+                "drop_capabilities" => (&mut capabilities, "drop"),
+                "capabilities" => (&mut container_ctx, "capabilities"),
+                _ => {
+                    self.note(
+                        &format!("k8s_raw security_context {:?}", key),
+                        "Argo templates have no equivalent; add it with podSpecPatch",
+                    );
+                    continue;
+                }
+            };
+            target.insert(name.to_string(), value);
+        }
+        if !capabilities.is_empty() {
+            container_ctx.insert("capabilities".to_string(), capabilities.into());
+        }
+        if !pod_ctx.is_empty() {
+            pod.insert("securityContext".to_string(), pod_ctx.into());
+        }
+        if !container_ctx.is_empty() {
+            pod.insert("containerSecurityContext".to_string(), container_ctx.into());
+        }
+    }
+
+    /// Records everything about the task that has no Argo counterpart.
+    fn collect_notes(&mut self) {
+        let t = self.task;
+        for m in &t.mounts {
+            if m.mount_type == "cache" {
+                self.note(
+                    &format!("cache mount {:?} at {}", m.resource, m.path),
+                    "mount a persistent volume claim with podSpecPatch",
+                );
+            } else if m.mount_type == "git" {
+                self.note(
+                    &format!("git mount {:?} at {}", m.resource, m.path),
+                    "check the repository out with a git input artifact",
+                );
+            } else if m.is_download() {
+                self.note(
+                    &format!("file mount {:?} at {}", m.resource, m.path),
+                    "download it with an http input artifact",
+                );
+            } else {
+                self.note(
+                    &format!("{} mount at {}", m.mount_type, m.path),
+                    "check the source out with a git input artifact",
+                );
+            }
+        }
+        for s in &t.services {
+            self.note(
+                &format!("service {:?}", s.name),
+                "run it as a sidecar on the template",
+            );
+        }
+        for name in &t.secrets {
+            self.note(
+                &format!("secret {:?}", name),
+                "read it from a Kubernetes Secret with env valueFrom",
+            );
+        }
+        for sr in &t.secret_refs {
+            if let Some(ref path) = sr.mount_path {
+                self.note(
+                    &format!("secret file {:?} at {}", sr.name, path),
+                    "mount it from a Kubernetes Secret volume",
+                );
+            } else {
+                self.note(
+                    &format!("{} secret {:?}", sr.source.as_str(), sr.name),
+                    "read it from a Kubernetes Secret with env valueFrom",
+                );
+            }
+        }
+        for st in &t.secret_templates {
+            self.note(
+                &format!("secret template {:?}", st.dest),
+                "mount it from a Kubernetes Secret",
+            );
+        }
+        for (output, _) in &t.publish {
+            self.note(
+                &format!("publish_output {:?}", output),
+                "configure an artifact repository for the output",
+            );
+        }
+        if t.entrypoint.as_deref().is_some_and(|e| !e.is_empty()) {
+            self.note(
+                "entrypoint",
+                "the container command replaces it; call it from the command",
+            );
+        }
+        if let Some(ref user) = t.user {
+            self.note(
+                &format!("user {:?}", user),
+                "set runAsUser and runAsGroup in the pod's securityContext",
+            );
+        }
+        if t.manual {
+            self.note("manual", "the task runs once its dependencies finish");
+        }
+        if t.retry.as_ref().is_some_and(|r| r.is_structured()) {
+            self.note(
+                "retry policy",
+                "only the limit is exported; add backoff or an expression to retryStrategy",
+            );
+        }
+        if let Some(ref c) = t.concurrency {
+            self.note(
+                &format!("concurrency group {:?}", c.group),
+                "add a synchronization mutex to the template",
+            );
+        }
+        let mut selected: Vec<&String> = t.dep_selectors.keys().collect();
+        selected.sort();
+        for dep in selected {
+            self.note(
+                &format!("dependency on some variants of {:?}", dep),
+                "the task waits for every variant",
+            );
+        }
+    }
+
+    /// Task env and matrix values, sorted by name.
+    fn env(&self) -> BTreeMap<String, String> {
+        let t = self.task;
+        let mut env: BTreeMap<String, String> =
+            t.env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        for key in t.matrix.keys() {
+            let value = format!("{{{{item.{}}}}}", key);
+            env.insert(key.to_string(), value.clone());
+            env.insert(env_alias(key), value);
+        }
+        env
+    }
+}
+
+/// Writes a JSON object as a YAML block mapping, values in flow style.
+fn write_map(out: &mut String, indent: &str, map: &Value) {
+    for (k, v) in map.as_object().into_iter().flatten() {
+        writeln!(out, "{}{}: {}", indent, yaml_quote(k), v).unwrap();
+    }
+}
+
+/// Translates a condition into a `when:` expression. `Some(None)` means
+/// always true; `None` means it can't be expressed.
+fn when_expr(expr: &Expr) -> Option<Option<String>> {
+    match expr {
+        Expr::Compare(c) => compare_expr(c),
+        Expr::Not(inner) => Some(Some(format!("!({})", when_expr(inner)??))),
+        Expr::And(lhs, rhs) => match (when_expr(lhs)?, when_expr(rhs)?) {
+            (Some(l), Some(r)) => Some(Some(format!("({}) && ({})", l, r))),
+            (l, r) => Some(l.or(r)),
+        },
+        Expr::Or(lhs, rhs) => match (when_expr(lhs)?, when_expr(rhs)?) {
+            (Some(l), Some(r)) => Some(Some(format!("({}) || ({})", l, r))),
+            _ => Some(None),
+        },
+    }
+}
+
+fn compare_expr(c: &Comparison) -> Option<Option<String>> {
+    let Comparison { field, op, value } = c;
+    let value = match value {
+        Literal::Str(s) => s.as_str(),
+        Literal::Bool(true) if field == "ci" && *op == Op::Eq => return Some(None),
+        Literal::Bool(_) => return None,
+    };
+    if !PARAMETERS.contains(&field.as_str()) {
+        return None;
+    }
+    let lhs = literal(&format!("{{{{workflow.parameters.{}}}}}", field));
+    let expr = match op {
+        Op::Eq => format!("{} == {}", lhs, literal(value)),
+        Op::Ne => format!("{} != {}", lhs, literal(value)),
+        Op::Matches => format!("{} =~ {}", lhs, literal(&condition::glob_regex(value))),
+    };
+    Some(Some(expr))
+}
+
+/// Whether the task fails when `output` is missing.
+fn output_required(t: &TaskData, output: &str) -> bool {
+    t.output_options.get(output).map_or(true, |o| o.required)
+}
+
+/// The directory a glob's matches live under: its components before the
+/// first one with a wildcard, or `.`.
+fn glob_dir(glob: &str) -> &str {
+    let wild = glob.find(['*', '?', '[', '{']).unwrap_or(glob.len());
+    match glob[..wild].rfind('/') {
+        Some(0) => "/",
+        Some(i) => &glob[..i],
+        None => ".",
+    }
+}
+
+/// Quotes a string literal for a `when:` expression.
+fn literal(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Name of the input artifact carrying `output` from the template `from`.
+fn input_name(from: &str, output: &str) -> String {
+    format!("{}-{}", from, artifact_name(output))
+}
+
+/// Artifact names allow letters, digits, `-` and `_`.
+fn artifact_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Workflow names get a 5 character suffix and must stay a DNS label.
+fn is_dns_label(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 57
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// A template name for `name`: lowercase letters, digits and `-`.
+fn template_id(name: &str) -> String {
+    let id: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match id.trim_matches('-') {
+        "" => "task".to_string(),
+        id => id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_dir;
+    use crate::export::{argo_workflow, argo_workflow_with_warnings, ExportError};
+    use crate::{Backoff, K8sOptions, OutputOptions, Pipeline, RetryPolicy};
+
+    fn golden_pipeline() -> Pipeline {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("2Gi".into()),
+            cpu: Some("1".into()),
+            ..Default::default()
+        });
+        let src = p.dir(".");
+
+        let _ = p
+            .task("build")
+            .container("rust:1.80")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .env("CARGO_TERM_COLOR", "always")
+            .run("cargo build --release")
+            .output("binary", "target/release/app")
+            .retry(2);
+        let _ = p
+            .task("test")
+            .container("rust:1.80")
+            .run("cargo test")
+            .matrix("os", &["linux", "musl"])
+            .allow_failure(true)
+            .after(&["build"]);
+        let _ = p
+            .task("train.gpu")
+            .container("pytorch/pytorch:2.3.0-cuda12.1-cudnn8-runtime")
+            .run("python train.py --model /in/app")
+            .input_from("build", "binary", "/in/app")
+            .k8s(K8sOptions {
+                memory: Some("32Gi".into()),
+                gpu: Some(1),
+                ..Default::default()
+            })
+            .k8s_raw(
+                r#"{
+                    "node_selector": {"gpu": "a100"},
+                    "tolerations": [{"key": "nvidia.com/gpu", "operator": "Exists", "effect": "NoSchedule"}],
+                    "service_account": "trainer",
+                    "security_context": {"run_as_user": 1000, "run_as_non_root": true, "drop_capabilities": ["ALL"]},
+                    "labels": {"team": "ml"},
+                    "host_network": true
+                }"#,
+            )
+            .timeout(3600);
+        let _ = p
+            .gate("approve")
+            .gate_message("Ship it?")
+            .after(&["test", "train.gpu"]);
+        let _ = p
+            .task("deploy")
+            .container("alpine:3.20")
+            .run("./deploy.sh")
+            .secret("DEPLOY_TOKEN")
+            .when("branch == 'main' || tag matches 'v*'")
+            .after(&["approve"]);
+        p
+    }
+
+    #[test]
+    fn test_argo_golden() {
+        let (yaml, warnings) = argo_workflow_with_warnings(&golden_pipeline(), "ci").unwrap();
+        assert_eq!(yaml, include_str!("../../tests/golden/argo-workflow.yml"));
+        let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            [
+                r#"task "build": directory mount at /src not exported: check the source out with a git input artifact"#,
+                r#"task "train.gpu": k8s_raw "host_network" not exported: Argo templates have no equivalent; add it with podSpecPatch"#,
+                r#"task "deploy": secret "DEPLOY_TOKEN" not exported: read it from a Kubernetes Secret with env valueFrom"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_argo_condition_mapping() {
+        let cases = [
+            (
+                "branch != 'main'",
+                r#"when: "'{{workflow.parameters.branch}}' != 'main'""#,
+            ),
+            (
+                "branch matches 'release/*'",
+                r#"when: "'{{workflow.parameters.branch}}' =~ '^release/[^/]*$'""#,
+            ),
+            (
+                "tag != ''",
+                r#"when: "'{{workflow.parameters.tag}}' != ''""#,
+            ),
+            (
+                "ci == true && !(branch == 'main')",
+                r#"when: "!('{{workflow.parameters.branch}}' == 'main')""#,
+            ),
+        ];
+        for (cond, expected) in cases {
+            let mut p = Pipeline::new();
+            let _ = p.task("t").container("alpine").run("true").when(cond);
+            let yaml = argo_workflow(&p, "ci").unwrap();
+            assert!(yaml.contains(expected), "{}: {}", cond, yaml);
+        }
+    }
+
+    #[test]
+    fn test_argo_untranslatable_condition_disables_task() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("docs")
+            .container("alpine")
+            .run("make docs")
+            .when("event == 'pull_request'");
+
+        let (yaml, warnings) = argo_workflow_with_warnings(&p, "ci").unwrap();
+        assert!(yaml.contains("            when: \"false\"\n"));
+        assert_eq!(warnings[0].task, "docs");
+        assert_eq!(warnings[0].feature, "condition \"event == 'pull_request'\"");
+    }
+
+    #[test]
+    fn test_argo_max_parallel_and_concurrency() {
+        let mut p = Pipeline::new();
+        p.max_parallel(3);
+        let _ = p
+            .task("deploy")
+            .container("alpine")
+            .run("./deploy.sh")
+            .concurrency_group("staging-deploy");
+
+        let (yaml, warnings) = argo_workflow_with_warnings(&p, "ci").unwrap();
+        assert!(
+            yaml.contains("  entrypoint: main\n  parallelism: 3\n"),
+            "{}",
+            yaml
+        );
+        assert_eq!(warnings[0].feature, "concurrency group \"staging-deploy\"");
+    }
+
+    #[test]
+    fn test_argo_retry_policy() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("push")
+            .container("docker")
+            .run("docker push app")
+            .retry_policy(RetryPolicy {
+                max: 3,
+                backoff: Backoff::Fixed { secs: 10 },
+                ..RetryPolicy::default()
+            });
+
+        let (yaml, warnings) = argo_workflow_with_warnings(&p, "ci").unwrap();
+        assert!(
+            yaml.contains("      retryStrategy:\n        limit: 3\n"),
+            "{}",
+            yaml
+        );
+        assert_eq!(warnings[0].feature, "retry policy");
+    }
+
+    #[test]
+    fn test_argo_glob_output_exports_directory() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("package")
+            .container("alpine:3")
+            .run("make dist")
+            .output_glob("bundles", "/out/dist/*.tar.gz")
+            .output_dir("site", "/out/public/");
+
+        let (yaml, warnings) = argo_workflow_with_warnings(&p, "ci").unwrap();
+        assert!(
+            yaml.contains(
+                "          - name: bundles\n            path: \"/out/dist\"\n          - name: site\n            path: \"/out/public/\"\n"
+            ),
+            "{}",
+            yaml
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].feature, "glob output \"bundles\"");
+
+        assert_eq!(glob_dir("dist/**/*.js"), "dist");
+        assert_eq!(glob_dir("*.tar.gz"), ".");
+        assert_eq!(glob_dir("/*.log"), "/");
+    }
+
+    #[test]
+    fn test_argo_optional_output() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("coverage")
+            .container("rust")
+            .run("make")
+            .output_with(
+                "report",
+                "/out/coverage/",
+                OutputOptions {
+                    required: false,
+                    ..OutputOptions::default()
+                },
+            );
+        let _ = p
+            .task("upload")
+            .container("alpine:3")
+            .run("./upload.sh")
+            .input_from("coverage", "report", "/in/coverage/");
+
+        let yaml = argo_workflow(&p, "ci").unwrap();
+        assert!(
+            yaml.contains("            path: \"/out/coverage/\"\n            optional: true\n"),
+            "{}",
+            yaml
+        );
+        assert!(
+            yaml.contains("            path: \"/in/coverage/\"\n            optional: true\n"),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_argo_template_names() {
+        let mut p = Pipeline::new();
+        let _ = p.task("Build.Linux").container("alpine").run("make");
+        let _ = p.task("build/linux").container("alpine").run("make");
+        let _ = p
+            .task("main")
+            .container("alpine")
+            .run("true")
+            .after(&["build/linux"]);
+
+        let yaml = argo_workflow(&p, "ci").unwrap();
+        assert!(yaml.contains("          - name: build-linux\n"));
+        assert!(yaml.contains("          - name: build-linux-2\n"));
+        assert!(yaml.contains(
+            "          - name: main-2\n            template: main-2\n            dependencies: [build-linux-2]\n"
+        ));
+    }
+
+    #[test]
+    fn test_argo_exec_skips_shell() {
+        let mut p = Pipeline::new();
+        let _ =
+            p.task("test")
+                .container("rust:1.80")
+                .exec(&["cargo", "test", "--features", "foo bar"]);
+
+        let yaml = argo_workflow(&p, "ci").unwrap();
+        assert!(
+            yaml.contains("        command: [\"cargo\", \"test\", \"--features\", \"foo bar\"]\n"),
+            "{}",
+            yaml
+        );
+        assert!(!yaml.contains("\"sh\""));
+    }
+
+    #[test]
+    fn test_argo_hook_deps_use_depends() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").container("rust:1.80").run("cargo build");
+        let _ = p
+            .task("deploy")
+            .container("alpine")
+            .run("./deploy.sh")
+            .after(&["build"]);
+        let _ = p
+            .task("alert")
+            .container("alpine")
+            .run("./notify.sh")
+            .on_failure_of(&["deploy"]);
+        let _ = p
+            .task("logs")
+            .container("alpine")
+            .run("./upload-logs.sh")
+            .after(&["build"])
+            .always_after(&["deploy"]);
+
+        let yaml = argo_workflow(&p, "ci").unwrap();
+        assert!(
+            yaml.contains("            dependencies: [build]\n"),
+            "{}",
+            yaml
+        );
+        assert!(yaml.contains("            depends: \"deploy.Failed\"\n"));
+        assert!(yaml
+            .contains("            depends: \"build && (deploy.Succeeded || deploy.Failed)\"\n"));
+    }
+
+    #[test]
+    fn test_argo_errors() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+
+        let err = argo_workflow(&p, "ci").unwrap_err();
+        assert!(
+            err.to_string().contains("task without a container"),
+            "{}",
+            err
+        );
+        assert!(matches!(
+            argo_workflow(&p, "My_CI"),
+            Err(ExportError::Invalid(_))
+        ));
+        let _ = p
+            .task("build")
+            .container("alpine")
+            .run("make")
+            .after(&["tset"]);
+        assert!(matches!(
+            argo_workflow(&p, "ci"),
+            Err(ExportError::Invalid(_))
+        ));
+    }
+}
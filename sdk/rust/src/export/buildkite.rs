@@ -0,0 +1,772 @@
+//! Buildkite pipeline export.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use super::{shell_quote, uniform_run_when, yaml_quote, ExportError};
+use crate::condition::{self, Comparison, Expr, Literal, Op};
+use crate::matrix::{env_alias, Matrix};
+use crate::{NodeKind, OutputKind, Pipeline, RunWhen, SecretSource, TaskData};
+
+/// Docker plugin used to run container tasks.
+const DOCKER_PLUGIN: &str = "docker#v5.12.0";
+
+/// How a `when` condition maps onto a Buildkite step.
+enum StepFilter {
+    /// `branches:` filter.
+    Branches(String),
+    /// `if:` conditional expression.
+    If(String),
+    /// Always true on Buildkite (e.g. `ci == true`).
+    Always,
+}
+
+impl Pipeline {
+    /// Renders the pipeline as a Buildkite pipeline YAML.
+    ///
+    /// Requires the `export-buildkite` feature.
+    ///
+    /// - tasks become command steps with `key` = task name and `depends_on` carried over
+    /// - container tasks run through the docker plugin with their mounts, workdir and env
+    /// - gates become `block` steps
+    /// - branch conditions become `branches:` filters, tag and event conditions `if:` expressions
+    /// - `retry(n)` maps to `retry.automatic.limit`, timeouts to `timeout_in_minutes` (rounded up)
+    /// - retry exit codes become one `retry.automatic` rule each; backoff is dropped, since
+    ///   Buildkite retries right away
+    /// - `allow_failure(true)` maps to `soft_fail: true`, `always_after` to
+    ///   `allow_dependency_failure: true`
+    /// - concurrency groups map to `concurrency_group` with `concurrency: 1`
+    /// - matrix dimensions become a build matrix, exposed to the command as env vars;
+    ///   matrix excludes and includes become `adjustments`
+    /// - secrets are listed as environment the agent must provide, never as values
+    ///
+    /// # Errors
+    /// Returns [`ExportError::Invalid`] if the pipeline fails emit validation, and
+    /// [`ExportError::Unsupported`] for conditions, secret sources, services,
+    /// published outputs, lazy env files, manual tasks, `on_failure_of`,
+    /// `cancel_in_progress`, secret files, dependencies on some variants of a
+    /// matrix task or review nodes Buildkite can't express.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test");
+    /// let yaml = p.to_buildkite().unwrap();
+    /// assert!(yaml.contains("key: \"test\""));
+    /// ```
+    pub fn to_buildkite(&self) -> Result<String, ExportError> {
+        self.validate_for_emit()
+            .map_err(|e| ExportError::Invalid(e.to_string()))?;
+
+        let mut out = String::from("steps:\n");
+        for t in &self.tasks {
+            let t = &*self.with_defaults(t);
+            if t.kind == NodeKind::Review {
+                return Err(ExportError::unsupported(
+                    &t.name,
+                    "review node",
+                    "Buildkite has no equivalent step type",
+                ));
+            }
+            if t.gate.is_some() {
+                write_block_step(&mut out, t);
+            } else {
+                write_command_step(&mut out, t)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn write_block_step(out: &mut String, t: &TaskData) {
+    let gate = t.gate.as_ref().expect("gate step without gate config");
+    let prompt = gate.message.as_deref().unwrap_or(&t.name);
+    writeln!(out, "  - block: {}", yaml_quote(prompt)).unwrap();
+    writeln!(out, "    key: {}", yaml_quote(&t.name)).unwrap();
+    write_depends_on(out, t);
+}
+
+fn write_command_step(out: &mut String, t: &TaskData) -> Result<(), ExportError> {
+    let allow_dependency_failure =
+        match uniform_run_when(t) {
+            Some(RunWhen::Success) => false,
+            Some(RunWhen::Always) => true,
+            Some(RunWhen::Failure) | None => return Err(ExportError::unsupported(
+                &t.name,
+                "on_failure_of",
+                "Buildkite can only run a step regardless of its dependencies; use always_after",
+            )),
+        };
+    if !t.services.is_empty() {
+        return Err(ExportError::unsupported(
+            &t.name,
+            "services",
+            "use the docker-compose plugin and run the task through it",
+        ));
+    }
+    if let Some(m) = t.mounts.iter().find(|m| m.mount_type == "git") {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("git mount {:?}", m.resource),
+            "clone the repository in the command",
+        ));
+    }
+    if let Some(m) = t.mounts.iter().find(|m| m.is_download()) {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("file mount {:?}", m.resource),
+            "download the file in the command",
+        ));
+    }
+    if t.container_build.is_some() {
+        return Err(ExportError::unsupported(
+            &t.name,
+            "container_from_dockerfile",
+            "use the docker-compose plugin to build the image",
+        ));
+    }
+    if let Some(st) = t.secret_templates.first() {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("secret template {:?}", st.dest),
+            "render the file in the command from secrets in the agent environment",
+        ));
+    }
+    if let Some(path) = t.runtime_env_files.first() {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("env_file_lazy {:?}", path),
+            "use env_file() to set its variables at emit, or source it in the command",
+        ));
+    }
+    if let Some((output, _)) = t.publish.first() {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("publish_output {:?}", output),
+            "upload the artifact from a follow-up step or plugin",
+        ));
+    }
+    if t.manual {
+        return Err(ExportError::unsupported(
+            &t.name,
+            "manual approval",
+            "put a gate before the task instead",
+        ));
+    }
+    let selected = t.dep_selectors.keys().min().or_else(|| {
+        t.task_inputs
+            .iter()
+            .find(|ti| !ti.matrix.is_empty())
+            .map(|ti| &ti.from_task)
+    });
+    if let Some(dep) = selected {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("dependency on some variants of {:?}", dep),
+            "Buildkite steps depend on every job of a matrix step",
+        ));
+    }
+    if t.concurrency.as_ref().is_some_and(|c| c.cancel_in_progress) {
+        return Err(ExportError::unsupported(
+            &t.name,
+            "cancel_in_progress",
+            "Buildkite queues jobs in a concurrency group instead of cancelling them",
+        ));
+    }
+    let filter = match t.effective_condition() {
+        Some(cond) => Some(condition_filter(&t.name, &cond)?),
+        None => None,
+    };
+
+    let mut expected_secrets = Vec::new();
+    for name in &t.secrets {
+        expected_secrets.push(name.clone());
+    }
+    for sr in &t.secret_refs {
+        if sr.mount_path.is_some() {
+            return Err(ExportError::unsupported(
+                &t.name,
+                &format!("secret file {:?}", sr.name),
+                "write the file in the command from a secret in the agent environment",
+            ));
+        }
+        match sr.source {
+            SecretSource::Env if sr.key == sr.name => expected_secrets.push(sr.name.clone()),
+            SecretSource::Env => {
+                expected_secrets.push(format!("{} (from ${})", sr.name, sr.key));
+            }
+            SecretSource::File => {
+                expected_secrets.push(format!("{} (from file {})", sr.name, sr.key));
+            }
+            SecretSource::Vault => {
+                return Err(ExportError::unsupported(
+                    &t.name,
+                    &format!("vault secret {:?}", sr.name),
+                    "resolve it into the agent environment (e.g. with the vault-secrets plugin) and declare it with secret()",
+                ));
+            }
+            SecretSource::K8s | SecretSource::Aws | SecretSource::Gcp | SecretSource::Sops => {
+                return Err(ExportError::unsupported(
+                    &t.name,
+                    &format!("{} secret {:?}", sr.source.as_str(), sr.name),
+                    "resolve it into the agent environment (e.g. with a secrets plugin) and declare it with secret()",
+                ));
+            }
+        }
+    }
+    if !expected_secrets.is_empty() {
+        writeln!(
+            out,
+            "  # Secrets expected in the agent environment: {}",
+            expected_secrets.join(", ")
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "  - label: {}", yaml_quote(&t.name)).unwrap();
+    writeln!(out, "    key: {}", yaml_quote(&t.name)).unwrap();
+
+    let mut commands: Vec<String> = t
+        .task_inputs
+        .iter()
+        .map(|ti| {
+            format!(
+                "buildkite-agent artifact download {} {} --step {}",
+                shell_quote(&ti.output),
+                shell_quote(&ti.dest_path),
+                shell_quote(&ti.from_task)
+            )
+        })
+        .collect();
+    // Commands share one shell, so a host workdir needs a single cd.
+    if let (None, Some(workdir)) = (&t.container, &t.workdir) {
+        commands.push(format!("cd {}", shell_quote(workdir)));
+    }
+    if t.steps.is_empty() {
+        commands.push(t.command.clone());
+    } else {
+        commands.extend(t.steps.iter().cloned());
+    }
+    if commands.len() == 1 {
+        writeln!(
+            out,
+            "    command: {}",
+            yaml_quote(&escape_dollars(&commands[0]))
+        )
+        .unwrap();
+    } else {
+        writeln!(out, "    commands:").unwrap();
+        for cmd in &commands {
+            writeln!(out, "      - {}", yaml_quote(&escape_dollars(cmd))).unwrap();
+        }
+    }
+
+    write_depends_on(out, t);
+
+    match filter {
+        Some(StepFilter::Branches(branches)) => {
+            writeln!(out, "    branches: {}", yaml_quote(&branches)).unwrap();
+        }
+        Some(StepFilter::If(expr)) => writeln!(out, "    if: {}", yaml_quote(&expr)).unwrap(),
+        Some(StepFilter::Always) | None => {}
+    }
+
+    let mut env: BTreeMap<&str, String> = t
+        .env
+        .iter()
+        .map(|(k, v)| (k.as_str(), escape_dollars(v)))
+        .collect();
+    let aliases: Vec<_> = t.matrix.keys().map(|k| (k, env_alias(k))).collect();
+    for (key, alias) in &aliases {
+        let value = format!("{{{{matrix.{}}}}}", key);
+        env.insert(key, value.clone());
+        env.insert(alias, value);
+    }
+    if !env.is_empty() {
+        writeln!(out, "    env:").unwrap();
+        for (k, v) in &env {
+            writeln!(out, "      {}: {}", k, yaml_quote(v)).unwrap();
+        }
+    }
+
+    if !t.matrix.is_empty() {
+        writeln!(out, "    matrix:").unwrap();
+        writeln!(out, "      setup:").unwrap();
+        for (key, values) in t.matrix.iter() {
+            writeln!(out, "        {}:", key).unwrap();
+            for v in values {
+                writeln!(out, "          - {}", yaml_quote(v)).unwrap();
+            }
+        }
+        // Adjustments name whole combinations: skip each one an exclude
+        // drops and add each include beyond the product.
+        let mut product = Matrix::default();
+        for (key, values) in t.matrix.iter() {
+            product.insert(key.to_string(), values.to_vec());
+        }
+        let product = product.variants();
+        let variants = t.matrix.variants();
+        let skipped = product.iter().filter(|v| !variants.contains(v));
+        let added = variants.iter().filter(|v| !product.contains(v));
+        let adjustments: Vec<_> = skipped
+            .map(|v| (v, true))
+            .chain(added.map(|v| (v, false)))
+            .collect();
+        if !adjustments.is_empty() {
+            writeln!(out, "      adjustments:").unwrap();
+            for (variant, skip) in adjustments {
+                writeln!(out, "        - with:").unwrap();
+                for (k, v) in variant.values() {
+                    writeln!(out, "            {}: {}", k, yaml_quote(v)).unwrap();
+                }
+                if skip {
+                    writeln!(out, "          skip: true").unwrap();
+                }
+            }
+        }
+    }
+
+    if !t.outputs.is_empty() {
+        let paths: BTreeMap<_, _> = t.outputs.iter().collect();
+        writeln!(out, "    artifact_paths:").unwrap();
+        for (name, path) in paths {
+            // Buildkite uploads files matching a glob, so a directory needs
+            // one for everything under it.
+            let path = match t.output_kinds.get(name) {
+                Some(OutputKind::Dir) => format!("{}/**/*", path.trim_end_matches('/')),
+                _ => path.clone(),
+            };
+            writeln!(out, "      - {}", yaml_quote(&path)).unwrap();
+        }
+    }
+
+    if let Some(ref retry) = t.retry {
+        writeln!(out, "    retry:").unwrap();
+        writeln!(out, "      automatic:").unwrap();
+        match retry.retry_on_exit_codes {
+            Some(ref codes) => {
+                for code in codes {
+                    writeln!(out, "        - exit_status: {}", code).unwrap();
+                    writeln!(out, "          limit: {}", retry.max).unwrap();
+                }
+            }
+            None => writeln!(out, "        limit: {}", retry.max).unwrap(),
+        }
+    }
+    if let Some(secs) = t.timeout {
+        writeln!(out, "    timeout_in_minutes: {}", secs.div_ceil(60)).unwrap();
+    }
+    if t.allow_failure {
+        writeln!(out, "    soft_fail: true").unwrap();
+    }
+    if allow_dependency_failure {
+        writeln!(out, "    allow_dependency_failure: true").unwrap();
+    }
+    if let Some(ref c) = t.concurrency {
+        writeln!(out, "    concurrency: 1").unwrap();
+        writeln!(out, "    concurrency_group: {}", yaml_quote(&c.group)).unwrap();
+    }
+
+    if let Some(ref image) = t.container {
+        writeln!(out, "    plugins:").unwrap();
+        writeln!(out, "      - {}:", DOCKER_PLUGIN).unwrap();
+        writeln!(out, "          image: {}", yaml_quote(image)).unwrap();
+        if let Some(ref workdir) = t.workdir {
+            writeln!(out, "          workdir: {}", yaml_quote(workdir)).unwrap();
+        }
+        if let Some(ref entrypoint) = t.entrypoint {
+            writeln!(out, "          entrypoint: {}", yaml_quote(entrypoint)).unwrap();
+        }
+        if let Some(ref user) = t.user {
+            writeln!(out, "          user: {}", yaml_quote(user)).unwrap();
+        }
+        if !t.mounts.is_empty() {
+            writeln!(out, "          volumes:").unwrap();
+            for m in &t.mounts {
+                let source = if m.is_bind() {
+                    m.host_path().to_string()
+                } else {
+                    m.resource.clone()
+                };
+                writeln!(
+                    out,
+                    "            - {}",
+                    yaml_quote(&format!("{}:{}", source, m.path))
+                )
+                .unwrap();
+            }
+        }
+        if !env.is_empty() {
+            writeln!(out, "          propagate-environment: true").unwrap();
+        }
+    }
+
+    Ok(())
+}
+
+fn write_depends_on(out: &mut String, t: &TaskData) {
+    if t.depends_on.is_empty() {
+        return;
+    }
+    writeln!(out, "    depends_on:").unwrap();
+    for dep in &t.depends_on {
+        writeln!(out, "      - {}", yaml_quote(dep)).unwrap();
+    }
+}
+
+/// Maps a single sykli condition onto a Buildkite filter.
+fn condition_filter(task: &str, condition: &str) -> Result<StepFilter, ExportError> {
+    let unsupported = || {
+        ExportError::unsupported(
+            task,
+            &format!("condition {:?}", condition),
+            "only single branch, tag or event comparisons can be exported",
+        )
+    };
+    let Ok(Expr::Compare(Comparison { field, op, value })) = condition::parse(condition) else {
+        return Err(unsupported());
+    };
+    let value = match value {
+        Literal::Str(value) => value,
+        Literal::Bool(true) if field == "ci" && op == Op::Eq => return Ok(StepFilter::Always),
+        Literal::Bool(_) => return Err(unsupported()),
+    };
+    let value = value.as_str();
+
+    match (field.as_str(), op) {
+        ("branch", Op::Eq) | ("branch", Op::Matches) => Ok(StepFilter::Branches(value.to_string())),
+        ("branch", Op::Ne) => Ok(StepFilter::Branches(format!("!{}", value))),
+        ("tag", Op::Ne) if value.is_empty() => Ok(StepFilter::If("build.tag != null".to_string())),
+        ("tag", Op::Eq) => Ok(StepFilter::If(format!(
+            "build.tag == {}",
+            yaml_value(value)
+        ))),
+        ("tag", Op::Ne) => Ok(StepFilter::If(format!(
+            "build.tag != {}",
+            yaml_value(value)
+        ))),
+        ("tag", Op::Matches) => Ok(StepFilter::If(format!(
+            "build.tag =~ /{}/",
+            condition::glob_regex(value).replace('/', "\\/")
+        ))),
+        ("event", Op::Eq) | ("event", Op::Ne) => {
+            let expr = match value {
+                "pull_request" => "build.pull_request.id != null",
+                "schedule" => "build.source == 'schedule'",
+                "push" => "build.source == 'webhook' && build.pull_request.id == null",
+                _ => return Err(unsupported()),
+            };
+            if op == Op::Eq {
+                Ok(StepFilter::If(expr.to_string()))
+            } else {
+                Ok(StepFilter::If(format!("!({})", expr)))
+            }
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+/// Quotes a literal for a Buildkite conditional expression.
+fn yaml_value(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Escapes `$` so `buildkite-agent pipeline upload` doesn't interpolate it.
+fn escape_dollars(s: &str) -> String {
+    s.replace('$', "$$")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::ExportError;
+    use crate::{Backoff, Pipeline, RetryPolicy, SecretRef};
+
+    fn golden_pipeline() -> Pipeline {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let registry = p.cache("cargo-registry");
+
+        let _ = p
+            .task("lint")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .workdir("/src")
+            .env("CARGO_TERM_COLOR", "always")
+            .run("cargo clippy -- -D warnings")
+            .allow_failure(true);
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux", "macos"])
+            .retry(2)
+            .timeout(90);
+        let _ = p
+            .task("build")
+            .run("cargo build --release")
+            .output("binary", "target/release/app")
+            .after(&["lint", "test"]);
+        let _ = p
+            .task("package")
+            .run("docker build -t app:$BUILDKITE_COMMIT .")
+            .input_from("build", "binary", "./app")
+            .when("tag matches 'v*'");
+        let _ = p
+            .gate("approve")
+            .gate_message("Ship it?")
+            .after(&["package"]);
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret("DEPLOY_TOKEN")
+            .secret_from("KUBECONFIG", SecretRef::from_file("/etc/kube/config"))
+            .when_cond(crate::Condition::branch("main"))
+            .after(&["approve"]);
+        p
+    }
+
+    #[test]
+    fn test_buildkite_golden() {
+        let yaml = golden_pipeline().to_buildkite().unwrap();
+        assert_eq!(yaml, include_str!("../../tests/golden/buildkite.yml"));
+    }
+
+    #[test]
+    fn test_buildkite_matrix_adjustments() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.70", "1.80"])
+            .matrix("os", &["linux", "macos"])
+            .matrix_exclude(&[("rust", "1.70"), ("os", "macos")])
+            .matrix_include(&[("rust", "nightly"), ("os", "linux")]);
+
+        let yaml = p.to_buildkite().unwrap();
+        assert!(
+            yaml.contains(
+                "      adjustments:\n        - with:\n            rust: \"1.70\"\n            \
+                 os: \"macos\"\n          skip: true\n        - with:\n            \
+                 rust: \"nightly\"\n            os: \"linux\"\n"
+            ),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_buildkite_vault_secret_errors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret_from("DB_PASS", SecretRef::from_vault("secret/data/db#password"));
+
+        let err = p.to_buildkite().unwrap_err();
+        assert!(matches!(err, ExportError::Unsupported { ref task, .. } if task == "deploy"));
+        assert!(err.to_string().contains("vault secret \"DB_PASS\""));
+    }
+
+    #[test]
+    fn test_buildkite_git_mount_errors() {
+        let mut p = Pipeline::new();
+        let charts = p.git("https://github.com/org/charts.git").branch("main");
+        let _ = p
+            .task("deploy")
+            .container("alpine/helm:3")
+            .mount_git(&charts, "/charts")
+            .run("helm upgrade app /charts/app");
+
+        let err = p.to_buildkite().unwrap_err();
+        assert!(matches!(err, ExportError::Unsupported { ref task, .. } if task == "deploy"));
+        assert!(err.to_string().contains("git mount"));
+    }
+
+    #[test]
+    fn test_buildkite_file_mount_errors() {
+        let mut p = Pipeline::new();
+        let protoc = p.http("https://example.com/protoc.zip");
+        let _ = p
+            .task("gen")
+            .container("debian:12")
+            .mount_file(&protoc, "/opt/protoc.zip")
+            .run("unzip /opt/protoc.zip");
+
+        let err = p.to_buildkite().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("file mount \"http:https://example.com/protoc.zip\""));
+    }
+
+    #[test]
+    fn test_buildkite_manual_task_errors() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh").manual();
+
+        let err = p.to_buildkite().unwrap_err();
+        assert!(err.to_string().contains("manual approval"));
+    }
+
+    #[test]
+    fn test_buildkite_matrix_selector_errors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"]);
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after_matrix("build", &[("os", "linux")]);
+
+        let err = p.to_buildkite().unwrap_err();
+        assert!(matches!(err, ExportError::Unsupported { ref task, .. } if task == "package"));
+        assert!(err
+            .to_string()
+            .contains("dependency on some variants of \"build\""));
+    }
+
+    #[test]
+    fn test_buildkite_retry_exit_codes() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("push")
+            .run("docker push app")
+            .retry_policy(RetryPolicy {
+                max: 3,
+                backoff: Backoff::Fixed { secs: 10 },
+                retry_on_exit_codes: Some(vec![75, 111]),
+                ..RetryPolicy::default()
+            });
+
+        let yaml = p.to_buildkite().unwrap();
+        assert!(
+            yaml.contains(
+                "    retry:\n      automatic:\n        - exit_status: 75\n          limit: 3\n        - exit_status: 111\n          limit: 3\n"
+            ),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_buildkite_output_kinds() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("docs")
+            .run("make docs")
+            .output("index", "public/index.html")
+            .output_dir("site", "public/")
+            .output_glob("bundles", "dist/*.tar.gz");
+
+        let yaml = p.to_buildkite().unwrap();
+        assert!(
+            yaml.contains(
+                "    artifact_paths:\n      - \"dist/*.tar.gz\"\n      - \"public/index.html\"\n      - \"public/**/*\"\n"
+            ),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_buildkite_host_workdir() {
+        let mut p = Pipeline::new();
+        let _ = p.task("web-test").workdir("web").run("npm test");
+
+        let yaml = p.to_buildkite().unwrap();
+        assert!(
+            yaml.contains("    commands:\n      - \"cd 'web'\"\n      - \"npm test\"\n"),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_buildkite_concurrency_group() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .concurrency_group("staging-deploy");
+
+        let yaml = p.to_buildkite().unwrap();
+        assert!(
+            yaml.contains("    concurrency: 1\n    concurrency_group: \"staging-deploy\"\n"),
+            "{}",
+            yaml
+        );
+
+        let _ = p
+            .task("preview")
+            .run("./deploy.sh")
+            .concurrency_group("preview")
+            .cancel_in_progress(true);
+        let err = p.to_buildkite().unwrap_err();
+        assert!(matches!(err, ExportError::Unsupported { ref task, .. } if task == "preview"));
+    }
+
+    #[test]
+    fn test_buildkite_hook_deps() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh");
+        let _ = p
+            .task("logs")
+            .run("./upload-logs.sh")
+            .always_after(&["deploy"]);
+        let yaml = p.to_buildkite().unwrap();
+        assert!(
+            yaml.contains("    allow_dependency_failure: true\n"),
+            "{}",
+            yaml
+        );
+
+        let _ = p
+            .task("alert")
+            .run("./notify.sh")
+            .on_failure_of(&["deploy"]);
+        let err = p.to_buildkite().unwrap_err();
+        assert!(err.to_string().contains("on_failure_of"), "{}", err);
+    }
+
+    #[test]
+    fn test_buildkite_condition_mapping() {
+        let cases = [
+            ("branch != 'main'", "branches: \"!main\""),
+            ("branch matches 'release/*'", "branches: \"release/*\""),
+            ("tag != ''", "if: \"build.tag != null\""),
+            (
+                "event == 'pull_request'",
+                "if: \"build.pull_request.id != null\"",
+            ),
+        ];
+        for (cond, expected) in cases {
+            let mut p = Pipeline::new();
+            let _ = p.task("t").run("true").when(cond);
+            let yaml = p.to_buildkite().unwrap();
+            assert!(yaml.contains(expected), "{}: {}", cond, yaml);
+        }
+    }
+
+    #[test]
+    fn test_buildkite_compound_condition_errors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("t")
+            .run("true")
+            .when("branch == 'main' || tag != ''");
+
+        let err = p.to_buildkite().unwrap_err();
+        assert!(err.to_string().contains("only single branch, tag or event"));
+    }
+
+    #[test]
+    fn test_buildkite_invalid_pipeline() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("make").after(&["tset"]);
+
+        assert!(matches!(p.to_buildkite(), Err(ExportError::Invalid(_))));
+    }
+}
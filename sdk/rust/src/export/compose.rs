@@ -0,0 +1,485 @@
+//! Docker Compose export of a single task.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use super::{yaml_quote, ExportError};
+use crate::{Pipeline, ReadyProbe, SecretSource, TaskData};
+
+/// Network every service joins, so service names resolve as hostnames.
+const NETWORK: &str = "sykli";
+
+/// Healthcheck retries, one a second, when a service has no `ready_timeout`.
+const DEFAULT_READY_TIMEOUT: u32 = 30;
+
+pub(super) fn render(p: &Pipeline, task: &str) -> Result<String, ExportError> {
+    p.validate_for_emit()
+        .map_err(|e| ExportError::Invalid(e.to_string()))?;
+    let Some(t) = p.tasks.iter().find(|t| t.name == task) else {
+        let names: Vec<&str> = p.tasks.iter().map(|t| t.name.as_str()).collect();
+        let msg = match crate::suggest_task_name(task, &names) {
+            Some(s) => format!("unknown task {:?} (did you mean {:?}?)", task, s),
+            None => format!("unknown task {:?}", task),
+        };
+        return Err(ExportError::Invalid(msg));
+    };
+    let t = &*p.with_defaults(t);
+    let first = t.matrix.variants().into_iter().next();
+    let t = &*t.interpolate_matrix(|key| first.as_ref()?.get(key).map(str::to_string));
+    if t.container.is_none() && t.container_build.is_none() {
+        return Err(ExportError::unsupported(
+            &t.name,
+            "task without a container",
+            "compose runs every service in a container; set one with container()",
+        ));
+    }
+
+    let service = service_name(&t.name);
+    if let Some(s) = t.services.iter().find(|s| s.name == service) {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("service {:?}", s.name),
+            "it has the same name as the task's own compose service",
+        ));
+    }
+    if let Some(m) = t.mounts.iter().find(|m| m.mount_type == "git") {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("git mount {:?}", m.resource),
+            format!("clone the repository and bind-mount it at {}", m.path),
+        ));
+    }
+    if let Some(m) = t.mounts.iter().find(|m| m.is_download()) {
+        return Err(ExportError::unsupported(
+            &t.name,
+            &format!("file mount {:?}", m.resource),
+            format!("download the file and bind-mount it at {}", m.path),
+        ));
+    }
+    let env = environment(t)?;
+
+    let mut out = format!(
+        "# Generated by sykli for task {:?}. Run from the project root.\n",
+        t.name
+    );
+    let variants = t.matrix.variants();
+    if let Some(first) = variants.first() {
+        let values: Vec<String> = first
+            .values()
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        writeln!(
+            out,
+            "# Matrix: using {} (1 of {} combinations).",
+            values.join(", "),
+            variants.len()
+        )
+        .unwrap();
+    }
+    for ti in &t.task_inputs {
+        writeln!(
+            out,
+            "# Not available: input {:?} from {:?}; put it at {} yourself.",
+            ti.output, ti.from_task, ti.dest_path
+        )
+        .unwrap();
+    }
+    for s in &t.services {
+        if let Some(probe) = s.ready_probes.first() {
+            if !matches!(probe, ReadyProbe::Cmd(_)) {
+                writeln!(
+                    out,
+                    "# Not available: readiness {} of service {:?}; the task starts without waiting.",
+                    probe.describe(),
+                    s.name
+                )
+                .unwrap();
+            }
+        }
+    }
+    // Project names can't contain dots.
+    let project = format!("sykli-{}", service.replace('.', "-"));
+    writeln!(out, "name: {}", yaml_quote(&project)).unwrap();
+    writeln!(out, "services:").unwrap();
+    for s in &t.services {
+        writeln!(out, "  {}:", s.name).unwrap();
+        writeln!(out, "    image: {}", yaml_quote(&s.image)).unwrap();
+        if let Some(ref command) = s.command {
+            writeln!(out, "    command: {}", yaml_quote(&escape_dollars(command))).unwrap();
+        }
+        if !s.env.is_empty() {
+            writeln!(out, "    environment:").unwrap();
+            for (k, v) in &s.env {
+                writeln!(out, "      {}: {}", k, yaml_quote(&escape_dollars(v))).unwrap();
+            }
+        }
+        if !s.ports.is_empty() {
+            writeln!(out, "    expose:").unwrap();
+            for port in &s.ports {
+                writeln!(out, "      - \"{}\"", port).unwrap();
+            }
+        }
+        if let Some(ReadyProbe::Cmd(command)) = s.ready_probes.first() {
+            writeln!(out, "    healthcheck:").unwrap();
+            writeln!(
+                out,
+                "      test: [\"CMD-SHELL\", {}]",
+                yaml_quote(&escape_dollars(command))
+            )
+            .unwrap();
+            writeln!(out, "      interval: 1s").unwrap();
+            writeln!(
+                out,
+                "      retries: {}",
+                s.ready_timeout.unwrap_or(DEFAULT_READY_TIMEOUT)
+            )
+            .unwrap();
+        }
+        writeln!(out, "    networks:").unwrap();
+        writeln!(out, "      - {}", NETWORK).unwrap();
+    }
+
+    writeln!(out, "  {}:", service).unwrap();
+    if let Some(ref image) = t.container {
+        writeln!(out, "    image: {}", yaml_quote(image)).unwrap();
+    } else if let Some(ref cb) = t.container_build {
+        writeln!(out, "    build:").unwrap();
+        writeln!(
+            out,
+            "      context: {}",
+            yaml_quote(&bind_source(cb.context_path()))
+        )
+        .unwrap();
+        writeln!(out, "      dockerfile: {}", yaml_quote(&cb.dockerfile)).unwrap();
+    }
+    if let Some(ref workdir) = t.workdir {
+        writeln!(out, "    working_dir: {}", yaml_quote(workdir)).unwrap();
+    }
+    if let Some(ref entrypoint) = t.entrypoint {
+        writeln!(
+            out,
+            "    entrypoint: {}",
+            yaml_quote(&escape_dollars(entrypoint))
+        )
+        .unwrap();
+    }
+    if let Some(ref user) = t.user {
+        writeln!(out, "    user: {}", yaml_quote(user)).unwrap();
+    }
+    let argv = if t.args.is_empty() {
+        vec!["sh".to_string(), "-c".to_string(), t.script()]
+    } else {
+        t.args.clone()
+    };
+    let argv: Vec<String> = argv
+        .iter()
+        .map(|a| yaml_quote(&escape_dollars(a)))
+        .collect();
+    writeln!(out, "    command: [{}]", argv.join(", ")).unwrap();
+    if !env.is_empty() {
+        writeln!(out, "    environment:").unwrap();
+        for (k, v) in &env {
+            writeln!(out, "      {}: {}", k, yaml_quote(v)).unwrap();
+        }
+    }
+    if !t.runtime_env_files.is_empty() {
+        writeln!(out, "    env_file:").unwrap();
+        for path in &t.runtime_env_files {
+            writeln!(out, "      - {}", yaml_quote(path)).unwrap();
+        }
+    }
+    let mut volumes = BTreeMap::new();
+    if !t.mounts.is_empty() {
+        writeln!(out, "    volumes:").unwrap();
+        for m in &t.mounts {
+            let source = if m.is_bind() {
+                bind_source(m.host_path())
+            } else {
+                let name = volume_name(&m.resource);
+                volumes.insert(name.clone(), m.resource.as_str());
+                name
+            };
+            writeln!(
+                out,
+                "      - {}",
+                yaml_quote(&format!("{}:{}", source, m.path))
+            )
+            .unwrap();
+        }
+    }
+    if !t.services.is_empty() {
+        writeln!(out, "    depends_on:").unwrap();
+        let healthchecks = t
+            .services
+            .iter()
+            .any(|s| matches!(s.ready_probes.first(), Some(ReadyProbe::Cmd(_))));
+        for s in &t.services {
+            if !healthchecks {
+                writeln!(out, "      - {}", s.name).unwrap();
+                continue;
+            }
+            let condition = match s.ready_probes.first() {
+                Some(ReadyProbe::Cmd(_)) => "service_healthy",
+                _ => "service_started",
+            };
+            writeln!(out, "      {}:", s.name).unwrap();
+            writeln!(out, "        condition: {}", condition).unwrap();
+        }
+    }
+    writeln!(out, "    networks:").unwrap();
+    writeln!(out, "      - {}", NETWORK).unwrap();
+
+    writeln!(out, "networks:").unwrap();
+    writeln!(out, "  {}: {{}}", NETWORK).unwrap();
+    if !volumes.is_empty() {
+        writeln!(out, "volumes:").unwrap();
+        for (name, cache) in &volumes {
+            writeln!(out, "  {}:", name).unwrap();
+            writeln!(
+                out,
+                "    name: {}",
+                yaml_quote(&format!("sykli-cache-{}", cache))
+            )
+            .unwrap();
+        }
+    }
+    Ok(out)
+}
+
+/// Task env, first matrix combination and secrets, sorted by name. Secrets
+/// are interpolated by compose from the shell running it.
+fn environment(t: &TaskData) -> Result<BTreeMap<String, String>, ExportError> {
+    let mut env: BTreeMap<String, String> = t
+        .env
+        .iter()
+        .map(|(k, v)| (k.clone(), escape_dollars(v)))
+        .collect();
+    if let Some(first) = t.matrix.variants().first() {
+        env.extend(
+            first
+                .env()
+                .into_iter()
+                .map(|(k, v)| (k, escape_dollars(&v))),
+        );
+    }
+    for name in &t.secrets {
+        env.insert(
+            name.clone(),
+            format!("${{{}:?secret {} is not set}}", name, name),
+        );
+    }
+    for sr in &t.secret_refs {
+        if let Some(ref path) = sr.mount_path {
+            return Err(ExportError::unsupported(
+                &t.name,
+                &format!("secret file {:?}", sr.name),
+                format!("bind-mount the file at {} instead", path),
+            ));
+        }
+        match sr.source {
+            SecretSource::Env => {
+                let value = if sr.optional {
+                    format!("${{{}:-}}", sr.key)
+                } else {
+                    format!("${{{}:?secret {} is not set}}", sr.key, sr.key)
+                };
+                env.insert(sr.name.clone(), value);
+            }
+            SecretSource::File
+            | SecretSource::Vault
+            | SecretSource::K8s
+            | SecretSource::Aws
+            | SecretSource::Gcp
+            | SecretSource::Sops => {
+                return Err(ExportError::unsupported(
+                    &t.name,
+                    &format!("{} secret {:?}", sr.source.as_str(), sr.name),
+                    "export it into your shell and declare it with secret()",
+                ))
+            }
+        }
+    }
+    Ok(env)
+}
+
+/// Bind mount source for a directory's host path, relative to the compose
+/// file.
+fn bind_source(dir: &str) -> String {
+    match dir {
+        "." => ".".to_string(),
+        dir if dir.starts_with('/') || dir.starts_with("./") || dir.starts_with("../") => {
+            dir.to_string()
+        }
+        dir => format!("./{}", dir),
+    }
+}
+
+/// Compose service names allow letters, digits, `.`, `_` and `-`.
+fn service_name(task: &str) -> String {
+    task.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Key for a cache volume in the compose file's `volumes:` section.
+fn volume_name(cache: &str) -> String {
+    service_name(cache)
+}
+
+/// Escapes `$` so compose doesn't interpolate it.
+fn escape_dollars(s: &str) -> String {
+    s.replace('$', "$$")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{compose_for_task, ExportError};
+    use crate::{Pipeline, SecretRef};
+
+    /// The `integration` task from `examples/06-matrix`.
+    fn matrix_example() -> Pipeline {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let cache = p.cache("cargo-1.75");
+        let _ = p
+            .task("test-rust-1.75")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount_cache(&cache, "/usr/local/cargo/registry")
+            .workdir("/src")
+            .run("cargo test");
+        let _ = p
+            .task("integration")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .service("postgres:15", "db")
+            .service("redis:7", "cache")
+            .env(
+                "DATABASE_URL",
+                "postgres://postgres:postgres@db:5432/test?sslmode=disable",
+            )
+            .env("REDIS_URL", "redis://cache:6379")
+            .run("cargo test --features integration")
+            .timeout(300)
+            .after(&["test-rust-1.75"]);
+        p
+    }
+
+    #[test]
+    fn test_compose_golden() {
+        let yaml = compose_for_task(&matrix_example(), "integration").unwrap();
+        assert_eq!(
+            yaml,
+            include_str!("../../tests/golden/compose-integration.yml")
+        );
+    }
+
+    #[test]
+    fn test_compose_caches_secrets_and_matrix() {
+        let mut p = Pipeline::new();
+        let web = p.dir("web");
+        let npm = p.cache("npm");
+        let _ = p
+            .task("e2e")
+            .container("node:20")
+            .mount(&web, "/app")
+            .mount_cache(&npm, "/root/.npm")
+            .matrix("browser", &["chromium", "firefox"])
+            .secret("NPM_TOKEN")
+            .secret_from("API_KEY", SecretRef::from_env("STAGING_API_KEY"))
+            .entrypoint("")
+            .user("node")
+            .run("npm test -- --browser=$browser");
+
+        let yaml = compose_for_task(&p, "e2e").unwrap();
+        assert!(yaml.contains("    entrypoint: \"\"\n    user: \"node\"\n"));
+        assert!(yaml.contains("# Matrix: using browser=chromium (1 of 2 combinations).\n"));
+        assert!(yaml.contains("command: [\"sh\", \"-c\", \"npm test -- --browser=$$browser\"]\n"));
+        assert!(yaml.contains("      - \"./web:/app\"\n      - \"npm:/root/.npm\"\n"));
+        assert!(yaml.contains(
+            "      API_KEY: \"${STAGING_API_KEY:?secret STAGING_API_KEY is not set}\"\n"
+        ));
+        assert!(yaml.contains("      browser: \"chromium\"\n"));
+        assert!(yaml.ends_with("volumes:\n  npm:\n    name: \"sykli-cache-npm\"\n"));
+    }
+
+    #[test]
+    fn test_compose_service_config() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .mount_cwd()
+            .service_with("postgres:15", "db", |s| {
+                s.env("POSTGRES_PASSWORD", "te$t")
+                    .port(5433)
+                    .command("postgres -p 5433")
+            })
+            .run("cargo test");
+
+        let yaml = compose_for_task(&p, "test").unwrap();
+        assert!(yaml.contains(
+            "  db:\n    image: \"postgres:15\"\n    command: \"postgres -p 5433\"\n    \
+             environment:\n      POSTGRES_PASSWORD: \"te$$t\"\n    expose:\n      - \"5433\"\n"
+        ));
+    }
+
+    #[test]
+    fn test_compose_service_readiness() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .mount_cwd()
+            .service_with("postgres:15", "db", |s| {
+                s.ready_cmd("pg_isready -U postgres").ready_timeout(60)
+            })
+            .service_with("redis:7", "cache", |s| s.ready_tcp(6379))
+            .run("cargo test");
+
+        let yaml = compose_for_task(&p, "test").unwrap();
+        assert!(yaml.contains(
+            "# Not available: readiness tcp:6379 of service \"cache\"; the task starts without waiting.\n"
+        ));
+        assert!(yaml.contains(
+            "    healthcheck:\n      test: [\"CMD-SHELL\", \"pg_isready -U postgres\"]\n      \
+             interval: 1s\n      retries: 60\n"
+        ));
+        assert!(yaml.contains(
+            "    depends_on:\n      db:\n        condition: service_healthy\n      cache:\n        \
+             condition: service_started\n"
+        ));
+    }
+
+    #[test]
+    fn test_compose_errors() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("deploy")
+            .container("alpine")
+            .run("./deploy.sh")
+            .secret_from("KEY", SecretRef::from_vault("secret/data/app#key"));
+
+        let err = compose_for_task(&p, "lint").unwrap_err();
+        assert!(
+            err.to_string().contains("task without a container"),
+            "{}",
+            err
+        );
+        let err = compose_for_task(&p, "deploy").unwrap_err();
+        assert!(err.to_string().contains("vault secret \"KEY\""), "{}", err);
+        assert_eq!(
+            compose_for_task(&p, "deplyo").unwrap_err(),
+            ExportError::Invalid("unknown task \"deplyo\" (did you mean \"deploy\"?)".to_string())
+        );
+    }
+}
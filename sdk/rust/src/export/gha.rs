@@ -0,0 +1,742 @@
+//! GitHub Actions workflow export.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+use super::{shell_quote, uniform_run_when, yaml_quote, ExportError, ExportWarning, Ids};
+use crate::condition::{self, Comparison, Expr, Literal, Op};
+use crate::matrix::env_alias;
+use crate::{NodeKind, Pipeline, RunWhen, SecretSource, TaskData};
+
+const RUNNER: &str = "ubuntu-latest";
+const CHECKOUT: &str = "actions/checkout@v4";
+
+pub(super) fn render(p: &Pipeline) -> Result<(String, Vec<ExportWarning>), ExportError> {
+    p.validate_for_emit()
+        .map_err(|e| ExportError::Invalid(e.to_string()))?;
+
+    let mut job_ids = Ids::new('_', job_id);
+    let ids: HashMap<&str, String> = p
+        .tasks
+        .iter()
+        .filter(|t| t.kind != NodeKind::Review)
+        .map(|t| (t.name.as_str(), job_ids.alloc(&t.name)))
+        .collect();
+
+    let mut warnings = Vec::new();
+    let mut out = String::from("name: sykli\non:\n  push:\n  pull_request:\njobs:\n");
+    for t in &p.tasks {
+        let t = &*p.with_defaults(t);
+        let t = &*t.interpolate_matrix(|key| Some(format!("${{{{ matrix.{} }}}}", key)));
+        let mut job = Job {
+            task: t,
+            notes: Vec::new(),
+        };
+        if t.kind == NodeKind::Review {
+            job.note("review node", "GitHub Actions has no equivalent; skipped");
+            writeln!(out, "  # {:?}: {}", t.name, comment(&job.notes[0])).unwrap();
+        } else {
+            job.write(&mut out, &ids);
+        }
+        warnings.extend(job.notes);
+    }
+    Ok((out, warnings))
+}
+
+/// One task being written as a job, with what couldn't be exported.
+struct Job<'a> {
+    task: &'a TaskData,
+    notes: Vec<ExportWarning>,
+}
+
+/// A warning as a YAML comment on the job.
+fn comment(w: &ExportWarning) -> String {
+    format!("{} not exported: {}", w.feature, w.reason)
+}
+
+impl Job<'_> {
+    fn note(&mut self, what: &str, why: &str) {
+        self.notes
+            .push(ExportWarning::new(&self.task.name, what, why));
+    }
+
+    fn write(&mut self, out: &mut String, ids: &HashMap<&str, String>) {
+        let t = self.task;
+        let condition = t.effective_condition().map(|cond| {
+            condition::parse(&cond)
+                .ok()
+                .and_then(|expr| if_expr(&expr))
+                .ok_or(cond)
+        });
+        self.collect_notes(ids);
+
+        let mut body = String::new();
+        writeln!(body, "    name: {}", yaml_quote(&t.name)).unwrap();
+        writeln!(body, "    runs-on: {}", RUNNER).unwrap();
+        if let Some(ref image) = t.container {
+            writeln!(body, "    container:").unwrap();
+            writeln!(body, "      image: {}", yaml_quote(image)).unwrap();
+            if let Some(ref user) = t.user {
+                let options = format!("--user {}", user);
+                writeln!(body, "      options: {}", yaml_quote(&options)).unwrap();
+            }
+            let volumes: Vec<String> = t
+                .mounts
+                .iter()
+                .filter(|m| m.is_bind())
+                .map(|m| {
+                    let dir = m.host_path();
+                    let host = match dir {
+                        "." => "${{ github.workspace }}".to_string(),
+                        dir => format!("${{{{ github.workspace }}}}/{}", dir),
+                    };
+                    format!("{}:{}", host, m.path)
+                })
+                .collect();
+            if !volumes.is_empty() {
+                writeln!(body, "      volumes:").unwrap();
+                for v in &volumes {
+                    writeln!(body, "        - {}", yaml_quote(v)).unwrap();
+                }
+            }
+        }
+        if !t.services.is_empty() {
+            writeln!(body, "    services:").unwrap();
+            for s in &t.services {
+                writeln!(body, "      {}:", s.name).unwrap();
+                writeln!(body, "        image: {}", yaml_quote(&s.image)).unwrap();
+                if !s.env.is_empty() {
+                    writeln!(body, "        env:").unwrap();
+                    for (k, v) in &s.env {
+                        writeln!(body, "          {}: {}", k, yaml_quote(v)).unwrap();
+                    }
+                }
+                if !s.ports.is_empty() {
+                    writeln!(body, "        ports:").unwrap();
+                    for port in &s.ports {
+                        writeln!(body, "          - {}", port).unwrap();
+                    }
+                }
+            }
+        }
+        let needs: Vec<&String> = t
+            .depends_on
+            .iter()
+            .filter_map(|d| ids.get(d.as_str()))
+            .collect();
+        if !needs.is_empty() {
+            writeln!(body, "    needs:").unwrap();
+            for id in needs {
+                writeln!(body, "      - {}", id).unwrap();
+            }
+        }
+        // Without a status function, `if:` only runs once all needs succeed.
+        let status = match uniform_run_when(t) {
+            Some(RunWhen::Success) => Some(None),
+            Some(RunWhen::Failure) => Some(Some("failure()")),
+            Some(RunWhen::Always) => Some(Some("always()")),
+            None => None,
+        };
+        match (condition, status) {
+            (_, None) => {
+                self.note(
+                    "dependencies with different run_when",
+                    "a job has one status check for all its needs; the job is disabled",
+                );
+                writeln!(body, "    if: false").unwrap();
+            }
+            (Some(Err(cond)), _) => {
+                // Never run a task whose condition we couldn't translate.
+                self.note(
+                    &format!("condition {:?}", cond),
+                    "no GitHub Actions expression; the job is disabled",
+                );
+                writeln!(body, "    if: false").unwrap();
+            }
+            (Some(Ok(Some(expr))), Some(None)) => {
+                writeln!(body, "    if: {}", yaml_quote(&expr)).unwrap()
+            }
+            (Some(Ok(Some(expr))), Some(Some(status))) => {
+                let expr = format!("{} && ({})", status, expr);
+                writeln!(body, "    if: {}", yaml_quote(&expr)).unwrap()
+            }
+            (Some(Ok(None)) | None, Some(Some(status))) => {
+                writeln!(body, "    if: {}", status).unwrap()
+            }
+            (Some(Ok(None)) | None, Some(None)) => {}
+        }
+        if t.gate.is_some() || t.manual {
+            self.note(
+                "approval",
+                &format!("add required reviewers to the {:?} environment", t.name),
+            );
+            writeln!(body, "    environment: {}", yaml_quote(&t.name)).unwrap();
+        }
+        if let Some(secs) = t.timeout {
+            writeln!(body, "    timeout-minutes: {}", secs.div_ceil(60)).unwrap();
+        }
+        if t.allow_failure {
+            writeln!(body, "    continue-on-error: true").unwrap();
+        }
+        if let Some(ref c) = t.concurrency {
+            writeln!(body, "    concurrency:").unwrap();
+            writeln!(body, "      group: {}", yaml_quote(&c.group)).unwrap();
+            if c.cancel_in_progress {
+                writeln!(body, "      cancel-in-progress: true").unwrap();
+            }
+        }
+        if !t.matrix.is_empty() {
+            writeln!(body, "    strategy:").unwrap();
+            writeln!(body, "      matrix:").unwrap();
+            for (key, values) in t.matrix.iter() {
+                writeln!(body, "        {}:", key).unwrap();
+                for v in values {
+                    writeln!(body, "          - {}", yaml_quote(v)).unwrap();
+                }
+            }
+            // Actions applies exclude before include, as sykli does.
+            for (list, entries) in [
+                ("exclude", t.matrix.excludes()),
+                ("include", t.matrix.includes()),
+            ] {
+                if entries.is_empty() {
+                    continue;
+                }
+                writeln!(body, "        {}:", list).unwrap();
+                for entry in entries {
+                    let mut prefix = "          - ";
+                    for (k, v) in entry {
+                        writeln!(body, "{}{}: {}", prefix, k, yaml_quote(v)).unwrap();
+                        prefix = "            ";
+                    }
+                }
+            }
+        }
+
+        let env = self.env();
+        if !env.is_empty() {
+            writeln!(body, "    env:").unwrap();
+            for (k, v) in &env {
+                writeln!(body, "      {}: {}", k, yaml_quote(v)).unwrap();
+            }
+        }
+
+        writeln!(body, "    steps:").unwrap();
+        if let Some(ref gate) = t.gate {
+            let message = gate.message.as_deref().unwrap_or("approved");
+            let echo = format!("echo {}", shell_quote(message));
+            writeln!(body, "      - run: {}", yaml_quote(&echo)).unwrap();
+        } else {
+            writeln!(body, "      - uses: {}", CHECKOUT).unwrap();
+            // Steps stay separate steps, so each is logged on its own.
+            let commands = if t.steps.is_empty() {
+                std::slice::from_ref(&t.command)
+            } else {
+                t.steps.as_slice()
+            };
+            for cmd in commands {
+                writeln!(body, "      - run: {}", yaml_quote(cmd)).unwrap();
+                if let Some(ref workdir) = t.workdir {
+                    writeln!(body, "        working-directory: {}", yaml_quote(workdir)).unwrap();
+                }
+            }
+        }
+
+        writeln!(out, "  {}:", ids[t.name.as_str()]).unwrap();
+        for n in &self.notes {
+            writeln!(out, "    # {}", comment(n)).unwrap();
+        }
+        out.push_str(&body);
+    }
+
+    /// Records everything about the task that has no GitHub Actions
+    /// counterpart.
+    fn collect_notes(&mut self, ids: &HashMap<&str, String>) {
+        let t = self.task;
+        for m in t.mounts.iter().filter(|m| m.mount_type == "cache") {
+            self.note(
+                &format!("cache mount {:?} at {}", m.resource, m.path),
+                "use actions/cache",
+            );
+        }
+        for m in t.mounts.iter().filter(|m| m.mount_type == "git") {
+            self.note(
+                &format!("git mount {:?} at {}", m.resource, m.path),
+                "check it out with actions/checkout (repository, ref and path)",
+            );
+        }
+        for m in t.mounts.iter().filter(|m| m.is_download()) {
+            self.note(
+                &format!("file mount {:?} at {}", m.resource, m.path),
+                "download it in a step before the task",
+            );
+        }
+        if t.container.is_none() && t.mounts.iter().any(|m| m.mount_type == "directory") {
+            self.note(
+                "directory mounts",
+                "the job runs in the checked-out workspace instead",
+            );
+        }
+        for ti in &t.task_inputs {
+            self.note(
+                &format!("input {:?} from {:?}", ti.output, ti.from_task),
+                "pass it with actions/upload-artifact and actions/download-artifact",
+            );
+        }
+        if t.entrypoint.as_deref().is_some_and(|e| !e.is_empty()) {
+            self.note(
+                "entrypoint",
+                "job containers run with their own entrypoint; call it from the command",
+            );
+        }
+        for s in &t.services {
+            if let Some(probe) = s.ready_probes.first() {
+                self.note(
+                    &format!("readiness {} of service {:?}", probe.describe(), s.name),
+                    "add --health-cmd to the service's options",
+                );
+            }
+        }
+        for s in t.services.iter().filter(|s| s.command.is_some()) {
+            self.note(
+                &format!("command of service {:?}", s.name),
+                "service containers always run their image's default command",
+            );
+        }
+        if t.container_build.is_some() {
+            self.note(
+                "container_from_dockerfile",
+                "build and push the image in an earlier job",
+            );
+        }
+        for sr in &t.secret_refs {
+            if let Some(ref path) = sr.mount_path {
+                self.note(
+                    &format!("secret file {:?} at {}", sr.name, path),
+                    "write it to the file in a step from a repository secret",
+                );
+            } else if !matches!(sr.source, SecretSource::Env) {
+                self.note(
+                    &format!("{} secret {:?}", sr.source.as_str(), sr.name),
+                    "store it as a repository secret and use secret()",
+                );
+            }
+        }
+        for st in &t.secret_templates {
+            self.note(
+                &format!("secret template {:?}", st.dest),
+                "render it in the command from repository secrets",
+            );
+        }
+        for path in &t.runtime_env_files {
+            self.note(
+                &format!("env_file_lazy {:?}", path),
+                "append its variables to $GITHUB_ENV in an earlier step",
+            );
+        }
+        for (output, _) in &t.publish {
+            self.note(
+                &format!("publish_output {:?}", output),
+                "upload it from a follow-up step",
+            );
+        }
+        if let Some(ref retry) = t.retry {
+            self.note(
+                &format!("retry({})", retry.max),
+                "GitHub Actions can't retry a job",
+            );
+        }
+        for dep in &t.depends_on {
+            if !ids.contains_key(dep.as_str()) {
+                self.note(
+                    &format!("dependency on review node {:?}", dep),
+                    "the review is skipped",
+                );
+            }
+        }
+        let mut selected: Vec<&String> = t.dep_selectors.keys().collect();
+        selected.sort();
+        for dep in selected {
+            self.note(
+                &format!("dependency on some variants of {:?}", dep),
+                "the job waits for every variant",
+            );
+        }
+    }
+
+    /// Task env, matrix values and secrets, sorted by name.
+    fn env(&self) -> BTreeMap<String, String> {
+        let t = self.task;
+        let mut env: BTreeMap<String, String> =
+            t.env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        for key in t.matrix.keys() {
+            let value = format!("${{{{ matrix.{} }}}}", key);
+            env.insert(key.to_string(), value.clone());
+            env.insert(env_alias(key), value);
+        }
+        for name in &t.secrets {
+            env.insert(name.clone(), format!("${{{{ secrets.{} }}}}", name));
+        }
+        for sr in &t.secret_refs {
+            if matches!(sr.source, SecretSource::Env) && sr.mount_path.is_none() {
+                env.insert(sr.name.clone(), format!("${{{{ secrets.{} }}}}", sr.key));
+            }
+        }
+        env
+    }
+}
+
+/// Translates a condition into a job `if:` expression. `Some(None)` means
+/// always true on GitHub Actions; `None` means it can't be expressed.
+fn if_expr(expr: &Expr) -> Option<Option<String>> {
+    match expr {
+        Expr::Compare(c) => compare_expr(c),
+        Expr::Not(inner) => Some(Some(format!("!({})", if_expr(inner)??))),
+        Expr::And(lhs, rhs) => match (if_expr(lhs)?, if_expr(rhs)?) {
+            (Some(l), Some(r)) => Some(Some(format!("({}) && ({})", l, r))),
+            (l, r) => Some(l.or(r)),
+        },
+        Expr::Or(lhs, rhs) => match (if_expr(lhs)?, if_expr(rhs)?) {
+            (Some(l), Some(r)) => Some(Some(format!("({}) || ({})", l, r))),
+            _ => Some(None),
+        },
+    }
+}
+
+fn compare_expr(c: &Comparison) -> Option<Option<String>> {
+    let Comparison { field, op, value } = c;
+    let value = match value {
+        Literal::Str(s) => s.as_str(),
+        Literal::Bool(true) if field == "ci" && *op == Op::Eq => return Some(None),
+        Literal::Bool(_) => return None,
+    };
+    let (lhs, prefix) = match field.as_str() {
+        "branch" => ("github.ref", "refs/heads/"),
+        "tag" if value.is_empty() && *op == Op::Ne => {
+            return Some(Some("startsWith(github.ref, 'refs/tags/')".to_string()))
+        }
+        "tag" => ("github.ref", "refs/tags/"),
+        "event" => ("github.event_name", ""),
+        "pr_target" => ("github.base_ref", ""),
+        "cron" => ("github.event.schedule", ""),
+        _ => return None,
+    };
+    let expr = match op {
+        Op::Eq => format!("{} == {}", lhs, literal(&format!("{}{}", prefix, value))),
+        Op::Ne => format!("{} != {}", lhs, literal(&format!("{}{}", prefix, value))),
+        Op::Matches => {
+            // Expressions have no globs; a trailing `*` is a prefix match.
+            let head = value
+                .strip_suffix('*')
+                .filter(|h| !h.contains(['*', '?']))?;
+            format!(
+                "startsWith({}, {})",
+                lhs,
+                literal(&format!("{}{}", prefix, head))
+            )
+        }
+    };
+    Some(Some(expr))
+}
+
+/// Quotes a string literal for a GitHub Actions expression.
+fn literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// A job ID for `name`: letters, digits, `-` and `_`, starting with a letter
+/// or `_`.
+fn job_id(name: &str) -> String {
+    let mut id: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        id.insert(0, '_');
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{github_actions, github_actions_with_warnings, ExportError};
+    use crate::{Pipeline, SecretRef};
+
+    fn golden_pipeline() -> Pipeline {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let registry = p.cache("cargo-registry");
+
+        let _ = p
+            .task("lint")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .workdir("/src")
+            .env("CARGO_TERM_COLOR", "always")
+            .run("cargo clippy -- -D warnings");
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux", "macos"])
+            .service("postgres:16", "db")
+            .retry(2)
+            .timeout(90);
+        let _ = p
+            .task("build")
+            .run("cargo build --release")
+            .output("binary", "target/release/app")
+            .after(&["lint", "test"]);
+        let _ = p
+            .task("package")
+            .run("docker build -t app:$GITHUB_SHA .")
+            .input_from("build", "binary", "./app")
+            .when("tag matches 'v*'");
+        let _ = p
+            .gate("approve")
+            .gate_message("Ship it?")
+            .after(&["package"]);
+        let _ = p
+            .task("deploy.prod")
+            .run("./deploy.sh")
+            .secret("DEPLOY_TOKEN")
+            .secret_from("KUBECONFIG", SecretRef::from_env("PROD_KUBECONFIG"))
+            .when_cond(crate::Condition::branch("main"))
+            .after(&["approve"]);
+        p
+    }
+
+    #[test]
+    fn test_github_actions_golden() {
+        let (yaml, warnings) = github_actions_with_warnings(&golden_pipeline()).unwrap();
+        assert_eq!(yaml, include_str!("../../tests/golden/github_actions.yml"));
+        let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            [
+                r#"task "lint": cache mount "cargo-registry" at /usr/local/cargo/registry not exported: use actions/cache"#,
+                r#"task "test": retry(2) not exported: GitHub Actions can't retry a job"#,
+                r#"task "package": input "binary" from "build" not exported: pass it with actions/upload-artifact and actions/download-artifact"#,
+                r#"task "approve": approval not exported: add required reviewers to the "approve" environment"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_github_actions_condition_mapping() {
+        let cases = [
+            (
+                "branch != 'main'",
+                "if: \"github.ref != 'refs/heads/main'\"",
+            ),
+            (
+                "branch matches 'release/*'",
+                "if: \"startsWith(github.ref, 'refs/heads/release/')\"",
+            ),
+            ("tag != ''", "if: \"startsWith(github.ref, 'refs/tags/')\""),
+            (
+                "event == 'pull_request' && !(pr_target == 'main')",
+                "if: \"(github.event_name == 'pull_request') && (!(github.base_ref == 'main'))\"",
+            ),
+            (
+                "ci == true && branch == 'main'",
+                "if: \"github.ref == 'refs/heads/main'\"",
+            ),
+        ];
+        for (cond, expected) in cases {
+            let mut p = Pipeline::new();
+            let _ = p.task("t").run("true").when(cond);
+            let yaml = github_actions(&p).unwrap();
+            assert!(yaml.contains(expected), "{}: {}", cond, yaml);
+        }
+    }
+
+    #[test]
+    fn test_github_actions_untranslatable_condition_disables_job() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("docs")
+            .run("make docs")
+            .when("changed matches 'docs/**'");
+
+        let (yaml, warnings) = github_actions_with_warnings(&p).unwrap();
+        assert!(yaml.contains("    if: false\n"));
+        assert_eq!(warnings[0].task, "docs");
+        assert_eq!(
+            warnings[0].feature,
+            "condition \"changed matches 'docs/**'\""
+        );
+    }
+
+    #[test]
+    fn test_github_actions_job_ids() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build.linux").run("make");
+        let _ = p.task("build/linux").run("make");
+        let _ = p.task("1st").run("true").after(&["build/linux"]);
+
+        let yaml = github_actions(&p).unwrap();
+        assert!(yaml.contains("  build_linux:\n    name: \"build.linux\"\n"));
+        assert!(yaml.contains("  build_linux_2:\n    name: \"build/linux\"\n"));
+        assert!(yaml.contains("  _1st:\n"));
+        assert!(yaml.contains("    needs:\n      - build_linux_2\n"));
+    }
+
+    #[test]
+    fn test_github_actions_matrix_exclude_and_include() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.70", "1.80"])
+            .matrix("os", &["linux", "macos"])
+            .matrix_exclude(&[("rust", "1.70"), ("os", "macos")])
+            .matrix_include(&[("rust", "nightly"), ("os", "linux")])
+            .container("rust:{{matrix.rust}}");
+
+        let yaml = github_actions(&p).unwrap();
+        assert!(
+            yaml.contains("      image: \"rust:${{ matrix.rust }}\"\n"),
+            "{}",
+            yaml
+        );
+        assert!(
+            yaml.contains(
+                "        exclude:\n          - os: \"macos\"\n            rust: \"1.70\"\n        \
+                 include:\n          - os: \"linux\"\n            rust: \"nightly\"\n"
+            ),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_github_actions_allow_failure() {
+        let mut p = Pipeline::new();
+        let _ = p.task("audit").run("cargo audit").allow_failure(true);
+
+        let yaml = github_actions(&p).unwrap();
+        assert!(yaml.contains("    continue-on-error: true\n"), "{}", yaml);
+    }
+
+    #[test]
+    fn test_github_actions_concurrency_group() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .concurrency_group("staging-deploy")
+            .cancel_in_progress(true);
+
+        let yaml = github_actions(&p).unwrap();
+        assert!(
+            yaml.contains(
+                "    concurrency:\n      group: \"staging-deploy\"\n      cancel-in-progress: true\n"
+            ),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn test_github_actions_hook_deps() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build");
+        let _ = p.task("deploy").run("./deploy.sh").after(&["build"]);
+        let _ = p
+            .task("alert")
+            .run("./notify.sh")
+            .when("branch == 'main'")
+            .on_failure_of(&["deploy"]);
+        let _ = p
+            .task("logs")
+            .run("./upload-logs.sh")
+            .always_after(&["deploy"]);
+        let _ = p
+            .task("mixed")
+            .run("true")
+            .after(&["build"])
+            .on_failure_of(&["deploy"]);
+
+        let (yaml, warnings) = github_actions_with_warnings(&p).unwrap();
+        assert!(yaml.contains(
+            "  alert:\n    name: \"alert\"\n    runs-on: ubuntu-latest\n    needs:\n      - deploy\n    \
+             if: \"failure() && (github.ref == 'refs/heads/main')\"\n"
+        ), "{}", yaml);
+        assert!(yaml.contains("      - deploy\n    if: always()\n"));
+        assert!(yaml.contains("      - deploy\n    if: false\n"));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].task, "mixed");
+    }
+
+    #[test]
+    fn test_github_actions_matrix_selector_waits_for_all_variants() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"]);
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after_matrix("build", &[("os", "linux")]);
+
+        let (yaml, warnings) = github_actions_with_warnings(&p).unwrap();
+        assert!(yaml.contains("    needs:\n      - build\n"), "{}", yaml);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].task, "package");
+        assert_eq!(
+            warnings[0].feature,
+            "dependency on some variants of \"build\""
+        );
+    }
+
+    #[test]
+    fn test_github_actions_steps() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("check")
+            .workdir("/src")
+            .run_steps(&["cargo fmt --check", "cargo test"]);
+
+        let yaml = github_actions(&p).unwrap();
+        assert!(yaml.ends_with(
+            "      - run: \"cargo fmt --check\"\n        working-directory: \"/src\"\n      - run: \"cargo test\"\n        working-directory: \"/src\"\n"
+        ), "{}", yaml);
+    }
+
+    #[test]
+    fn test_github_actions_service_config() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .service_with("postgres:15", "db", |s| {
+                s.env("POSTGRES_PASSWORD", "test")
+                    .port(5432)
+                    .command("postgres -c fsync=off")
+            })
+            .run("cargo test");
+
+        let (yaml, warnings) = github_actions_with_warnings(&p).unwrap();
+        assert!(yaml.contains(
+            "      db:\n        image: \"postgres:15\"\n        env:\n          \
+             POSTGRES_PASSWORD: \"test\"\n        ports:\n          - 5432\n"
+        ));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].feature, "command of service \"db\"");
+    }
+
+    #[test]
+    fn test_github_actions_invalid_pipeline() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("make").after(&["tset"]);
+
+        assert!(matches!(github_actions(&p), Err(ExportError::Invalid(_))));
+    }
+}
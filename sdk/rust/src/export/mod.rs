@@ -0,0 +1,303 @@
+//! Export pipelines to other CI systems.
+//!
+//! [`Pipeline::to_shell_script`](crate::Pipeline::to_shell_script),
+//! [`compose_for_task`] and [`argo_workflow`] are always available. Other
+//! exporters live behind their own cargo feature so the default build stays
+//! dependency-free:
+//!
+//! - `export-buildkite` - [`Pipeline::to_buildkite`](crate::Pipeline::to_buildkite)
+//! - `export-gha` - [`github_actions`]
+//!
+//! Exporters run the same validation as `emit_to` first, then translate
+//! what the target system can express. Anything that can't be translated
+//! faithfully is reported as an [`ExportError`] rather than silently dropped,
+//! except by `github_actions` and `argo_workflow`, which are meant for
+//! migrations and return an [`ExportWarning`] for it instead.
+
+use std::collections::HashSet;
+use std::fmt;
+
+mod argo;
+#[cfg(feature = "export-buildkite")]
+mod buildkite;
+mod compose;
+#[cfg(feature = "export-gha")]
+mod gha;
+mod shell;
+
+/// Error returned when a pipeline can't be exported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportError {
+    /// The pipeline failed emit-time validation.
+    Invalid(String),
+    /// A task uses something the export format can't express.
+    Unsupported {
+        /// Task name.
+        task: String,
+        /// What couldn't be exported.
+        feature: String,
+        /// Why, and what to do instead.
+        reason: String,
+    },
+}
+
+impl ExportError {
+    pub(crate) fn unsupported(task: &str, feature: &str, reason: impl Into<String>) -> Self {
+        ExportError::Unsupported {
+            task: task.to_string(),
+            feature: feature.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Invalid(msg) => write!(f, "invalid pipeline: {}", msg),
+            ExportError::Unsupported {
+                task,
+                feature,
+                reason,
+            } => write!(
+                f,
+                "task {:?}: {} is not supported: {}",
+                task, feature, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// Something an exporter left out of its output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportWarning {
+    /// Task name.
+    pub task: String,
+    /// What was left out.
+    pub feature: String,
+    /// Why, and what to do instead.
+    pub reason: String,
+}
+
+impl ExportWarning {
+    pub(crate) fn new(task: &str, feature: &str, reason: impl Into<String>) -> Self {
+        ExportWarning {
+            task: task.to_string(),
+            feature: feature.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ExportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "task {:?}: {} not exported: {}",
+            self.task, self.feature, self.reason
+        )
+    }
+}
+
+/// Quotes a string as a YAML double-quoted scalar.
+///
+/// JSON string syntax is a subset of YAML double-quoted scalars, so this is
+/// safe for any content, including newlines and quotes.
+pub(crate) fn yaml_quote(s: &str) -> String {
+    serde_json::to_string(s).expect("string serialization cannot fail")
+}
+
+/// Quotes `s` as a single shell word.
+pub(crate) fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Hands out unique IDs derived from task names, for formats with stricter
+/// naming rules than sykli: `base` maps a name to the allowed characters and
+/// repeats get `<sep>2`, `<sep>3`, ...
+pub(crate) struct Ids {
+    used: HashSet<String>,
+    sep: char,
+    base: fn(&str) -> String,
+}
+
+impl Ids {
+    pub(crate) fn new(sep: char, base: fn(&str) -> String) -> Self {
+        Ids {
+            used: HashSet::new(),
+            sep,
+            base,
+        }
+    }
+
+    /// Marks `id` as taken, e.g. a name the format itself uses.
+    pub(crate) fn reserve(&mut self, id: &str) {
+        self.used.insert(id.to_string());
+    }
+
+    pub(crate) fn alloc(&mut self, name: &str) -> String {
+        let base = (self.base)(name);
+        let mut id = base.clone();
+        let mut n = 1;
+        while !self.used.insert(id.clone()) {
+            n += 1;
+            id = format!("{}{}{}", base, self.sep, n);
+        }
+        id
+    }
+}
+
+/// How every dependency of `t` must end for it to run, or `None` when they
+/// differ, e.g. one from `after()` and one from `on_failure_of()`.
+#[cfg(any(feature = "export-buildkite", feature = "export-gha"))]
+fn uniform_run_when(t: &crate::TaskData) -> Option<crate::RunWhen> {
+    let mut kinds = t.depends_on.iter().map(|d| {
+        t.run_when
+            .get(d)
+            .copied()
+            .unwrap_or(crate::RunWhen::Success)
+    });
+    let first = kinds.next().unwrap_or(crate::RunWhen::Success);
+    kinds.all(|k| k == first).then_some(first)
+}
+
+/// Renders the pipeline as a GitHub Actions workflow.
+///
+/// Requires the `export-gha` feature.
+///
+/// - tasks become jobs on `ubuntu-latest`, named after the task, with
+///   `depends_on` as `needs`; job IDs replace characters GitHub rejects with `_`
+/// - containers become `container:`, with directory mounts as workspace volumes
+/// - services become `services:`, matrix dimensions `strategy.matrix`
+/// - branch, tag, event, PR target and cron conditions become `if:` expressions
+/// - secrets are read from `${{ secrets.NAME }}` into the job env
+/// - timeouts map to `timeout-minutes` (rounded up); gates and manual tasks
+///   to an `environment:` named after the task
+/// - `allow_failure(true)` maps to `continue-on-error: true`, concurrency
+///   groups to the job's `concurrency:`
+/// - `on_failure_of` and `always_after` add `failure()` and `always()` to the
+///   job's `if:`; a job mixing them with other dependencies is disabled
+///
+/// Everything else (cache mounts, `input_from`, retries, file and vault
+/// secrets, ...) is left out with a `#` comment on the job; see
+/// [`github_actions_with_warnings`] for the list. A job whose condition can't
+/// be translated gets `if: false` rather than running unconditionally.
+///
+/// # Errors
+/// Returns [`ExportError::Invalid`] if the pipeline fails emit validation.
+///
+/// # Example
+/// ```rust
+/// use sykli::Pipeline;
+///
+/// let mut p = Pipeline::new();
+/// p.task("test").run("cargo test");
+/// let yaml = sykli::export::github_actions(&p).unwrap();
+/// assert!(yaml.contains("  test:\n    name: \"test\"\n"));
+/// ```
+#[cfg(feature = "export-gha")]
+pub fn github_actions(p: &crate::Pipeline) -> Result<String, ExportError> {
+    gha::render(p).map(|(yaml, _)| yaml)
+}
+
+/// Like [`github_actions`], also returning one warning per feature left out,
+/// e.g. `task "test": retry(2) not exported: GitHub Actions can't retry a job`
+/// once displayed.
+///
+/// # Errors
+/// As [`github_actions`].
+#[cfg(feature = "export-gha")]
+pub fn github_actions_with_warnings(
+    p: &crate::Pipeline,
+) -> Result<(String, Vec<ExportWarning>), ExportError> {
+    gha::render(p)
+}
+
+/// Renders one task and its services as a Docker Compose file, to debug the
+/// task locally with `docker compose up`.
+///
+/// Each [`Task::service`](crate::Task::service) becomes a compose service
+/// named after its hostname, and the task becomes one more, with its
+/// container, mounts, env, lazy env files, workdir and command. All of them
+/// share a network, so hostnames resolve as they do in the engine. Directory
+/// mounts are bind mounts and env files are read relative to the compose
+/// file, so write it to the project root; caches become named volumes. A matrix task uses its first combination.
+///
+/// # Errors
+/// Returns [`ExportError::Invalid`] if the pipeline fails emit validation or
+/// `task` doesn't exist, and [`ExportError::Unsupported`] if the task has no
+/// container or uses file or vault secrets.
+///
+/// # Example
+/// ```rust
+/// use sykli::Pipeline;
+///
+/// let mut p = Pipeline::new();
+/// p.task("integration")
+///     .container("rust:1.80")
+///     .service("postgres:16", "db")
+///     .run("cargo test");
+///
+/// let yaml = sykli::export::compose_for_task(&p, "integration").unwrap();
+/// assert!(yaml.contains("  db:\n    image: \"postgres:16\"\n"));
+/// ```
+pub fn compose_for_task(p: &crate::Pipeline, task: &str) -> Result<String, ExportError> {
+    compose::render(p, task)
+}
+
+/// Renders the pipeline as an Argo Workflows `Workflow` named `name-<suffix>`.
+///
+/// - tasks become container templates run from a DAG template, `main`, with
+///   `depends_on` as `dependencies`; template names are the task names in
+///   lowercase with anything but letters and digits replaced by `-`
+/// - `sh -c` runs the command, with env, workdir, retries
+///   (`retryStrategy`) and timeouts (`activeDeadlineSeconds`)
+/// - merged [`K8sOptions`](crate::K8sOptions) become container resources;
+///   `k8s_raw` node selectors, tolerations, service account, priority class,
+///   security context, labels and annotations go on the template
+/// - outputs and `input_from` become Argo artifacts passed between tasks,
+///   which needs an artifact repository configured for the namespace
+/// - branch and tag conditions become `when:` expressions on the workflow
+///   parameters `branch` and `tag`, e.g. `argo submit -p branch=main`
+/// - matrix tasks fan out with `withItems`; gates become `suspend` templates
+/// - `allow_failure(true)` becomes `continueOn: {failed: true}`
+/// - `on_failure_of` and `always_after` switch the task to a `depends:`
+///   expression, e.g. `deploy.Failed`
+/// - [`Pipeline::max_parallel`](crate::Pipeline::max_parallel) becomes the
+///   workflow's `parallelism`
+///
+/// Everything else (mounts, services, secrets, ...) is left out; see
+/// [`argo_workflow_with_warnings`]. A task whose condition can't be
+/// translated gets `when: "false"` rather than running unconditionally.
+///
+/// # Errors
+/// Returns [`ExportError::Invalid`] if the pipeline fails emit validation or
+/// `name` isn't a valid Kubernetes name, and [`ExportError::Unsupported`] if
+/// a task has no container.
+///
+/// # Example
+/// ```rust
+/// use sykli::Pipeline;
+///
+/// let mut p = Pipeline::new();
+/// p.task("test").container("rust:1.80").run("cargo test");
+///
+/// let yaml = sykli::export::argo_workflow(&p, "ci").unwrap();
+/// assert!(yaml.contains("  generateName: \"ci-\"\n"));
+/// ```
+pub fn argo_workflow(p: &crate::Pipeline, name: &str) -> Result<String, ExportError> {
+    argo::render(p, name).map(|(yaml, _)| yaml)
+}
+
+/// Like [`argo_workflow`], also returning one warning per feature left out.
+///
+/// # Errors
+/// As [`argo_workflow`].
+pub fn argo_workflow_with_warnings(
+    p: &crate::Pipeline,
+    name: &str,
+) -> Result<(String, Vec<ExportWarning>), ExportError> {
+    argo::render(p, name)
+}
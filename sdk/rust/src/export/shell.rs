@@ -0,0 +1,704 @@
+//! Standalone shell script export.
+
+use std::fmt::Write as _;
+
+use super::{shell_quote, ExportError};
+use crate::condition::{self, Comparison, Expr, Literal, Op};
+use crate::{NodeKind, Pipeline, RunWhen, SecretSource, TaskData};
+
+/// Helpers every generated script starts with.
+const PRELUDE: &str = r#"set -euo pipefail
+
+branch=$(git rev-parse --abbrev-ref HEAD 2>/dev/null || true)
+tag=$(git describe --tags --exact-match 2>/dev/null || true)
+sykli_passed=0
+sykli_skipped=0
+
+# sykli_run NAME RETRIES TIMEOUT COMMAND...
+sykli_run() {
+  sykli_task=$1
+  sykli_retries=$2
+  sykli_timeout=$3
+  shift 3
+  if [ "$sykli_timeout" -gt 0 ]; then
+    set -- timeout "$sykli_timeout" "$@"
+  fi
+  sykli_attempt=0
+  echo "==> $sykli_task" >&2
+  until "$@"; do
+    sykli_status=$?
+    sykli_attempt=$((sykli_attempt + 1))
+    if [ "$sykli_attempt" -gt "$sykli_retries" ]; then
+      echo "sykli: $sykli_task failed with exit code $sykli_status" >&2
+      echo "sykli: $sykli_passed passed, $sykli_skipped skipped before the failure" >&2
+      exit "$sykli_status"
+    fi
+    echo "sykli: retrying $sykli_task ($sykli_attempt/$sykli_retries)" >&2
+  done
+  sykli_passed=$((sykli_passed + 1))
+}
+
+# sykli_skip NAME REASON
+sykli_skip() {
+  echo "--> skipping $1: $2" >&2
+  sykli_skipped=$((sykli_skipped + 1))
+}
+
+# sykli_glob VALUE REGEX
+sykli_glob() {
+  [[ $1 =~ $2 ]]
+}
+
+# sykli_approve NAME MESSAGE
+sykli_approve() {
+  if [ ! -t 0 ]; then
+    echo "sykli: $1 needs approval, but stdin is not a terminal" >&2
+    exit 1
+  fi
+  printf '%s [y/N] ' "$2" >&2
+  read -r sykli_answer
+  case "$sykli_answer" in
+    y | Y | yes) ;;
+    *)
+      echo "sykli: $1 was not approved" >&2
+      exit 1
+      ;;
+  esac
+}
+"#;
+
+impl Pipeline {
+    /// Renders the pipeline as a bash script that needs nothing but `git`,
+    /// coreutils and, for container tasks, `docker`. It isn't POSIX `sh`: it
+    /// relies on `set -o pipefail` and runs under `#!/usr/bin/env bash`.
+    ///
+    /// Run it from the project root. Tasks run one at a time in dependency
+    /// order, and the script stops at the first failure with a summary:
+    ///
+    /// - container tasks run through `docker run --rm` with their mounts,
+    ///   workdir and env; caches become named docker volumes
+    /// - `retry(n)` reruns a failing task up to `n` times, `timeout` uses
+    ///   `timeout(1)`
+    /// - branch and tag conditions are checked against the local `git`
+    ///   checkout; conditions on anything else can't be checked, so the task
+    ///   is skipped
+    /// - matrix tasks run once per combination, with the values in the env
+    /// - gates and manual tasks prompt for approval on the terminal
+    /// - `on_failure_of` tasks are skipped, and `always_after` tasks only run
+    ///   when their dependencies pass, since the script stops at the first
+    ///   failure
+    /// - secrets are read from the environment, or from files for
+    ///   [`SecretRef::from_file`](crate::SecretRef::from_file)
+    ///
+    /// Vault secrets, services, `input_from`, published outputs, lazy env files,
+    /// `allow_failure` and retry backoff or exit code filters aren't available; the script says so in a comment on
+    /// the task.
+    ///
+    /// # Errors
+    /// Returns [`ExportError::Invalid`] if the pipeline fails emit validation,
+    /// e.g. for a dependency cycle or an unknown dependency.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test");
+    ///
+    /// let script = p.to_shell_script().unwrap();
+    /// assert!(script.contains("sykli_run 'test' 0 0 sh -c 'cargo test'\n"));
+    /// ```
+    pub fn to_shell_script(&self) -> Result<String, ExportError> {
+        self.validate_for_emit()
+            .map_err(|e| ExportError::Invalid(e.to_string()))?;
+
+        let mut out = String::from("#!/usr/bin/env bash\n");
+        out.push_str("# Generated by sykli. Run from the project root.\n");
+        out.push_str(PRELUDE);
+        for t in self.topological_sort() {
+            out.push('\n');
+            write_task(&mut out, &self.with_defaults(t));
+        }
+        out.push_str("\necho \"sykli: $sykli_passed passed, $sykli_skipped skipped\" >&2\n");
+        Ok(out)
+    }
+}
+
+fn write_task(out: &mut String, t: &TaskData) {
+    writeln!(out, "# {}", t.name).unwrap();
+    if t.kind == NodeKind::Review {
+        writeln!(out, "# review nodes can't run from a script; skipped").unwrap();
+        writeln!(out, "sykli_skip {} 'review node'", shell_quote(&t.name)).unwrap();
+        return;
+    }
+    for note in unsupported(t) {
+        writeln!(out, "# not available: {}", note).unwrap();
+    }
+    let on_failure = t.hook_deps(RunWhen::Failure);
+    if !on_failure.is_empty() {
+        writeln!(
+            out,
+            "sykli_skip {} {}",
+            shell_quote(&t.name),
+            shell_quote(&format!(
+                "runs on failure of {}, and the script stops at the first failure",
+                on_failure.join(", ")
+            ))
+        )
+        .unwrap();
+        return;
+    }
+
+    let condition = t.effective_condition();
+    let test = match condition.as_deref() {
+        None => None,
+        Some(cond) => match condition::parse(cond).ok().and_then(|e| shell_test(&e)) {
+            Some(test) => Some((cond, test)),
+            None => {
+                writeln!(
+                    out,
+                    "sykli_skip {} {}",
+                    shell_quote(&t.name),
+                    shell_quote(&format!("condition {:?} can't be checked locally", cond))
+                )
+                .unwrap();
+                return;
+            }
+        },
+    };
+    let indent = match test {
+        Some((_, ref test)) => {
+            writeln!(out, "if {}; then", test).unwrap();
+            "  "
+        }
+        None => "",
+    };
+
+    if let Some(ref gate) = t.gate {
+        let message = gate.message.as_deref().unwrap_or(&t.name);
+        writeln!(
+            out,
+            "{}sykli_approve {} {}",
+            indent,
+            shell_quote(&t.name),
+            shell_quote(message)
+        )
+        .unwrap();
+    } else {
+        if t.manual {
+            writeln!(
+                out,
+                "{}sykli_approve {} {}",
+                indent,
+                shell_quote(&t.name),
+                shell_quote(&format!("Run {}?", t.name))
+            )
+            .unwrap();
+        }
+        if let Some(ref cb) = t.container_build {
+            let context = cb.context_path();
+            writeln!(
+                out,
+                "{}docker build -t {} -f {} {}",
+                indent,
+                shell_quote(&built_image(&t.name)),
+                shell_quote(&format!("{}/{}", context, cb.dockerfile)),
+                shell_quote(context)
+            )
+            .unwrap();
+        }
+        if t.matrix.is_empty() {
+            let cmd = command(t, &[]);
+            write_run(out, indent, t, &t.name, &cmd);
+        } else {
+            for variant in t.matrix.variants() {
+                let vt = t.interpolate_matrix(|key| variant.get(key).map(str::to_string));
+                let cmd = command(&vt, &variant.env());
+                write_run(out, indent, t, &variant.task_name(&t.name), &cmd);
+            }
+        }
+    }
+
+    if let Some((cond, _)) = test {
+        writeln!(out, "else").unwrap();
+        writeln!(
+            out,
+            "  sykli_skip {} {}",
+            shell_quote(&t.name),
+            shell_quote(&format!("condition {:?} is false", cond))
+        )
+        .unwrap();
+        writeln!(out, "fi").unwrap();
+    }
+}
+
+fn write_run(out: &mut String, indent: &str, t: &TaskData, name: &str, cmd: &str) {
+    writeln!(
+        out,
+        "{}sykli_run {} {} {} {}",
+        indent,
+        shell_quote(name),
+        t.retry.as_ref().map_or(0, |r| r.max),
+        t.timeout.unwrap_or(0),
+        cmd
+    )
+    .unwrap();
+}
+
+/// The command line running the task, with `extra_env` (matrix values) set.
+fn command(t: &TaskData, extra_env: &[(String, String)]) -> String {
+    let mut env: Vec<(String, String)> = t
+        .env
+        .iter()
+        .map(|(k, v)| (k.clone(), shell_quote(v)))
+        .collect();
+    env.sort();
+    env.extend(extra_env.iter().map(|(k, v)| (k.clone(), shell_quote(v))));
+    // Secrets are expanded when the line runs, so they never appear in the
+    // script itself.
+    for name in &t.secrets {
+        env.push((name.clone(), required_var(name)));
+    }
+    for sr in t.secret_refs.iter().filter(|sr| sr.mount_path.is_none()) {
+        match sr.source {
+            SecretSource::Env if sr.optional => {
+                env.push((sr.name.clone(), format!("\"${{{}:-}}\"", sr.key)))
+            }
+            SecretSource::Env => env.push((sr.name.clone(), required_var(&sr.key))),
+            SecretSource::File => env.push((
+                sr.name.clone(),
+                format!("\"$(cat {})\"", shell_quote(&sr.key)),
+            )),
+            SecretSource::Vault
+            | SecretSource::K8s
+            | SecretSource::Aws
+            | SecretSource::Gcp
+            | SecretSource::Sops => {}
+        }
+    }
+
+    let image = match (&t.container, &t.container_build) {
+        (Some(image), _) => image.clone(),
+        (None, Some(_)) => built_image(&t.name),
+        (None, None) => {
+            let mut line = String::new();
+            if !env.is_empty() {
+                line.push_str("env");
+                for (k, v) in &env {
+                    write!(line, " {}={} ", k, v).unwrap();
+                }
+            }
+            let script = match t.workdir {
+                Some(ref workdir) => format!("cd {} && {}", shell_quote(workdir), t.script()),
+                None => t.script(),
+            };
+            write!(line, "sh -c {}", shell_quote(&script)).unwrap();
+            return line;
+        }
+    };
+
+    let mut line = String::from("docker run --rm");
+    // Git and downloaded file mounts are noted instead
+    let volumes = t
+        .mounts
+        .iter()
+        .filter(|m| m.is_bind() || m.mount_type == "cache");
+    for m in volumes {
+        let source = if m.is_bind() {
+            match m.host_path() {
+                "." => "\"$PWD\"".to_string(),
+                dir if dir.starts_with('/') => shell_quote(dir),
+                dir => format!("\"$PWD\"/{}", shell_quote(dir)),
+            }
+        } else {
+            shell_quote(&format!("sykli-cache-{}", m.resource))
+        };
+        write!(line, " -v {}:{}", source, shell_quote(&m.path)).unwrap();
+    }
+    if let Some(ref workdir) = t.workdir {
+        write!(line, " -w {}", shell_quote(workdir)).unwrap();
+    }
+    if let Some(ref entrypoint) = t.entrypoint {
+        write!(line, " --entrypoint {}", shell_quote(entrypoint)).unwrap();
+    }
+    if let Some(ref user) = t.user {
+        write!(line, " -u {}", shell_quote(user)).unwrap();
+    }
+    for (k, v) in &env {
+        write!(line, " -e {}={}", k, v).unwrap();
+    }
+    write!(line, " {}", shell_quote(&image)).unwrap();
+    if t.args.is_empty() {
+        write!(line, " sh -c {}", shell_quote(&t.script())).unwrap();
+    } else {
+        for arg in &t.args {
+            write!(line, " {}", shell_quote(arg)).unwrap();
+        }
+    }
+    line
+}
+
+/// Expands env var `name`, failing with a clear message if it's unset.
+fn required_var(name: &str) -> String {
+    format!("\"${{{}:?secret {} is not set}}\"", name, name)
+}
+
+/// Features the script can't provide, one line each.
+fn unsupported(t: &TaskData) -> Vec<String> {
+    let mut notes = Vec::new();
+    for sr in &t.secret_refs {
+        if let Some(ref path) = sr.mount_path {
+            notes.push(format!(
+                "secret file {} (write it to {} first)",
+                sr.name, path
+            ));
+        } else if !matches!(sr.source, SecretSource::Env | SecretSource::File) {
+            notes.push(format!(
+                "{} secret {} (set it in the environment)",
+                sr.source.as_str(),
+                sr.name
+            ));
+        }
+    }
+    for m in t.mounts.iter().filter(|m| m.mount_type == "git") {
+        notes.push(format!(
+            "git mount {} at {} (clone it first)",
+            m.resource, m.path
+        ));
+    }
+    for m in t.mounts.iter().filter(|m| m.is_download()) {
+        notes.push(format!(
+            "file mount {} at {} (download it first)",
+            m.resource, m.path
+        ));
+    }
+    for s in &t.services {
+        notes.push(format!("service {} ({})", s.name, s.image));
+    }
+    let always = t.hook_deps(RunWhen::Always);
+    if !always.is_empty() {
+        notes.push(format!(
+            "always_after {} (runs only if they pass)",
+            always.join(", ")
+        ));
+    }
+    for ti in &t.task_inputs {
+        notes.push(format!(
+            "input {:?} from {} (expected at {})",
+            ti.output, ti.from_task, ti.dest_path
+        ));
+    }
+    for st in &t.secret_templates {
+        notes.push(format!("secret template {}", st.dest));
+    }
+    for path in &t.runtime_env_files {
+        notes.push(format!("env_file_lazy {} (use env_file() instead)", path));
+    }
+    for (output, _) in &t.publish {
+        notes.push(format!("publishing output {:?}", output));
+    }
+    if t.allow_failure {
+        notes.push("allow_failure (the script still stops if this task fails)".to_string());
+    }
+    if t.retry.as_ref().is_some_and(|r| r.is_structured()) {
+        notes.push("retry policy (every failure is retried right away)".to_string());
+    }
+    notes
+}
+
+/// Tag for an image built by `container_from_dockerfile`.
+fn built_image(task: &str) -> String {
+    let name: String = task
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("sykli-{}", name)
+}
+
+/// Translates a condition into a shell test, or `None` if it depends on
+/// something only the engine knows.
+fn shell_test(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Compare(c) => compare_test(c),
+        Expr::Not(inner) => Some(format!("! {{ {}; }}", shell_test(inner)?)),
+        Expr::And(lhs, rhs) => Some(format!(
+            "{{ {} && {}; }}",
+            shell_test(lhs)?,
+            shell_test(rhs)?
+        )),
+        Expr::Or(lhs, rhs) => Some(format!(
+            "{{ {} || {}; }}",
+            shell_test(lhs)?,
+            shell_test(rhs)?
+        )),
+    }
+}
+
+fn compare_test(c: &Comparison) -> Option<String> {
+    let Comparison { field, op, value } = c;
+    let var = match field.as_str() {
+        "branch" => "\"$branch\"".to_string(),
+        "tag" => "\"$tag\"".to_string(),
+        "ci" => {
+            let in_ci = "[ -n \"${CI:-}\" ]".to_string();
+            return match (op, value) {
+                (Op::Eq, Literal::Bool(true)) | (Op::Ne, Literal::Bool(false)) => Some(in_ci),
+                (Op::Eq, Literal::Bool(false)) | (Op::Ne, Literal::Bool(true)) => {
+                    Some(format!("! {}", in_ci))
+                }
+                _ => None,
+            };
+        }
+        field => format!("\"${{{}:-}}\"", field.strip_prefix("env.")?),
+    };
+    let Literal::Str(value) = value else {
+        return None;
+    };
+    Some(match op {
+        Op::Eq => format!("[ {} = {} ]", var, shell_quote(value)),
+        Op::Ne => format!("[ {} != {} ]", var, shell_quote(value)),
+        Op::Matches => format!(
+            "sykli_glob {} {}",
+            var,
+            shell_quote(&condition::glob_regex(value))
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::{Command, Output};
+
+    use crate::export::ExportError;
+    use crate::{Pipeline, SecretRef};
+
+    /// Runs the script with bash in an empty directory outside any git repo.
+    fn run(p: &Pipeline, name: &str) -> Output {
+        let dir = std::env::temp_dir().join(format!("sykli-sh-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("pipeline.sh");
+        std::fs::write(&script, p.to_shell_script().unwrap()).unwrap();
+        let out = Command::new("bash")
+            .arg(&script)
+            .current_dir(&dir)
+            .env("SYKLI_SH_TEST", "on")
+            .output()
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        out
+    }
+
+    #[test]
+    fn test_script_runs_in_order() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("echo \"test $os {{matrix.os}}\"")
+            .matrix("os", &["linux", "it's mac"])
+            .after(&["setup"]);
+        let _ = p
+            .task("setup")
+            .run("echo \"setup $GREETING\"")
+            .env("GREETING", "hello 'world'");
+        let _ = p.task("deploy").run("echo deploy").when("branch == 'main'");
+        let _ = p
+            .task("flagged")
+            .run("echo flagged")
+            .when("env.SYKLI_SH_TEST == 'on'");
+
+        let out = run(&p, "order");
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap(),
+            "setup hello 'world'\nflagged\ntest linux linux\ntest it's mac it's mac\n"
+        );
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(stderr.contains("==> test-it_s_mac\n"), "{}", stderr);
+        assert!(stderr.contains("--> skipping deploy: condition \"branch == 'main'\" is false\n"));
+        assert!(
+            stderr.ends_with("sykli: 4 passed, 1 skipped\n"),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn test_script_stops_at_first_failure() {
+        let mut p = Pipeline::new();
+        let _ = p.task("ok").run("true");
+        let _ = p.task("flaky").run("exit 3").retry(1).after(&["ok"]);
+        let _ = p.task("after").run("echo after").after(&["flaky"]);
+
+        let out = run(&p, "failure");
+        assert_eq!(out.status.code(), Some(3));
+        assert!(out.stdout.is_empty());
+        let stderr = String::from_utf8(out.stderr).unwrap();
+        assert!(
+            stderr.contains("sykli: retrying flaky (1/1)\n"),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("sykli: flaky failed with exit code 3\n"));
+        assert!(stderr.contains("sykli: 1 passed, 0 skipped before the failure\n"));
+        assert!(!stderr.contains("==> after"));
+    }
+
+    #[test]
+    fn test_invalid_pipeline_is_rejected() {
+        let mut p = Pipeline::new();
+        let _ = p.task("a").run("true").after(&["b"]);
+        let _ = p.task("b").run("true").after(&["a"]);
+        let err = p.to_shell_script().unwrap_err();
+        assert!(
+            matches!(err, ExportError::Invalid(ref msg) if msg.contains("cycle")),
+            "{}",
+            err
+        );
+
+        let mut p = Pipeline::new();
+        let _ = p.task("c").run("true").after(&["missing"]);
+        assert!(matches!(p.to_shell_script(), Err(ExportError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_script_skips_failure_hooks() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("true");
+        let _ = p.task("alert").run("echo alert").on_failure_of(&["deploy"]);
+        let _ = p.task("logs").run("echo logs").always_after(&["deploy"]);
+
+        let script = p.to_shell_script().unwrap();
+        assert!(script.contains(
+            "sykli_skip 'alert' 'runs on failure of deploy, and the script stops at the first failure'\n"
+        ));
+        assert!(script.contains("# not available: always_after deploy (runs only if they pass)\n"));
+        let out = run(&p, "hooks");
+        assert_eq!(out.status.code(), Some(0));
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), "logs\n");
+    }
+
+    #[test]
+    fn test_script_timeout_and_missing_secret() {
+        let mut p = Pipeline::new();
+        let _ = p.task("slow").run("sleep 5").timeout(1);
+        assert_eq!(run(&p, "timeout").status.code(), Some(124));
+
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("true").secret("SYKLI_SH_UNSET_TOKEN");
+        let out = run(&p, "secret");
+        assert!(!out.status.success());
+        assert!(String::from_utf8(out.stderr)
+            .unwrap()
+            .contains("secret SYKLI_SH_UNSET_TOKEN is not set"));
+
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("echo \"[$SYKLI_SH_UNSET_TRACING]\"")
+            .secret_opt("SYKLI_SH_UNSET_TRACING");
+        let out = run(&p, "optional");
+        assert_eq!(out.status.code(), Some(0));
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn test_container_task_command() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let registry = p.cache("cargo-registry");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .workdir("/src")
+            .entrypoint("")
+            .user("1000:1000")
+            .env("RUSTFLAGS", "-D warnings")
+            .secret_from("TOKEN", SecretRef::from_env("CI_TOKEN"))
+            .run("cargo build")
+            .retry(2)
+            .timeout(600);
+
+        let script = p.to_shell_script().unwrap();
+        assert!(script.contains(
+            "sykli_run 'build' 2 600 docker run --rm -v \"$PWD\":'/src' \
+             -v 'sykli-cache-cargo-registry':'/usr/local/cargo/registry' -w '/src' \
+             --entrypoint '' -u '1000:1000' -e RUSTFLAGS='-D warnings' -e TOKEN=\"${CI_TOKEN:?secret CI_TOKEN is not set}\" \
+             'rust:1.75' sh -c 'cargo build'\n"
+        ));
+    }
+
+    #[test]
+    fn test_git_mount_noted() {
+        let mut p = Pipeline::new();
+        let charts = p.git("https://github.com/org/charts.git").tag("v1");
+        let _ = p
+            .task("deploy")
+            .container("alpine/helm:3")
+            .mount_git(&charts, "/charts")
+            .run("helm upgrade app /charts/app");
+
+        let script = p.to_shell_script().unwrap();
+        assert!(script.contains(
+            "# not available: git mount git:https://github.com/org/charts.git#v1 at /charts (clone it first)\n"
+        ));
+        assert!(script.contains("docker run --rm 'alpine/helm:3'"));
+    }
+
+    #[test]
+    fn test_file_resource_bind_mounted() {
+        let mut p = Pipeline::new();
+        let config = p.file("rustfmt.toml");
+        let _ = p
+            .task("fmt")
+            .container("rust:1.80")
+            .mount_file_res(&config, "/src/rustfmt.toml")
+            .run("cargo fmt --check");
+
+        let script = p.to_shell_script().unwrap();
+        assert!(script.contains(
+            "docker run --rm -v \"$PWD\"/'rustfmt.toml':'/src/rustfmt.toml' 'rust:1.80'"
+        ));
+        assert!(!script.contains("not available"));
+    }
+
+    #[test]
+    fn test_conditions() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("release")
+            .run("./release.sh")
+            .when("tag matches 'v*' && !(branch == 'wip')");
+        let _ = p.task("pr").run("true").when("event == 'pull_request'");
+
+        let script = p.to_shell_script().unwrap();
+        assert!(script.contains(
+            "if { sykli_glob \"$tag\" '^v[^/]*$' && ! { [ \"$branch\" = 'wip' ]; }; }; then\n  \
+             sykli_run 'release' 0 0 sh -c './release.sh'\nelse\n"
+        ));
+        assert!(script.contains(
+            "sykli_skip 'pr' 'condition \"event == '\\''pull_request'\\''\" can'\\''t be checked locally'\n"
+        ));
+
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("glob")
+            .run("echo glob")
+            .when("env.SYKLI_SH_TEST matches 'o?'");
+        let _ = p
+            .task("nested")
+            .run("echo nested")
+            .when("env.SYKLI_SH_TEST matches 'o*/*'");
+        let out = run(&p, "glob");
+        assert!(out.status.success(), "{:?}", out);
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), "glob\n");
+    }
+}
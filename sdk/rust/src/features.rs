@@ -0,0 +1,654 @@
+//! Which optional JSON features a pipeline uses, and the runner that reads them.
+//!
+//! Runners ignore fields they don't know, so a pipeline using a newer feature
+//! would silently lose it on an old runner. `emit` records the minimum runner
+//! version as `requires: {"runner": ">=X.Y.Z"}` whenever it's above the base
+//! version every runner supports. Versions are engine releases, as listed in
+//! the repository's `CHANGELOG.md`; fields the 0.6 engine already reads
+//! (gates, review nodes, `secret_refs`, `k8s`) are part of the base.
+//!
+//! ```rust
+//! use sykli::features::Feature;
+//! use sykli::Pipeline;
+//!
+//! let mut p = Pipeline::new();
+//! p.task("test").run("cargo test");
+//! p.gate("approve").after(&["test"]);
+//! assert_eq!(p.required_runner_version(), "0.6");
+//!
+//! p.task("deploy").run("./deploy.sh").manual();
+//! assert_eq!(p.required_features(), [Feature::ManualTasks]);
+//! assert_eq!(p.required_runner_version(), "0.6.2");
+//! ```
+//!
+//! Organisations pinned to an old runner can make emit fail instead, with
+//! [`Pipeline::max_runner_version`].
+
+use serde::Serialize;
+
+use crate::validate::{EmitError, Issues};
+use crate::{CacheSharing, Pipeline, RetryPolicy, RunWhen, TaskData};
+
+/// Runner version that reads everything without a [`Feature`] entry.
+pub const BASE_RUNNER_VERSION: &str = "0.6";
+
+/// An optional part of the pipeline JSON that needs a minimum runner version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Feature {
+    /// Files rendered from secrets (`secret_templates`).
+    SecretTemplates,
+    /// Images built from a Dockerfile (`container_build`).
+    ContainerBuild,
+    /// Uploaded outputs (`publish`).
+    PublishOutputs,
+    /// Explicit cache sharing keys (`key` on cache resources).
+    CacheKeys,
+    /// Tasks waiting for approval (`manual`).
+    ManualTasks,
+    /// Glob and directory outputs, and outputs with [`crate::OutputOptions`]
+    /// (`{"path", "type", ...}` under `outputs`).
+    TypedOutputs,
+    /// Env files the engine reads when the task runs (`env_files`).
+    EnvFiles,
+    /// Matrix combinations to drop or add (`matrix_exclude`,
+    /// `matrix_include`).
+    MatrixAdjustments,
+    /// Dependencies and inputs on some variants of a matrix task, by
+    /// variant name in `depends_on` and `task_inputs`.
+    MatrixSelectors,
+    /// Secrets written to files (`mount_path` on `secret_refs`).
+    SecretFiles,
+    /// Optional or unmasked secrets (`optional`, `mask` on `secret_refs`).
+    SecretOptions,
+    /// Remote git repositories (`git` resources and mounts).
+    GitResources,
+    /// Downloaded files (`http` resources, `file` mounts).
+    HttpResources,
+    /// Cache keys from file hashes and cache scopes (`key_files`, `scope`,
+    /// `fallback` on cache resources).
+    CacheKeyRecipes,
+    /// Cache size limits and eviction hints (`max_size`, `eviction` on cache
+    /// resources).
+    CacheLimits,
+    /// Locked or private cache mounts (`sharing` on mounts).
+    CacheSharing,
+    /// Single workspace files (`file` resources).
+    FileResources,
+    /// Tasks that run when a dependency fails or whenever it ends
+    /// (`run_when` on `depends_on_meta` entries). Older runners read these
+    /// edges as plain dependencies and would run the task only on success.
+    DependencyHooks,
+    /// Registered templates and the template each task came from
+    /// (`templates`, `template`).
+    Templates,
+    /// Tasks whose failure doesn't fail the pipeline (`allow_failure`).
+    AllowFailure,
+    /// Commands run and logged as separate steps (`steps`).
+    Steps,
+    /// Programs run without a shell (`args`).
+    ExecArgs,
+    /// Container entrypoint and user overrides (`entrypoint`, `user`).
+    EntrypointUser,
+    /// Service env, ports and commands (`env`, `ports`, `command` on
+    /// `services`).
+    ServiceOptions,
+    /// Service readiness probes (`readiness` on `services`).
+    ServiceReadiness,
+    /// Concurrency groups and parallelism caps (`concurrency` on tasks,
+    /// `max_parallel`).
+    ConcurrencyLimits,
+    /// Retries with backoff or exit-code filters (`retry` as an object).
+    RetryPolicies,
+}
+
+impl Feature {
+    /// Human-readable name, as used in errors.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::SecretTemplates => "secret templates",
+            Feature::ContainerBuild => "container_from_dockerfile",
+            Feature::PublishOutputs => "publish_output",
+            Feature::CacheKeys => "cache keys",
+            Feature::ManualTasks => "manual tasks",
+            Feature::TypedOutputs => "typed outputs",
+            Feature::EnvFiles => "env_file_lazy",
+            Feature::MatrixAdjustments => "matrix exclude/include",
+            Feature::MatrixSelectors => "matrix variant dependencies",
+            Feature::SecretFiles => "secret files",
+            Feature::SecretOptions => "secret options",
+            Feature::GitResources => "git resources",
+            Feature::HttpResources => "http resources",
+            Feature::CacheKeyRecipes => "cache key files and scopes",
+            Feature::CacheLimits => "cache size limits",
+            Feature::CacheSharing => "cache sharing modes",
+            Feature::FileResources => "file resources",
+            Feature::DependencyHooks => "dependency hooks",
+            Feature::Templates => "templates",
+            Feature::AllowFailure => "allow_failure",
+            Feature::Steps => "steps",
+            Feature::ExecArgs => "exec args",
+            Feature::EntrypointUser => "entrypoint and user",
+            Feature::ServiceOptions => "service options",
+            Feature::ServiceReadiness => "service readiness",
+            Feature::ConcurrencyLimits => "concurrency limits",
+            Feature::RetryPolicies => "retry policies",
+        }
+    }
+
+    /// First runner version that reads this feature, as `MAJOR.MINOR.PATCH`.
+    pub fn min_runner_version(&self) -> &'static str {
+        match self {
+            Feature::SecretTemplates
+            | Feature::ContainerBuild
+            | Feature::PublishOutputs
+            | Feature::CacheKeys
+            | Feature::ManualTasks
+            | Feature::TypedOutputs
+            | Feature::EnvFiles
+            | Feature::MatrixAdjustments
+            | Feature::MatrixSelectors
+            | Feature::SecretFiles
+            | Feature::SecretOptions
+            | Feature::GitResources
+            | Feature::HttpResources
+            | Feature::CacheKeyRecipes
+            | Feature::CacheLimits
+            | Feature::CacheSharing
+            | Feature::FileResources
+            | Feature::DependencyHooks
+            | Feature::Templates
+            | Feature::AllowFailure
+            | Feature::Steps
+            | Feature::ExecArgs
+            | Feature::EntrypointUser
+            | Feature::ServiceOptions
+            | Feature::ServiceReadiness
+            | Feature::ConcurrencyLimits
+            | Feature::RetryPolicies => "0.6.2",
+        }
+    }
+}
+
+/// Parses `MAJOR.MINOR` or `MAJOR.MINOR.PATCH` into a comparable triple.
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.split('.').map(|n| n.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/// Formats a parsed version, leaving out a zero patch.
+pub(crate) fn format_version((major, minor, patch): (u32, u32, u32)) -> String {
+    if patch == 0 {
+        format!("{}.{}", major, minor)
+    } else {
+        format!("{}.{}.{}", major, minor, patch)
+    }
+}
+
+fn version_of(feature: Feature) -> (u32, u32, u32) {
+    parse_version(feature.min_runner_version()).unwrap()
+}
+
+#[derive(Serialize)]
+pub(crate) struct JsonRequires {
+    runner: String,
+}
+
+impl Pipeline {
+    /// Features used by this pipeline, sorted and without duplicates.
+    pub fn required_features(&self) -> Vec<Feature> {
+        let mut features: Vec<Feature> = self.feature_uses().into_iter().map(|(f, _)| f).collect();
+        features.sort();
+        features.dedup();
+        features
+    }
+
+    /// Minimum runner version for this pipeline.
+    pub fn required_runner_version(&self) -> &'static str {
+        self.required_features()
+            .into_iter()
+            .max_by_key(|f| version_of(*f))
+            .map_or(BASE_RUNNER_VERSION, |f| f.min_runner_version())
+    }
+
+    /// Makes emit fail if the pipeline uses a feature newer than `version`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.max_runner_version("0.6");
+    /// p.task("deploy").run("./deploy.sh").manual();
+    ///
+    /// let err = p.emit_to(&mut Vec::new()).unwrap_err();
+    /// assert!(err.to_string().contains("manual tasks"));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `version` isn't `MAJOR.MINOR` or `MAJOR.MINOR.PATCH`.
+    pub fn max_runner_version(&mut self, version: &str) -> &mut Self {
+        let parsed = parse_version(version).unwrap_or_else(|| {
+            panic!(
+                "max_runner_version: {:?} is not MAJOR.MINOR[.PATCH]",
+                version
+            )
+        });
+        self.max_runner_version = Some(parsed);
+        self
+    }
+
+    /// Each feature use, with the task using it (None for pipeline-wide ones).
+    fn feature_uses(&self) -> Vec<(Feature, Option<&TaskData>)> {
+        let mut uses = Vec::new();
+        for t in &self.tasks {
+            let mut used = |feature, on: bool| {
+                if on {
+                    uses.push((feature, Some(t)));
+                }
+            };
+            let inherited = self.inherited_secrets(t);
+            let refs = || {
+                t.secret_refs
+                    .iter()
+                    .chain(inherited.iter().filter_map(|s| s.secret_ref.as_ref()))
+            };
+            used(Feature::SecretTemplates, !t.secret_templates.is_empty());
+            used(
+                Feature::SecretFiles,
+                refs().any(|sr| sr.mount_path.is_some()),
+            );
+            used(
+                Feature::SecretOptions,
+                refs().any(|sr| sr.optional || !sr.mask),
+            );
+            used(Feature::ContainerBuild, t.container_build.is_some());
+            used(
+                Feature::GitResources,
+                t.mounts.iter().any(|m| m.mount_type == "git"),
+            );
+            used(
+                Feature::HttpResources,
+                t.mounts.iter().any(|m| m.is_download()),
+            );
+            used(
+                Feature::FileResources,
+                t.mounts.iter().any(|m| m.file.is_some()),
+            );
+            used(
+                Feature::CacheSharing,
+                t.mounts.iter().any(|m| m.sharing != CacheSharing::Shared),
+            );
+            used(Feature::PublishOutputs, !t.publish.is_empty());
+            used(Feature::ManualTasks, t.manual);
+            used(
+                Feature::DependencyHooks,
+                t.run_when.values().any(|w| *w != RunWhen::Success),
+            );
+            used(
+                Feature::TypedOutputs,
+                !t.output_kinds.is_empty() || !t.output_options.is_empty(),
+            );
+            used(Feature::EnvFiles, !t.runtime_env_files.is_empty());
+            used(
+                Feature::MatrixAdjustments,
+                !t.matrix.excludes().is_empty() || !t.matrix.includes().is_empty(),
+            );
+            used(
+                Feature::MatrixSelectors,
+                !t.dep_selectors.is_empty() || t.task_inputs.iter().any(|i| !i.matrix.is_empty()),
+            );
+            used(Feature::AllowFailure, t.allow_failure);
+            used(Feature::Steps, !t.steps.is_empty());
+            used(Feature::ExecArgs, !t.args.is_empty());
+            used(
+                Feature::EntrypointUser,
+                t.entrypoint.is_some() || t.user.is_some(),
+            );
+            used(
+                Feature::ServiceOptions,
+                t.services
+                    .iter()
+                    .any(|s| !s.env.is_empty() || !s.ports.is_empty() || s.command.is_some()),
+            );
+            used(
+                Feature::ServiceReadiness,
+                t.services.iter().any(|s| !s.ready_probes.is_empty()),
+            );
+            used(Feature::ConcurrencyLimits, t.concurrency.is_some());
+            used(
+                Feature::RetryPolicies,
+                t.retry.as_ref().is_some_and(RetryPolicy::is_structured),
+            );
+        }
+        let caches = self.caches();
+        if caches.iter().any(|c| c.key.is_some()) {
+            uses.push((Feature::CacheKeys, None));
+        }
+        if caches.iter().any(|c| c.has_recipe()) {
+            uses.push((Feature::CacheKeyRecipes, None));
+        }
+        if caches.iter().any(|c| c.has_limits()) {
+            uses.push((Feature::CacheLimits, None));
+        }
+        if !self.templates.is_empty() {
+            uses.push((Feature::Templates, None));
+        }
+        if self.max_parallel.is_some() {
+            uses.push((Feature::ConcurrencyLimits, None));
+        }
+        uses
+    }
+
+    /// The `requires` object for emit, or None at the base version.
+    pub(crate) fn json_requires(&self) -> Option<JsonRequires> {
+        let version = self.required_runner_version();
+        (version != BASE_RUNNER_VERSION).then(|| JsonRequires {
+            runner: format!(">={}", version),
+        })
+    }
+
+    /// Reports features above [`Pipeline::max_runner_version`].
+    pub(crate) fn check_runner_version(&self, issues: &mut Issues) {
+        let Some(max) = self.max_runner_version else {
+            return;
+        };
+        for (feature, task) in self.feature_uses() {
+            if version_of(feature) <= max {
+                continue;
+            }
+            issues.error(EmitError::RunnerVersion {
+                task: task.map(|t| t.name.clone()),
+                feature,
+                max,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::{Backoff, K8sOptions, SecretRef};
+
+    fn emit(p: &Pipeline) -> io::Result<serde_json::Value> {
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf)?;
+        Ok(serde_json::from_slice(&buf).unwrap())
+    }
+
+    #[test]
+    fn test_basic_pipeline_requires_base_version() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .run("cargo test")
+            .retry(2)
+            .timeout(300);
+
+        assert!(p.required_features().is_empty());
+        assert_eq!(p.required_runner_version(), BASE_RUNNER_VERSION);
+        assert!(emit(&p).unwrap()["requires"].is_null());
+    }
+
+    #[test]
+    fn test_base_runner_features_need_no_requires() {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("1Gi".to_string()),
+            ..Default::default()
+        });
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .secret_from("TOKEN", SecretRef::from_env("TOKEN"));
+        let _ = p.gate("approve").after(&["test"]);
+        let _ = p.review("review-code").primitive("lint").after(&["test"]);
+
+        assert!(p.required_features().is_empty());
+        assert!(emit(&p).unwrap()["requires"].is_null());
+
+        let _ = p.task("deploy").run("./deploy.sh").manual();
+        assert_eq!(p.required_features(), [Feature::ManualTasks]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_only_typed_outputs_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("make").output("binary", "target/app");
+        assert!(p.required_features().is_empty());
+
+        let _ = p
+            .task("docs")
+            .run("make docs")
+            .output_dir("site", "public/");
+        assert_eq!(p.required_features(), [Feature::TypedOutputs]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_failure_hooks_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh");
+        let _ = p.task("tag").run("git tag").on_success_of(&["deploy"]);
+        assert!(p.required_features().is_empty());
+
+        let _ = p
+            .task("alert")
+            .run("./notify.sh")
+            .on_failure_of(&["deploy"]);
+        assert_eq!(p.required_features(), [Feature::DependencyHooks]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_templates_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        assert!(p.required_features().is_empty());
+
+        let _ = p.template("rust").container("rust:1.75");
+        assert_eq!(p.required_features(), [Feature::Templates]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_allow_failure_bumps_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("audit").run("cargo audit").allow_failure(false);
+        assert!(p.required_features().is_empty());
+
+        let _ = p.task_or_get("audit").allow_failure(true);
+        assert_eq!(p.required_features(), [Feature::AllowFailure]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_steps_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        assert!(p.required_features().is_empty());
+
+        let _ = p
+            .task("check")
+            .step("cargo fmt --check")
+            .step("cargo clippy");
+        assert_eq!(p.required_features(), [Feature::Steps]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_exec_args_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        assert!(p.required_features().is_empty());
+
+        let _ = p
+            .task("build")
+            .exec(&["cargo", "build", "--features", "a b"]);
+        assert_eq!(p.required_features(), [Feature::ExecArgs]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_entrypoint_and_user_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").container("rust:1.75").run("cargo test");
+        assert!(p.required_features().is_empty());
+
+        let _ = p.task_or_get("test").user("1000:1000");
+        assert_eq!(p.required_features(), [Feature::EntrypointUser]);
+
+        let _ = p
+            .task("docker")
+            .container("docker:24")
+            .entrypoint("")
+            .run("docker build .");
+        assert_eq!(p.required_features(), [Feature::EntrypointUser]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_service_options_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test").service("redis:7", "cache");
+        assert!(p.required_features().is_empty());
+
+        let _ = p
+            .task_or_get("test")
+            .service_with("postgres:15", "db", |s| s.port(5433));
+        assert_eq!(p.required_features(), [Feature::ServiceOptions]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_service_readiness_bumps_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .service_with("postgres:15", "db", |s| s.ready_tcp(5432));
+        assert_eq!(p.required_features(), [Feature::ServiceReadiness]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_concurrency_limits_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        assert!(p.required_features().is_empty());
+
+        p.max_parallel(2);
+        assert_eq!(p.required_features(), [Feature::ConcurrencyLimits]);
+
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .concurrency_group("staging-deploy");
+        assert_eq!(p.required_features(), [Feature::ConcurrencyLimits]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_retry_policies_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p.task("push").run("docker push app").retry(3);
+        assert!(p.required_features().is_empty());
+
+        let _ = p.task_or_get("push").retry_policy(RetryPolicy {
+            backoff: Backoff::Fixed { secs: 5 },
+            ..RetryPolicy::new(3)
+        });
+        assert_eq!(p.required_features(), [Feature::RetryPolicies]);
+        assert_eq!(emit(&p).unwrap()["requires"]["runner"], ">=0.6.2");
+    }
+
+    #[test]
+    fn test_matrix_excludes_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux", "macos"]);
+        assert!(p.required_features().is_empty());
+
+        let _ = p.task_or_get("test").matrix_exclude(&[("os", "macos")]);
+        assert_eq!(p.required_features(), [Feature::MatrixAdjustments]);
+    }
+
+    #[test]
+    fn test_matrix_selectors_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("make")
+            .matrix("os", &["linux", "macos"]);
+        let _ = p.task("package").run("make dist");
+        assert!(p.required_features().is_empty());
+
+        let _ = p
+            .task_or_get("package")
+            .after_matrix("build", &[("os", "linux")]);
+        assert_eq!(p.required_features(), [Feature::MatrixSelectors]);
+    }
+
+    #[test]
+    fn test_secret_options_bump_required_version() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .secret_from("TOKEN", SecretRef::from_env("TOKEN"));
+        assert!(p.required_features().is_empty());
+
+        let _ = p.task_or_get("test").secret_opt("TRACING_TOKEN");
+        assert_eq!(p.required_features(), [Feature::SecretOptions]);
+    }
+
+    #[test]
+    fn test_pipeline_features_count() {
+        let mut p = Pipeline::new();
+        let _ = p.cache_with_key("cargo-registry", "shared");
+        let _ = p.task("deploy").run("./deploy.sh");
+
+        assert_eq!(p.required_features(), [Feature::CacheKeys]);
+        assert_eq!(p.required_runner_version(), "0.6.2");
+    }
+
+    #[test]
+    fn test_max_runner_version_names_feature_and_task() {
+        let mut p = Pipeline::new();
+        p.max_runner_version("0.6");
+        let _ = p.task("test").run("cargo test");
+        let _ = p.gate("approve").after(&["test"]);
+        assert!(emit(&p).is_ok());
+
+        let _ = p.task("deploy").run("./deploy.sh").manual();
+        let err = emit(&p).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "task \"deploy\" uses manual tasks, which requires runner >=0.6.2 \
+             (max_runner_version is 0.6)"
+        );
+
+        p.max_runner_version("0.6.2");
+        assert!(emit(&p).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "is not MAJOR.MINOR")]
+    fn test_max_runner_version_rejects_bad_version() {
+        Pipeline::new().max_runner_version("latest");
+    }
+}
@@ -0,0 +1,364 @@
+//! Renders the task graph for docs and PR descriptions.
+//!
+//! [`Pipeline::to_dot`] emits a Graphviz digraph. Edges point from a
+//! dependency to the task waiting for it; artifact flows from
+//! [`Task::input_from`](crate::Task::input_from) are dashed and labelled with
+//! the output name:
+//!
+//! ```rust
+//! use sykli::Pipeline;
+//!
+//! let mut p = Pipeline::new();
+//! p.task("build").run("cargo build").output("binary", "target/release/app");
+//! p.task("package")
+//!     .run("./package.sh")
+//!     .input_from("build", "binary", "/in/app");
+//!
+//! let dot = p.to_dot();
+//! assert!(dot.contains(r#""build" -> "package" [style=dashed, label="binary"];"#));
+//! ```
+//!
+//! Render it with `dot -Tsvg pipeline.dot > pipeline.svg`.
+//!
+//! [`Pipeline::to_mermaid`] emits a Mermaid flowchart, which GitHub renders
+//! inline in Markdown.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use crate::export::Ids;
+use crate::{EdgeKind, Pipeline, TaskData};
+
+impl Pipeline {
+    /// Returns the task graph as a Graphviz digraph.
+    ///
+    /// Conditional tasks have a dotted border, with the condition as tooltip.
+    pub fn to_dot(&self) -> String {
+        let mut buf = Vec::new();
+        self.dot_to(&mut buf).expect("writing to a Vec never fails");
+        String::from_utf8(buf).expect("DOT output is UTF-8")
+    }
+
+    /// Writes [`Pipeline::to_dot`] to the given writer.
+    pub fn dot_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "digraph pipeline {{")?;
+        writeln!(w, "  rankdir=LR;")?;
+        writeln!(w, "  node [shape=box];")?;
+        for t in &self.tasks {
+            writeln!(w, "  {}{};", dot_id(&t.name), node_attrs(t))?;
+        }
+        for t in &self.tasks {
+            // Deps that only exist because of input_from are drawn as the
+            // dashed artifact edge below, not twice.
+            for dep in &t.depends_on {
+                if t.edge_kind(dep) != EdgeKind::Artifact {
+                    writeln!(w, "  {} -> {};", dot_id(dep), dot_id(&t.name))?;
+                }
+            }
+            for input in &self.with_defaults(t).task_inputs {
+                writeln!(
+                    w,
+                    "  {} -> {} [style=dashed, label={}];",
+                    dot_id(&input.from_task),
+                    dot_id(&t.name),
+                    dot_id(&input.output)
+                )?;
+            }
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Returns the task graph as a Mermaid `flowchart TD`, for READMEs.
+    ///
+    /// Node IDs are task names with anything but ASCII letters and digits
+    /// replaced by `_`; a clash gets `_2`, `_3`, ... in declaration order.
+    /// Groups passed to [`Task::after_group`](crate::Task::after_group) become
+    /// subgraphs holding their tasks (a task in several groups is drawn in the
+    /// first). Conditional tasks are drawn as `{...}` with the condition on
+    /// their incoming edges; artifact-only edges are dotted.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test");
+    /// p.task("deploy.prod")
+    ///     .run("./deploy.sh")
+    ///     .after(&["test"])
+    ///     .when("branch == 'main'");
+    ///
+    /// assert_eq!(
+    ///     p.to_mermaid(),
+    ///     "flowchart TD\n    \
+    ///      test[\"test\"]\n    \
+    ///      deploy_prod{\"deploy.prod\"}\n    \
+    ///      test -->|\"branch == 'main'\"| deploy_prod\n"
+    /// );
+    /// ```
+    pub fn to_mermaid(&self) -> String {
+        let mut ids = Ids::new('_', mermaid_id);
+        let task_ids: HashMap<&str, String> = self
+            .tasks
+            .iter()
+            .map(|t| (t.name.as_str(), ids.alloc(&t.name)))
+            .collect();
+
+        // Groups in first-use order, each with the tasks drawn inside it.
+        let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+        let mut grouped = HashSet::new();
+        for group in self.tasks.iter().flat_map(|t| &t.after_groups) {
+            if groups.iter().any(|(name, _)| *name == group.name) {
+                continue;
+            }
+            let members = group
+                .task_names
+                .iter()
+                .map(String::as_str)
+                .filter(|n| task_ids.contains_key(n) && grouped.insert(*n))
+                .collect();
+            groups.push((&group.name, members));
+        }
+
+        let mut out = String::from("flowchart TD\n");
+        let node = |t: &TaskData, indent: &str| {
+            let label = mermaid_label(&t.name);
+            let id = &task_ids[t.name.as_str()];
+            if t.effective_condition().is_some() {
+                format!("{}{}{{{}}}\n", indent, id, label)
+            } else {
+                format!("{}{}[{}]\n", indent, id, label)
+            }
+        };
+        for (name, members) in &groups {
+            out.push_str(&format!(
+                "    subgraph {}[{}]\n",
+                ids.alloc(name),
+                mermaid_label(name)
+            ));
+            for member in members {
+                let t = self.tasks.iter().find(|t| t.name == *member).unwrap();
+                out.push_str(&node(t, "        "));
+            }
+            out.push_str("    end\n");
+        }
+        for t in &self.tasks {
+            if !grouped.contains(t.name.as_str()) {
+                out.push_str(&node(t, "    "));
+            }
+        }
+        for t in &self.tasks {
+            let condition = t.effective_condition();
+            for dep in &t.depends_on {
+                let Some(from) = task_ids.get(dep.as_str()) else {
+                    continue;
+                };
+                let arrow = match t.edge_kind(dep) {
+                    EdgeKind::Artifact => "-.->",
+                    _ => "-->",
+                };
+                let label = condition
+                    .as_deref()
+                    .map(|c| format!("|{}|", mermaid_label(c)))
+                    .unwrap_or_default();
+                out.push_str(&format!(
+                    "    {} {}{} {}\n",
+                    from,
+                    arrow,
+                    label,
+                    task_ids[t.name.as_str()]
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// A Mermaid node ID for `name`.
+fn mermaid_id(name: &str) -> String {
+    let mut id: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    // `end` closes a subgraph, so it can't be a node ID.
+    if id.is_empty() || id.eq_ignore_ascii_case("end") {
+        id.push('_');
+    }
+    id
+}
+
+/// Quotes `s` as Mermaid label text; `"` becomes the `#quot;` entity.
+fn mermaid_label(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "#quot;"))
+}
+
+fn node_attrs(t: &TaskData) -> String {
+    let mut attrs = Vec::new();
+    if let Some(ref target) = t.target_name {
+        attrs.push(format!(
+            "label={}",
+            dot_id(&format!("{} [target: {}]", t.name, target))
+        ));
+    }
+    if let Some(cond) = t.effective_condition() {
+        attrs.push("style=dotted".to_string());
+        attrs.push(format!("tooltip={}", dot_id(&cond)));
+    }
+    if attrs.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", attrs.join(", "))
+    }
+}
+
+/// Quotes `s` as a DOT ID. Quoting keeps names with `-`, `.`, `/` or spaces
+/// intact; backslashes and quotes are escaped.
+fn dot_id(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(dot: &str) -> Vec<&str> {
+        dot.lines()
+            .map(str::trim)
+            .filter(|l| l.contains(" -> "))
+            .collect()
+    }
+
+    #[test]
+    fn test_diamond_edges() {
+        let mut p = Pipeline::new();
+        let _ = p.task("checkout").run("git fetch");
+        let _ = p.task("unit-test").run("cargo test").after(&["checkout"]);
+        let _ = p.task("lint").run("cargo clippy").after(&["checkout"]);
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .after(&["unit-test", "lint"])
+            .when("branch == 'main'");
+
+        let dot = p.to_dot();
+        assert!(dot.starts_with("digraph pipeline {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("  \"unit-test\";\n"));
+        assert!(dot.contains(r#""deploy" [style=dotted, tooltip="branch == 'main'"];"#));
+        assert_eq!(
+            edges(&dot),
+            [
+                r#""checkout" -> "unit-test";"#,
+                r#""checkout" -> "lint";"#,
+                r#""unit-test" -> "deploy";"#,
+                r#""lint" -> "deploy";"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_artifact_edges_are_dashed() {
+        let mut p = Pipeline::new();
+        let _ = p.task("setup").run("./setup.sh");
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .output("binary", "target/release/app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .input_from("build", "binary", "/in/app")
+            .after(&["setup"]);
+        let _ = p
+            .task("publish")
+            .run("./publish.sh")
+            .input_from("build", "binary", "/in/app")
+            .after(&["build"]);
+
+        assert_eq!(
+            edges(&p.to_dot()),
+            [
+                r#""setup" -> "package";"#,
+                r#""build" -> "package" [style=dashed, label="binary"];"#,
+                r#""build" -> "publish";"#,
+                r#""build" -> "publish" [style=dashed, label="binary"];"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mermaid_groups_and_conditions() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p.task("unit test").run("cargo test");
+        let checks = p.group("checks", &["lint", "unit test"]);
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .output("binary", "target/release/app")
+            .after_group(&checks);
+        let _ = p
+            .task("deploy/prod")
+            .run("./deploy.sh")
+            .input_from("build", "binary", "/in/app")
+            .when("branch == 'main'");
+
+        assert_eq!(
+            p.to_mermaid(),
+            r#"flowchart TD
+    subgraph checks["checks"]
+        lint["lint"]
+        unit_test["unit test"]
+    end
+    build["build"]
+    deploy_prod{"deploy/prod"}
+    lint --> build
+    unit_test --> build
+    build -.->|"branch == 'main'"| deploy_prod
+"#
+        );
+    }
+
+    #[test]
+    fn test_mermaid_ids_are_unique_and_safe() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build.linux").run("make");
+        let _ = p.task("build linux").run("make");
+        let _ = p.task("build/linux").run("make");
+        let _ = p.task("end").run("true").after(&["build/linux"]);
+        let _ = p.task("say \"hi\"").run("echo hi").after(&["end"]);
+
+        assert_eq!(
+            p.to_mermaid(),
+            r#"flowchart TD
+    build_linux["build.linux"]
+    build_linux_2["build linux"]
+    build_linux_3["build/linux"]
+    end_["end"]
+    say__hi_["say #quot;hi#quot;"]
+    build_linux_3 --> end_
+    end_ --> say__hi_
+"#
+        );
+    }
+
+    #[test]
+    fn test_dot_id_escapes() {
+        assert_eq!(dot_id("a-b"), r#""a-b""#);
+        assert_eq!(dot_id(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(dot_id(r"C:\path"), r#""C:\\path""#);
+    }
+}
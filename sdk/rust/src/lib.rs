@@ -47,13 +47,32 @@
 //! }
 //! ```
 
+pub mod condition;
+pub mod dotenv;
+pub mod export;
+pub mod features;
+pub mod graph;
+pub mod lint;
+pub mod load;
+pub mod matrix;
+pub mod plan;
+pub mod secrets_report;
+pub mod subset;
 pub mod target;
+pub mod validate;
+pub mod view;
+
+pub use load::PipelineParseError;
 
 use regex::Regex;
+use serde::ser::{SerializeSeq, SerializeStruct, Serializer};
 use serde::Serialize;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::LazyLock;
 use tracing::debug;
 
@@ -63,6 +82,10 @@ static K8S_MEMORY_PATTERN: LazyLock<Regex> =
 static K8S_CPU_PATTERN: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^[0-9]+(\.[0-9]+)?m?$").unwrap());
 
+// `{{name}}` placeholders in secret templates
+pub(crate) static SECRET_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap());
+
 // =============================================================================
 // RESOURCES
 // =============================================================================
@@ -72,4563 +95,15845 @@ static K8S_CPU_PATTERN: LazyLock<Regex> =
 pub struct Directory {
     path: String,
     globs: Vec<String>,
+    // Set by Pipeline::dir_named
+    name: Option<String>,
+    // Set by Pipeline::hash_dir_globs
+    hash_globs: bool,
 }
 
 impl Directory {
-    /// Returns the resource ID for this directory.
+    /// Returns the resource ID for this directory: `src:<path>`, with
+    /// `#<name>` for [named](Pipeline::dir_named) directories, or `#<hash>`
+    /// of the globs for filtered ones once [`Pipeline::hash_dir_globs`] is
+    /// set.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// assert_eq!(p.dir("web").id(), "src:web");
+    /// assert_eq!(p.dir("web").glob(&["**/*.ts"]).id(), "src:web");
+    /// assert_eq!(p.dir_named("web-src", "web").id(), "src:web#web-src");
+    /// ```
     pub fn id(&self) -> String {
-        format!("src:{}", self.path)
+        match &self.name {
+            Some(name) => format!("src:{}#{}", self.path, name),
+            None if self.hash_globs && !self.globs.is_empty() => {
+                format!("src:{}#{}", self.path, self.globs_hash())
+            }
+            None => format!("src:{}", self.path),
+        }
     }
 
-    /// Adds glob patterns to filter the directory.
+    /// Adds glob patterns to filter the directory. With
+    /// [`Pipeline::hash_dir_globs`], this changes its [`id`](Directory::id).
     pub fn glob(mut self, patterns: &[&str]) -> Self {
         self.globs.extend(patterns.iter().map(|s| s.to_string()));
         self
     }
-}
-
-/// A named cache volume that persists between runs.
-#[derive(Clone)]
-pub struct CacheVolume {
-    name: String,
-}
 
-impl CacheVolume {
-    /// Returns the resource ID for this cache.
-    pub fn id(&self) -> String {
-        self.name.clone()
+    /// The globs sorted and deduplicated, since their order doesn't matter.
+    fn glob_set(&self) -> Vec<&str> {
+        let mut globs: Vec<&str> = self.globs.iter().map(String::as_str).collect();
+        globs.sort_unstable();
+        globs.dedup();
+        globs
     }
-}
 
-// =============================================================================
-// MOUNT
-// =============================================================================
+    /// Six hex digits of the FNV-1a hash of the sorted globs. Stable across
+    /// builds, unlike `std`'s hasher.
+    fn globs_hash(&self) -> String {
+        let mut hash: u32 = 0x811c_9dc5;
+        for byte in self.glob_set().join("\n").bytes() {
+            hash ^= u32::from(byte);
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+        format!("{:06x}", hash & 0xff_ffff)
+    }
 
-#[derive(Clone)]
-struct Mount {
-    resource: String,
-    path: String,
-    mount_type: String,
+    /// Whether this is a filtered or named view of `path` rather than the
+    /// plain directory.
+    fn is_view(&self) -> bool {
+        self.name.is_some() || !self.globs.is_empty()
+    }
 }
 
-#[derive(Clone)]
-struct Service {
-    image: String,
-    name: String,
+/// Normalizes a directory path so different spellings give one resource ID.
+///
+/// `.` segments and trailing slashes are dropped and `..` is resolved where
+/// possible. Absolute paths under the current directory become relative.
+fn normalize_dir_path(path: &str) -> String {
+    use std::path::{Component, Path};
+
+    let mut path = Path::new(path).to_path_buf();
+    if path.is_absolute() {
+        if let Ok(cwd) = env::current_dir() {
+            if let Ok(rel) = path.strip_prefix(&cwd) {
+                path = rel.to_path_buf();
+            }
+        }
+    }
+    let mut parts: Vec<String> = Vec::new();
+    let mut absolute = false;
+    for c in path.components() {
+        match c {
+            Component::RootDir | Component::Prefix(_) => absolute = true,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if parts.last().is_some_and(|p| p != "..") {
+                    parts.pop();
+                } else if !absolute {
+                    parts.push("..".to_string());
+                }
+            }
+            Component::Normal(s) => parts.push(s.to_string_lossy().into_owned()),
+        }
+    }
+    match (absolute, parts.is_empty()) {
+        (true, _) => format!("/{}", parts.join("/")),
+        (false, true) => ".".to_string(),
+        (false, false) => parts.join("/"),
+    }
 }
 
-// =============================================================================
-// K8S OPTIONS (Minimal API)
-// =============================================================================
-
-/// Kubernetes-specific configuration for a task.
+/// A named cache volume that persists between runs.
 ///
-/// This is a minimal API covering 95% of CI use cases.
-/// For advanced options (tolerations, affinity, security contexts),
-/// use `k8s_raw()` to pass raw JSON.
+/// By default one volume is shared by every run. Key it on file contents with
+/// [`key_files`](CacheVolume::key_files) and limit who shares it with
+/// [`scope`](CacheVolume::scope); the engine computes the final key. Settings
+/// made after [`Pipeline::cache`] apply once the cache is mounted.
 ///
 /// # Example
-/// ```rust,ignore
-/// use sykli::{Pipeline, K8sOptions};
+/// ```rust
+/// use sykli::{CacheScope, Pipeline};
 ///
 /// let mut p = Pipeline::new();
-/// p.task("build")
-///     .run("cargo build")
-///     .k8s(K8sOptions {
-///         memory: Some("4Gi".into()),
-///         cpu: Some("2".into()),
-///         ..Default::default()
-///     });
+/// let cargo = p
+///     .cache("cargo")
+///     .key_files(&["Cargo.lock"])
+///     .scope(CacheScope::Branch)
+///     .fallback_scope(CacheScope::Default);
+/// p.task("test")
+///     .container("rust:1.80")
+///     .mount_cache(&cargo, "/usr/local/cargo/registry")
+///     .run("cargo test");
 ///
-/// // For GPU tasks
-/// p.task("train")
-///     .run("python train.py")
-///     .k8s(K8sOptions {
-///         memory: Some("32Gi".into()),
-///         gpu: Some(1),
-///         ..Default::default()
-///     });
+/// let json = p.to_json_value().unwrap();
+/// assert_eq!(json["resources"]["cargo"]["key_files"][0], "Cargo.lock");
+/// assert_eq!(json["resources"]["cargo"]["scope"], "branch");
+/// assert_eq!(json["resources"]["cargo"]["fallback"], "default");
 /// ```
-#[derive(Clone, Default, Debug)]
-pub struct K8sOptions {
-    /// Memory (e.g., "4Gi", "512Mi"). Sets both request and limit.
-    pub memory: Option<String>,
-    /// CPU (e.g., "2", "500m"). Sets both request and limit.
-    pub cpu: Option<String>,
-    /// Number of NVIDIA GPUs to request.
-    pub gpu: Option<u32>,
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheVolume {
+    name: String,
+    key: Option<String>,
+    key_files: Vec<String>,
+    scope: Option<CacheScope>,
+    fallback: Option<CacheScope>,
+    max_size: Option<String>,
+    eviction: Option<Eviction>,
 }
 
-impl K8sOptions {
-    /// Merges defaults with task-specific options.
-    /// Task options override defaults.
-    pub fn merge(defaults: &K8sOptions, task: &K8sOptions) -> K8sOptions {
-        let mut result = defaults.clone();
+/// Which runs share a cache, set with [`CacheVolume::scope`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheScope {
+    /// Every run, whatever its branch.
+    Global,
+    /// Runs on the same branch.
+    Branch,
+    /// Runs on the repository's default branch.
+    Default,
+}
 
-        if task.memory.is_some() {
-            result.memory = task.memory.clone();
-        }
-        if task.cpu.is_some() {
-            result.cpu = task.cpu.clone();
-        }
-        if task.gpu.is_some() {
-            result.gpu = task.gpu;
+impl CacheScope {
+    /// The name emitted in JSON: `"global"`, `"branch"` or `"default"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheScope::Global => "global",
+            CacheScope::Branch => "branch",
+            CacheScope::Default => "default",
         }
-
-        result
-    }
-
-    /// Returns true if no options are set.
-    pub fn is_empty(&self) -> bool {
-        self.memory.is_none() && self.cpu.is_none() && self.gpu.is_none()
     }
 
-    /// Validates K8s options and returns a list of errors.
-    pub fn validate(&self) -> Vec<K8sValidationError> {
-        let mut errors = Vec::new();
-
-        if let Some(ref v) = self.memory {
-            if let Some(err) = validate_k8s_memory("memory", v) {
-                errors.push(err);
-            }
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "global" => Some(CacheScope::Global),
+            "branch" => Some(CacheScope::Branch),
+            "default" => Some(CacheScope::Default),
+            _ => None,
         }
+    }
 
-        if let Some(ref v) = self.cpu {
-            if let Some(err) = validate_k8s_cpu("cpu", v) {
-                errors.push(err);
-            }
+    /// How `explain` describes the scope.
+    fn describe(&self) -> &'static str {
+        match self {
+            CacheScope::Global => "shared by all branches",
+            CacheScope::Branch => "per branch",
+            CacheScope::Default => "from the default branch",
         }
-
-        errors
     }
 }
 
-/// Error from K8s options validation.
-#[derive(Debug, Clone)]
-pub struct K8sValidationError {
-    /// Field that failed validation (e.g., "resources.memory").
-    pub field: String,
-    /// The invalid value.
-    pub value: String,
-    /// Description of what's wrong.
-    pub message: String,
+/// Which entries a size-limited cache drops first, set with
+/// [`CacheVolume::eviction`]. A hint: engines may approximate it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+    /// Least recently used.
+    Lru,
+    /// Least frequently used.
+    Lfu,
+    /// Oldest first.
+    Fifo,
 }
 
-impl std::fmt::Display for K8sValidationError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "k8s.{}: {} (got {:?})",
-            self.field, self.message, self.value
-        )
+impl Eviction {
+    /// The name emitted in JSON: `"lru"`, `"lfu"` or `"fifo"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Eviction::Lru => "lru",
+            Eviction::Lfu => "lfu",
+            Eviction::Fifo => "fifo",
+        }
     }
-}
-
-impl std::error::Error for K8sValidationError {}
 
-fn validate_k8s_memory(field: &str, value: &str) -> Option<K8sValidationError> {
-    if K8S_MEMORY_PATTERN.is_match(value) {
-        return None;
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "lru" => Some(Eviction::Lru),
+            "lfu" => Some(Eviction::Lfu),
+            "fifo" => Some(Eviction::Fifo),
+            _ => None,
+        }
     }
-
-    // Provide helpful suggestions for common mistakes
-    let lower = value.to_lowercase();
-    let suggestion = if lower.ends_with("gb") {
-        " (did you mean 'Gi'?)"
-    } else if lower.ends_with("mb") {
-        " (did you mean 'Mi'?)"
-    } else if lower.ends_with("kb") {
-        " (did you mean 'Ki'?)"
-    } else {
-        ""
-    };
-
-    Some(K8sValidationError {
-        field: field.to_string(),
-        value: value.to_string(),
-        message: format!(
-            "invalid memory format, use Ki/Mi/Gi/Ti (e.g., '512Mi', '4Gi'){}",
-            suggestion
-        ),
-    })
 }
 
-fn validate_k8s_cpu(field: &str, value: &str) -> Option<K8sValidationError> {
-    if K8S_CPU_PATTERN.is_match(value) {
-        return None;
-    }
-
-    Some(K8sValidationError {
-        field: field.to_string(),
-        value: value.to_string(),
-        message: "invalid CPU format, use cores or millicores (e.g., '500m', '0.5', '2')"
-            .to_string(),
-    })
+/// How tasks mounting the same cache at once share it, set per mount with
+/// [`Task::mount_cache_with`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheSharing {
+    /// Every task reads and writes the cache concurrently.
+    #[default]
+    Shared,
+    /// One task at a time; others wait for the lock.
+    Locked,
+    /// Each concurrent task gets its own copy.
+    Private,
 }
 
-// =============================================================================
-// STRING SIMILARITY
-// =============================================================================
-
-/// Finds the most similar task name using Jaro-Winkler distance.
-fn suggest_task_name<'a>(unknown: &str, known: &[&'a str]) -> Option<&'a str> {
-    let mut best: Option<&str> = None;
-    let mut best_score = 0.0;
+impl CacheSharing {
+    /// The name emitted in JSON: `"shared"`, `"locked"` or `"private"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheSharing::Shared => "shared",
+            CacheSharing::Locked => "locked",
+            CacheSharing::Private => "private",
+        }
+    }
 
-    for &name in known {
-        let score = jaro_winkler(unknown, name);
-        if score > best_score && score >= 0.8 {
-            best_score = score;
-            best = Some(name);
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "shared" => Some(CacheSharing::Shared),
+            "locked" => Some(CacheSharing::Locked),
+            "private" => Some(CacheSharing::Private),
+            _ => None,
         }
     }
-    best
 }
 
-/// Computes the Jaro-Winkler similarity between two strings (0-1).
-fn jaro_winkler(s1: &str, s2: &str) -> f64 {
-    if s1 == s2 {
-        return 1.0;
+impl CacheVolume {
+    /// Returns the resource ID for this cache.
+    pub fn id(&self) -> String {
+        self.name.clone()
     }
-    if s1.is_empty() || s2.is_empty() {
-        return 0.0;
+
+    /// Derives the key from the contents of files matching `patterns`, e.g.
+    /// `Cargo.lock`, so the cache starts fresh when they change.
+    #[must_use]
+    pub fn key_files(mut self, patterns: &[&str]) -> Self {
+        self.key_files
+            .extend(patterns.iter().map(|s| s.to_string()));
+        self
     }
 
-    let s1_chars: Vec<char> = s1.chars().collect();
-    let s2_chars: Vec<char> = s2.chars().collect();
+    /// Limits which runs share the cache.
+    #[must_use]
+    pub fn scope(mut self, scope: CacheScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
 
-    let match_window = (s1_chars.len().max(s2_chars.len()) / 2).saturating_sub(1);
+    /// Where a run without a cache of its own [`scope`](CacheVolume::scope)
+    /// starts from, e.g. the default branch's cache for a new branch.
+    #[must_use]
+    pub fn fallback_scope(mut self, scope: CacheScope) -> Self {
+        self.fallback = Some(scope);
+        self
+    }
 
-    let mut s1_matches = vec![false; s1_chars.len()];
-    let mut s2_matches = vec![false; s2_chars.len()];
+    /// Caps the cache's size, as a K8s-style quantity like `10Gi`; the
+    /// engine evicts entries beyond it. Emit fails with
+    /// [`EmitError::InvalidCacheSize`] for a malformed size.
+    ///
+    /// # Panics
+    /// Panics if `size` is empty.
+    #[must_use]
+    pub fn max_size(mut self, size: &str) -> Self {
+        assert!(!size.is_empty(), "cache max size cannot be empty");
+        self.max_size = Some(size.to_string());
+        self
+    }
 
-    let mut matches = 0usize;
-    let mut transpositions = 0usize;
+    /// Which entries to evict first once [`max_size`](CacheVolume::max_size)
+    /// is reached.
+    #[must_use]
+    pub fn eviction(mut self, eviction: Eviction) -> Self {
+        self.eviction = Some(eviction);
+        self
+    }
 
-    for i in 0..s1_chars.len() {
-        let start = i.saturating_sub(match_window);
-        let end = (i + match_window + 1).min(s2_chars.len());
+    /// Whether the key is more than the cache's name or sharing key.
+    fn has_recipe(&self) -> bool {
+        !self.key_files.is_empty() || self.scope.is_some() || self.fallback.is_some()
+    }
 
-        for j in start..end {
-            if s2_matches[j] || s1_chars[i] != s2_chars[j] {
-                continue;
-            }
-            s1_matches[i] = true;
-            s2_matches[j] = true;
-            matches += 1;
-            break;
-        }
+    fn has_limits(&self) -> bool {
+        self.max_size.is_some() || self.eviction.is_some()
     }
 
-    if matches == 0 {
-        return 0.0;
+    /// Whether any setting made on the handle after [`Pipeline::cache`]
+    /// is set.
+    fn is_configured(&self) -> bool {
+        self.has_recipe() || self.has_limits()
     }
 
-    let mut k = 0;
-    for i in 0..s1_chars.len() {
-        if !s1_matches[i] {
-            continue;
+    /// The key recipe and limits, e.g. `key cargo per branch, max size
+    /// 10Gi, lru eviction`.
+    fn settings(&self) -> String {
+        let mut settings = format!("key {}", self.key_recipe());
+        if let Some(ref size) = self.max_size {
+            settings.push_str(&format!(", max size {}", size));
         }
-        while !s2_matches[k] {
-            k += 1;
-        }
-        if s1_chars[i] != s2_chars[k] {
-            transpositions += 1;
+        if let Some(eviction) = self.eviction {
+            settings.push_str(&format!(", {} eviction", eviction.as_str()));
         }
-        k += 1;
+        settings
     }
 
-    let jaro = (matches as f64 / s1_chars.len() as f64
-        + matches as f64 / s2_chars.len() as f64
-        + (matches as f64 - transpositions as f64 / 2.0) / matches as f64)
-        / 3.0;
-
-    // Apply Winkler prefix bonus
-    let mut prefix = 0;
-    for i in 0..4.min(s1_chars.len()).min(s2_chars.len()) {
-        if s1_chars[i] == s2_chars[i] {
-            prefix += 1;
-        } else {
-            break;
+    /// How the engine computes the key, e.g.
+    /// `cargo-${hash(Cargo.lock)} per branch, else from the default branch`.
+    fn key_recipe(&self) -> String {
+        let mut recipe = self.key.clone().unwrap_or_else(|| self.name.clone());
+        if !self.key_files.is_empty() {
+            recipe.push_str(&format!("-${{hash({})}}", self.key_files.join(", ")));
+        }
+        if let Some(scope) = self.scope {
+            recipe.push_str(&format!(" {}", scope.describe()));
+        }
+        if let Some(fallback) = self.fallback {
+            recipe.push_str(&format!(", else {}", fallback.describe()));
         }
+        recipe
     }
-
-    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
 }
 
-// =============================================================================
-// TEMPLATE
-// =============================================================================
-
-/// A reusable task configuration template.
-///
-/// Templates allow you to define common settings (container, mounts, env)
-/// that can be inherited by multiple tasks via `from()`.
-///
-/// # Example
-/// ```rust
-/// use sykli::{Pipeline, Template};
-///
-/// let mut p = Pipeline::new();
-/// let src = p.dir(".");
-///
-/// let rust = Template::new()
-///     .container("rust:1.75")
-///     .mount_dir(&src, "/src")
-///     .workdir("/src");
+/// A remote git repository checked out at one branch, tag or commit.
 ///
-/// p.task("test").from(&rust).run("cargo test");
-/// p.task("build").from(&rust).run("cargo build");
-/// ```
-#[derive(Clone, Default)]
-pub struct Template {
-    container: Option<String>,
-    workdir: Option<String>,
-    env: HashMap<String, String>,
-    mounts: Vec<Mount>,
+/// Created with [`Pipeline::git`] and mounted with [`Task::mount_git`].
+/// Emit fails unless exactly one of [`branch`](GitSource::branch),
+/// [`tag`](GitSource::tag) or [`commit`](GitSource::commit) is set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GitSource {
+    url: String,
+    branch: Option<String>,
+    tag: Option<String>,
+    commit: Option<String>,
+    depth: Option<u32>,
 }
 
-impl Template {
-    /// Creates a new empty template.
+impl GitSource {
+    /// Returns the resource ID: `git:<url>#<ref>`, or `git:<url>` before a
+    /// ref is set.
+    pub fn id(&self) -> String {
+        match self.reference() {
+            Some(r) => format!("git:{}#{}", self.url, r),
+            None => format!("git:{}", self.url),
+        }
+    }
+
+    /// Checks out the tip of `branch`.
+    ///
+    /// # Panics
+    /// Panics if `branch` is empty.
     #[must_use]
-    pub fn new() -> Self {
-        Template::default()
+    pub fn branch(mut self, branch: &str) -> Self {
+        assert!(!branch.is_empty(), "git branch cannot be empty");
+        self.branch = Some(branch.to_string());
+        self
     }
 
-    /// Sets the container image for tasks using this template.
+    /// Checks out `tag`.
+    ///
+    /// # Panics
+    /// Panics if `tag` is empty.
     #[must_use]
-    pub fn container(mut self, image: &str) -> Self {
-        assert!(!image.is_empty(), "container image cannot be empty");
-        self.container = Some(image.to_string());
+    pub fn tag(mut self, tag: &str) -> Self {
+        assert!(!tag.is_empty(), "git tag cannot be empty");
+        self.tag = Some(tag.to_string());
         self
     }
 
-    /// Sets the working directory for tasks using this template.
+    /// Checks out commit `sha`.
+    ///
+    /// # Panics
+    /// Panics if `sha` is empty.
     #[must_use]
-    pub fn workdir(mut self, path: &str) -> Self {
-        assert!(!path.is_empty(), "workdir cannot be empty");
-        assert!(path.starts_with('/'), "workdir must be absolute");
-        self.workdir = Some(path.to_string());
+    pub fn commit(mut self, sha: &str) -> Self {
+        assert!(!sha.is_empty(), "git commit cannot be empty");
+        self.commit = Some(sha.to_string());
         self
     }
 
-    /// Sets an environment variable for tasks using this template.
+    /// Makes a shallow clone with the last `depth` commits.
+    ///
+    /// # Panics
+    /// Panics if `depth` is 0.
     #[must_use]
-    pub fn env(mut self, key: &str, value: &str) -> Self {
-        assert!(!key.is_empty(), "env key cannot be empty");
-        self.env.insert(key.to_string(), value.to_string());
+    pub fn depth(mut self, depth: u32) -> Self {
+        assert!(depth > 0, "git depth must be at least 1");
+        self.depth = Some(depth);
         self
     }
 
-    /// Adds a directory mount for tasks using this template.
+    /// The ref as emitted: the branch, tag or commit, in that order.
+    fn reference(&self) -> Option<&str> {
+        self.branch
+            .as_deref()
+            .or(self.tag.as_deref())
+            .or(self.commit.as_deref())
+    }
+
+    /// Which of branch, tag and commit are set.
+    fn ref_kinds(&self) -> Vec<&'static str> {
+        [
+            ("branch", &self.branch),
+            ("tag", &self.tag),
+            ("commit", &self.commit),
+        ]
+        .into_iter()
+        .filter(|(_, r)| r.is_some())
+        .map(|(kind, _)| kind)
+        .collect()
+    }
+}
+
+/// A file downloaded over HTTP, such as a tool release, fetched once and
+/// mounted into every task that needs it.
+///
+/// Created with [`Pipeline::http`] and mounted with [`Task::mount_file`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpFile {
+    url: String,
+    sha256: Option<String>,
+    unpack: bool,
+}
+
+impl HttpFile {
+    /// Returns the resource ID: `http:<url>`.
+    pub fn id(&self) -> String {
+        format!("http:{}", self.url)
+    }
+
+    /// Checks the download against a SHA-256 digest. Required in
+    /// [strict](Pipeline::strict) mode.
+    ///
+    /// # Panics
+    /// Panics if `digest` isn't 64 hex digits.
     #[must_use]
-    pub fn mount_dir(mut self, dir: &Directory, path: &str) -> Self {
-        assert!(!path.is_empty(), "mount path cannot be empty");
-        assert!(path.starts_with('/'), "mount path must be absolute");
-        self.mounts.push(Mount {
-            resource: dir.id(),
-            path: path.to_string(),
-            mount_type: "directory".to_string(),
-        });
+    pub fn sha256(mut self, digest: &str) -> Self {
+        assert!(
+            is_sha256(digest),
+            "sha256 must be 64 hex digits, got {:?}",
+            digest
+        );
+        self.sha256 = Some(digest.to_ascii_lowercase());
         self
     }
 
-    /// Adds a cache mount for tasks using this template.
+    /// Unpacks the archive (zip or tar) at the mount path instead of
+    /// mounting the file itself.
     #[must_use]
-    pub fn mount_cache(mut self, cache: &CacheVolume, path: &str) -> Self {
-        assert!(!path.is_empty(), "mount path cannot be empty");
-        assert!(path.starts_with('/'), "mount path must be absolute");
-        self.mounts.push(Mount {
-            resource: cache.id(),
-            path: path.to_string(),
-            mount_type: "cache".to_string(),
-        });
+    pub fn unpack(mut self, unpack: bool) -> Self {
+        self.unpack = unpack;
         self
     }
 }
 
+fn is_sha256(digest: &str) -> bool {
+    digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Whether `url` is an https URL (`https://host/path`) or in ssh form
+/// (`ssh://[user@]host/path` or `user@host:path`).
+fn is_git_url(url: &str) -> bool {
+    let host_and_path = |rest: &str| {
+        rest.split_once('/')
+            .is_some_and(|(host, path)| !host.is_empty() && !path.is_empty())
+    };
+    if let Some(rest) = url.strip_prefix("https://") {
+        return host_and_path(rest);
+    }
+    if let Some(rest) = url.strip_prefix("ssh://") {
+        return host_and_path(rest);
+    }
+    if url.contains("://") {
+        return false;
+    }
+    let Some((user, rest)) = url.split_once('@') else {
+        return false;
+    };
+    rest.split_once(':').is_some_and(|(host, path)| {
+        !user.is_empty() && !host.is_empty() && !host.contains('/') && !path.is_empty()
+    })
+}
+
+/// A single workspace file, such as `rustfmt.toml`, mounted without the
+/// rest of the repository.
+///
+/// Created with [`Pipeline::file`] or [`Pipeline::file_named`] and mounted
+/// with [`Task::mount_file_res`]. Runners key task caching on the file's
+/// SHA-256, so changes elsewhere in the repository don't invalidate it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileSource {
+    path: String,
+    // Set by Pipeline::file_named
+    name: Option<String>,
+}
+
+impl FileSource {
+    /// Returns the resource ID: `file:<path>`, with `#<name>` for
+    /// [named](Pipeline::file_named) files.
+    pub fn id(&self) -> String {
+        match &self.name {
+            Some(name) => format!("file:{}#{}", self.path, name),
+            None => format!("file:{}", self.path),
+        }
+    }
+}
+
+/// Whether `path` contains glob metacharacters.
+fn has_glob_chars(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
 // =============================================================================
-// TASK
+// MOUNT
 // =============================================================================
 
-/// A task in the pipeline.
-pub struct Task<'a> {
-    pipeline: &'a mut Pipeline,
-    index: usize,
+#[derive(Clone)]
+struct Mount {
+    resource: String,
+    path: String,
+    mount_type: String,
+    // The mounted directory, for directory mounts
+    dir: Option<Directory>,
+    // The mounted cache with its key settings, for cache mounts
+    cache: Option<CacheVolume>,
+    // The mounted workspace file, for local file mounts
+    file: Option<FileSource>,
+    // How concurrent tasks share a cache mount
+    sharing: CacheSharing,
 }
 
-/// An experimental review node in the pipeline graph.
-pub struct Review<'a> {
-    pipeline: &'a mut Pipeline,
-    index: usize,
+impl Mount {
+    /// Host path of a directory or local file mount.
+    fn host_path(&self) -> &str {
+        match &self.file {
+            Some(f) => &f.path,
+            None => dir_host_path(self.dir.as_ref(), &self.resource),
+        }
+    }
+
+    /// Whether this mounts a file downloaded over HTTP.
+    fn is_download(&self) -> bool {
+        self.mount_type == "file" && self.resource.starts_with("http:")
+    }
+
+    /// Whether this bind-mounts a host path: a directory or workspace file.
+    fn is_bind(&self) -> bool {
+        self.mount_type == "directory" || (self.mount_type == "file" && !self.is_download())
+    }
 }
 
-/// Semantic class of an executable task.
-#[derive(Clone, Debug, PartialEq)]
-pub enum TaskType {
-    Build,
-    Test,
-    Lint,
-    Format,
-    Scan,
-    Package,
-    Publish,
-    Deploy,
-    Migrate,
-    Generate,
-    Verify,
-    Cleanup,
+/// Host path of a directory resource. Without the directory (a mount
+/// whose resource wasn't in the loaded JSON), the path in a plain
+/// `src:<path>` ID.
+fn dir_host_path<'a>(dir: Option<&'a Directory>, resource: &'a str) -> &'a str {
+    match dir {
+        Some(d) => &d.path,
+        None => resource.strip_prefix("src:").unwrap_or(resource),
+    }
 }
 
-/// Declared verification metadata for executable task success.
-/// Phase 3C-1 SDKs emit this metadata, but the engine does not evaluate it yet.
-#[derive(Clone, Debug, PartialEq)]
-pub enum SuccessCriterion {
-    ExitCode(i32),
-    FileExists(String),
-    FileNonEmpty(String),
+#[derive(Clone)]
+struct Service {
+    image: String,
+    name: String,
+    env: BTreeMap<String, String>,
+    ports: Vec<u16>,
+    command: Option<String>,       // replaces the image's default command
+    ready_probes: Vec<ReadyProbe>, // more than one fails emit
+    ready_timeout: Option<u32>,
 }
 
-impl SuccessCriterion {
-    fn to_json(&self) -> JsonSuccessCriterion {
+/// How to tell a service is ready to accept connections.
+#[derive(Clone)]
+enum ReadyProbe {
+    // Runs inside the service container; exit 0 means ready
+    Cmd(String),
+    Tcp(u16),
+    Http { path: String, port: u16 },
+}
+
+impl ReadyProbe {
+    fn type_name(&self) -> &'static str {
         match self {
-            SuccessCriterion::ExitCode(code) => {
-                assert!(
-                    (0..=255).contains(code),
-                    "exit_code success criterion must be between 0 and 255"
-                );
-                JsonSuccessCriterion {
-                    type_: "exit_code".to_string(),
-                    equals: Some(*code),
-                    path: None,
-                }
-            }
-            SuccessCriterion::FileExists(path) => {
-                assert!(
-                    !path.is_empty(),
-                    "file_exists success criterion path cannot be empty"
-                );
-                JsonSuccessCriterion {
-                    type_: "file_exists".to_string(),
-                    equals: None,
-                    path: Some(path.clone()),
-                }
-            }
-            SuccessCriterion::FileNonEmpty(path) => {
-                assert!(
-                    !path.is_empty(),
-                    "file_non_empty success criterion path cannot be empty"
-                );
-                JsonSuccessCriterion {
-                    type_: "file_non_empty".to_string(),
-                    equals: None,
-                    path: Some(path.clone()),
-                }
-            }
+            ReadyProbe::Cmd(_) => "cmd",
+            ReadyProbe::Tcp(_) => "tcp",
+            ReadyProbe::Http { .. } => "http",
         }
     }
-}
 
-impl TaskType {
-    fn as_str(&self) -> &'static str {
+    /// Short form for explain and errors, e.g. `tcp:5432`.
+    fn describe(&self) -> String {
         match self {
-            TaskType::Build => "build",
-            TaskType::Test => "test",
-            TaskType::Lint => "lint",
-            TaskType::Format => "format",
-            TaskType::Scan => "scan",
-            TaskType::Package => "package",
-            TaskType::Publish => "publish",
-            TaskType::Deploy => "deploy",
-            TaskType::Migrate => "migrate",
-            TaskType::Generate => "generate",
-            TaskType::Verify => "verify",
-            TaskType::Cleanup => "cleanup",
+            ReadyProbe::Cmd(command) => format!("cmd:{}", command),
+            ReadyProbe::Tcp(port) => format!("tcp:{}", port),
+            ReadyProbe::Http { path, port } => format!("http:{}{}", port, path),
         }
     }
 }
 
-#[derive(Clone, Default, PartialEq)]
-enum NodeKind {
-    #[default]
-    Task,
-    Review,
+/// Configuration for a service container, passed to the closure given to
+/// [`Task::service_with`].
+pub struct ServiceBuilder {
+    service: Service,
 }
 
-/// Represents an input artifact from another task's output.
-#[derive(Clone, Default)]
-struct TaskInput {
-    from_task: String,
-    output: String,
-    dest_path: String,
-}
+impl ServiceBuilder {
+    /// Sets an environment variable in the service container.
+    ///
+    /// # Panics
+    /// Panics if `key` is empty.
+    #[must_use]
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        assert!(!key.is_empty(), "service env key cannot be empty");
+        self.service.env.insert(key.to_string(), value.to_string());
+        self
+    }
 
-// =============================================================================
-// TYPED SECRET REFERENCES
-// =============================================================================
+    /// Declares a port the service listens on, for services that don't
+    /// use their image's default.
+    ///
+    /// # Panics
+    /// Panics if `port` is 0.
+    #[must_use]
+    pub fn port(mut self, port: u16) -> Self {
+        assert!(port != 0, "service port cannot be 0");
+        if !self.service.ports.contains(&port) {
+            self.service.ports.push(port);
+        }
+        self
+    }
 
-/// Source of a secret value.
-#[derive(Clone, Debug)]
-pub enum SecretSource {
-    /// Read from environment variable
-    Env,
-    /// Read from file
-    File,
-    /// Read from HashiCorp Vault
-    Vault,
-}
-
-/// A typed reference to a secret with its source.
-///
-/// # Example
-/// ```rust,ignore
-/// use sykli::{Pipeline, SecretRef};
-///
-/// let mut p = Pipeline::new();
-/// p.task("deploy")
-///     .run("./deploy.sh")
-///     .secret_from("TOKEN", SecretRef::from_env("GITHUB_TOKEN"))
-///     .secret_from("DB_PASS", SecretRef::from_vault("secret/data/db#password"));
-/// ```
-#[derive(Clone, Debug)]
-pub struct SecretRef {
-    /// Environment variable name in the task
-    pub name: String,
-    /// Where the secret comes from
-    pub source: SecretSource,
-    /// Source-specific key (env var name, file path, or vault path)
-    pub key: String,
-}
-
-impl SecretRef {
-    /// Creates a secret reference that reads from an environment variable.
+    /// Replaces the image's default command. The string is split into
+    /// arguments like a shell would, but not run through one, so the image's
+    /// entrypoint still receives them.
     ///
     /// # Panics
-    /// Panics if env_var is empty.
-    pub fn from_env(env_var: &str) -> Self {
-        if env_var.is_empty() {
-            panic!("SecretRef::from_env() requires a non-empty environment variable name");
-        }
-        SecretRef {
-            name: String::new(),
-            source: SecretSource::Env,
-            key: env_var.to_string(),
-        }
+    /// Panics if `command` is empty.
+    #[must_use]
+    pub fn command(mut self, command: &str) -> Self {
+        assert!(!command.is_empty(), "service command cannot be empty");
+        self.service.command = Some(command.to_string());
+        self
     }
 
-    /// Creates a secret reference that reads from a file.
+    /// Waits until `command`, run inside the service container, exits 0
+    /// before starting the task.
+    ///
+    /// A service takes one readiness probe; setting several fails emit.
     ///
     /// # Panics
-    /// Panics if path is empty.
-    pub fn from_file(path: &str) -> Self {
-        if path.is_empty() {
-            panic!("SecretRef::from_file() requires a non-empty file path");
-        }
-        SecretRef {
-            name: String::new(),
-            source: SecretSource::File,
-            key: path.to_string(),
-        }
+    /// Panics if `command` is empty.
+    #[must_use]
+    pub fn ready_cmd(mut self, command: &str) -> Self {
+        assert!(!command.is_empty(), "readiness command cannot be empty");
+        self.service
+            .ready_probes
+            .push(ReadyProbe::Cmd(command.to_string()));
+        self
     }
 
-    /// Creates a secret reference that reads from HashiCorp Vault.
-    /// The path format is "path/to/secret#field".
+    /// Waits until the service accepts TCP connections on `port` before
+    /// starting the task.
     ///
     /// # Panics
-    /// Panics if path doesn't contain '#' separator (required format: "path#field").
-    pub fn from_vault(path: &str) -> Self {
-        if !path.contains('#') {
-            panic!("SecretRef::from_vault() requires 'path#field' format (e.g., 'secret/data/db#password')");
-        }
-        SecretRef {
-            name: String::new(),
-            source: SecretSource::Vault,
-            key: path.to_string(),
-        }
+    /// Panics if `port` is 0.
+    #[must_use]
+    pub fn ready_tcp(mut self, port: u16) -> Self {
+        assert!(port != 0, "readiness port cannot be 0");
+        self.service.ready_probes.push(ReadyProbe::Tcp(port));
+        self
     }
-}
-
-// =============================================================================
-// AI-NATIVE TYPES
-// =============================================================================
-
-/// Task criticality for AI prioritization.
-#[derive(Clone, Debug, Default, PartialEq)]
-pub enum Criticality {
-    /// High priority task
-    High,
-    /// Medium priority task
-    #[default]
-    Medium,
-    /// Low priority task
-    Low,
-}
-
-/// What AI should do when a task fails.
-#[derive(Clone, Debug, Default, PartialEq)]
-pub enum OnFailAction {
-    /// AI should analyze the failure
-    #[default]
-    Analyze,
-    /// AI should retry with modifications
-    Retry,
-    /// AI can skip without analysis
-    Skip,
-}
-
-/// How AI should select this task for execution.
-#[derive(Clone, Debug, Default, PartialEq)]
-pub enum SelectMode {
-    /// Only run if covers changed files
-    Smart,
-    /// Always run regardless of changes
-    #[default]
-    Always,
-    /// Only run when explicitly requested
-    Manual,
-}
 
-/// Semantic metadata for AI understanding of the task.
-#[derive(Clone, Default)]
-struct Semantic {
-    covers: Vec<String>,
-    intent: Option<String>,
-    criticality: Option<Criticality>,
-}
+    /// Waits until `GET path` on `port` answers with a 2xx status before
+    /// starting the task.
+    ///
+    /// # Panics
+    /// Panics if `path` doesn't start with `/` or `port` is 0.
+    #[must_use]
+    pub fn ready_http(mut self, path: &str, port: u16) -> Self {
+        assert!(path.starts_with('/'), "readiness path must start with /");
+        assert!(port != 0, "readiness port cannot be 0");
+        self.service.ready_probes.push(ReadyProbe::Http {
+            path: path.to_string(),
+            port,
+        });
+        self
+    }
 
-/// AI behavioral hooks.
-#[derive(Clone, Default)]
-struct AiHooks {
-    on_fail: Option<OnFailAction>,
-    select: Option<SelectMode>,
+    /// Fails the task if the service isn't ready within `secs` seconds.
+    /// Without it, the runner's default applies.
+    #[must_use]
+    pub fn ready_timeout(mut self, secs: u32) -> Self {
+        self.service.ready_timeout = Some(secs);
+        self
+    }
 }
 
 // =============================================================================
-// CONDITION BUILDER (Type-safe conditions)
+// K8S OPTIONS (Minimal API)
 // =============================================================================
 
-/// A type-safe condition for when a task should run.
+/// Kubernetes-specific configuration for a task.
 ///
-/// Use the builder functions to create conditions:
+/// This is a minimal API covering 95% of CI use cases.
+/// For advanced options (tolerations, affinity, security contexts),
+/// use `k8s_raw()` to pass raw JSON.
 ///
 /// # Example
 /// ```rust,ignore
-/// use sykli::{Pipeline, Condition};
+/// use sykli::{Pipeline, K8sOptions};
 ///
 /// let mut p = Pipeline::new();
-/// p.task("deploy")
-///     .run("kubectl apply")
-///     .when_cond(Condition::branch("main").or(Condition::tag("v*")));
+/// p.task("build")
+///     .run("cargo build")
+///     .k8s(K8sOptions {
+///         memory: Some("4Gi".into()),
+///         cpu: Some("2".into()),
+///         ..Default::default()
+///     });
 ///
-/// p.task("test")
-///     .run("cargo test")
-///     .when_cond(Condition::negate(Condition::branch("wip/*")));
+/// // For GPU tasks
+/// p.task("train")
+///     .run("python train.py")
+///     .k8s(K8sOptions {
+///         memory: Some("32Gi".into()),
+///         gpu: Some(1),
+///         ..Default::default()
+///     });
 /// ```
-#[derive(Clone, Default, Debug)]
-pub struct Condition {
-    expr: String,
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct K8sOptions {
+    /// Memory (e.g., "4Gi", "512Mi"). Sets both request and limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// CPU (e.g., "2", "500m"). Sets both request and limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<String>,
+    /// Number of NVIDIA GPUs to request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu: Option<u32>,
 }
 
-impl Condition {
-    /// Creates a condition that matches a branch name or pattern.
-    /// Supports glob patterns like "feature/*".
-    ///
-    /// # Panics
-    /// Panics if pattern is empty. Use `Condition::default()` for an always-true condition.
-    pub fn branch(pattern: &str) -> Self {
-        if pattern.is_empty() {
-            panic!("Condition::branch() requires a non-empty pattern. Use Condition::default() for always-true.");
+impl K8sOptions {
+    /// Merges defaults with task-specific options.
+    /// Task options override defaults.
+    pub fn merge(defaults: &K8sOptions, task: &K8sOptions) -> K8sOptions {
+        let mut result = defaults.clone();
+
+        if task.memory.is_some() {
+            result.memory = task.memory.clone();
         }
-        if pattern.contains('*') {
-            Condition {
-                expr: format!("branch matches '{}'", pattern),
-            }
-        } else {
-            Condition {
-                expr: format!("branch == '{}'", pattern),
-            }
+        if task.cpu.is_some() {
+            result.cpu = task.cpu.clone();
         }
-    }
-
-    /// Creates a condition that matches a tag name or pattern.
-    /// Supports glob patterns like "v*".
-    pub fn tag(pattern: &str) -> Self {
-        if pattern.is_empty() {
-            Condition {
-                expr: "tag != ''".to_string(),
-            }
-        } else if pattern.contains('*') {
-            Condition {
-                expr: format!("tag matches '{}'", pattern),
-            }
-        } else {
-            Condition {
-                expr: format!("tag == '{}'", pattern),
-            }
+        if task.gpu.is_some() {
+            result.gpu = task.gpu;
         }
-    }
 
-    /// Creates a condition that matches when any tag is present.
-    pub fn has_tag() -> Self {
-        Condition {
-            expr: "tag != ''".to_string(),
-        }
+        result
     }
 
-    /// Creates a condition that matches a CI event type.
-    pub fn event(event_type: &str) -> Self {
-        Condition {
-            expr: format!("event == '{}'", event_type),
-        }
+    /// Returns true if no options are set.
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_none() && self.cpu.is_none() && self.gpu.is_none()
     }
 
-    /// Creates a condition that matches when running in CI.
-    pub fn in_ci() -> Self {
-        Condition {
-            expr: "ci == true".to_string(),
+    /// Validates K8s options and returns a list of errors.
+    pub fn validate(&self) -> Vec<K8sValidationError> {
+        let mut errors = Vec::new();
+
+        if let Some(ref v) = self.memory {
+            if let Some(err) = validate_k8s_memory("memory", v) {
+                errors.push(err);
+            }
         }
-    }
 
-    /// Negates a condition.
-    ///
-    /// Note: Named `negate` instead of `not` to avoid confusion with `std::ops::Not`.
-    pub fn negate(c: Condition) -> Self {
-        Condition {
-            expr: format!("!({})", c.expr),
+        if let Some(ref v) = self.cpu {
+            if let Some(err) = validate_k8s_cpu("cpu", v) {
+                errors.push(err);
+            }
         }
+
+        errors
     }
+}
 
-    /// Combines conditions with OR logic.
-    pub fn or(self, other: Condition) -> Self {
-        Condition {
-            expr: format!("({}) || ({})", self.expr, other.expr),
-        }
-    }
-
-    /// Combines conditions with AND logic.
-    pub fn and(self, other: Condition) -> Self {
-        Condition {
-            expr: format!("({}) && ({})", self.expr, other.expr),
-        }
-    }
+/// Error from K8s options validation.
+#[derive(Debug, Clone)]
+pub struct K8sValidationError {
+    /// Field that failed validation (e.g., "resources.memory").
+    pub field: String,
+    /// The invalid value.
+    pub value: String,
+    /// Description of what's wrong.
+    pub message: String,
 }
 
-impl std::fmt::Display for Condition {
+impl std::fmt::Display for K8sValidationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.expr)
+        write!(
+            f,
+            "k8s.{}: {} (got {:?})",
+            self.field, self.message, self.value
+        )
     }
 }
 
-#[derive(Clone, Default)]
-struct TaskData {
-    kind: NodeKind,
-    name: String,
-    task_type: Option<TaskType>,
-    success_criteria: Vec<SuccessCriterion>,
-    command: String,
-    primitive: Option<String>,
-    agent: Option<String>,
-    context: Vec<String>,
-    deterministic: bool,
-    container: Option<String>,
-    workdir: Option<String>,
-    env: HashMap<String, String>,
-    mounts: Vec<Mount>,
-    inputs: Vec<String>,         // v1-style file patterns
-    task_inputs: Vec<TaskInput>, // v2-style inputs from other tasks
-    outputs: HashMap<String, String>,
-    depends_on: Vec<String>,
-    condition: Option<String>,
-    when_cond: Option<Condition>, // Type-safe condition (alternative to string)
-    secrets: Vec<String>,         // v1-style secret names
-    secret_refs: Vec<SecretRef>,  // v2-style typed secret references
-    matrix: HashMap<String, Vec<String>>,
-    services: Vec<Service>,
-    // Robustness features
-    retry: Option<u32>,   // Number of retries on failure
-    timeout: Option<u32>, // Timeout in seconds
-    // K8s options
-    k8s_options: Option<K8sOptions>,
-    k8s_raw: Option<String>, // Raw K8s JSON for advanced options
-    // Deprecated: no longer serialized
-    target_name: Option<String>,
-    // Node placement - required node labels
-    requires: Vec<String>,
-    // AI-native fields
-    semantic: Semantic,
-    ai_hooks: AiHooks,
-    // Capability-based dependencies
-    provides: Vec<(String, Option<String>)>,
-    needs: Vec<String>,
-    // Gate fields (if set, this is a gate not a regular task)
-    gate: Option<GateConfig>,
-    // Cross-platform verification mode
-    verify: Option<String>,
+impl std::error::Error for K8sValidationError {}
+
+fn validate_k8s_memory(field: &str, value: &str) -> Option<K8sValidationError> {
+    Some(K8sValidationError {
+        field: field.to_string(),
+        value: value.to_string(),
+        message: memory_quantity_error("memory", value)?,
+    })
 }
 
-/// Gate configuration for approval gates.
-#[derive(Clone, Default)]
-struct GateConfig {
-    strategy: String,
-    timeout: u32,
-    message: Option<String>,
-    env_var: Option<String>,
-    file_path: Option<String>,
+/// Why `value` isn't a K8s-style memory quantity like `512Mi`, with a
+/// suggestion for common unit mistakes. `what` names the setting, e.g.
+/// `"memory"`.
+fn memory_quantity_error(what: &str, value: &str) -> Option<String> {
+    if K8S_MEMORY_PATTERN.is_match(value) {
+        return None;
+    }
+
+    // Provide helpful suggestions for common mistakes
+    let lower = value.to_lowercase();
+    let suggestion = if lower.ends_with("gb") {
+        " (did you mean 'Gi'?)"
+    } else if lower.ends_with("mb") {
+        " (did you mean 'Mi'?)"
+    } else if lower.ends_with("kb") {
+        " (did you mean 'Ki'?)"
+    } else {
+        ""
+    };
+
+    Some(format!(
+        "invalid {} format, use Ki/Mi/Gi/Ti (e.g., '512Mi', '4Gi'){}",
+        what, suggestion
+    ))
 }
 
-impl<'a> Task<'a> {
-    /// Applies a template's configuration to this task.
-    ///
-    /// Template settings are applied first, then task-specific settings override them.
-    #[must_use]
-    pub fn from(self, tmpl: &Template) -> Self {
-        let task = &mut self.pipeline.tasks[self.index];
+fn validate_k8s_cpu(field: &str, value: &str) -> Option<K8sValidationError> {
+    if K8S_CPU_PATTERN.is_match(value) {
+        return None;
+    }
 
-        // Apply template settings (task settings will override these)
-        if task.container.is_none() {
-            task.container = tmpl.container.clone();
-        }
-        if task.workdir.is_none() {
-            task.workdir = tmpl.workdir.clone();
-        }
+    Some(K8sValidationError {
+        field: field.to_string(),
+        value: value.to_string(),
+        message: "invalid CPU format, use cores or millicores (e.g., '500m', '0.5', '2')"
+            .to_string(),
+    })
+}
 
-        // Merge env: template first, then task overrides
-        for (k, v) in &tmpl.env {
-            if !task.env.contains_key(k) {
-                task.env.insert(k.clone(), v.clone());
-            }
-        }
+// =============================================================================
+// STRING SIMILARITY
+// =============================================================================
 
-        // Prepend template mounts
-        let mut new_mounts = tmpl.mounts.clone();
-        new_mounts.append(&mut task.mounts);
-        task.mounts = new_mounts;
+/// Finds the most similar task name using Jaro-Winkler distance.
+fn suggest_task_name<'a>(unknown: &str, known: &[&'a str]) -> Option<&'a str> {
+    let mut best: Option<&str> = None;
+    let mut best_score = 0.0;
 
-        self
+    for &name in known {
+        let score = jaro_winkler(unknown, name);
+        if score > best_score && score >= 0.8 {
+            best_score = score;
+            best = Some(name);
+        }
     }
+    best
+}
 
-    /// Returns the name of this task.
-    pub fn name(&self) -> String {
-        self.pipeline.tasks[self.index].name.clone()
+/// Computes the Jaro-Winkler similarity between two strings (0-1).
+fn jaro_winkler(s1: &str, s2: &str) -> f64 {
+    if s1 == s2 {
+        return 1.0;
     }
-
-    /// Sets the command for this task.
-    ///
-    /// # Panics
-    /// Panics if `cmd` is empty.
-    #[must_use]
-    pub fn run(self, cmd: &str) -> Self {
-        assert!(!cmd.is_empty(), "command cannot be empty");
-        self.pipeline.tasks[self.index].command = cmd.to_string();
-        self
+    if s1.is_empty() || s2.is_empty() {
+        return 0.0;
     }
 
-    /// Sets the semantic class of this executable task.
-    #[must_use]
-    pub fn task_type(self, task_type: TaskType) -> Self {
-        self.pipeline.tasks[self.index].task_type = Some(task_type);
-        self
-    }
+    let s1_chars: Vec<char> = s1.chars().collect();
+    let s2_chars: Vec<char> = s2.chars().collect();
 
-    /// Declares verification metadata for this executable task.
-    ///
-    /// Phase 3C-1 emits these criteria but does not change execution behavior.
-    #[must_use]
-    pub fn success_criteria(self, criteria: &[SuccessCriterion]) -> Self {
-        let exit_code_count = criteria
-            .iter()
-            .filter(|criterion| matches!(criterion, SuccessCriterion::ExitCode(_)))
-            .count();
-        assert!(
-            exit_code_count <= 1,
-            "multiple exit_code success criteria are not allowed"
-        );
-        for criterion in criteria {
-            match criterion {
-                SuccessCriterion::FileExists(path) | SuccessCriterion::FileNonEmpty(path) => {
-                    assert!(
-                        !path.is_empty(),
-                        "file success criterion path cannot be empty"
-                    );
-                }
-                SuccessCriterion::ExitCode(code) => {
-                    assert!(
-                        (0..=255).contains(code),
-                        "exit_code success criterion must be between 0 and 255"
-                    );
-                }
+    let match_window = (s1_chars.len().max(s2_chars.len()) / 2).saturating_sub(1);
+
+    let mut s1_matches = vec![false; s1_chars.len()];
+    let mut s2_matches = vec![false; s2_chars.len()];
+
+    let mut matches = 0usize;
+    let mut transpositions = 0usize;
+
+    for i in 0..s1_chars.len() {
+        let start = i.saturating_sub(match_window);
+        let end = (i + match_window + 1).min(s2_chars.len());
+
+        for j in start..end {
+            if s2_matches[j] || s1_chars[i] != s2_chars[j] {
+                continue;
             }
+            s1_matches[i] = true;
+            s2_matches[j] = true;
+            matches += 1;
+            break;
         }
-        self.pipeline.tasks[self.index]
-            .success_criteria
-            .extend(criteria.iter().cloned());
-        self
-    }
-
-    /// Sets the container image for this task.
-    ///
-    /// # Panics
-    /// Panics if `image` is empty.
-    #[must_use]
-    pub fn container(self, image: &str) -> Self {
-        assert!(!image.is_empty(), "container image cannot be empty");
-        self.pipeline.tasks[self.index].container = Some(image.to_string());
-        self
     }
 
-    /// Mounts a directory into the container.
-    ///
-    /// # Panics
-    /// Panics if `path` is empty or not absolute (must start with `/`).
-    #[must_use]
-    pub fn mount(self, dir: &Directory, path: &str) -> Self {
-        assert!(!path.is_empty(), "container mount path cannot be empty");
-        assert!(
-            path.starts_with('/'),
-            "container mount path must be absolute (start with /)"
-        );
-        self.pipeline.tasks[self.index].mounts.push(Mount {
-            resource: dir.id(),
-            path: path.to_string(),
-            mount_type: "directory".to_string(),
-        });
-        self
+    if matches == 0 {
+        return 0.0;
     }
 
-    /// Mounts a cache volume into the container.
-    ///
-    /// # Panics
-    /// Panics if `path` is empty or not absolute (must start with `/`).
-    #[must_use]
-    pub fn mount_cache(self, cache: &CacheVolume, path: &str) -> Self {
-        assert!(!path.is_empty(), "container mount path cannot be empty");
-        assert!(
-            path.starts_with('/'),
-            "container mount path must be absolute (start with /)"
-        );
-        self.pipeline.tasks[self.index].mounts.push(Mount {
-            resource: cache.id(),
-            path: path.to_string(),
-            mount_type: "cache".to_string(),
-        });
-        self
+    let mut k = 0;
+    for i in 0..s1_chars.len() {
+        if !s1_matches[i] {
+            continue;
+        }
+        while !s2_matches[k] {
+            k += 1;
+        }
+        if s1_chars[i] != s2_chars[k] {
+            transpositions += 1;
+        }
+        k += 1;
     }
 
-    /// Mounts the current working directory to `/work` and sets workdir.
-    /// This is a convenience method that combines mount + workdir for the common case.
+    let jaro = (matches as f64 / s1_chars.len() as f64
+        + matches as f64 / s2_chars.len() as f64
+        + (matches as f64 - transpositions as f64 / 2.0) / matches as f64)
+        / 3.0;
+
+    // Apply Winkler prefix bonus
+    let mut prefix = 0;
+    for i in 0..4.min(s1_chars.len()).min(s2_chars.len()) {
+        if s1_chars[i] == s2_chars[i] {
+            prefix += 1;
+        } else {
+            break;
+        }
+    }
+
+    jaro + prefix as f64 * 0.1 * (1.0 - jaro)
+}
+
+// =============================================================================
+// TEMPLATE
+// =============================================================================
+
+/// A reusable task configuration template.
+///
+/// Templates allow you to define common settings (container, mounts, env)
+/// that can be inherited by multiple tasks via `from()`.
+///
+/// # Example
+/// ```rust
+/// use sykli::{Pipeline, Template};
+///
+/// let mut p = Pipeline::new();
+/// let src = p.dir(".");
+///
+/// let rust = Template::new()
+///     .container("rust:1.75")
+///     .mount_dir(&src, "/src")
+///     .workdir("/src");
+///
+/// p.task("test").from(&rust).run("cargo test");
+/// p.task("build").from(&rust).run("cargo build");
+/// ```
+#[derive(Clone, Default)]
+pub struct Template {
+    // Set for templates registered with Pipeline::template
+    name: Option<String>,
+    container: Option<String>,
+    workdir: Option<String>,
+    env: HashMap<String, String>,
+    mounts: Vec<Mount>,
+    inputs: Vec<String>,
+    secrets: Vec<String>,
+    retry: Option<RetryPolicy>,
+    timeout: Option<u32>,
+    k8s: Option<K8sOptions>,
+}
+
+impl Template {
+    /// Creates a new empty template.
     #[must_use]
-    pub fn mount_cwd(self) -> Self {
-        self.pipeline.tasks[self.index].mounts.push(Mount {
-            resource: "src:.".to_string(),
-            path: "/work".to_string(),
-            mount_type: "directory".to_string(),
-        });
-        self.pipeline.tasks[self.index].workdir = Some("/work".to_string());
-        self
+    pub fn new() -> Self {
+        Template::default()
     }
 
-    /// Mounts the current working directory to a custom path and sets workdir.
-    ///
-    /// # Panics
-    /// Panics if `path` is empty or not absolute (must start with `/`).
+    /// Sets the container image for tasks using this template.
     #[must_use]
-    pub fn mount_cwd_at(self, path: &str) -> Self {
-        assert!(!path.is_empty(), "container mount path cannot be empty");
-        assert!(
-            path.starts_with('/'),
-            "container mount path must be absolute (start with /)"
-        );
-        self.pipeline.tasks[self.index].mounts.push(Mount {
-            resource: "src:.".to_string(),
-            path: path.to_string(),
-            mount_type: "directory".to_string(),
-        });
-        self.pipeline.tasks[self.index].workdir = Some(path.to_string());
+    pub fn container(mut self, image: &str) -> Self {
+        assert!(!image.is_empty(), "container image cannot be empty");
+        self.container = Some(image.to_string());
         self
     }
 
-    /// Sets the working directory inside the container.
-    ///
-    /// # Panics
-    /// Panics if `path` is empty or not absolute (must start with `/`).
+    /// Sets the working directory for tasks using this template. It may be
+    /// relative to the project root while the template has no container;
+    /// see [`Task::workdir`].
     #[must_use]
-    pub fn workdir(self, path: &str) -> Self {
-        assert!(
-            !path.is_empty(),
-            "container working directory cannot be empty"
-        );
+    pub fn workdir(mut self, path: &str) -> Self {
+        assert!(!path.is_empty(), "workdir cannot be empty");
         assert!(
-            path.starts_with('/'),
-            "container working directory must be absolute (start with /)"
+            self.container.is_none() || path.starts_with('/'),
+            "workdir must be absolute"
         );
-        self.pipeline.tasks[self.index].workdir = Some(path.to_string());
+        self.workdir = Some(path.to_string());
         self
     }
 
-    /// Sets an environment variable.
-    ///
-    /// # Panics
-    /// Panics if `key` is empty.
+    /// Sets an environment variable for tasks using this template.
     #[must_use]
-    pub fn env(self, key: &str, value: &str) -> Self {
-        assert!(!key.is_empty(), "environment variable key cannot be empty");
-        self.pipeline.tasks[self.index]
-            .env
-            .insert(key.to_string(), value.to_string());
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        assert!(!key.is_empty(), "env key cannot be empty");
+        self.env.insert(key.to_string(), value.to_string());
         self
     }
 
-    /// Sets input file patterns for caching.
+    /// Adds input file patterns for tasks using this template.
     #[must_use]
-    pub fn inputs(self, patterns: &[&str]) -> Self {
-        self.pipeline.tasks[self.index]
-            .inputs
+    pub fn inputs(mut self, patterns: &[&str]) -> Self {
+        self.inputs
             .extend(patterns.iter().map(|s| (*s).to_string()));
         self
     }
 
-    /// Sets a named output path.
-    ///
-    /// # Panics
-    /// Panics if `name` or `path` is empty.
-    #[must_use]
-    pub fn output(self, name: &str, path: &str) -> Self {
-        assert!(!name.is_empty(), "output name cannot be empty");
-        assert!(!path.is_empty(), "output path cannot be empty");
-        self.pipeline.tasks[self.index]
-            .outputs
-            .insert(name.to_string(), path.to_string());
-        self
-    }
-
-    /// Declares that this task needs an artifact from another task's output.
-    ///
-    /// This automatically adds a dependency on the source task.
-    ///
-    /// # Arguments
-    /// * `from_task` - Name of the task that produces the artifact
-    /// * `output_name` - Name of the output from that task
-    /// * `dest_path` - Path where the artifact should be available in this task
-    ///
-    /// # Panics
-    /// Panics if any argument is empty.
+    /// Declares a secret required by tasks using this template.
     #[must_use]
-    pub fn input_from(self, from_task: &str, output_name: &str, dest_path: &str) -> Self {
-        assert!(
-            !from_task.is_empty(),
-            "input_from: from_task cannot be empty"
-        );
-        assert!(
-            !output_name.is_empty(),
-            "input_from: output_name cannot be empty"
-        );
-        assert!(
-            !dest_path.is_empty(),
-            "input_from: dest_path cannot be empty"
-        );
-
-        let task = &mut self.pipeline.tasks[self.index];
-
-        // Add the task input
-        task.task_inputs.push(TaskInput {
-            from_task: from_task.to_string(),
-            output: output_name.to_string(),
-            dest_path: dest_path.to_string(),
-        });
-
-        // Auto-add dependency if not already present
-        if !task.depends_on.contains(&from_task.to_string()) {
-            task.depends_on.push(from_task.to_string());
-        }
-
+    pub fn secret(mut self, name: &str) -> Self {
+        assert!(!name.is_empty(), "secret name cannot be empty");
+        self.secrets.push(name.to_string());
         self
     }
 
-    /// Sets output paths (for backward compatibility).
-    ///
-    /// # Panics
-    /// Panics if any path is empty.
+    /// Sets the retry count for tasks using this template. 0 clears it.
     #[must_use]
-    pub fn outputs(self, paths: &[&str]) -> Self {
-        for (i, path) in paths.iter().enumerate() {
-            assert!(!path.is_empty(), "output path cannot be empty");
-            self.pipeline.tasks[self.index]
-                .outputs
-                .insert(format!("output_{i}"), (*path).to_string());
-        }
+    pub fn retry(mut self, count: u32) -> Self {
+        self.retry = (count > 0).then(|| RetryPolicy::new(count));
         self
     }
 
-    /// Sets a single dependency - this task runs after the named task.
-    ///
-    /// This is a convenience method matching the Go SDK's `After(task)` signature.
+    /// Sets the retry policy for tasks using this template.
     #[must_use]
-    pub fn after_one(self, task: &str) -> Self {
-        if !task.is_empty() {
-            let deps = &mut self.pipeline.tasks[self.index].depends_on;
-            if !deps.contains(&task.to_string()) {
-                deps.push(task.to_string());
-            }
-        }
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
         self
     }
 
-    /// Sets dependencies - this task runs after the named tasks.
-    /// Duplicate dependencies are ignored.
+    /// Sets the timeout in seconds for tasks using this template.
     #[must_use]
-    pub fn after(self, tasks: &[&str]) -> Self {
-        let deps = &mut self.pipeline.tasks[self.index].depends_on;
-        for task in tasks {
-            if !task.is_empty() && !deps.contains(&task.to_string()) {
-                deps.push(task.to_string());
-            }
-        }
+    pub fn timeout(mut self, seconds: u32) -> Self {
+        assert!(seconds > 0, "timeout must be greater than 0");
+        self.timeout = Some(seconds);
         self
     }
 
-    /// Sets dependencies on all tasks in a TaskGroup.
-    /// Duplicate dependencies are ignored.
+    /// Sets Kubernetes options for tasks using this template.
     ///
-    /// # Example
-    /// ```rust,ignore
-    /// let tests = p.matrix("versions", &["1.21", "1.22"], |p, v| {
-    ///     p.task(&format!("test-{}", v)).run("go test");
-    /// });
-    /// p.task("deploy").after_group(&tests).run("deploy");
-    /// ```
+    /// Merged with the task's own options via [`K8sOptions::merge`], the
+    /// task winning.
     #[must_use]
-    pub fn after_group(self, group: &TaskGroup) -> Self {
-        let deps = &mut self.pipeline.tasks[self.index].depends_on;
-        for name in &group.task_names {
-            if !name.is_empty() && !deps.contains(name) {
-                deps.push(name.clone());
-            }
-        }
+    pub fn k8s(mut self, opts: K8sOptions) -> Self {
+        self.k8s = Some(opts);
         self
     }
 
-    /// Sets a condition for when this task should run.
+    /// Returns a new template with `other` layered over this one.
     ///
-    /// The condition is evaluated at runtime based on CI context variables:
-    /// - `branch == 'main'` - run only on main branch
-    /// - `branch != 'main'` - run on all branches except main
-    /// - `tag != ''` - run only when a tag is present
-    /// - `event == 'push'` - run only on push events
-    /// - `ci == true` - run only in CI environment
+    /// Scalars set in `other` (container, workdir, retry, timeout) win, env
+    /// vars from `other` override same-named ones, mounts, inputs and
+    /// secrets are appended, and K8s options are merged with `other`
+    /// winning. The result is unnamed.
     ///
     /// # Example
     /// ```rust
-    /// use sykli::Pipeline;
+    /// use sykli::{Pipeline, Template};
     ///
     /// let mut p = Pipeline::new();
-    /// p.task("deploy")
-    ///     .run("./deploy.sh")
-    ///     .when("branch == 'main'");
-    /// ```
+    /// let src = p.dir(".");
+    /// let registry = p.cache("cargo-registry");
     ///
-    /// # Panics
-    /// Panics if `condition` is empty.
+    /// let base = Template::new().container("rust:1.75").mount_dir(&src, "/src");
+    /// let rust = base.extend(
+    ///     &Template::new().mount_cache(&registry, "/usr/local/cargo/registry"),
+    /// );
+    /// p.task("test").from(&rust).run("cargo test");
+    /// ```
     #[must_use]
-    pub fn when(self, condition: &str) -> Self {
-        assert!(!condition.is_empty(), "condition cannot be empty");
-        self.pipeline.tasks[self.index].condition = Some(condition.to_string());
-        self
+    pub fn extend(&self, other: &Template) -> Template {
+        let mut result = self.clone();
+        result.name = None;
+        if other.container.is_some() {
+            result.container = other.container.clone();
+        }
+        if other.workdir.is_some() {
+            result.workdir = other.workdir.clone();
+        }
+        result
+            .env
+            .extend(other.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        result.mounts.extend(other.mounts.iter().cloned());
+        for input in &other.inputs {
+            if !result.inputs.contains(input) {
+                result.inputs.push(input.clone());
+            }
+        }
+        for secret in &other.secrets {
+            if !result.secrets.contains(secret) {
+                result.secrets.push(secret.clone());
+            }
+        }
+        if other.retry.is_some() {
+            result.retry = other.retry.clone();
+        }
+        result.timeout = other.timeout.or(result.timeout);
+        result.k8s = match (&result.k8s, &other.k8s) {
+            (Some(base), Some(over)) => Some(K8sOptions::merge(base, over)),
+            (base, over) => over.clone().or_else(|| base.clone()),
+        };
+        result
     }
 
-    /// Declares that this task requires a secret environment variable.
-    ///
-    /// The secret should be provided by the CI environment (e.g., GitHub Actions secrets).
-    /// The executor will validate that the secret is present before running the task.
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("deploy")
-    ///     .run("./deploy.sh")
-    ///     .secret("GITHUB_TOKEN")
-    ///     .secret("NPM_TOKEN");
-    /// ```
+    /// Returns the name of a template registered with [`Pipeline::template`].
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Adds a directory mount for tasks using this template.
     ///
-    /// # Panics
-    /// Panics if `name` is empty.
+    /// Same as [`Template::mount_dir`], named to match [`Task::mount`].
     #[must_use]
-    pub fn secret(self, name: &str) -> Self {
-        assert!(!name.is_empty(), "secret name cannot be empty");
-        self.pipeline.tasks[self.index]
-            .secrets
-            .push(name.to_string());
-        self
-    }
-
-    /// Declares multiple secrets that this task requires.
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("deploy")
-    ///     .run("./deploy.sh")
-    ///     .secrets(&["GITHUB_TOKEN", "NPM_TOKEN", "AWS_KEY"]);
-    /// ```
-    ///
-    /// # Panics
-    /// Panics if any secret name is empty.
-    #[must_use]
-    pub fn secrets(self, names: &[&str]) -> Self {
-        for name in names {
-            assert!(!name.is_empty(), "secret name cannot be empty");
-        }
-        self.pipeline.tasks[self.index]
-            .secrets
-            .extend(names.iter().map(|s| (*s).to_string()));
-        self
+    pub fn mount(self, dir: &Directory, path: &str) -> Self {
+        self.mount_dir(dir, path)
     }
 
-    /// Declares node labels that must be present for this task to run.
-    ///
-    /// Tasks with requires will only run on nodes that have all specified labels.
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("train")
-    ///     .run("python train.py")
-    ///     .requires(&["gpu", "docker"]);
-    ///
-    /// p.task("build")
-    ///     .run("docker build .")
-    ///     .requires(&["docker"]);
-    /// ```
-    ///
-    /// # Panics
-    /// Panics if any label is empty.
+    /// Adds a directory mount for tasks using this template.
     #[must_use]
-    pub fn requires(self, labels: &[&str]) -> Self {
-        for label in labels {
-            assert!(!label.is_empty(), "label cannot be empty");
-        }
-        self.pipeline.tasks[self.index]
-            .requires
-            .extend(labels.iter().map(|s| (*s).to_string()));
+    pub fn mount_dir(mut self, dir: &Directory, path: &str) -> Self {
+        assert!(!path.is_empty(), "mount path cannot be empty");
+        assert!(path.starts_with('/'), "mount path must be absolute");
+        self.mounts.push(Mount {
+            resource: dir.id(),
+            path: path.to_string(),
+            mount_type: "directory".to_string(),
+            dir: Some(dir.clone()),
+            cache: None,
+            file: None,
+            sharing: CacheSharing::Shared,
+        });
         self
     }
 
-    /// Sets the cross-platform verification mode for this task.
-    ///
-    /// Modes:
-    ///   - `"cross_platform"` — verify on a node with different OS/arch
-    ///   - `"always"` — always verify on any remote node
-    ///   - `"never"` — never verify this task remotely
-    ///
-    /// # Panics
-    /// Panics if `mode` is empty.
+    /// Adds a cache mount for tasks using this template.
     #[must_use]
-    pub fn verify(self, mode: &str) -> Self {
-        assert!(!mode.is_empty(), "verify mode cannot be empty");
-        self.pipeline.tasks[self.index].verify = Some(mode.to_string());
+    pub fn mount_cache(mut self, cache: &CacheVolume, path: &str) -> Self {
+        assert!(!path.is_empty(), "mount path cannot be empty");
+        assert!(path.starts_with('/'), "mount path must be absolute");
+        self.mounts.push(Mount {
+            resource: cache.id(),
+            path: path.to_string(),
+            mount_type: "cache".to_string(),
+            dir: None,
+            cache: Some(cache.clone()),
+            file: None,
+            sharing: CacheSharing::Shared,
+        });
         self
     }
+}
 
-    // ─────────────────────────────────────────────────────────────────────────────
-    // AI-NATIVE METHODS
-    // ─────────────────────────────────────────────────────────────────────────────
+/// Builder for a template registered with [`Pipeline::template`].
+///
+/// Method names match [`Task`]. Fetch the finished template with
+/// [`Pipeline::get_template`] to apply it.
+pub struct NamedTemplate<'a> {
+    pipeline: &'a mut Pipeline,
+    index: usize,
+}
 
-    /// Sets file patterns this task tests or affects (for smart selection).
-    #[must_use]
-    pub fn covers(self, patterns: &[&str]) -> Self {
-        self.pipeline.tasks[self.index]
-            .semantic
-            .covers
-            .extend(patterns.iter().map(|s| (*s).to_string()));
+impl NamedTemplate<'_> {
+    fn update(self, f: impl FnOnce(Template) -> Template) -> Self {
+        let tmpl = &mut self.pipeline.templates[self.index];
+        *tmpl = f(std::mem::take(tmpl));
         self
     }
 
-    /// Sets a description of what this task does (for AI context).
+    /// Sets the container image for tasks using this template.
     #[must_use]
-    pub fn intent(self, description: &str) -> Self {
-        self.pipeline.tasks[self.index].semantic.intent = Some(description.to_string());
-        self
+    pub fn container(self, image: &str) -> Self {
+        self.update(|t| t.container(image))
     }
 
-    /// Marks this task as high-criticality.
+    /// Sets the working directory for tasks using this template.
     #[must_use]
-    pub fn critical(self) -> Self {
-        self.pipeline.tasks[self.index].semantic.criticality = Some(Criticality::High);
-        self
+    pub fn workdir(self, path: &str) -> Self {
+        self.update(|t| t.workdir(path))
     }
 
-    /// Sets the criticality level.
+    /// Sets an environment variable for tasks using this template.
     #[must_use]
-    pub fn set_criticality(self, level: Criticality) -> Self {
-        self.pipeline.tasks[self.index].semantic.criticality = Some(level);
-        self
+    pub fn env(self, key: &str, value: &str) -> Self {
+        self.update(|t| t.env(key, value))
     }
 
-    /// Sets what AI should do when this task fails.
+    /// Adds a directory mount for tasks using this template.
     #[must_use]
-    pub fn on_fail(self, action: OnFailAction) -> Self {
-        self.pipeline.tasks[self.index].ai_hooks.on_fail = Some(action);
-        self
+    pub fn mount(self, dir: &Directory, path: &str) -> Self {
+        self.update(|t| t.mount_dir(dir, path))
     }
 
-    /// Sets how AI should select this task for execution.
+    /// Adds a cache mount for tasks using this template.
     #[must_use]
-    pub fn select_mode(self, mode: SelectMode) -> Self {
-        self.pipeline.tasks[self.index].ai_hooks.select = Some(mode);
-        self
+    pub fn mount_cache(self, cache: &CacheVolume, path: &str) -> Self {
+        self.update(|t| t.mount_cache(cache, path))
     }
 
-    /// Enables smart task selection based on covers patterns.
+    /// Adds input file patterns for tasks using this template.
     #[must_use]
-    pub fn smart(self) -> Self {
-        self.pipeline.tasks[self.index].ai_hooks.select = Some(SelectMode::Smart);
-        self
+    pub fn inputs(self, patterns: &[&str]) -> Self {
+        self.update(|t| t.inputs(patterns))
     }
 
-    // ─────────────────────────────────────────────────────────────────────────────
-    // CAPABILITY-BASED DEPENDENCIES
-    // ─────────────────────────────────────────────────────────────────────────────
-
-    /// Declares that this task produces a named capability.
-    /// Other tasks can declare they need this capability via `needs()`.
-    /// The optional value is injected as SYKLI_CAP_{NAME} env var in needing tasks.
-    ///
-    /// # Panics
-    /// Panics if `name` is empty.
+    /// Declares a secret required by tasks using this template.
     #[must_use]
-    pub fn provides(self, name: &str, value: Option<&str>) -> Self {
-        assert!(!name.is_empty(), "capability name cannot be empty");
-        self.pipeline.tasks[self.index]
-            .provides
-            .push((name.to_string(), value.map(|v| v.to_string())));
-        self
+    pub fn secret(self, name: &str) -> Self {
+        self.update(|t| t.secret(name))
     }
 
-    /// Declares that this task needs named capabilities.
-    /// A dependency on the providing task is auto-resolved by the engine.
-    ///
-    /// # Panics
-    /// Panics if any name is empty.
+    /// Sets the retry count for tasks using this template. 0 clears it.
     #[must_use]
-    pub fn needs(self, names: &[&str]) -> Self {
-        for name in names {
-            assert!(!name.is_empty(), "capability name cannot be empty");
-        }
-        self.pipeline.tasks[self.index]
-            .needs
-            .extend(names.iter().map(|s| (*s).to_string()));
-        self
+    pub fn retry(self, count: u32) -> Self {
+        self.update(|t| t.retry(count))
     }
 
-    // ─────────────────────────────────────────────────────────────────────────
-    // GATE METHODS
-    // ─────────────────────────────────────────────────────────────────────────
-
-    /// Sets the approval strategy for this gate task.
-    ///
-    /// # Panics
-    /// Panics if called on a non-gate task.
+    /// Sets the retry policy for tasks using this template.
     #[must_use]
-    pub fn gate_strategy(self, strategy: &str) -> Self {
-        let task = &mut self.pipeline.tasks[self.index];
-        let gate = task.gate.as_mut()
-            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
-        gate.strategy = strategy.to_string();
-        self
+    pub fn retry_policy(self, policy: RetryPolicy) -> Self {
+        self.update(|t| t.retry_policy(policy))
     }
 
-    /// Sets the approval prompt message for this gate task.
-    ///
-    /// # Panics
-    /// Panics if called on a non-gate task.
+    /// Sets the timeout in seconds for tasks using this template.
     #[must_use]
-    pub fn gate_message(self, message: &str) -> Self {
-        let task = &mut self.pipeline.tasks[self.index];
-        let gate = task.gate.as_mut()
-            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
-        gate.message = Some(message.to_string());
-        self
+    pub fn timeout(self, seconds: u32) -> Self {
+        self.update(|t| t.timeout(seconds))
     }
 
-    /// Sets the timeout in seconds for this gate task.
-    ///
-    /// # Panics
-    /// Panics if called on a non-gate task or if seconds is 0.
+    /// Sets Kubernetes options for tasks using this template.
     #[must_use]
-    pub fn gate_timeout(self, seconds: u32) -> Self {
-        assert!(seconds > 0, "gate timeout must be positive");
-        let task = &mut self.pipeline.tasks[self.index];
-        let gate = task.gate.as_mut()
-            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
-        gate.timeout = seconds;
-        self
+    pub fn k8s(self, opts: K8sOptions) -> Self {
+        self.update(|t| t.k8s(opts))
     }
+}
 
-    /// Sets the environment variable to poll for the env strategy.
-    ///
-    /// # Panics
-    /// Panics if called on a non-gate task.
-    #[must_use]
-    pub fn gate_env_var(self, env_var: &str) -> Self {
-        let task = &mut self.pipeline.tasks[self.index];
-        let gate = task.gate.as_mut()
-            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
-        gate.env_var = Some(env_var.to_string());
-        self
+// =============================================================================
+// BUILDER ERRORS
+// =============================================================================
+
+/// Error from a fallible builder method such as [`Pipeline::try_task`] or
+/// [`Task::try_run`].
+///
+/// The panicking methods panic with this error's message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PipelineError {
+    /// The task name is empty.
+    EmptyTaskName,
+    /// A task, gate or review node with this name already exists.
+    DuplicateTask {
+        /// The duplicate name.
+        name: String,
+    },
+    /// The command is empty.
+    EmptyCommand,
+    /// The container image is empty.
+    EmptyImage,
+    /// `container()` on a task that builds its image with
+    /// `container_from_dockerfile()`.
+    ContainerAlreadyBuilt {
+        /// Task name.
+        task: String,
+    },
+    /// A required value is empty.
+    Empty {
+        /// What it is, e.g. `"environment variable key"`.
+        what: &'static str,
+    },
+    /// A container path doesn't start with `/`.
+    RelativePath {
+        /// What it is, e.g. `"container mount path"`.
+        what: &'static str,
+        /// The path given.
+        value: String,
+    },
+    /// A timeout of 0 seconds.
+    ZeroTimeout,
+    /// A tag with characters other than lowercase letters, digits and `-`.
+    InvalidTag {
+        /// The tag given.
+        tag: String,
+    },
+    /// A task reads outputs of a matrix task being expanded, which no longer
+    /// has a single set of outputs.
+    MatrixInput {
+        /// The task reading the outputs.
+        task: String,
+        /// The matrix task.
+        from_task: String,
+    },
+    /// A git source is mounted with a different depth than where it was
+    /// mounted before.
+    GitDepthConflict {
+        /// Resource ID.
+        id: String,
+    },
+    /// A URL is mounted with a different digest or unpack setting than
+    /// where it was mounted before.
+    HttpFileConflict {
+        /// Resource ID.
+        id: String,
+    },
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineError::EmptyTaskName => write!(f, "task name cannot be empty"),
+            PipelineError::DuplicateTask { name } => write!(f, "task {:?} already exists", name),
+            PipelineError::EmptyCommand => write!(f, "command cannot be empty"),
+            PipelineError::EmptyImage => write!(f, "container image cannot be empty"),
+            PipelineError::ContainerAlreadyBuilt { task } => write!(
+                f,
+                "task {:?} already builds its image with container_from_dockerfile()",
+                task
+            ),
+            PipelineError::Empty { what } => write!(f, "{} cannot be empty", what),
+            PipelineError::RelativePath { what, .. } => {
+                write!(f, "{} must be absolute (start with /)", what)
+            }
+            PipelineError::ZeroTimeout => write!(f, "timeout must be greater than 0"),
+            PipelineError::InvalidTag { tag } => write!(
+                f,
+                "invalid tag {:?} (use lowercase letters, digits and -)",
+                tag
+            ),
+            PipelineError::MatrixInput { task, from_task } => write!(
+                f,
+                "task {:?} takes input from matrix task {:?}, which has one set of outputs per variant once expanded",
+                task, from_task
+            ),
+            PipelineError::GitDepthConflict { id } => write!(
+                f,
+                "git source {:?} is already mounted with a different depth",
+                id
+            ),
+            PipelineError::HttpFileConflict { id } => write!(
+                f,
+                "http file {:?} is already mounted with a different sha256 or unpack setting",
+                id
+            ),
+        }
     }
+}
 
-    /// Sets the file path to poll for the file strategy.
-    ///
-    /// # Panics
-    /// Panics if called on a non-gate task.
-    #[must_use]
-    pub fn gate_file_path(self, path: &str) -> Self {
-        let task = &mut self.pipeline.tasks[self.index];
-        let gate = task.gate.as_mut()
-            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
-        gate.file_path = Some(path.to_string());
-        self
+impl std::error::Error for PipelineError {}
+
+/// Unwraps the result of a `try_*` builder method, panicking with the error's
+/// message like the builder always has.
+#[track_caller]
+fn or_panic<T>(result: Result<T, PipelineError>) -> T {
+    result.unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Checks a path inside the container: non-empty and absolute.
+fn container_path(what: &'static str, path: &str) -> Result<(), PipelineError> {
+    if path.is_empty() {
+        return Err(PipelineError::Empty { what });
     }
+    if !path.starts_with('/') {
+        return Err(PipelineError::RelativePath {
+            what,
+            value: path.to_string(),
+        });
+    }
+    Ok(())
+}
 
-    /// Declares a typed secret reference with explicit source.
-    ///
-    /// This provides better DX than plain secret names by making the source explicit.
-    ///
-    /// # Example
-    /// ```rust,ignore
-    /// use sykli::{Pipeline, SecretRef};
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("deploy")
-    ///     .run("./deploy.sh")
-    ///     .secret_from("GITHUB_TOKEN", SecretRef::from_env("GH_TOKEN"))
-    ///     .secret_from("DB_PASSWORD", SecretRef::from_vault("secret/data/db#password"));
-    /// ```
-    ///
-    /// # Panics
-    /// Panics if `name` or `ref.key` is empty.
+// =============================================================================
+// TASK
+// =============================================================================
+
+/// A task in the pipeline.
+pub struct Task<'a> {
+    pipeline: &'a mut Pipeline,
+    index: usize,
+    // Handle to an already-defined task from task_or_get(): run() keeps its command
+    keep_command: bool,
+}
+
+/// An experimental review node in the pipeline graph.
+pub struct Review<'a> {
+    pipeline: &'a mut Pipeline,
+    index: usize,
+}
+
+/// Semantic class of an executable task.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TaskType {
+    Build,
+    Test,
+    Lint,
+    Format,
+    Scan,
+    Package,
+    Publish,
+    Deploy,
+    Migrate,
+    Generate,
+    Verify,
+    Cleanup,
+}
+
+/// Declared verification metadata for executable task success.
+/// Phase 3C-1 SDKs emit this metadata, but the engine does not evaluate it yet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SuccessCriterion {
+    ExitCode(i32),
+    FileExists(String),
+    FileNonEmpty(String),
+}
+
+impl SuccessCriterion {
+    fn to_json(&self) -> JsonSuccessCriterion {
+        match self {
+            SuccessCriterion::ExitCode(code) => {
+                assert!(
+                    (0..=255).contains(code),
+                    "exit_code success criterion must be between 0 and 255"
+                );
+                JsonSuccessCriterion {
+                    type_: "exit_code".to_string(),
+                    equals: Some(*code),
+                    path: None,
+                }
+            }
+            SuccessCriterion::FileExists(path) => {
+                assert!(
+                    !path.is_empty(),
+                    "file_exists success criterion path cannot be empty"
+                );
+                JsonSuccessCriterion {
+                    type_: "file_exists".to_string(),
+                    equals: None,
+                    path: Some(path.clone()),
+                }
+            }
+            SuccessCriterion::FileNonEmpty(path) => {
+                assert!(
+                    !path.is_empty(),
+                    "file_non_empty success criterion path cannot be empty"
+                );
+                JsonSuccessCriterion {
+                    type_: "file_non_empty".to_string(),
+                    equals: None,
+                    path: Some(path.clone()),
+                }
+            }
+        }
+    }
+}
+
+impl TaskType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TaskType::Build => "build",
+            TaskType::Test => "test",
+            TaskType::Lint => "lint",
+            TaskType::Format => "format",
+            TaskType::Scan => "scan",
+            TaskType::Package => "package",
+            TaskType::Publish => "publish",
+            TaskType::Deploy => "deploy",
+            TaskType::Migrate => "migrate",
+            TaskType::Generate => "generate",
+            TaskType::Verify => "verify",
+            TaskType::Cleanup => "cleanup",
+        }
+    }
+}
+
+#[derive(Clone, Default, PartialEq)]
+enum NodeKind {
+    #[default]
+    Task,
+    Review,
+}
+
+/// What an output path names, when it isn't a single file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputKind {
+    Glob,
+    Dir,
+}
+
+impl OutputKind {
+    /// Wire name, the `type` of a structured output.
+    fn as_str(&self) -> &'static str {
+        match self {
+            OutputKind::Glob => "glob",
+            OutputKind::Dir => "directory",
+        }
+    }
+}
+
+/// How the artifact store keeps an output, set with [`Task::output_with`].
+///
+/// The default keeps it for the store's own retention, uncompressed, and
+/// fails the task if the file is missing, the same as [`Task::output`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputOptions {
+    /// Days to keep the artifact; `None` uses the store's default. Emit
+    /// fails if 0.
+    pub retention_days: Option<u32>,
+    /// Whether to compress the artifact before storing it.
+    pub compress: bool,
+    /// Whether the task fails when the output is missing. Tasks reading an
+    /// optional output with [`Task::input_from`] get a lint warning.
+    pub required: bool,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            retention_days: None,
+            compress: false,
+            required: true,
+        }
+    }
+}
+
+/// Represents an input artifact from another task's output.
+#[derive(Clone, Default)]
+struct TaskInput {
+    from_task: String,
+    output: String,
+    dest_path: String,
+    matrix: BTreeMap<String, String>, // variant selector from input_from_matrix(); empty otherwise
+}
+
+// =============================================================================
+// TYPED SECRET REFERENCES
+// =============================================================================
+
+/// Source of a secret value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecretSource {
+    /// Read from environment variable
+    Env,
+    /// Read from file
+    File,
+    /// Read from HashiCorp Vault
+    Vault,
+    /// Read from a Kubernetes Secret
+    K8s,
+    /// Read from AWS Secrets Manager
+    Aws,
+    /// Read from Google Cloud Secret Manager
+    Gcp,
+    /// Read from a sops-encrypted file in the repo
+    Sops,
+}
+
+impl SecretSource {
+    /// Returns the source name used in pipeline JSON (`env`, `file`, `vault`,
+    /// `k8s`, `aws`, `gcp`, `sops`).
     #[must_use]
-    pub fn secret_from(self, name: &str, secret_ref: SecretRef) -> Self {
-        assert!(!name.is_empty(), "secret name cannot be empty");
-        assert!(!secret_ref.key.is_empty(), "secret key cannot be empty");
-        let mut sr = secret_ref;
-        sr.name = name.to_string();
-        self.pipeline.tasks[self.index].secret_refs.push(sr);
-        self
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecretSource::Env => "env",
+            SecretSource::File => "file",
+            SecretSource::Vault => "vault",
+            SecretSource::K8s => "k8s",
+            SecretSource::Aws => "aws",
+            SecretSource::Gcp => "gcp",
+            SecretSource::Sops => "sops",
+        }
     }
 
-    /// Sets a type-safe condition for when this task should run.
-    ///
-    /// This is an alternative to `when()` that catches errors at compile time.
+    /// Checks that `key` is in this source's format, returning the expected
+    /// format otherwise.
+    pub(crate) fn check_key(&self, key: &str) -> Result<(), &'static str> {
+        let ok = match self {
+            SecretSource::Env | SecretSource::File => !key.is_empty(),
+            SecretSource::Vault => key.matches('#').count() == 1,
+            SecretSource::K8s => is_k8s_secret_key(key),
+            SecretSource::Aws => is_aws_secret_key(key),
+            SecretSource::Gcp => is_gcp_secret_key(key),
+            SecretSource::Sops => is_sops_secret_key(key),
+        };
+        if ok {
+            return Ok(());
+        }
+        Err(match self {
+            SecretSource::Env => "a non-empty environment variable name",
+            SecretSource::File => "a non-empty file path",
+            SecretSource::Vault => {
+                "'path#field' format with a single '#' (e.g., 'secret/data/db#password')"
+            }
+            SecretSource::K8s => {
+                "'namespace/secret-name#key' format (e.g., 'prod/db-credentials#password')"
+            }
+            SecretSource::Aws => {
+                "'name' or 'name#jsonKey' format, where name may be an ARN (e.g., 'prod/db#password')"
+            }
+            SecretSource::Gcp => {
+                "'projects/PROJECT/secrets/NAME' format, optionally with '/versions/VERSION'"
+            }
+            SecretSource::Sops => {
+                "'path#key' format, where key may be dotted (e.g., 'ci/secrets.enc.yaml#deploy.token')"
+            }
+        })
+    }
+}
+
+/// `path#key`, where key is one or more non-empty dot-separated segments.
+fn is_sops_secret_key(key: &str) -> bool {
+    match key.split_once('#') {
+        Some((path, key)) => {
+            !path.is_empty() && !key.contains('#') && key.split('.').all(|s| !s.is_empty())
+        }
+        None => false,
+    }
+}
+
+/// `namespace/secret-name#key`, with lowercase DNS names and a Secret data key.
+fn is_k8s_secret_key(key: &str) -> bool {
+    let is_dns_name = |s: &str| {
+        !s.is_empty()
+            && s.len() <= 253
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+            && !s.starts_with(['-', '.'])
+            && !s.ends_with(['-', '.'])
+    };
+    let Some((path, data_key)) = key.split_once('#') else {
+        return false;
+    };
+    let Some((namespace, name)) = path.split_once('/') else {
+        return false;
+    };
+    is_dns_name(namespace)
+        && is_dns_name(name)
+        && !data_key.is_empty()
+        && data_key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// A secret name or ARN, optionally followed by `#jsonKey`.
+fn is_aws_secret_key(key: &str) -> bool {
+    let (id, json_key) = match key.split_once('#') {
+        Some((id, json_key)) => (id, Some(json_key)),
+        None => (key, None),
+    };
+    if json_key.is_some_and(str::is_empty) {
+        return false;
+    }
+    if id.starts_with("arn:") {
+        let parts: Vec<&str> = id.splitn(7, ':').collect();
+        return parts.len() == 7
+            && parts[2] == "secretsmanager"
+            && parts[5] == "secret"
+            && !parts[6].is_empty();
+    }
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/_+=.@-".contains(c))
+}
+
+/// `projects/PROJECT/secrets/NAME`, optionally with `/versions/VERSION`.
+fn is_gcp_secret_key(key: &str) -> bool {
+    let parts: Vec<&str> = key.split('/').collect();
+    let shape_ok = matches!(
+        parts[..],
+        ["projects", _, "secrets", _] | ["projects", _, "secrets", _, "versions", _]
+    );
+    shape_ok && parts.iter().all(|p| !p.is_empty())
+}
+
+/// Which secret names emit accepts, set with [`Pipeline::secret_name_rule`].
+/// Env secret keys follow the same rule. Without one, names outside
+/// `UpperSnake` are only a lint warning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SecretNameRule {
+    /// `[A-Z_][A-Z0-9_]*`, the usual shape of env var names.
+    #[default]
+    UpperSnake,
+    /// `[A-Za-z_][A-Za-z0-9_]*`, for environments with lowercase names.
+    Identifier,
+    /// Any non-empty name.
+    Unchecked,
+}
+
+impl SecretNameRule {
+    /// Whether `name` follows the rule.
+    pub fn allows(&self, name: &str) -> bool {
+        let word = |c: char| match self {
+            SecretNameRule::UpperSnake => c.is_ascii_uppercase() || c == '_',
+            _ => c.is_ascii_alphabetic() || c == '_',
+        };
+        match self {
+            SecretNameRule::Unchecked => !name.is_empty(),
+            _ => name.starts_with(word) && name.chars().all(|c| word(c) || c.is_ascii_digit()),
+        }
+    }
+
+    /// The rule as a pattern, for error messages.
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            SecretNameRule::UpperSnake => "[A-Z_][A-Z0-9_]*",
+            SecretNameRule::Identifier => "[A-Za-z_][A-Za-z0-9_]*",
+            SecretNameRule::Unchecked => ".+",
+        }
+    }
+}
+
+/// A typed reference to a secret with its source.
+///
+/// # Example
+/// ```rust,ignore
+/// use sykli::{Pipeline, SecretRef};
+///
+/// let mut p = Pipeline::new();
+/// p.task("deploy")
+///     .run("./deploy.sh")
+///     .secret_from("TOKEN", SecretRef::from_env("GITHUB_TOKEN"))
+///     .secret_from("DB_PASS", SecretRef::from_vault("secret/data/db#password"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecretRef {
+    /// Environment variable name in the task
+    pub name: String,
+    /// Where the secret comes from
+    pub source: SecretSource,
+    /// Source-specific key (env var name, file path, vault path, or secret
+    /// manager reference)
+    pub key: String,
+    /// Absolute path the value is written to instead of an env var, set by
+    /// [`Task::secret_file`]
+    pub mount_path: Option<String>,
+    /// Whether the task may run without it, set by [`SecretRef::optional`]
+    pub optional: bool,
+    /// Whether the engine masks the value in logs (default true)
+    pub mask: bool,
+}
+
+impl SecretRef {
+    fn new(source: SecretSource, key: &str) -> Self {
+        SecretRef {
+            name: String::new(),
+            source,
+            key: key.to_string(),
+            mount_path: None,
+            optional: false,
+            mask: true,
+        }
+    }
+
+    fn to_json(&self) -> JsonSecretRef {
+        JsonSecretRef {
+            name: self.name.clone(),
+            source: self.source.as_str().to_string(),
+            key: self.key.clone(),
+            mount_path: self.mount_path.clone(),
+            optional: self.optional.then_some(true),
+            mask: (!self.mask).then_some(false),
+        }
+    }
+
+    /// Lets the task run when the secret is absent, e.g. for a tracing
+    /// token. Emitted as `"optional": true`.
     ///
     /// # Example
-    /// ```rust,ignore
-    /// use sykli::{Pipeline, Condition};
+    /// ```rust
+    /// use sykli::{Pipeline, SecretRef};
     ///
     /// let mut p = Pipeline::new();
-    /// p.task("deploy")
-    ///     .run("kubectl apply")
-    ///     .when_cond(Condition::branch("main").or(Condition::tag("v*")));
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .secret_from("TRACING_TOKEN", SecretRef::from_env("TRACING_TOKEN").optional());
     /// ```
     #[must_use]
-    pub fn when_cond(self, c: Condition) -> Self {
-        self.pipeline.tasks[self.index].when_cond = Some(c);
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
         self
     }
 
-    /// Deprecated. No longer affects emitted pipeline JSON.
-    ///
-    /// Use concrete execution requirements such as `container`, `mount_dir`,
-    /// `mount_cache`, `k8s`, `service`, `workdir`, and `env` instead.
-    #[deprecated(
-        note = "target no longer affects emitted pipeline JSON; use concrete execution requirement fields instead"
-    )]
+    /// Tells the engine not to mask the value in logs, for values that
+    /// aren't sensitive but come from a secret store, like account IDs.
+    /// Emitted as `"mask": false`.
     #[must_use]
-    pub fn target(self, _name: &str) -> Self {
+    pub fn no_mask(mut self) -> Self {
+        self.mask = false;
         self
     }
 
-    /// Adds a matrix dimension for this task.
-    ///
-    /// Matrix builds run the task multiple times with different parameter combinations.
-    /// Each dimension's values are exposed as environment variables.
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("test")
-    ///     .run("cargo test")
-    ///     .matrix("rust_version", &["1.70", "1.75", "1.80"])
-    ///     .matrix("os", &["ubuntu", "macos"]);
-    /// // This creates 6 task variants (3 versions × 2 OS)
-    /// ```
+    /// Creates a secret reference that reads from an environment variable.
     ///
     /// # Panics
-    /// Panics if `key` or `values` is empty.
-    #[must_use]
-    pub fn matrix(self, key: &str, values: &[&str]) -> Self {
-        assert!(!key.is_empty(), "matrix key cannot be empty");
-        assert!(!values.is_empty(), "matrix values cannot be empty");
-        self.pipeline.tasks[self.index].matrix.insert(
-            key.to_string(),
-            values.iter().map(|s| (*s).to_string()).collect(),
-        );
-        self
+    /// Panics if env_var is empty.
+    pub fn from_env(env_var: &str) -> Self {
+        if env_var.is_empty() {
+            panic!("SecretRef::from_env() requires a non-empty environment variable name");
+        }
+        SecretRef::new(SecretSource::Env, env_var)
     }
 
-    /// Adds a service container that runs alongside this task.
-    ///
-    /// Services are background containers (like databases) that run during task execution.
-    /// The service is accessible via its name as hostname.
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("test")
-    ///     .run("cargo test")
-    ///     .service("postgres:15", "db")
-    ///     .service("redis:7", "cache");
-    /// // postgres available at hostname "db", redis at "cache"
-    /// ```
+    /// Creates a secret reference that reads from a file.
     ///
     /// # Panics
-    /// Panics if `image` or `name` is empty.
-    #[must_use]
-    pub fn service(self, image: &str, name: &str) -> Self {
-        assert!(!image.is_empty(), "service image cannot be empty");
-        assert!(!name.is_empty(), "service name cannot be empty");
-        self.pipeline.tasks[self.index].services.push(Service {
-            image: image.to_string(),
-            name: name.to_string(),
-        });
-        self
+    /// Panics if path is empty.
+    pub fn from_file(path: &str) -> Self {
+        if path.is_empty() {
+            panic!("SecretRef::from_file() requires a non-empty file path");
+        }
+        SecretRef::new(SecretSource::File, path)
     }
 
-    /// Sets the number of retries on failure.
-    ///
-    /// If the task fails, it will be retried up to `count` times before being marked as failed.
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
+    /// Creates a secret reference that reads from HashiCorp Vault.
+    /// The path format is "path/to/secret#field".
     ///
-    /// let mut p = Pipeline::new();
-    /// p.task("flaky-test")
-    ///     .run("cargo test -- --include-ignored")
-    ///     .retry(3);  // Retry up to 3 times on failure
-    /// ```
-    #[must_use]
-    pub fn retry(self, count: u32) -> Self {
-        debug!(task = %self.pipeline.tasks[self.index].name, retry = count, "setting retry");
-        self.pipeline.tasks[self.index].retry = Some(count);
-        self
+    /// # Panics
+    /// Panics if path doesn't contain exactly one '#' separator (required
+    /// format: "path#field").
+    pub fn from_vault(path: &str) -> Self {
+        if path.matches('#').count() != 1 {
+            panic!("SecretRef::from_vault() requires 'path#field' format (e.g., 'secret/data/db#password')");
+        }
+        SecretRef::new(SecretSource::Vault, path)
     }
 
-    /// Sets the timeout for this task in seconds.
-    ///
-    /// If the task doesn't complete within the timeout, it will be killed and marked as failed.
-    /// Default timeout is 300 seconds (5 minutes).
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("long-build")
-    ///     .run("cargo build --release")
-    ///     .timeout(600);  // 10 minute timeout
-    /// ```
+    /// Creates a secret reference that reads one key of a Kubernetes Secret.
+    /// The format is "namespace/secret-name#key".
     ///
     /// # Panics
-    /// Panics if `seconds` is 0.
-    #[must_use]
-    pub fn timeout(self, seconds: u32) -> Self {
-        assert!(seconds > 0, "timeout must be greater than 0");
-        debug!(task = %self.pipeline.tasks[self.index].name, timeout = seconds, "setting timeout");
-        self.pipeline.tasks[self.index].timeout = Some(seconds);
-        self
+    /// Panics if the reference isn't "namespace/secret-name#key" with
+    /// lowercase DNS names.
+    pub fn from_k8s(reference: &str) -> Self {
+        Self::checked("from_k8s", SecretSource::K8s, reference)
     }
 
-    /// Sets Kubernetes-specific options for this task.
-    ///
-    /// These options are only used when running with a K8s target.
-    /// If pipeline-level K8s defaults are set, task options will be merged
-    /// with task values overriding defaults.
+    /// Creates a secret reference that reads from AWS Secrets Manager.
+    /// The format is "name" or "name#jsonKey", where name may be the secret's
+    /// ARN and jsonKey picks one field of a JSON secret.
     ///
-    /// # Example
-    /// ```rust,ignore
-    /// use sykli::{Pipeline, K8sOptions, K8sResources};
+    /// # Panics
+    /// Panics if the name is empty or has characters AWS doesn't allow, the
+    /// ARN isn't a Secrets Manager secret ARN, or jsonKey is empty.
+    pub fn from_aws(reference: &str) -> Self {
+        Self::checked("from_aws", SecretSource::Aws, reference)
+    }
+
+    /// Creates a secret reference that reads from Google Cloud Secret
+    /// Manager. The format is "projects/PROJECT/secrets/NAME", optionally
+    /// followed by "/versions/VERSION" (the latest version otherwise).
     ///
-    /// let mut p = Pipeline::new();
-    /// p.task("build")
-    ///     .run("cargo build")
-    ///     .k8s(K8sOptions {
-    ///         resources: K8sResources {
-    ///             memory: Some("4Gi".into()),
-    ///             cpu: Some("2".into()),
-    ///             ..Default::default()
-    ///         },
-    ///         ..Default::default()
-    ///     });
-    /// ```
-    #[must_use]
-    pub fn k8s(self, opts: K8sOptions) -> Self {
-        debug!(task = %self.pipeline.tasks[self.index].name, "setting k8s options");
-        self.pipeline.tasks[self.index].k8s_options = Some(opts);
-        self
+    /// # Panics
+    /// Panics if the reference isn't a secret or secret version resource name.
+    pub fn from_gcp(reference: &str) -> Self {
+        Self::checked("from_gcp", SecretSource::Gcp, reference)
     }
 
-    /// Adds raw Kubernetes configuration as JSON.
+    /// Creates a secret reference that reads one value of a sops-encrypted
+    /// file. The format is "path#key", where key may be dotted to reach
+    /// nested values, e.g. "ci/secrets.enc.yaml#deploy.token".
     ///
-    /// Use this for advanced options not covered by `K8sOptions` (tolerations, affinity, etc.).
-    /// The JSON is passed directly to the executor and merged with K8sOptions.
-    /// K8sOptions fields take precedence over k8s_raw for overlapping settings.
+    /// Runners decrypt the file with `sops -d`; see
+    /// [`SopsSecrets`](crate::target::SopsSecrets).
     ///
-    /// # Example
-    /// ```rust,ignore
-    /// // GPU node with toleration
-    /// p.task("train")
-    ///     .k8s(K8sOptions { memory: Some("32Gi".into()), gpu: Some(1), ..Default::default() })
-    ///     .k8s_raw(r#"{"nodeSelector": {"gpu": "true"}, "tolerations": [{"key": "gpu", "effect": "NoSchedule"}]}"#);
-    /// ```
-    pub fn k8s_raw(self, json_config: &str) -> Self {
-        debug!(task = %self.pipeline.tasks[self.index].name, "setting k8s raw JSON");
-        self.pipeline.tasks[self.index].k8s_raw = Some(json_config.to_string());
-        self
+    /// # Panics
+    /// Panics if the path or any key segment is empty.
+    pub fn from_sops(reference: &str) -> Self {
+        Self::checked("from_sops", SecretSource::Sops, reference)
+    }
+
+    fn checked(constructor: &str, source: SecretSource, key: &str) -> Self {
+        if let Err(format) = source.check_key(key) {
+            panic!(
+                "SecretRef::{}() requires {}, got {:?}",
+                constructor, format, key
+            );
+        }
+        SecretRef::new(source, key)
     }
 }
 
-impl<'a> Review<'a> {
-    /// Returns the review node's name.
-    pub fn name(&self) -> String {
-        self.pipeline.tasks[self.index].name.clone()
+/// A file rendered from secrets by the executor, never at emit.
+#[derive(Clone)]
+struct SecretTemplate {
+    dest: String,
+    template: String,
+    refs: Vec<SecretRef>,
+}
+
+// =============================================================================
+// OUTPUT PUBLISHING
+// =============================================================================
+
+/// Where [`Task::publish_output`] uploads an output.
+///
+/// Templates may use `{task}`, `{output}`, `{file}`, `{branch}`, `{tag}` and
+/// `{commit}`, filled in by the executor when publishing.
+#[derive(Clone, Debug)]
+pub enum PublishDest {
+    /// Attach the output to a GitHub release.
+    GithubRelease {
+        /// Repository as `owner/name`.
+        repo: String,
+        /// Release tag, e.g. `"{tag}"` or `"v{commit}"`.
+        tag_expr: String,
+        /// Token with permission to upload release assets.
+        token: SecretRef,
+    },
+    /// Upload the output with an HTTP PUT.
+    HttpPut {
+        /// Target URL, e.g. `"https://artifacts.example.com/{branch}/{file}"`.
+        url_template: String,
+        /// Sent as a bearer token, if set.
+        auth: Option<SecretRef>,
+    },
+}
+
+const PUBLISH_PLACEHOLDERS: &[&str] = &["task", "output", "file", "branch", "tag", "commit"];
+
+/// Checks `{placeholder}` syntax in a publish template.
+fn check_publish_template(what: &str, template: &str) {
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        assert!(
+            rest.as_bytes()[open] == b'{',
+            "publish_output: unmatched '}}' in {} {:?}",
+            what,
+            template
+        );
+        let after = &rest[open + 1..];
+        let close = after
+            .find('}')
+            .unwrap_or_else(|| panic!("publish_output: unclosed '{{' in {} {:?}", what, template));
+        let name = &after[..close];
+        assert!(
+            PUBLISH_PLACEHOLDERS.contains(&name),
+            "publish_output: unknown placeholder {{{}}} in {} {:?} (expected one of {})",
+            name,
+            what,
+            template,
+            PUBLISH_PLACEHOLDERS.join(", ")
+        );
+        rest = &after[close + 1..];
     }
+}
 
-    /// Sets the review primitive identifier.
-    ///
-    /// # Panics
-    /// Panics if `name` is empty.
-    #[must_use]
-    pub fn primitive(self, name: &str) -> Self {
-        assert!(!name.is_empty(), "review primitive cannot be empty");
-        self.pipeline.tasks[self.index].primitive = Some(name.to_string());
-        self
+impl PublishDest {
+    fn validate(&self) {
+        match self {
+            PublishDest::GithubRelease { repo, tag_expr, .. } => {
+                let valid_repo = repo.split_once('/').is_some_and(|(owner, name)| {
+                    !owner.is_empty() && !name.is_empty() && !name.contains('/')
+                });
+                assert!(
+                    valid_repo,
+                    "publish_output: repo {:?} must be \"owner/name\"",
+                    repo
+                );
+                assert!(
+                    !tag_expr.is_empty(),
+                    "publish_output: tag_expr cannot be empty"
+                );
+                check_publish_template("tag_expr", tag_expr);
+            }
+            PublishDest::HttpPut { url_template, .. } => {
+                let host = url_template
+                    .strip_prefix("https://")
+                    .or_else(|| url_template.strip_prefix("http://"));
+                assert!(
+                    host.is_some_and(|h| !h.is_empty() && !h.starts_with('/')),
+                    "publish_output: url_template {:?} must be an http(s) URL",
+                    url_template
+                );
+                check_publish_template("url_template", url_template);
+            }
+        }
     }
 
-    /// Sets the agent identifier for this review node.
-    ///
-    /// # Panics
-    /// Panics if `name` is empty.
-    #[must_use]
-    pub fn agent(self, name: &str) -> Self {
-        assert!(!name.is_empty(), "review agent cannot be empty");
-        self.pipeline.tasks[self.index].agent = Some(name.to_string());
-        self
+    fn to_json(&self, output: &str) -> JsonPublish {
+        match self {
+            PublishDest::GithubRelease {
+                repo,
+                tag_expr,
+                token,
+            } => JsonPublish {
+                output: output.to_string(),
+                type_: "github_release".to_string(),
+                repo: Some(repo.clone()),
+                tag: Some(tag_expr.clone()),
+                url: None,
+                token: Some(JsonSecretSource::from(token)),
+                auth: None,
+            },
+            PublishDest::HttpPut { url_template, auth } => JsonPublish {
+                output: output.to_string(),
+                type_: "http_put".to_string(),
+                repo: None,
+                tag: None,
+                url: Some(url_template.clone()),
+                token: None,
+                auth: auth.as_ref().map(JsonSecretSource::from),
+            },
+        }
     }
+}
 
-    /// Adds context paths for the review.
-    ///
-    /// # Panics
-    /// Panics if any path is empty.
+// =============================================================================
+// RETRY POLICY
+// =============================================================================
+
+/// How a task is retried, set with [`Task::retry_policy`].
+///
+/// [`Task::retry`] is the same as a policy with only `max` set, which the
+/// default leaves retrying every failure right away, timeouts included.
+///
+/// # Example
+/// ```rust
+/// use sykli::{Backoff, Pipeline, RetryPolicy};
+///
+/// let mut p = Pipeline::new();
+/// p.task("push").run("docker push app").retry_policy(RetryPolicy {
+///     max: 3,
+///     backoff: Backoff::Exponential { base_secs: 5, max_secs: 120 },
+///     retry_on_exit_codes: Some(vec![75, 111]),
+///     retry_on_timeout: true,
+/// });
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt. Emit fails if 0.
+    pub max: u32,
+    /// Wait between attempts.
+    pub backoff: Backoff,
+    /// Only retry when the command exits with one of these codes; `None`
+    /// retries any failure.
+    pub retry_on_exit_codes: Option<Vec<i32>>,
+    /// Whether an attempt killed by [`Task::timeout`] is retried.
+    pub retry_on_timeout: bool,
+}
+
+impl RetryPolicy {
+    /// Retries any failure up to `max` times, right away.
     #[must_use]
-    pub fn context(self, paths: &[&str]) -> Self {
-        for path in paths {
-            assert!(!path.is_empty(), "review context path cannot be empty");
+    pub fn new(max: u32) -> Self {
+        RetryPolicy {
+            max,
+            ..RetryPolicy::default()
         }
-        self.pipeline.tasks[self.index]
-            .context
-            .extend(paths.iter().map(|s| (*s).to_string()));
-        self
     }
 
-    /// Records review input references.
-    ///
-    /// # Panics
-    /// Panics if any input reference is empty.
-    #[must_use]
-    pub fn inputs(self, refs: &[&str]) -> Self {
-        for reference in refs {
-            assert!(
-                !reference.is_empty(),
-                "review input reference cannot be empty"
-            );
+    /// Whether anything beyond `max` is set, so the policy can't be emitted
+    /// as a bare retry count.
+    fn is_structured(&self) -> bool {
+        self.backoff != Backoff::Immediate
+            || self.retry_on_exit_codes.is_some()
+            || !self.retry_on_timeout
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max: 1,
+            backoff: Backoff::Immediate,
+            retry_on_exit_codes: None,
+            retry_on_timeout: true,
         }
-        self.pipeline.tasks[self.index]
-            .inputs
-            .extend(refs.iter().map(|s| (*s).to_string()));
-        self
+    }
+}
+
+/// Wait between retry attempts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Backoff {
+    /// Retry right away.
+    #[default]
+    Immediate,
+    /// Wait the same time before every retry.
+    Fixed {
+        /// Seconds to wait.
+        secs: u32,
+    },
+    /// Wait `base_secs`, doubling before each further retry up to `max_secs`.
+    /// Emit fails if `max_secs` is below `base_secs`.
+    Exponential {
+        /// Wait before the first retry, in seconds.
+        base_secs: u32,
+        /// Longest wait, in seconds.
+        max_secs: u32,
+    },
+}
+
+// =============================================================================
+// AI-NATIVE TYPES
+// =============================================================================
+
+/// Task criticality for AI prioritization.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Criticality {
+    /// High priority task
+    High,
+    /// Medium priority task
+    #[default]
+    Medium,
+    /// Low priority task
+    Low,
+}
+
+/// What AI should do when a task fails.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum OnFailAction {
+    /// AI should analyze the failure
+    #[default]
+    Analyze,
+    /// AI should retry with modifications
+    Retry,
+    /// AI can skip without analysis
+    Skip,
+}
+
+/// How AI should select this task for execution.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SelectMode {
+    /// Only run if covers changed files
+    Smart,
+    /// Always run regardless of changes
+    #[default]
+    Always,
+    /// Only run when explicitly requested
+    Manual,
+}
+
+/// Semantic metadata for AI understanding of the task.
+#[derive(Clone, Default)]
+struct Semantic {
+    covers: Vec<String>,
+    intent: Option<String>,
+    criticality: Option<Criticality>,
+}
+
+/// AI behavioral hooks.
+#[derive(Clone, Default)]
+struct AiHooks {
+    on_fail: Option<OnFailAction>,
+    select: Option<SelectMode>,
+}
+
+// =============================================================================
+// CONDITION BUILDER (Type-safe conditions)
+// =============================================================================
+
+/// A type-safe condition for when a task should run.
+///
+/// Use the builder functions to create conditions:
+///
+/// # Example
+/// ```rust,ignore
+/// use sykli::{Pipeline, Condition};
+///
+/// let mut p = Pipeline::new();
+/// p.task("deploy")
+///     .run("kubectl apply")
+///     .when_cond(Condition::branch("main").or(Condition::tag("v*")));
+///
+/// p.task("test")
+///     .run("cargo test")
+///     .when_cond(Condition::negate(Condition::branch("wip/*")));
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct Condition {
+    expr: String,
+}
+
+impl Condition {
+    /// Creates a condition that matches a branch name or pattern.
+    /// Supports glob patterns like "feature/*".
+    ///
+    /// # Panics
+    /// Panics if pattern is empty. Use `Condition::default()` for an always-true condition.
+    pub fn branch(pattern: &str) -> Self {
+        if pattern.is_empty() {
+            panic!("Condition::branch() requires a non-empty pattern. Use Condition::default() for always-true.");
+        }
+        if pattern.contains('*') {
+            Condition {
+                expr: format!("branch matches {}", condition::quote(pattern)),
+            }
+        } else {
+            Condition {
+                expr: format!("branch == {}", condition::quote(pattern)),
+            }
+        }
+    }
+
+    /// Creates a condition that matches a tag name or pattern.
+    /// Supports glob patterns like "v*".
+    pub fn tag(pattern: &str) -> Self {
+        if pattern.is_empty() {
+            Condition {
+                expr: "tag != ''".to_string(),
+            }
+        } else if pattern.contains('*') {
+            Condition {
+                expr: format!("tag matches {}", condition::quote(pattern)),
+            }
+        } else {
+            Condition {
+                expr: format!("tag == {}", condition::quote(pattern)),
+            }
+        }
+    }
+
+    /// Creates a condition that matches when any tag is present.
+    pub fn has_tag() -> Self {
+        Condition {
+            expr: "tag != ''".to_string(),
+        }
+    }
+
+    /// Creates a condition that matches a CI event type.
+    pub fn event(event_type: &str) -> Self {
+        Condition {
+            expr: format!("event == {}", condition::quote(event_type)),
+        }
+    }
+
+    /// Creates a condition that matches when any changed file matches one of
+    /// the glob patterns.
+    ///
+    /// Emits `changed matches '<pattern>'` per pattern, joined with `||`.
+    /// The engine evaluates it against the commit's diff; explain uses
+    /// [`ExplainContext::changed_files`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Condition;
+    ///
+    /// let web = Condition::paths_changed(&["web/**", "package.json"]);
+    /// assert_eq!(
+    ///     web.to_string(),
+    ///     "(changed matches 'web/**') || (changed matches 'package.json')"
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `patterns` is empty or contains an empty pattern.
+    pub fn paths_changed(patterns: &[&str]) -> Self {
+        assert!(
+            !patterns.is_empty(),
+            "Condition::paths_changed() requires at least one pattern"
+        );
+        patterns
+            .iter()
+            .map(|pattern| {
+                assert!(
+                    !pattern.is_empty(),
+                    "Condition::paths_changed() patterns cannot be empty"
+                );
+                Condition {
+                    expr: format!("changed matches {}", condition::quote(pattern)),
+                }
+            })
+            .reduce(Condition::or)
+            .unwrap()
+    }
+
+    /// Creates a condition on an environment variable passed through by the
+    /// engine, e.g. `env.ENVIRONMENT == 'staging'`.
+    /// Supports glob patterns like "staging-*".
+    ///
+    /// Use [`Condition::var`] for `!=`.
+    ///
+    /// # Panics
+    /// Panics if `name` isn't a valid variable name.
+    pub fn env(name: &str, value: &str) -> Self {
+        let var = Condition::var(&format!("env.{}", name));
+        if value.contains('*') {
+            var.matches(value)
+        } else {
+            var.eq(value)
+        }
+    }
+
+    /// Starts a condition on a custom context variable.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Condition;
+    ///
+    /// let c = Condition::var("env.ENVIRONMENT").ne("production");
+    /// assert_eq!(c.to_string(), "env.ENVIRONMENT != 'production'");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` isn't letters, digits, `_` and `.`, starting with a
+    /// letter or `_`.
+    pub fn var(name: &str) -> ConditionVar {
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        assert!(valid, "invalid condition variable name {:?}", name);
+        ConditionVar {
+            name: name.to_string(),
+        }
+    }
+
+    /// Marks a task as requiring manual approval.
+    ///
+    /// `when_cond(Condition::manual())` is the same as [`Task::manual`]; it
+    /// can't be combined with other conditions.
+    pub fn manual() -> Self {
+        Condition {
+            expr: "manual == true".to_string(),
+        }
+    }
+
+    /// Creates a condition that matches pull request builds.
+    pub fn pull_request() -> Self {
+        Condition::event("pull_request")
+    }
+
+    /// Creates a condition that matches pull requests targeting a branch.
+    /// Supports glob patterns like "release/*".
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Condition;
+    ///
+    /// let c = Condition::pull_request().and(Condition::pr_target("main"));
+    /// assert_eq!(
+    ///     c.to_string(),
+    ///     "(event == 'pull_request') && (pr_target == 'main')"
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if branch is empty.
+    pub fn pr_target(branch: &str) -> Self {
+        assert!(
+            !branch.is_empty(),
+            "Condition::pr_target() requires a non-empty branch"
+        );
+        let var = Condition::var("pr_target");
+        if branch.contains('*') {
+            var.matches(branch)
+        } else {
+            var.eq(branch)
+        }
+    }
+
+    /// Creates a condition that matches scheduled runs.
+    pub fn schedule() -> Self {
+        Condition::event("schedule")
+    }
+
+    /// Creates a condition that matches runs triggered by a specific
+    /// schedule, e.g. `cron == '0 3 * * *'`.
+    ///
+    /// The expression has five fields (minute, hour, day of month, month,
+    /// day of week), each `*`, a number, a range `a-b`, a step `*/n` or
+    /// `a-b/n`, or a comma-separated list of those. Months may be written
+    /// `JAN`-`DEC` and days of the week `SUN`-`SAT`, in any case. The
+    /// macros `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` are
+    /// accepted in place of the five fields.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Condition;
+    ///
+    /// let nightly = Condition::cron("0 3 * * *");
+    /// assert_eq!(nightly.to_string(), "cron == '0 3 * * *'");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `expr` isn't a valid five-field cron expression.
+    pub fn cron(expr: &str) -> Self {
+        if let Err(msg) = check_cron(expr) {
+            panic!(
+                "Condition::cron(): invalid cron expression {:?}: {}",
+                expr, msg
+            );
+        }
+        Condition {
+            expr: format!("cron == '{}'", expr),
+        }
+    }
+
+    /// Parses a condition string in the grammar `when()` accepts, so it can
+    /// be combined with typed conditions.
+    ///
+    /// The result is normalized to the builder's form, so
+    /// `Condition::parse(&c.to_string())` gives back `c` for any builder
+    /// condition.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Condition;
+    ///
+    /// let from_config = Condition::parse("branch == 'main' || tag matches 'v*'").unwrap();
+    /// let c = from_config.and(Condition::in_ci());
+    /// assert_eq!(
+    ///     c.to_string(),
+    ///     "((branch == 'main') || (tag matches 'v*')) && (ci == true)"
+    /// );
+    ///
+    /// let err = Condition::parse("branch = 'main'").unwrap_err();
+    /// assert_eq!(err.offset, 7);
+    /// assert_eq!(err.expected, ["==", "!=", "matches"]);
+    /// ```
+    ///
+    /// # Errors
+    /// Returns the byte offset and the tokens expected there if `s` doesn't
+    /// parse.
+    pub fn parse(s: &str) -> Result<Condition, ConditionParseError> {
+        Ok(Condition {
+            expr: condition::parse(s)?.to_string(),
+        })
+    }
+
+    /// Creates a condition that matches when running in CI.
+    pub fn in_ci() -> Self {
+        Condition {
+            expr: "ci == true".to_string(),
+        }
+    }
+
+    /// Negates a condition.
+    ///
+    /// Note: Named `negate` instead of `not` to avoid confusion with `std::ops::Not`.
+    pub fn negate(c: Condition) -> Self {
+        Condition {
+            expr: format!("!({})", c.expr),
+        }
+    }
+
+    /// Combines conditions with OR logic.
+    pub fn or(self, other: Condition) -> Self {
+        Condition {
+            expr: format!("({}) || ({})", self.expr, other.expr),
+        }
+    }
+
+    /// Combines conditions with AND logic.
+    pub fn and(self, other: Condition) -> Self {
+        Condition {
+            expr: format!("({}) && ({})", self.expr, other.expr),
+        }
+    }
+
+    /// Decides whether a task with this condition would run in `ctx`.
+    ///
+    /// Uses the same evaluation as [`Pipeline::explain`]. A condition that
+    /// doesn't parse, uses an identifier the engine doesn't resolve, or
+    /// compares a variable `ctx` doesn't set is
+    /// [`Decision::Unknown`](condition::Decision::Unknown).
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::condition::Decision;
+    /// use sykli::{Condition, ExplainContext};
+    ///
+    /// let release = Condition::branch("main").or(Condition::tag("v*"));
+    /// let ctx = ExplainContext::default()
+    ///     .with_branch("dev")
+    ///     .with_tag("v1.2.0");
+    /// assert_eq!(release.evaluate(&ctx), Decision::Run);
+    /// assert!(matches!(
+    ///     release.evaluate(&ExplainContext::default()),
+    ///     Decision::Skip(_)
+    /// ));
+    /// ```
+    pub fn evaluate(&self, ctx: &ExplainContext) -> condition::Decision {
+        // The default condition is empty and always true
+        if self.expr.is_empty() {
+            return condition::Decision::Run;
+        }
+        match condition::parse(&self.expr) {
+            Ok(expr) => expr.evaluate(ctx),
+            Err(err) => condition::Decision::Unknown(format!("invalid condition: {}", err)),
+        }
+    }
+}
+
+/// Checks a five-field cron expression, returning what's wrong with it.
+fn check_cron(expr: &str) -> Result<(), String> {
+    const MONTHS: &[&str] = &[
+        "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+    ];
+    const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+    const MACROS: &[&str] = &["@yearly", "@monthly", "@weekly", "@daily", "@hourly"];
+    // Names stand for `min + index`.
+    const FIELDS: [(&str, u32, u32, &[&str]); 5] = [
+        ("minute", 0, 59, &[]),
+        ("hour", 0, 23, &[]),
+        ("day of month", 1, 31, &[]),
+        ("month", 1, 12, MONTHS),
+        ("day of week", 0, 7, WEEKDAYS),
+    ];
+    if expr.starts_with('@') {
+        if MACROS.contains(&expr) {
+            return Ok(());
+        }
+        return Err(format!(
+            "unknown macro {:?}, expected one of {}",
+            expr,
+            MACROS.join(", ")
+        ));
+    }
+    let parts: Vec<&str> = expr.split_whitespace().collect();
+    if parts.len() != FIELDS.len() {
+        return Err(format!("expected 5 fields, got {}", parts.len()));
+    }
+    for (part, (name, min, max, names)) in parts.iter().zip(FIELDS) {
+        let number = |s: &str| -> Result<u32, String> {
+            if let Some(i) = names.iter().position(|n| n.eq_ignore_ascii_case(s)) {
+                return Ok(min + i as u32);
+            }
+            let n: u32 = s.parse().map_err(|_| {
+                if names.is_empty() {
+                    format!("{} {:?} is not a number", name, s)
+                } else {
+                    format!("{} {:?} is not a number or name", name, s)
+                }
+            })?;
+            if n < min || n > max {
+                return Err(format!("{} {} is out of range {}-{}", name, n, min, max));
+            }
+            Ok(n)
+        };
+        for item in part.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (item, None),
+            };
+            if let Some(step) = step {
+                match step.parse::<u32>() {
+                    Ok(n) if n > 0 => {}
+                    _ => return Err(format!("{} step {:?} is not a positive number", name, step)),
+                }
+            }
+            if range == "*" {
+                continue;
+            }
+            match range.split_once('-') {
+                Some((start, end)) => {
+                    if number(start)? > number(end)? {
+                        return Err(format!("{} range {:?} is backwards", name, range));
+                    }
+                }
+                None => {
+                    number(range)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Error returned by [`Condition::parse`].
+pub type ConditionParseError = condition::ParseError;
+
+/// Why a pipeline can't be emitted; see [`Pipeline::emit_to_checked`].
+pub type EmitError = validate::EmitError;
+
+/// A context variable to compare, created by [`Condition::var`].
+#[derive(Clone, Debug)]
+pub struct ConditionVar {
+    name: String,
+}
+
+impl ConditionVar {
+    /// Matches when the variable equals `value`.
+    pub fn eq(self, value: &str) -> Condition {
+        Condition {
+            expr: format!("{} == {}", self.name, condition::quote(value)),
+        }
+    }
+
+    /// Matches when the variable doesn't equal `value`.
+    pub fn ne(self, value: &str) -> Condition {
+        Condition {
+            expr: format!("{} != {}", self.name, condition::quote(value)),
+        }
+    }
+
+    /// Matches when the variable matches the glob `pattern`.
+    pub fn matches(self, pattern: &str) -> Condition {
+        Condition {
+            expr: format!("{} matches {}", self.name, condition::quote(pattern)),
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+#[derive(Clone, Default)]
+struct TaskData {
+    kind: NodeKind,
+    name: String,
+    task_type: Option<TaskType>,
+    success_criteria: Vec<SuccessCriterion>,
+    command: String,
+    steps: Vec<String>, // set by step(); run in order instead of command
+    args: Vec<String>,  // set by exec(); run directly instead of through a shell
+    primitive: Option<String>,
+    agent: Option<String>,
+    context: Vec<String>,
+    deterministic: bool,
+    container: Option<String>,
+    container_build: Option<ContainerBuild>, // image built from a Dockerfile instead of pulled
+    workdir: Option<String>,
+    entrypoint: Option<String>, // Some("") clears the image's entrypoint
+    user: Option<String>,
+    env: HashMap<String, String>,
+    env_files: Vec<String>,         // read at emit, under env
+    runtime_env_files: Vec<String>, // emitted as env_files for the engine
+    mounts: Vec<Mount>,
+    inputs: Vec<String>,                    // v1-style file patterns
+    task_inputs: Vec<TaskInput>,            // v2-style inputs from other tasks
+    inputs_from_all: Vec<(String, String)>, // (producer, base dir), expanded into task_inputs at emit
+    outputs: HashMap<String, String>,
+    output_kinds: HashMap<String, OutputKind>, // outputs from output_glob()/output_dir(); others are files
+    output_options: HashMap<String, OutputOptions>, // only outputs from output_with() with non-default options
+    depends_on: Vec<String>,
+    dep_selectors: HashMap<String, Vec<BTreeMap<String, String>>>, // deps on some matrix variants only
+    order_deps: Vec<String>, // deps declared via after()/chain(), not just input_from
+    run_when: HashMap<String, RunWhen>, // deps from on_*_of()/always_after(); others need success
+    condition: Option<String>,
+    when_cond: Option<Condition>, // Type-safe condition (alternative to string)
+    unless: Option<String>,       // Skip condition, emitted negated into `when`
+    unless_cond: Option<Condition>,
+    secrets: Vec<String>,        // v1-style secret names
+    secret_refs: Vec<SecretRef>, // v2-style typed secret references
+    secret_templates: Vec<SecretTemplate>,
+    publish: Vec<(String, PublishDest)>, // (output name, destination)
+    matrix: matrix::Matrix,
+    services: Vec<Service>,
+    bare: bool, // Exempt from pipeline-level defaults
+    // Robustness features
+    retry: Option<RetryPolicy>,      // Retries on failure
+    timeout: Option<u32>,            // Timeout in seconds
+    estimated_duration: Option<u32>, // Expected run time in seconds, for explain
+    // K8s options
+    k8s_options: Option<K8sOptions>,
+    template_k8s: Option<K8sOptions>, // From Task::from, below k8s_options
+    k8s_raw: Option<String>,          // Raw K8s JSON for advanced options
+    // Deprecated: no longer serialized
+    target_name: Option<String>,
+    // Node placement - required node labels
+    requires: Vec<String>,
+    // AI-native fields
+    semantic: Semantic,
+    ai_hooks: AiHooks,
+    // Capability-based dependencies
+    provides: Vec<(String, Option<String>)>,
+    needs: Vec<String>,
+    // Gate fields (if set, this is a gate not a regular task)
+    gate: Option<GateConfig>,
+    // Never starts automatically; waits for approval
+    manual: bool,
+    // Failing doesn't fail the pipeline
+    allow_failure: bool,
+    // At most one task of the group runs at a time, across pipeline runs
+    concurrency: Option<Concurrency>,
+    // Cross-platform verification mode
+    verify: Option<String>,
+    // Selection tags, for subset and explain_filtered
+    tags: Vec<String>,
+    description: Option<String>,
+    // Deps given as handles from another pipeline (rejected at emit)
+    foreign_deps: Vec<String>,
+    // Groups passed to after_group(), for explain
+    after_groups: Vec<TaskGroup>,
+    // Name of the last registered template applied with from()
+    template: Option<String>,
+}
+
+impl TaskData {
+    /// Adds an explicit ordering dependency. Duplicates are ignored.
+    fn add_order_dep(&mut self, name: &str) {
+        if name.is_empty() {
+            return;
+        }
+        self.dep_selectors.remove(name);
+        if !self.depends_on.iter().any(|d| d == name) {
+            self.depends_on.push(name.to_string());
+        }
+        if !self.order_deps.iter().any(|d| d == name) {
+            self.order_deps.push(name.to_string());
+        }
+    }
+
+    /// Whether pipeline defaults apply: not to bare tasks, gates or reviews.
+    fn takes_defaults(&self) -> bool {
+        !self.bare && self.kind != NodeKind::Review && self.gate.is_none()
+    }
+
+    /// True if the task itself declares secret `name`, plain or typed.
+    fn declares_secret(&self, name: &str) -> bool {
+        self.secrets.iter().any(|s| s == name) || self.secret_refs.iter().any(|sr| sr.name == name)
+    }
+
+    /// Every secret declaration as a ref, plain secrets reading the env var
+    /// of the same name, in declaration order (plain ones first).
+    fn secret_declarations(&self) -> impl Iterator<Item = Cow<'_, SecretRef>> {
+        self.secrets
+            .iter()
+            .map(|name| {
+                Cow::Owned(SecretRef {
+                    name: name.clone(),
+                    ..SecretRef::new(SecretSource::Env, name)
+                })
+            })
+            .chain(self.secret_refs.iter().map(Cow::Borrowed))
+    }
+
+    /// Names declared more than once with identical settings, with the
+    /// number of declarations. Emit keeps one of each.
+    fn duplicate_secrets(&self) -> Vec<(String, usize)> {
+        let decls: Vec<_> = self.secret_declarations().collect();
+        let mut duplicates: Vec<(String, usize)> = Vec::new();
+        for (i, sr) in decls.iter().enumerate() {
+            if decls[..i].iter().any(|o| o == sr) {
+                continue;
+            }
+            let count = decls[i..].iter().filter(|o| *o == sr).count();
+            if count > 1 {
+                duplicates.push((sr.name.clone(), count));
+            }
+        }
+        duplicates
+    }
+
+    /// The task with repeated identical secret declarations dropped.
+    /// Conflicting ones are kept for validation to report.
+    fn without_duplicate_secrets(t: Cow<'_, TaskData>) -> Cow<'_, TaskData> {
+        if t.duplicate_secrets().is_empty() {
+            return t;
+        }
+        let mut t = t.into_owned();
+        let mut seen: Vec<SecretRef> = Vec::new();
+        let mut secrets = Vec::new();
+        for name in std::mem::take(&mut t.secrets) {
+            let sr = SecretRef {
+                name: name.clone(),
+                ..SecretRef::new(SecretSource::Env, &name)
+            };
+            if !seen.contains(&sr) {
+                seen.push(sr);
+                secrets.push(name);
+            }
+        }
+        t.secrets = secrets;
+        t.secret_refs.retain(|sr| {
+            let keep = !seen.contains(sr);
+            if keep {
+                seen.push(sr.clone());
+            }
+            keep
+        });
+        Cow::Owned(t)
+    }
+
+    /// The task with variables from its [`Task::env_file`]s added under its
+    /// own env. Unreadable files are skipped; validation reports them.
+    fn with_env_files(&self) -> Cow<'_, TaskData> {
+        if self.env_files.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        let mut t = self.clone();
+        for path in &self.env_files {
+            for (k, v) in dotenv::read(path).unwrap_or_default() {
+                if !self.env.contains_key(&k) {
+                    t.env.insert(k, v);
+                }
+            }
+        }
+        Cow::Owned(t)
+    }
+
+    /// Fields that may hold `{{matrix.<key>}}` placeholders, with the name
+    /// validation reports them under.
+    fn interpolated_fields(&self) -> Vec<(&'static str, &str)> {
+        let mut fields = vec![("command", self.command.as_str())];
+        fields.extend(self.steps.iter().map(|s| ("command", s.as_str())));
+        fields.extend(self.args.iter().map(|s| ("command", s.as_str())));
+        fields.extend(self.container.iter().map(|s| ("container", s.as_str())));
+        fields.extend(self.workdir.iter().map(|s| ("workdir", s.as_str())));
+        let mut env: Vec<_> = self.env.iter().collect();
+        env.sort();
+        fields.extend(env.into_iter().map(|(_, v)| ("env", v.as_str())));
+        fields
+    }
+
+    /// The task with each `{{matrix.<key>}}` placeholder replaced by
+    /// `value(key)`, or left as it is where that's `None`.
+    fn interpolate_matrix(&self, value: impl Fn(&str) -> Option<String>) -> Cow<'_, TaskData> {
+        let fields = self.interpolated_fields();
+        if fields
+            .iter()
+            .all(|(_, s)| matrix::placeholders(s).is_empty())
+        {
+            return Cow::Borrowed(self);
+        }
+        let sub = |s: &str| matrix::replace_placeholders(s, &value);
+        let mut t = self.clone();
+        t.command = sub(&t.command);
+        for s in t.steps.iter_mut().chain(t.args.iter_mut()) {
+            *s = sub(s);
+        }
+        t.container = t.container.as_deref().map(sub);
+        t.workdir = t.workdir.as_deref().map(sub);
+        for v in t.env.values_mut() {
+            *v = sub(v);
+        }
+        Cow::Owned(t)
+    }
+
+    /// Adds dependencies that run this task depending on how they end.
+    fn add_hook_deps(&mut self, deps: &[&str], when: RunWhen) {
+        for dep in deps.iter().filter(|d| !d.is_empty()) {
+            self.add_order_dep(dep);
+            self.run_when.insert((*dep).to_string(), when);
+        }
+    }
+
+    /// Dependencies added with `when`, in declaration order.
+    fn hook_deps(&self, when: RunWhen) -> Vec<&str> {
+        self.depends_on
+            .iter()
+            .filter(|d| self.run_when.get(*d) == Some(&when))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Name of the group passed to `after_group`, if it's the only one and
+    /// accounts for every dependency.
+    fn single_group_deps(&self) -> Option<&str> {
+        let [group] = self.after_groups.as_slice() else {
+            return None;
+        };
+        let same = self.depends_on.len() == group.task_names.len()
+            && self.depends_on.iter().all(|d| group.task_names.contains(d));
+        same.then_some(group.name.as_str())
+    }
+
+    /// Classifies why this task depends on `dep`.
+    fn edge_kind(&self, dep: &str) -> EdgeKind {
+        let artifact = self.task_inputs.iter().any(|ti| ti.from_task == dep)
+            || self.inputs_from_all.iter().any(|(from, _)| from == dep);
+        let order = self.order_deps.iter().any(|d| d == dep);
+        match (artifact, order) {
+            (true, true) => EdgeKind::Both,
+            (true, false) => EdgeKind::Artifact,
+            _ => EdgeKind::Order,
+        }
+    }
+
+    /// Edge metadata for emit, or None when `depends_on` and `task_inputs`
+    /// already say everything: each edge is an artifact edge exactly when a
+    /// task input reads from it, and no edge hooks onto how its dependency
+    /// ends.
+    /// `names` gives the emitted names of a dependency, which are its
+    /// variants' names when it has matrix selectors.
+    fn json_depends_on_meta(
+        &self,
+        names: impl Fn(&str) -> Vec<String>,
+    ) -> Option<Vec<JsonDependency>> {
+        let implied = |dep: &String| {
+            let artifact = self.task_inputs.iter().any(|ti| ti.from_task == *dep);
+            let kind = if artifact {
+                EdgeKind::Artifact
+            } else {
+                EdgeKind::Order
+            };
+            !self.run_when.contains_key(dep) && self.edge_kind(dep) == kind
+        };
+        if self.depends_on.iter().all(implied) {
+            return None;
+        }
+        let meta = self
+            .depends_on
+            .iter()
+            .flat_map(|dep| {
+                let kind = self.edge_kind(dep).as_str();
+                let run_when = self.run_when.get(dep).map(RunWhen::as_str);
+                names(dep).into_iter().map(move |task| JsonDependency {
+                    task,
+                    kind: kind.to_string(),
+                    run_when,
+                })
+            })
+            .collect();
+        Some(meta)
+    }
+
+    /// The command to run as a shell string: the `run()` command, the steps
+    /// joined with `&&` so the first failing step stops the task, or the
+    /// `exec()` args quoted for the shell.
+    fn script(&self) -> String {
+        if !self.args.is_empty() {
+            shell_join(&self.args)
+        } else if !self.steps.is_empty() {
+            self.steps.join(" && ")
+        } else {
+            self.command.clone()
+        }
+    }
+
+    /// The task's condition, preferring the typed `when_cond` over the string form,
+    /// combined with the negated `unless` condition.
+    fn effective_condition(&self) -> Option<String> {
+        let when = self
+            .when_cond
+            .as_ref()
+            .map(|c| c.to_string())
+            .or_else(|| self.condition.clone());
+        let unless = self
+            .unless_cond
+            .as_ref()
+            .map(|c| c.to_string())
+            .or_else(|| self.unless.clone())
+            .map(|u| format!("!({})", u));
+        match (when, unless) {
+            (Some(when), Some(unless)) => Some(format!("({}) && ({})", when, unless)),
+            (when, unless) => when.or(unless),
+        }
+    }
+}
+
+/// Joins argv into a shell command line, single-quoting arguments that need
+/// it.
+fn shell_join(args: &[String]) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c);
+    args.iter()
+        .map(|a| {
+            if !a.is_empty() && a.chars().all(plain) {
+                a.clone()
+            } else {
+                format!("'{}'", a.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A matrix include or exclude entry; a repeated key keeps its last value.
+fn matrix_entry(entry: &[(&str, &str)]) -> BTreeMap<String, String> {
+    entry
+        .iter()
+        .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+        .collect()
+}
+
+/// How a dependency has to end for the dependent task to run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RunWhen {
+    Success,
+    Failure,
+    Always,
+}
+
+impl RunWhen {
+    const ALL: [RunWhen; 3] = [RunWhen::Success, RunWhen::Failure, RunWhen::Always];
+
+    /// Wire name used in `depends_on_meta`.
+    fn as_str(&self) -> &'static str {
+        match self {
+            RunWhen::Success => "success",
+            RunWhen::Failure => "failure",
+            RunWhen::Always => "always",
+        }
+    }
+
+    /// Label for explain, e.g. `on failure of: deploy`.
+    fn label(&self) -> &'static str {
+        match self {
+            RunWhen::Success => "on success of",
+            RunWhen::Failure => "on failure of",
+            RunWhen::Always => "always after",
+        }
+    }
+}
+
+/// Why a task depends on another task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// The dependency exists only because of `input_from` (data transfer barrier).
+    Artifact,
+    /// Explicit ordering via `after()` and friends.
+    Order,
+    /// Both an explicit ordering edge and an artifact flow.
+    Both,
+}
+
+impl EdgeKind {
+    /// Returns the wire name used in `depends_on_meta`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdgeKind::Artifact => "artifact",
+            EdgeKind::Order => "order",
+            EdgeKind::Both => "both",
+        }
+    }
+}
+
+impl std::fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Concurrency group set by [`Task::concurrency_group`].
+#[derive(Clone)]
+struct Concurrency {
+    group: String,
+    cancel_in_progress: bool,
+}
+
+/// Image built on the fly from a Dockerfile in a directory resource.
+#[derive(Clone)]
+struct ContainerBuild {
+    context_resource: String,
+    // The context directory, as for Mount::dir
+    context: Option<Directory>,
+    dockerfile: String,
+}
+
+impl ContainerBuild {
+    /// Host path of the build context.
+    fn context_path(&self) -> &str {
+        dir_host_path(self.context.as_ref(), &self.context_resource)
+    }
+}
+
+/// Gate configuration for approval gates.
+#[derive(Clone, Default)]
+struct GateConfig {
+    strategy: String,
+    timeout: u32,
+    message: Option<String>,
+    env_var: Option<String>,
+    file_path: Option<String>,
+}
+
+impl<'a> Task<'a> {
+    /// Applies a template's configuration to this task.
+    ///
+    /// Template settings are applied first, then task-specific settings override them.
+    #[must_use]
+    pub fn from(self, tmpl: &Template) -> Self {
+        let task = &mut self.pipeline.tasks[self.index];
+        if tmpl.name.is_some() {
+            task.template = tmpl.name.clone();
+        }
+
+        // Apply template settings (task settings will override these)
+        if task.container.is_none() && task.container_build.is_none() {
+            task.container = tmpl.container.clone();
+        }
+        if task.workdir.is_none() {
+            task.workdir = tmpl.workdir.clone();
+        }
+
+        // Merge env: template first, then task overrides
+        for (k, v) in &tmpl.env {
+            if !task.env.contains_key(k) {
+                task.env.insert(k.clone(), v.clone());
+            }
+        }
+
+        // Prepend template mounts
+        let mut new_mounts = tmpl.mounts.clone();
+        new_mounts.append(&mut task.mounts);
+        task.mounts = new_mounts;
+
+        // Prepend template inputs and secrets the task doesn't already have
+        let mut inputs: Vec<String> = tmpl
+            .inputs
+            .iter()
+            .filter(|i| !task.inputs.contains(i))
+            .cloned()
+            .collect();
+        inputs.append(&mut task.inputs);
+        task.inputs = inputs;
+
+        let mut secrets: Vec<String> = tmpl
+            .secrets
+            .iter()
+            .filter(|s| !task.secrets.contains(s))
+            .cloned()
+            .collect();
+        secrets.append(&mut task.secrets);
+        task.secrets = secrets;
+
+        if task.retry.is_none() {
+            task.retry = tmpl.retry.clone();
+        }
+        if task.timeout.is_none() {
+            task.timeout = tmpl.timeout;
+        }
+
+        // Kept apart from the task's own options so k8s() after from()
+        // still merges with the template's
+        if tmpl.k8s.is_some() {
+            task.template_k8s = tmpl.k8s.clone();
+        }
+
+        self
+    }
+
+    /// Exempts this task from pipeline-level defaults such as
+    /// [`Pipeline::with_k8s_defaults`] and [`Pipeline::default_env`].
+    ///
+    /// Use it for tasks that run in minimal images, like pushes or
+    /// notifications. Settings made on the task itself, including
+    /// [`Task::from`], still apply. Not serialized; it only changes how
+    /// defaults are merged at emit.
+    #[must_use]
+    pub fn bare(self) -> Self {
+        self.pipeline.tasks[self.index].bare = true;
+        self
+    }
+
+    /// Returns the name of this task.
+    pub fn name(&self) -> String {
+        self.pipeline.tasks[self.index].name.clone()
+    }
+
+    /// Sets the command for this task.
+    ///
+    /// On a handle from [`Pipeline::task_or_get`] for a task that already has a
+    /// command, the existing command is kept. Use [`Task::run_replace`] to override it.
+    ///
+    /// # Panics
+    /// Panics if `cmd` is empty; see [`Task::try_run`].
+    #[must_use]
+    pub fn run(self, cmd: &str) -> Self {
+        or_panic(self.try_run(cmd))
+    }
+
+    /// Like [`Task::run`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::EmptyCommand`] if `cmd` is empty.
+    pub fn try_run(self, cmd: &str) -> Result<Self, PipelineError> {
+        if cmd.is_empty() {
+            return Err(PipelineError::EmptyCommand);
+        }
+        let task = &mut self.pipeline.tasks[self.index];
+        if self.keep_command && !task.command.is_empty() {
+            debug!(task = %task.name, "keeping existing command");
+            return Ok(self);
+        }
+        task.command = cmd.to_string();
+        Ok(self)
+    }
+
+    /// Appends a step. Steps run in order, each logged and attributed on its
+    /// own by the engine, and the first failing step fails the task; use them
+    /// instead of `run("a && b && c")`.
+    ///
+    /// Emitted as a `steps` array, with `command` set to the steps joined
+    /// with `&&` for runners that don't read it. A task uses either
+    /// [`Task::run`] or steps; emit fails if it has both.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("check")
+    ///     .step("cargo fmt --check")
+    ///     .step("cargo clippy -- -D warnings");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `cmd` is empty.
+    #[must_use]
+    pub fn step(self, cmd: &str) -> Self {
+        assert!(!cmd.is_empty(), "{}", PipelineError::EmptyCommand);
+        self.pipeline.tasks[self.index].steps.push(cmd.to_string());
+        self
+    }
+
+    /// Runs the program directly with these arguments, without a shell, so
+    /// quotes and spaces in arguments need no escaping and the image needs
+    /// no shell.
+    ///
+    /// Emitted as an `args` array, with `command` set to the arguments
+    /// quoted for the shell for runners that don't read it. A task uses
+    /// either `exec`, [`Task::run`] or steps; emit fails if it has more than
+    /// one.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").exec(&["cargo", "test", "--features", "foo bar"]);
+    ///
+    /// let json = p.to_json().unwrap();
+    /// assert!(json.contains(r#""args":["cargo","test","--features","foo bar"]"#));
+    /// assert!(json.contains(r#""command":"cargo test --features 'foo bar'""#));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `argv` or the program name is empty.
+    #[must_use]
+    pub fn exec(self, argv: &[&str]) -> Self {
+        assert!(
+            argv.first().is_some_and(|p| !p.is_empty()),
+            "exec: program cannot be empty"
+        );
+        self.pipeline.tasks[self.index].args = argv.iter().map(|a| a.to_string()).collect();
+        self
+    }
+
+    /// Appends each command as a [`Task::step`].
+    ///
+    /// # Panics
+    /// Panics if any command is empty.
+    #[must_use]
+    pub fn run_steps(self, cmds: &[&str]) -> Self {
+        cmds.iter().fold(self, |task, cmd| task.step(cmd))
+    }
+
+    /// Sets the command for this task, replacing any existing command.
+    ///
+    /// # Panics
+    /// Panics if `cmd` is empty.
+    #[must_use]
+    pub fn run_replace(self, cmd: &str) -> Self {
+        assert!(!cmd.is_empty(), "{}", PipelineError::EmptyCommand);
+        self.pipeline.tasks[self.index].command = cmd.to_string();
+        self
+    }
+
+    /// Sets the semantic class of this executable task.
+    #[must_use]
+    pub fn task_type(self, task_type: TaskType) -> Self {
+        self.pipeline.tasks[self.index].task_type = Some(task_type);
+        self
+    }
+
+    /// Declares verification metadata for this executable task.
+    ///
+    /// Phase 3C-1 emits these criteria but does not change execution behavior.
+    #[must_use]
+    pub fn success_criteria(self, criteria: &[SuccessCriterion]) -> Self {
+        let exit_code_count = criteria
+            .iter()
+            .filter(|criterion| matches!(criterion, SuccessCriterion::ExitCode(_)))
+            .count();
+        assert!(
+            exit_code_count <= 1,
+            "multiple exit_code success criteria are not allowed"
+        );
+        for criterion in criteria {
+            match criterion {
+                SuccessCriterion::FileExists(path) | SuccessCriterion::FileNonEmpty(path) => {
+                    assert!(
+                        !path.is_empty(),
+                        "file success criterion path cannot be empty"
+                    );
+                }
+                SuccessCriterion::ExitCode(code) => {
+                    assert!(
+                        (0..=255).contains(code),
+                        "exit_code success criterion must be between 0 and 255"
+                    );
+                }
+            }
+        }
+        self.pipeline.tasks[self.index]
+            .success_criteria
+            .extend(criteria.iter().cloned());
+        self
+    }
+
+    /// Sets the container image for this task.
+    ///
+    /// # Panics
+    /// Panics if `image` is empty or the task already builds its image with
+    /// `container_from_dockerfile()`; see [`Task::try_container`].
+    #[must_use]
+    pub fn container(self, image: &str) -> Self {
+        or_panic(self.try_container(image))
+    }
+
+    /// Like [`Task::container`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::EmptyImage`] if `image` is empty and
+    /// [`PipelineError::ContainerAlreadyBuilt`] if the task builds its image
+    /// with `container_from_dockerfile()`.
+    pub fn try_container(self, image: &str) -> Result<Self, PipelineError> {
+        if image.is_empty() {
+            return Err(PipelineError::EmptyImage);
+        }
+        let task = &mut self.pipeline.tasks[self.index];
+        if task.container_build.is_some() {
+            return Err(PipelineError::ContainerAlreadyBuilt {
+                task: task.name.clone(),
+            });
+        }
+        task.container = Some(image.to_string());
+        Ok(self)
+    }
+
+    /// Runs this task in an image built from a Dockerfile, instead of a
+    /// published image.
+    ///
+    /// `dockerfile` is relative to the `context` directory. Targets that
+    /// support it build the image before the task runs and cache it by the
+    /// content of the Dockerfile and context.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let ci = p.dir("ci");
+    /// p.task("e2e")
+    ///     .container_from_dockerfile(&ci, "e2e.Dockerfile")
+    ///     .run("./run-e2e.sh");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `dockerfile` is empty or the task already has a `container()` image.
+    #[must_use]
+    pub fn container_from_dockerfile(self, context: &Directory, dockerfile: &str) -> Self {
+        assert!(!dockerfile.is_empty(), "dockerfile cannot be empty");
+        let task = &mut self.pipeline.tasks[self.index];
+        assert!(
+            task.container.is_none(),
+            "task {:?} already has container image {:?}; use either container() or container_from_dockerfile()",
+            task.name,
+            task.container.as_deref().unwrap_or_default()
+        );
+        task.container_build = Some(ContainerBuild {
+            context_resource: context.id(),
+            context: Some(context.clone()),
+            dockerfile: dockerfile.to_string(),
+        });
+        self
+    }
+
+    /// Mounts a directory into the container.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty or not absolute (must start with `/`); see
+    /// [`Task::try_mount`].
+    #[must_use]
+    pub fn mount(self, dir: &Directory, path: &str) -> Self {
+        or_panic(self.try_mount(dir, path))
+    }
+
+    /// Like [`Task::mount`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] or [`PipelineError::RelativePath`] if
+    /// `path` is empty or not absolute.
+    pub fn try_mount(self, dir: &Directory, path: &str) -> Result<Self, PipelineError> {
+        container_path("container mount path", path)?;
+        self.pipeline.tasks[self.index].mounts.push(Mount {
+            resource: dir.id(),
+            path: path.to_string(),
+            mount_type: "directory".to_string(),
+            dir: Some(dir.clone()),
+            cache: None,
+            file: None,
+            sharing: CacheSharing::Shared,
+        });
+        Ok(self)
+    }
+
+    /// Mounts a cache volume into the container.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty or not absolute (must start with `/`); see
+    /// [`Task::try_mount_cache`].
+    #[must_use]
+    pub fn mount_cache(self, cache: &CacheVolume, path: &str) -> Self {
+        or_panic(self.try_mount_cache(cache, path))
+    }
+
+    /// Like [`Task::mount_cache`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] or [`PipelineError::RelativePath`] if
+    /// `path` is empty or not absolute.
+    pub fn try_mount_cache(self, cache: &CacheVolume, path: &str) -> Result<Self, PipelineError> {
+        self.try_mount_cache_with(cache, path, CacheSharing::Shared)
+    }
+
+    /// Mounts a cache volume with an explicit sharing mode, e.g.
+    /// [`CacheSharing::Locked`] for a build output directory that tasks
+    /// running at the same time would otherwise write over each other.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty or not absolute; see
+    /// [`Task::try_mount_cache_with`].
+    #[must_use]
+    pub fn mount_cache_with(self, cache: &CacheVolume, path: &str, sharing: CacheSharing) -> Self {
+        or_panic(self.try_mount_cache_with(cache, path, sharing))
+    }
+
+    /// Like [`Task::mount_cache_with`], but returns an error instead of
+    /// panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] or [`PipelineError::RelativePath`] if
+    /// `path` is empty or not absolute.
+    pub fn try_mount_cache_with(
+        self,
+        cache: &CacheVolume,
+        path: &str,
+        sharing: CacheSharing,
+    ) -> Result<Self, PipelineError> {
+        container_path("container mount path", path)?;
+        self.pipeline.tasks[self.index].mounts.push(Mount {
+            resource: cache.id(),
+            path: path.to_string(),
+            mount_type: "cache".to_string(),
+            dir: None,
+            cache: Some(cache.clone()),
+            file: None,
+            sharing,
+        });
+        Ok(self)
+    }
+
+    /// Clones a git repository into the container at `path`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let charts = p.git("https://github.com/org/charts.git").branch("main").depth(1);
+    /// p.task("deploy")
+    ///     .container("alpine/helm:3")
+    ///     .mount_git(&charts, "/charts")
+    ///     .run("helm upgrade app /charts/app");
+    ///
+    /// let json = p.to_json_value().unwrap();
+    /// let id = "git:https://github.com/org/charts.git#main";
+    /// assert_eq!(json["resources"][id]["ref"], "main");
+    /// assert_eq!(json["tasks"][0]["mounts"][0]["type"], "git");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `path` is empty or not absolute, or if the same URL and ref
+    /// are already mounted with another depth; see [`Task::try_mount_git`].
+    #[must_use]
+    pub fn mount_git(self, git: &GitSource, path: &str) -> Self {
+        or_panic(self.try_mount_git(git, path))
+    }
+
+    /// Like [`Task::mount_git`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] or [`PipelineError::RelativePath`] if
+    /// `path` is empty or not absolute, and
+    /// [`PipelineError::GitDepthConflict`] if the same URL and ref are
+    /// already mounted with another depth.
+    pub fn try_mount_git(self, git: &GitSource, path: &str) -> Result<Self, PipelineError> {
+        container_path("container mount path", path)?;
+        let id = git.id();
+        match self.pipeline.git_sources.iter().find(|g| g.id() == id) {
+            Some(existing) if existing.depth != git.depth => {
+                return Err(PipelineError::GitDepthConflict { id });
+            }
+            Some(_) => {}
+            None => self.pipeline.git_sources.push(git.clone()),
+        }
+        self.pipeline.tasks[self.index].mounts.push(Mount {
+            resource: id,
+            path: path.to_string(),
+            mount_type: "git".to_string(),
+            dir: None,
+            cache: None,
+            file: None,
+            sharing: CacheSharing::Shared,
+        });
+        Ok(self)
+    }
+
+    /// Downloads a file into the container at `path`; with
+    /// [`HttpFile::unpack`], `path` is the directory it is unpacked into.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let protoc = p
+    ///     .http("https://github.com/protocolbuffers/protobuf/releases/download/v27.0/protoc-27.0-linux-x86_64.zip")
+    ///     .unpack(true);
+    /// p.task("gen")
+    ///     .container("debian:12")
+    ///     .mount_file(&protoc, "/opt/protoc")
+    ///     .run("/opt/protoc/bin/protoc --version");
+    ///
+    /// let json = p.to_json_value().unwrap();
+    /// assert_eq!(json["resources"][protoc.id()]["unpack"], true);
+    /// assert_eq!(json["tasks"][0]["mounts"][0]["type"], "file");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `path` is empty or not absolute, or if the URL is already
+    /// mounted with another digest or unpack setting; see
+    /// [`Task::try_mount_file`].
+    #[must_use]
+    pub fn mount_file(self, file: &HttpFile, path: &str) -> Self {
+        or_panic(self.try_mount_file(file, path))
+    }
+
+    /// Like [`Task::mount_file`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] or [`PipelineError::RelativePath`] if
+    /// `path` is empty or not absolute, and
+    /// [`PipelineError::HttpFileConflict`] if the URL is already mounted with
+    /// another digest or unpack setting.
+    pub fn try_mount_file(self, file: &HttpFile, path: &str) -> Result<Self, PipelineError> {
+        container_path("container mount path", path)?;
+        let id = file.id();
+        match self.pipeline.http_files.iter().find(|f| f.id() == id) {
+            Some(existing) if existing != file => {
+                return Err(PipelineError::HttpFileConflict { id });
+            }
+            Some(_) => {}
+            None => self.pipeline.http_files.push(file.clone()),
+        }
+        self.pipeline.tasks[self.index].mounts.push(Mount {
+            resource: id,
+            path: path.to_string(),
+            mount_type: "file".to_string(),
+            dir: None,
+            cache: None,
+            file: None,
+            sharing: CacheSharing::Shared,
+        });
+        Ok(self)
+    }
+
+    /// Mounts a single workspace file into the container at `path`, e.g.
+    /// `rustfmt.toml` without the rest of the repository.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let config = p.file("rustfmt.toml");
+    /// p.task("fmt")
+    ///     .container("rust:1.80")
+    ///     .mount_file_res(&config, "/src/rustfmt.toml")
+    ///     .run("rustfmt --check --config-path /src/rustfmt.toml src/lib.rs");
+    ///
+    /// let json = p.to_json_value().unwrap();
+    /// assert_eq!(json["resources"]["file:rustfmt.toml"]["hash"], "sha256");
+    /// assert_eq!(json["tasks"][0]["mounts"][0]["type"], "file");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `path` is empty or not absolute; see
+    /// [`Task::try_mount_file_res`].
+    #[must_use]
+    pub fn mount_file_res(self, file: &FileSource, path: &str) -> Self {
+        or_panic(self.try_mount_file_res(file, path))
+    }
+
+    /// Like [`Task::mount_file_res`], but returns an error instead of
+    /// panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] or [`PipelineError::RelativePath`] if
+    /// `path` is empty or not absolute.
+    pub fn try_mount_file_res(self, file: &FileSource, path: &str) -> Result<Self, PipelineError> {
+        container_path("container mount path", path)?;
+        self.pipeline.tasks[self.index].mounts.push(Mount {
+            resource: file.id(),
+            path: path.to_string(),
+            mount_type: "file".to_string(),
+            dir: None,
+            cache: None,
+            file: Some(file.clone()),
+            sharing: CacheSharing::Shared,
+        });
+        Ok(self)
+    }
+
+    /// Mounts the current working directory to `/work` and sets workdir.
+    /// This is a convenience method that combines mount + workdir for the common case.
+    ///
+    /// Registers the `src:.` directory resource. Calling it again doesn't add a
+    /// second mount, and a workdir set earlier with [`Task::workdir`] is kept.
+    #[must_use]
+    pub fn mount_cwd(self) -> Self {
+        self.mount_cwd_at("/work")
+    }
+
+    /// Mounts the current working directory to a custom path and sets workdir.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty or not absolute (must start with `/`).
+    #[must_use]
+    pub fn mount_cwd_at(self, path: &str) -> Self {
+        or_panic(container_path("container mount path", path));
+        let cwd = self.pipeline.dir(".");
+        let task = &mut self.pipeline.tasks[self.index];
+        let resource = cwd.id();
+        if !task
+            .mounts
+            .iter()
+            .any(|m| m.resource == resource && m.path == path)
+        {
+            task.mounts.push(Mount {
+                resource,
+                path: path.to_string(),
+                mount_type: "directory".to_string(),
+                dir: Some(cwd),
+                cache: None,
+                file: None,
+                sharing: CacheSharing::Shared,
+            });
+        }
+        if task.workdir.is_none() {
+            task.workdir = Some(path.to_string());
+        }
+        self
+    }
+
+    /// Sets the working directory: a path inside the container for
+    /// container tasks, or a directory on the host, usually relative to the
+    /// project root, for tasks without one.
+    ///
+    /// A relative workdir saves a `cd web &&` prefix on every command. It is
+    /// emitted unchanged; emit fails if the task also runs in a container.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("web-test").workdir("web").run("npm test");
+    /// p.task("test")
+    ///     .container("rust:1.80")
+    ///     .workdir("/src")
+    ///     .run("cargo test");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `path` is empty, or relative when the task already has a
+    /// container; see [`Task::try_workdir`].
+    #[must_use]
+    pub fn workdir(self, path: &str) -> Self {
+        or_panic(self.try_workdir(path))
+    }
+
+    /// Like [`Task::workdir`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] if `path` is empty, or
+    /// [`PipelineError::RelativePath`] if it is relative and the task
+    /// already runs in a container.
+    pub fn try_workdir(self, path: &str) -> Result<Self, PipelineError> {
+        let task = &mut self.pipeline.tasks[self.index];
+        if task.container.is_some() || task.container_build.is_some() {
+            container_path("container working directory", path)?;
+        } else if path.is_empty() {
+            return Err(PipelineError::Empty {
+                what: "working directory",
+            });
+        }
+        task.workdir = Some(path.to_string());
+        Ok(self)
+    }
+
+    /// Overrides the container image's entrypoint. An empty string clears
+    /// it, so the command runs without the image's wrapper script.
+    ///
+    /// Only applies to container tasks; setting it without one is linted.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("plan")
+    ///     .container("hashicorp/terraform:1.7")
+    ///     .entrypoint("")
+    ///     .run("terraform plan");
+    /// ```
+    #[must_use]
+    pub fn entrypoint(self, entrypoint: &str) -> Self {
+        self.pipeline.tasks[self.index].entrypoint = Some(entrypoint.to_string());
+        self
+    }
+
+    /// Runs the container as `user`, a name or `uid[:gid]` such as
+    /// `"1000:1000"`, instead of the image's default user.
+    ///
+    /// Only applies to container tasks; setting it without one is linted.
+    ///
+    /// # Panics
+    /// Panics if `user` is empty; see [`Task::try_user`].
+    #[must_use]
+    pub fn user(self, user: &str) -> Self {
+        or_panic(self.try_user(user))
+    }
+
+    /// Like [`Task::user`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] if `user` is empty.
+    pub fn try_user(self, user: &str) -> Result<Self, PipelineError> {
+        if user.is_empty() {
+            return Err(PipelineError::Empty {
+                what: "container user",
+            });
+        }
+        self.pipeline.tasks[self.index].user = Some(user.to_string());
+        Ok(self)
+    }
+
+    /// Sets an environment variable.
+    ///
+    /// # Panics
+    /// Panics if `key` is empty; see [`Task::try_env`].
+    #[must_use]
+    pub fn env(self, key: &str, value: &str) -> Self {
+        or_panic(self.try_env(key, value))
+    }
+
+    /// Like [`Task::env`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] if `key` is empty.
+    pub fn try_env(self, key: &str, value: &str) -> Result<Self, PipelineError> {
+        if key.is_empty() {
+            return Err(PipelineError::Empty {
+                what: "environment variable key",
+            });
+        }
+        self.pipeline.tasks[self.index]
+            .env
+            .insert(key.to_string(), value.to_string());
+        Ok(self)
+    }
+
+    /// Sets the variables in a `.env` file, read when the pipeline is
+    /// emitted. See [`dotenv`] for the format.
+    ///
+    /// Relative paths are resolved against the working directory of the
+    /// program emitting the pipeline. [`Task::env`] wins over the file, and
+    /// the file over [`Pipeline::default_env`]; a later file wins over an
+    /// earlier one. Emit fails if the file is missing or malformed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .env_file(".env.test")
+    ///     .env("RUST_LOG", "debug");
+    /// p.emit();
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `path` is empty.
+    #[must_use]
+    pub fn env_file(self, path: &str) -> Self {
+        assert!(!path.is_empty(), "env file path cannot be empty");
+        self.pipeline.tasks[self.index]
+            .env_files
+            .push(path.to_string());
+        self
+    }
+
+    /// Like [`Task::env_file`], but leaves reading the file to the engine
+    /// when the task runs, e.g. for files only present on the runner.
+    ///
+    /// Emitted as `env_files`, in call order. The engine decides how the
+    /// file's variables combine with `env`.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty.
+    #[must_use]
+    pub fn env_file_lazy(self, path: &str) -> Self {
+        assert!(!path.is_empty(), "env file path cannot be empty");
+        self.pipeline.tasks[self.index]
+            .runtime_env_files
+            .push(path.to_string());
+        self
+    }
+
+    /// Sets input file patterns for caching.
+    #[must_use]
+    pub fn inputs(self, patterns: &[&str]) -> Self {
+        self.pipeline.tasks[self.index]
+            .inputs
+            .extend(patterns.iter().map(|s| (*s).to_string()));
+        self
+    }
+
+    /// Sets a named output path.
+    ///
+    /// # Panics
+    /// Panics if `name` or `path` is empty; see [`Task::try_output`].
+    #[must_use]
+    pub fn output(self, name: &str, path: &str) -> Self {
+        or_panic(self.try_output(name, path))
+    }
+
+    /// Like [`Task::output`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] if `name` or `path` is empty.
+    pub fn try_output(self, name: &str, path: &str) -> Result<Self, PipelineError> {
+        self.try_output_kind(name, path, None)
+    }
+
+    /// Declares an output made of every file matching a glob, for artifacts
+    /// whose names aren't known up front, like version-stamped tarballs.
+    ///
+    /// Emitted as `{"path": ..., "type": "glob"}` under `outputs`. A task
+    /// reading it with [`Task::input_from`] gets the matches in a directory,
+    /// so emit fails unless that destination ends with `/`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("package")
+    ///     .run("make dist")
+    ///     .output_glob("bundles", "dist/*.tar.gz");
+    /// p.task("upload")
+    ///     .run("./upload.sh /in/bundles/*")
+    ///     .input_from("package", "bundles", "/in/bundles/");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` or `pattern` is empty.
+    #[must_use]
+    pub fn output_glob(self, name: &str, pattern: &str) -> Self {
+        or_panic(self.try_output_kind(name, pattern, Some(OutputKind::Glob)))
+    }
+
+    /// Declares a directory as an output, with everything under it.
+    ///
+    /// Emitted as `{"path": ..., "type": "directory"}` under `outputs`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("docs").run("zola build").output_dir("site", "public/");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` or `path` is empty.
+    #[must_use]
+    pub fn output_dir(self, name: &str, path: &str) -> Self {
+        or_panic(self.try_output_kind(name, path, Some(OutputKind::Dir)))
+    }
+
+    /// Declares a named output with retention, compression and whether it
+    /// must exist.
+    ///
+    /// With default options this is [`Task::output`]. Otherwise the output
+    /// is emitted as `{"path": ..., "type": "file", ...}` under `outputs`,
+    /// with `retention_days`, `compress: true` and `required: false` when set.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{OutputOptions, Pipeline};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("coverage")
+    ///     .run("cargo llvm-cov --html")
+    ///     .output_with(
+    ///         "report",
+    ///         "target/llvm-cov/html/",
+    ///         OutputOptions {
+    ///             retention_days: Some(7),
+    ///             compress: true,
+    ///             required: false,
+    ///         },
+    ///     );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` or `path` is empty.
+    #[must_use]
+    pub fn output_with(self, name: &str, path: &str, options: OutputOptions) -> Self {
+        let task = or_panic(self.try_output_kind(name, path, None));
+        if options != OutputOptions::default() {
+            task.pipeline.tasks[task.index]
+                .output_options
+                .insert(name.to_string(), options);
+        }
+        task
+    }
+
+    /// Declares an output of `kind`, or a single file for `None`, replacing
+    /// any output of the same name.
+    fn try_output_kind(
+        self,
+        name: &str,
+        path: &str,
+        kind: Option<OutputKind>,
+    ) -> Result<Self, PipelineError> {
+        if name.is_empty() {
+            return Err(PipelineError::Empty {
+                what: "output name",
+            });
+        }
+        if path.is_empty() {
+            return Err(PipelineError::Empty {
+                what: "output path",
+            });
+        }
+        let t = &mut self.pipeline.tasks[self.index];
+        t.outputs.insert(name.to_string(), path.to_string());
+        t.output_options.remove(name);
+        match kind {
+            Some(kind) => t.output_kinds.insert(name.to_string(), kind),
+            None => t.output_kinds.remove(name),
+        };
+        Ok(self)
+    }
+
+    /// Uploads a named output once the task succeeds.
+    ///
+    /// Emitted as a structured `publish` entry; credentials stay secret
+    /// references and are never turned into commands. The output must be
+    /// declared on this task with [`Task::output`] (checked at emit).
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{Pipeline, PublishDest, SecretRef};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("release")
+    ///     .run("cargo build --release")
+    ///     .output("binary", "target/release/app")
+    ///     .publish_output(
+    ///         "binary",
+    ///         PublishDest::GithubRelease {
+    ///             repo: "acme/app".to_string(),
+    ///             tag_expr: "{tag}".to_string(),
+    ///             token: SecretRef::from_env("GITHUB_TOKEN"),
+    ///         },
+    ///     );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `output_name` is empty, the repo isn't `owner/name`, the URL
+    /// isn't http(s), or a template uses an unknown placeholder.
+    #[must_use]
+    pub fn publish_output(self, output_name: &str, dest: PublishDest) -> Self {
+        assert!(
+            !output_name.is_empty(),
+            "publish_output: output name cannot be empty"
+        );
+        dest.validate();
+        self.pipeline.tasks[self.index]
+            .publish
+            .push((output_name.to_string(), dest));
+        self
+    }
+
+    /// Declares that this task needs an artifact from another task's output.
+    ///
+    /// This automatically adds a dependency on the source task. Emit fails if
+    /// `from_task` doesn't declare `output_name`, or if this task runs in a
+    /// container and `dest_path` isn't absolute.
+    ///
+    /// # Arguments
+    /// * `from_task` - Name of the task that produces the artifact
+    /// * `output_name` - Name of the output from that task
+    /// * `dest_path` - Path where the artifact should be available in this task
+    ///
+    /// # Panics
+    /// Panics if any argument is empty.
+    #[must_use]
+    pub fn input_from(self, from_task: &str, output_name: &str, dest_path: &str) -> Self {
+        assert!(
+            !from_task.is_empty(),
+            "input_from: from_task cannot be empty"
+        );
+        assert!(
+            !output_name.is_empty(),
+            "input_from: output_name cannot be empty"
+        );
+        assert!(
+            !dest_path.is_empty(),
+            "input_from: dest_path cannot be empty"
+        );
+
+        let task = &mut self.pipeline.tasks[self.index];
+
+        // Add the task input
+        task.task_inputs.push(TaskInput {
+            from_task: from_task.to_string(),
+            output: output_name.to_string(),
+            dest_path: dest_path.to_string(),
+            matrix: BTreeMap::new(),
+        });
+
+        // Auto-add dependency if not already present
+        task.dep_selectors.remove(from_task);
+        if !task.depends_on.contains(&from_task.to_string()) {
+            task.depends_on.push(from_task.to_string());
+        }
+
+        self
+    }
+
+    /// Like [`Task::input_from`], reading the output of the one variant of
+    /// matrix task `from_task` that has every `(key, value)` pair of
+    /// `selector`.
+    ///
+    /// The task then depends only on that variant, as with
+    /// [`Task::after_matrix`]. Emit fails unless the selector picks exactly
+    /// one variant.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build")
+    ///     .run("cargo build --release")
+    ///     .matrix("os", &["linux", "macos"])
+    ///     .output("binary", "target/release/app");
+    /// p.task("package")
+    ///     .run("./package.sh")
+    ///     .input_from_matrix("build", &[("os", "linux")], "binary", "./app");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if any argument is empty.
+    #[must_use]
+    pub fn input_from_matrix(
+        self,
+        from_task: &str,
+        selector: &[(&str, &str)],
+        output_name: &str,
+        dest_path: &str,
+    ) -> Self {
+        assert!(
+            !selector.is_empty(),
+            "input_from_matrix: selector cannot be empty"
+        );
+        let t = &self.pipeline.tasks[self.index];
+        // None if it already depends on every variant.
+        let selectors = match t.dep_selectors.get(from_task) {
+            Some(selectors) => Some(selectors.clone()),
+            None if t.depends_on.iter().any(|d| d == from_task) => None,
+            None => Some(Vec::new()),
+        };
+        let index = self.index;
+        let task = self.input_from(from_task, output_name, dest_path);
+        let t = &mut task.pipeline.tasks[index];
+        let selector = matrix_entry(selector);
+        t.task_inputs.last_mut().unwrap().matrix = selector.clone();
+        if let Some(mut selectors) = selectors {
+            if !selectors.contains(&selector) {
+                selectors.push(selector);
+            }
+            t.dep_selectors.insert(from_task.to_string(), selectors);
+        }
+        task
+    }
+
+    /// Reads every output of `from_task`, each into `<base_dir>/<output>`.
+    ///
+    /// Expanded at emit into one [`Task::input_from`] per output the
+    /// producer declares by then, so outputs added after this call count.
+    /// Glob outputs land in a directory, `<base_dir>/<output>/`, and an
+    /// explicit `input_from` of the same output wins. Adds a dependency on
+    /// `from_task`; emit fails if it declares no outputs.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("package")
+    ///     .container("alpine:3")
+    ///     .run("./package.sh")
+    ///     .inputs_from_all("build", "/artifacts/build");
+    /// p.task("build")
+    ///     .run("make")
+    ///     .output("binary", "target/app")
+    ///     .output("manifest", "target/manifest.json");
+    /// // package reads /artifacts/build/binary and /artifacts/build/manifest
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if either argument is empty.
+    #[must_use]
+    pub fn inputs_from_all(self, from_task: &str, base_dir: &str) -> Self {
+        assert!(
+            !from_task.is_empty(),
+            "inputs_from_all: from_task cannot be empty"
+        );
+        assert!(
+            !base_dir.is_empty(),
+            "inputs_from_all: base_dir cannot be empty"
+        );
+
+        let task = &mut self.pipeline.tasks[self.index];
+        task.inputs_from_all
+            .push((from_task.to_string(), base_dir.to_string()));
+        task.dep_selectors.remove(from_task);
+        if !task.depends_on.contains(&from_task.to_string()) {
+            task.depends_on.push(from_task.to_string());
+        }
+        self
+    }
+
+    /// Sets output paths (for backward compatibility).
+    ///
+    /// # Panics
+    /// Panics if any path is empty.
+    #[must_use]
+    pub fn outputs(self, paths: &[&str]) -> Self {
+        for (i, path) in paths.iter().enumerate() {
+            assert!(!path.is_empty(), "output path cannot be empty");
+            let t = &mut self.pipeline.tasks[self.index];
+            let name = format!("output_{i}");
+            t.output_kinds.remove(&name);
+            t.output_options.remove(&name);
+            t.outputs.insert(name, (*path).to_string());
+        }
+        self
+    }
+
+    /// Sets a single dependency - this task runs after the named task.
+    ///
+    /// This is a convenience method matching the Go SDK's `After(task)` signature.
+    #[must_use]
+    pub fn after_one(self, task: &str) -> Self {
+        self.pipeline.tasks[self.index].add_order_dep(task);
+        self
+    }
+
+    /// Sets dependencies - this task runs after the given tasks.
+    /// Duplicate dependencies are ignored.
+    ///
+    /// Accepts task names (`"lint"`, `&["lint", "test"]`), a [`TaskGroup`],
+    /// a [`TaskHandle`], or a tuple mixing them. See [`IntoDeps`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let build = p.task("build").run("cargo build").handle();
+    /// p.task("lint").run("cargo clippy");
+    /// p.task("deploy").run("./deploy.sh").after((&build, "lint"));
+    /// ```
+    #[must_use]
+    pub fn after(self, deps: impl IntoDeps) -> Self {
+        let pipeline_id = self.pipeline.id;
+        let task = &mut self.pipeline.tasks[self.index];
+        for dep in deps.into_deps() {
+            match dep.pipeline {
+                Some(id) if id != pipeline_id => {
+                    if !task.foreign_deps.contains(&dep.name) {
+                        task.foreign_deps.push(dep.name);
+                    }
+                }
+                _ => task.add_order_dep(&dep.name),
+            }
+        }
+        self
+    }
+
+    /// Runs this task after only the variants of matrix task `task` that
+    /// have every `(key, value)` pair of `selector`, e.g. only the
+    /// `os=linux` builds.
+    ///
+    /// Emitted in `depends_on` as the names of those variants, e.g.
+    /// `build-linux`, which runners give the expanded tasks (see
+    /// [`Variant::task_name`](matrix::Variant::task_name)). Calling it again for the same task adds
+    /// more variants, and a plain [`Task::after`] on it depends on all of
+    /// them. Emit fails if a key isn't one of the producer's dimensions, a
+    /// value isn't one of its values, or no variant matches.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build")
+    ///     .run("cargo build")
+    ///     .matrix("os", &["linux", "macos"]);
+    /// p.task("package")
+    ///     .run("./package.sh")
+    ///     .after_matrix("build", &[("os", "linux")]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `task` or `selector` is empty.
+    #[must_use]
+    pub fn after_matrix(self, task: &str, selector: &[(&str, &str)]) -> Self {
+        assert!(!task.is_empty(), "after_matrix: task cannot be empty");
+        assert!(
+            !selector.is_empty(),
+            "after_matrix: selector cannot be empty"
+        );
+        let t = &mut self.pipeline.tasks[self.index];
+        if t.depends_on.iter().any(|d| d == task) && !t.dep_selectors.contains_key(task) {
+            // Already depends on every variant.
+            if !t.order_deps.iter().any(|d| d == task) {
+                t.order_deps.push(task.to_string());
+            }
+            return self;
+        }
+        let selectors = t.dep_selectors.entry(task.to_string()).or_default();
+        let selector = matrix_entry(selector);
+        if !selectors.contains(&selector) {
+            selectors.push(selector);
+        }
+        if !t.depends_on.iter().any(|d| d == task) {
+            t.depends_on.push(task.to_string());
+        }
+        if !t.order_deps.iter().any(|d| d == task) {
+            t.order_deps.push(task.to_string());
+        }
+        self
+    }
+
+    /// Runs this task only after all of `tasks` succeed. Same as
+    /// [`Task::after`], but recorded as an explicit `run_when: "success"`.
+    #[must_use]
+    pub fn on_success_of(self, tasks: &[&str]) -> Self {
+        self.pipeline.tasks[self.index].add_hook_deps(tasks, RunWhen::Success);
+        self
+    }
+
+    /// Runs this task only when `tasks` fail, e.g. to send an alert.
+    ///
+    /// The dependencies are normal edges otherwise: they must exist and
+    /// can't form a cycle.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy").run("./deploy.sh");
+    /// p.task("alert")
+    ///     .run("./notify-slack.sh 'deploy failed'")
+    ///     .on_failure_of(&["deploy"]);
+    /// ```
+    #[must_use]
+    pub fn on_failure_of(self, tasks: &[&str]) -> Self {
+        self.pipeline.tasks[self.index].add_hook_deps(tasks, RunWhen::Failure);
+        self
+    }
+
+    /// Runs this task once `tasks` finish, whether they pass or fail, e.g.
+    /// to upload logs.
+    #[must_use]
+    pub fn always_after(self, tasks: &[&str]) -> Self {
+        self.pipeline.tasks[self.index].add_hook_deps(tasks, RunWhen::Always);
+        self
+    }
+
+    /// Returns a handle to this task that outlives the builder borrow.
+    ///
+    /// Passing it to [`Task::after`] names a task that is known to exist.
+    pub fn handle(&self) -> TaskHandle {
+        TaskHandle {
+            name: self.pipeline.tasks[self.index].name.clone(),
+            pipeline: Some(self.pipeline.id),
+        }
+    }
+
+    /// Sets dependencies on all tasks in a TaskGroup.
+    /// Duplicate dependencies are ignored.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let tests = p.matrix("versions", &["1.21", "1.22"], |p, v| {
+    ///     p.task(&format!("test-{}", v)).run("go test");
+    /// });
+    /// p.task("deploy").after_group(&tests).run("deploy");
+    /// ```
+    #[must_use]
+    pub fn after_group(self, group: &TaskGroup) -> Self {
+        let task = &mut self.pipeline.tasks[self.index];
+        for name in &group.task_names {
+            task.add_order_dep(name);
+        }
+        if !task.after_groups.iter().any(|g| g.name == group.name) {
+            task.after_groups.push(group.clone());
+        }
+        self
+    }
+
+    /// Sets a condition for when this task should run.
+    ///
+    /// The condition is evaluated at runtime based on CI context variables:
+    /// - `branch == 'main'` - run only on main branch
+    /// - `branch != 'main'` - run on all branches except main
+    /// - `tag != ''` - run only when a tag is present
+    /// - `event == 'push'` - run only on push events
+    /// - `ci == true` - run only in CI environment
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy")
+    ///     .run("./deploy.sh")
+    ///     .when("branch == 'main'");
+    /// ```
+    ///
+    /// Emit checks the string against the [condition grammar](condition)
+    /// and rejects unknown identifiers, unless
+    /// [`Pipeline::allow_unchecked_conditions`] is set.
+    ///
+    /// # Panics
+    /// Panics if `condition` is empty.
+    #[must_use]
+    pub fn when(self, condition: &str) -> Self {
+        assert!(!condition.is_empty(), "condition cannot be empty");
+        self.pipeline.tasks[self.index].condition = Some(condition.to_string());
+        self
+    }
+
+    /// Declares that this task requires a secret environment variable.
+    ///
+    /// The secret should be provided by the CI environment (e.g., GitHub Actions secrets).
+    /// The executor will validate that the secret is present before running the task.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy")
+    ///     .run("./deploy.sh")
+    ///     .secret("GITHUB_TOKEN")
+    ///     .secret("NPM_TOKEN");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` is empty; see [`Task::try_secret`].
+    #[must_use]
+    pub fn secret(self, name: &str) -> Self {
+        or_panic(self.try_secret(name))
+    }
+
+    /// Like [`Task::secret`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] if `name` is empty.
+    pub fn try_secret(self, name: &str) -> Result<Self, PipelineError> {
+        if name.is_empty() {
+            return Err(PipelineError::Empty {
+                what: "secret name",
+            });
+        }
+        self.pipeline.tasks[self.index]
+            .secrets
+            .push(name.to_string());
+        Ok(self)
+    }
+
+    /// Declares multiple secrets that this task requires.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy")
+    ///     .run("./deploy.sh")
+    ///     .secrets(&["GITHUB_TOKEN", "NPM_TOKEN", "AWS_KEY"]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if any secret name is empty.
+    #[must_use]
+    pub fn secrets(self, names: &[&str]) -> Self {
+        for name in names {
+            assert!(!name.is_empty(), "secret name cannot be empty");
+        }
+        self.pipeline.tasks[self.index]
+            .secrets
+            .extend(names.iter().map(|s| (*s).to_string()));
+        self
+    }
+
+    /// Declares an env secret the task can run without, like a tracing
+    /// token. Explain reports it as missing without failing the check.
+    ///
+    /// Plain `secrets` can't carry options, so this emits an env entry under
+    /// `secret_refs` with `"optional": true`, replacing any earlier
+    /// [`Task::secret`] of the same name.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .secret_opt("TRACING_TOKEN");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` is empty.
+    #[must_use]
+    pub fn secret_opt(self, name: &str) -> Self {
+        assert!(!name.is_empty(), "secret name cannot be empty");
+        self.pipeline.tasks[self.index]
+            .secrets
+            .retain(|s| s != name);
+        self.secret_from(name, SecretRef::from_env(name).optional())
+    }
+
+    /// Declares node labels that must be present for this task to run.
+    ///
+    /// Tasks with requires will only run on nodes that have all specified labels.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("train")
+    ///     .run("python train.py")
+    ///     .requires(&["gpu", "docker"]);
+    ///
+    /// p.task("build")
+    ///     .run("docker build .")
+    ///     .requires(&["docker"]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if any label is empty.
+    #[must_use]
+    pub fn requires(self, labels: &[&str]) -> Self {
+        for label in labels {
+            assert!(!label.is_empty(), "label cannot be empty");
+        }
+        self.pipeline.tasks[self.index]
+            .requires
+            .extend(labels.iter().map(|s| (*s).to_string()));
+        self
+    }
+
+    /// Sets the cross-platform verification mode for this task.
+    ///
+    /// Modes:
+    ///   - `"cross_platform"` — verify on a node with different OS/arch
+    ///   - `"always"` — always verify on any remote node
+    ///   - `"never"` — never verify this task remotely
+    ///
+    /// # Panics
+    /// Panics if `mode` is empty.
+    #[must_use]
+    pub fn verify(self, mode: &str) -> Self {
+        assert!(!mode.is_empty(), "verify mode cannot be empty");
+        self.pipeline.tasks[self.index].verify = Some(mode.to_string());
+        self
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────────
+    // AI-NATIVE METHODS
+    // ─────────────────────────────────────────────────────────────────────────────
+
+    /// Sets file patterns this task tests or affects (for smart selection).
+    #[must_use]
+    pub fn covers(self, patterns: &[&str]) -> Self {
+        self.pipeline.tasks[self.index]
+            .semantic
+            .covers
+            .extend(patterns.iter().map(|s| (*s).to_string()));
+        self
+    }
+
+    /// Sets a description of what this task does (for AI context).
+    #[must_use]
+    pub fn intent(self, description: &str) -> Self {
+        self.pipeline.tasks[self.index].semantic.intent = Some(description.to_string());
+        self
+    }
+
+    /// Marks this task as high-criticality.
+    #[must_use]
+    pub fn critical(self) -> Self {
+        self.pipeline.tasks[self.index].semantic.criticality = Some(Criticality::High);
+        self
+    }
+
+    /// Sets the criticality level.
+    #[must_use]
+    pub fn set_criticality(self, level: Criticality) -> Self {
+        self.pipeline.tasks[self.index].semantic.criticality = Some(level);
+        self
+    }
+
+    /// Sets what AI should do when this task fails.
+    #[must_use]
+    pub fn on_fail(self, action: OnFailAction) -> Self {
+        self.pipeline.tasks[self.index].ai_hooks.on_fail = Some(action);
+        self
+    }
+
+    /// Sets how AI should select this task for execution.
+    #[must_use]
+    pub fn select_mode(self, mode: SelectMode) -> Self {
+        self.pipeline.tasks[self.index].ai_hooks.select = Some(mode);
+        self
+    }
+
+    /// Enables smart task selection based on covers patterns.
+    #[must_use]
+    pub fn smart(self) -> Self {
+        self.pipeline.tasks[self.index].ai_hooks.select = Some(SelectMode::Smart);
+        self
+    }
+
+    /// Describes what this task does in a sentence, shown by `explain`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo nextest run")
+    ///     .describe("Runs unit tests with nextest");
+    /// ```
+    #[must_use]
+    pub fn describe(self, description: &str) -> Self {
+        self.pipeline.tasks[self.index].description =
+            (!description.is_empty()).then(|| description.to_string());
+        self
+    }
+
+    /// Tags this task for selection with [`Pipeline::subset`] and
+    /// [`Pipeline::explain_filtered`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test").tag("required");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or has characters other than lowercase
+    /// letters, digits and `-`; see [`Task::try_tag`].
+    #[must_use]
+    pub fn tag(self, name: &str) -> Self {
+        or_panic(self.try_tag(name))
+    }
+
+    /// Like [`Task::tag`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] or [`PipelineError::InvalidTag`].
+    pub fn try_tag(self, name: &str) -> Result<Self, PipelineError> {
+        if name.is_empty() {
+            return Err(PipelineError::Empty { what: "tag" });
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            return Err(PipelineError::InvalidTag {
+                tag: name.to_string(),
+            });
+        }
+        let task = &mut self.pipeline.tasks[self.index];
+        if !task.tags.iter().any(|t| t == name) {
+            task.tags.push(name.to_string());
+        }
+        Ok(self)
+    }
+
+    /// Adds multiple tags. See [`Task::tag`].
+    #[must_use]
+    pub fn tags(mut self, names: &[&str]) -> Self {
+        for name in names {
+            self = self.tag(name);
+        }
+        self
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────────
+    // CAPABILITY-BASED DEPENDENCIES
+    // ─────────────────────────────────────────────────────────────────────────────
+
+    /// Declares that this task produces a named capability.
+    /// Other tasks can declare they need this capability via `needs()`.
+    /// The optional value is injected as SYKLI_CAP_{NAME} env var in needing tasks.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty.
+    #[must_use]
+    pub fn provides(self, name: &str, value: Option<&str>) -> Self {
+        assert!(!name.is_empty(), "capability name cannot be empty");
+        self.pipeline.tasks[self.index]
+            .provides
+            .push((name.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Declares that this task needs named capabilities.
+    /// A dependency on the providing task is auto-resolved by the engine.
+    ///
+    /// # Panics
+    /// Panics if any name is empty.
+    #[must_use]
+    pub fn needs(self, names: &[&str]) -> Self {
+        for name in names {
+            assert!(!name.is_empty(), "capability name cannot be empty");
+        }
+        self.pipeline.tasks[self.index]
+            .needs
+            .extend(names.iter().map(|s| (*s).to_string()));
+        self
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // GATE METHODS
+    // ─────────────────────────────────────────────────────────────────────────
+
+    /// Sets the approval strategy for this gate task.
+    ///
+    /// # Panics
+    /// Panics if called on a non-gate task.
+    #[must_use]
+    pub fn gate_strategy(self, strategy: &str) -> Self {
+        let task = &mut self.pipeline.tasks[self.index];
+        let gate = task.gate.as_mut()
+            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
+        gate.strategy = strategy.to_string();
+        self
+    }
+
+    /// Sets the approval prompt message for this gate task.
+    ///
+    /// # Panics
+    /// Panics if called on a non-gate task.
+    #[must_use]
+    pub fn gate_message(self, message: &str) -> Self {
+        let task = &mut self.pipeline.tasks[self.index];
+        let gate = task.gate.as_mut()
+            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
+        gate.message = Some(message.to_string());
+        self
+    }
+
+    /// Sets the timeout in seconds for this gate task.
+    ///
+    /// # Panics
+    /// Panics if called on a non-gate task or if seconds is 0.
+    #[must_use]
+    pub fn gate_timeout(self, seconds: u32) -> Self {
+        assert!(seconds > 0, "gate timeout must be positive");
+        let task = &mut self.pipeline.tasks[self.index];
+        let gate = task.gate.as_mut()
+            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
+        gate.timeout = seconds;
+        self
+    }
+
+    /// Sets the environment variable to poll for the env strategy.
+    ///
+    /// # Panics
+    /// Panics if called on a non-gate task.
+    #[must_use]
+    pub fn gate_env_var(self, env_var: &str) -> Self {
+        let task = &mut self.pipeline.tasks[self.index];
+        let gate = task.gate.as_mut()
+            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
+        gate.env_var = Some(env_var.to_string());
+        self
+    }
+
+    /// Sets the file path to poll for the file strategy.
+    ///
+    /// # Panics
+    /// Panics if called on a non-gate task.
+    #[must_use]
+    pub fn gate_file_path(self, path: &str) -> Self {
+        let task = &mut self.pipeline.tasks[self.index];
+        let gate = task.gate.as_mut()
+            .unwrap_or_else(|| panic!("task {:?}: gate methods can only be used on gate tasks created via Pipeline::gate()", task.name));
+        gate.file_path = Some(path.to_string());
+        self
+    }
+
+    /// Declares a typed secret reference with explicit source.
+    ///
+    /// This provides better DX than plain secret names by making the source explicit.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use sykli::{Pipeline, SecretRef};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy")
+    ///     .run("./deploy.sh")
+    ///     .secret_from("GITHUB_TOKEN", SecretRef::from_env("GH_TOKEN"))
+    ///     .secret_from("DB_PASSWORD", SecretRef::from_vault("secret/data/db#password"));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` or `ref.key` is empty.
+    #[must_use]
+    pub fn secret_from(self, name: &str, secret_ref: SecretRef) -> Self {
+        assert!(!name.is_empty(), "secret name cannot be empty");
+        assert!(!secret_ref.key.is_empty(), "secret key cannot be empty");
+        let mut sr = secret_ref;
+        sr.name = name.to_string();
+        self.pipeline.tasks[self.index].secret_refs.push(sr);
+        self
+    }
+
+    /// Writes a secret to the file at `mount_path` instead of exporting it
+    /// as an env var, for tools that want a path (kubeconfigs, TLS keys,
+    /// `.npmrc`). `name` identifies the secret; the task only sees the file.
+    ///
+    /// Emitted under `secret_refs` with a `mount_path`. Emit fails if the
+    /// path isn't absolute or another secret file, secret template or mount
+    /// of the task uses it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{Pipeline, SecretRef};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy")
+    ///     .container("bitnami/kubectl")
+    ///     .env("KUBECONFIG", "/secrets/kubeconfig")
+    ///     .run("kubectl apply -f k8s/")
+    ///     .secret_file(
+    ///         "KUBECONFIG_CONTENT",
+    ///         SecretRef::from_vault("secret/ci#kubeconfig"),
+    ///         "/secrets/kubeconfig",
+    ///     );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name`, `ref.key` or `mount_path` is empty.
+    #[must_use]
+    pub fn secret_file(self, name: &str, secret_ref: SecretRef, mount_path: &str) -> Self {
+        assert!(!mount_path.is_empty(), "secret file path cannot be empty");
+        let task = self.secret_from(name, secret_ref);
+        let t = &mut task.pipeline.tasks[task.index];
+        t.secret_refs.last_mut().unwrap().mount_path = Some(mount_path.to_string());
+        task
+    }
+
+    /// Renders secrets into a file at `dest_path` before the task runs.
+    ///
+    /// `template` contains `{{name}}` placeholders, each resolved from the
+    /// matching ref by the executor at runtime. Secret values never appear in
+    /// the emitted pipeline, only the template and refs do.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{Pipeline, SecretRef};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("plan")
+    ///     .run("terraform plan")
+    ///     .env("AWS_SHARED_CREDENTIALS_FILE", "/run/secrets/aws")
+    ///     .secret_template(
+    ///         "/run/secrets/aws",
+    ///         "[default]\naws_access_key_id = {{key_id}}\naws_secret_access_key = {{secret}}\n",
+    ///         &[
+    ///             ("key_id", SecretRef::from_env("AWS_ACCESS_KEY_ID")),
+    ///             ("secret", SecretRef::from_env("AWS_SECRET_ACCESS_KEY")),
+    ///         ],
+    ///     );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `dest_path` is not absolute, if a placeholder has no matching
+    /// ref, if a ref is not used by the template, or if another template
+    /// already writes to `dest_path`.
+    #[must_use]
+    pub fn secret_template(
+        self,
+        dest_path: &str,
+        template: &str,
+        refs: &[(&str, SecretRef)],
+    ) -> Self {
+        assert!(
+            dest_path.starts_with('/'),
+            "secret_template: dest path {:?} must be absolute",
+            dest_path
+        );
+        let placeholders: Vec<&str> = SECRET_PLACEHOLDER
+            .captures_iter(template)
+            .map(|c| c.get(1).unwrap().as_str())
+            .collect();
+        for name in &placeholders {
+            assert!(
+                refs.iter().any(|(n, _)| n == name),
+                "secret_template {:?}: placeholder {{{{{}}}}} has no matching ref",
+                dest_path,
+                name
+            );
+        }
+        for (name, _) in refs {
+            assert!(
+                placeholders.contains(name),
+                "secret_template {:?}: ref {:?} is not used by the template",
+                dest_path,
+                name
+            );
+        }
+
+        let task = &mut self.pipeline.tasks[self.index];
+        assert!(
+            !task.secret_templates.iter().any(|st| st.dest == dest_path),
+            "secret_template {:?} is already declared for task {:?}",
+            dest_path,
+            task.name
+        );
+        task.secret_templates.push(SecretTemplate {
+            dest: dest_path.to_string(),
+            template: template.to_string(),
+            refs: refs
+                .iter()
+                .map(|(name, sr)| {
+                    let mut sr = sr.clone();
+                    sr.name = name.to_string();
+                    sr
+                })
+                .collect(),
+        });
+        self
+    }
+
+    /// Sets a type-safe condition for when this task should run.
+    ///
+    /// This is an alternative to `when()` that catches errors at compile time.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use sykli::{Pipeline, Condition};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy")
+    ///     .run("kubectl apply")
+    ///     .when_cond(Condition::branch("main").or(Condition::tag("v*")));
+    /// ```
+    #[must_use]
+    pub fn when_cond(self, c: Condition) -> Self {
+        // Condition::manual() is a marker rather than an expression
+        if c.expr == Condition::manual().expr {
+            return self.manual();
+        }
+        self.pipeline.tasks[self.index].when_cond = Some(c);
+        self
+    }
+
+    /// Sets a string condition for when this task should be skipped.
+    ///
+    /// Emitted negated into `when`, combined with `&&` if the task also has
+    /// a `when` condition.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .unless("branch matches 'wip/*'");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `condition` is empty.
+    #[must_use]
+    pub fn unless(self, condition: &str) -> Self {
+        assert!(!condition.is_empty(), "condition cannot be empty");
+        self.pipeline.tasks[self.index].unless = Some(condition.to_string());
+        self
+    }
+
+    /// Sets a type-safe condition for when this task should be skipped.
+    ///
+    /// This is an alternative to `unless()`, like `when_cond()` is to `when()`.
+    #[must_use]
+    pub fn unless_cond(self, c: Condition) -> Self {
+        self.pipeline.tasks[self.index].unless_cond = Some(c);
+        self
+    }
+
+    /// Makes this task wait for approval instead of starting automatically.
+    ///
+    /// Emitted as `manual: true`; the engine starts the task once it is
+    /// approved in the UI or CLI. Tasks depending on a manual task must be
+    /// manual or conditional themselves, or emit fails, since they would
+    /// otherwise wait forever.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build").run("make");
+    /// p.task("deploy-prod")
+    ///     .run("./deploy.sh prod")
+    ///     .after(&["build"])
+    ///     .manual();
+    /// ```
+    #[must_use]
+    pub fn manual(self) -> Self {
+        self.pipeline.tasks[self.index].manual = true;
+        self
+    }
+
+    /// Lets this task fail without failing the pipeline, for checks that
+    /// should report rather than block, like `cargo audit`.
+    ///
+    /// Emitted as `allow_failure: true`. Tasks depending on it still run
+    /// when it fails, so reading its outputs with [`Task::input_from`] is
+    /// linted: the artifact may be missing.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("audit").run("cargo audit").allow_failure(true);
+    /// ```
+    #[must_use]
+    pub fn allow_failure(self, allow: bool) -> Self {
+        self.pipeline.tasks[self.index].allow_failure = allow;
+        self
+    }
+
+    /// Puts this task in a concurrency group: the engine runs at most one
+    /// task of the group at a time, across runs of the pipeline, so two
+    /// deploys to the same environment never overlap.
+    ///
+    /// Emitted as `concurrency: {"group": ...}`. Later runs queue behind the
+    /// running one unless [`Task::cancel_in_progress`] is set.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy-api")
+    ///     .run("./deploy.sh api")
+    ///     .concurrency_group("staging-deploy");
+    /// p.task("deploy-web")
+    ///     .run("./deploy.sh web")
+    ///     .concurrency_group("staging-deploy");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `group` is empty; see [`Task::try_concurrency_group`].
+    #[must_use]
+    pub fn concurrency_group(self, group: &str) -> Self {
+        or_panic(self.try_concurrency_group(group))
+    }
+
+    /// Like [`Task::concurrency_group`], but returns an error instead of
+    /// panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::Empty`] if `group` is empty.
+    pub fn try_concurrency_group(self, group: &str) -> Result<Self, PipelineError> {
+        if group.is_empty() {
+            return Err(PipelineError::Empty {
+                what: "concurrency group",
+            });
+        }
+        let t = &mut self.pipeline.tasks[self.index];
+        let cancel_in_progress = t.concurrency.as_ref().is_some_and(|c| c.cancel_in_progress);
+        t.concurrency = Some(Concurrency {
+            group: group.to_string(),
+            cancel_in_progress,
+        });
+        Ok(self)
+    }
+
+    /// Makes a new run of this task cancel the one already running in its
+    /// concurrency group, instead of queueing behind it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("preview")
+    ///     .run("./deploy.sh preview")
+    ///     .concurrency_group("preview")
+    ///     .cancel_in_progress(true);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if [`Task::concurrency_group`] hasn't been called first.
+    #[must_use]
+    pub fn cancel_in_progress(self, cancel: bool) -> Self {
+        match self.pipeline.tasks[self.index].concurrency {
+            Some(ref mut c) => c.cancel_in_progress = cancel,
+            None => panic!(
+                "task {:?}: cancel_in_progress() must be called after concurrency_group()",
+                self.pipeline.tasks[self.index].name
+            ),
+        }
+        self
+    }
+
+    /// Deprecated. No longer affects emitted pipeline JSON.
+    ///
+    /// Use concrete execution requirements such as `container`, `mount_dir`,
+    /// `mount_cache`, `k8s`, `service`, `workdir`, and `env` instead.
+    #[deprecated(
+        note = "target no longer affects emitted pipeline JSON; use concrete execution requirement fields instead"
+    )]
+    #[must_use]
+    pub fn target(self, _name: &str) -> Self {
+        self
+    }
+
+    /// Adds a matrix dimension for this task.
+    ///
+    /// Matrix builds run the task multiple times with different parameter combinations.
+    /// Dimensions keep their declaration order; each value is exposed as an env var
+    /// under `key` and `SYKLI_MATRIX_<KEY>`, and replaces `{{matrix.<key>}}`
+    /// in the command, container image, workdir and env values. See the
+    /// [`matrix`] module for the expansion and variant naming contract.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .container("rust:{{matrix.rust_version}}")
+    ///     .run("cargo test")
+    ///     .matrix("rust_version", &["1.70", "1.75", "1.80"])
+    ///     .matrix("os", &["ubuntu", "macos"]);
+    /// // This creates 6 task variants (3 versions × 2 OS)
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `key` or `values` is empty.
+    #[must_use]
+    pub fn matrix(self, key: &str, values: &[&str]) -> Self {
+        assert!(!key.is_empty(), "matrix key cannot be empty");
+        assert!(!values.is_empty(), "matrix values cannot be empty");
+        self.pipeline.tasks[self.index].matrix.insert(
+            key.to_string(),
+            values.iter().map(|s| (*s).to_string()).collect(),
+        );
+        self
+    }
+
+    /// Drops every matrix variant that has all of `entry`'s `(key, value)`
+    /// pairs.
+    ///
+    /// An entry may name only some dimensions, so `&[("os", "macos")]` drops
+    /// every macOS variant. Keys must be matrix dimensions and values must be
+    /// in their lists, or emit fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .matrix("rust", &["1.70", "1.80"])
+    ///     .matrix("os", &["linux", "macos"])
+    ///     .matrix_exclude(&[("rust", "1.70"), ("os", "macos")]);
+    ///
+    /// assert_eq!(p.expand_matrix("test").len(), 3);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `entry` is empty.
+    #[must_use]
+    pub fn matrix_exclude(self, entry: &[(&str, &str)]) -> Self {
+        assert!(!entry.is_empty(), "matrix_exclude entry cannot be empty");
+        self.pipeline.tasks[self.index]
+            .matrix
+            .exclude(matrix_entry(entry));
+        self
+    }
+
+    /// Adds a matrix variant beyond the cartesian product, such as a nightly
+    /// toolchain on one OS only.
+    ///
+    /// The entry must set every matrix dimension, or emit fails; its values
+    /// needn't be in the dimensions' lists. Includes are added after
+    /// [`matrix_exclude`](Self::matrix_exclude) is applied.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .matrix("rust", &["1.70", "1.80"])
+    ///     .matrix("os", &["linux", "macos"])
+    ///     .matrix_include(&[("rust", "nightly"), ("os", "windows")]);
+    ///
+    /// let variants = p.expand_matrix("test");
+    /// assert_eq!(variants.len(), 5);
+    /// assert_eq!(variants[4]["rust"], "nightly");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `entry` is empty.
+    #[must_use]
+    pub fn matrix_include(self, entry: &[(&str, &str)]) -> Self {
+        assert!(!entry.is_empty(), "matrix_include entry cannot be empty");
+        self.pipeline.tasks[self.index]
+            .matrix
+            .include(matrix_entry(entry));
+        self
+    }
+
+    /// Adds a service container that runs alongside this task.
+    ///
+    /// Services are background containers (like databases) that run during task execution.
+    /// The service is accessible via its name as hostname.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .service("postgres:15", "db")
+    ///     .service("redis:7", "cache");
+    /// // postgres available at hostname "db", redis at "cache"
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `image` or `name` is empty.
+    #[must_use]
+    pub fn service(self, image: &str, name: &str) -> Self {
+        self.service_with(image, name, |s| s)
+    }
+
+    /// Like [`Task::service`], but configures the service's environment,
+    /// ports and command.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .service_with("postgres:15", "db", |s| {
+    ///         s.env("POSTGRES_PASSWORD", "test")
+    ///             .port(5432)
+    ///             .command("postgres -c fsync=off")
+    ///     });
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `image` or `name` is empty.
+    #[must_use]
+    pub fn service_with(
+        self,
+        image: &str,
+        name: &str,
+        configure: impl FnOnce(ServiceBuilder) -> ServiceBuilder,
+    ) -> Self {
+        assert!(!image.is_empty(), "service image cannot be empty");
+        assert!(!name.is_empty(), "service name cannot be empty");
+        let builder = configure(ServiceBuilder {
+            service: Service {
+                image: image.to_string(),
+                name: name.to_string(),
+                env: BTreeMap::new(),
+                ports: Vec::new(),
+                command: None,
+                ready_probes: Vec::new(),
+                ready_timeout: None,
+            },
+        });
+        self.pipeline.tasks[self.index]
+            .services
+            .push(builder.service);
+        self
+    }
+
+    /// Sets the number of retries on failure.
+    ///
+    /// If the task fails, it will be retried up to `count` times before being marked as failed.
+    /// Same as [`RetryPolicy::new`]`(count)`; 0 clears any retry.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("flaky-test")
+    ///     .run("cargo test -- --include-ignored")
+    ///     .retry(3);  // Retry up to 3 times on failure
+    /// ```
+    #[must_use]
+    pub fn retry(self, count: u32) -> Self {
+        debug!(task = %self.pipeline.tasks[self.index].name, retry = count, "setting retry");
+        self.pipeline.tasks[self.index].retry = (count > 0).then(|| RetryPolicy::new(count));
+        self
+    }
+
+    /// Sets how failures are retried: how often, how long to wait between
+    /// attempts, and which exit codes are worth retrying at all.
+    ///
+    /// Emitted as a `retry` object, or as a bare count when only `max` is
+    /// set. Emit fails if `max` is 0 or an exponential backoff's `max_secs`
+    /// is below its `base_secs`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{Backoff, Pipeline, RetryPolicy};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("push")
+    ///     .run("docker push registry.example.com/app")
+    ///     .retry_policy(RetryPolicy {
+    ///         max: 3,
+    ///         backoff: Backoff::Exponential { base_secs: 5, max_secs: 120 },
+    ///         retry_on_exit_codes: Some(vec![75, 111]),
+    ///         retry_on_timeout: true,
+    ///     });
+    /// ```
+    #[must_use]
+    pub fn retry_policy(self, policy: RetryPolicy) -> Self {
+        self.pipeline.tasks[self.index].retry = Some(policy);
+        self
+    }
+
+    /// Sets the timeout for this task in seconds.
+    ///
+    /// If the task doesn't complete within the timeout, it will be killed and marked as failed.
+    /// Default timeout is 300 seconds (5 minutes).
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("long-build")
+    ///     .run("cargo build --release")
+    ///     .timeout(600);  // 10 minute timeout
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `seconds` is 0; see [`Task::try_timeout`].
+    #[must_use]
+    pub fn timeout(self, seconds: u32) -> Self {
+        or_panic(self.try_timeout(seconds))
+    }
+
+    /// Like [`Task::timeout`], but returns an error instead of panicking.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::ZeroTimeout`] if `seconds` is 0.
+    pub fn try_timeout(self, seconds: u32) -> Result<Self, PipelineError> {
+        if seconds == 0 {
+            return Err(PipelineError::ZeroTimeout);
+        }
+        debug!(task = %self.pipeline.tasks[self.index].name, timeout = seconds, "setting timeout");
+        self.pipeline.tasks[self.index].timeout = Some(seconds);
+        Ok(self)
+    }
+
+    /// Records how long this task usually takes, in seconds.
+    ///
+    /// Informational: `explain` uses it to estimate wall-clock time along
+    /// the [critical path](Pipeline::critical_path).
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build").run("cargo build").estimated_duration(240);
+    /// ```
+    #[must_use]
+    pub fn estimated_duration(self, secs: u32) -> Self {
+        self.pipeline.tasks[self.index].estimated_duration = Some(secs);
+        self
+    }
+
+    /// Sets Kubernetes-specific options for this task.
+    ///
+    /// These options are only used when running with a K8s target.
+    /// If pipeline-level K8s defaults are set, task options will be merged
+    /// with task values overriding defaults.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use sykli::{Pipeline, K8sOptions, K8sResources};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build")
+    ///     .run("cargo build")
+    ///     .k8s(K8sOptions {
+    ///         resources: K8sResources {
+    ///             memory: Some("4Gi".into()),
+    ///             cpu: Some("2".into()),
+    ///             ..Default::default()
+    ///         },
+    ///         ..Default::default()
+    ///     });
+    /// ```
+    #[must_use]
+    pub fn k8s(self, opts: K8sOptions) -> Self {
+        debug!(task = %self.pipeline.tasks[self.index].name, "setting k8s options");
+        self.pipeline.tasks[self.index].k8s_options = Some(opts);
+        self
+    }
+
+    /// Adds raw Kubernetes configuration as JSON.
+    ///
+    /// Use this for advanced options not covered by `K8sOptions` (tolerations, affinity, etc.).
+    /// The JSON is passed directly to the executor and merged with K8sOptions.
+    /// K8sOptions fields take precedence over k8s_raw for overlapping settings.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// // GPU node with toleration
+    /// p.task("train")
+    ///     .k8s(K8sOptions { memory: Some("32Gi".into()), gpu: Some(1), ..Default::default() })
+    ///     .k8s_raw(r#"{"nodeSelector": {"gpu": "true"}, "tolerations": [{"key": "gpu", "effect": "NoSchedule"}]}"#);
+    /// ```
+    pub fn k8s_raw(self, json_config: &str) -> Self {
+        debug!(task = %self.pipeline.tasks[self.index].name, "setting k8s raw JSON");
+        self.pipeline.tasks[self.index].k8s_raw = Some(json_config.to_string());
+        self
+    }
+}
+
+impl<'a> Review<'a> {
+    /// Returns the review node's name.
+    pub fn name(&self) -> String {
+        self.pipeline.tasks[self.index].name.clone()
+    }
+
+    /// Sets the review primitive identifier.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty.
+    #[must_use]
+    pub fn primitive(self, name: &str) -> Self {
+        assert!(!name.is_empty(), "review primitive cannot be empty");
+        self.pipeline.tasks[self.index].primitive = Some(name.to_string());
+        self
+    }
+
+    /// Sets the agent identifier for this review node.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty.
+    #[must_use]
+    pub fn agent(self, name: &str) -> Self {
+        assert!(!name.is_empty(), "review agent cannot be empty");
+        self.pipeline.tasks[self.index].agent = Some(name.to_string());
+        self
+    }
+
+    /// Adds context paths for the review.
+    ///
+    /// # Panics
+    /// Panics if any path is empty.
+    #[must_use]
+    pub fn context(self, paths: &[&str]) -> Self {
+        for path in paths {
+            assert!(!path.is_empty(), "review context path cannot be empty");
+        }
+        self.pipeline.tasks[self.index]
+            .context
+            .extend(paths.iter().map(|s| (*s).to_string()));
+        self
+    }
+
+    /// Records review input references.
+    ///
+    /// # Panics
+    /// Panics if any input reference is empty.
+    #[must_use]
+    pub fn inputs(self, refs: &[&str]) -> Self {
+        for reference in refs {
+            assert!(
+                !reference.is_empty(),
+                "review input reference cannot be empty"
+            );
+        }
+        self.pipeline.tasks[self.index]
+            .inputs
+            .extend(refs.iter().map(|s| (*s).to_string()));
+        self
+    }
+
+    /// Sets dependencies for this review node. Duplicates are ignored.
+    #[must_use]
+    pub fn after(self, tasks: &[&str]) -> Self {
+        let task = &mut self.pipeline.tasks[self.index];
+        for name in tasks {
+            task.add_order_dep(name);
+        }
+        self
+    }
+
+    /// Sets whether the review is deterministic.
+    #[must_use]
+    pub fn deterministic(self, value: bool) -> Self {
+        self.pipeline.tasks[self.index].deterministic = value;
+        self
+    }
+}
+
+/// Returns `Some(None)` for `--emit`/`--emit-pretty` (stdout) and
+/// `Some(Some(path))` for `--emit=<path>`.
+fn emit_arg(args: &[String]) -> Option<Option<&str>> {
+    args.iter().find_map(|arg| match arg.as_str() {
+        "--emit" | "--emit-pretty" => Some(None),
+        _ => arg.strip_prefix("--emit=").map(Some),
+    })
+}
+
+/// Whether `emit` should indent: `--emit-pretty`, or `SYKLI_EMIT_PRETTY=1`.
+fn pretty_requested(args: &[String], env_value: Option<&str>) -> bool {
+    args.iter().any(|arg| arg == "--emit-pretty") || env_value == Some("1")
+}
+
+// =============================================================================
+// EXPLAIN CONTEXT
+// =============================================================================
+
+/// Context for evaluating conditions during explain/dry-run.
+///
+/// Build one from [`ExplainContext::default`] and the `with_*` methods;
+/// struct literals outside this crate don't compile, so fields can be added
+/// without a break.
+///
+/// ```rust
+/// use sykli::ExplainContext;
+///
+/// let ctx = ExplainContext::default()
+///     .with_branch("main")
+///     .with_ci(true)
+///     .with_var("ENVIRONMENT", "staging");
+/// assert_eq!(ctx.branch, "main");
+/// ```
+#[derive(Default)]
+#[non_exhaustive]
+pub struct ExplainContext {
+    /// Current branch name
+    pub branch: String,
+    /// Current tag (empty if none)
+    pub tag: String,
+    /// CI event type (push, pull_request, etc.)
+    pub event: String,
+    /// Base branch a pull request targets (empty if not a pull request)
+    pub pr_target: String,
+    /// Cron expression of the schedule that triggered the run (empty if none)
+    pub cron: String,
+    /// Whether running in CI environment
+    pub ci: bool,
+    /// Files changed by the commit, for [`Condition::paths_changed`]
+    pub changed_files: Vec<String>,
+    /// Custom variables, for [`Condition::env`] and [`Condition::var`].
+    /// `env.NAME` in a condition is looked up as `NAME`; `pr_number`,
+    /// `platform` and `runner` are looked up as themselves.
+    pub vars: HashMap<String, String>,
+    /// Show extra per-task detail (dependency kinds, ...)
+    pub verbose: bool,
+    /// How many matrix variants to list per task; None lists
+    /// [`DEFAULT_MATRIX_PREVIEW`].
+    pub matrix_preview: Option<usize>,
+    /// Check that each secret resolves on this machine: env sources against
+    /// the process environment, file sources against the filesystem. Vault
+    /// sources are not checked.
+    pub check_secrets: bool,
+}
+
+impl ExplainContext {
+    /// Sets the current branch name.
+    #[must_use]
+    pub fn with_branch(mut self, branch: &str) -> Self {
+        self.branch = branch.to_string();
+        self
+    }
+
+    /// Sets the current tag.
+    #[must_use]
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tag = tag.to_string();
+        self
+    }
+
+    /// Sets the CI event type, e.g. `push` or `pull_request`.
+    #[must_use]
+    pub fn with_event(mut self, event: &str) -> Self {
+        self.event = event.to_string();
+        self
+    }
+
+    /// Sets the base branch a pull request targets.
+    #[must_use]
+    pub fn with_pr_target(mut self, branch: &str) -> Self {
+        self.pr_target = branch.to_string();
+        self
+    }
+
+    /// Sets the cron expression of the schedule that triggered the run.
+    #[must_use]
+    pub fn with_cron(mut self, expr: &str) -> Self {
+        self.cron = expr.to_string();
+        self
+    }
+
+    /// Sets whether the run is in CI.
+    #[must_use]
+    pub fn with_ci(mut self, ci: bool) -> Self {
+        self.ci = ci;
+        self
+    }
+
+    /// Sets the files changed by the commit.
+    #[must_use]
+    pub fn with_changed_files(mut self, files: &[&str]) -> Self {
+        self.changed_files = files.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    /// Sets a custom variable; `env.NAME` in a condition is looked up as `NAME`.
+    #[must_use]
+    pub fn with_var(mut self, name: &str, value: &str) -> Self {
+        self.vars.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Sets whether explain shows extra per-task detail.
+    #[must_use]
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Sets how many matrix variants explain lists per task.
+    #[must_use]
+    pub fn with_matrix_preview(mut self, n: usize) -> Self {
+        self.matrix_preview = Some(n);
+        self
+    }
+
+    /// Sets whether explain checks that each secret resolves on this machine.
+    #[must_use]
+    pub fn with_check_secrets(mut self, check: bool) -> Self {
+        self.check_secrets = check;
+        self
+    }
+}
+
+/// What explain found, for wrappers that fail fast.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExplainReport {
+    /// Secrets that didn't resolve, as `(task, secret)`. Only filled in
+    /// with [`ExplainContext::check_secrets`].
+    pub missing_secrets: Vec<(String, String)>,
+}
+
+impl ExplainReport {
+    /// Number of secrets that didn't resolve.
+    pub fn missing_secret_count(&self) -> usize {
+        self.missing_secrets.len()
+    }
+}
+
+/// Formats seconds for explain: `45s`, `14m`, `2m 30s`, `1h 5m`.
+fn format_duration(secs: u32) -> String {
+    let (h, m, s) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    match (h, m, s) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, 0) => format!("{}m", m),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, 0, _) => format!("{}h", h),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// Matrix variants explain lists per task unless
+/// [`ExplainContext::matrix_preview`] says otherwise.
+pub const DEFAULT_MATRIX_PREVIEW: usize = 20;
+
+// =============================================================================
+// TASK GROUP
+// =============================================================================
+
+/// A group of tasks that can be used as a dependency.
+/// Created by `matrix()` and can be passed to `after()` or `chain()`.
+#[derive(Clone, Debug)]
+pub struct TaskGroup {
+    /// Name of the group (for identification)
+    pub name: String,
+    /// Names of tasks in this group
+    pub task_names: Vec<String>,
+}
+
+impl TaskGroup {
+    /// Creates a new task group.
+    #[must_use]
+    pub fn new(name: impl Into<String>, task_names: Vec<String>) -> Self {
+        TaskGroup {
+            name: name.into(),
+            task_names,
+        }
+    }
+
+    /// Returns the task names in this group.
+    #[must_use]
+    pub fn names(&self) -> &[String] {
+        &self.task_names
+    }
+}
+
+// =============================================================================
+// TASK HANDLES
+// =============================================================================
+
+/// A reference to a task by name, returned by [`Task::handle`].
+///
+/// Unlike a bare string, a handle can only name a task that was created, and
+/// remembers which pipeline it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskHandle {
+    name: String,
+    // None for deps given by name
+    pipeline: Option<u64>,
+}
+
+impl TaskHandle {
+    /// Name of the task.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn named(name: &str) -> Self {
+        TaskHandle {
+            name: name.to_string(),
+            pipeline: None,
+        }
+    }
+}
+
+/// Anything [`Task::after`] accepts as dependencies.
+///
+/// Implemented for task names (`&str`, `&String`, slices, arrays and vecs of
+/// them), [`TaskGroup`], [`TaskHandle`], and tuples of up to four of these.
+pub trait IntoDeps {
+    /// Converts into handles; names get handles with no pipeline attached.
+    fn into_deps(self) -> Vec<TaskHandle>;
+}
+
+impl IntoDeps for &str {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        vec![TaskHandle::named(self)]
+    }
+}
+
+impl IntoDeps for &String {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        vec![TaskHandle::named(self)]
+    }
+}
+
+impl IntoDeps for &[&str] {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        self.iter().map(|n| TaskHandle::named(n)).collect()
+    }
+}
+
+impl<const N: usize> IntoDeps for &[&str; N] {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        self.as_slice().into_deps()
+    }
+}
+
+impl IntoDeps for &Vec<&str> {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        self.as_slice().into_deps()
+    }
+}
+
+impl IntoDeps for &[String] {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        self.iter().map(|n| TaskHandle::named(n)).collect()
+    }
+}
+
+impl IntoDeps for &Vec<String> {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        self.as_slice().into_deps()
+    }
+}
+
+impl IntoDeps for &TaskGroup {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        self.task_names.as_slice().into_deps()
+    }
+}
+
+impl IntoDeps for TaskHandle {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        vec![self]
+    }
+}
+
+impl IntoDeps for &TaskHandle {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        vec![self.clone()]
+    }
+}
+
+impl IntoDeps for &[TaskHandle] {
+    fn into_deps(self) -> Vec<TaskHandle> {
+        self.to_vec()
+    }
+}
+
+macro_rules! impl_into_deps_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: IntoDeps),+> IntoDeps for ($($t,)+) {
+            #[allow(non_snake_case)]
+            fn into_deps(self) -> Vec<TaskHandle> {
+                let ($($t,)+) = self;
+                let mut deps = Vec::new();
+                $(deps.extend($t.into_deps());)+
+                deps
+            }
+        }
+    };
+}
+
+impl_into_deps_tuple!(A, B);
+impl_into_deps_tuple!(A, B, C);
+impl_into_deps_tuple!(A, B, C, D);
+
+// =============================================================================
+// PIPELINE
+// =============================================================================
+
+/// A CI pipeline with tasks and resources.
+pub struct Pipeline {
+    id: u64,
+    tasks: Vec<TaskData>,
+    templates: Vec<Template>,
+    dirs: Vec<Directory>,
+    caches: Vec<CacheVolume>,
+    git_sources: Vec<GitSource>, // registered when first mounted
+    http_files: Vec<HttpFile>,   // registered when first mounted
+    files: Vec<FileSource>,
+    k8s_defaults: Option<K8sOptions>,
+    defaults: TaskDefaults,
+    max_tasks: Option<usize>,
+    max_parallel: Option<usize>,
+    barriers: Vec<Barrier>,
+    normalize_resource_ids: bool,
+    hash_dir_globs: bool,
+    allowed_lints: Vec<lint::LintCode>,
+    max_runner_version: Option<(u32, u32, u32)>,
+    unchecked_conditions: bool,
+    strict: bool,
+    reserved_env_prefixes: Vec<String>,
+    secret_name_rule: Option<SecretNameRule>,
+}
+
+/// Settings from [`Pipeline::default_timeout`], [`Pipeline::default_retry`],
+/// [`Pipeline::default_env`] and [`Pipeline::secret`], filled into tasks at
+/// emit.
+#[derive(Clone, Default)]
+struct TaskDefaults {
+    timeout: Option<u32>,
+    retry: Option<RetryPolicy>,
+    env: BTreeMap<String, String>,
+    secrets: Vec<SharedSecret>,
+}
+
+/// A secret declared on the pipeline, in declaration order.
+#[derive(Clone)]
+struct SharedSecret {
+    name: String,
+    secret_ref: Option<SecretRef>, // None for a plain secret from Pipeline::secret()
+    pattern: Option<String>,       // task name glob; None matches every task
+}
+
+/// A secret added to many tasks with [`Pipeline::secret`] or
+/// [`Pipeline::secret_from`].
+pub struct PipelineSecret<'a> {
+    pipeline: &'a mut Pipeline,
+    index: usize,
+}
+
+impl PipelineSecret<'_> {
+    /// Adds the secret only to tasks whose name matches `pattern`, where `*`
+    /// matches any run of characters and `?` exactly one.
+    ///
+    /// # Panics
+    /// Panics if `pattern` is empty.
+    pub fn for_tasks_matching(self, pattern: &str) -> Self {
+        assert!(!pattern.is_empty(), "task pattern cannot be empty");
+        self.pipeline.defaults.secrets[self.index].pattern = Some(pattern.to_string());
+        self
+    }
+}
+
+/// A join point recorded by [`Pipeline::barrier`].
+#[derive(Clone)]
+struct Barrier {
+    name: String,
+    // Number of tasks declared before the barrier
+    position: usize,
+}
+
+impl Pipeline {
+    /// Creates a new pipeline.
+    #[must_use]
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Pipeline {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            tasks: Vec::new(),
+            templates: Vec::new(),
+            dirs: Vec::new(),
+            caches: Vec::new(),
+            git_sources: Vec::new(),
+            http_files: Vec::new(),
+            files: Vec::new(),
+            k8s_defaults: None,
+            defaults: TaskDefaults::default(),
+            max_tasks: None,
+            max_parallel: None,
+            barriers: Vec::new(),
+            normalize_resource_ids: false,
+            hash_dir_globs: false,
+            allowed_lints: Vec::new(),
+            max_runner_version: None,
+            unchecked_conditions: false,
+            strict: false,
+            reserved_env_prefixes: vec!["SYKLI_".to_string()],
+            secret_name_rule: None,
+        }
+    }
+
+    /// Creates a new pipeline with K8s defaults.
+    ///
+    /// All tasks inherit these settings unless they override them.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use sykli::{Pipeline, K8sOptions, K8sResources};
+    ///
+    /// let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+    ///     namespace: Some("ci-jobs".into()),
+    ///     resources: K8sResources {
+    ///         memory: Some("2Gi".into()),
+    ///         ..Default::default()
+    ///     },
+    ///     ..Default::default()
+    /// });
+    ///
+    /// p.task("test").run("go test");     // inherits defaults
+    /// p.task("heavy").k8s(K8sOptions {   // overrides memory
+    ///     resources: K8sResources { memory: Some("32Gi".into()), ..Default::default() },
+    ///     ..Default::default()
+    /// }).run("heavy-job");
+    /// ```
+    #[must_use]
+    pub fn with_k8s_defaults(k8s_defaults: K8sOptions) -> Self {
+        Pipeline {
+            k8s_defaults: Some(k8s_defaults),
+            ..Pipeline::new()
+        }
+    }
+
+    /// Caps the number of tasks this pipeline may contain.
+    ///
+    /// Generated pipelines (large matrices, per-package fan-out) can explode in
+    /// size. With a limit set, adding the task that crosses it panics right away,
+    /// during expansion, instead of failing after everything has been built.
+    ///
+    /// # Example
+    /// ```rust,should_panic
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.max_tasks(2);
+    /// let _ = p.task("a").run("echo a");
+    /// let _ = p.task("b").run("echo b");
+    /// let _ = p.task("c").run("echo c"); // panics: exceeds max_tasks limit of 2
+    /// ```
+    pub fn max_tasks(&mut self, limit: usize) -> &mut Self {
+        self.max_tasks = Some(limit);
+        self
+    }
+
+    /// Caps how many tasks the engine runs at once, e.g. to stay under a
+    /// runner's CPU or a registry's rate limit.
+    ///
+    /// Emitted as `max_parallel` at the pipeline root. Emit fails if `n` is 0,
+    /// since nothing could run.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.max_parallel(4);
+    /// p.task("test").run("cargo test");
+    /// ```
+    pub fn max_parallel(&mut self, n: usize) -> &mut Self {
+        self.max_parallel = Some(n);
+        self
+    }
+
+    /// Sets the timeout of every task that doesn't set its own, directly or
+    /// through a [`Template`]. [`Task::bare`] tasks are exempt.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.default_timeout(900);
+    /// p.task("test").run("cargo test"); // timeout 900
+    /// p.task("e2e").run("./e2e.sh").timeout(3600);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `seconds` is 0.
+    pub fn default_timeout(&mut self, seconds: u32) -> &mut Self {
+        assert!(seconds > 0, "timeout must be greater than 0");
+        self.defaults.timeout = Some(seconds);
+        self
+    }
+
+    /// Sets the retry count of every task that doesn't set its own, like
+    /// [`Pipeline::default_timeout`]. 0 clears it.
+    pub fn default_retry(&mut self, count: u32) -> &mut Self {
+        self.defaults.retry = (count > 0).then(|| RetryPolicy::new(count));
+        self
+    }
+
+    /// Sets an env var on every task that doesn't set `key` itself or
+    /// through a [`Template`]. [`Task::bare`] tasks are exempt.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.default_env("CARGO_TERM_COLOR", "always");
+    /// p.task("test").run("cargo test");
+    /// p.task("logs").run("./collect.sh").env("CARGO_TERM_COLOR", "never");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `key` is empty.
+    pub fn default_env(&mut self, key: &str, value: &str) -> &mut Self {
+        assert!(!key.is_empty(), "env key cannot be empty");
+        self.defaults.env.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Adds secret `name` to every task at emit, as if each called
+    /// [`Task::secret`]. Narrow it down with
+    /// [`PipelineSecret::for_tasks_matching`].
+    ///
+    /// A task that declares `name` itself keeps its own secret, and when two
+    /// pipeline secrets with the same name match a task, the later one wins.
+    /// [`Task::bare`] tasks, gates and review nodes are exempt. `explain`
+    /// marks these secrets `(inherited)`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.secret("DEPLOY_TOKEN").for_tasks_matching("deploy-*");
+    /// p.task("test").run("cargo test");
+    /// p.task("deploy-eu").run("./deploy.sh eu");
+    /// p.task("deploy-us").run("./deploy.sh us");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` is empty.
+    pub fn secret(&mut self, name: &str) -> PipelineSecret<'_> {
+        assert!(!name.is_empty(), "secret name cannot be empty");
+        self.add_shared_secret(name, None)
+    }
+
+    /// Like [`Pipeline::secret`], with an explicit source as in
+    /// [`Task::secret_from`].
+    ///
+    /// # Panics
+    /// Panics if `name` or `ref.key` is empty.
+    pub fn secret_from(&mut self, name: &str, secret_ref: SecretRef) -> PipelineSecret<'_> {
+        assert!(!name.is_empty(), "secret name cannot be empty");
+        assert!(!secret_ref.key.is_empty(), "secret key cannot be empty");
+        let mut sr = secret_ref;
+        sr.name = name.to_string();
+        self.add_shared_secret(name, Some(sr))
+    }
+
+    fn add_shared_secret(
+        &mut self,
+        name: &str,
+        secret_ref: Option<SecretRef>,
+    ) -> PipelineSecret<'_> {
+        self.defaults.secrets.push(SharedSecret {
+            name: name.to_string(),
+            secret_ref,
+            pattern: None,
+        });
+        let index = self.defaults.secrets.len() - 1;
+        PipelineSecret {
+            pipeline: self,
+            index,
+        }
+    }
+
+    /// Reserves an env var prefix for the engine or CI system, so emit fails
+    /// if a task sets a variable starting with it. `SYKLI_` is always
+    /// reserved.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.reserve_env_prefix("CI_");
+    /// p.task("test").run("cargo test").env("CI_JOB_ID", "1");
+    ///
+    /// let err = p.to_json().unwrap_err();
+    /// assert!(err.to_string().contains("the \"CI_\" prefix is reserved"));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `prefix` is empty.
+    pub fn reserve_env_prefix(&mut self, prefix: &str) -> &mut Self {
+        assert!(!prefix.is_empty(), "env prefix cannot be empty");
+        if !self.reserved_env_prefixes.iter().any(|p| p == prefix) {
+            self.reserved_env_prefixes.push(prefix.to_string());
+        }
+        self
+    }
+
+    /// Makes emit fail on secret names (and env secret keys) that don't
+    /// follow `rule`, e.g. [`SecretNameRule::UpperSnake`] to catch typos like
+    /// `"GITHUB TOKEN"`. Without a rule any name is emitted, and names that
+    /// aren't upper snake case get an `unconventional-secret-name` warning.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{Pipeline, SecretNameRule};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test").secret("github_token");
+    /// assert!(p.to_json().is_ok());
+    ///
+    /// p.secret_name_rule(SecretNameRule::UpperSnake);
+    /// assert!(p.to_json().is_err());
+    /// ```
+    pub fn secret_name_rule(&mut self, rule: SecretNameRule) -> &mut Self {
+        self.secret_name_rule = Some(rule);
+        self
+    }
+
+    /// Derives directory resource IDs from the normalized path.
+    ///
+    /// Without this, `dir(".")`, `dir("./")` and `dir("./x/..")` are three
+    /// resources with different IDs (and cache keys downstream). With it they
+    /// collapse into `src:.`, and absolute paths under the current directory
+    /// are made relative so IDs match across machines. [`Pipeline::file`]
+    /// paths are normalized the same way.
+    ///
+    /// Migration: this changes the emitted ID of any directory not already
+    /// spelled in normal form (e.g. `./src` becomes `src:src`), which
+    /// invalidates caches keyed on the old ID once. It is opt-in until the
+    /// next schema version makes it the default.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.normalize_resource_ids();
+    /// assert_eq!(p.dir("./").id(), "src:.");
+    /// assert_eq!(p.dir("./web/../api/").id(), "src:api");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if called after `dir()` or `file()`, since existing IDs may
+    /// already be mounted.
+    pub fn normalize_resource_ids(&mut self) -> &mut Self {
+        assert!(
+            self.dirs.is_empty() && self.files.is_empty(),
+            "normalize_resource_ids() must be called before dir() or file()"
+        );
+        self.normalize_resource_ids = true;
+        self
+    }
+
+    /// Suffixes the ID of each filtered directory with a hash of its globs,
+    /// e.g. `src:.#3f2a9c`, so `dir(".").glob(&["web/**"])` and
+    /// `dir(".").glob(&["api/**"])` are two resources.
+    ///
+    /// Without this, filtered views of one path share the ID `src:<path>`
+    /// and emit fails with `DirectoryGlobConflict` if tasks mount more than
+    /// one of them; [`Pipeline::dir_named`] is the other way out. Opt-in
+    /// because it changes the emitted ID of every filtered directory, which
+    /// invalidates caches keyed on it once, and other SDKs don't hash.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.hash_dir_globs();
+    /// let web = p.dir(".").glob(&["web/**"]);
+    /// let api = p.dir(".").glob(&["api/**"]);
+    /// assert!(web.id().starts_with("src:.#"));
+    /// assert_ne!(web.id(), api.id());
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if called after `dir()`, since existing IDs may already be
+    /// mounted.
+    pub fn hash_dir_globs(&mut self) -> &mut Self {
+        assert!(
+            self.dirs.is_empty(),
+            "hash_dir_globs() must be called before dir()"
+        );
+        self.hash_dir_globs = true;
+        self
+    }
+
+    /// Appends a node, enforcing the `max_tasks` limit. Returns its index.
+    fn push_task(&mut self, mut data: TaskData) -> usize {
+        if let Some(limit) = self.max_tasks {
+            assert!(
+                self.tasks.len() < limit,
+                "adding task {:?} exceeds max_tasks limit of {}",
+                data.name,
+                limit
+            );
+        }
+        for dep in self.barrier_deps() {
+            data.add_order_dep(&dep);
+        }
+        self.tasks.push(data);
+        self.tasks.len() - 1
+    }
+
+    /// Records a barrier: every task declared after it depends on every task
+    /// declared before it, without a placeholder task.
+    ///
+    /// The edges are added to the real tasks, so the barrier never appears in
+    /// the emitted JSON. Explicit dependencies can still be added with `after()`.
+    /// Barriers stack: tasks after a second barrier depend on the tasks between
+    /// the two, which already depend on everything before the first.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("lint").run("cargo clippy");
+    /// p.task("test").run("cargo test");
+    /// p.barrier("checks");
+    /// p.task("package").run("cargo package");
+    ///
+    /// let deps: Vec<_> = p.dependency_kinds("package").into_iter().map(|(d, _)| d).collect();
+    /// assert_eq!(deps, ["lint", "test"]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or a barrier with this name already exists.
+    pub fn barrier(&mut self, name: &str) {
+        assert!(!name.is_empty(), "barrier name cannot be empty");
+        assert!(
+            !self.barriers.iter().any(|b| b.name == name),
+            "barrier {name:?} already exists"
+        );
+        self.barriers.push(Barrier {
+            name: name.to_string(),
+            position: self.tasks.len(),
+        });
+    }
+
+    /// Tasks a newly declared task must wait for because of barriers: those
+    /// between the last barrier and the one before it, skipping empty spans.
+    fn barrier_deps(&self) -> Vec<String> {
+        for (i, b) in self.barriers.iter().enumerate().rev() {
+            let start = if i == 0 {
+                0
+            } else {
+                self.barriers[i - 1].position
+            };
+            if start < b.position {
+                return self.tasks[start..b.position]
+                    .iter()
+                    .map(|t| t.name.clone())
+                    .collect();
+            }
+        }
+        Vec::new()
+    }
+
+    /// Creates a directory resource.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty.
+    pub fn dir(&mut self, path: &str) -> Directory {
+        assert!(!path.is_empty(), "directory path cannot be empty");
+        let path = if self.normalize_resource_ids {
+            normalize_dir_path(path)
+        } else {
+            path.to_string()
+        };
+        let dir = Directory {
+            path,
+            globs: Vec::new(),
+            name: None,
+            hash_globs: self.hash_dir_globs,
+        };
+        if !self.dirs.iter().any(|d| d.id() == dir.id()) {
+            self.dirs.push(dir.clone());
+        }
+        dir
+    }
+
+    /// Creates a directory resource with an explicit name, giving the ID
+    /// `src:<path>#<name>`. Use it to mount several filtered views of one
+    /// path, which would otherwise share `src:<path>`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let web = p.dir_named("web-src", ".").glob(&["web/**"]);
+    /// let api = p.dir_named("api-src", ".").glob(&["api/**"]);
+    /// p.task("web").container("node:20").mount(&web, "/src").run("npm test");
+    /// p.task("api").container("rust:1.80").mount(&api, "/src").run("cargo test");
+    ///
+    /// let json = p.to_json_value().unwrap();
+    /// assert_eq!(json["resources"]["src:.#web-src"]["globs"][0], "web/**");
+    /// assert_eq!(json["resources"]["src:.#api-src"]["globs"][0], "api/**");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` or `path` is empty, or if `name` is already used
+    /// for another path.
+    pub fn dir_named(&mut self, name: &str, path: &str) -> Directory {
+        assert!(!name.is_empty(), "directory name cannot be empty");
+        assert!(!path.is_empty(), "directory path cannot be empty");
+        let path = if self.normalize_resource_ids {
+            normalize_dir_path(path)
+        } else {
+            path.to_string()
+        };
+        let dir = Directory {
+            path,
+            globs: Vec::new(),
+            name: Some(name.to_string()),
+            hash_globs: self.hash_dir_globs,
+        };
+        if let Some(other) = self
+            .dirs
+            .iter()
+            .find(|d| d.name.as_deref() == Some(name) && d.path != dir.path)
+        {
+            panic!(
+                "directory name {:?} is already used for {:?}",
+                name, other.path
+            );
+        }
+        if !self.dirs.iter().any(|d| d.id() == dir.id()) {
+            self.dirs.push(dir.clone());
+        }
+        dir
+    }
+
+    /// Directory resources as emitted: declared ones, then views first
+    /// mounted (or used as a build context) by tasks and templates. A plain
+    /// directory nothing mounts is left out when views of its path are used,
+    /// since `dir(path).glob(..)` declares it on the way.
+    fn directories(&self) -> Vec<&Directory> {
+        let used = self.used_directories();
+        let mut dirs: Vec<&Directory> = Vec::new();
+        for d in &self.dirs {
+            // Named directories get their globs after they are declared.
+            match used.iter().find(|u| u.id() == d.id()) {
+                Some(u) => dirs.push(u),
+                None if d.is_view() || !used.iter().any(|u| u.is_view() && u.path == d.path) => {
+                    dirs.push(d)
+                }
+                None => {}
+            }
+        }
+        for d in used {
+            if !dirs.iter().any(|o| o.id() == d.id()) {
+                dirs.push(d);
+            }
+        }
+        dirs
+    }
+
+    /// Directories as mounted (or used as a build context) by tasks and
+    /// templates, with their globs, in order and with repeats.
+    fn used_directories(&self) -> Vec<&Directory> {
+        let templates = self.templates.iter().flat_map(|t| &t.mounts);
+        self.tasks
+            .iter()
+            .flat_map(|t| {
+                t.mounts.iter().filter_map(|m| m.dir.as_ref()).chain(
+                    t.container_build
+                        .iter()
+                        .filter_map(|cb| cb.context.as_ref()),
+                )
+            })
+            .chain(templates.filter_map(|m| m.dir.as_ref()))
+            .collect()
+    }
+
+    /// Creates a named cache volume.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty.
+    pub fn cache(&mut self, name: &str) -> CacheVolume {
+        self.add_cache(name, None)
+    }
+
+    /// Cache resources as emitted: each declared cache with the settings it
+    /// was first mounted with, since those are made on the returned value.
+    fn caches(&self) -> Vec<&CacheVolume> {
+        self.caches
+            .iter()
+            .map(|c| {
+                self.mounted_caches()
+                    .find(|m| m.name == c.name && m.is_configured())
+                    .unwrap_or(c)
+            })
+            .collect()
+    }
+
+    /// Caches as mounted by tasks, then templates, with their key settings.
+    fn mounted_caches(&self) -> impl Iterator<Item = &CacheVolume> {
+        self.tasks
+            .iter()
+            .flat_map(|t| &t.mounts)
+            .chain(self.templates.iter().flat_map(|t| &t.mounts))
+            .filter_map(|m| m.cache.as_ref())
+    }
+
+    /// Creates a named cache volume with an explicit sharing key.
+    ///
+    /// Runners that share caches across pipelines (e.g. one volume per
+    /// machine) identify the cache by `key` instead of `name`. Two repos
+    /// using `cache_with_key("cargo-registry", "cargo-registry")` share a
+    /// volume; giving each a repo-specific key keeps them apart. The resource
+    /// ID is still `name`.
+    ///
+    /// # Panics
+    /// Panics if `name` or `key` is empty, or if a cache named `name` already
+    /// exists with a different key.
+    pub fn cache_with_key(&mut self, name: &str, key: &str) -> CacheVolume {
+        assert!(!key.is_empty(), "cache key cannot be empty");
+        self.add_cache(name, Some(key))
+    }
+
+    fn add_cache(&mut self, name: &str, key: Option<&str>) -> CacheVolume {
+        assert!(!name.is_empty(), "cache name cannot be empty");
+        let cache = CacheVolume {
+            name: name.to_string(),
+            key: key.map(str::to_string),
+            key_files: Vec::new(),
+            scope: None,
+            fallback: None,
+            max_size: None,
+            eviction: None,
+        };
+        match self.caches.iter().find(|c| c.name == name) {
+            Some(existing) => assert!(
+                existing.key == cache.key,
+                "cache {:?} already exists with key {:?}",
+                name,
+                existing.key
+            ),
+            None => self.caches.push(cache.clone()),
+        }
+        cache
+    }
+
+    /// Creates a remote git repository resource. Set exactly one of
+    /// [`branch`](GitSource::branch), [`tag`](GitSource::tag) or
+    /// [`commit`](GitSource::commit), and mount it with [`Task::mount_git`];
+    /// it is emitted under `resources` once mounted.
+    ///
+    /// `url` must be `https://...` or ssh (`ssh://...` or `git@host:path`),
+    /// or emit fails with [`EmitError::InvalidGitUrl`].
+    ///
+    /// # Panics
+    /// Panics if `url` is empty.
+    pub fn git(&self, url: &str) -> GitSource {
+        assert!(!url.is_empty(), "git URL cannot be empty");
+        GitSource {
+            url: url.to_string(),
+            branch: None,
+            tag: None,
+            commit: None,
+            depth: None,
+        }
+    }
+
+    /// Creates a resource for a file downloaded over HTTP, e.g. a tool
+    /// release several tasks need. Mount it with [`Task::mount_file`]; it is
+    /// emitted under `resources` once mounted, and the runner downloads it
+    /// once for all of them.
+    ///
+    /// `url` must be `http://` or `https://`, or emit fails with
+    /// [`EmitError::InvalidHttpUrl`]. In [strict](Pipeline::strict) mode it
+    /// also needs a [`sha256`](HttpFile::sha256).
+    ///
+    /// # Panics
+    /// Panics if `url` is empty.
+    pub fn http(&self, url: &str) -> HttpFile {
+        assert!(!url.is_empty(), "http URL cannot be empty");
+        HttpFile {
+            url: url.to_string(),
+            sha256: None,
+            unpack: false,
+        }
+    }
+
+    /// Creates a resource for a single workspace file, e.g. `rustfmt.toml`
+    /// for a formatting container that needs nothing else from the repo.
+    /// Mount it with [`Task::mount_file_res`].
+    ///
+    /// Emitted with `"hash": "sha256"`: runners key task caching on the
+    /// file's contents. Globs aren't allowed; emit fails with
+    /// [`EmitError::FileGlob`] if `path` has any. Mount a
+    /// [filtered directory](Directory::glob) instead.
+    ///
+    /// # Panics
+    /// Panics if `path` is empty.
+    pub fn file(&mut self, path: &str) -> FileSource {
+        assert!(!path.is_empty(), "file path cannot be empty");
+        self.add_file(None, path)
+    }
+
+    /// Creates a file resource with an explicit name, giving the ID
+    /// `file:<path>#<name>`, so that two resources for one path (say, a
+    /// config generated into place by different tasks) don't collide.
+    ///
+    /// # Panics
+    /// Panics if `name` or `path` is empty, or if `name` is already used
+    /// for another path.
+    pub fn file_named(&mut self, name: &str, path: &str) -> FileSource {
+        assert!(!name.is_empty(), "file name cannot be empty");
+        assert!(!path.is_empty(), "file path cannot be empty");
+        self.add_file(Some(name), path)
+    }
+
+    fn add_file(&mut self, name: Option<&str>, path: &str) -> FileSource {
+        let path = if self.normalize_resource_ids {
+            normalize_dir_path(path)
+        } else {
+            path.to_string()
+        };
+        if let Some(other) = self
+            .files
+            .iter()
+            .find(|f| name.is_some() && f.name.as_deref() == name && f.path != path)
+        {
+            panic!(
+                "file name {:?} is already used for {:?}",
+                name.unwrap_or_default(),
+                other.path
+            );
+        }
+        let file = FileSource {
+            path,
+            name: name.map(str::to_string),
+        };
+        if !self.files.iter().any(|f| f.id() == file.id()) {
+            self.files.push(file.clone());
+        }
+        file
+    }
+
+    /// Registers a named template and returns a builder for it.
+    ///
+    /// Emitted JSON lists registered templates under `templates`, and tasks
+    /// that applied one record its name as `template`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let src = p.dir(".");
+    /// p.template("rust")
+    ///     .container("rust:1.75")
+    ///     .mount(&src, "/src")
+    ///     .workdir("/src");
+    ///
+    /// let rust = p.get_template("rust").unwrap().clone();
+    /// p.task("test").from(&rust).run("cargo test");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or a template with the same name exists.
+    pub fn template(&mut self, name: &str) -> NamedTemplate<'_> {
+        assert!(!name.is_empty(), "template name cannot be empty");
+        assert!(
+            self.get_template(name).is_none(),
+            "template {name:?} already exists"
+        );
+        self.templates.push(Template {
+            name: Some(name.to_string()),
+            ..Template::default()
+        });
+        NamedTemplate {
+            index: self.templates.len() - 1,
+            pipeline: self,
+        }
+    }
+
+    /// Returns a template registered with [`Pipeline::template`].
+    pub fn get_template(&self, name: &str) -> Option<&Template> {
+        self.templates
+            .iter()
+            .find(|t| t.name.as_deref() == Some(name))
+    }
+
+    /// Creates a new task with the given name.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or if a task with the same name already
+    /// exists; see [`Pipeline::try_task`].
+    pub fn task(&mut self, name: &str) -> Task<'_> {
+        or_panic(self.try_task(name))
+    }
+
+    /// Like [`Pipeline::task`], but returns an error instead of panicking, for
+    /// pipelines built from data.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{Pipeline, PipelineError};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.try_task("test")?.try_run("cargo test")?;
+    ///
+    /// let err = p.try_task("test").err().unwrap();
+    /// assert_eq!(err, PipelineError::DuplicateTask { name: "test".into() });
+    /// assert_eq!(err.to_string(), r#"task "test" already exists"#);
+    /// # Ok::<(), PipelineError>(())
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::EmptyTaskName`] if `name` is empty and
+    /// [`PipelineError::DuplicateTask`] if a task, gate or review node with
+    /// the same name exists.
+    pub fn try_task(&mut self, name: &str) -> Result<Task<'_>, PipelineError> {
+        if name.is_empty() {
+            return Err(PipelineError::EmptyTaskName);
+        }
+        if self.has_task(name) {
+            return Err(PipelineError::DuplicateTask {
+                name: name.to_string(),
+            });
+        }
+        let index = self.push_task(TaskData {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        Ok(Task {
+            pipeline: self,
+            index,
+            keep_command: false,
+        })
+    }
+
+    /// Returns a handle to the task named `name`, creating it if needed.
+    ///
+    /// Lets library crates contribute tasks that the user may already have
+    /// defined. Settings applied through the handle are added to the existing
+    /// task, but its command wins: `run()` is ignored when the task already has
+    /// one, so use [`Task::run_replace`] to override it deliberately.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("sbom").run("syft . -o json");
+    ///
+    /// // A library adding its own "sbom" task doesn't clash with the user's.
+    /// p.task_or_get("sbom").run("syft . -o spdx"); // keeps "syft . -o json"
+    /// assert!(p.has_task("sbom"));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or names a gate or review node.
+    pub fn task_or_get(&mut self, name: &str) -> Task<'_> {
+        assert!(!name.is_empty(), "{}", PipelineError::EmptyTaskName);
+        match self.tasks.iter().position(|t| t.name == name) {
+            Some(index) => {
+                let t = &self.tasks[index];
+                assert!(
+                    t.kind == NodeKind::Task && t.gate.is_none(),
+                    "{name:?} exists but is not a task"
+                );
+                Task {
+                    pipeline: self,
+                    index,
+                    keep_command: true,
+                }
+            }
+            None => self.task(name),
+        }
+    }
+
+    /// Returns true if a task, gate or review node named `name` exists.
+    #[must_use]
+    pub fn has_task(&self, name: &str) -> bool {
+        self.tasks.iter().any(|t| t.name == name)
+    }
+
+    /// Creates an experimental review node with the given name.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or if a node with the same name already exists.
+    pub fn review(&mut self, name: &str) -> Review<'_> {
+        assert!(!name.is_empty(), "review name cannot be empty");
+        assert!(
+            !self.tasks.iter().any(|t| t.name == name),
+            "task/gate/review {name:?} already exists"
+        );
+        let index = self.push_task(TaskData {
+            kind: NodeKind::Review,
+            name: name.to_string(),
+            ..Default::default()
+        });
+        Review {
+            pipeline: self,
+            index,
+        }
+    }
+
+    /// Creates an approval gate that pauses the pipeline until approved.
+    ///
+    /// A gate is a special task with no command that waits for an approval signal.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty or if a task with the same name already exists.
+    pub fn gate(&mut self, name: &str) -> Task<'_> {
+        assert!(!name.is_empty(), "gate name cannot be empty");
+        assert!(
+            !self.tasks.iter().any(|t| t.name == name),
+            "task/gate {name:?} already exists"
+        );
+        let index = self.push_task(TaskData {
+            name: name.to_string(),
+            gate: Some(GateConfig {
+                strategy: "prompt".to_string(),
+                timeout: 3600,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        Task {
+            pipeline: self,
+            index,
+            keep_command: false,
+        }
+    }
+
+    /// Returns a Rust preset builder.
+    pub fn rust(&mut self) -> RustPreset<'_> {
+        RustPreset { pipeline: self }
+    }
+
+    /// Returns a Node.js preset builder. Its tasks are named `node-<step>`
+    /// unless [`NodePreset::prefix`] says otherwise.
+    pub fn node(&mut self) -> NodePreset<'_> {
+        NodePreset {
+            pipeline: self,
+            prefix: "node".to_string(),
+        }
+    }
+
+    /// Returns a Go preset builder. Its tasks are named `go-<step>` unless
+    /// [`GoPreset::prefix`] says otherwise.
+    pub fn go(&mut self) -> GoPreset<'_> {
+        GoPreset {
+            pipeline: self,
+            prefix: "go".to_string(),
+        }
+    }
+
+    /// Creates a sequential dependency chain between tasks.
+    ///
+    /// Each task in the chain depends on the previous one: a → b → c
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("a").run("echo a");
+    /// p.task("b").run("echo b");
+    /// p.task("c").run("echo c");
+    /// p.chain(&["a", "b", "c"]); // b depends on a, c depends on b
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if any task name doesn't exist.
+    pub fn chain(&mut self, task_names: &[&str]) {
+        for window in task_names.windows(2) {
+            let prev = window[0];
+            let curr = window[1];
+
+            // Find the current task and add dependency
+            let task = self
+                .tasks
+                .iter_mut()
+                .find(|t| t.name == curr)
+                .unwrap_or_else(|| panic!("task {:?} not found", curr));
+
+            // Only add the dependency if it doesn't already exist to avoid duplicates.
+            task.add_order_dep(prev);
+        }
+    }
+
+    /// Creates a TaskGroup from existing tasks (for parallel dependencies).
+    ///
+    /// This is a convenience method to create a named group of tasks that can be
+    /// used as a dependency. The tasks must already exist in the pipeline.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("lint").run("cargo clippy");
+    /// p.task("test").run("cargo test");
+    ///
+    /// // Create parallel group from existing tasks
+    /// let checks = p.parallel("checks", &["lint", "test"]);
+    ///
+    /// // Build depends on the parallel group
+    /// p.task("build").after_group(&checks).run("cargo build");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if any task name doesn't exist in the pipeline.
+    #[must_use]
+    pub fn parallel(&self, name: &str, task_names: &[&str]) -> TaskGroup {
+        // Validate all tasks exist
+        for &task_name in task_names {
+            if !self.tasks.iter().any(|t| t.name == task_name) {
+                panic!("task {:?} not found in pipeline", task_name);
+            }
+        }
+
+        TaskGroup::new(name, task_names.iter().map(|s| (*s).to_string()).collect())
+    }
+
+    /// Creates a named group of existing tasks, for use with
+    /// [`Task::after_group`].
+    ///
+    /// Like [`Pipeline::parallel`], but a misspelled name gets a suggestion.
+    /// Groups built with [`TaskGroup::new`] aren't checked until emit.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("lint").run("cargo clippy");
+    /// p.task("test").run("cargo test");
+    /// let checks = p.group("checks", &["lint", "test"]);
+    /// p.task("build").after_group(&checks).run("cargo build");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if any task name doesn't exist in the pipeline.
+    #[must_use]
+    pub fn group(&self, name: &str, task_names: &[&str]) -> TaskGroup {
+        let existing: Vec<&str> = self.tasks.iter().map(|t| t.name.as_str()).collect();
+        for &task_name in task_names {
+            if existing.contains(&task_name) {
+                continue;
+            }
+            match suggest_task_name(task_name, &existing) {
+                Some(s) => panic!(
+                    "group {:?}: unknown task {:?} (did you mean {:?}?)",
+                    name, task_name, s
+                ),
+                None => panic!("group {:?}: unknown task {:?}", name, task_name),
+            }
+        }
+        TaskGroup::new(name, task_names.iter().map(|s| (*s).to_string()).collect())
+    }
+
+    /// Creates tasks for each value in the matrix, returning a TaskGroup.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let mut p = Pipeline::new();
+    /// let tests = p.matrix("go-versions", &["1.21", "1.22", "1.23"], |p, version| {
+    ///     p.task(&format!("test-go-{}", version))
+    ///         .container(&format!("golang:{}", version))
+    ///         .mount_cwd()
+    ///         .run("go test ./...");
+    /// });
+    /// // Use the group as a dependency
+    /// p.task("deploy").after_group(&tests).run("deploy");
+    /// ```
+    pub fn matrix<F>(&mut self, name: &str, values: &[&str], mut generator: F) -> TaskGroup
+    where
+        F: FnMut(&mut Pipeline, &str),
+    {
+        assert!(!values.is_empty(), "matrix: values must not be empty");
+        let start_len = self.tasks.len();
+
+        for v in values {
+            generator(self, v);
+        }
+
+        // Collect names of newly created tasks
+        let task_names: Vec<String> = self.tasks[start_len..]
+            .iter()
+            .map(|t| t.name.clone())
+            .collect();
+
+        TaskGroup::new(name, task_names)
+    }
+
+    /// Returns the dependencies of `task` together with why each edge exists.
+    ///
+    /// Returns an empty list if the task doesn't exist or has no dependencies.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{EdgeKind, Pipeline};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build").run("cargo build").output("binary", "target/app");
+    /// p.task("lint").run("cargo clippy");
+    /// p.task("package")
+    ///     .run("./package.sh")
+    ///     .input_from("build", "binary", "/app")
+    ///     .after(&["lint"]);
+    ///
+    /// assert_eq!(
+    ///     p.dependency_kinds("package"),
+    ///     vec![
+    ///         ("build".to_string(), EdgeKind::Artifact),
+    ///         ("lint".to_string(), EdgeKind::Order),
+    ///     ]
+    /// );
+    /// ```
+    pub fn dependency_kinds(&self, task: &str) -> Vec<(String, EdgeKind)> {
+        self.tasks
+            .iter()
+            .find(|t| t.name == task)
+            .map(|t| {
+                t.depends_on
+                    .iter()
+                    .map(|dep| (dep.clone(), t.edge_kind(dep)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // =========================================================================
+    // EXPLAIN (Dry-run mode)
+    // =========================================================================
+
+    /// Context for evaluating conditions during explain.
+    /// Pass None to use empty defaults.
+    pub fn explain(&self, ctx: Option<&ExplainContext>) -> ExplainReport {
+        self.explain_to(&mut io::stdout(), ctx)
+    }
+
+    /// Writes the execution plan to the given writer.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{ExplainContext, Pipeline, SecretRef};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy")
+    ///     .run("./deploy.sh")
+    ///     .secret_from("TOKEN", SecretRef::from_env("SYKLI_DOC_UNSET_TOKEN"));
+    ///
+    /// let ctx = ExplainContext::default().with_check_secrets(true);
+    /// let mut out = Vec::new();
+    /// let report = p.explain_to(&mut out, Some(&ctx));
+    /// assert_eq!(report.missing_secret_count(), 1);
+    /// assert!(String::from_utf8(out).unwrap().contains("1 secret missing"));
+    /// ```
+    pub fn explain_to<W: Write>(&self, w: &mut W, ctx: Option<&ExplainContext>) -> ExplainReport {
+        self.explain_scoped(w, ctx, None)
+    }
+
+    /// Writes the execution plan for `task_name` and everything it depends
+    /// on, keeping the levels and order of the full plan.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build").run("cargo build");
+    /// p.task("docs").run("cargo doc");
+    /// p.task("deploy").run("./deploy.sh").after(&["build"]);
+    ///
+    /// let mut out = Vec::new();
+    /// p.explain_task(&mut out, "deploy", None).unwrap();
+    /// let out = String::from_utf8(out).unwrap();
+    /// assert!(out.contains("1. build"));
+    /// assert!(!out.contains("docs"));
+    ///
+    /// let err = p.explain_task(&mut Vec::new(), "deplyo", None).unwrap_err();
+    /// assert_eq!(err.to_string(), r#"unknown task "deplyo" (did you mean "deploy"?)"#);
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`SubsetError::UnknownTask`](subset::SubsetError::UnknownTask)
+    /// if there is no task named `task_name`.
+    pub fn explain_task<W: Write>(
+        &self,
+        w: &mut W,
+        task_name: &str,
+        ctx: Option<&ExplainContext>,
+    ) -> Result<ExplainReport, subset::SubsetError> {
+        let scope = self.related_tasks(task_name, subset::Direction::Dependencies)?;
+        let title = format!("{} and everything it depends on", task_name);
+        Ok(self.explain_scoped(w, ctx, Some((&title, &scope))))
+    }
+
+    /// Writes the execution plan for `task_name` and everything that depends
+    /// on it: what a change to that task could affect.
+    ///
+    /// # Errors
+    /// Returns [`SubsetError::UnknownTask`](subset::SubsetError::UnknownTask)
+    /// if there is no task named `task_name`.
+    pub fn explain_dependents<W: Write>(
+        &self,
+        w: &mut W,
+        task_name: &str,
+        ctx: Option<&ExplainContext>,
+    ) -> Result<ExplainReport, subset::SubsetError> {
+        let scope = self.related_tasks(task_name, subset::Direction::Dependents)?;
+        let title = format!("{} and everything that depends on it", task_name);
+        Ok(self.explain_scoped(w, ctx, Some((&title, &scope))))
+    }
+
+    /// Writes the execution plan for the tasks selected by `filter` and
+    /// everything they depend on, keeping the levels and order of the full
+    /// plan.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::subset::TagFilter;
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("build").run("cargo build");
+    /// p.task("docs").run("cargo doc").tag("ci");
+    /// p.task("publish").run("cargo publish").tag("release").after(&["build"]);
+    ///
+    /// let mut out = Vec::new();
+    /// p.explain_filtered(&mut out, TagFilter::Any(&["release"]), None).unwrap();
+    /// let out = String::from_utf8(out).unwrap();
+    /// assert!(out.contains("1. build"));
+    /// assert!(!out.contains("docs"));
+    /// ```
+    ///
+    /// # Errors
+    /// Returns [`SubsetError::NoMatch`](subset::SubsetError::NoMatch) if no
+    /// task carries any of the tags.
+    pub fn explain_filtered<W: Write>(
+        &self,
+        w: &mut W,
+        filter: subset::TagFilter<'_>,
+        ctx: Option<&ExplainContext>,
+    ) -> Result<ExplainReport, subset::SubsetError> {
+        let scope = self.tagged_tasks(filter)?;
+        let title = format!("{} and everything they depend on", filter);
+        Ok(self.explain_scoped(w, ctx, Some((&title, &scope))))
+    }
+
+    /// Writes the execution plan, limited to the tasks in `scope` if given.
+    fn explain_scoped<W: Write>(
+        &self,
+        w: &mut W,
+        ctx: Option<&ExplainContext>,
+        scope: Option<(&str, &HashSet<&str>)>,
+    ) -> ExplainReport {
+        let default_ctx = ExplainContext::default();
+        let ctx = ctx.unwrap_or(&default_ctx);
+        let mut report = ExplainReport::default();
+
+        let in_scope = |name: &str| scope.map_or(true, |(_, tasks)| tasks.contains(name));
+        let levels: Vec<Vec<&TaskData>> = self
+            .task_levels()
+            .into_iter()
+            .map(|level| {
+                level
+                    .into_iter()
+                    .filter(|t| in_scope(&t.name))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|level| !level.is_empty())
+            .collect();
+
+        writeln!(w, "Pipeline Execution Plan").ok();
+        writeln!(w, "=======================").ok();
+        if let Some((title, _)) = scope {
+            writeln!(w, "Scope: {}", title).ok();
+            writeln!(w).ok();
+        }
+
+        let mut barriers = self.barriers.iter().peekable();
+        let plural = |n: usize, word: &str| {
+            if n == 1 {
+                format!("{} {}", n, word)
+            } else {
+                format!("{} {}s", n, word)
+            }
+        };
+
+        // Estimates are only shown once some task has one
+        let estimated = levels
+            .iter()
+            .flatten()
+            .any(|t| t.estimated_duration.is_some());
+
+        let mut number = 0;
+        let mut expanded = 0;
+        for (level, tasks) in levels.iter().enumerate() {
+            // Barrier dividers go before the first level declared after them.
+            // Tasks after a barrier depend on all tasks before it, so a level
+            // never straddles one.
+            let index = self
+                .tasks
+                .iter()
+                .position(|d| d.name == tasks[0].name)
+                .unwrap();
+            while let Some(b) = barriers.next_if(|b| b.position <= index) {
+                writeln!(w, "--- barrier: {} ---", b.name).ok();
+                writeln!(w).ok();
+            }
+
+            let names: Vec<_> = tasks.iter().map(|t| t.name.as_str()).collect();
+            let estimate = if estimated {
+                let secs = tasks
+                    .iter()
+                    .map(|t| t.estimated_duration.unwrap_or(0))
+                    .max()
+                    .unwrap_or(0);
+                format!(" (est. {})", format_duration(secs))
+            } else {
+                String::new()
+            };
+            if tasks.len() > 1 {
+                writeln!(
+                    w,
+                    "Level {} (parallel): {}{}",
+                    level,
+                    names.join(", "),
+                    estimate
+                )
+                .ok();
+            } else {
+                writeln!(w, "Level {}: {}{}", level, names[0], estimate).ok();
+            }
+            writeln!(w).ok();
+
+            for t in tasks {
+                number += 1;
+                expanded += t.matrix.variants().len().max(1);
+
+                // Build task header
+                let mut header = format!("{}. {}", number, t.name);
+                if t.bare {
+                    header.push_str(" (bare)");
+                }
+
+                // Add dependencies, by group name when they all came from one group
+                if let Some(group) = t.single_group_deps() {
+                    header.push_str(&format!(" (after group: {})", group));
+                } else {
+                    let plain: Vec<&str> = t
+                        .depends_on
+                        .iter()
+                        .filter(|d| !t.run_when.contains_key(*d))
+                        .map(String::as_str)
+                        .collect();
+                    if !plain.is_empty() {
+                        header.push_str(&format!(" (after: {})", plain.join(", ")));
+                    }
+                    for when in RunWhen::ALL {
+                        let deps = t.hook_deps(when);
+                        if !deps.is_empty() {
+                            header.push_str(&format!(" ({}: {})", when.label(), deps.join(", ")));
+                        }
+                    }
+                }
+
+                // Add target override
+                if let Some(ref target) = t.target_name {
+                    header.push_str(&format!(" [target: {}]", target));
+                }
+
+                if t.manual {
+                    header.push_str(" [MANUAL APPROVAL REQUIRED]");
+                }
+                if t.allow_failure {
+                    header.push_str(" [allowed to fail]");
+                }
+                if let Some(ref c) = t.concurrency {
+                    header.push_str(&format!(" [concurrency: {}]", c.group));
+                }
+
+                // Check if task would be skipped
+                let condition = t.effective_condition();
+                let mut parse_error = None;
+                match plan::skip_decision(t, ctx) {
+                    plan::SkipDecision::Run => {}
+                    plan::SkipDecision::Skip { reason } => {
+                        header.push_str(&format!(" [SKIPPED: {}]", reason));
+                    }
+                    plan::SkipDecision::Unknown { reason } => {
+                        header.push_str(&format!(" [UNKNOWN: {}]", reason));
+                    }
+                    plan::SkipDecision::Invalid { error } => parse_error = Some(error),
+                }
+
+                writeln!(w, "{}", header).ok();
+                if let Some(ref description) = t.description {
+                    writeln!(w, "   {}", description).ok();
+                }
+                if !t.tags.is_empty() {
+                    writeln!(w, "   Tags: {}", t.tags.join(", ")).ok();
+                }
+                if t.steps.is_empty() {
+                    writeln!(w, "   Command: {}", t.script()).ok();
+                } else {
+                    writeln!(w, "   Steps:").ok();
+                    for (i, step) in t.steps.iter().enumerate() {
+                        writeln!(w, "     {}. {}", i + 1, step).ok();
+                    }
+                }
+                if estimated {
+                    match t.estimated_duration {
+                        Some(secs) => writeln!(w, "   Estimate: {}", format_duration(secs)).ok(),
+                        None => writeln!(w, "   Estimate: none (counted as 0)").ok(),
+                    };
+                }
+                let defaults = self.pipeline_defaults_for(t);
+                if !defaults.is_empty() {
+                    writeln!(w, "   Defaults: {}", defaults.join(", ")).ok();
+                }
+
+                if let Some(ref cond) = condition {
+                    writeln!(w, "   Condition: {}", cond).ok();
+                }
+                if let Some(err) = parse_error {
+                    writeln!(w, "   Warning: condition not evaluated: {}", err).ok();
+                }
+                let waits: Vec<String> = t
+                    .services
+                    .iter()
+                    .filter_map(|s| {
+                        let probe = s.ready_probes.first()?;
+                        Some(match s.ready_timeout {
+                            Some(secs) => {
+                                format!("{} ({}, \u{2264}{}s)", s.name, probe.describe(), secs)
+                            }
+                            None => format!("{} ({})", s.name, probe.describe()),
+                        })
+                    })
+                    .collect();
+                if !waits.is_empty() {
+                    writeln!(w, "   Waits for: {}", waits.join(", ")).ok();
+                }
+                let caches = self.caches();
+                for m in t.mounts.iter().filter(|m| m.mount_type == "cache") {
+                    if let Some(c) = caches
+                        .iter()
+                        .find(|c| c.name == m.resource && c.is_configured())
+                    {
+                        writeln!(w, "   Cache: {} at {}, {}", c.name, m.path, c.settings()).ok();
+                    }
+                }
+
+                if ctx.verbose && !t.depends_on.is_empty() {
+                    let deps: Vec<_> = t
+                        .depends_on
+                        .iter()
+                        .map(|dep| format!("{} ({})", dep, t.edge_kind(dep)))
+                        .collect();
+                    writeln!(w, "   Depends on: {}", deps.join(", ")).ok();
+                }
+
+                // Plain secrets are read from the env var of the same name.
+                let resolved = self.with_defaults(t);
+                let refs: Vec<SecretRef> = resolved
+                    .secrets
+                    .iter()
+                    .map(|name| SecretRef {
+                        name: name.clone(),
+                        ..SecretRef::new(SecretSource::Env, name)
+                    })
+                    .chain(resolved.secret_refs.iter().cloned())
+                    .collect();
+                let inherited = |sr: &SecretRef| {
+                    if t.declares_secret(&sr.name) {
+                        ""
+                    } else {
+                        " (inherited)"
+                    }
+                };
+                if ctx.check_secrets && !refs.is_empty() {
+                    writeln!(w, "   Secrets:").ok();
+                    for sr in &refs {
+                        let status = match sr.source {
+                            SecretSource::Env => std::env::var_os(&sr.key).is_some(),
+                            SecretSource::File => std::path::Path::new(&sr.key).exists(),
+                            // Secret stores can't be checked from here.
+                            SecretSource::Vault
+                            | SecretSource::K8s
+                            | SecretSource::Aws
+                            | SecretSource::Gcp
+                            | SecretSource::Sops => {
+                                writeln!(
+                                    w,
+                                    "     {} ({src}:{}) unchecked ({src}){}",
+                                    sr.name,
+                                    sr.key,
+                                    inherited(sr),
+                                    src = sr.source.as_str()
+                                )
+                                .ok();
+                                continue;
+                            }
+                        };
+                        let mark = if status {
+                            "\u{2713} present"
+                        } else if sr.optional {
+                            // Optional secrets may be absent; don't fail on them.
+                            "\u{2717} missing (optional)"
+                        } else {
+                            report
+                                .missing_secrets
+                                .push((t.name.clone(), sr.name.clone()));
+                            "\u{2717} MISSING"
+                        };
+                        writeln!(
+                            w,
+                            "     {} ({}:{}) {}{}",
+                            sr.name,
+                            sr.source.as_str(),
+                            sr.key,
+                            mark,
+                            inherited(sr)
+                        )
+                        .ok();
+                    }
+                } else if !refs.is_empty() {
+                    let plain = resolved.secrets.len();
+                    let secrets: Vec<_> = refs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, sr)| {
+                            if i < plain {
+                                format!("{}{}", sr.name, inherited(sr))
+                            } else {
+                                let src = sr.source.as_str();
+                                let optional = if sr.optional { ", optional" } else { "" };
+                                format!(
+                                    "{} ({}:{}{}){}",
+                                    sr.name,
+                                    src,
+                                    sr.key,
+                                    optional,
+                                    inherited(sr)
+                                )
+                            }
+                        })
+                        .collect();
+                    writeln!(w, "   Secrets: {}", secrets.join(", ")).ok();
+                }
+
+                let variants = t.matrix.variants();
+                if !variants.is_empty() {
+                    writeln!(w, "   Matrix: {}", plural(variants.len(), "variant")).ok();
+                    let preview = ctx.matrix_preview.unwrap_or(DEFAULT_MATRIX_PREVIEW);
+                    for v in variants.iter().take(preview) {
+                        let values: Vec<_> = v
+                            .values()
+                            .iter()
+                            .map(|(k, v)| format!("{}={}", k, v))
+                            .collect();
+                        writeln!(w, "     {} [{}]", t.name, values.join(", ")).ok();
+                    }
+                    if variants.len() > preview {
+                        writeln!(w, "     … and {} more", variants.len() - preview).ok();
+                    }
+                }
+
+                writeln!(w).ok();
+            }
+        }
+
+        let mut tasks = plural(number, "task");
+        if expanded != number {
+            tasks.push_str(&format!(" ({} after matrix expansion)", expanded));
+        }
+        write!(
+            w,
+            "{} in {}, max parallelism {}",
+            tasks,
+            plural(levels.len(), "level"),
+            levels.iter().map(Vec::len).max().unwrap_or(0)
+        )
+        .ok();
+        match self.max_parallel {
+            Some(cap) => writeln!(w, ", capped at {} by max_parallel", cap).ok(),
+            None => writeln!(w).ok(),
+        };
+        if estimated {
+            let (path, total) = self.longest_path(&levels);
+            writeln!(
+                w,
+                "estimated total: {}, critical path: {}",
+                format_duration(total),
+                path.join(" \u{2192} ")
+            )
+            .ok();
+            let unestimated: Vec<_> = levels
+                .iter()
+                .flatten()
+                .filter(|t| t.estimated_duration.is_none())
+                .map(|t| t.name.as_str())
+                .collect();
+            if !unestimated.is_empty() {
+                writeln!(w, "no estimate (counted as 0): {}", unestimated.join(", ")).ok();
+            }
+        }
+        if ctx.check_secrets {
+            writeln!(
+                w,
+                "{} missing",
+                plural(report.missing_secret_count(), "secret")
+            )
+            .ok();
+        }
+
+        let warnings: Vec<_> = self
+            .lint()
+            .into_iter()
+            .filter(|l| ctx.verbose || l.severity == lint::Severity::Warning)
+            .filter(|l| l.task.as_deref().map_or(true, in_scope))
+            .collect();
+        if !warnings.is_empty() {
+            writeln!(w, "Warnings").ok();
+            writeln!(w, "--------").ok();
+            for warning in &warnings {
+                writeln!(w, "{}", warning).ok();
+            }
+        }
+        report
+    }
+
+    /// Returns the chain of dependent tasks with the largest total
+    /// [`Task::estimated_duration`], and that total in seconds.
+    ///
+    /// Tasks without an estimate count as 0. Ties go to the chain that
+    /// `explain` lists first.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("lint").run("cargo clippy").estimated_duration(60);
+    /// p.task("test").run("cargo test").estimated_duration(300);
+    /// p.task("build")
+    ///     .run("cargo build")
+    ///     .after(&["lint", "test"])
+    ///     .estimated_duration(120);
+    ///
+    /// let (path, total) = p.critical_path();
+    /// assert_eq!(path, ["test", "build"]);
+    /// assert_eq!(total, 420);
+    /// ```
+    pub fn critical_path(&self) -> (Vec<String>, u32) {
+        let (path, total) = self.longest_path(&self.task_levels());
+        (path.into_iter().map(str::to_string).collect(), total)
+    }
+
+    /// Longest estimated path through the tasks in `levels`.
+    fn longest_path<'a>(&self, levels: &[Vec<&'a TaskData>]) -> (Vec<&'a str>, u32) {
+        // Total along the longest chain ending at each task, and the
+        // dependency it came through. Levels are in dependency order.
+        let mut best: HashMap<&str, (u32, Option<&str>)> = HashMap::new();
+        let mut end: Option<(&str, u32)> = None;
+        for t in levels.iter().flatten() {
+            let mut from = None;
+            let mut base = 0;
+            for dep in &t.depends_on {
+                if let Some(&(total, _)) = best.get(dep.as_str()) {
+                    if from.is_none() || total > base {
+                        from = Some(dep.as_str());
+                        base = total;
+                    }
+                }
+            }
+            let total = base.saturating_add(t.estimated_duration.unwrap_or(0));
+            best.insert(&t.name, (total, from));
+            if end.map_or(true, |(_, max)| total > max) {
+                end = Some((&t.name, total));
+            }
+        }
+        let Some((last, total)) = end else {
+            return (Vec::new(), 0);
+        };
+        let mut path = vec![last];
+        while let Some(&(_, Some(prev))) = best.get(path[path.len() - 1]) {
+            path.push(prev);
+        }
+        path.reverse();
+        (path, total)
+    }
+
+    /// Expands a task's matrix into one map of dimension values per variant,
+    /// in the order described in the [`matrix`] module.
+    ///
+    /// Returns no variants if the task has no matrix.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .matrix("rust", &["1.75", "1.80"])
+    ///     .matrix("os", &["linux", "macos"]);
+    ///
+    /// let variants = p.expand_matrix("test");
+    /// assert_eq!(variants.len(), 4);
+    /// assert_eq!(variants[1]["rust"], "1.75");
+    /// assert_eq!(variants[1]["os"], "macos");
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if there is no task named `task_name`.
+    pub fn expand_matrix(&self, task_name: &str) -> Vec<HashMap<String, String>> {
+        let Some(t) = self.tasks.iter().find(|t| t.name == task_name) else {
+            let existing: Vec<&str> = self.tasks.iter().map(|t| t.name.as_str()).collect();
+            match suggest_task_name(task_name, &existing) {
+                Some(s) => panic!(
+                    "expand_matrix: unknown task {:?} (did you mean {:?}?)",
+                    task_name, s
+                ),
+                None => panic!("expand_matrix: unknown task {:?}", task_name),
+            }
+        };
+        t.matrix
+            .variants()
+            .iter()
+            .map(|v| v.values().iter().cloned().collect())
+            .collect()
+    }
+
+    /// Replaces every matrix task with one concrete task per variant, for
+    /// runners that don't understand `matrix`.
+    ///
+    /// Variants are named as the [matrix contract](crate::matrix) says, by
+    /// [`matrix::Variant::task_name`], e.g. `test-1.75-linux`; see
+    /// [`Pipeline::expand_matrices_named`] for other names. Each variant:
+    ///
+    /// - gets its dimension values as env vars under the raw keys (not the
+    ///   `SYKLI_MATRIX_` aliases, which are reserved)
+    /// - has `{{matrix.<key>}}` in its command, steps, exec args, container
+    ///   image, workdir and env values replaced with its value
+    /// - keeps everything else from the original task
+    ///
+    /// Tasks that depended on the original task depend on all of its variants
+    /// instead, or on those picked with [`Task::after_matrix`], and
+    /// [`Task::input_from_matrix`] reads from the variant it picks. Validation
+    /// runs at emit as usual, so cycles and unknown dependencies are reported
+    /// against the expanded tasks.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .container("rust:{{matrix.rust}}")
+    ///     .run("cargo test")
+    ///     .matrix("rust", &["1.75", "1.80"]);
+    /// p.task("build").run("cargo build").after(&["test"]);
+    ///
+    /// p.expand_matrices();
+    /// let names: Vec<_> = p.iter_tasks().map(|t| t.name().to_string()).collect();
+    /// assert_eq!(names, ["test-1.75", "test-1.80", "build"]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if a variant name is already taken or a task reads outputs of
+    /// a matrix task; see [`Pipeline::try_expand_matrices_named`].
+    pub fn expand_matrices(&mut self) {
+        or_panic(self.expand_matrices_by(|t, v| v.task_name(t)))
+    }
+
+    /// Like [`Pipeline::expand_matrices`], with variant names from
+    /// `template`: `{{task}}` is replaced with the task name and
+    /// `{{matrix.<key>}}` with the variant's value.
+    ///
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test")
+    ///     .run("cargo test")
+    ///     .matrix("rust", &["1.70", "1.75"]);
+    ///
+    /// p.expand_matrices_named("{{task}}-rust-{{matrix.rust}}");
+    /// assert!(p.has_task("test-rust-1.70"));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics where [`Pipeline::try_expand_matrices_named`] returns an error.
+    pub fn expand_matrices_named(&mut self, template: &str) {
+        or_panic(self.try_expand_matrices_named(template))
+    }
+
+    /// Like [`Pipeline::expand_matrices_named`], but returns an error instead
+    /// of panicking. The pipeline is left unchanged on error.
+    ///
+    /// # Errors
+    /// Returns [`PipelineError::EmptyTaskName`] or
+    /// [`PipelineError::DuplicateTask`] if a variant name is empty or already
+    /// taken, e.g. because `template` leaves out a dimension, and
+    /// [`PipelineError::MatrixInput`] if a task uses `input_from` or
+    /// `inputs_from_all` on a matrix task, or `input_from_matrix` with a
+    /// selector that doesn't pick exactly one variant.
+    pub fn try_expand_matrices_named(&mut self, template: &str) -> Result<(), PipelineError> {
+        self.expand_matrices_by(|t, v| v.interpolate(&template.replace("{{task}}", t)))
+    }
+
+    fn expand_matrices_by(
+        &mut self,
+        name: impl Fn(&str, &matrix::Variant) -> String,
+    ) -> Result<(), PipelineError> {
+        // Original task name -> its variants, with their names.
+        let mut expanded: HashMap<String, Vec<(String, matrix::Variant)>> = HashMap::new();
+        let mut tasks = Vec::with_capacity(self.tasks.len());
+        let mut shift = vec![0; self.tasks.len() + 1];
+        for (i, t) in self.tasks.iter().enumerate() {
+            let variants = t.matrix.variants();
+            shift[i + 1] = shift[i] + variants.len().saturating_sub(1);
+            if variants.is_empty() {
+                tasks.push(t.clone());
+                continue;
+            }
+            let mut named = Vec::with_capacity(variants.len());
+            for v in variants {
+                let mut vt = t
+                    .interpolate_matrix(|k| v.get(k).map(str::to_string))
+                    .into_owned();
+                vt.name = name(&t.name, &v);
+                vt.matrix = matrix::Matrix::default();
+                for (k, value) in v.values() {
+                    vt.env.insert(k.clone(), value.clone());
+                }
+                named.push((vt.name.clone(), v));
+                tasks.push(vt);
+            }
+            expanded.insert(t.name.clone(), named);
+        }
+        if expanded.is_empty() {
+            return Ok(());
+        }
+
+        // Names of the variants of `dep` matching any of `selectors`, or of
+        // all of them without selectors; `dep` itself if it isn't expanded.
+        let select =
+            |dep: &String, selectors: Option<&Vec<BTreeMap<String, String>>>| match expanded
+                .get(dep)
+            {
+                Some(named) => named
+                    .iter()
+                    .filter(|(_, v)| selectors.map_or(true, |s| s.iter().any(|m| v.matches(m))))
+                    .map(|(n, _)| n.clone())
+                    .collect(),
+                None => vec![dep.clone()],
+            };
+
+        let mut seen = HashSet::new();
+        for t in &tasks {
+            if t.name.is_empty() {
+                return Err(PipelineError::EmptyTaskName);
+            }
+            if !seen.insert(t.name.as_str()) {
+                return Err(PipelineError::DuplicateTask {
+                    name: t.name.clone(),
+                });
+            }
+            let inputs = t
+                .task_inputs
+                .iter()
+                .filter(|ti| select(&ti.from_task, Some(&vec![ti.matrix.clone()])).len() != 1)
+                .map(|ti| &ti.from_task);
+            let all = t.inputs_from_all.iter().map(|(from, _)| from);
+            if let Some(from) = inputs.chain(all).find(|f| expanded.contains_key(*f)) {
+                return Err(PipelineError::MatrixInput {
+                    task: t.name.clone(),
+                    from_task: from.clone(),
+                });
+            }
+        }
+
+        for t in &mut tasks {
+            let selectors = std::mem::take(&mut t.dep_selectors);
+            let rewire = |deps: &[String]| -> Vec<String> {
+                deps.iter()
+                    .flat_map(|d| select(d, selectors.get(d)))
+                    .collect()
+            };
+            t.depends_on = rewire(&t.depends_on);
+            t.order_deps = rewire(&t.order_deps);
+            for original in expanded.keys() {
+                if let Some(when) = t.run_when.remove(original) {
+                    for n in select(original, selectors.get(original)) {
+                        t.run_when.insert(n, when);
+                    }
+                }
+            }
+            for g in &mut t.after_groups {
+                g.task_names = rewire(&g.task_names);
+            }
+            for ti in &mut t.task_inputs {
+                if expanded.contains_key(&ti.from_task) {
+                    let matrix = std::mem::take(&mut ti.matrix);
+                    ti.from_task = select(&ti.from_task, Some(&vec![matrix])).remove(0);
+                }
+            }
+            t.dep_selectors = selectors
+                .into_iter()
+                .filter(|(d, _)| !expanded.contains_key(d))
+                .collect();
+        }
+        for b in &mut self.barriers {
+            b.position += shift[b.position];
+        }
+        self.tasks = tasks;
+        Ok(())
+    }
+
+    /// Groups tasks into dependency levels: each task's level is the length
+    /// of the longest dependency chain leading to it.
+    ///
+    /// Tasks in a level don't depend on each other, so they can run in
+    /// parallel. Levels list tasks in declaration order. Tasks in a
+    /// dependency cycle are left out.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("lint").run("cargo clippy");
+    /// p.task("test").run("cargo test");
+    /// p.task("build").run("cargo build").after(&["lint", "test"]);
+    ///
+    /// assert_eq!(p.levels(), vec![vec!["lint", "test"], vec!["build"]]);
+    /// ```
+    pub fn levels(&self) -> Vec<Vec<String>> {
+        self.task_levels()
+            .into_iter()
+            .map(|level| level.into_iter().map(|t| t.name.clone()).collect())
+            .collect()
+    }
+
+    fn task_levels(&self) -> Vec<Vec<&TaskData>> {
+        let index: HashMap<&str, usize> = self
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.name.as_str(), i))
+            .collect();
+        let mut level: Vec<Option<usize>> = vec![None; self.tasks.len()];
+
+        // Assign a level once every known dependency has one
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, t) in self.tasks.iter().enumerate() {
+                if level[i].is_some() {
+                    continue;
+                }
+                let deps: Option<Vec<usize>> = t
+                    .depends_on
+                    .iter()
+                    .filter_map(|d| index.get(d.as_str()))
+                    .map(|&d| level[d])
+                    .collect();
+                if let Some(deps) = deps {
+                    level[i] = Some(deps.into_iter().max().map_or(0, |l| l + 1));
+                    changed = true;
+                }
+            }
+        }
+
+        let mut levels: Vec<Vec<&TaskData>> = Vec::new();
+        for (t, l) in self.tasks.iter().zip(level) {
+            let Some(l) = l else { continue };
+            if levels.len() <= l {
+                levels.resize_with(l + 1, Vec::new);
+            }
+            levels[l].push(t);
+        }
+        levels
+    }
+
+    /// Tasks in explain order: level by level, declaration order within one.
+    fn topological_sort(&self) -> Vec<&TaskData> {
+        self.task_levels().into_iter().flatten().collect()
+    }
+
+    /// Emits the pipeline as JSON to stdout if `--emit` flag is present.
+    ///
+    /// This method checks for `--emit` in command line arguments and if found,
+    /// writes the pipeline JSON to stdout and exits the process with code 0.
+    /// If emission fails, exits with code 1.
+    ///
+    /// With `--emit-subset <filter>` (e.g. `tag=required` or `name=test,lint`), only
+    /// the [`subset`](Pipeline::subset) selected by the filter is emitted. With
+    /// `--secrets-report`, the [`secrets_report`](Pipeline::secrets_report) table is
+    /// printed instead of the JSON.
+    ///
+    /// `--emit-pretty`, or `--emit` with `SYKLI_EMIT_PRETTY=1` set, writes
+    /// indented JSON (see [`Pipeline::emit_pretty_to`]). `--emit=<path>`
+    /// writes to a file instead of stdout (see [`Pipeline::emit_file`]).
+    /// `--emit-yaml` prints [`Pipeline::to_yaml`] instead, and fails unless
+    /// built with the `yaml` feature.
+    ///
+    /// **Note:** This method exits the process and does not return. Binaries
+    /// that embed pipelines should use [`Pipeline::to_json`] or
+    /// [`Pipeline::emit_to`] instead.
+    pub fn emit(&self) {
+        let args: Vec<String> = env::args().collect();
+        let pretty = pretty_requested(&args, env::var("SYKLI_EMIT_PRETTY").ok().as_deref());
+        if args.iter().any(|arg| arg == "--secrets-report") {
+            print!("{}", self.secrets_report());
+            std::process::exit(0);
+        }
+        if args.iter().any(|arg| arg == "--emit-yaml") {
+            if let Err(e) = self.emit_yaml_stdout() {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        if let Some(spec) = subset::emit_subset_arg(&args) {
+            let result = subset::SubsetFilter::parse(spec)
+                .and_then(|filter| self.subset(filter))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+                .and_then(|sub| sub.emit_stdout(pretty));
+            if let Err(e) = result {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        if let Some(path) = emit_arg(&args) {
+            let result = match path {
+                Some(path) => self.write_file(Path::new(path), pretty),
+                None => self.emit_stdout(pretty),
+            };
+            if let Err(e) = result {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+    }
+
+    /// Always emits the pipeline as JSON to stdout and exits.
+    ///
+    /// Unlike [`Pipeline::emit`], this method always writes the JSON output
+    /// regardless of command line arguments. This matches the Go SDK's `MustEmit()`.
+    ///
+    /// **Note:** This method exits the process and does not return.
+    pub fn force_emit(&self) {
+        if let Err(e) = self.emit_stdout(false) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+
+    #[cfg(feature = "yaml")]
+    fn emit_yaml_stdout(&self) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        out.write_all(self.to_yaml()?.as_bytes())?;
+        out.flush()
+    }
+
+    #[cfg(not(feature = "yaml"))]
+    fn emit_yaml_stdout(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--emit-yaml requires the sykli \"yaml\" feature",
+        ))
+    }
+
+    /// Streams the pipeline JSON to a buffered stdout, indented if `pretty`.
+    fn emit_stdout(&self, pretty: bool) -> io::Result<()> {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        if pretty {
+            self.emit_pretty_to(&mut out)?;
+        } else {
+            self.emit_streaming_to(&mut out)?;
+        }
+        out.flush()
+    }
+
+    /// Writes the pipeline JSON to the given writer.
+    ///
+    /// Map keys (`env`, `outputs`, `resources`, ...) are sorted and tasks keep
+    /// declaration order, so the same pipeline always emits the same bytes.
+    ///
+    /// # Errors
+    /// Validation failures are `InvalidData` errors wrapping an
+    /// [`EmitError`]; use [`Pipeline::emit_to_checked`] to match on it.
+    pub fn emit_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        Ok(self.emit_to_checked(w)?)
+    }
+
+    /// Like [`Pipeline::emit_to`], returning a typed error.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::{EmitError, Pipeline};
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("deploy").run("./deploy.sh").after(&["tset"]);
+    /// p.task("test").run("cargo test");
+    ///
+    /// match p.emit_to_checked(&mut Vec::new()) {
+    ///     Err(EmitError::UnknownDependency { dep, suggestion, .. }) => {
+    ///         assert_eq!(dep, "tset");
+    ///         assert_eq!(suggestion.as_deref(), Some("test"));
+    ///     }
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// The first validation failure, or [`EmitError::Multiple`] listing all
+    /// of them if there are several; [`EmitError::Io`] if writing fails.
+    pub fn emit_to_checked<W: Write>(&self, w: &mut W) -> Result<(), EmitError> {
+        self.validate_for_emit()?;
+        let json = serde_json::to_string(&self.json_pipeline()).map_err(io::Error::from)?;
+        w.write_all(json.as_bytes())?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    /// Returns the pipeline JSON that [`Pipeline::emit_to`] writes, without
+    /// the trailing newline.
+    ///
+    /// Runs the same validation, but never touches stdout or exits, so a
+    /// binary can hold several pipelines and emit the one it needs. Prefer
+    /// this over [`Pipeline::emit`] when using sykli as a library.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test");
+    ///
+    /// let json = p.to_json().unwrap();
+    /// assert!(json.starts_with(r#"{"version":"#));
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an `InvalidData` error if validation fails.
+    pub fn to_json(&self) -> io::Result<String> {
+        Ok(serde_json::to_string(&self.json_document()?)?)
+    }
+
+    /// Like [`Pipeline::to_json`], as a [`serde_json::Value`] to inspect or
+    /// embed in a larger document.
+    ///
+    /// # Errors
+    /// Returns an `InvalidData` error if validation fails.
+    pub fn to_json_value(&self) -> io::Result<serde_json::Value> {
+        Ok(serde_json::to_value(self.json_document()?)?)
+    }
+
+    /// Returns the same document as [`Pipeline::to_json`], as YAML.
+    ///
+    /// For reading in reviews only; the engine reads JSON. Requires the
+    /// `yaml` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test");
+    ///
+    /// let yaml = p.to_yaml().unwrap();
+    /// assert!(yaml.contains("- name: test\n  command: cargo test\n"));
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an `InvalidData` error if validation fails.
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> io::Result<String> {
+        serde_yaml::to_string(&self.json_document()?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Validates, then builds the JSON document. Every non-streaming emit
+    /// path goes through here.
+    fn json_document(&self) -> io::Result<JsonPipeline> {
+        self.validate_for_emit()?;
+        Ok(self.json_pipeline())
+    }
+
+    /// Writes the pipeline JSON to a file, atomically.
+    ///
+    /// The JSON goes to a temporary file next to `path`, which is then
+    /// renamed over it, so a crash never leaves a truncated pipeline behind.
+    /// Use it instead of redirecting stdout when tasks also print there.
+    ///
+    /// # Errors
+    /// Validation and I/O errors, with `path` in the message. The file at
+    /// `path` is left as it was.
+    pub fn emit_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_file(path.as_ref(), false)
+    }
+
+    fn write_file(&self, path: &Path, pretty: bool) -> io::Result<()> {
+        let with_path =
+            |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+        let name = path.file_name().ok_or_else(|| {
+            with_path(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a file path",
+            ))
+        })?;
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".{}.tmp", std::process::id()));
+        let tmp = path.with_file_name(tmp_name);
+
+        let write = || -> io::Result<()> {
+            let mut out = io::BufWriter::new(std::fs::File::create(&tmp)?);
+            if pretty {
+                self.emit_pretty_to(&mut out)?;
+            } else {
+                self.emit_streaming_to(&mut out)?;
+            }
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            std::fs::rename(&tmp, path)
+        };
+        write().map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            with_path(e)
+        })
+    }
+
+    /// Writes the pipeline JSON to the given writer, indented for reading.
+    ///
+    /// Same document as [`Pipeline::emit_to`]; only the whitespace differs.
+    /// The engine reads either, but `emit` stays compact unless asked.
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test");
+    ///
+    /// let mut out = Vec::new();
+    /// p.emit_pretty_to(&mut out).unwrap();
+    /// assert!(String::from_utf8(out).unwrap().contains("\n  \"tasks\": ["));
+    /// ```
+    pub fn emit_pretty_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *w, &self.json_document()?)?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    /// Writes the pipeline JSON to the given writer, one task at a time.
+    ///
+    /// Produces exactly the same bytes as [`Pipeline::emit_to`], but never
+    /// materializes the whole JSON document: each task is converted and
+    /// serialized on its own, so peak memory stays flat for generated
+    /// pipelines with tens of thousands of tasks.
+    pub fn emit_streaming_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.validate_for_emit()?;
+        serde_json::to_writer(
+            &mut *w,
+            &StreamingPipeline {
+                version: self.emit_version(),
+                requires: self.json_requires(),
+                resources: self.json_resources(),
+                templates: self.json_templates(),
+                max_parallel: self.max_parallel,
+                pipeline: self,
+            },
+        )?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    /// `t` as emitted: with its env files, [`Task::inputs_from_all`]
+    /// expanded, and then [`TaskDefaults`] filled in where it sets nothing
+    /// itself. Borrowed when none of these apply, e.g. for most bare tasks
+    /// and gates.
+    fn with_defaults<'t>(&self, t: &'t TaskData) -> Cow<'t, TaskData> {
+        let d = &self.defaults;
+        let t = TaskData::without_duplicate_secrets(self.with_all_inputs(t.with_env_files()));
+        if !t.takes_defaults() {
+            return t;
+        }
+        let secrets = self.inherited_secrets(&t);
+        let applies = (d.timeout.is_some() && t.timeout.is_none())
+            || (d.retry.is_some() && t.retry.is_none())
+            || d.env.keys().any(|k| !t.env.contains_key(k))
+            || !secrets.is_empty();
+        if !applies {
+            return t;
+        }
+        let mut t = t.into_owned();
+        if t.timeout.is_none() {
+            t.timeout = d.timeout;
+        }
+        if t.retry.is_none() {
+            t.retry = d.retry.clone();
+        }
+        for (k, v) in &d.env {
+            t.env.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+        for s in secrets {
+            match s.secret_ref {
+                Some(ref sr) => t.secret_refs.push(sr.clone()),
+                None => t.secrets.push(s.name.clone()),
+            }
+        }
+        Cow::Owned(t)
+    }
+
+    /// Pipeline secrets `t` picks up: those matching it, minus names the
+    /// task declares, the last declaration winning per name.
+    fn inherited_secrets(&self, t: &TaskData) -> Vec<&SharedSecret> {
+        if !t.takes_defaults() {
+            return Vec::new();
+        }
+        let mut inherited: Vec<&SharedSecret> = Vec::new();
+        for s in self.defaults.secrets.iter().rev() {
+            let matches = s
+                .pattern
+                .as_deref()
+                .map_or(true, |p| condition::glob_matches(p, &t.name));
+            if matches && !t.declares_secret(&s.name) && !inherited.iter().any(|i| i.name == s.name)
+            {
+                inherited.push(s);
+            }
+        }
+        inherited.reverse();
+        inherited
+    }
+
+    /// `t` with one input per output of each [`Task::inputs_from_all`]
+    /// producer, in output name order.
+    fn with_all_inputs<'t>(&self, t: Cow<'t, TaskData>) -> Cow<'t, TaskData> {
+        if t.inputs_from_all.is_empty() {
+            return t;
+        }
+        let mut t = t.into_owned();
+        for (from, base) in t.inputs_from_all.clone() {
+            let Some(producer) = self.tasks.iter().find(|p| p.name == from) else {
+                continue;
+            };
+            let mut outputs: Vec<&String> = producer.outputs.keys().collect();
+            outputs.sort();
+            for output in outputs {
+                if t.task_inputs
+                    .iter()
+                    .any(|ti| ti.from_task == from && ti.output == *output)
+                {
+                    continue;
+                }
+                let mut dest_path = format!("{}/{}", base.trim_end_matches('/'), output);
+                if producer.output_kinds.get(output) == Some(&OutputKind::Glob) {
+                    dest_path.push('/');
+                }
+                t.task_inputs.push(TaskInput {
+                    from_task: from.clone(),
+                    output: output.clone(),
+                    dest_path,
+                    matrix: BTreeMap::new(),
+                });
+            }
+        }
+        Cow::Owned(t)
+    }
+
+    /// Pipeline defaults `t` picks up, for explain, e.g. `timeout 15m`.
+    fn pipeline_defaults_for(&self, t: &TaskData) -> Vec<String> {
+        let resolved = self.with_defaults(t);
+        let t = &*t.with_env_files();
+        let mut applied = Vec::new();
+        if let (None, Some(secs)) = (t.timeout, resolved.timeout) {
+            applied.push(format!("timeout {}", format_duration(secs)));
+        }
+        if let (None, Some(retry)) = (&t.retry, &resolved.retry) {
+            applied.push(format!("retry {}", retry.max));
+        }
+        let mut env: Vec<String> = resolved
+            .env
+            .iter()
+            .filter(|(k, _)| !t.env.contains_key(*k))
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        env.sort();
+        applied.extend(env);
+        applied
+    }
+
+    /// Merges pipeline K8s defaults, then template options, with the task's
+    /// own options.
+    fn merged_k8s(&self, t: &TaskData) -> Option<K8sOptions> {
+        let defaults = if t.bare { &None } else { &self.k8s_defaults };
+        [defaults, &t.template_k8s, &t.k8s_options]
+            .into_iter()
+            .flatten()
+            .fold(None, |acc, opts| match acc {
+                None => Some(opts.clone()),
+                Some(acc) => Some(K8sOptions::merge(&acc, opts)),
+            })
+    }
+
+    /// Returns true if the pipeline uses containers, mounts or resources.
+    fn has_v2_features(&self) -> bool {
+        !self.dirs.is_empty()
+            || !self.caches.is_empty()
+            || !self.git_sources.is_empty()
+            || !self.http_files.is_empty()
+            || !self.files.is_empty()
+            || self
+                .tasks
+                .iter()
+                .any(|t| t.container.is_some() || !t.mounts.is_empty())
+    }
+
+    /// Detects the schema version based on usage.
+    fn emit_version(&self) -> &'static str {
+        let has_v3_features = self
+            .tasks
+            .iter()
+            .any(|t| t.task_type.is_some() || !t.success_criteria.is_empty());
+
+        if has_v3_features {
+            "3"
+        } else if self.has_v2_features() {
+            "2"
+        } else {
+            "1"
+        }
+    }
+
+    /// Builds the `resources` map, or None when no v2 features are used.
+    fn json_resources(&self) -> Option<BTreeMap<String, JsonResource>> {
+        if !self.has_v2_features() {
+            return None;
+        }
+        let mut resources = BTreeMap::new();
+        for d in self.directories() {
+            resources.insert(
+                d.id(),
+                JsonResource {
+                    type_: "directory".to_string(),
+                    path: Some(d.path.clone()),
+                    name: None,
+                    globs: if d.globs.is_empty() {
+                        None
+                    } else {
+                        Some(d.globs.clone())
+                    },
+                    ..Default::default()
+                },
+            );
+        }
+        for c in self.caches() {
+            resources.insert(
+                c.id(),
+                JsonResource {
+                    type_: "cache".to_string(),
+                    name: Some(c.name.clone()),
+                    key: c.key.clone(),
+                    key_files: (!c.key_files.is_empty()).then(|| c.key_files.clone()),
+                    scope: c.scope.map(|s| s.as_str()),
+                    fallback: c.fallback.map(|s| s.as_str()),
+                    max_size: c.max_size.clone(),
+                    eviction: c.eviction.map(|e| e.as_str()),
+                    ..Default::default()
+                },
+            );
+        }
+        for g in &self.git_sources {
+            resources.insert(
+                g.id(),
+                JsonResource {
+                    type_: "git".to_string(),
+                    url: Some(g.url.clone()),
+                    ref_: g.reference().map(str::to_string),
+                    depth: g.depth,
+                    ..Default::default()
+                },
+            );
+        }
+        for h in &self.http_files {
+            resources.insert(
+                h.id(),
+                JsonResource {
+                    type_: "http".to_string(),
+                    url: Some(h.url.clone()),
+                    sha256: h.sha256.clone(),
+                    unpack: h.unpack.then_some(true),
+                    ..Default::default()
+                },
+            );
+        }
+        for f in &self.files {
+            resources.insert(
+                f.id(),
+                JsonResource {
+                    type_: "file".to_string(),
+                    path: Some(f.path.clone()),
+                    hash: Some("sha256"),
+                    ..Default::default()
+                },
+            );
+        }
+        // Only include resources if non-empty (matches Go SDK behavior)
+        if resources.is_empty() {
+            None
+        } else {
+            Some(resources)
+        }
+    }
+
+    /// Builds the `templates` map, or None when no templates are registered.
+    fn json_templates(&self) -> Option<BTreeMap<String, JsonTemplate>> {
+        if self.templates.is_empty() {
+            return None;
+        }
+        let templates = self
+            .templates
+            .iter()
+            .map(|t| {
+                let json = JsonTemplate {
+                    container: t.container.clone(),
+                    workdir: t.workdir.clone(),
+                    env: if t.env.is_empty() {
+                        None
+                    } else {
+                        Some(t.env.clone().into_iter().collect())
+                    },
+                    mounts: if t.mounts.is_empty() {
+                        None
+                    } else {
+                        Some(t.mounts.iter().map(JsonMount::from).collect())
+                    },
+                    inputs: if t.inputs.is_empty() {
+                        None
+                    } else {
+                        Some(t.inputs.clone())
+                    },
+                    secrets: if t.secrets.is_empty() {
+                        None
+                    } else {
+                        Some(t.secrets.clone())
+                    },
+                    retry: t.retry.as_ref().map(json_retry),
+                    timeout: t.timeout,
+                    k8s: t
+                        .k8s
+                        .as_ref()
+                        .filter(|o| !o.is_empty())
+                        .map(|o| convert_k8s_options(o, None)),
+                };
+                (t.name.clone().unwrap_or_default(), json)
+            })
+            .collect();
+        Some(templates)
+    }
+
+    /// Builds the complete JSON document in memory.
+    fn json_pipeline(&self) -> JsonPipeline {
+        JsonPipeline {
+            version: self.emit_version().to_string(),
+            requires: self.json_requires(),
+            resources: self.json_resources(),
+            templates: self.json_templates(),
+            max_parallel: self.max_parallel,
+            tasks: self.tasks.iter().map(|t| self.json_task(t)).collect(),
+        }
+    }
+
+    /// Converts one task to its JSON representation.
+    /// Names of the variants of matrix task `task` that any of `selectors`
+    /// picks, in variant order, as runners name them; just `task` without
+    /// selectors or when none match.
+    fn variant_names(
+        &self,
+        task: &str,
+        selectors: Option<&Vec<BTreeMap<String, String>>>,
+    ) -> Vec<String> {
+        let picked: Vec<String> = match (selectors, self.tasks.iter().find(|t| t.name == task)) {
+            (Some(selectors), Some(producer)) => producer
+                .matrix
+                .variants()
+                .iter()
+                .filter(|v| selectors.iter().any(|m| v.matches(m)))
+                .map(|v| v.task_name(task))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if picked.is_empty() {
+            vec![task.to_string()]
+        } else {
+            picked
+        }
+    }
+
+    fn json_task(&self, t: &TaskData) -> JsonTask {
+        let t = &*self.with_defaults(t);
+        JsonTask {
+            name: t.name.clone(),
+            description: t.description.clone(),
+            tags: (!t.tags.is_empty()).then(|| t.tags.clone()),
+            kind: if t.kind == NodeKind::Review {
+                Some("review".to_string())
+            } else {
+                None
+            },
+            template: t.template.clone(),
+            task_type: if t.kind == NodeKind::Review {
+                None
+            } else {
+                t.task_type.as_ref().map(|tt| tt.as_str().to_string())
+            },
+            success_criteria: if t.kind == NodeKind::Review || t.success_criteria.is_empty() {
+                None
+            } else {
+                Some(
+                    t.success_criteria
+                        .iter()
+                        .map(SuccessCriterion::to_json)
+                        .collect(),
+                )
+            },
+            command: if t.kind == NodeKind::Review || t.script().is_empty() {
+                None
+            } else {
+                Some(t.script())
+            },
+            args: if t.kind == NodeKind::Review || t.args.is_empty() {
+                None
+            } else {
+                Some(t.args.clone())
+            },
+            steps: if t.kind == NodeKind::Review || t.steps.is_empty() {
+                None
+            } else {
+                Some(t.steps.clone())
+            },
+            primitive: if t.kind == NodeKind::Review {
+                t.primitive.clone()
+            } else {
+                None
+            },
+            agent: if t.kind == NodeKind::Review {
+                t.agent.clone()
+            } else {
+                None
+            },
+            context: if t.kind == NodeKind::Review && !t.context.is_empty() {
+                Some(t.context.clone())
+            } else {
+                None
+            },
+            deterministic: if t.kind == NodeKind::Review {
+                Some(t.deterministic)
+            } else {
+                None
+            },
+            container: t.container.clone(),
+            container_build: t.container_build.as_ref().map(|cb| JsonContainerBuild {
+                context_resource: cb.context_resource.clone(),
+                dockerfile: cb.dockerfile.clone(),
+            }),
+            workdir: t.workdir.clone(),
+            entrypoint: t.entrypoint.clone(),
+            user: t.user.clone(),
+            env: if t.env.is_empty() {
+                None
+            } else {
+                Some(t.env.clone().into_iter().collect())
+            },
+            env_files: (!t.runtime_env_files.is_empty()).then(|| t.runtime_env_files.clone()),
+            mounts: if t.mounts.is_empty() {
+                None
+            } else {
+                Some(t.mounts.iter().map(JsonMount::from).collect())
+            },
+            inputs: if t.inputs.is_empty() {
+                None
+            } else {
+                Some(t.inputs.clone())
+            },
+            task_inputs: if t.task_inputs.is_empty() {
+                None
+            } else {
+                Some(
+                    t.task_inputs
+                        .iter()
+                        .map(|ti| JsonTaskInput {
+                            from_task: if ti.matrix.is_empty() {
+                                ti.from_task.clone()
+                            } else {
+                                self.variant_names(&ti.from_task, Some(&vec![ti.matrix.clone()]))
+                                    .remove(0)
+                            },
+                            output: ti.output.clone(),
+                            dest: ti.dest_path.clone(),
+                        })
+                        .collect(),
+                )
+            },
+            outputs: if t.kind == NodeKind::Review || t.outputs.is_empty() {
+                None
+            } else {
+                Some(
+                    t.outputs
+                        .iter()
+                        .map(|(name, path)| {
+                            let kind = t.output_kinds.get(name);
+                            let options = t.output_options.get(name);
+                            let output = if kind.is_none() && options.is_none() {
+                                JsonOutput::Path(path.clone())
+                            } else {
+                                let options = options.cloned().unwrap_or_default();
+                                JsonOutput::Rich {
+                                    path: path.clone(),
+                                    type_: kind.map_or("file", OutputKind::as_str),
+                                    retention_days: options.retention_days,
+                                    compress: options.compress.then_some(true),
+                                    required: (!options.required).then_some(false),
+                                }
+                            };
+                            (name.clone(), output)
+                        })
+                        .collect(),
+                )
+            },
+            depends_on: if t.depends_on.is_empty() {
+                None
+            } else {
+                Some(
+                    t.depends_on
+                        .iter()
+                        .flat_map(|dep| self.variant_names(dep, t.dep_selectors.get(dep)))
+                        .collect(),
+                )
+            },
+            depends_on_meta: t
+                .json_depends_on_meta(|dep| self.variant_names(dep, t.dep_selectors.get(dep))),
+            condition: t.effective_condition(),
+            secrets: if t.secrets.is_empty() {
+                None
+            } else {
+                Some(t.secrets.clone())
+            },
+            secret_refs: if t.secret_refs.is_empty() {
+                None
+            } else {
+                Some(t.secret_refs.iter().map(SecretRef::to_json).collect())
+            },
+            publish: if t.publish.is_empty() {
+                None
+            } else {
+                Some(
+                    t.publish
+                        .iter()
+                        .map(|(output, dest)| dest.to_json(output))
+                        .collect(),
+                )
+            },
+            secret_templates: if t.secret_templates.is_empty() {
+                None
+            } else {
+                Some(
+                    t.secret_templates
+                        .iter()
+                        .map(|st| JsonSecretTemplate {
+                            dest: st.dest.clone(),
+                            template: st.template.clone(),
+                            refs: st.refs.iter().map(SecretRef::to_json).collect(),
+                        })
+                        .collect(),
+                )
+            },
+            matrix: if t.matrix.is_empty() {
+                None
+            } else {
+                Some(t.matrix.clone())
+            },
+            matrix_exclude: (!t.matrix.excludes().is_empty()).then(|| t.matrix.excludes().to_vec()),
+            matrix_include: (!t.matrix.includes().is_empty()).then(|| t.matrix.includes().to_vec()),
+            services: if t.services.is_empty() {
+                None
+            } else {
+                Some(
+                    t.services
+                        .iter()
+                        .map(|s| JsonService {
+                            image: s.image.clone(),
+                            name: s.name.clone(),
+                            env: (!s.env.is_empty()).then(|| s.env.clone()),
+                            ports: (!s.ports.is_empty()).then(|| s.ports.clone()),
+                            command: s.command.clone(),
+                            readiness: s.ready_probes.first().map(|probe| {
+                                let (command, port, path) = match probe {
+                                    ReadyProbe::Cmd(command) => (Some(command.clone()), None, None),
+                                    ReadyProbe::Tcp(port) => (None, Some(*port), None),
+                                    ReadyProbe::Http { path, port } => {
+                                        (None, Some(*port), Some(path.clone()))
+                                    }
+                                };
+                                JsonReadiness {
+                                    type_: probe.type_name(),
+                                    command,
+                                    port,
+                                    path,
+                                    timeout: s.ready_timeout,
+                                }
+                            }),
+                        })
+                        .collect(),
+                )
+            },
+            retry: t.retry.as_ref().map(json_retry),
+            timeout: t.timeout,
+            estimated_duration: t.estimated_duration,
+            k8s: {
+                let merged = self.merged_k8s(t);
+                // Include k8s options if we have either structured opts or raw JSON
+                if merged.as_ref().is_some_and(|o| !o.is_empty()) || t.k8s_raw.is_some() {
+                    Some(convert_k8s_options(
+                        merged.as_ref().unwrap_or(&K8sOptions::default()),
+                        t.k8s_raw.as_ref(),
+                    ))
+                } else {
+                    None
+                }
+            },
+            requires: if t.requires.is_empty() {
+                None
+            } else {
+                Some(t.requires.clone())
+            },
+            provides: if t.provides.is_empty() {
+                None
+            } else {
+                Some(
+                    t.provides
+                        .iter()
+                        .map(|(name, value)| JsonProvide {
+                            name: name.clone(),
+                            value: value.clone(),
+                        })
+                        .collect(),
+                )
+            },
+            needs: if t.needs.is_empty() {
+                None
+            } else {
+                Some(t.needs.clone())
+            },
+            semantic: {
+                let s = &t.semantic;
+                if s.covers.is_empty() && s.intent.is_none() && s.criticality.is_none() {
+                    None
+                } else {
+                    Some(JsonSemantic {
+                        covers: if s.covers.is_empty() {
+                            None
+                        } else {
+                            Some(s.covers.clone())
+                        },
+                        intent: s.intent.clone(),
+                        criticality: s.criticality.as_ref().map(|c| match c {
+                            Criticality::High => "high".to_string(),
+                            Criticality::Medium => "medium".to_string(),
+                            Criticality::Low => "low".to_string(),
+                        }),
+                    })
+                }
+            },
+            ai_hooks: {
+                let h = &t.ai_hooks;
+                if h.on_fail.is_none() && h.select.is_none() {
+                    None
+                } else {
+                    Some(JsonAiHooks {
+                        on_fail: h.on_fail.as_ref().map(|a| match a {
+                            OnFailAction::Analyze => "analyze".to_string(),
+                            OnFailAction::Retry => "retry".to_string(),
+                            OnFailAction::Skip => "skip".to_string(),
+                        }),
+                        select: h.select.as_ref().map(|s| match s {
+                            SelectMode::Smart => "smart".to_string(),
+                            SelectMode::Always => "always".to_string(),
+                            SelectMode::Manual => "manual".to_string(),
+                        }),
+                    })
+                }
+            },
+            gate: t.gate.as_ref().map(|g| JsonGate {
+                strategy: g.strategy.clone(),
+                timeout: if g.timeout > 0 { Some(g.timeout) } else { None },
+                message: g.message.clone(),
+                env_var: g.env_var.clone(),
+                file_path: g.file_path.clone(),
+            }),
+            manual: t.manual.then_some(true),
+            allow_failure: t.allow_failure.then_some(true),
+            concurrency: t.concurrency.as_ref().map(|c| JsonConcurrency {
+                group: c.group.clone(),
+                cancel_in_progress: c.cancel_in_progress.then_some(true),
+            }),
+            verify: t.verify.clone(),
+        }
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// =============================================================================
+// RUST PRESET
+// =============================================================================
+
+/// Convenience methods for Rust projects.
+pub struct RustPreset<'a> {
+    pipeline: &'a mut Pipeline,
+}
+
+impl<'a> RustPreset<'a> {
+    /// Standard input patterns for Rust projects.
+    pub fn inputs() -> Vec<&'static str> {
+        vec!["**/*.rs", "Cargo.toml", "Cargo.lock"]
+    }
+
+    /// Adds a "cargo test" task.
+    pub fn test(self) -> Task<'a> {
+        self.pipeline
+            .task("test")
+            .run("cargo test")
+            .inputs(&Self::inputs())
+    }
+
+    /// Adds a "cargo clippy" task.
+    pub fn lint(self) -> Task<'a> {
+        self.pipeline
+            .task("lint")
+            .run("cargo clippy -- -D warnings")
+            .inputs(&Self::inputs())
+    }
+
+    /// Adds a "cargo build --release" task.
+    pub fn build(self, output: &str) -> Task<'a> {
+        self.pipeline
+            .task("build")
+            .run("cargo build --release")
+            .inputs(&Self::inputs())
+            .outputs(&[output])
+    }
+
+    /// [`RustPreset::inputs`] plus a tool's own config files.
+    fn inputs_with(extra: &[&'static str]) -> Vec<&'static str> {
+        let mut inputs = Self::inputs();
+        inputs.extend_from_slice(extra);
+        inputs
+    }
+
+    /// Adds a "cargo fmt --check" task, which also watches `rustfmt.toml`.
+    pub fn fmt(self) -> Task<'a> {
+        self.pipeline
+            .task("fmt")
+            .run("cargo fmt --check")
+            .inputs(&Self::inputs_with(&["rustfmt.toml", ".rustfmt.toml"]))
+    }
+
+    /// Adds a "cargo audit" task, which also watches `.cargo/audit.toml`.
+    pub fn audit(self) -> Task<'a> {
+        self.pipeline
+            .task("audit")
+            .run("cargo audit")
+            .inputs(&Self::inputs_with(&[".cargo/audit.toml"]))
+    }
+
+    /// Adds a "cargo doc --no-deps" task with `target/doc` as its `docs`
+    /// directory output.
+    pub fn doc(self) -> Task<'a> {
+        self.pipeline
+            .task("doc")
+            .run("cargo doc --no-deps")
+            .inputs(&Self::inputs())
+            .output_dir("docs", "target/doc")
+    }
+
+    /// Adds a "cargo llvm-cov" task writing an lcov report to its `lcov`
+    /// output, `lcov.info`.
+    pub fn coverage(self) -> Task<'a> {
+        self.pipeline
+            .task("coverage")
+            .run("cargo llvm-cov --lcov --output-path lcov.info")
+            .inputs(&Self::inputs())
+            .output("lcov", "lcov.info")
+    }
+
+    /// Adds fmt, lint, test and audit tasks and returns them as the
+    /// `rust-checks` group, e.g. for a build to run
+    /// [after](Task::after_group).
+    ///
+    /// # Example
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// let checks = p.rust().all_checks();
+    /// p.rust().build("target/release/app").after_group(&checks);
+    ///
+    /// assert_eq!(checks.names(), ["fmt", "lint", "test", "audit"]);
+    /// ```
+    pub fn all_checks(self) -> TaskGroup {
+        let p = self.pipeline;
+        p.rust().fmt();
+        p.rust().lint();
+        p.rust().test();
+        p.rust().audit();
+        p.group("rust-checks", &["fmt", "lint", "test", "audit"])
+    }
+}
+
+/// Task name for a preset step: `<prefix>-<step>`, or just `<step>` without
+/// a prefix.
+fn preset_task_name(prefix: &str, step: &str) -> String {
+    if prefix.is_empty() {
+        step.to_string()
+    } else {
+        format!("{}-{}", prefix, step)
+    }
+}
+
+// =============================================================================
+// NODE PRESET
+// =============================================================================
+
+/// Convenience methods for Node.js projects using npm.
+///
+/// # Example
+/// ```rust
+/// use sykli::Pipeline;
+///
+/// let mut p = Pipeline::new();
+/// p.node().prefix("web").install();
+/// p.node().prefix("web").test().after(&["web-install"]);
+///
+/// let json = p.to_json_value().unwrap();
+/// assert_eq!(json["tasks"][1]["name"], "web-test");
+/// ```
+pub struct NodePreset<'a> {
+    pipeline: &'a mut Pipeline,
+    prefix: String,
+}
+
+impl<'a> NodePreset<'a> {
+    /// Standard input patterns for Node.js projects.
+    pub fn inputs() -> Vec<&'static str> {
+        vec!["**/*.ts", "**/*.js", "package.json", "package-lock.json"]
+    }
+
+    /// Names tasks `<prefix>-<step>`, e.g. `web-test`, instead of
+    /// `node-test`. An empty prefix gives bare names like `test`.
+    #[must_use]
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    fn task(self, step: &str) -> Task<'a> {
+        let name = preset_task_name(&self.prefix, step);
+        self.pipeline.task(&name)
+    }
+
+    /// Adds an "npm ci" task, named `<prefix>-install`.
+    pub fn install(self) -> Task<'a> {
+        self.task("install")
+            .run("npm ci")
+            .inputs(&["package.json", "package-lock.json"])
+    }
+
+    /// Adds an "npm test" task, named `<prefix>-test`.
+    pub fn test(self) -> Task<'a> {
+        self.task("test").run("npm test").inputs(&Self::inputs())
+    }
+
+    /// Adds an "eslint" task, named `<prefix>-lint`.
+    pub fn lint(self) -> Task<'a> {
+        self.task("lint")
+            .run("npx eslint .")
+            .inputs(&Self::inputs())
+    }
+
+    /// Adds an "npm run build" task writing to `outdir`, named
+    /// `<prefix>-build`.
+    pub fn build(self, outdir: &str) -> Task<'a> {
+        self.task("build")
+            .run("npm run build")
+            .inputs(&Self::inputs())
+            .outputs(&[outdir])
+    }
+}
+
+// =============================================================================
+// GO PRESET
+// =============================================================================
+
+/// Convenience methods for Go projects.
+pub struct GoPreset<'a> {
+    pipeline: &'a mut Pipeline,
+    prefix: String,
+}
+
+impl<'a> GoPreset<'a> {
+    /// Standard input patterns for Go projects.
+    pub fn inputs() -> Vec<&'static str> {
+        vec!["**/*.go", "go.mod", "go.sum"]
+    }
+
+    /// Names tasks `<prefix>-<step>`, e.g. `api-test`, instead of
+    /// `go-test`. An empty prefix gives bare names like `test`.
+    #[must_use]
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    fn task(self, step: &str) -> Task<'a> {
+        let name = preset_task_name(&self.prefix, step);
+        self.pipeline.task(&name)
+    }
+
+    /// Adds a "go test ./..." task, named `<prefix>-test`.
+    pub fn test(self) -> Task<'a> {
+        self.task("test")
+            .run("go test ./...")
+            .inputs(&Self::inputs())
+    }
+
+    /// Adds a "go vet ./..." task, named `<prefix>-vet`.
+    pub fn vet(self) -> Task<'a> {
+        self.task("vet").run("go vet ./...").inputs(&Self::inputs())
+    }
+
+    /// Adds a "go build" task for the main package, writing the binary to
+    /// `output`; named `<prefix>-build`.
+    pub fn build(self, output: &str) -> Task<'a> {
+        self.task("build")
+            .run(&format!("go build -o {} .", output))
+            .inputs(&Self::inputs())
+            .outputs(&[output])
+    }
+}
+
+// =============================================================================
+// CYCLE DETECTION
+// =============================================================================
+
+/// Color for DFS cycle detection
+#[derive(Clone, Copy, PartialEq)]
+enum Color {
+    White, // unvisited
+    Gray,  // currently visiting (in recursion stack)
+    Black, // completely processed
+}
+
+impl Pipeline {
+    /// Detects cycles in the task dependency graph using DFS.
+    /// Returns the cycle path if found, None otherwise.
+    fn detect_cycle(&self) -> Option<Vec<String>> {
+        // Build adjacency map: task name -> dependencies
+        let deps: HashMap<&str, Vec<&str>> = self
+            .tasks
+            .iter()
+            .map(|t| {
+                (
+                    t.name.as_str(),
+                    t.depends_on.iter().map(|s| s.as_str()).collect(),
+                )
+            })
+            .collect();
+
+        let mut color: HashMap<&str, Color> = self
+            .tasks
+            .iter()
+            .map(|t| (t.name.as_str(), Color::White))
+            .collect();
+
+        let mut parent: HashMap<&str, &str> = HashMap::new();
+
+        // DFS from each unvisited node
+        for task in &self.tasks {
+            if color[task.name.as_str()] == Color::White {
+                if let Some(cycle) =
+                    self.dfs_detect_cycle(task.name.as_str(), &deps, &mut color, &mut parent)
+                {
+                    return Some(cycle);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Performs DFS and returns cycle path if found.
+    fn dfs_detect_cycle<'a>(
+        &self,
+        node: &'a str,
+        deps: &HashMap<&'a str, Vec<&'a str>>,
+        color: &mut HashMap<&'a str, Color>,
+        parent: &mut HashMap<&'a str, &'a str>,
+    ) -> Option<Vec<String>> {
+        color.insert(node, Color::Gray);
+
+        if let Some(node_deps) = deps.get(node) {
+            for &dep in node_deps {
+                if color.get(dep) == Some(&Color::Gray) {
+                    // Found a cycle - reconstruct the path
+                    return Some(self.reconstruct_cycle(node, dep, parent));
+                }
+                if color.get(dep) == Some(&Color::White) {
+                    parent.insert(dep, node);
+                    if let Some(cycle) = self.dfs_detect_cycle(dep, deps, color, parent) {
+                        return Some(cycle);
+                    }
+                }
+            }
+        }
+
+        color.insert(node, Color::Black);
+        None
+    }
+
+    /// Reconstructs the cycle path from the detected back edge.
+    fn reconstruct_cycle(&self, from: &str, to: &str, parent: &HashMap<&str, &str>) -> Vec<String> {
+        // Cycle: to -> ... -> from -> to
+        let mut cycle = vec![to.to_string()];
+        let mut current = from;
+        while current != to {
+            cycle.insert(0, current.to_string());
+            current = parent.get(current).unwrap_or(&to);
+        }
+        cycle.insert(0, to.to_string()); // Close the cycle
+        cycle
+    }
+}
+
+// =============================================================================
+// JSON SERIALIZATION
+// =============================================================================
+
+#[derive(Serialize)]
+struct JsonPipeline {
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requires: Option<features::JsonRequires>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<BTreeMap<String, JsonResource>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    templates: Option<BTreeMap<String, JsonTemplate>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_parallel: Option<usize>,
+    tasks: Vec<JsonTask>,
+}
+
+/// Serializes like [`JsonPipeline`] but converts tasks lazily, one at a time.
+struct StreamingPipeline<'a> {
+    version: &'static str,
+    requires: Option<features::JsonRequires>,
+    resources: Option<BTreeMap<String, JsonResource>>,
+    templates: Option<BTreeMap<String, JsonTemplate>>,
+    max_parallel: Option<usize>,
+    pipeline: &'a Pipeline,
+}
+
+impl Serialize for StreamingPipeline<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = 2
+            + usize::from(self.requires.is_some())
+            + usize::from(self.resources.is_some())
+            + usize::from(self.templates.is_some())
+            + usize::from(self.max_parallel.is_some());
+        let mut state = serializer.serialize_struct("JsonPipeline", len)?;
+        state.serialize_field("version", self.version)?;
+        match self.requires {
+            Some(ref requires) => state.serialize_field("requires", requires)?,
+            None => state.skip_field("requires")?,
+        }
+        match self.resources {
+            Some(ref resources) => state.serialize_field("resources", resources)?,
+            None => state.skip_field("resources")?,
+        }
+        match self.templates {
+            Some(ref templates) => state.serialize_field("templates", templates)?,
+            None => state.skip_field("templates")?,
+        }
+        match self.max_parallel {
+            Some(n) => state.serialize_field("max_parallel", &n)?,
+            None => state.skip_field("max_parallel")?,
+        }
+        state.serialize_field("tasks", &StreamingTasks(self.pipeline))?;
+        state.end()
+    }
+}
+
+struct StreamingTasks<'a>(&'a Pipeline);
+
+impl Serialize for StreamingTasks<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.tasks.len()))?;
+        for t in &self.0.tasks {
+            seq.serialize_element(&self.0.json_task(t))?;
+        }
+        seq.end()
+    }
+}
+
+#[derive(Serialize, Default)]
+struct JsonResource {
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    globs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_files: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fallback: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eviction: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    ref_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depth: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unpack: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct JsonTaskInput {
+    from_task: String,
+    output: String,
+    dest: String,
+}
+
+#[derive(Serialize)]
+struct JsonDependency {
+    task: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_when: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct JsonSuccessCriterion {
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    equals: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonSecretRef {
+    name: String,
+    source: String,
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mount_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optional: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mask: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct JsonPublish {
+    output: String,
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<JsonSecretSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth: Option<JsonSecretSource>,
+}
+
+#[derive(Serialize)]
+struct JsonSecretSource {
+    source: String,
+    key: String,
+}
+
+impl From<&SecretRef> for JsonSecretSource {
+    fn from(sr: &SecretRef) -> Self {
+        JsonSecretSource {
+            source: sr.source.as_str().to_string(),
+            key: sr.key.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonContainerBuild {
+    context_resource: String,
+    dockerfile: String,
+}
+
+#[derive(Serialize)]
+struct JsonSecretTemplate {
+    dest: String,
+    template: String,
+    refs: Vec<JsonSecretRef>,
+}
+
+#[derive(Serialize)]
+struct JsonProvide {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonSemantic {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    covers: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    intent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    criticality: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonAiHooks {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on_fail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    select: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonTask {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    success_criteria: Option<Vec<JsonSuccessCriterion>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    steps: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    primitive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deterministic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container_build: Option<JsonContainerBuild>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workdir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entrypoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env_files: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mounts: Option<Vec<JsonMount>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inputs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_inputs: Option<Vec<JsonTaskInput>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outputs: Option<BTreeMap<String, JsonOutput>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depends_on: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depends_on_meta: Option<Vec<JsonDependency>>,
+    #[serde(rename = "when", skip_serializing_if = "Option::is_none")]
+    condition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secrets: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_refs: Option<Vec<JsonSecretRef>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_templates: Option<Vec<JsonSecretTemplate>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publish: Option<Vec<JsonPublish>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix: Option<matrix::Matrix>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix_exclude: Option<Vec<BTreeMap<String, String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix_include: Option<Vec<BTreeMap<String, String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    services: Option<Vec<JsonService>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry: Option<JsonRetry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_duration: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    k8s: Option<JsonK8sOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requires: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provides: Option<Vec<JsonProvide>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    needs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    semantic: Option<JsonSemantic>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ai_hooks: Option<JsonAiHooks>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gate: Option<JsonGate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manual: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_failure: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    concurrency: Option<JsonConcurrency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verify: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JsonConcurrency {
+    group: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cancel_in_progress: Option<bool>,
+}
+
+/// A plain path for single files with default options, which every engine
+/// version reads.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonOutput {
+    Path(String),
+    Rich {
+        path: String,
+        #[serde(rename = "type")]
+        type_: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retention_days: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        compress: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        required: Option<bool>,
+    },
+}
+
+/// A bare count when only `max` is set, which every engine version reads.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonRetry {
+    Count(u32),
+    Policy {
+        max: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        backoff: Option<JsonBackoff>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_on_exit_codes: Option<Vec<i32>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_on_timeout: Option<bool>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonBackoff {
+    Fixed { secs: u32 },
+    Exponential { base_secs: u32, max_secs: u32 },
+}
+
+fn json_retry(policy: &RetryPolicy) -> JsonRetry {
+    if !policy.is_structured() {
+        return JsonRetry::Count(policy.max);
+    }
+    JsonRetry::Policy {
+        max: policy.max,
+        backoff: match policy.backoff {
+            Backoff::Immediate => None,
+            Backoff::Fixed { secs } => Some(JsonBackoff::Fixed { secs }),
+            Backoff::Exponential {
+                base_secs,
+                max_secs,
+            } => Some(JsonBackoff::Exponential {
+                base_secs,
+                max_secs,
+            }),
+        },
+        retry_on_exit_codes: policy.retry_on_exit_codes.clone(),
+        retry_on_timeout: (!policy.retry_on_timeout).then_some(false),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonGate {
+    strategy: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env_var: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_path: Option<String>,
+}
+
+/// Minimal K8s options for JSON serialization.
+/// For advanced options, use k8s_raw which is passed through as-is.
+#[derive(Serialize)]
+struct JsonK8sOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gpu: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw: Option<String>,
+}
+
+fn convert_k8s_options(opts: &K8sOptions, raw: Option<&String>) -> JsonK8sOptions {
+    JsonK8sOptions {
+        memory: opts.memory.clone(),
+        cpu: opts.cpu.clone(),
+        gpu: opts.gpu,
+        raw: raw.cloned(),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonMount {
+    resource: String,
+    path: String,
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sharing: Option<&'static str>,
+}
+
+impl From<&Mount> for JsonMount {
+    fn from(m: &Mount) -> Self {
+        JsonMount {
+            resource: m.resource.clone(),
+            path: m.path.clone(),
+            type_: m.mount_type.clone(),
+            sharing: (m.sharing != CacheSharing::Shared).then(|| m.sharing.as_str()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workdir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mounts: Option<Vec<JsonMount>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inputs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secrets: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry: Option<JsonRetry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    k8s: Option<JsonK8sOptions>,
+}
+
+#[derive(Serialize)]
+struct JsonService {
+    image: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ports: Option<Vec<u16>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    readiness: Option<JsonReadiness>,
+}
+
+#[derive(Serialize)]
+struct JsonReadiness {
+    #[serde(rename = "type")]
+    type_: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_task() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "1");
+        assert_eq!(json["tasks"][0]["name"], "test");
+        assert_eq!(json["tasks"][0]["command"], "cargo test");
+    }
+
+    #[test]
+    fn test_task_type_serialization() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("go build ./...")
+            .task_type(TaskType::Build);
+        let _ = p
+            .task("test")
+            .run("go test ./...")
+            .task_type(TaskType::Test)
+            .after(&["build"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "3");
+        assert_eq!(json["tasks"][0]["task_type"], "build");
+        assert_eq!(json["tasks"][1]["task_type"], "test");
+    }
+
+    #[test]
+    fn test_task_type_with_v2_features_emits_version_3() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("go test ./...")
+            .task_type(TaskType::Test)
+            .container("golang:1.22");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "3");
+    }
+
+    #[test]
+    fn test_success_criteria_serialization() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("go test ./...").success_criteria(&[
+            SuccessCriterion::ExitCode(0),
+            SuccessCriterion::FileExists("coverage.out".into()),
+        ]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "3");
+        assert_eq!(json["tasks"][0]["success_criteria"][0]["type"], "exit_code");
+        assert_eq!(json["tasks"][0]["success_criteria"][0]["equals"], 0);
+        assert_eq!(
+            json["tasks"][0]["success_criteria"][1]["type"],
+            "file_exists"
+        );
+        assert_eq!(
+            json["tasks"][0]["success_criteria"][1]["path"],
+            "coverage.out"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "multiple exit_code success criteria are not allowed")]
+    fn test_duplicate_exit_code_success_criteria_panics() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("go test ./...")
+            .success_criteria(&[SuccessCriterion::ExitCode(0), SuccessCriterion::ExitCode(1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "exit_code success criterion must be between 0 and 255")]
+    fn test_exit_code_success_criteria_range_panics() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("go test ./...")
+            .success_criteria(&[SuccessCriterion::ExitCode(256)]);
+    }
+
+    #[test]
+    fn test_task_with_dependencies() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+        p.task("build").run("cargo build").after(&["test"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][1]["depends_on"][0], "test");
+    }
+
+    #[test]
+    fn test_review_node_serialization() {
+        let mut p = Pipeline::new();
+        p.task("test").run("go test ./...");
+        p.review("review-code")
+            .primitive("lint")
+            .agent("claude")
+            .context(&["src/**/*.go"])
+            .after(&["test"])
+            .deterministic(true);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let review = &json["tasks"][1];
+
+        assert_eq!(review["name"], "review-code");
+        assert_eq!(review["kind"], "review");
+        assert_eq!(review["primitive"], "lint");
+        assert_eq!(review["agent"], "claude");
+        assert_eq!(review["context"][0], "src/**/*.go");
+        assert_eq!(review["depends_on"][0], "test");
+        assert_eq!(review["deterministic"], true);
+        assert!(review.get("command").is_none());
+        assert!(review.get("outputs").is_none());
+    }
+
+    #[test]
+    fn test_review_node_does_not_require_command() {
+        let mut p = Pipeline::new();
+        p.review("review-code").primitive("lint");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+    }
+
+    #[test]
+    fn test_review_node_requires_primitive() {
+        let mut p = Pipeline::new();
+        p.review("review-code");
+
+        assert!(matches!(
+            emit_err(&p),
+            EmitError::MissingPrimitive { review } if review == "review-code"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "review name cannot be empty")]
+    fn test_review_empty_name_panics() {
+        let mut p = Pipeline::new();
+        p.review("");
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn test_review_duplicate_name_panics() {
+        let mut p = Pipeline::new();
+        p.task("review-code").run("echo test");
+        p.review("review-code");
+    }
+
+    #[test]
+    #[should_panic(expected = "review primitive cannot be empty")]
+    fn test_review_empty_primitive_panics() {
+        let mut p = Pipeline::new();
+        p.review("review-code").primitive("");
+    }
+
+    #[test]
+    fn test_container_task() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+
+        p.task("test")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "2");
+        assert_eq!(json["tasks"][0]["container"], "rust:1.75");
+        assert_eq!(json["resources"]["src:."]["type"], "directory");
+    }
+
+    #[test]
+    fn test_cache_mount() {
+        let mut p = Pipeline::new();
+        let cache = p.cache("cargo-registry");
+
+        p.task("build")
+            .container("rust:1.75")
+            .mount_cache(&cache, "/usr/local/cargo/registry")
+            .run("cargo build");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["resources"]["cargo-registry"]["type"], "cache");
+        assert_eq!(json["tasks"][0]["mounts"][0]["type"], "cache");
+    }
+
+    #[test]
+    fn test_rust_preset() {
+        let mut p = Pipeline::new();
+        p.rust().test();
+        p.rust().build("target/release/app").after(&["test"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["name"], "test");
+        assert_eq!(json["tasks"][0]["command"], "cargo test");
+        assert_eq!(json["tasks"][1]["name"], "build");
+    }
+
+    #[test]
+    #[should_panic(expected = "task name cannot be empty")]
+    fn test_empty_task_name_panics() {
+        let mut p = Pipeline::new();
+        p.task("");
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn test_duplicate_task_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+        p.task("test").run("cargo test");
+    }
+
+    #[test]
+    fn test_unknown_dependency_fails() {
+        let mut p = Pipeline::new();
+        p.task("build").run("cargo build").after(&["nonexistent"]);
+
+        assert!(matches!(
+            emit_err(&p),
+            EmitError::UnknownDependency { task, dep, suggestion: None }
+                if task == "build" && dep == "nonexistent"
+        ));
+    }
+
+    #[test]
+    fn test_env_in_json() {
+        let mut p = Pipeline::new();
+        p.task("build")
+            .run("cargo build")
+            .env("RUST_BACKTRACE", "1")
+            .env("CARGO_TERM_COLOR", "always");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["env"]["RUST_BACKTRACE"], "1");
+        assert_eq!(json["tasks"][0]["env"]["CARGO_TERM_COLOR"], "always");
+    }
+
+    #[test]
+    fn test_inputs_in_json() {
+        let mut p = Pipeline::new();
+        p.task("test")
+            .run("cargo test")
+            .inputs(&["**/*.rs", "Cargo.toml"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let inputs = json["tasks"][0]["inputs"].as_array().unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[0], "**/*.rs");
+        assert_eq!(inputs[1], "Cargo.toml");
+    }
+
+    #[test]
+    fn test_directory_glob() {
+        // Test that glob() works on Directory (returns updated Directory)
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let src_with_glob = src.glob(&["**/*.rs", "Cargo.toml"]);
+
+        // The glob patterns are stored on the returned Directory
+        assert_eq!(src_with_glob.globs.len(), 2);
+        assert_eq!(src_with_glob.globs[0], "**/*.rs");
+        assert_eq!(src_with_glob.globs[1], "Cargo.toml");
+    }
+
+    #[test]
+    fn test_filtered_views_of_one_dir_conflict() {
+        // Both views share the ID "src:.", so one resources entry stood for
+        // both and the api task silently saw the web globs.
+        let mut p = Pipeline::new();
+        let web = p.dir(".").glob(&["web/**"]);
+        let api = p.dir(".").glob(&["api/**"]);
+        assert_eq!(web.id(), "src:.");
+        let _ = p
+            .task("web")
+            .container("node:20")
+            .mount(&web, "/src")
+            .run("npm test");
+        let _ = p
+            .task("api")
+            .container("rust:1.80")
+            .mount(&api, "/src")
+            .run("cargo test");
+
+        match emit_err(&p) {
+            EmitError::DirectoryGlobConflict { id, first, second } => {
+                assert_eq!(id, "src:.");
+                assert_eq!(first, ["web/**"]);
+                assert_eq!(second, ["api/**"]);
+            }
+            err => panic!("expected DirectoryGlobConflict, got: {}", err),
+        }
+
+        // One view mounted twice is fine, whatever the glob order.
+        let mut p = Pipeline::new();
+        let a = p.dir(".").glob(&["a/**", "b/**"]);
+        let b = p.dir(".").glob(&["b/**", "a/**"]);
+        let _ = p.task("a").container("alpine").mount(&a, "/src").run("ls");
+        let _ = p.task("b").container("alpine").mount(&b, "/src").run("ls");
+        assert!(p.to_json().is_ok());
+    }
+
+    #[test]
+    fn test_filtered_views_of_one_dir_are_distinct_resources() {
+        let mut p = Pipeline::new();
+        p.hash_dir_globs();
+        let web = p.dir(".").glob(&["web/**"]);
+        let api = p.dir(".").glob(&["api/**"]);
+        let _ = p
+            .task("web")
+            .container("node:20")
+            .mount(&web, "/src")
+            .run("npm test");
+        let _ = p
+            .task("api")
+            .container("rust:1.80")
+            .mount(&api, "/src")
+            .run("cargo test");
+
+        assert_ne!(web.id(), api.id());
+        let json = emit_json(&p);
+        let resources = json["resources"].as_object().unwrap();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[&web.id()]["path"], ".");
+        assert_eq!(resources[&web.id()]["globs"], serde_json::json!(["web/**"]));
+        assert_eq!(resources[&api.id()]["globs"], serde_json::json!(["api/**"]));
+        assert_eq!(json["tasks"][0]["mounts"][0]["resource"], web.id().as_str());
+        assert_eq!(json["tasks"][1]["mounts"][0]["resource"], api.id().as_str());
+        // The plain "." they were derived from isn't reported as unused.
+        assert!(p.lint().is_empty());
+
+        // Same globs in another order give the same ID.
+        assert_eq!(
+            p.dir(".").glob(&["b/**", "a/**"]).id(),
+            p.dir(".").glob(&["a/**", "b/**"]).id()
+        );
+
+        // Exports mount the path, not the ID.
+        assert!(p.to_shell_script().unwrap().contains("-v \"$PWD\":'/src'"));
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_dir_named_round_trip() {
+        let mut p = Pipeline::new();
+        let web = p.dir_named("web-src", ".").glob(&["web/**"]);
+        let _ = p
+            .task("web")
+            .container("node:20")
+            .mount(&web, "/src")
+            .run("npm test");
+
+        let json = p.to_json().unwrap();
+        let loaded = Pipeline::from_json(&json).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), json);
+        let ids: Vec<_> = loaded.resources().map(|r| r.id()).collect();
+        assert_eq!(ids, ["src:.#web-src"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "directory name \"src\" is already used for \"web\"")]
+    fn test_dir_named_reused_for_other_path_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.dir_named("src", "web");
+        let _ = p.dir_named("src", "api");
+    }
+
+    #[test]
+    fn test_git_resource_and_mount() {
+        let mut p = Pipeline::new();
+        let charts = p
+            .git("https://github.com/org/charts.git")
+            .branch("main")
+            .depth(1);
+        let _ = p
+            .task("deploy")
+            .container("alpine/helm:3")
+            .mount_git(&charts, "/charts")
+            .run("helm upgrade app /charts/app");
+        let _ = p
+            .task("diff")
+            .container("alpine/helm:3")
+            .mount_git(&charts, "/charts")
+            .run("helm diff upgrade app /charts/app");
+
+        let json = emit_json(&p);
+        assert_eq!(json["version"], "2");
+        assert_eq!(
+            json["resources"],
+            serde_json::json!({
+                "git:https://github.com/org/charts.git#main": {
+                    "type": "git",
+                    "url": "https://github.com/org/charts.git",
+                    "ref": "main",
+                    "depth": 1
+                }
+            })
+        );
+        assert_eq!(
+            json["tasks"][0]["mounts"],
+            serde_json::json!([{
+                "resource": "git:https://github.com/org/charts.git#main",
+                "path": "/charts",
+                "type": "git"
+            }])
+        );
+        assert_eq!(json["requires"]["runner"], ">=0.6.2");
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_git_refs_are_distinct_resources() {
+        let mut p = Pipeline::new();
+        let url = "git@github.com:org/charts.git";
+        let stable = p.git(url).tag("v1");
+        let pinned = p
+            .git(url)
+            .commit("4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        let _ = p
+            .task("a")
+            .container("alpine")
+            .mount_git(&stable, "/charts")
+            .run("ls /charts");
+        let _ = p
+            .task("b")
+            .container("alpine")
+            .mount_git(&pinned, "/charts")
+            .run("ls /charts");
+
+        let json = emit_json(&p);
+        assert_eq!(json["resources"].as_object().unwrap().len(), 2);
+        assert_eq!(json["resources"][&stable.id()]["ref"], "v1");
+        assert!(json["resources"][&stable.id()].get("depth").is_none());
+
+        // A shallow clone of the same ref is another checkout.
+        let shallow = p.git(url).tag("v1").depth(1);
+        assert!(matches!(
+            p.task("c")
+                .container("alpine")
+                .try_mount_git(&shallow, "/charts"),
+            Err(PipelineError::GitDepthConflict { .. })
+        ));
+    }
+
+    #[test]
+    fn test_http_resource_and_mount() {
+        let url = "https://example.com/protoc-27.0-linux-x86_64.zip";
+        let digest = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        let mut p = Pipeline::new();
+        let protoc = p.http(url).sha256(digest);
+        for name in ["gen-go", "gen-rust", "gen-ts"] {
+            let _ = p
+                .task(name)
+                .container("debian:12")
+                .mount_file(&protoc, "/opt/protoc.zip")
+                .run("unzip -o /opt/protoc.zip -d /usr/local");
+        }
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["resources"],
+            serde_json::json!({
+                "http:https://example.com/protoc-27.0-linux-x86_64.zip": {
+                    "type": "http",
+                    "url": url,
+                    "sha256": digest.to_ascii_lowercase()
+                }
+            })
+        );
+        for task in json["tasks"].as_array().unwrap() {
+            assert_eq!(
+                task["mounts"],
+                serde_json::json!([{
+                    "resource": protoc.id(),
+                    "path": "/opt/protoc.zip",
+                    "type": "file"
+                }])
+            );
+        }
+        assert_eq!(json["requires"]["runner"], ">=0.6.2");
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+
+        // Unpacking changes what the mount holds, so it can't differ per task.
+        let unpacked = p.http(url).sha256(digest).unpack(true);
+        assert!(matches!(
+            p.task("gen-py")
+                .container("debian:12")
+                .try_mount_file(&unpacked, "/opt/protoc"),
+            Err(PipelineError::HttpFileConflict { .. })
+        ));
+    }
+
+    #[test]
+    fn test_file_resource_and_mount() {
+        let mut p = Pipeline::new();
+        let config = p.file("rustfmt.toml");
+        let _ = p
+            .task("fmt")
+            .container("rust:1.80")
+            .mount_file_res(&config, "/src/rustfmt.toml")
+            .run("cargo fmt --check");
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["resources"],
+            serde_json::json!({
+                "file:rustfmt.toml": {"type": "file", "path": "rustfmt.toml", "hash": "sha256"}
+            })
+        );
+        assert_eq!(
+            json["tasks"][0]["mounts"],
+            serde_json::json!([{
+                "resource": "file:rustfmt.toml",
+                "path": "/src/rustfmt.toml",
+                "type": "file"
+            }])
+        );
+        assert_eq!(json["requires"]["runner"], ">=0.6.2");
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+    }
+
+    #[test]
+    fn test_file_named_is_distinct_resource() {
+        let mut p = Pipeline::new();
+        let plain = p.file("config.toml");
+        let staging = p.file_named("staging", "config.toml");
+        let prod = p.file_named("prod", "config.toml");
+        assert_eq!(plain.id(), "file:config.toml");
+        assert_eq!(staging.id(), "file:config.toml#staging");
+        for (name, file) in [("a", &plain), ("b", &staging), ("c", &prod)] {
+            let _ = p
+                .task(name)
+                .container("alpine")
+                .mount_file_res(file, "/etc/app/config.toml")
+                .run("true");
+        }
+
+        let json = emit_json(&p);
+        let resources = json["resources"].as_object().unwrap();
+        let ids: Vec<&str> = resources.keys().map(String::as_str).collect();
+        assert_eq!(
+            ids,
+            [
+                "file:config.toml",
+                "file:config.toml#prod",
+                "file:config.toml#staging"
+            ]
+        );
+        assert_eq!(
+            json["tasks"][2]["mounts"][0]["resource"],
+            "file:config.toml#prod"
+        );
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "file name \"ci\" is already used for \"a.toml\"")]
+    fn test_file_named_reused_for_other_path_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.file_named("ci", "a.toml");
+        let _ = p.file_named("ci", "b.toml");
+    }
+
+    #[test]
+    #[should_panic(expected = "sha256 must be 64 hex digits")]
+    fn test_http_short_sha256_panics() {
+        let p = Pipeline::new();
+        let _ = p.http("https://example.com/tool.tar.gz").sha256("abc123");
+    }
+
+    #[test]
+    #[should_panic(expected = "container image cannot be empty")]
+    fn test_empty_container_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").container("");
+    }
+
+    #[test]
+    #[should_panic(expected = "container working directory must be absolute")]
+    fn test_relative_workdir_panics() {
+        let mut p = Pipeline::new();
+        p.task("test")
+            .container("rust:1.75")
+            .workdir("relative/path");
+    }
+
+    #[test]
+    fn test_relative_workdir_on_host() {
+        let mut p = Pipeline::new();
+        let _ = p.task("web-test").workdir("web").run("npm test");
+        let _ = p.task("docs").workdir("./docs/site").run("make");
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["workdir"], "web");
+        assert_eq!(json["tasks"][1]["workdir"], "./docs/site");
+
+        let tmpl = Template::new().workdir("web");
+        let _ = p.task("lint").from(&tmpl).run("npm run lint");
+        assert_eq!(emit_json(&p)["tasks"][2]["workdir"], "web");
+    }
+
+    #[test]
+    #[should_panic(expected = "output name cannot be empty")]
+    fn test_empty_output_name_panics() {
+        let mut p = Pipeline::new();
+        p.task("build").run("cargo build").output("", "./app");
+    }
+
+    #[test]
+    #[should_panic(expected = "output path cannot be empty")]
+    fn test_empty_output_path_panics() {
+        let mut p = Pipeline::new();
+        p.task("build").run("cargo build").output("binary", "");
+    }
+
+    #[test]
+    #[should_panic(expected = "environment variable key cannot be empty")]
+    fn test_empty_env_key_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").env("", "value");
+    }
+
+    #[test]
+    #[should_panic(expected = "container mount path must be absolute")]
+    fn test_relative_mount_path_panics() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        p.task("test").mount(&src, "relative");
+    }
+
+    #[test]
+    #[should_panic(expected = "container mount path cannot be empty")]
+    fn test_empty_mount_path_panics() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        p.task("test").mount(&src, "");
+    }
+
+    #[test]
+    #[should_panic(expected = "working directory cannot be empty")]
+    fn test_empty_workdir_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").workdir("");
+    }
+
+    #[test]
+    fn test_try_builders_return_errors() {
+        fn err<T>(r: Result<T, PipelineError>) -> PipelineError {
+            r.err().expect("expected an error")
+        }
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let cache = p.cache("cargo");
+        let _ = p
+            .task("build")
+            .container_from_dockerfile(&src, "Dockerfile");
+
+        assert_eq!(err(p.try_task("")), PipelineError::EmptyTaskName);
+        assert_eq!(
+            err(p.try_task("build")),
+            PipelineError::DuplicateTask {
+                name: "build".into()
+            }
+        );
+        assert_eq!(
+            err(p.try_task("t").unwrap().try_run("")),
+            PipelineError::EmptyCommand
+        );
+        assert_eq!(
+            err(p.task_or_get("t").try_container("")),
+            PipelineError::EmptyImage
+        );
+        assert_eq!(
+            err(p.task_or_get("build").try_container("rust")),
+            PipelineError::ContainerAlreadyBuilt {
+                task: "build".into()
+            }
+        );
+        assert_eq!(
+            err(p.task_or_get("t").try_mount(&src, "src")),
+            PipelineError::RelativePath {
+                what: "container mount path",
+                value: "src".into()
+            }
+        );
+        assert_eq!(
+            err(p.task_or_get("t").try_mount_cache(&cache, "")),
+            PipelineError::Empty {
+                what: "container mount path"
+            }
+        );
+        assert_eq!(
+            err(p.task_or_get("build").try_workdir("work")),
+            PipelineError::RelativePath {
+                what: "container working directory",
+                value: "work".into()
+            }
+        );
+        assert_eq!(
+            err(p.task_or_get("t").try_env("", "1")),
+            PipelineError::Empty {
+                what: "environment variable key"
+            }
+        );
+        assert_eq!(
+            err(p.task_or_get("t").try_output("bin", "")),
+            PipelineError::Empty {
+                what: "output path"
+            }
+        );
+        assert_eq!(
+            err(p.task_or_get("t").try_secret("")),
+            PipelineError::Empty {
+                what: "secret name"
+            }
+        );
+        assert_eq!(
+            err(p.task_or_get("t").try_timeout(0)),
+            PipelineError::ZeroTimeout
+        );
+    }
+
+    #[test]
+    fn test_try_builders_chain() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let result: Result<(), PipelineError> = (|| {
+            let _ = p
+                .try_task("test")?
+                .try_container("rust:1.80")?
+                .try_mount(&src, "/src")?
+                .try_workdir("/src")?
+                .try_env("CI", "true")?
+                .try_run("cargo test")?
+                .try_timeout(60)?;
+            Ok(())
+        })();
+        assert_eq!(result, Ok(()));
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["command"], "cargo test");
+        assert_eq!(json["tasks"][0]["workdir"], "/src");
+    }
+
+    #[test]
+    fn test_rust_preset_inputs() {
+        let mut p = Pipeline::new();
+        p.rust().test();
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let inputs = json["tasks"][0]["inputs"].as_array().unwrap();
+        assert!(inputs.contains(&serde_json::json!("**/*.rs")));
+        assert!(inputs.contains(&serde_json::json!("Cargo.toml")));
+        assert!(inputs.contains(&serde_json::json!("Cargo.lock")));
+    }
+
+    #[test]
+    fn test_rust_preset_lint_command() {
+        let mut p = Pipeline::new();
+        p.rust().lint();
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["command"], "cargo clippy -- -D warnings");
+    }
+
+    #[test]
+    fn test_rust_preset_build_output() {
+        let mut p = Pipeline::new();
+        p.rust().build("target/release/myapp");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(
+            json["tasks"][0]["outputs"]["output_0"],
+            "target/release/myapp"
+        );
+    }
+
+    #[test]
+    fn test_rust_preset_check_commands_and_inputs() {
+        let mut p = Pipeline::new();
+        p.rust().fmt();
+        p.rust().audit();
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["command"], "cargo fmt --check");
+        assert_eq!(
+            json["tasks"][0]["inputs"],
+            serde_json::json!([
+                "**/*.rs",
+                "Cargo.toml",
+                "Cargo.lock",
+                "rustfmt.toml",
+                ".rustfmt.toml"
+            ])
+        );
+        assert_eq!(json["tasks"][1]["command"], "cargo audit");
+        assert_eq!(
+            json["tasks"][1]["inputs"],
+            serde_json::json!(["**/*.rs", "Cargo.toml", "Cargo.lock", ".cargo/audit.toml"])
+        );
+    }
+
+    #[test]
+    fn test_rust_preset_doc_and_coverage_outputs() {
+        let mut p = Pipeline::new();
+        p.rust().doc();
+        let _ = p.rust().coverage().after(&["doc"]);
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["command"], "cargo doc --no-deps");
+        assert_eq!(
+            json["tasks"][0]["outputs"]["docs"],
+            serde_json::json!({"path": "target/doc", "type": "directory"})
+        );
+        assert_eq!(
+            json["tasks"][1]["command"],
+            "cargo llvm-cov --lcov --output-path lcov.info"
+        );
+        assert_eq!(json["tasks"][1]["outputs"]["lcov"], "lcov.info");
+        assert_eq!(
+            json["tasks"][1]["inputs"],
+            serde_json::json!(["**/*.rs", "Cargo.toml", "Cargo.lock"])
+        );
+    }
+
+    #[test]
+    fn test_rust_preset_all_checks_group() {
+        let mut p = Pipeline::new();
+        let checks = p.rust().all_checks();
+        let _ = p.rust().build("target/release/app").after_group(&checks);
+
+        assert_eq!(checks.name, "rust-checks");
+        assert_eq!(checks.names(), ["fmt", "lint", "test", "audit"]);
+        let json = emit_json(&p);
+        let commands: Vec<&str> = json["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["command"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "cargo fmt --check",
+                "cargo clippy -- -D warnings",
+                "cargo test",
+                "cargo audit",
+                "cargo build --release"
+            ]
+        );
+        assert_eq!(
+            json["tasks"][4]["depends_on"],
+            serde_json::json!(["fmt", "lint", "test", "audit"])
+        );
+    }
+
+    #[test]
+    fn test_node_preset_names_and_commands() {
+        let mut p = Pipeline::new();
+        p.node().install();
+        p.node().test();
+        p.node().lint();
+        p.node().build("dist");
+
+        let json = emit_json(&p);
+        let tasks: Vec<(&str, &str)> = json["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["name"].as_str().unwrap(), t["command"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            tasks,
+            [
+                ("node-install", "npm ci"),
+                ("node-test", "npm test"),
+                ("node-lint", "npx eslint ."),
+                ("node-build", "npm run build"),
+            ]
+        );
+        assert_eq!(json["tasks"][3]["outputs"]["output_0"], "dist");
+    }
+
+    #[test]
+    fn test_node_preset_inputs() {
+        let mut p = Pipeline::new();
+        p.node().test();
+        p.node().install();
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["inputs"],
+            serde_json::json!(["**/*.ts", "**/*.js", "package.json", "package-lock.json"])
+        );
+        assert_eq!(
+            json["tasks"][1]["inputs"],
+            serde_json::json!(["package.json", "package-lock.json"])
+        );
+    }
+
+    #[test]
+    fn test_go_preset_names_and_commands() {
+        let mut p = Pipeline::new();
+        p.go().test();
+        p.go().vet();
+        p.go().build("bin/api");
+
+        let json = emit_json(&p);
+        let tasks: Vec<(&str, &str)> = json["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["name"].as_str().unwrap(), t["command"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            tasks,
+            [
+                ("go-test", "go test ./..."),
+                ("go-vet", "go vet ./..."),
+                ("go-build", "go build -o bin/api ."),
+            ]
+        );
+        assert_eq!(json["tasks"][2]["outputs"]["output_0"], "bin/api");
+        assert_eq!(
+            json["tasks"][0]["inputs"],
+            serde_json::json!(["**/*.go", "go.mod", "go.sum"])
+        );
+    }
+
+    #[test]
+    fn test_presets_prefix_avoids_collisions() {
+        let mut p = Pipeline::new();
+        p.rust().test();
+        p.node().prefix("web").test();
+        p.go().prefix("api").test();
+        p.go().prefix("").vet();
+
+        let json = emit_json(&p);
+        let names: Vec<&str> = json["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["test", "web-test", "api-test", "vet"]);
+    }
+
+    #[test]
+    fn test_version_v1_simple_tasks() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+        p.task("build").run("cargo build");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "1");
+        assert!(json["resources"].is_null());
+    }
+
+    #[test]
+    fn test_version_v2_with_dir() {
+        let mut p = Pipeline::new();
+        let _src = p.dir(".");
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "2");
+    }
+
+    #[test]
+    fn test_version_v2_with_cache() {
+        let mut p = Pipeline::new();
+        let _cache = p.cache("test-cache");
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "2");
+    }
+
+    #[test]
+    fn test_version_v2_with_container() {
+        let mut p = Pipeline::new();
+        p.task("test").container("rust:1.75").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["version"], "2");
+    }
+
+    #[test]
+    fn test_when_branch_condition() {
+        let mut p = Pipeline::new();
+        p.task("deploy").run("./deploy.sh").when("branch == 'main'");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["when"], "branch == 'main'");
+    }
+
+    #[test]
+    fn test_when_tag_condition() {
+        let mut p = Pipeline::new();
+        p.task("release").run("./release.sh").when("tag != ''");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["when"], "tag != ''");
+    }
+
+    #[test]
+    fn test_when_not_set() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["when"].is_null());
+    }
+
+    #[test]
+    #[should_panic(expected = "condition cannot be empty")]
+    fn test_when_empty_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test").when("");
+    }
+
+    #[test]
+    fn test_when_with_other_options() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+        p.task("build").run("cargo build");
+        p.task("deploy")
+            .run("./deploy.sh")
+            .after(&["test", "build"])
+            .when("branch == 'main'");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][2]["when"], "branch == 'main'");
+        assert_eq!(json["tasks"][2]["depends_on"][0], "test");
+        assert_eq!(json["tasks"][2]["depends_on"][1], "build");
+    }
+
+    // ----- SECRET TESTS -----
+
+    #[test]
+    fn test_secret_single() {
+        let mut p = Pipeline::new();
+        p.task("deploy").run("./deploy.sh").secret("GITHUB_TOKEN");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let secrets = json["tasks"][0]["secrets"].as_array().unwrap();
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(secrets[0], "GITHUB_TOKEN");
+    }
+
+    #[test]
+    fn test_secret_multiple() {
+        let mut p = Pipeline::new();
+        p.task("deploy")
+            .run("./deploy.sh")
+            .secret("GITHUB_TOKEN")
+            .secret("NPM_TOKEN")
+            .secret("AWS_ACCESS_KEY");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let secrets = json["tasks"][0]["secrets"].as_array().unwrap();
+        assert_eq!(secrets.len(), 3);
+        assert!(secrets.contains(&serde_json::json!("GITHUB_TOKEN")));
+        assert!(secrets.contains(&serde_json::json!("NPM_TOKEN")));
+        assert!(secrets.contains(&serde_json::json!("AWS_ACCESS_KEY")));
+    }
+
+    #[test]
+    fn test_secret_not_set() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["secrets"].is_null());
+    }
+
+    #[test]
+    #[should_panic(expected = "secret name cannot be empty")]
+    fn test_secret_empty_panics() {
+        let mut p = Pipeline::new();
+        p.task("deploy").run("./deploy.sh").secret("");
+    }
+
+    #[test]
+    fn test_secrets_method() {
+        let mut p = Pipeline::new();
+        p.task("deploy")
+            .run("./deploy.sh")
+            .secrets(&["GITHUB_TOKEN", "NPM_TOKEN"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let secrets = json["tasks"][0]["secrets"].as_array().unwrap();
+        assert_eq!(secrets.len(), 2);
+    }
+
+    #[test]
+    fn test_secret_store_refs_json() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret_from(
+                "DB_PASS",
+                SecretRef::from_k8s("prod/db-credentials#password"),
+            )
+            .secret_from(
+                "API_KEY",
+                SecretRef::from_aws(
+                    "arn:aws:secretsmanager:eu-west-1:123456789012:secret:api-AbCdEf#key",
+                ),
+            )
+            .secret_from("SIGNING_KEY", SecretRef::from_aws("release/signing"))
+            .secret_from(
+                "SA_JSON",
+                SecretRef::from_gcp("projects/acme/secrets/deployer/versions/3"),
+            )
+            .secret_from(
+                "DEPLOY_TOKEN",
+                SecretRef::from_sops("ci/secrets.enc.yaml#deploy.token"),
+            );
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["secret_refs"],
+            serde_json::json!([
+                {"name": "DB_PASS", "source": "k8s", "key": "prod/db-credentials#password"},
+                {
+                    "name": "API_KEY",
+                    "source": "aws",
+                    "key": "arn:aws:secretsmanager:eu-west-1:123456789012:secret:api-AbCdEf#key",
+                },
+                {"name": "SIGNING_KEY", "source": "aws", "key": "release/signing"},
+                {
+                    "name": "SA_JSON",
+                    "source": "gcp",
+                    "key": "projects/acme/secrets/deployer/versions/3",
+                },
+                {
+                    "name": "DEPLOY_TOKEN",
+                    "source": "sops",
+                    "key": "ci/secrets.enc.yaml#deploy.token",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_secret_file_json() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .container("bitnami/kubectl")
+            .run("kubectl apply -f k8s/")
+            .secret_from("TOKEN", SecretRef::from_env("DEPLOY_TOKEN"))
+            .secret_file(
+                "KUBECONFIG_CONTENT",
+                SecretRef::from_vault("secret/ci#kubeconfig"),
+                "/secrets/kubeconfig",
+            );
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["secret_refs"],
+            serde_json::json!([
+                {"name": "TOKEN", "source": "env", "key": "DEPLOY_TOKEN"},
+                {
+                    "name": "KUBECONFIG_CONTENT",
+                    "source": "vault",
+                    "key": "secret/ci#kubeconfig",
+                    "mount_path": "/secrets/kubeconfig",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_secret_options_json() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .secret("TRACING_TOKEN")
+            .secret("NPM_TOKEN")
+            .secret_opt("TRACING_TOKEN")
+            .secret_from(
+                "ACCOUNT_ID",
+                SecretRef::from_aws("ci/account#id").optional().no_mask(),
+            );
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["secrets"],
+            serde_json::json!(["NPM_TOKEN"])
+        );
+        assert_eq!(
+            json["tasks"][0]["secret_refs"],
+            serde_json::json!([
+                {
+                    "name": "TRACING_TOKEN",
+                    "source": "env",
+                    "key": "TRACING_TOKEN",
+                    "optional": true,
+                },
+                {
+                    "name": "ACCOUNT_ID",
+                    "source": "aws",
+                    "key": "ci/account#id",
+                    "optional": true,
+                    "mask": false,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_secret_store_ref_formats() {
+        let valid = |source: SecretSource, key: &str| source.check_key(key).is_ok();
+
+        assert!(valid(
+            SecretSource::K8s,
+            "ci/registry.auth#.dockerconfigjson"
+        ));
+        assert!(!valid(SecretSource::K8s, "db-credentials#password"));
+        assert!(!valid(SecretSource::K8s, "prod/db-credentials"));
+        assert!(!valid(SecretSource::K8s, "prod/DB#password"));
+        assert!(!valid(SecretSource::K8s, "prod/db/extra#password"));
+        assert!(!valid(SecretSource::K8s, "prod/db#"));
+
+        assert!(valid(SecretSource::Aws, "prod/db#password"));
+        assert!(!valid(SecretSource::Aws, "prod db"));
+        assert!(!valid(SecretSource::Aws, "prod/db#"));
+        assert!(!valid(SecretSource::Aws, "#password"));
+        assert!(!valid(
+            SecretSource::Aws,
+            "arn:aws:ssm:eu-west-1:123:parameter:db"
+        ));
+
+        assert!(valid(SecretSource::Gcp, "projects/acme/secrets/deployer"));
+        assert!(!valid(SecretSource::Gcp, "acme/deployer"));
+        assert!(!valid(
+            SecretSource::Gcp,
+            "projects/acme/secrets/deployer/versions/"
+        ));
+        assert!(!valid(SecretSource::Gcp, "projects//secrets/deployer"));
+
+        assert!(valid(
+            SecretSource::Sops,
+            "ci/secrets.enc.yaml#deploy_token"
+        ));
+        assert!(valid(SecretSource::Sops, "secrets.yaml#deploy.aws.key"));
+        assert!(!valid(SecretSource::Sops, "ci/secrets.enc.yaml"));
+        assert!(!valid(SecretSource::Sops, "#token"));
+        assert!(!valid(SecretSource::Sops, "secrets.yaml#deploy..key"));
+        assert!(!valid(SecretSource::Sops, "secrets.yaml#deploy."));
+        assert!(!valid(SecretSource::Sops, "secrets.yaml#a#b"));
+    }
+
+    #[test]
+    #[should_panic(expected = "SecretRef::from_k8s() requires 'namespace/secret-name#key' format")]
+    fn test_secret_from_k8s_without_namespace_panics() {
+        let _ = SecretRef::from_k8s("db-credentials#password");
+    }
+
+    #[test]
+    #[should_panic(expected = "SecretRef::from_aws() requires 'name' or 'name#jsonKey' format")]
+    fn test_secret_from_aws_empty_json_key_panics() {
+        let _ = SecretRef::from_aws("prod/db#");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "SecretRef::from_gcp() requires 'projects/PROJECT/secrets/NAME' format"
+    )]
+    fn test_secret_from_gcp_short_name_panics() {
+        let _ = SecretRef::from_gcp("deployer");
+    }
+
+    #[test]
+    #[should_panic(expected = "SecretRef::from_sops() requires 'path#key' format")]
+    fn test_secret_from_sops_without_key_panics() {
+        let _ = SecretRef::from_sops("ci/secrets.enc.yaml");
+    }
+
+    // ----- MATRIX TESTS -----
+
+    #[test]
+    fn test_matrix_single_dimension() {
+        let mut p = Pipeline::new();
+        p.task("test")
+            .run("cargo test")
+            .matrix("rust_version", &["1.70", "1.75", "1.80"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let matrix = json["tasks"][0]["matrix"].as_object().unwrap();
+        assert_eq!(matrix.len(), 1);
+        let versions = matrix["rust_version"].as_array().unwrap();
+        assert_eq!(versions.len(), 3);
+        assert_eq!(versions[0], "1.70");
+    }
+
+    #[test]
+    fn test_matrix_multiple_dimensions() {
+        let mut p = Pipeline::new();
+        p.task("test")
+            .run("cargo test")
+            .matrix("rust_version", &["1.70", "1.75"])
+            .matrix("os", &["ubuntu", "macos"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let matrix = json["tasks"][0]["matrix"].as_object().unwrap();
+        assert_eq!(matrix.len(), 2);
+        assert!(matrix.contains_key("rust_version"));
+        assert!(matrix.contains_key("os"));
+    }
+
+    #[test]
+    fn test_matrix_keys_serialized_in_declaration_order() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("zeta", &["1"])
+            .matrix("alpha", &["2", "1"])
+            .matrix("mid", &["x"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains(r#""matrix":{"zeta":["1"],"alpha":["2","1"],"mid":["x"]}"#));
+    }
+
+    #[test]
+    fn test_matrix_not_set() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["matrix"].is_null());
+    }
+
+    #[test]
+    fn test_matrix_exclude_and_include_json() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.70", "1.80"])
+            .matrix("os", &["linux", "macos"])
+            .matrix_exclude(&[("rust", "1.70"), ("os", "macos")])
+            .matrix_include(&[("rust", "nightly"), ("os", "windows")]);
+
+        let json = emit_json(&p);
+        let task = &json["tasks"][0];
+        assert_eq!(
+            task["matrix_exclude"],
+            serde_json::json!([{"rust": "1.70", "os": "macos"}])
+        );
+        assert_eq!(
+            task["matrix_include"],
+            serde_json::json!([{"rust": "nightly", "os": "windows"}])
+        );
+        assert_eq!(task["matrix"]["rust"], serde_json::json!(["1.70", "1.80"]));
+    }
+
+    #[test]
+    fn test_after_matrix_json_mixes_plain_and_selector_deps() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"])
+            .matrix("arch", &["x64", "arm64"]);
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after(&["lint"])
+            .after_matrix("build", &[("os", "linux"), ("arch", "x64")])
+            .after_matrix("build", &[("os", "macos"), ("arch", "arm64")]);
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][2]["depends_on"],
+            serde_json::json!(["lint", "build-linux-x64", "build-macos-arm64"])
+        );
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+        let package = loaded.iter_tasks().nth(2).unwrap();
+        assert_eq!(package.depends_on(), ["lint", "build"]);
+    }
+
+    #[test]
+    fn test_plain_dep_overrides_matrix_selector() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"]);
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after_matrix("build", &[("os", "linux")])
+            .after(&["build"]);
+        let _ = p
+            .task("publish")
+            .run("./publish.sh")
+            .after(&["build"])
+            .after_matrix("build", &[("os", "linux")]);
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][1]["depends_on"], serde_json::json!(["build"]));
+        assert_eq!(json["tasks"][2]["depends_on"], serde_json::json!(["build"]));
+    }
+
+    #[test]
+    fn test_input_from_matrix_json() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"])
+            .output("binary", "target/app");
+        let _ = p.task("package").run("./package.sh").input_from_matrix(
+            "build",
+            &[("os", "macos")],
+            "binary",
+            "./app",
+        );
+
+        let json = emit_json(&p);
+        let task = &json["tasks"][1];
+        assert_eq!(
+            task["task_inputs"],
+            serde_json::json!([{
+                "from_task": "build-macos",
+                "output": "binary",
+                "dest": "./app",
+            }])
+        );
+        assert_eq!(task["depends_on"], serde_json::json!(["build-macos"]));
+        assert!(task.get("depends_on_meta").is_none());
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "after_matrix: selector cannot be empty")]
+    fn test_after_matrix_empty_selector_panics() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after_matrix("build", &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "matrix_exclude entry cannot be empty")]
+    fn test_matrix_exclude_empty_entry_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test").matrix_exclude(&[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "matrix key cannot be empty")]
+    fn test_matrix_empty_key_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test").matrix("", &["value"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "matrix values cannot be empty")]
+    fn test_matrix_empty_values_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test").matrix("key", &[]);
+    }
+
+    // ----- SERVICE TESTS -----
+
+    #[test]
+    fn test_service_single() {
+        let mut p = Pipeline::new();
+        p.task("test")
+            .run("cargo test")
+            .service("postgres:15", "db");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let services = json["tasks"][0]["services"].as_array().unwrap();
+        assert_eq!(services.len(), 1);
+        assert_eq!(
+            services[0],
+            serde_json::json!({"image": "postgres:15", "name": "db"})
+        );
+    }
+
+    #[test]
+    fn test_service_with_config() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .service_with("postgres:15", "db", |s| {
+                s.env("POSTGRES_PASSWORD", "test")
+                    .env("POSTGRES_DB", "app")
+                    .port(5433)
+                    .port(5433)
+                    .command("postgres -p 5433 -c fsync=off")
+            })
+            .service_with("redis:7", "cache", |s| s.port(6379));
+
+        let json = emit_json(&p);
+        let services = &json["tasks"][0]["services"];
+        assert_eq!(
+            services[0],
+            serde_json::json!({
+                "image": "postgres:15",
+                "name": "db",
+                "env": {"POSTGRES_DB": "app", "POSTGRES_PASSWORD": "test"},
+                "ports": [5433],
+                "command": "postgres -p 5433 -c fsync=off",
+            })
+        );
+        assert_eq!(
+            services[1],
+            serde_json::json!({"image": "redis:7", "name": "cache", "ports": [6379]})
+        );
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
+    }
+
+    #[test]
+    fn test_service_readiness_serializes() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .service_with("postgres:15", "db", |s| {
+                s.ready_cmd("pg_isready -U postgres").ready_timeout(60)
+            })
+            .service_with("redis:7", "cache", |s| s.ready_tcp(6379))
+            .service_with("minio/minio", "s3", |s| {
+                s.ready_http("/minio/health/ready", 9000)
+            });
+
+        let json = emit_json(&p);
+        let services = &json["tasks"][0]["services"];
+        assert_eq!(
+            services[0]["readiness"],
+            serde_json::json!({"type": "cmd", "command": "pg_isready -U postgres", "timeout": 60})
+        );
+        assert_eq!(
+            services[1]["readiness"],
+            serde_json::json!({"type": "tcp", "port": 6379})
+        );
+        assert_eq!(
+            services[2]["readiness"],
+            serde_json::json!({"type": "http", "port": 9000, "path": "/minio/health/ready"})
+        );
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
+
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains(
+                "   Waits for: db (cmd:pg_isready -U postgres, \u{2264}60s), cache (tcp:6379), \
+                 s3 (http:9000/minio/health/ready)\n"
+            ),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "service port cannot be 0")]
+    fn test_service_port_zero_panics() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .service_with("postgres:15", "db", |s| s.port(0));
+    }
+
+    #[test]
+    fn test_service_multiple() {
+        let mut p = Pipeline::new();
+        p.task("test")
+            .run("cargo test")
+            .service("postgres:15", "db")
+            .service("redis:7", "cache");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let services = json["tasks"][0]["services"].as_array().unwrap();
+        assert_eq!(services.len(), 2);
+    }
+
+    #[test]
+    fn test_service_not_set() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["services"].is_null());
+    }
+
+    #[test]
+    #[should_panic(expected = "service image cannot be empty")]
+    fn test_service_empty_image_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test").service("", "db");
+    }
+
+    #[test]
+    #[should_panic(expected = "service name cannot be empty")]
+    fn test_service_empty_name_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test").service("postgres:15", "");
+    }
+
+    // ----- RETRY TESTS -----
+
+    #[test]
+    fn test_retry_in_json() {
+        let mut p = Pipeline::new();
+        p.task("flaky").run("./flaky.sh").retry(3);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["retry"], 3);
+    }
+
+    #[test]
+    fn test_retry_policy_with_only_max_is_a_count() {
+        let mut p = Pipeline::new();
+        let _ = p.task("a").run("./a.sh").retry_policy(RetryPolicy::new(2));
+        let _ = p.task("b").run("./b.sh").retry_policy(RetryPolicy {
+            max: 4,
+            ..RetryPolicy::default()
+        });
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["retry"], 2);
+        assert_eq!(json["tasks"][1]["retry"], 4);
+    }
+
+    #[test]
+    fn test_retry_policy_in_json() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("push")
+            .run("docker push app")
+            .retry_policy(RetryPolicy {
+                max: 3,
+                backoff: Backoff::Exponential {
+                    base_secs: 5,
+                    max_secs: 120,
+                },
+                retry_on_exit_codes: Some(vec![75, 111]),
+                retry_on_timeout: true,
+            });
+        let _ = p.task("fetch").run("./fetch.sh").retry_policy(RetryPolicy {
+            max: 2,
+            backoff: Backoff::Fixed { secs: 10 },
+            retry_on_timeout: false,
+            ..RetryPolicy::default()
+        });
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["retry"],
+            serde_json::json!({
+                "max": 3,
+                "backoff": {"type": "exponential", "base_secs": 5, "max_secs": 120},
+                "retry_on_exit_codes": [75, 111]
+            })
+        );
+        assert_eq!(
+            json["tasks"][1]["retry"],
+            serde_json::json!({
+                "max": 2,
+                "backoff": {"type": "fixed", "secs": 10},
+                "retry_on_timeout": false
+            })
+        );
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
+    }
+
+    #[test]
+    fn test_retry_zero_clears_retry() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test").retry(3).retry(0);
+
+        assert!(emit_json(&p)["tasks"][0].get("retry").is_none());
+    }
+
+    #[test]
+    fn test_retry_not_set() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["retry"].is_null());
+    }
+
+    // ----- TIMEOUT TESTS -----
+
+    #[test]
+    fn test_timeout_in_json() {
+        let mut p = Pipeline::new();
+        p.task("long").run("./long-running.sh").timeout(600);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["timeout"], 600);
+    }
+
+    #[test]
+    fn test_timeout_not_set() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["timeout"].is_null());
+    }
+
+    #[test]
+    #[should_panic(expected = "timeout must be greater than 0")]
+    fn test_timeout_zero_panics() {
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test").timeout(0);
+    }
+
+    #[test]
+    fn test_retry_and_timeout_combined() {
+        let mut p = Pipeline::new();
+        p.task("flaky").run("./flaky.sh").retry(2).timeout(120);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["retry"], 2);
+        assert_eq!(json["tasks"][0]["timeout"], 120);
+    }
+
+    // ----- CYCLE DETECTION TESTS -----
+
+    #[test]
+    fn test_cycle_self_reference() {
+        // A task that depends on itself: A -> A
+        let mut p = Pipeline::new();
+        p.task("build").run("cargo build").after(&["build"]);
+
+        let err = emit_err(&p);
+        assert!(
+            matches!(err, EmitError::Cycle { .. }),
+            "expected cycle error, got: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cycle_direct_two_tasks() {
+        // Direct cycle between two tasks: A -> B -> A
+        let mut p = Pipeline::new();
+        p.task("a").run("echo a").after(&["b"]);
+        p.task("b").run("echo b").after(&["a"]);
+
+        let err = emit_err(&p);
+        assert!(
+            matches!(err, EmitError::Cycle { .. }),
+            "expected cycle error, got: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cycle_indirect_three_tasks() {
+        // Indirect cycle: A -> B -> C -> A
+        let mut p = Pipeline::new();
+        p.task("a").run("echo a").after(&["b"]);
+        p.task("b").run("echo b").after(&["c"]);
+        p.task("c").run("echo c").after(&["a"]);
+
+        let err = emit_err(&p);
+        assert!(
+            matches!(err, EmitError::Cycle { .. }),
+            "expected cycle error, got: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cycle_longer_chain() {
+        // Longer cycle: A -> B -> C -> D -> E -> A
+        let mut p = Pipeline::new();
+        p.task("a").run("echo a").after(&["b"]);
+        p.task("b").run("echo b").after(&["c"]);
+        p.task("c").run("echo c").after(&["d"]);
+        p.task("d").run("echo d").after(&["e"]);
+        p.task("e").run("echo e").after(&["a"]);
+
+        let err = emit_err(&p);
+        assert!(
+            matches!(err, EmitError::Cycle { .. }),
+            "expected cycle error, got: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cycle_in_complex_graph() {
+        // Complex graph with a cycle hidden among valid dependencies
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+        p.task("lint").run("cargo clippy");
+        p.task("build").run("cargo build").after(&["test", "lint"]);
+        p.task("deploy")
+            .run("./deploy.sh")
+            .after(&["build", "verify"]);
+        p.task("verify").run("./verify.sh").after(&["deploy"]);
+
+        let err = emit_err(&p);
+        assert!(
+            matches!(err, EmitError::Cycle { .. }),
+            "expected cycle error, got: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cycle_error_shows_path() {
+        // Verify the error message includes the cycle path
+        let mut p = Pipeline::new();
+        p.task("a").run("echo a").after(&["b"]);
+        p.task("b").run("echo b").after(&["a"]);
+
+        match emit_err(&p) {
+            EmitError::Cycle { path } => assert!(
+                path.contains(&"a".to_string()) && path.contains(&"b".to_string()),
+                "cycle should contain both tasks, got: {:?}",
+                path
+            ),
+            err => panic!("expected cycle error, got: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_no_cycle_valid_dag() {
+        // Valid DAG with no cycles - should succeed
+        // build depends on test, lint; deploy depends on build
+        let mut p = Pipeline::new();
+        p.task("test").run("cargo test");
+        p.task("lint").run("cargo clippy");
+        p.task("build").run("cargo build").after(&["test", "lint"]);
+        p.task("deploy").run("./deploy.sh").after(&["build"]);
+
+        let mut buf = Vec::new();
+        let result = p.emit_to(&mut buf);
+        assert!(result.is_ok(), "valid DAG should not error: {:?}", result);
+    }
+
+    #[test]
+    fn test_no_cycle_diamond_pattern() {
+        // Diamond pattern: b -> a, c -> a, d -> b, d -> c
+        // (b,c depend on a; d depends on b,c; execution: a then b,c then d)
+        let mut p = Pipeline::new();
+        p.task("a").run("echo a");
+        p.task("b").run("echo b").after(&["a"]);
+        p.task("c").run("echo c").after(&["a"]);
+        p.task("d").run("echo d").after(&["b", "c"]);
+
+        let mut buf = Vec::new();
+        let result = p.emit_to(&mut buf);
+        assert!(
+            result.is_ok(),
+            "diamond pattern should not error: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_no_cycle_multiple_roots() {
+        // Multiple independent roots converging
+        let mut p = Pipeline::new();
+        p.task("a").run("echo a");
+        p.task("b").run("echo b");
+        p.task("c").run("echo c");
+        p.task("final").run("echo final").after(&["a", "b", "c"]);
+
+        let mut buf = Vec::new();
+        let result = p.emit_to(&mut buf);
+        assert!(
+            result.is_ok(),
+            "multiple roots should not error: {:?}",
+            result
+        );
+    }
+
+    // =============================================================================
+    // TEMPLATE TESTS
+    // =============================================================================
+
+    #[test]
+    fn test_template_basic() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+
+        // Create template with common config
+        let tmpl = Template::new()
+            .container("rust:1.75")
+            .mount_dir(&src, "/src")
+            .workdir("/src");
+
+        // Task inherits from template
+        p.task("test").from(&tmpl).run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["container"], "rust:1.75");
+        assert_eq!(json["tasks"][0]["workdir"], "/src");
+        assert_eq!(json["tasks"][0]["mounts"][0]["path"], "/src");
+    }
+
+    #[test]
+    fn test_template_with_cache() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let cache = p.cache("cargo-registry");
+
+        let tmpl = Template::new()
+            .container("rust:1.75")
+            .mount_dir(&src, "/src")
+            .mount_cache(&cache, "/usr/local/cargo/registry")
+            .workdir("/src");
+
+        p.task("test").from(&tmpl).run("cargo test");
+        p.task("build").from(&tmpl).run("cargo build");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // Both tasks should have 2 mounts
+        assert_eq!(json["tasks"][0]["mounts"].as_array().unwrap().len(), 2);
+        assert_eq!(json["tasks"][1]["mounts"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_template_with_env() {
+        let mut p = Pipeline::new();
+
+        let tmpl = Template::new()
+            .container("rust:1.75")
+            .env("RUST_BACKTRACE", "1")
+            .env("CARGO_TERM_COLOR", "always");
+
+        p.task("build").from(&tmpl).run("cargo build");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["env"]["RUST_BACKTRACE"], "1");
+        assert_eq!(json["tasks"][0]["env"]["CARGO_TERM_COLOR"], "always");
+    }
+
+    #[test]
+    fn test_template_override() {
+        let mut p = Pipeline::new();
+
+        let tmpl = Template::new()
+            .container("rust:1.75")
+            .env("FOO", "from-template");
+
+        // Task overrides env
+        p.task("test")
+            .from(&tmpl)
+            .env("FOO", "from-task")
+            .run("echo $FOO");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // Task-level should override
+        assert_eq!(json["tasks"][0]["env"]["FOO"], "from-task");
+    }
+
+    #[test]
+    fn test_template_multiple_tasks() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+
+        let rust = Template::new()
+            .container("rust:1.75")
+            .mount_dir(&src, "/src")
+            .workdir("/src");
+
+        p.task("lint").from(&rust).run("cargo clippy");
+        p.task("test").from(&rust).run("cargo test");
+        p.task("build")
+            .from(&rust)
+            .run("cargo build")
+            .after(&["lint", "test"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"].as_array().unwrap().len(), 3);
+        // All should have same container
+        for i in 0..3 {
+            assert_eq!(json["tasks"][i]["container"], "rust:1.75");
+        }
+    }
+
+    // =============================================================================
+    // CHAIN TESTS
+    // =============================================================================
+
+    #[test]
+    fn test_chain_basic() {
+        let mut p = Pipeline::new();
+        p.task("a").run("echo a");
+        p.task("b").run("echo b");
+        p.task("c").run("echo c");
+
+        // Chain creates: a → b → c
+        p.chain(&["a", "b", "c"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // a has no deps
+        assert!(json["tasks"][0]["depends_on"].is_null());
+        // b depends on a
+        assert_eq!(json["tasks"][1]["depends_on"][0], "a");
+        // c depends on b
+        assert_eq!(json["tasks"][2]["depends_on"][0], "b");
     }
 
-    /// Sets dependencies for this review node. Duplicates are ignored.
-    #[must_use]
-    pub fn after(self, tasks: &[&str]) -> Self {
-        let task = &mut self.pipeline.tasks[self.index];
-        for name in tasks {
-            if !name.is_empty() && !task.depends_on.iter().any(|dep| dep == name) {
-                task.depends_on.push((*name).to_string());
-            }
-        }
-        self
+    #[test]
+    fn test_chain_preserves_existing_deps() {
+        let mut p = Pipeline::new();
+        p.task("prereq").run("echo prereq");
+        p.task("a").run("echo a").after(&["prereq"]); // existing dep
+        p.task("b").run("echo b");
+
+        p.chain(&["a", "b"]);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // a should still have prereq AND nothing from chain (it's first)
+        let a_deps = json["tasks"][1]["depends_on"].as_array().unwrap();
+        assert_eq!(a_deps.len(), 1);
+        assert_eq!(a_deps[0], "prereq");
+
+        // b should depend on a (from chain)
+        assert_eq!(json["tasks"][2]["depends_on"][0], "a");
     }
 
-    /// Sets whether the review is deterministic.
-    #[must_use]
-    pub fn deterministic(self, value: bool) -> Self {
-        self.pipeline.tasks[self.index].deterministic = value;
-        self
+    #[test]
+    fn test_chain_single_task() {
+        let mut p = Pipeline::new();
+        p.task("only").run("echo only");
+
+        p.chain(&["only"]); // Single task - no deps added
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["depends_on"].is_null());
     }
-}
 
-// =============================================================================
-// EXPLAIN CONTEXT
-// =============================================================================
+    // =============================================================================
+    // PARALLEL GROUP TESTS
+    // =============================================================================
 
-/// Context for evaluating conditions during explain/dry-run.
-#[derive(Default)]
-pub struct ExplainContext {
-    /// Current branch name
-    pub branch: String,
-    /// Current tag (empty if none)
-    pub tag: String,
-    /// CI event type (push, pull_request, etc.)
-    pub event: String,
-    /// Whether running in CI environment
-    pub ci: bool,
-}
+    #[test]
+    fn test_parallel_as_dependency() {
+        let mut p = Pipeline::new();
+        p.task("lint").run("cargo clippy");
+        p.task("test").run("cargo test");
 
-// =============================================================================
-// TASK GROUP
-// =============================================================================
+        // Parallel group: both have no deps themselves
+        // Build depends on the group
+        let checks = &["lint", "test"];
+        p.task("build").run("cargo build").after(checks);
 
-/// A group of tasks that can be used as a dependency.
-/// Created by `matrix()` and can be passed to `after()` or `chain()`.
-#[derive(Clone, Debug)]
-pub struct TaskGroup {
-    /// Name of the group (for identification)
-    pub name: String,
-    /// Names of tasks in this group
-    pub task_names: Vec<String>,
-}
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-impl TaskGroup {
-    /// Creates a new task group.
-    #[must_use]
-    pub fn new(name: impl Into<String>, task_names: Vec<String>) -> Self {
-        TaskGroup {
-            name: name.into(),
-            task_names,
-        }
-    }
+        // lint and test have no deps
+        assert!(json["tasks"][0]["depends_on"].is_null());
+        assert!(json["tasks"][1]["depends_on"].is_null());
 
-    /// Returns the task names in this group.
-    #[must_use]
-    pub fn names(&self) -> &[String] {
-        &self.task_names
+        // build depends on both
+        let build_deps = json["tasks"][2]["depends_on"].as_array().unwrap();
+        assert_eq!(build_deps.len(), 2);
     }
-}
 
-// =============================================================================
-// PIPELINE
-// =============================================================================
+    #[test]
+    fn test_chain_with_parallel_group() {
+        let mut p = Pipeline::new();
+        // Parallel checks
+        p.task("lint").run("cargo clippy");
+        p.task("test").run("cargo test");
+        let checks = vec!["lint", "test"];
 
-/// A CI pipeline with tasks and resources.
-pub struct Pipeline {
-    tasks: Vec<TaskData>,
-    dirs: Vec<Directory>,
-    caches: Vec<CacheVolume>,
-    k8s_defaults: Option<K8sOptions>,
-}
+        // Build after checks
+        p.task("build").run("cargo build").after(&checks);
 
-impl Pipeline {
-    /// Creates a new pipeline.
-    #[must_use]
-    pub fn new() -> Self {
-        Pipeline {
-            tasks: Vec::new(),
-            dirs: Vec::new(),
-            caches: Vec::new(),
-            k8s_defaults: None,
-        }
-    }
+        // Deploy after build
+        p.task("deploy").run("./deploy.sh").after(&["build"]);
 
-    /// Creates a new pipeline with K8s defaults.
-    ///
-    /// All tasks inherit these settings unless they override them.
-    ///
-    /// # Example
-    /// ```rust,ignore
-    /// use sykli::{Pipeline, K8sOptions, K8sResources};
-    ///
-    /// let mut p = Pipeline::with_k8s_defaults(K8sOptions {
-    ///     namespace: Some("ci-jobs".into()),
-    ///     resources: K8sResources {
-    ///         memory: Some("2Gi".into()),
-    ///         ..Default::default()
-    ///     },
-    ///     ..Default::default()
-    /// });
-    ///
-    /// p.task("test").run("go test");     // inherits defaults
-    /// p.task("heavy").k8s(K8sOptions {   // overrides memory
-    ///     resources: K8sResources { memory: Some("32Gi".into()), ..Default::default() },
-    ///     ..Default::default()
-    /// }).run("heavy-job");
-    /// ```
-    #[must_use]
-    pub fn with_k8s_defaults(k8s_defaults: K8sOptions) -> Self {
-        Pipeline {
-            tasks: Vec::new(),
-            dirs: Vec::new(),
-            caches: Vec::new(),
-            k8s_defaults: Some(k8s_defaults),
-        }
-    }
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-    /// Creates a directory resource.
-    ///
-    /// # Panics
-    /// Panics if `path` is empty.
-    pub fn dir(&mut self, path: &str) -> Directory {
-        assert!(!path.is_empty(), "directory path cannot be empty");
-        let dir = Directory {
-            path: path.to_string(),
-            globs: Vec::new(),
-        };
-        self.dirs.push(dir.clone());
-        dir
-    }
+        // lint and test parallel (no deps)
+        assert!(json["tasks"][0]["depends_on"].is_null());
+        assert!(json["tasks"][1]["depends_on"].is_null());
 
-    /// Creates a named cache volume.
-    ///
-    /// # Panics
-    /// Panics if `name` is empty.
-    pub fn cache(&mut self, name: &str) -> CacheVolume {
-        assert!(!name.is_empty(), "cache name cannot be empty");
-        let cache = CacheVolume {
-            name: name.to_string(),
-        };
-        self.caches.push(cache.clone());
-        cache
+        // build depends on both
+        assert_eq!(json["tasks"][2]["depends_on"].as_array().unwrap().len(), 2);
+
+        // deploy depends on build
+        assert_eq!(json["tasks"][3]["depends_on"][0], "build");
     }
 
-    /// Creates a new task with the given name.
-    ///
-    /// # Panics
-    /// Panics if `name` is empty or if a task with the same name already exists.
-    pub fn task(&mut self, name: &str) -> Task<'_> {
-        assert!(!name.is_empty(), "task name cannot be empty");
-        assert!(
-            !self.tasks.iter().any(|t| t.name == name),
-            "task {name:?} already exists"
-        );
-        self.tasks.push(TaskData {
-            name: name.to_string(),
-            ..Default::default()
-        });
-        let index = self.tasks.len() - 1;
-        Task {
-            pipeline: self,
-            index,
-        }
+    // =============================================================================
+    // TASK NAME METHOD TEST
+    // =============================================================================
+
+    #[test]
+    fn test_task_name_method() {
+        let mut p = Pipeline::new();
+        let name = p.task("my-task").run("echo test").name();
+        assert_eq!(name, "my-task");
     }
 
-    /// Creates an experimental review node with the given name.
-    ///
-    /// # Panics
-    /// Panics if `name` is empty or if a node with the same name already exists.
-    pub fn review(&mut self, name: &str) -> Review<'_> {
-        assert!(!name.is_empty(), "review name cannot be empty");
-        assert!(
-            !self.tasks.iter().any(|t| t.name == name),
-            "task/gate/review {name:?} already exists"
-        );
-        self.tasks.push(TaskData {
-            kind: NodeKind::Review,
-            name: name.to_string(),
-            ..Default::default()
-        });
-        let index = self.tasks.len() - 1;
-        Review {
-            pipeline: self,
-            index,
-        }
+    // =============================================================================
+    // INPUT/OUTPUT BINDING TESTS
+    // =============================================================================
+
+    #[test]
+    fn test_input_from_basic() {
+        let mut p = Pipeline::new();
+
+        // Build produces output
+        p.task("build")
+            .run("cargo build --release")
+            .output("binary", "target/release/app");
+
+        // Package consumes it
+        p.task("package")
+            .input_from("build", "binary", "/app")
+            .run("docker build .");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // Check task_inputs
+        let inputs = json["tasks"][1]["task_inputs"].as_array().unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0]["from_task"], "build");
+        assert_eq!(inputs[0]["output"], "binary");
+        assert_eq!(inputs[0]["dest"], "/app");
     }
 
-    /// Creates an approval gate that pauses the pipeline until approved.
-    ///
-    /// A gate is a special task with no command that waits for an approval signal.
-    ///
-    /// # Panics
-    /// Panics if `name` is empty or if a task with the same name already exists.
-    pub fn gate(&mut self, name: &str) -> Task<'_> {
-        assert!(!name.is_empty(), "gate name cannot be empty");
-        assert!(
-            !self.tasks.iter().any(|t| t.name == name),
-            "task/gate {name:?} already exists"
-        );
-        self.tasks.push(TaskData {
-            name: name.to_string(),
-            gate: Some(GateConfig {
-                strategy: "prompt".to_string(),
-                timeout: 3600,
-                ..Default::default()
-            }),
-            ..Default::default()
-        });
-        let index = self.tasks.len() - 1;
-        Task {
-            pipeline: self,
-            index,
-        }
-    }
+    #[test]
+    fn test_input_from_auto_adds_dep() {
+        let mut p = Pipeline::new();
 
-    /// Returns a Rust preset builder.
-    pub fn rust(&mut self) -> RustPreset<'_> {
-        RustPreset { pipeline: self }
+        p.task("build").run("cargo build").output("binary", "./app");
+        p.task("package")
+            .input_from("build", "binary", "/app")
+            .run("docker build");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // package should depend on build
+        let deps = json["tasks"][1]["depends_on"].as_array().unwrap();
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0], "build");
     }
 
-    /// Creates a sequential dependency chain between tasks.
-    ///
-    /// Each task in the chain depends on the previous one: a → b → c
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("a").run("echo a");
-    /// p.task("b").run("echo b");
-    /// p.task("c").run("echo c");
-    /// p.chain(&["a", "b", "c"]); // b depends on a, c depends on b
-    /// ```
-    ///
-    /// # Panics
-    /// Panics if any task name doesn't exist.
-    pub fn chain(&mut self, task_names: &[&str]) {
-        for window in task_names.windows(2) {
-            let prev = window[0];
-            let curr = window[1];
+    #[test]
+    fn test_input_from_multiple() {
+        let mut p = Pipeline::new();
 
-            // Find the current task and add dependency
-            let task = self
-                .tasks
-                .iter_mut()
-                .find(|t| t.name == curr)
-                .unwrap_or_else(|| panic!("task {:?} not found", curr));
+        p.task("build-linux")
+            .run("cargo build")
+            .output("binary", "./linux");
+        p.task("build-darwin")
+            .run("cargo build")
+            .output("binary", "./darwin");
+        p.task("package")
+            .input_from("build-linux", "binary", "/linux")
+            .input_from("build-darwin", "binary", "/darwin")
+            .run("tar czf release.tar.gz /linux /darwin");
 
-            // Only add the dependency if it doesn't already exist to avoid duplicates.
-            if !task.depends_on.iter().any(|d| d == prev) {
-                task.depends_on.push(prev.to_string());
-            }
-        }
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        let inputs = json["tasks"][2]["task_inputs"].as_array().unwrap();
+        assert_eq!(inputs.len(), 2);
+
+        let deps = json["tasks"][2]["depends_on"].as_array().unwrap();
+        assert_eq!(deps.len(), 2);
     }
 
-    /// Creates a TaskGroup from existing tasks (for parallel dependencies).
-    ///
-    /// This is a convenience method to create a named group of tasks that can be
-    /// used as a dependency. The tasks must already exist in the pipeline.
-    ///
-    /// # Example
-    /// ```rust
-    /// use sykli::Pipeline;
-    ///
-    /// let mut p = Pipeline::new();
-    /// p.task("lint").run("cargo clippy");
-    /// p.task("test").run("cargo test");
-    ///
-    /// // Create parallel group from existing tasks
-    /// let checks = p.parallel("checks", &["lint", "test"]);
-    ///
-    /// // Build depends on the parallel group
-    /// p.task("build").after_group(&checks).run("cargo build");
-    /// ```
-    ///
-    /// # Panics
-    /// Panics if any task name doesn't exist in the pipeline.
-    #[must_use]
-    pub fn parallel(&self, name: &str, task_names: &[&str]) -> TaskGroup {
-        // Validate all tasks exist
-        for &task_name in task_names {
-            if !self.tasks.iter().any(|t| t.name == task_name) {
-                panic!("task {:?} not found in pipeline", task_name);
-            }
-        }
+    #[test]
+    fn test_input_from_no_duplicate_deps() {
+        let mut p = Pipeline::new();
 
-        TaskGroup::new(name, task_names.iter().map(|s| (*s).to_string()).collect())
+        p.task("build").run("cargo build").output("binary", "./app");
+        // Explicit after AND input_from - should not duplicate
+        p.task("package")
+            .after(&["build"])
+            .input_from("build", "binary", "/app")
+            .run("docker build");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // Should have only one dep, not duplicated
+        let deps = json["tasks"][1]["depends_on"].as_array().unwrap();
+        assert_eq!(deps.len(), 1);
     }
 
-    /// Creates tasks for each value in the matrix, returning a TaskGroup.
-    ///
-    /// # Example
-    /// ```rust,ignore
-    /// let mut p = Pipeline::new();
-    /// let tests = p.matrix("go-versions", &["1.21", "1.22", "1.23"], |p, version| {
-    ///     p.task(&format!("test-go-{}", version))
-    ///         .container(&format!("golang:{}", version))
-    ///         .mount_cwd()
-    ///         .run("go test ./...");
-    /// });
-    /// // Use the group as a dependency
-    /// p.task("deploy").after_group(&tests).run("deploy");
-    /// ```
-    pub fn matrix<F>(&mut self, name: &str, values: &[&str], mut generator: F) -> TaskGroup
-    where
-        F: FnMut(&mut Pipeline, &str),
-    {
-        assert!(!values.is_empty(), "matrix: values must not be empty");
-        let start_len = self.tasks.len();
+    #[test]
+    fn test_inputs_from_all_expands_late_outputs() {
+        let mut p = Pipeline::new();
+        // The consumer comes first; build's outputs are declared afterwards.
+        let _ = p
+            .task("package")
+            .container("alpine:3")
+            .run("./package.sh")
+            .inputs_from_all("build", "/artifacts/build/")
+            .input_from("build", "manifest", "/etc/manifest.json")
+            .after(&["build"]);
+        let _ = p
+            .task("build")
+            .run("make")
+            .output("binary", "target/app")
+            .output("manifest", "target/manifest.json")
+            .output_glob("debs", "target/*.deb");
 
-        for v in values {
-            generator(self, v);
-        }
+        let json = emit_json(&p);
+        let package = &json["tasks"][0];
+        assert_eq!(
+            package["task_inputs"],
+            serde_json::json!([
+                {"from_task": "build", "output": "manifest", "dest": "/etc/manifest.json"},
+                {"from_task": "build", "output": "binary", "dest": "/artifacts/build/binary"},
+                {"from_task": "build", "output": "debs", "dest": "/artifacts/build/debs/"},
+            ])
+        );
+        assert_eq!(package["depends_on"], serde_json::json!(["build"]));
+    }
 
-        // Collect names of newly created tasks
-        let task_names: Vec<String> = self.tasks[start_len..]
-            .iter()
-            .map(|t| t.name.clone())
-            .collect();
+    #[test]
+    fn test_inputs_from_all_without_outputs_fails_emit() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("report")
+            .run("./report.sh")
+            .inputs_from_all("lint", "in");
 
-        TaskGroup::new(name, task_names)
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "task \"report\" reads all outputs of task \"lint\", which declares none (declare them with output() or drop inputs_from_all())"
+        );
+        assert_eq!(p.validate()[0].field, "input_from");
+
+        let _ = p.task_or_get("lint").output("sarif", "clippy.sarif");
+        assert_eq!(
+            emit_json(&p)["tasks"][1]["task_inputs"][0]["dest"],
+            "in/sarif"
+        );
     }
 
-    // =========================================================================
-    // EXPLAIN (Dry-run mode)
-    // =========================================================================
+    #[test]
+    fn test_mixed_simple_and_rich_outputs() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("make")
+            .output("binary", "target/app")
+            .output_glob("bundles", "dist/*.tar.gz")
+            .output_dir("site", "public/")
+            .output_glob("report", "reports/*.xml")
+            // A plain output() replaces the glob of the same name.
+            .output("report", "reports/junit.xml");
+
+        let json: serde_json::Value = serde_json::from_str(&p.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["tasks"][0]["outputs"],
+            serde_json::json!({
+                "binary": "target/app",
+                "bundles": {"path": "dist/*.tar.gz", "type": "glob"},
+                "report": "reports/junit.xml",
+                "site": {"path": "public/", "type": "directory"},
+            })
+        );
 
-    /// Context for evaluating conditions during explain.
-    /// Pass None to use empty defaults.
-    pub fn explain(&self, ctx: Option<&ExplainContext>) {
-        self.explain_to(&mut io::stdout(), ctx);
+        let build = p.iter_tasks().next().unwrap();
+        assert_eq!(build.output_type("bundles"), Some("glob"));
+        assert_eq!(build.output_type("report"), Some("file"));
+        assert_eq!(build.output_type("missing"), None);
     }
 
-    /// Writes the execution plan to the given writer.
-    pub fn explain_to<W: Write>(&self, w: &mut W, ctx: Option<&ExplainContext>) {
-        let default_ctx = ExplainContext::default();
-        let ctx = ctx.unwrap_or(&default_ctx);
+    #[test]
+    fn test_output_with_options() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("coverage")
+            .run("cargo llvm-cov --html")
+            .output_with(
+                "report",
+                "target/llvm-cov/html/",
+                OutputOptions {
+                    retention_days: Some(7),
+                    compress: true,
+                    required: false,
+                },
+            )
+            .output_with("binary", "target/app", OutputOptions::default())
+            .output_with(
+                "lcov",
+                "lcov.info",
+                OutputOptions {
+                    required: false,
+                    ..OutputOptions::default()
+                },
+            );
 
-        // Topological sort
-        let sorted = self.topological_sort();
+        let json: serde_json::Value = serde_json::from_str(&p.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["tasks"][0]["outputs"],
+            serde_json::json!({
+                "binary": "target/app",
+                "lcov": {"path": "lcov.info", "type": "file", "required": false},
+                "report": {
+                    "path": "target/llvm-cov/html/",
+                    "type": "file",
+                    "retention_days": 7,
+                    "compress": true,
+                    "required": false,
+                },
+            })
+        );
 
-        writeln!(w, "Pipeline Execution Plan").ok();
-        writeln!(w, "=======================").ok();
+        // Redeclaring the output drops its options.
+        let _ = p.task_or_get("coverage").output("lcov", "lcov.info");
+        let json: serde_json::Value = serde_json::from_str(&p.to_json().unwrap()).unwrap();
+        assert_eq!(json["tasks"][0]["outputs"]["lcov"], "lcov.info");
+    }
 
-        for (i, t) in sorted.iter().enumerate() {
-            // Build task header
-            let mut header = format!("{}. {}", i + 1, t.name);
+    #[test]
+    #[should_panic(expected = "output path cannot be empty")]
+    fn test_empty_output_glob_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").output_glob("bundles", "");
+    }
 
-            // Add dependencies
-            if !t.depends_on.is_empty() {
-                header.push_str(&format!(" (after: {})", t.depends_on.join(", ")));
-            }
+    // =============================================================================
+    // STREAMING EMIT TESTS
+    // =============================================================================
 
-            // Add target override
-            if let Some(ref target) = t.target_name {
-                header.push_str(&format!(" [target: {}]", target));
-            }
+    #[test]
+    fn test_streaming_emit_matches_emit_to() {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("2Gi".into()),
+            ..Default::default()
+        });
+        let src = p.dir(".");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .env("CARGO_TERM_COLOR", "always")
+            .run("cargo build")
+            .output("binary", "target/release/app")
+            .k8s(K8sOptions {
+                cpu: Some("2".into()),
+                ..Default::default()
+            });
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux"])
+            .retry(2)
+            .timeout(60)
+            .after(&["build"]);
+        let _ = p
+            .task("package")
+            .input_from("build", "binary", "/app")
+            .secret_from("TOKEN", SecretRef::from_env("GH_TOKEN"))
+            .when("branch == 'main'")
+            .run("./package.sh");
+        let _ = p.gate("approve").after(&["package"]);
+        let _ = p.review("review").primitive("lint").after(&["test"]);
 
-            // Check if task would be skipped
-            let condition = t
-                .when_cond
-                .as_ref()
-                .map(|c| c.to_string())
-                .or_else(|| t.condition.clone());
-            if let Some(ref cond) = condition {
-                if let Some(reason) = self.would_skip(cond, ctx) {
-                    header.push_str(&format!(" [SKIPPED: {}]", reason));
-                }
-            }
+        let mut expected = Vec::new();
+        p.emit_to(&mut expected).unwrap();
+        let mut streamed = Vec::new();
+        p.emit_streaming_to(&mut streamed).unwrap();
 
-            writeln!(w, "{}", header).ok();
-            writeln!(w, "   Command: {}", t.command).ok();
+        assert_eq!(
+            String::from_utf8(streamed).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
 
-            if let Some(ref cond) = condition {
-                writeln!(w, "   Condition: {}", cond).ok();
-            }
+    #[test]
+    fn test_streaming_emit_v1_matches_emit_to() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        let _ = p.task("build").run("cargo build").after(&["test"]);
 
-            if !t.secret_refs.is_empty() {
-                let secrets: Vec<_> = t
-                    .secret_refs
-                    .iter()
-                    .map(|sr| {
-                        let source = match sr.source {
-                            SecretSource::Env => "env",
-                            SecretSource::File => "file",
-                            SecretSource::Vault => "vault",
-                        };
-                        format!("{} ({}:{})", sr.name, source, sr.key)
-                    })
-                    .collect();
-                writeln!(w, "   Secrets: {}", secrets.join(", ")).ok();
-            } else if !t.secrets.is_empty() {
-                writeln!(w, "   Secrets: {}", t.secrets.join(", ")).ok();
-            }
+        let mut expected = Vec::new();
+        p.emit_to(&mut expected).unwrap();
+        let mut streamed = Vec::new();
+        p.emit_streaming_to(&mut streamed).unwrap();
 
-            writeln!(w).ok();
-        }
+        assert_eq!(streamed, expected);
     }
 
-    /// Check if a task would be skipped given the context.
-    fn would_skip(&self, condition: &str, ctx: &ExplainContext) -> Option<String> {
-        let condition = condition.trim();
-
-        // branch == 'value'
-        if condition.starts_with("branch == '") {
-            let expected = condition
-                .strip_prefix("branch == '")
-                .and_then(|s| s.strip_suffix("'"))
-                .unwrap_or("");
-            if ctx.branch != expected {
-                return Some(format!("branch is '{}', not '{}'", ctx.branch, expected));
-            }
-        }
+    #[test]
+    fn test_streaming_emit_validates_first() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build").after(&["missing"]);
 
-        // branch != 'value'
-        if condition.starts_with("branch != '") {
-            let excluded = condition
-                .strip_prefix("branch != '")
-                .and_then(|s| s.strip_suffix("'"))
-                .unwrap_or("");
-            if ctx.branch == excluded {
-                return Some(format!("branch is '{}'", ctx.branch));
-            }
-        }
+        let mut buf = Vec::new();
+        assert!(p.emit_streaming_to(&mut buf).is_err());
+        // Nothing is written before validation passes
+        assert!(buf.is_empty());
+    }
 
-        // tag != '' (has tag)
-        if condition == "tag != ''" && ctx.tag.is_empty() {
-            return Some("no tag present".to_string());
-        }
+    #[test]
+    #[should_panic(expected = "adding task \"test-2\" exceeds max_tasks limit of 2")]
+    fn test_max_tasks_panics_during_expansion() {
+        let mut p = Pipeline::new();
+        p.max_tasks(2);
+        let _ = p.matrix("tests", &["0", "1", "2", "3"], |p, v| {
+            let _ = p.task(&format!("test-{}", v)).run("cargo test");
+        });
+    }
 
-        // ci == true
-        if condition == "ci == true" && !ctx.ci {
-            return Some("not running in CI".to_string());
-        }
+    #[test]
+    fn test_max_tasks_checked_at_emit() {
+        let mut p = Pipeline::new();
+        let _ = p.task("a").run("echo a");
+        let _ = p.task("b").run("echo b");
+        p.max_tasks(1);
 
-        None
+        assert!(matches!(
+            emit_err(&p),
+            EmitError::TooManyTasks { count: 2, limit: 1 }
+        ));
     }
 
-    /// Topological sort of tasks.
-    fn topological_sort(&self) -> Vec<&TaskData> {
-        // Build in-degree map
-        let mut in_degree: HashMap<&str, usize> = HashMap::new();
-        for t in &self.tasks {
-            in_degree.entry(&t.name).or_insert(0);
-            for _ in &t.depends_on {
-                *in_degree.entry(&t.name).or_insert(0) += 1;
-            }
-        }
-
-        // Kahn's algorithm
-        let mut queue: Vec<&str> = in_degree
-            .iter()
-            .filter(|(_, &d)| d == 0)
-            .map(|(n, _)| *n)
-            .collect();
+    // =============================================================================
+    // DEPENDENCY EDGE KIND TESTS
+    // =============================================================================
 
-        let task_map: HashMap<&str, &TaskData> =
-            self.tasks.iter().map(|t| (t.name.as_str(), t)).collect();
-        let mut sorted = Vec::new();
-
-        while let Some(name) = queue.pop() {
-            if let Some(t) = task_map.get(name) {
-                sorted.push(*t);
-
-                // Decrease in-degree of dependents
-                for other in &self.tasks {
-                    for dep in &other.depends_on {
-                        if dep == name {
-                            if let Some(d) = in_degree.get_mut(other.name.as_str()) {
-                                *d -= 1;
-                                if *d == 0 {
-                                    queue.push(&other.name);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+    #[test]
+    fn test_dependency_kinds_all_three() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build").output("binary", "./app");
+        let _ = p.task("docs").run("cargo doc").output("site", "./doc");
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("package")
+            .input_from("build", "binary", "/app")
+            .after(&["lint", "docs"])
+            .input_from("docs", "site", "/doc")
+            .run("./package.sh");
 
-        sorted
+        assert_eq!(
+            p.dependency_kinds("package"),
+            vec![
+                ("build".to_string(), EdgeKind::Artifact),
+                ("lint".to_string(), EdgeKind::Order),
+                ("docs".to_string(), EdgeKind::Both),
+            ]
+        );
+        assert!(p.dependency_kinds("build").is_empty());
+        assert!(p.dependency_kinds("missing").is_empty());
     }
 
-    /// Emits the pipeline as JSON to stdout if `--emit` flag is present.
-    ///
-    /// This method checks for `--emit` in command line arguments and if found,
-    /// writes the pipeline JSON to stdout and exits the process with code 0.
-    /// If emission fails, exits with code 1.
-    ///
-    /// **Note:** This method exits the process and does not return. For non-exiting
-    /// behavior, use [`Pipeline::emit_to`] directly.
-    pub fn emit(&self) {
-        if env::args().any(|arg| arg == "--emit") {
-            if let Err(e) = self.emit_to(&mut io::stdout()) {
-                eprintln!("error: {}", e);
-                std::process::exit(1);
-            }
-            std::process::exit(0);
-        }
-    }
+    #[test]
+    fn test_depends_on_meta_serialization() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build").output("binary", "./app");
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("package")
+            .after(&["lint"])
+            .input_from("build", "binary", "/app")
+            .run("./package.sh");
 
-    /// Always emits the pipeline as JSON to stdout and exits.
-    ///
-    /// Unlike [`Pipeline::emit`], this method always writes the JSON output
-    /// regardless of command line arguments. This matches the Go SDK's `MustEmit()`.
-    ///
-    /// **Note:** This method exits the process and does not return.
-    pub fn force_emit(&self) {
-        if let Err(e) = self.emit_to(&mut io::stdout()) {
-            eprintln!("error: {}", e);
-            std::process::exit(1);
-        }
-        std::process::exit(0);
-    }
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let package = &json["tasks"][2];
 
-    /// Writes the pipeline JSON to the given writer.
-    pub fn emit_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        // Validate
-        let task_names: Vec<_> = self.tasks.iter().map(|t| t.name.as_str()).collect();
-        for t in &self.tasks {
-            if t.kind == NodeKind::Review {
-                if t.primitive.as_deref().unwrap_or("").is_empty() {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("review {:?} has no primitive", t.name),
-                    ));
-                }
-            } else if t.command.is_empty() && t.gate.is_none() {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("task {:?} has no command", t.name),
-                ));
-            }
-            for dep in &t.depends_on {
-                if !task_names.contains(&dep.as_str()) {
-                    let suggestion = suggest_task_name(dep, &task_names);
-                    let msg = if let Some(suggested) = suggestion {
-                        format!(
-                            "task {:?} depends on unknown task {:?} (did you mean {:?}?)",
-                            t.name, dep, suggested
-                        )
-                    } else {
-                        format!("task {:?} depends on unknown task {:?}", t.name, dep)
-                    };
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
-                }
-            }
-        }
+        // depends_on and task_inputs already tell the edges apart.
+        assert_eq!(package["depends_on"], serde_json::json!(["lint", "build"]));
+        assert!(package.get("depends_on_meta").is_none());
 
-        // Cycle detection
-        if let Some(cycle) = self.detect_cycle() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("dependency cycle detected: {}", cycle.join(" -> ")),
-            ));
-        }
+        // An input from a task that is also ordered explicitly isn't implied.
+        let _ = p.task_or_get("package").after(&["build"]);
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][2]["depends_on_meta"],
+            serde_json::json!([
+                {"task": "lint", "kind": "order"},
+                {"task": "build", "kind": "both"}
+            ])
+        );
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(
+            loaded.dependency_kinds("package"),
+            p.dependency_kinds("package")
+        );
+    }
 
-        // Validate K8s options (merge defaults first, then validate)
-        for t in &self.tasks {
-            let merged = match (&self.k8s_defaults, &t.k8s_options) {
-                (None, None) => None,
-                (Some(defaults), None) => Some(defaults.clone()),
-                (None, Some(task)) => Some(task.clone()),
-                (Some(defaults), Some(task)) => Some(K8sOptions::merge(defaults, task)),
-            };
-            if let Some(ref opts) = merged {
-                let errors = opts.validate();
-                if !errors.is_empty() {
-                    tracing::error!(task = %t.name, error = %errors[0], "K8s validation failed");
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("task {:?}: {}", t.name, errors[0]),
-                    ));
-                }
-            }
-        }
+    #[test]
+    fn test_depends_on_meta_omitted_for_order_only_tasks() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        let _ = p.task("build").run("cargo build").after(&["test"]);
 
-        // Detect version based on usage
-        let has_v2_features = !self.dirs.is_empty()
-            || !self.caches.is_empty()
-            || self
-                .tasks
-                .iter()
-                .any(|t| t.container.is_some() || !t.mounts.is_empty());
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-        let has_v3_features = self
-            .tasks
-            .iter()
-            .any(|t| t.task_type.is_some() || !t.success_criteria.is_empty());
+        assert_eq!(json["tasks"][1]["depends_on"], serde_json::json!(["test"]));
+        assert!(json["tasks"][1].get("depends_on_meta").is_none());
+    }
 
-        let version = if has_v3_features {
-            "3"
-        } else if has_v2_features {
-            "2"
-        } else {
-            "1"
-        };
+    #[test]
+    fn test_hook_deps_serialize() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build");
+        let _ = p.task("deploy").run("./deploy.sh").after(&["build"]);
+        let _ = p
+            .task("alert")
+            .run("./notify.sh")
+            .on_failure_of(&["deploy"]);
+        let _ = p
+            .task("logs")
+            .run("./upload-logs.sh")
+            .after(&["build"])
+            .always_after(&["deploy"]);
+        let _ = p.task("tag").run("git tag").on_success_of(&["deploy"]);
 
-        // Build output
-        let output = JsonPipeline {
-            version: version.to_string(),
-            resources: if has_v2_features {
-                let mut resources = HashMap::new();
-                for d in &self.dirs {
-                    resources.insert(
-                        d.id(),
-                        JsonResource {
-                            type_: "directory".to_string(),
-                            path: Some(d.path.clone()),
-                            name: None,
-                            globs: if d.globs.is_empty() {
-                                None
-                            } else {
-                                Some(d.globs.clone())
-                            },
-                        },
-                    );
-                }
-                for c in &self.caches {
-                    resources.insert(
-                        c.id(),
-                        JsonResource {
-                            type_: "cache".to_string(),
-                            path: None,
-                            name: Some(c.name.clone()),
-                            globs: None,
-                        },
-                    );
-                }
-                // Only include resources if non-empty (matches Go SDK behavior)
-                if resources.is_empty() {
-                    None
-                } else {
-                    Some(resources)
-                }
-            } else {
-                None
-            },
-            tasks: self
-                .tasks
-                .iter()
-                .map(|t| JsonTask {
-                    name: t.name.clone(),
-                    kind: if t.kind == NodeKind::Review {
-                        Some("review".to_string())
-                    } else {
-                        None
-                    },
-                    task_type: if t.kind == NodeKind::Review {
-                        None
-                    } else {
-                        t.task_type.as_ref().map(|tt| tt.as_str().to_string())
-                    },
-                    success_criteria: if t.kind == NodeKind::Review || t.success_criteria.is_empty()
-                    {
-                        None
-                    } else {
-                        Some(
-                            t.success_criteria
-                                .iter()
-                                .map(SuccessCriterion::to_json)
-                                .collect(),
-                        )
-                    },
-                    command: if t.kind == NodeKind::Review || t.command.is_empty() {
-                        None
-                    } else {
-                        Some(t.command.clone())
-                    },
-                    primitive: if t.kind == NodeKind::Review {
-                        t.primitive.clone()
-                    } else {
-                        None
-                    },
-                    agent: if t.kind == NodeKind::Review {
-                        t.agent.clone()
-                    } else {
-                        None
-                    },
-                    context: if t.kind == NodeKind::Review && !t.context.is_empty() {
-                        Some(t.context.clone())
-                    } else {
-                        None
-                    },
-                    deterministic: if t.kind == NodeKind::Review {
-                        Some(t.deterministic)
-                    } else {
-                        None
-                    },
-                    container: t.container.clone(),
-                    workdir: t.workdir.clone(),
-                    env: if t.env.is_empty() {
-                        None
-                    } else {
-                        Some(t.env.clone())
-                    },
-                    mounts: if t.mounts.is_empty() {
-                        None
-                    } else {
-                        Some(
-                            t.mounts
-                                .iter()
-                                .map(|m| JsonMount {
-                                    resource: m.resource.clone(),
-                                    path: m.path.clone(),
-                                    type_: m.mount_type.clone(),
-                                })
-                                .collect(),
-                        )
-                    },
-                    inputs: if t.inputs.is_empty() {
-                        None
-                    } else {
-                        Some(t.inputs.clone())
-                    },
-                    task_inputs: if t.task_inputs.is_empty() {
-                        None
-                    } else {
-                        Some(
-                            t.task_inputs
-                                .iter()
-                                .map(|ti| JsonTaskInput {
-                                    from_task: ti.from_task.clone(),
-                                    output: ti.output.clone(),
-                                    dest: ti.dest_path.clone(),
-                                })
-                                .collect(),
-                        )
-                    },
-                    outputs: if t.kind == NodeKind::Review || t.outputs.is_empty() {
-                        None
-                    } else {
-                        Some(t.outputs.clone())
-                    },
-                    depends_on: if t.depends_on.is_empty() {
-                        None
-                    } else {
-                        Some(t.depends_on.clone())
-                    },
-                    condition: t
-                        .when_cond
-                        .as_ref()
-                        .map(|c| c.to_string())
-                        .or_else(|| t.condition.clone()),
-                    secrets: if t.secrets.is_empty() {
-                        None
-                    } else {
-                        Some(t.secrets.clone())
-                    },
-                    secret_refs: if t.secret_refs.is_empty() {
-                        None
-                    } else {
-                        Some(
-                            t.secret_refs
-                                .iter()
-                                .map(|sr| JsonSecretRef {
-                                    name: sr.name.clone(),
-                                    source: match sr.source {
-                                        SecretSource::Env => "env".to_string(),
-                                        SecretSource::File => "file".to_string(),
-                                        SecretSource::Vault => "vault".to_string(),
-                                    },
-                                    key: sr.key.clone(),
-                                })
-                                .collect(),
-                        )
-                    },
-                    matrix: if t.matrix.is_empty() {
-                        None
-                    } else {
-                        Some(t.matrix.clone())
-                    },
-                    services: if t.services.is_empty() {
-                        None
-                    } else {
-                        Some(
-                            t.services
-                                .iter()
-                                .map(|s| JsonService {
-                                    image: s.image.clone(),
-                                    name: s.name.clone(),
-                                })
-                                .collect(),
-                        )
-                    },
-                    retry: t.retry,
-                    timeout: t.timeout,
-                    k8s: {
-                        // Merge pipeline defaults with task options
-                        let merged = match (&self.k8s_defaults, &t.k8s_options) {
-                            (None, None) => None,
-                            (Some(defaults), None) => Some(defaults.clone()),
-                            (None, Some(task)) => Some(task.clone()),
-                            (Some(defaults), Some(task)) => Some(K8sOptions::merge(defaults, task)),
-                        };
-                        // Include k8s options if we have either structured opts or raw JSON
-                        if merged.as_ref().is_some_and(|o| !o.is_empty()) || t.k8s_raw.is_some() {
-                            Some(convert_k8s_options(
-                                merged.as_ref().unwrap_or(&K8sOptions::default()),
-                                t.k8s_raw.as_ref(),
-                            ))
-                        } else {
-                            None
-                        }
-                    },
-                    requires: if t.requires.is_empty() {
-                        None
-                    } else {
-                        Some(t.requires.clone())
-                    },
-                    provides: if t.provides.is_empty() {
-                        None
-                    } else {
-                        Some(
-                            t.provides
-                                .iter()
-                                .map(|(name, value)| JsonProvide {
-                                    name: name.clone(),
-                                    value: value.clone(),
-                                })
-                                .collect(),
-                        )
-                    },
-                    needs: if t.needs.is_empty() {
-                        None
-                    } else {
-                        Some(t.needs.clone())
-                    },
-                    semantic: {
-                        let s = &t.semantic;
-                        if s.covers.is_empty() && s.intent.is_none() && s.criticality.is_none() {
-                            None
-                        } else {
-                            Some(JsonSemantic {
-                                covers: if s.covers.is_empty() {
-                                    None
-                                } else {
-                                    Some(s.covers.clone())
-                                },
-                                intent: s.intent.clone(),
-                                criticality: s.criticality.as_ref().map(|c| match c {
-                                    Criticality::High => "high".to_string(),
-                                    Criticality::Medium => "medium".to_string(),
-                                    Criticality::Low => "low".to_string(),
-                                }),
-                            })
-                        }
-                    },
-                    ai_hooks: {
-                        let h = &t.ai_hooks;
-                        if h.on_fail.is_none() && h.select.is_none() {
-                            None
-                        } else {
-                            Some(JsonAiHooks {
-                                on_fail: h.on_fail.as_ref().map(|a| match a {
-                                    OnFailAction::Analyze => "analyze".to_string(),
-                                    OnFailAction::Retry => "retry".to_string(),
-                                    OnFailAction::Skip => "skip".to_string(),
-                                }),
-                                select: h.select.as_ref().map(|s| match s {
-                                    SelectMode::Smart => "smart".to_string(),
-                                    SelectMode::Always => "always".to_string(),
-                                    SelectMode::Manual => "manual".to_string(),
-                                }),
-                            })
-                        }
-                    },
-                    gate: t.gate.as_ref().map(|g| JsonGate {
-                        strategy: g.strategy.clone(),
-                        timeout: if g.timeout > 0 { Some(g.timeout) } else { None },
-                        message: g.message.clone(),
-                        env_var: g.env_var.clone(),
-                        file_path: g.file_path.clone(),
-                    }),
-                    verify: t.verify.clone(),
-                })
-                .collect(),
-        };
+        let json = emit_json(&p);
+        assert!(json["tasks"][1].get("depends_on_meta").is_none());
+        assert_eq!(
+            json["tasks"][2]["depends_on"],
+            serde_json::json!(["deploy"])
+        );
+        assert_eq!(
+            json["tasks"][2]["depends_on_meta"],
+            serde_json::json!([{"task": "deploy", "kind": "order", "run_when": "failure"}])
+        );
+        assert_eq!(
+            json["tasks"][3]["depends_on_meta"],
+            serde_json::json!([
+                {"task": "build", "kind": "order"},
+                {"task": "deploy", "kind": "order", "run_when": "always"}
+            ])
+        );
+        assert_eq!(
+            json["tasks"][4]["depends_on_meta"][0]["run_when"],
+            "success"
+        );
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
 
-        serde_json::to_writer(&mut *w, &output)?;
-        writeln!(w)?;
-        Ok(())
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains("3. alert (on failure of: deploy)\n"),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains("4. logs (after: build) (always after: deploy)\n"),
+            "{}",
+            out
+        );
+        assert!(out.contains("5. tag (on success of: deploy)\n"), "{}", out);
     }
-}
 
-impl Default for Pipeline {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn test_hook_deps_are_validated_as_edges() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh");
+        let _ = p
+            .task("alert")
+            .run("./notify.sh")
+            .on_failure_of(&["deplyo"]);
+        assert!(matches!(
+            emit_err(&p),
+            EmitError::UnknownDependency { task, dep, suggestion: Some(s) }
+                if task == "alert" && dep == "deplyo" && s == "deploy"
+        ));
+
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh").after(&["cleanup"]);
+        let _ = p
+            .task("cleanup")
+            .run("./cleanup.sh")
+            .always_after(&["deploy"]);
+        let err = emit_err(&p);
+        assert!(
+            matches!(&err, EmitError::Cycle { path } if path.len() == 3),
+            "expected cycle error, got: {}",
+            err
+        );
     }
-}
 
-// =============================================================================
-// RUST PRESET
-// =============================================================================
+    #[test]
+    fn test_explain_verbose_shows_edge_kinds() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build").output("binary", "./app");
+        let _ = p
+            .task("package")
+            .input_from("build", "binary", "/app")
+            .after(&["build"])
+            .run("./package.sh");
 
-/// Convenience methods for Rust projects.
-pub struct RustPreset<'a> {
-    pipeline: &'a mut Pipeline,
-}
+        let ctx = ExplainContext {
+            verbose: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("Depends on: build (both)"));
 
-impl<'a> RustPreset<'a> {
-    /// Standard input patterns for Rust projects.
-    pub fn inputs() -> Vec<&'static str> {
-        vec!["**/*.rs", "Cargo.toml", "Cargo.lock"]
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(!out.contains("Depends on:"));
     }
 
-    /// Adds a "cargo test" task.
-    pub fn test(self) -> Task<'a> {
-        self.pipeline
-            .task("test")
-            .run("cargo test")
-            .inputs(&Self::inputs())
+    // =============================================================================
+    // K8S VALIDATION TESTS
+    // =============================================================================
+
+    #[test]
+    fn test_k8s_validation_valid_memory_formats() {
+        let valid = ["512Mi", "4Gi", "1Ti", "256Ki", "1G", "500M", "100"];
+        for mem in valid {
+            let opts = K8sOptions {
+                memory: Some(mem.to_string()),
+                ..Default::default()
+            };
+            let errors = opts.validate();
+            assert!(errors.is_empty(), "expected {} to be valid", mem);
+        }
     }
 
-    /// Adds a "cargo clippy" task.
-    pub fn lint(self) -> Task<'a> {
-        self.pipeline
-            .task("lint")
-            .run("cargo clippy -- -D warnings")
-            .inputs(&Self::inputs())
+    #[test]
+    fn test_k8s_validation_invalid_memory_formats() {
+        let cases = [
+            ("32gb", "did you mean 'Gi'"),
+            ("512mb", "did you mean 'Mi'"),
+            ("1kb", "did you mean 'Ki'"),
+            ("4GB", "did you mean 'Gi'"),
+            ("lots", "invalid memory format"),
+        ];
+        for (mem, expected_hint) in cases {
+            let mut p = Pipeline::new();
+            p.task("test").run("echo test").k8s(K8sOptions {
+                memory: Some(mem.to_string()),
+                ..Default::default()
+            });
+            let mut buf = Vec::new();
+            let result = p.emit_to(&mut buf);
+            assert!(result.is_err(), "expected {} to fail", mem);
+            let err_msg = result.unwrap_err().to_string();
+            assert!(
+                err_msg.contains(expected_hint),
+                "expected error for {} to contain '{}', got: {}",
+                mem,
+                expected_hint,
+                err_msg
+            );
+        }
     }
 
-    /// Adds a "cargo build --release" task.
-    pub fn build(self, output: &str) -> Task<'a> {
-        self.pipeline
-            .task("build")
-            .run("cargo build --release")
-            .inputs(&Self::inputs())
-            .outputs(&[output])
+    #[test]
+    fn test_k8s_validation_valid_cpu_formats() {
+        let valid = ["100m", "500m", "1", "2", "0.5", "1.5"];
+        for cpu in valid {
+            let opts = K8sOptions {
+                cpu: Some(cpu.to_string()),
+                ..Default::default()
+            };
+            let errors = opts.validate();
+            assert!(errors.is_empty(), "expected {} to be valid", cpu);
+        }
     }
-}
 
-// =============================================================================
-// CYCLE DETECTION
-// =============================================================================
+    #[test]
+    fn test_k8s_validation_invalid_cpu_formats() {
+        let cases = ["100cores", "2 cores", "fast"];
+        for cpu in cases {
+            let mut p = Pipeline::new();
+            p.task("test").run("echo test").k8s(K8sOptions {
+                cpu: Some(cpu.to_string()),
+                ..Default::default()
+            });
+            let mut buf = Vec::new();
+            let result = p.emit_to(&mut buf);
+            assert!(result.is_err(), "expected {} to fail", cpu);
+        }
+    }
 
-/// Color for DFS cycle detection
-#[derive(Clone, Copy, PartialEq)]
-enum Color {
-    White, // unvisited
-    Gray,  // currently visiting (in recursion stack)
-    Black, // completely processed
-}
+    #[test]
+    fn test_k8s_gpu() {
+        let mut p = Pipeline::new();
+        p.task("train").run("python train.py").k8s(K8sOptions {
+            memory: Some("32Gi".into()),
+            gpu: Some(2),
+            ..Default::default()
+        });
 
-impl Pipeline {
-    /// Detects cycles in the task dependency graph using DFS.
-    /// Returns the cycle path if found, None otherwise.
-    fn detect_cycle(&self) -> Option<Vec<String>> {
-        // Build adjacency map: task name -> dependencies
-        let deps: HashMap<&str, Vec<&str>> = self
-            .tasks
-            .iter()
-            .map(|t| {
-                (
-                    t.name.as_str(),
-                    t.depends_on.iter().map(|s| s.as_str()).collect(),
-                )
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(json["tasks"][0]["k8s"]["memory"], "32Gi");
+        assert_eq!(json["tasks"][0]["k8s"]["gpu"], 2);
+    }
+
+    #[test]
+    fn test_k8s_raw_escape_hatch() {
+        // k8s_raw allows advanced options (tolerations, volumes, etc.)
+        let mut p = Pipeline::new();
+        p.task("gpu-train")
+            .run("python train.py")
+            .k8s(K8sOptions {
+                memory: Some("32Gi".into()),
+                gpu: Some(1),
+                ..Default::default()
             })
-            .collect();
+            .k8s_raw(r#"{"nodeSelector": {"gpu": "true"}, "tolerations": [{"key": "gpu", "effect": "NoSchedule"}]}"#);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        // Structured options
+        assert_eq!(json["tasks"][0]["k8s"]["memory"], "32Gi");
+        assert_eq!(json["tasks"][0]["k8s"]["gpu"], 1);
+        // Raw JSON passed through
+        assert!(json["tasks"][0]["k8s"]["raw"]
+            .as_str()
+            .unwrap()
+            .contains("nodeSelector"));
+    }
+
+    #[test]
+    fn test_k8s_raw_only() {
+        // Can use k8s_raw without structured K8sOptions
+        let mut p = Pipeline::new();
+        p.task("custom")
+            .run("echo test")
+            .k8s_raw(r#"{"serviceAccount": "my-sa"}"#);
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert!(json["tasks"][0]["k8s"]["raw"]
+            .as_str()
+            .unwrap()
+            .contains("serviceAccount"));
+    }
+
+    #[test]
+    fn test_k8s_validation_with_defaults() {
+        // Validation should happen after merging with defaults
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("invalid_memory".to_string()),
+            ..Default::default()
+        });
+        p.task("test").run("echo test");
+
+        match emit_err(&p) {
+            EmitError::K8sDefaults { error } => assert_eq!(error.field, "memory"),
+            err => panic!("expected k8s defaults error, got: {}", err),
+        }
+    }
+
+    // =========================================================================
+    // MATRIX BUILD TESTS
+    // =========================================================================
+
+    #[test]
+    fn test_matrix_basic() {
+        let mut p = Pipeline::new();
+
+        let group = p.matrix("go-versions", &["1.21", "1.22", "1.23"], |p, version| {
+            p.task(&format!("test-go-{}", version))
+                .container(&format!("golang:{}", version))
+                .run("go test ./...");
+        });
+
+        // Should have 3 task names
+        assert_eq!(group.task_names.len(), 3);
+        assert_eq!(group.name, "go-versions");
+
+        // Verify task names
+        assert!(group.task_names.contains(&"test-go-1.21".to_string()));
+        assert!(group.task_names.contains(&"test-go-1.22".to_string()));
+        assert!(group.task_names.contains(&"test-go-1.23".to_string()));
+
+        // Should emit successfully
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let tasks = json["tasks"].as_array().unwrap();
+        assert_eq!(tasks.len(), 3);
+    }
 
-        let mut color: HashMap<&str, Color> = self
-            .tasks
-            .iter()
-            .map(|t| (t.name.as_str(), Color::White))
-            .collect();
+    #[test]
+    #[should_panic(expected = "matrix: values must not be empty")]
+    fn test_matrix_empty_panics() {
+        let mut p = Pipeline::new();
+        p.matrix("empty", &[], |p, v| {
+            p.task(&format!("test-{}", v)).run("test");
+        });
+    }
 
-        let mut parent: HashMap<&str, &str> = HashMap::new();
+    #[test]
+    fn test_matrix_as_dependency() {
+        let mut p = Pipeline::new();
 
-        // DFS from each unvisited node
-        for task in &self.tasks {
-            if color[task.name.as_str()] == Color::White {
-                if let Some(cycle) =
-                    self.dfs_detect_cycle(task.name.as_str(), &deps, &mut color, &mut parent)
-                {
-                    return Some(cycle);
-                }
-            }
-        }
+        let tests = p.matrix("tests", &["1.21", "1.22"], |p, version| {
+            p.task(&format!("test-{}", version)).run("go test");
+        });
 
-        None
-    }
+        // Another task depends on the entire matrix
+        p.task("deploy").after_group(&tests).run("deploy");
 
-    /// Performs DFS and returns cycle path if found.
-    fn dfs_detect_cycle<'a>(
-        &self,
-        node: &'a str,
-        deps: &HashMap<&'a str, Vec<&'a str>>,
-        color: &mut HashMap<&'a str, Color>,
-        parent: &mut HashMap<&'a str, &'a str>,
-    ) -> Option<Vec<String>> {
-        color.insert(node, Color::Gray);
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-        if let Some(node_deps) = deps.get(node) {
-            for &dep in node_deps {
-                if color.get(dep) == Some(&Color::Gray) {
-                    // Found a cycle - reconstruct the path
-                    return Some(self.reconstruct_cycle(node, dep, parent));
-                }
-                if color.get(dep) == Some(&Color::White) {
-                    parent.insert(dep, node);
-                    if let Some(cycle) = self.dfs_detect_cycle(dep, deps, color, parent) {
-                        return Some(cycle);
-                    }
-                }
-            }
-        }
+        // Find deploy task
+        let tasks = json["tasks"].as_array().unwrap();
+        let deploy = tasks.iter().find(|t| t["name"] == "deploy").unwrap();
+        let deps = deploy["depends_on"].as_array().unwrap();
 
-        color.insert(node, Color::Black);
-        None
+        // Should depend on both matrix tasks
+        assert_eq!(deps.len(), 2);
     }
 
-    /// Reconstructs the cycle path from the detected back edge.
-    fn reconstruct_cycle(&self, from: &str, to: &str, parent: &HashMap<&str, &str>) -> Vec<String> {
-        // Cycle: to -> ... -> from -> to
-        let mut cycle = vec![to.to_string()];
-        let mut current = from;
-        while current != to {
-            cycle.insert(0, current.to_string());
-            current = parent.get(current).unwrap_or(&to);
-        }
-        cycle.insert(0, to.to_string()); // Close the cycle
-        cycle
+    #[test]
+    fn test_task_group_new() {
+        let group = TaskGroup::new(
+            "test-group",
+            vec!["task-a".to_string(), "task-b".to_string()],
+        );
+
+        assert_eq!(group.name, "test-group");
+        assert_eq!(group.names(), &["task-a", "task-b"]);
     }
-}
 
-// =============================================================================
-// JSON SERIALIZATION
-// =============================================================================
+    #[test]
+    fn test_after_group() {
+        let mut p = Pipeline::new();
 
-#[derive(Serialize)]
-struct JsonPipeline {
-    version: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    resources: Option<HashMap<String, JsonResource>>,
-    tasks: Vec<JsonTask>,
-}
+        p.task("a").run("echo a");
+        p.task("b").run("echo b");
 
-#[derive(Serialize)]
-struct JsonResource {
-    #[serde(rename = "type")]
-    type_: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    path: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    globs: Option<Vec<String>>,
-}
+        let group = TaskGroup::new("prereqs", vec!["a".to_string(), "b".to_string()]);
 
-#[derive(Serialize)]
-struct JsonTaskInput {
-    from_task: String,
-    output: String,
-    dest: String,
-}
+        p.task("c").after_group(&group).run("echo c");
 
-#[derive(Serialize)]
-struct JsonSuccessCriterion {
-    #[serde(rename = "type")]
-    type_: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    equals: Option<i32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    path: Option<String>,
-}
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-#[derive(Serialize)]
-struct JsonSecretRef {
-    name: String,
-    source: String,
-    key: String,
-}
+        let tasks = json["tasks"].as_array().unwrap();
+        let task_c = tasks.iter().find(|t| t["name"] == "c").unwrap();
+        let deps = task_c["depends_on"].as_array().unwrap();
 
-#[derive(Serialize)]
-struct JsonProvide {
-    name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    value: Option<String>,
-}
+        // Should depend on both a and b
+        assert_eq!(deps.len(), 2);
+        assert!(deps.contains(&serde_json::json!("a")));
+        assert!(deps.contains(&serde_json::json!("b")));
+    }
 
-#[derive(Serialize)]
-struct JsonSemantic {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    covers: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    intent: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    criticality: Option<String>,
-}
+    #[test]
+    fn test_after_group_no_duplicates() {
+        let mut p = Pipeline::new();
+        let _ = p.task("a").run("echo a");
+        let _ = p.task("b").run("echo b");
+        let group = p.group("prereqs", &["a", "b"]);
 
-#[derive(Serialize)]
-struct JsonAiHooks {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    on_fail: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    select: Option<String>,
-}
+        let _ = p
+            .task("c")
+            .after(&["a"])
+            .after_group(&group)
+            .after_group(&group)
+            .run("echo c");
 
-#[derive(Serialize)]
-struct JsonTask {
-    name: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    kind: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    task_type: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    success_criteria: Option<Vec<JsonSuccessCriterion>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    command: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    primitive: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    agent: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    context: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    deterministic: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    container: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    workdir: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    env: Option<HashMap<String, String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    mounts: Option<Vec<JsonMount>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    inputs: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    task_inputs: Option<Vec<JsonTaskInput>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    outputs: Option<HashMap<String, String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    depends_on: Option<Vec<String>>,
-    #[serde(rename = "when", skip_serializing_if = "Option::is_none")]
-    condition: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    secrets: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    secret_refs: Option<Vec<JsonSecretRef>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    matrix: Option<HashMap<String, Vec<String>>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    services: Option<Vec<JsonService>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    retry: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    timeout: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    k8s: Option<JsonK8sOptions>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    requires: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    provides: Option<Vec<JsonProvide>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    needs: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    semantic: Option<JsonSemantic>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    ai_hooks: Option<JsonAiHooks>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    gate: Option<JsonGate>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    verify: Option<String>,
-}
+        let json = emit_json(&p);
+        assert_eq!(depends_on(&json, "c"), ["a", "b"]);
+    }
 
-#[derive(Serialize)]
-struct JsonGate {
-    strategy: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    timeout: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    env_var: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    file_path: Option<String>,
-}
+    #[test]
+    #[should_panic(expected = "group \"checks\": unknown task \"tset\" (did you mean \"test\"?)")]
+    fn test_group_unknown_task_panics_with_suggestion() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        let _ = p.group("checks", &["tset"]);
+    }
 
-/// Minimal K8s options for JSON serialization.
-/// For advanced options, use k8s_raw which is passed through as-is.
-#[derive(Serialize)]
-struct JsonK8sOptions {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    memory: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    cpu: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    gpu: Option<u32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    raw: Option<String>,
-}
+    #[test]
+    fn test_unchecked_group_fails_at_emit_with_suggestion() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let group = TaskGroup::new("checks", vec!["lnit".to_string()]);
+        let _ = p.task("build").after_group(&group).run("cargo build");
 
-fn convert_k8s_options(opts: &K8sOptions, raw: Option<&String>) -> JsonK8sOptions {
-    JsonK8sOptions {
-        memory: opts.memory.clone(),
-        cpu: opts.cpu.clone(),
-        gpu: opts.gpu,
-        raw: raw.cloned(),
+        let mut buf = Vec::new();
+        let err = p.emit_to(&mut buf).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "task \"build\" depends on unknown task \"lnit\" (did you mean \"lint\"?)"
+        );
     }
-}
 
-#[derive(Serialize)]
-struct JsonMount {
-    resource: String,
-    path: String,
-    #[serde(rename = "type")]
-    type_: String,
-}
+    #[test]
+    fn test_explain_shows_after_group() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p.task("test").run("cargo test");
+        let checks = p.group("checks", &["lint", "test"]);
+        let _ = p.task("build").after_group(&checks).run("cargo build");
+        let _ = p
+            .task("package")
+            .after_group(&checks)
+            .after(&["build"])
+            .run("cargo package");
 
-#[derive(Serialize)]
-struct JsonService {
-    image: String,
-    name: String,
-}
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("3. build (after group: checks)\n"));
+        // Deps beyond the group are listed by name
+        assert!(out.contains("4. package (after: lint, test, build)\n"));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // =============================================================================
+    // PARALLEL METHOD TESTS
+    // =============================================================================
 
     #[test]
-    fn test_basic_task() {
+    fn test_parallel_creates_group() {
+        let mut p = Pipeline::new();
+        p.task("lint").run("cargo clippy");
+        p.task("test").run("cargo test");
+
+        let checks = p.parallel("checks", &["lint", "test"]);
+
+        assert_eq!(checks.name, "checks");
+        assert_eq!(checks.names(), &["lint", "test"]);
+    }
+
+    #[test]
+    fn test_parallel_as_group_dependency() {
         let mut p = Pipeline::new();
+        p.task("lint").run("cargo clippy");
         p.task("test").run("cargo test");
 
+        let checks = p.parallel("checks", &["lint", "test"]);
+
+        p.task("build").after_group(&checks).run("cargo build");
+
         let mut buf = Vec::new();
         p.emit_to(&mut buf).unwrap();
         let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-        assert_eq!(json["version"], "1");
-        assert_eq!(json["tasks"][0]["name"], "test");
-        assert_eq!(json["tasks"][0]["command"], "cargo test");
+        let tasks = json["tasks"].as_array().unwrap();
+        let build = tasks.iter().find(|t| t["name"] == "build").unwrap();
+        let deps = build["depends_on"].as_array().unwrap();
+
+        assert_eq!(deps.len(), 2);
+        assert!(deps.contains(&serde_json::json!("lint")));
+        assert!(deps.contains(&serde_json::json!("test")));
     }
 
     #[test]
-    fn test_task_type_serialization() {
+    #[should_panic(expected = "task \"unknown\" not found")]
+    fn test_parallel_unknown_task_panics() {
         let mut p = Pipeline::new();
-        let _ = p
-            .task("build")
-            .run("go build ./...")
-            .task_type(TaskType::Build);
-        let _ = p
-            .task("test")
-            .run("go test ./...")
-            .task_type(TaskType::Test)
-            .after(&["build"]);
+        p.task("lint").run("cargo clippy");
+
+        // "unknown" doesn't exist
+        p.parallel("checks", &["lint", "unknown"]);
+    }
+
+    #[test]
+    fn test_gate_basic() {
+        let mut p = Pipeline::new();
+        p.task("build").run("make build");
+        p.gate("approve-deploy")
+            .after(&["build"])
+            .gate_strategy("env")
+            .gate_timeout(600)
+            .gate_message("Approve deployment to production?")
+            .gate_env_var("DEPLOY_APPROVED");
+        p.task("deploy")
+            .run("make deploy")
+            .after(&["approve-deploy"]);
 
         let mut buf = Vec::new();
         p.emit_to(&mut buf).unwrap();
         let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-        assert_eq!(json["version"], "3");
-        assert_eq!(json["tasks"][0]["task_type"], "build");
-        assert_eq!(json["tasks"][1]["task_type"], "test");
+        // Gate task has no command
+        assert_eq!(json["tasks"][1]["name"], "approve-deploy");
+        assert!(json["tasks"][1]["command"].is_null());
+        // Gate config
+        assert_eq!(json["tasks"][1]["gate"]["strategy"], "env");
+        assert_eq!(json["tasks"][1]["gate"]["timeout"], 600);
+        assert_eq!(
+            json["tasks"][1]["gate"]["message"],
+            "Approve deployment to production?"
+        );
+        assert_eq!(json["tasks"][1]["gate"]["env_var"], "DEPLOY_APPROVED");
+        // Non-gate tasks still have commands
+        assert_eq!(json["tasks"][0]["command"], "make build");
+        assert_eq!(json["tasks"][2]["command"], "make deploy");
     }
 
     #[test]
-    fn test_task_type_with_v2_features_emits_version_3() {
+    #[should_panic(expected = "gate methods can only be used on gate tasks")]
+    fn test_gate_methods_on_non_gate_panics() {
         let mut p = Pipeline::new();
-        let _ = p
-            .task("test")
-            .run("go test ./...")
-            .task_type(TaskType::Test)
-            .container("golang:1.22");
+        p.task("build").run("make build").gate_strategy("env");
+    }
+
+    #[test]
+    fn test_gate_no_command_validation() {
+        // Gates should not fail validation for missing command
+        let mut p = Pipeline::new();
+        p.gate("approve");
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
+        p.emit_to(&mut buf).unwrap(); // should not error
         let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        assert_eq!(json["version"], "3");
+        assert_eq!(json["tasks"][0]["gate"]["strategy"], "prompt");
+        assert_eq!(json["tasks"][0]["gate"]["timeout"], 3600);
     }
 
+    // ====== SECRET TEMPLATE TESTS ======
+
     #[test]
-    fn test_success_criteria_serialization() {
+    fn test_secret_template_serialization() {
         let mut p = Pipeline::new();
-        let _ = p.task("test").run("go test ./...").success_criteria(&[
-            SuccessCriterion::ExitCode(0),
-            SuccessCriterion::FileExists("coverage.out".into()),
-        ]);
+        let _ = p.task("plan").run("terraform plan").secret_template(
+            "/run/secrets/aws",
+            "id = {{key_id}}\nkey = {{secret}}\n",
+            &[
+                ("key_id", SecretRef::from_env("AWS_ACCESS_KEY_ID")),
+                ("secret", SecretRef::from_vault("secret/data/aws#key")),
+            ],
+        );
 
         let mut buf = Vec::new();
         p.emit_to(&mut buf).unwrap();
         let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
 
-        assert_eq!(json["version"], "3");
-        assert_eq!(json["tasks"][0]["success_criteria"][0]["type"], "exit_code");
-        assert_eq!(json["tasks"][0]["success_criteria"][0]["equals"], 0);
-        assert_eq!(
-            json["tasks"][0]["success_criteria"][1]["type"],
-            "file_exists"
-        );
-        assert_eq!(
-            json["tasks"][0]["success_criteria"][1]["path"],
-            "coverage.out"
-        );
+        let st = &json["tasks"][0]["secret_templates"][0];
+        assert_eq!(st["dest"], "/run/secrets/aws");
+        assert_eq!(st["template"], "id = {{key_id}}\nkey = {{secret}}\n");
+        assert_eq!(st["refs"][0]["name"], "key_id");
+        assert_eq!(st["refs"][0]["source"], "env");
+        assert_eq!(st["refs"][0]["key"], "AWS_ACCESS_KEY_ID");
+        assert_eq!(st["refs"][1]["source"], "vault");
+        // Template refs are not task env secrets
+        assert!(json["tasks"][0]["secret_refs"].is_null());
     }
 
     #[test]
-    #[should_panic(expected = "multiple exit_code success criteria are not allowed")]
-    fn test_duplicate_exit_code_success_criteria_panics() {
+    fn test_secret_template_omitted_when_unused() {
         let mut p = Pipeline::new();
-        let _ = p
-            .task("test")
-            .run("go test ./...")
-            .success_criteria(&[SuccessCriterion::ExitCode(0), SuccessCriterion::ExitCode(1)]);
+        let _ = p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        assert!(!String::from_utf8(buf).unwrap().contains("secret_templates"));
     }
 
     #[test]
-    #[should_panic(expected = "exit_code success criterion must be between 0 and 255")]
-    fn test_exit_code_success_criteria_range_panics() {
+    #[should_panic(expected = "placeholder {{token}} has no matching ref")]
+    fn test_secret_template_missing_ref_panics() {
         let mut p = Pipeline::new();
         let _ = p
-            .task("test")
-            .run("go test ./...")
-            .success_criteria(&[SuccessCriterion::ExitCode(256)]);
+            .task("t")
+            .run("true")
+            .secret_template("/run/token", "{{token}}", &[]);
     }
 
     #[test]
-    fn test_task_with_dependencies() {
+    #[should_panic(expected = "ref \"extra\" is not used by the template")]
+    fn test_secret_template_unused_ref_panics() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-        p.task("build").run("cargo build").after(&["test"]);
+        let _ = p.task("t").run("true").secret_template(
+            "/run/token",
+            "{{token}}",
+            &[
+                ("token", SecretRef::from_env("TOKEN")),
+                ("extra", SecretRef::from_env("EXTRA")),
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "must be absolute")]
+    fn test_secret_template_relative_dest_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.task("t").run("true").secret_template(
+            "secrets/token",
+            "{{token}}",
+            &[("token", SecretRef::from_env("TOKEN"))],
+        );
+    }
 
+    // ====== BARRIER TESTS ======
+
+    fn depends_on(json: &serde_json::Value, task: &str) -> Vec<String> {
+        let t = json["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|t| t["name"] == task)
+            .unwrap();
+        t["depends_on"]
+            .as_array()
+            .map(|deps| {
+                deps.iter()
+                    .map(|d| d.as_str().unwrap().to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn emit_err(p: &Pipeline) -> EmitError {
+        p.emit_to_checked(&mut Vec::new()).unwrap_err()
+    }
+
+    fn emit_json(p: &Pipeline) -> serde_json::Value {
         let mut buf = Vec::new();
         p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        serde_json::from_slice(&buf).unwrap()
+    }
 
-        assert_eq!(json["tasks"][1]["depends_on"][0], "test");
+    #[test]
+    fn test_barrier_straddling_tasks() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p.task("test").run("cargo test");
+        p.barrier("checks");
+        let _ = p.task("build").run("cargo build");
+        let _ = p.task("docs").run("cargo doc").after(&["build"]);
+
+        let json = emit_json(&p);
+        assert!(depends_on(&json, "lint").is_empty());
+        assert!(depends_on(&json, "test").is_empty());
+        assert_eq!(depends_on(&json, "build"), ["lint", "test"]);
+        assert_eq!(depends_on(&json, "docs"), ["lint", "test", "build"]);
     }
 
     #[test]
-    fn test_review_node_serialization() {
+    fn test_barriers_compose() {
         let mut p = Pipeline::new();
-        p.task("test").run("go test ./...");
-        p.review("review-code")
-            .primitive("lint")
-            .agent("claude")
-            .context(&["src/**/*.go"])
-            .after(&["test"])
-            .deterministic(true);
+        let _ = p.task("fetch").run("cargo fetch");
+        p.barrier("fetched");
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p.task("test").run("cargo test");
+        p.barrier("checks");
+        p.barrier("checks-again");
+        let _ = p.task("release").run("cargo build --release");
+
+        let json = emit_json(&p);
+        assert_eq!(depends_on(&json, "lint"), ["fetch"]);
+        assert_eq!(depends_on(&json, "test"), ["fetch"]);
+        // Only the span before "checks"; "fetch" is reached transitively.
+        assert_eq!(depends_on(&json, "release"), ["lint", "test"]);
+    }
+
+    #[test]
+    fn test_barrier_not_emitted_as_task() {
+        let mut p = Pipeline::new();
+        let _ = p.task("a").run("echo a");
+        p.barrier("join");
+        let _ = p.task("b").run("echo b");
+
+        let json = emit_json(&p);
+        let names: Vec<_> = json["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_barrier_cycle_detected() {
+        let mut p = Pipeline::new();
+        let _ = p.task("a").run("echo a").after(&["b"]);
+        p.barrier("join");
+        let _ = p.task("b").run("echo b");
+
+        assert!(matches!(emit_err(&p), EmitError::Cycle { .. }));
+    }
+
+    #[test]
+    fn test_barrier_explain_divider() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        p.barrier("checks");
+        let _ = p.task("build").run("cargo build");
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-        let review = &json["tasks"][1];
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        let divider = out.find("--- barrier: checks ---").unwrap();
+        assert!(out.find("1. test").unwrap() < divider);
+        assert!(divider < out.find("2. build").unwrap());
+    }
 
-        assert_eq!(review["name"], "review-code");
-        assert_eq!(review["kind"], "review");
-        assert_eq!(review["primitive"], "lint");
-        assert_eq!(review["agent"], "claude");
-        assert_eq!(review["context"][0], "src/**/*.go");
-        assert_eq!(review["depends_on"][0], "test");
-        assert_eq!(review["deterministic"], true);
-        assert!(review.get("command").is_none());
-        assert!(review.get("outputs").is_none());
+    // ====== TASK_OR_GET TESTS ======
+
+    #[test]
+    fn test_task_or_get_creates_missing_task() {
+        let mut p = Pipeline::new();
+        assert!(!p.has_task("sbom"));
+        let _ = p.task_or_get("sbom").run("syft .");
+        assert!(p.has_task("sbom"));
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["command"], "syft .");
+    }
+
+    #[test]
+    fn test_task_or_get_returns_existing_task() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build");
+        let _ = p.task("sbom").run("syft . -o json").after(&["build"]);
+        let _ = p
+            .task_or_get("sbom")
+            .run("syft . -o spdx")
+            .env("SYFT_QUIET", "1")
+            .after(&["build"]);
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"].as_array().unwrap().len(), 2);
+        // The user's command wins; other settings are merged in.
+        assert_eq!(json["tasks"][1]["command"], "syft . -o json");
+        assert_eq!(json["tasks"][1]["env"]["SYFT_QUIET"], "1");
+        assert_eq!(depends_on(&json, "sbom"), ["build"]);
     }
 
     #[test]
-    fn test_review_node_does_not_require_command() {
+    fn test_task_or_get_run_replace() {
         let mut p = Pipeline::new();
-        p.review("review-code").primitive("lint");
+        let _ = p.task("sbom").run("syft . -o json");
+        let _ = p.task_or_get("sbom").run_replace("syft . -o spdx");
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["command"], "syft . -o spdx");
     }
 
     #[test]
-    fn test_review_node_requires_primitive() {
+    fn test_task_or_get_fills_in_missing_command() {
         let mut p = Pipeline::new();
-        p.review("review-code");
+        let _ = p.task("sbom").env("A", "1");
+        let _ = p.task_or_get("sbom").run("syft .");
 
-        let mut buf = Vec::new();
-        let err = p.emit_to(&mut buf).unwrap_err();
-        assert!(err.to_string().contains("has no primitive"));
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["command"], "syft .");
     }
 
     #[test]
-    #[should_panic(expected = "review name cannot be empty")]
-    fn test_review_empty_name_panics() {
+    fn test_run_on_new_task_still_overrides() {
+        // Fresh handles keep last-run-wins; only task_or_get handles defer.
         let mut p = Pipeline::new();
-        p.review("");
+        let _ = p.task("test").run("cargo test").run("cargo nextest run");
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["command"], "cargo nextest run");
     }
 
     #[test]
-    #[should_panic(expected = "already exists")]
-    fn test_review_duplicate_name_panics() {
+    #[should_panic(expected = "\"approve\" exists but is not a task")]
+    fn test_task_or_get_on_gate_panics() {
         let mut p = Pipeline::new();
-        p.task("review-code").run("echo test");
-        p.review("review-code");
+        let _ = p.gate("approve");
+        let _ = p.task_or_get("approve");
     }
 
+    // ====== CONTAINER BUILD TESTS ======
+
     #[test]
-    #[should_panic(expected = "review primitive cannot be empty")]
-    fn test_review_empty_primitive_panics() {
+    fn test_container_from_dockerfile_serialization() {
         let mut p = Pipeline::new();
-        p.review("review-code").primitive("");
+        let ci = p.dir("ci");
+        let _ = p
+            .task("e2e")
+            .container_from_dockerfile(&ci, "e2e.Dockerfile")
+            .run("./run-e2e.sh");
+
+        let json = emit_json(&p);
+        let task = &json["tasks"][0];
+        assert_eq!(task["container_build"]["context_resource"], "src:ci");
+        assert_eq!(task["container_build"]["dockerfile"], "e2e.Dockerfile");
+        assert!(task["container"].is_null());
+        assert_eq!(json["resources"]["src:ci"]["type"], "directory");
     }
 
     #[test]
-    fn test_container_task() {
+    #[should_panic(expected = "use either container() or container_from_dockerfile()")]
+    fn test_container_then_dockerfile_panics() {
         let mut p = Pipeline::new();
-        let src = p.dir(".");
-
-        p.task("test")
-            .container("rust:1.75")
-            .mount(&src, "/src")
-            .workdir("/src")
-            .run("cargo test");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let ci = p.dir("ci");
+        let _ = p
+            .task("e2e")
+            .container("ubuntu:24.04")
+            .container_from_dockerfile(&ci, "Dockerfile");
+    }
 
-        assert_eq!(json["version"], "2");
-        assert_eq!(json["tasks"][0]["container"], "rust:1.75");
-        assert_eq!(json["resources"]["src:."]["type"], "directory");
+    #[test]
+    #[should_panic(expected = "already builds its image with container_from_dockerfile()")]
+    fn test_dockerfile_then_container_panics() {
+        let mut p = Pipeline::new();
+        let ci = p.dir("ci");
+        let _ = p
+            .task("e2e")
+            .container_from_dockerfile(&ci, "Dockerfile")
+            .container("ubuntu:24.04");
     }
 
     #[test]
-    fn test_cache_mount() {
+    fn test_container_build_ignores_template_image() {
         let mut p = Pipeline::new();
-        let cache = p.cache("cargo-registry");
+        let ci = p.dir("ci");
+        let tmpl = Template::new().container("rust:1.75");
+        let _ = p
+            .task("e2e")
+            .container_from_dockerfile(&ci, "Dockerfile")
+            .from(&tmpl)
+            .run("./run-e2e.sh");
 
-        p.task("build")
-            .container("rust:1.75")
-            .mount_cache(&cache, "/usr/local/cargo/registry")
-            .run("cargo build");
+        let json = emit_json(&p);
+        assert!(json["tasks"][0]["container"].is_null());
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    // ====== OUTPUT PUBLISHING TESTS ======
 
-        assert_eq!(json["resources"]["cargo-registry"]["type"], "cache");
-        assert_eq!(json["tasks"][0]["mounts"][0]["type"], "cache");
+    fn github_release() -> PublishDest {
+        PublishDest::GithubRelease {
+            repo: "acme/app".to_string(),
+            tag_expr: "{tag}".to_string(),
+            token: SecretRef::from_env("GITHUB_TOKEN"),
+        }
     }
 
     #[test]
-    fn test_rust_preset() {
+    fn test_publish_output_serialization() {
         let mut p = Pipeline::new();
-        p.rust().test();
-        p.rust().build("target/release/app").after(&["test"]);
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let _ = p
+            .task("release")
+            .run("cargo build --release && tar czf app.tar.gz target/release/app")
+            .output("binary", "target/release/app")
+            .output("bundle", "app.tar.gz")
+            .publish_output("binary", github_release())
+            .publish_output(
+                "bundle",
+                PublishDest::HttpPut {
+                    url_template: "https://dl.example.com/{branch}/{file}".to_string(),
+                    auth: Some(SecretRef::from_vault("secret/data/dl#token")),
+                },
+            );
 
-        assert_eq!(json["tasks"][0]["name"], "test");
-        assert_eq!(json["tasks"][0]["command"], "cargo test");
-        assert_eq!(json["tasks"][1]["name"], "build");
+        let json = emit_json(&p);
+        let publish = &json["tasks"][0]["publish"];
+        assert_eq!(
+            publish[0],
+            serde_json::json!({
+                "output": "binary",
+                "type": "github_release",
+                "repo": "acme/app",
+                "tag": "{tag}",
+                "token": {"source": "env", "key": "GITHUB_TOKEN"},
+            })
+        );
+        assert_eq!(
+            publish[1],
+            serde_json::json!({
+                "output": "bundle",
+                "type": "http_put",
+                "url": "https://dl.example.com/{branch}/{file}",
+                "auth": {"source": "vault", "key": "secret/data/dl#token"},
+            })
+        );
+        // Credentials stay references; the command is left alone
+        assert_eq!(
+            json["tasks"][0]["command"],
+            "cargo build --release && tar czf app.tar.gz target/release/app"
+        );
     }
 
     #[test]
-    #[should_panic(expected = "task name cannot be empty")]
-    fn test_empty_task_name_panics() {
+    fn test_publish_omitted_when_unused() {
         let mut p = Pipeline::new();
-        p.task("");
+        let _ = p.task("test").run("cargo test");
+
+        let json = emit_json(&p);
+        assert!(json["tasks"][0]["publish"].is_null());
     }
 
     #[test]
-    #[should_panic(expected = "already exists")]
-    fn test_duplicate_task_panics() {
+    fn test_publish_unknown_output_fails_emit() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-        p.task("test").run("cargo test");
+        let _ = p
+            .task("release")
+            .run("cargo build --release")
+            .output("binary", "target/release/app")
+            .publish_output("bundle", github_release());
+
+        assert!(matches!(
+            emit_err(&p),
+            EmitError::UnknownPublishedOutput { task, output }
+                if task == "release" && output == "bundle"
+        ));
     }
 
     #[test]
-    fn test_unknown_dependency_fails() {
+    #[should_panic(expected = "must be an http(s) URL")]
+    fn test_publish_non_http_url_panics() {
         let mut p = Pipeline::new();
-        p.task("build").run("cargo build").after(&["nonexistent"]);
-
-        let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
+        let _ = p.task("t").run("true").output("o", "o").publish_output(
+            "o",
+            PublishDest::HttpPut {
+                url_template: "ftp://example.com/o".to_string(),
+                auth: None,
+            },
+        );
     }
 
     #[test]
-    fn test_env_in_json() {
+    #[should_panic(expected = "unknown placeholder {version}")]
+    fn test_publish_unknown_placeholder_panics() {
         let mut p = Pipeline::new();
-        p.task("build")
-            .run("cargo build")
-            .env("RUST_BACKTRACE", "1")
-            .env("CARGO_TERM_COLOR", "always");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let _ = p.task("t").run("true").output("o", "o").publish_output(
+            "o",
+            PublishDest::HttpPut {
+                url_template: "https://example.com/{version}/o".to_string(),
+                auth: None,
+            },
+        );
+    }
 
-        assert_eq!(json["tasks"][0]["env"]["RUST_BACKTRACE"], "1");
-        assert_eq!(json["tasks"][0]["env"]["CARGO_TERM_COLOR"], "always");
+    #[test]
+    #[should_panic(expected = "unclosed '{'")]
+    fn test_publish_unclosed_placeholder_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.task("t").run("true").output("o", "o").publish_output(
+            "o",
+            PublishDest::HttpPut {
+                url_template: "https://example.com/{tag/o".to_string(),
+                auth: None,
+            },
+        );
     }
 
     #[test]
-    fn test_inputs_in_json() {
+    #[should_panic(expected = "must be \"owner/name\"")]
+    fn test_publish_bad_repo_panics() {
         let mut p = Pipeline::new();
-        p.task("test")
-            .run("cargo test")
-            .inputs(&["**/*.rs", "Cargo.toml"]);
+        let _ = p.task("t").run("true").output("o", "o").publish_output(
+            "o",
+            PublishDest::GithubRelease {
+                repo: "acme".to_string(),
+                tag_expr: "{tag}".to_string(),
+                token: SecretRef::from_env("GITHUB_TOKEN"),
+            },
+        );
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    // ====== BARE TASK TESTS ======
 
-        let inputs = json["tasks"][0]["inputs"].as_array().unwrap();
-        assert_eq!(inputs.len(), 2);
-        assert_eq!(inputs[0], "**/*.rs");
-        assert_eq!(inputs[1], "Cargo.toml");
+    #[test]
+    fn test_bare_task_skips_pipeline_defaults() {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("4Gi".to_string()),
+            cpu: Some("2".to_string()),
+            ..Default::default()
+        });
+        let _ = p.task("test").run("cargo test");
+        let _ = p.task("notify").run("./notify.sh").bare();
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["k8s"]["memory"], "4Gi");
+        assert!(json["tasks"][1]["k8s"].is_null());
+        assert!(json["tasks"][1]["bare"].is_null());
     }
 
     #[test]
-    fn test_directory_glob() {
-        // Test that glob() works on Directory (returns updated Directory)
-        let mut p = Pipeline::new();
-        let src = p.dir(".");
-        let src_with_glob = src.glob(&["**/*.rs", "Cargo.toml"]);
+    fn test_bare_task_keeps_explicit_settings() {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("4Gi".to_string()),
+            cpu: Some("2".to_string()),
+            ..Default::default()
+        });
+        let tmpl = Template::new().env("REGISTRY", "ghcr.io/acme");
+        let _ = p
+            .task("push")
+            .bare()
+            .from(&tmpl)
+            .container("docker:cli")
+            .run("docker push app")
+            .k8s(K8sOptions {
+                memory: Some("256Mi".to_string()),
+                ..Default::default()
+            });
 
-        // The glob patterns are stored on the returned Directory
-        assert_eq!(src_with_glob.globs.len(), 2);
-        assert_eq!(src_with_glob.globs[0], "**/*.rs");
-        assert_eq!(src_with_glob.globs[1], "Cargo.toml");
+        let json = emit_json(&p);
+        let push = &json["tasks"][0];
+        assert_eq!(push["container"], "docker:cli");
+        assert_eq!(push["env"]["REGISTRY"], "ghcr.io/acme");
+        assert_eq!(push["k8s"]["memory"], "256Mi");
+        assert!(push["k8s"]["cpu"].is_null());
     }
 
     #[test]
-    #[should_panic(expected = "container image cannot be empty")]
-    fn test_empty_container_panics() {
+    fn test_explain_marks_bare_tasks() {
         let mut p = Pipeline::new();
-        p.task("test").container("");
+        let _ = p.task("test").run("cargo test");
+        let _ = p.task("notify").run("./notify.sh").bare().after(&["test"]);
+
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("2. notify (bare) (after: test)"));
+        assert!(!out.contains("test (bare)"));
     }
 
+    // ====== PIPELINE DEFAULTS TESTS ======
+
     #[test]
-    #[should_panic(expected = "container working directory must be absolute")]
-    fn test_relative_workdir_panics() {
+    fn test_pipeline_defaults_fill_unset_values() {
         let mut p = Pipeline::new();
-        p.task("test").workdir("relative/path");
+        p.default_timeout(900)
+            .default_retry(2)
+            .default_env("CARGO_TERM_COLOR", "always");
+        let _ = p.task("test").run("cargo test");
+        let _ = p
+            .task("e2e")
+            .run("./e2e.sh")
+            .timeout(3600)
+            .retry(1)
+            .env("CARGO_TERM_COLOR", "never");
+        let _ = p.task("notify").run("./notify.sh").bare();
+
+        let json = emit_json(&p);
+        let (test, e2e, notify) = (&json["tasks"][0], &json["tasks"][1], &json["tasks"][2]);
+        assert_eq!(test["timeout"], 900);
+        assert_eq!(test["retry"], 2);
+        assert_eq!(test["env"]["CARGO_TERM_COLOR"], "always");
+        assert_eq!(e2e["timeout"], 3600);
+        assert_eq!(e2e["retry"], 1);
+        assert_eq!(e2e["env"]["CARGO_TERM_COLOR"], "never");
+        assert!(notify.get("timeout").is_none());
+        assert!(notify.get("retry").is_none());
+        assert!(notify.get("env").is_none());
     }
 
     #[test]
-    #[should_panic(expected = "output name cannot be empty")]
-    fn test_empty_output_name_panics() {
+    fn test_pipeline_defaults_apply_to_tasks_declared_earlier() {
         let mut p = Pipeline::new();
-        p.task("build").run("cargo build").output("", "./app");
+        let _ = p.task("test").run("cargo test");
+        p.default_timeout(900);
+
+        assert_eq!(emit_json(&p)["tasks"][0]["timeout"], 900);
+        assert_eq!(p.iter_tasks().next().unwrap().timeout(), Some(900));
     }
 
     #[test]
-    #[should_panic(expected = "output path cannot be empty")]
-    fn test_empty_output_path_panics() {
+    fn test_template_env_beats_pipeline_default() {
         let mut p = Pipeline::new();
-        p.task("build").run("cargo build").output("binary", "");
+        p.default_env("RUST_LOG", "info")
+            .default_env("CARGO_TERM_COLOR", "always")
+            .default_timeout(900);
+        let tmpl = Template::new().env("RUST_LOG", "debug").timeout(600);
+        let _ = p.task("test").from(&tmpl).run("cargo test");
+        let _ = p
+            .task("bench")
+            .from(&tmpl)
+            .env("RUST_LOG", "warn")
+            .timeout(1800)
+            .run("cargo bench");
+
+        let json = emit_json(&p);
+        let (test, bench) = (&json["tasks"][0], &json["tasks"][1]);
+        assert_eq!(test["env"]["RUST_LOG"], "debug");
+        assert_eq!(test["env"]["CARGO_TERM_COLOR"], "always");
+        assert_eq!(test["timeout"], 600);
+        assert_eq!(bench["env"]["RUST_LOG"], "warn");
+        assert_eq!(bench["timeout"], 1800);
     }
 
     #[test]
-    #[should_panic(expected = "environment variable key cannot be empty")]
-    fn test_empty_env_key_panics() {
+    fn test_explain_shows_applied_defaults() {
         let mut p = Pipeline::new();
-        p.task("test").env("", "value");
+        p.default_timeout(900)
+            .default_retry(2)
+            .default_env("CARGO_TERM_COLOR", "always");
+        let _ = p.task("test").run("cargo test");
+        let _ = p
+            .task("e2e")
+            .run("./e2e.sh")
+            .timeout(3600)
+            .retry(1)
+            .env("CARGO_TERM_COLOR", "never");
+
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains("   Defaults: timeout 15m, retry 2, CARGO_TERM_COLOR=always\n"),
+            "{}",
+            out
+        );
+        assert_eq!(out.matches("Defaults:").count(), 1, "{}", out);
     }
 
     #[test]
-    #[should_panic(expected = "container mount path must be absolute")]
-    fn test_relative_mount_path_panics() {
+    fn test_pipeline_secrets_merge_and_dedup() {
         let mut p = Pipeline::new();
-        let src = p.dir(".");
-        p.task("test").mount(&src, "relative");
+        p.secret("DEPLOY_TOKEN");
+        p.secret("DEPLOY_TOKEN");
+        let _ = p.task("deploy").run("./deploy.sh").secret("NPM_TOKEN");
+        let _ = p.task("publish").run("./publish.sh").secret("DEPLOY_TOKEN");
+        let _ = p.task("push").run("./push.sh").bare();
+        let _ = p.gate("approve").after(&["deploy"]);
+
+        let json = emit_json(&p);
+        let tasks = json["tasks"].as_array().unwrap();
+        assert_eq!(
+            tasks[0]["secrets"],
+            serde_json::json!(["NPM_TOKEN", "DEPLOY_TOKEN"])
+        );
+        assert_eq!(tasks[1]["secrets"], serde_json::json!(["DEPLOY_TOKEN"]));
+        assert!(tasks[2]["secrets"].is_null());
+        assert!(tasks[3]["secrets"].is_null());
     }
 
     #[test]
-    #[should_panic(expected = "container mount path cannot be empty")]
-    fn test_empty_mount_path_panics() {
+    fn test_pipeline_secrets_for_tasks_matching() {
         let mut p = Pipeline::new();
-        let src = p.dir(".");
-        p.task("test").mount(&src, "");
+        p.secret_from("DEPLOY_TOKEN", SecretRef::from_env("CI_DEPLOY_TOKEN"))
+            .for_tasks_matching("deploy-*");
+        p.secret("REGION_KEY").for_tasks_matching("deploy-??");
+        let _ = p.task("test").run("cargo test");
+        let _ = p.task("deploy-eu").run("./deploy.sh eu");
+        let _ = p.task("deploy-apac").run("./deploy.sh apac");
+
+        let json = emit_json(&p);
+        let tasks = json["tasks"].as_array().unwrap();
+        assert!(tasks[0]["secret_refs"].is_null());
+        assert!(tasks[0]["secrets"].is_null());
+        assert_eq!(tasks[1]["secret_refs"][0]["key"], "CI_DEPLOY_TOKEN");
+        assert_eq!(tasks[1]["secrets"], serde_json::json!(["REGION_KEY"]));
+        assert_eq!(tasks[2]["secret_refs"][0]["name"], "DEPLOY_TOKEN");
+        assert!(tasks[2]["secrets"].is_null());
     }
 
     #[test]
-    #[should_panic(expected = "container working directory cannot be empty")]
-    fn test_empty_workdir_panics() {
+    fn test_pipeline_secret_conflicts() {
         let mut p = Pipeline::new();
-        p.task("test").workdir("");
+        p.secret_from("TOKEN", SecretRef::from_env("CI_TOKEN"));
+        p.secret_from("TOKEN", SecretRef::from_vault("secret/deploy#token"))
+            .for_tasks_matching("deploy");
+        let _ = p.task("test").run("cargo test");
+        let _ = p.task("deploy").run("./deploy.sh");
+        let _ = p
+            .task("release")
+            .run("./release.sh")
+            .secret_from("TOKEN", SecretRef::from_file("/etc/ci/token"));
+
+        let json = emit_json(&p);
+        let refs: Vec<&serde_json::Value> = json["tasks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| &t["secret_refs"])
+            .collect();
+        assert_eq!(refs[0][0]["source"], "env");
+        assert_eq!(refs[1][0]["source"], "vault");
+        assert_eq!(refs[1].as_array().unwrap().len(), 1);
+        assert_eq!(refs[2][0]["source"], "file");
+        assert_eq!(refs[2].as_array().unwrap().len(), 1);
     }
 
     #[test]
-    fn test_rust_preset_inputs() {
+    fn test_explain_marks_inherited_secrets() {
         let mut p = Pipeline::new();
-        p.rust().test();
+        p.secret("DEPLOY_TOKEN");
+        p.secret_from("KUBECONFIG", SecretRef::from_k8s("ci/deployer#config"))
+            .for_tasks_matching("deploy");
+        let _ = p.task("deploy").run("./deploy.sh").secret("NPM_TOKEN");
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains(
+                "   Secrets: NPM_TOKEN, DEPLOY_TOKEN (inherited), KUBECONFIG (k8s:ci/deployer#config) (inherited)\n"
+            ),
+            "{}",
+            out
+        );
 
-        let inputs = json["tasks"][0]["inputs"].as_array().unwrap();
-        assert!(inputs.contains(&serde_json::json!("**/*.rs")));
-        assert!(inputs.contains(&serde_json::json!("Cargo.toml")));
-        assert!(inputs.contains(&serde_json::json!("Cargo.lock")));
+        let ctx = ExplainContext {
+            check_secrets: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("     DEPLOY_TOKEN (env:DEPLOY_TOKEN) \u{2717} MISSING (inherited)\n"));
+        assert!(
+            out.contains("     KUBECONFIG (k8s:ci/deployer#config) unchecked (k8s) (inherited)\n")
+        );
+    }
+
+    // ====== ENV FILE TESTS ======
+
+    /// Writes `contents` to a fresh `name` file, returning its path.
+    fn write_env_file(name: &str, contents: &str) -> String {
+        let path = temp_path(name).with_file_name(".env");
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
     }
 
     #[test]
-    fn test_rust_preset_lint_command() {
+    fn test_env_file_precedence() {
+        let base = write_env_file("env-base", "A=base\r\nB=base\r\nC=base\r\n");
+        let local = write_env_file("env-local", "# overrides\nB=\"local\"\nD=\n");
         let mut p = Pipeline::new();
-        p.rust().lint();
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.default_env("C", "default").default_env("E", "default");
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .env("A", "explicit")
+            .env_file(&base)
+            .env_file(&local);
 
-        assert_eq!(json["tasks"][0]["command"], "cargo clippy -- -D warnings");
+        let env = &emit_json(&p)["tasks"][0]["env"];
+        assert_eq!(
+            *env,
+            serde_json::json!({
+                "A": "explicit",
+                "B": "local",
+                "C": "base",
+                "D": "",
+                "E": "default",
+            })
+        );
+        assert!(emit_json(&p)["tasks"][0].get("env_files").is_none());
     }
 
     #[test]
-    fn test_rust_preset_build_output() {
+    fn test_env_file_missing_or_malformed_fails_emit() {
         let mut p = Pipeline::new();
-        p.rust().build("target/release/myapp");
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .env_file("does/not/exist.env");
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        assert!(
+            matches!(err, EmitError::EnvFile { ref task, ref path, .. } if task == "test" && path == "does/not/exist.env")
+        );
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let bad = write_env_file("env-bad", "OK=1\nnot a pair\n");
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test").env_file(&bad);
+        assert_eq!(
+            p.emit_to_checked(&mut Vec::new()).unwrap_err().to_string(),
+            format!(
+                "task \"test\" can't read env file {:?}: line 2: expected KEY=value",
+                bad
+            )
+        );
+    }
 
+    #[test]
+    fn test_env_file_lazy_is_emitted() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .env_file_lazy("/etc/ci/deploy.env")
+            .env_file_lazy(".env");
+
+        let json = emit_json(&p);
         assert_eq!(
-            json["tasks"][0]["outputs"]["output_0"],
-            "target/release/myapp"
+            json["tasks"][0]["env_files"],
+            serde_json::json!(["/etc/ci/deploy.env", ".env"])
         );
+        assert!(json["tasks"][0].get("env").is_none());
+        assert_eq!(json["requires"]["runner"], ">=0.6.2");
+        let deploy = p.iter_tasks().next().unwrap();
+        assert_eq!(deploy.runtime_env_files(), ["/etc/ci/deploy.env", ".env"]);
+    }
+
+    // ====== RESOURCE ID TESTS ======
+
+    #[test]
+    fn test_normalized_dir_spellings_collapse() {
+        let mut p = Pipeline::new();
+        p.normalize_resource_ids();
+        let a = p.dir(".");
+        let b = p.dir("./");
+        let c = p.dir("./x/..");
+        let _ = p
+            .task("build")
+            .run("make")
+            .mount(&a, "/src")
+            .mount(&c, "/c");
+
+        assert_eq!(a.id(), "src:.");
+        assert_eq!(b.id(), "src:.");
+        assert_eq!(c.id(), "src:.");
+        assert_eq!(p.resources().count(), 1);
+
+        let json = emit_json(&p);
+        let resources = json["resources"].as_object().unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources["src:."]["path"], ".");
     }
 
     #[test]
-    fn test_version_v1_simple_tasks() {
+    fn test_dir_ids_unchanged_without_normalization() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-        p.task("build").run("cargo build");
+        assert_eq!(p.dir("./").id(), "src:./");
+        assert_eq!(p.dir(".").id(), "src:.");
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    #[test]
+    fn test_normalize_dir_path() {
+        assert_eq!(normalize_dir_path("src/"), "src");
+        assert_eq!(normalize_dir_path("./a/./b//c/"), "a/b/c");
+        assert_eq!(normalize_dir_path("a/../../b"), "../b");
+        assert_eq!(normalize_dir_path("/opt/../srv/"), "/srv");
+        assert_eq!(normalize_dir_path("/.."), "/");
 
-        assert_eq!(json["version"], "1");
-        assert!(json["resources"].is_null());
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(normalize_dir_path(cwd.to_str().unwrap()), ".");
+        assert_eq!(normalize_dir_path(cwd.join("web").to_str().unwrap()), "web");
     }
 
     #[test]
-    fn test_version_v2_with_dir() {
+    #[should_panic(expected = "must be called before dir()")]
+    fn test_normalize_resource_ids_after_dir_panics() {
         let mut p = Pipeline::new();
-        let _src = p.dir(".");
-        p.task("test").run("cargo test");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        assert_eq!(json["version"], "2");
+        let _ = p.dir(".");
+        p.normalize_resource_ids();
     }
 
     #[test]
-    fn test_version_v2_with_cache() {
+    fn test_cache_with_key_serialization() {
         let mut p = Pipeline::new();
-        let _cache = p.cache("test-cache");
-        p.task("test").run("cargo test");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let shared = p.cache_with_key("cargo-registry", "acme-cargo");
+        let local = p.cache("target");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount_cache(&shared, "/usr/local/cargo/registry")
+            .mount_cache(&local, "/src/target")
+            .run("cargo build");
 
-        assert_eq!(json["version"], "2");
+        let json = emit_json(&p);
+        assert_eq!(shared.id(), "cargo-registry");
+        assert_eq!(json["resources"]["cargo-registry"]["key"], "acme-cargo");
+        assert!(json["resources"]["target"]["key"].is_null());
     }
 
     #[test]
-    fn test_version_v2_with_container() {
+    fn test_cache_key_recipe() {
         let mut p = Pipeline::new();
-        p.task("test").container("rust:1.75").run("cargo test");
+        let cargo = p
+            .cache("cargo")
+            .key_files(&["Cargo.lock", "rust-toolchain.toml"])
+            .scope(CacheScope::Branch)
+            .fallback_scope(CacheScope::Default);
+        let target = p.cache("target");
+        let _ = p
+            .task("build")
+            .container("rust:1.80")
+            .mount_cache(&cargo, "/usr/local/cargo/registry")
+            .mount_cache(&target, "/src/target")
+            .run("cargo build");
+        // Mounting the plain handle shares the same volume and recipe.
+        let plain = p.cache("cargo");
+        let _ = p
+            .task("test")
+            .container("rust:1.80")
+            .mount_cache(&plain, "/usr/local/cargo/registry")
+            .run("cargo test")
+            .after(&["build"]);
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["resources"]["cargo"],
+            serde_json::json!({
+                "type": "cache",
+                "name": "cargo",
+                "key_files": ["Cargo.lock", "rust-toolchain.toml"],
+                "scope": "branch",
+                "fallback": "default"
+            })
+        );
+        assert_eq!(
+            json["resources"]["target"],
+            serde_json::json!({"type": "cache", "name": "target"})
+        );
+        assert_eq!(json["requires"]["runner"], ">=0.6.2");
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        let recipe = "   Cache: cargo at /usr/local/cargo/registry, key \
+                      cargo-${hash(Cargo.lock, rust-toolchain.toml)} per branch, \
+                      else from the default branch\n";
+        assert_eq!(out.matches(recipe).count(), 2);
+        assert!(!out.contains("Cache: target"));
 
-        assert_eq!(json["version"], "2");
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
     }
 
     #[test]
-    fn test_when_branch_condition() {
+    fn test_cache_limits() {
         let mut p = Pipeline::new();
-        p.task("deploy").run("./deploy.sh").when("branch == 'main'");
+        let target = p.cache("target").max_size("10Gi").eviction(Eviction::Lru);
+        let _ = p
+            .task("build")
+            .container("rust:1.80")
+            .mount_cache(&target, "/src/target")
+            .run("cargo build");
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["resources"]["target"],
+            serde_json::json!({
+                "type": "cache",
+                "name": "target",
+                "max_size": "10Gi",
+                "eviction": "lru"
+            })
+        );
+        assert_eq!(json["requires"]["runner"], ">=0.6.2");
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains(
+            "   Cache: target at /src/target, key target, max size 10Gi, lru eviction\n"
+        ));
 
-        assert_eq!(json["tasks"][0]["when"], "branch == 'main'");
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+        let opts = loaded.resources().next().unwrap().volume_options().unwrap();
+        assert_eq!(opts.max_size.as_deref(), Some("10Gi"));
+        assert_eq!(opts.eviction, Some(Eviction::Lru));
+        assert!(opts.size.is_none());
     }
 
     #[test]
-    fn test_when_tag_condition() {
+    fn test_cache_sharing_modes() {
         let mut p = Pipeline::new();
-        p.task("release").run("./release.sh").when("tag != ''");
+        let target = p.cache("target");
+        let _ = p
+            .task("build")
+            .mount_cache_with(&target, "/src/target", CacheSharing::Locked)
+            .run("cargo build");
+        let _ = p
+            .task("test")
+            .mount_cache_with(&target, "/src/target", CacheSharing::Private)
+            .run("cargo test");
+        let _ = p
+            .task("doc")
+            .mount_cache(&target, "/src/target")
+            .run("cargo doc");
+
+        let json = emit_json(&p);
+        let mount = |i: usize| json["tasks"][i]["mounts"][0].clone();
+        assert_eq!(mount(0)["sharing"], "locked");
+        assert_eq!(mount(1)["sharing"], "private");
+        assert!(mount(2).get("sharing").is_none());
+        assert_eq!(json["requires"]["runner"], ">=0.6.2");
+
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), p.to_json().unwrap());
+        let sharing: Vec<_> = loaded
+            .iter_tasks()
+            .map(|t| t.mounts().next().unwrap().sharing())
+            .collect();
+        assert_eq!(
+            sharing,
+            [
+                CacheSharing::Locked,
+                CacheSharing::Private,
+                CacheSharing::Shared
+            ]
+        );
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    #[test]
+    fn test_cache_shared_mode_is_not_a_feature() {
+        let mut p = Pipeline::new();
+        let target = p.cache("target");
+        let _ = p
+            .task("build")
+            .mount_cache_with(&target, "/src/target", CacheSharing::Shared)
+            .run("cargo build");
 
-        assert_eq!(json["tasks"][0]["when"], "tag != ''");
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["mounts"][0],
+            serde_json::json!({"resource": "target", "path": "/src/target", "type": "cache"})
+        );
+        assert!(json.get("requires").is_none());
     }
 
     #[test]
-    fn test_when_not_set() {
+    #[should_panic(expected = "cache \"cargo-registry\" already exists with key None")]
+    fn test_cache_conflicting_key_panics() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
+        let _ = p.cache("cargo-registry");
+        let _ = p.cache_with_key("cargo-registry", "shared");
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    // ====== TASK HANDLE TESTS ======
 
-        assert!(json["tasks"][0]["when"].is_null());
+    #[test]
+    fn test_after_accepts_each_into_deps() {
+        let mut p = Pipeline::new();
+        let build = p.task("build").run("cargo build").handle();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p.task("fmt").run("cargo fmt --check");
+        let group = p.parallel("checks", &["lint", "fmt"]);
+        let owned = vec!["lint".to_string()];
+
+        let _ = p.task("a").run("true").after("lint");
+        let _ = p.task("b").run("true").after(&["lint", "fmt"]);
+        let _ = p.task("c").run("true").after(&owned);
+        let _ = p.task("d").run("true").after(&group);
+        let _ = p.task("e").run("true").after(&build);
+        let _ = p.task("f").run("true").after(build.clone());
+
+        let json = emit_json(&p);
+        assert_eq!(depends_on(&json, "a"), ["lint"]);
+        assert_eq!(depends_on(&json, "b"), ["lint", "fmt"]);
+        assert_eq!(depends_on(&json, "c"), ["lint"]);
+        assert_eq!(depends_on(&json, "d"), ["lint", "fmt"]);
+        assert_eq!(depends_on(&json, "e"), ["build"]);
+        assert_eq!(depends_on(&json, "f"), ["build"]);
     }
 
     #[test]
-    #[should_panic(expected = "condition cannot be empty")]
-    fn test_when_empty_panics() {
+    fn test_after_mixed_deps() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test").when("");
+        let build = p.task("build").run("cargo build").handle();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p.task("test").run("cargo test");
+        let tests = p.parallel("tests", &["test"]);
+
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .after((&build, "lint", &tests))
+            .after(&build);
+
+        let json = emit_json(&p);
+        assert_eq!(depends_on(&json, "deploy"), ["build", "lint", "test"]);
     }
 
     #[test]
-    fn test_when_with_other_options() {
+    fn test_handle_from_other_pipeline_rejected() {
+        let mut other = Pipeline::new();
+        let foreign = other.task("build").run("make").handle();
+
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-        p.task("build").run("cargo build");
-        p.task("deploy")
-            .run("./deploy.sh")
-            .after(&["test", "build"])
-            .when("branch == 'main'");
+        let _ = p.task("build").run("cargo build");
+        let _ = p.task("deploy").run("./deploy.sh").after(&foreign);
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        assert_eq!(json["tasks"][2]["when"], "branch == 'main'");
-        assert_eq!(json["tasks"][2]["depends_on"][0], "test");
-        assert_eq!(json["tasks"][2]["depends_on"][1], "build");
+        let err = p.emit_to(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "task \"deploy\" depends on a handle to \"build\" from another pipeline"
+        );
     }
 
-    // ----- SECRET TESTS -----
+    // ====== MOUNT CWD TESTS ======
 
     #[test]
-    fn test_secret_single() {
+    fn test_mount_cwd_registers_resource() {
         let mut p = Pipeline::new();
-        p.task("deploy").run("./deploy.sh").secret("GITHUB_TOKEN");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .mount_cwd()
+            .run("cargo test");
 
-        let secrets = json["tasks"][0]["secrets"].as_array().unwrap();
-        assert_eq!(secrets.len(), 1);
-        assert_eq!(secrets[0], "GITHUB_TOKEN");
+        let json = emit_json(&p);
+        let task = &json["tasks"][0];
+        assert_eq!(task["workdir"], "/work");
+        assert_eq!(task["mounts"][0]["resource"], "src:.");
+        assert_eq!(task["mounts"][0]["path"], "/work");
+        assert_eq!(task["mounts"][0]["type"], "directory");
+        assert_eq!(json["resources"]["src:."]["type"], "directory");
+        assert_eq!(json["resources"]["src:."]["path"], ".");
     }
 
     #[test]
-    fn test_secret_multiple() {
+    fn test_mount_cwd_twice_single_mount() {
         let mut p = Pipeline::new();
-        p.task("deploy")
-            .run("./deploy.sh")
-            .secret("GITHUB_TOKEN")
-            .secret("NPM_TOKEN")
-            .secret("AWS_ACCESS_KEY");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let _ = p.dir(".");
+        let _ = p.task("a").container("alpine").mount_cwd().run("ls");
+        let _ = p
+            .task("b")
+            .container("alpine")
+            .mount_cwd()
+            .mount_cwd()
+            .run("ls");
 
-        let secrets = json["tasks"][0]["secrets"].as_array().unwrap();
-        assert_eq!(secrets.len(), 3);
-        assert!(secrets.contains(&serde_json::json!("GITHUB_TOKEN")));
-        assert!(secrets.contains(&serde_json::json!("NPM_TOKEN")));
-        assert!(secrets.contains(&serde_json::json!("AWS_ACCESS_KEY")));
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][1]["mounts"].as_array().unwrap().len(), 1);
+        assert_eq!(p.resources().count(), 1);
     }
 
     #[test]
-    fn test_secret_not_set() {
+    fn test_mount_cwd_keeps_explicit_workdir() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .workdir("/work/crates/core")
+            .mount_cwd()
+            .run("cargo test");
 
-        assert!(json["tasks"][0]["secrets"].is_null());
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["workdir"], "/work/crates/core");
+        assert_eq!(json["tasks"][0]["mounts"][0]["path"], "/work");
     }
 
+    // ====== NAMED TEMPLATE TESTS ======
+
     #[test]
-    #[should_panic(expected = "secret name cannot be empty")]
-    fn test_secret_empty_panics() {
+    fn test_named_template_applies_and_emits() {
         let mut p = Pipeline::new();
-        p.task("deploy").run("./deploy.sh").secret("");
+        let src = p.dir(".");
+        let registry = p.cache("cargo-registry");
+        let _ = p
+            .template("rust")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .workdir("/src")
+            .env("CARGO_TERM_COLOR", "always");
+
+        let rust = p.get_template("rust").unwrap().clone();
+        assert_eq!(rust.name(), Some("rust"));
+        let _ = p.task("test").from(&rust).run("cargo test");
+        let _ = p.task("plain").run("echo hi");
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["template"], "rust");
+        assert_eq!(json["tasks"][0]["container"], "rust:1.75");
+        assert!(json["tasks"][1]["template"].is_null());
+
+        let tmpl = &json["templates"]["rust"];
+        assert_eq!(tmpl["container"], "rust:1.75");
+        assert_eq!(tmpl["workdir"], "/src");
+        assert_eq!(tmpl["env"]["CARGO_TERM_COLOR"], "always");
+        assert_eq!(tmpl["mounts"].as_array().unwrap().len(), 2);
     }
 
     #[test]
-    fn test_secrets_method() {
+    fn test_unnamed_templates_not_emitted() {
         let mut p = Pipeline::new();
-        p.task("deploy")
-            .run("./deploy.sh")
-            .secrets(&["GITHUB_TOKEN", "NPM_TOKEN"]);
+        let tmpl = Template::new().container("alpine");
+        let _ = p.task("a").from(&tmpl).run("ls");
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let json = emit_json(&p);
+        assert!(json.get("templates").is_none());
+        assert!(json["tasks"][0]["template"].is_null());
+        assert!(p.get_template("alpine").is_none());
+    }
 
-        let secrets = json["tasks"][0]["secrets"].as_array().unwrap();
-        assert_eq!(secrets.len(), 2);
+    #[test]
+    #[should_panic(expected = "template \"rust\" already exists")]
+    fn test_duplicate_template_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.template("rust").container("rust:1.75");
+        let _ = p.template("rust");
     }
 
-    // ----- MATRIX TESTS -----
+    // ====== TEMPLATE FIELD TESTS ======
 
     #[test]
-    fn test_matrix_single_dimension() {
+    fn test_template_inputs_and_secrets() {
         let mut p = Pipeline::new();
-        p.task("test")
-            .run("cargo test")
-            .matrix("rust_version", &["1.70", "1.75", "1.80"]);
+        let rust = Template::new()
+            .inputs(&["**/*.rs", "Cargo.toml"])
+            .secret("CARGO_TOKEN");
+        let _ = p
+            .task("test")
+            .inputs(&["Cargo.toml", "tests/**"])
+            .secret("CODECOV_TOKEN")
+            .from(&rust)
+            .run("cargo test");
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["inputs"],
+            serde_json::json!(["**/*.rs", "Cargo.toml", "tests/**"])
+        );
+        assert_eq!(
+            json["tasks"][0]["secrets"],
+            serde_json::json!(["CARGO_TOKEN", "CODECOV_TOKEN"])
+        );
+    }
 
-        let matrix = json["tasks"][0]["matrix"].as_object().unwrap();
-        assert_eq!(matrix.len(), 1);
-        let versions = matrix["rust_version"].as_array().unwrap();
-        assert_eq!(versions.len(), 3);
-        assert_eq!(versions[0], "1.70");
+    #[test]
+    fn test_template_retry_and_timeout() {
+        let mut p = Pipeline::new();
+        let rust = Template::new().retry(2).timeout(600);
+        let _ = p.task("a").from(&rust).run("cargo build");
+        let _ = p.task("b").retry(5).from(&rust).run("cargo test");
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["retry"], 2);
+        assert_eq!(json["tasks"][0]["timeout"], 600);
+        assert_eq!(json["tasks"][1]["retry"], 5);
     }
 
     #[test]
-    fn test_matrix_multiple_dimensions() {
+    fn test_task_timeout_wins_over_template() {
         let mut p = Pipeline::new();
-        p.task("test")
-            .run("cargo test")
-            .matrix("rust_version", &["1.70", "1.75"])
-            .matrix("os", &["ubuntu", "macos"]);
+        let rust = Template::new().timeout(600);
+        let _ = p.task("before").timeout(60).from(&rust).run("make");
+        let _ = p.task("after").from(&rust).timeout(60).run("make");
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["timeout"], 60);
+        assert_eq!(json["tasks"][1]["timeout"], 60);
+    }
 
-        let matrix = json["tasks"][0]["matrix"].as_object().unwrap();
-        assert_eq!(matrix.len(), 2);
-        assert!(matrix.contains_key("rust_version"));
-        assert!(matrix.contains_key("os"));
+    #[test]
+    fn test_template_k8s_merges_with_task() {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            cpu: Some("1".to_string()),
+            ..Default::default()
+        });
+        let rust = Template::new().k8s(K8sOptions {
+            memory: Some("4Gi".to_string()),
+            cpu: Some("2".to_string()),
+            ..Default::default()
+        });
+        let _ = p.task("plain").from(&rust).run("cargo build");
+        let _ = p
+            .task("big")
+            .from(&rust)
+            .k8s(K8sOptions {
+                memory: Some("8Gi".to_string()),
+                ..Default::default()
+            })
+            .run("cargo test");
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["k8s"]["memory"], "4Gi");
+        assert_eq!(json["tasks"][0]["k8s"]["cpu"], "2");
+        assert_eq!(json["tasks"][1]["k8s"]["memory"], "8Gi");
+        assert_eq!(json["tasks"][1]["k8s"]["cpu"], "2");
     }
 
     #[test]
-    fn test_matrix_not_set() {
+    fn test_named_template_emits_new_fields() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let _ = p
+            .template("rust")
+            .inputs(&["**/*.rs"])
+            .secret("CARGO_TOKEN")
+            .retry(1)
+            .timeout(600)
+            .k8s(K8sOptions {
+                memory: Some("4Gi".to_string()),
+                ..Default::default()
+            });
+        let _ = p.task("test").run("cargo test");
 
-        assert!(json["tasks"][0]["matrix"].is_null());
+        let tmpl = &emit_json(&p)["templates"]["rust"];
+        assert_eq!(tmpl["inputs"][0], "**/*.rs");
+        assert_eq!(tmpl["secrets"][0], "CARGO_TOKEN");
+        assert_eq!(tmpl["retry"], 1);
+        assert_eq!(tmpl["timeout"], 600);
+        assert_eq!(tmpl["k8s"]["memory"], "4Gi");
     }
 
+    // ====== TEMPLATE EXTEND TESTS ======
+
     #[test]
-    #[should_panic(expected = "matrix key cannot be empty")]
-    fn test_matrix_empty_key_panics() {
+    fn test_template_extend_overrides_workdir() {
+        let base = Template::new()
+            .container("rust:1.75")
+            .workdir("/src")
+            .env("A", "base")
+            .env("B", "base");
+        let ext = base.extend(&Template::new().workdir("/app").env("B", "ext"));
+
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test").matrix("", &["value"]);
+        let _ = p.task("extended").from(&ext).run("make");
+        let _ = p
+            .task("manual")
+            .container("rust:1.75")
+            .workdir("/app")
+            .env("A", "base")
+            .env("B", "ext")
+            .run("make");
+
+        let json = emit_json(&p);
+        let (extended, manual) = (&json["tasks"][0], &json["tasks"][1]);
+        assert_eq!(extended["workdir"], "/app");
+        for field in ["container", "workdir", "env"] {
+            assert_eq!(extended[field], manual[field], "{}", field);
+        }
     }
 
     #[test]
-    #[should_panic(expected = "matrix values cannot be empty")]
-    fn test_matrix_empty_values_panics() {
+    fn test_template_extend_appends_mounts_in_order() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test").matrix("key", &[]);
+        let src = p.dir(".");
+        let registry = p.cache("cargo-registry");
+        let base = Template::new().mount_dir(&src, "/src");
+        let rust = base.extend(&Template::new().mount_cache(&registry, "/cargo"));
+        let _ = p.task("test").from(&rust).run("cargo test");
+
+        let json = emit_json(&p);
+        let mounts = json["tasks"][0]["mounts"].as_array().unwrap();
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0]["path"], "/src");
+        assert_eq!(mounts[1]["path"], "/cargo");
+        assert_eq!(mounts[1]["type"], "cache");
     }
 
-    // ----- SERVICE TESTS -----
+    // ====== CONDITION EVALUATE TESTS ======
 
     #[test]
-    fn test_service_single() {
-        let mut p = Pipeline::new();
-        p.task("test")
-            .run("cargo test")
-            .service("postgres:15", "db");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        let services = json["tasks"][0]["services"].as_array().unwrap();
-        assert_eq!(services.len(), 1);
-        assert_eq!(services[0]["image"], "postgres:15");
-        assert_eq!(services[0]["name"], "db");
+    fn test_condition_evaluate_compound() {
+        let ctx = ExplainContext {
+            branch: "release/1.2".to_string(),
+            event: "push".to_string(),
+            ci: true,
+            ..Default::default()
+        };
+        let release = Condition::branch("release/*").and(Condition::in_ci());
+        assert_eq!(release.evaluate(&ctx), condition::Decision::Run);
+        assert!(matches!(
+            Condition::negate(release.clone()).evaluate(&ctx),
+            condition::Decision::Skip(_)
+        ));
+        assert!(matches!(
+            release
+                .clone()
+                .and(Condition::event("schedule"))
+                .evaluate(&ctx),
+            condition::Decision::Skip(_)
+        ));
+        assert_eq!(
+            Condition::branch("main")
+                .or(Condition::negate(Condition::event("pull_request")))
+                .evaluate(&ctx),
+            condition::Decision::Run
+        );
+        assert_eq!(
+            Condition::default().evaluate(&ctx),
+            condition::Decision::Run
+        );
     }
 
     #[test]
-    fn test_service_multiple() {
-        let mut p = Pipeline::new();
-        p.task("test")
-            .run("cargo test")
-            .service("postgres:15", "db")
-            .service("redis:7", "cache");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    fn test_condition_evaluate_unknown_identifiers() {
+        let mut ctx = ExplainContext {
+            branch: "main".to_string(),
+            ..Default::default()
+        };
+        let custom = Condition::var("release").eq("yes");
+        assert_eq!(
+            custom.evaluate(&ctx),
+            condition::Decision::Unknown("unknown identifier release".to_string())
+        );
+        assert_eq!(
+            Condition::branch("main").and(custom).evaluate(&ctx),
+            condition::Decision::Unknown("unknown identifier release".to_string())
+        );
 
-        let services = json["tasks"][0]["services"].as_array().unwrap();
-        assert_eq!(services.len(), 2);
+        let linux = Condition::var("platform").eq("linux");
+        assert_eq!(
+            linux.evaluate(&ctx),
+            condition::Decision::Unknown("platform not set in context".to_string())
+        );
+        ctx.vars.insert("platform".to_string(), "linux".to_string());
+        assert_eq!(linux.evaluate(&ctx), condition::Decision::Run);
     }
 
     #[test]
-    fn test_service_not_set() {
+    fn test_explain_warns_on_malformed_condition() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
+        let _ = p.task("deploy").run("./deploy.sh").when("branch = 'main'");
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        assert!(json["tasks"][0]["services"].is_null());
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(!out.contains("SKIPPED"));
+        assert!(out.contains(
+            "   Warning: condition not evaluated: expected '==', '!=' or 'matches' at offset 7\n"
+        ));
     }
 
     #[test]
-    #[should_panic(expected = "service image cannot be empty")]
-    fn test_service_empty_image_panics() {
+    fn test_paths_changed_emits_patterns() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test").service("", "db");
-    }
+        let _ = p
+            .task("web")
+            .run("npm test")
+            .when_cond(Condition::paths_changed(&["web/**"]));
+        let _ = p
+            .task("api")
+            .run("go test ./...")
+            .when_cond(Condition::negate(Condition::paths_changed(&[
+                "web/**", "docs/**",
+            ])));
 
-    #[test]
-    #[should_panic(expected = "service name cannot be empty")]
-    fn test_service_empty_name_panics() {
-        let mut p = Pipeline::new();
-        p.task("test").run("cargo test").service("postgres:15", "");
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["when"], "changed matches 'web/**'");
+        assert_eq!(
+            json["tasks"][1]["when"],
+            "!((changed matches 'web/**') || (changed matches 'docs/**'))"
+        );
     }
 
-    // ----- RETRY TESTS -----
+    // ====== CONDITION VARIABLE TESTS ======
 
     #[test]
-    fn test_retry_in_json() {
-        let mut p = Pipeline::new();
-        p.task("flaky").run("./flaky.sh").retry(3);
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        assert_eq!(json["tasks"][0]["retry"], 3);
+    fn test_condition_env_and_var_expressions() {
+        assert_eq!(
+            Condition::env("ENVIRONMENT", "staging").to_string(),
+            "env.ENVIRONMENT == 'staging'"
+        );
+        assert_eq!(
+            Condition::env("ENVIRONMENT", "staging-*").to_string(),
+            "env.ENVIRONMENT matches 'staging-*'"
+        );
+        assert_eq!(
+            Condition::var("env.REGION").ne("eu").to_string(),
+            "env.REGION != 'eu'"
+        );
+        assert_eq!(
+            Condition::var("release_train")
+                .matches("2024.*")
+                .to_string(),
+            "release_train matches '2024.*'"
+        );
     }
 
     #[test]
-    fn test_retry_not_set() {
+    fn test_explain_resolves_vars() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
+        let _ = p
+            .task("staging")
+            .run("./deploy.sh staging")
+            .when_cond(Condition::env("ENVIRONMENT", "staging"));
+        let _ = p
+            .task("not-prod")
+            .run("./smoke.sh")
+            .when_cond(Condition::var("env.ENVIRONMENT").ne("production"));
+        let _ = p
+            .task("preview")
+            .run("./preview.sh")
+            .when_cond(Condition::env("ENVIRONMENT", "preview-*"));
+        let _ = p
+            .task("canary")
+            .run("./canary.sh")
+            .when_cond(Condition::env("CANARY", "yes"));
 
+        let ctx = ExplainContext {
+            vars: HashMap::from([("ENVIRONMENT".to_string(), "production".to_string())]),
+            ..Default::default()
+        };
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
+
+        let header = |name: &str| {
+            out.lines()
+                .find(|l| l.contains(&format!(". {}", name)))
+                .unwrap()
+                .to_string()
+        };
+        assert!(
+            header("staging").contains("[SKIPPED: env.ENVIRONMENT is 'production', not 'staging']")
+        );
+        assert!(header("not-prod").contains("[SKIPPED: env.ENVIRONMENT is 'production']"));
+        assert!(header("preview")
+            .contains("[SKIPPED: env.ENVIRONMENT 'production' does not match 'preview-*']"));
+        assert!(header("canary").contains("[UNKNOWN: env.CANARY not set in context]"));
+    }
 
-        assert!(json["tasks"][0]["retry"].is_null());
+    #[test]
+    #[should_panic(expected = "invalid condition variable name")]
+    fn test_condition_var_rejects_bad_name() {
+        let _ = Condition::var("env.MY VAR");
     }
 
-    // ----- TIMEOUT TESTS -----
+    // ====== MANUAL TASK TESTS ======
 
     #[test]
-    fn test_timeout_in_json() {
+    fn test_manual_task_serializes() {
         let mut p = Pipeline::new();
-        p.task("long").run("./long-running.sh").timeout(600);
+        let _ = p.task("build").run("make");
+        let _ = p
+            .task("deploy-prod")
+            .run("./deploy.sh prod")
+            .after(&["build"])
+            .manual();
+        let _ = p
+            .task("deploy-staging")
+            .run("./deploy.sh staging")
+            .after(&["build"])
+            .when_cond(Condition::manual());
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let json = emit_json(&p);
+        assert!(json["tasks"][0].get("manual").is_none());
+        assert_eq!(json["tasks"][1]["manual"], true);
+        assert_eq!(json["tasks"][2]["manual"], true);
+        assert!(json["tasks"][2].get("when").is_none());
 
-        assert_eq!(json["tasks"][0]["timeout"], 600);
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("deploy-prod (after: build) [MANUAL APPROVAL REQUIRED]"));
     }
 
     #[test]
-    fn test_timeout_not_set() {
+    fn test_steps_emit_in_order() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
+        let _ = p
+            .task("check")
+            .step("cargo fmt --check")
+            .run_steps(&["cargo clippy -- -D warnings", "cargo test"]);
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["steps"],
+            serde_json::json!([
+                "cargo fmt --check",
+                "cargo clippy -- -D warnings",
+                "cargo test"
+            ])
+        );
+        assert_eq!(
+            json["tasks"][0]["command"],
+            "cargo fmt --check && cargo clippy -- -D warnings && cargo test"
+        );
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
 
-        assert!(json["tasks"][0]["timeout"].is_null());
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains(
+                "1. check\n   Steps:\n     1. cargo fmt --check\n     2. cargo clippy -- -D warnings\n     3. cargo test\n"
+            ),
+            "{}",
+            out
+        );
     }
 
     #[test]
-    #[should_panic(expected = "timeout must be greater than 0")]
-    fn test_timeout_zero_panics() {
+    fn test_run_and_steps_rejected() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test").timeout(0);
+        let _ = p.task("check").run("make lint").step("make test");
+
+        let err = emit_err(&p);
+        assert!(matches!(&err, EmitError::CommandAndSteps { task } if task == "check"));
+        assert_eq!(
+            err.to_string(),
+            "task \"check\" has both a run() command and steps (use one of them)"
+        );
     }
 
     #[test]
-    fn test_retry_and_timeout_combined() {
-        let mut p = Pipeline::new();
-        p.task("flaky").run("./flaky.sh").retry(2).timeout(120);
+    fn test_steps_keep_emit_version() {
+        // run() alone emits exactly as before, with no steps key.
+        let mut v1 = Pipeline::new();
+        let _ = v1.task("test").run("cargo test");
+        assert_eq!(
+            v1.to_json().unwrap(),
+            r#"{"version":"1","tasks":[{"name":"test","command":"cargo test"}]}"#
+        );
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let mut v2 = Pipeline::new();
+        let src = v2.dir(".");
+        let _ = v2
+            .task("test")
+            .container("rust:1.80")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .run("cargo test");
+        let json = emit_json(&v2);
+        assert_eq!(json["version"], "2");
+        assert!(json["tasks"][0].get("steps").is_none());
 
-        assert_eq!(json["tasks"][0]["retry"], 2);
-        assert_eq!(json["tasks"][0]["timeout"], 120);
+        // Steps alone don't change the version.
+        let mut steps = Pipeline::new();
+        let _ = steps.task("test").run_steps(&["cargo build", "cargo test"]);
+        assert_eq!(emit_json(&steps)["version"], "1");
     }
 
-    // ----- CYCLE DETECTION TESTS -----
-
     #[test]
-    fn test_cycle_self_reference() {
-        // A task that depends on itself: A -> A
+    fn test_exec_emits_args() {
         let mut p = Pipeline::new();
-        p.task("build").run("cargo build").after(&["build"]);
+        let _ = p
+            .task("test")
+            .exec(&["cargo", "test", "--features", "foo bar", "--", "it's"]);
 
-        let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("cycle"), "expected cycle error, got: {}", err);
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["args"],
+            serde_json::json!(["cargo", "test", "--features", "foo bar", "--", "it's"])
+        );
+        assert_eq!(
+            json["tasks"][0]["command"],
+            r#"cargo test --features 'foo bar' -- 'it'\''s'"#
+        );
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
+        assert!(loaded.iter_tasks().next().unwrap().command().is_none());
     }
 
     #[test]
-    fn test_cycle_direct_two_tasks() {
-        // Direct cycle between two tasks: A -> B -> A
+    fn test_exec_conflicts_with_run_and_steps() {
         let mut p = Pipeline::new();
-        p.task("a").run("echo a").after(&["b"]);
-        p.task("b").run("echo b").after(&["a"]);
+        let _ = p.task("a").run("make").exec(&["make"]);
+        let _ = p.task("b").step("make").exec(&["make"]);
 
-        let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("cycle"), "expected cycle error, got: {}", err);
+        let EmitError::Multiple(errors) = emit_err(&p) else {
+            panic!("expected two errors");
+        };
+        assert!(matches!(
+            &errors[..],
+            [
+                EmitError::ExecAndCommand { other: "run()", .. },
+                EmitError::ExecAndCommand {
+                    other: "step()",
+                    ..
+                },
+            ]
+        ));
+        assert_eq!(
+            errors[0].to_string(),
+            "task \"a\" uses both exec() and run() (use one of them)"
+        );
     }
 
     #[test]
-    fn test_cycle_indirect_three_tasks() {
-        // Indirect cycle: A -> B -> C -> A
+    #[should_panic(expected = "exec: program cannot be empty")]
+    fn test_exec_rejects_empty_argv() {
         let mut p = Pipeline::new();
-        p.task("a").run("echo a").after(&["b"]);
-        p.task("b").run("echo b").after(&["c"]);
-        p.task("c").run("echo c").after(&["a"]);
-
-        let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("cycle"), "expected cycle error, got: {}", err);
+        let _ = p.task("test").exec(&[]);
     }
 
     #[test]
-    fn test_cycle_longer_chain() {
-        // Longer cycle: A -> B -> C -> D -> E -> A
+    fn test_description_and_tags_serialize() {
         let mut p = Pipeline::new();
-        p.task("a").run("echo a").after(&["b"]);
-        p.task("b").run("echo b").after(&["c"]);
-        p.task("c").run("echo c").after(&["d"]);
-        p.task("d").run("echo d").after(&["e"]);
-        p.task("e").run("echo e").after(&["a"]);
+        let _ = p
+            .task("test")
+            .run("cargo nextest run")
+            .describe("Runs unit tests with nextest")
+            .tags(&["ci", "release-2"]);
+        let _ = p.task("lint").run("cargo clippy");
+
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["description"],
+            "Runs unit tests with nextest"
+        );
+        assert_eq!(
+            json["tasks"][0]["tags"],
+            serde_json::json!(["ci", "release-2"])
+        );
+        assert!(json["tasks"][1].get("description").is_none());
+        assert!(json["tasks"][1].get("tags").is_none());
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
 
         let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("cycle"), "expected cycle error, got: {}", err);
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains(
+                "1. test\n   Runs unit tests with nextest\n   Tags: ci, release-2\n   \
+                 Command: cargo nextest run\n"
+            ),
+            "{}",
+            out
+        );
     }
 
     #[test]
-    fn test_cycle_in_complex_graph() {
-        // Complex graph with a cycle hidden among valid dependencies
+    fn test_tag_validation() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-        p.task("lint").run("cargo clippy");
-        p.task("build").run("cargo build").after(&["test", "lint"]);
-        p.task("deploy")
-            .run("./deploy.sh")
-            .after(&["build", "verify"]);
-        p.task("verify").run("./verify.sh").after(&["deploy"]);
-
-        let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("cycle"), "expected cycle error, got: {}", err);
+        for bad in ["Release", "nightly build", "ci_fast"] {
+            let err = p.task_or_get("test").try_tag(bad).err().unwrap();
+            assert_eq!(
+                err,
+                PipelineError::InvalidTag {
+                    tag: bad.to_string()
+                }
+            );
+        }
+        assert_eq!(
+            PipelineError::InvalidTag {
+                tag: "Release".to_string()
+            }
+            .to_string(),
+            "invalid tag \"Release\" (use lowercase letters, digits and -)"
+        );
+        assert!(p.task_or_get("test").try_tag("").is_err());
     }
 
     #[test]
-    fn test_cycle_error_shows_path() {
-        // Verify the error message includes the cycle path
+    #[should_panic(expected = "invalid tag \"CI\"")]
+    fn test_tag_invalid_panics() {
         let mut p = Pipeline::new();
-        p.task("a").run("echo a").after(&["b"]);
-        p.task("b").run("echo b").after(&["a"]);
-
-        let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
-        let err = result.unwrap_err().to_string();
-        // Error should mention both tasks in the cycle
-        assert!(
-            err.contains("a") && err.contains("b"),
-            "cycle error should mention tasks in cycle, got: {}",
-            err
-        );
+        let _ = p.task("test").run("cargo test").tag("CI");
     }
 
     #[test]
-    fn test_no_cycle_valid_dag() {
-        // Valid DAG with no cycles - should succeed
-        // build depends on test, lint; deploy depends on build
+    fn test_allow_failure_serializes() {
         let mut p = Pipeline::new();
-        p.task("test").run("cargo test");
-        p.task("lint").run("cargo clippy");
-        p.task("build").run("cargo build").after(&["test", "lint"]);
-        p.task("deploy").run("./deploy.sh").after(&["build"]);
+        let _ = p.task("build").run("make").allow_failure(false);
+        let _ = p
+            .task("audit")
+            .run("cargo audit")
+            .after(&["build"])
+            .allow_failure(true);
+
+        let json = emit_json(&p);
+        assert!(json["tasks"][0].get("allow_failure").is_none());
+        assert_eq!(json["tasks"][1]["allow_failure"], true);
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
 
         let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_ok(), "valid DAG should not error: {:?}", result);
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains("2. audit (after: build) [allowed to fail]\n"),
+            "{}",
+            out
+        );
     }
 
     #[test]
-    fn test_no_cycle_diamond_pattern() {
-        // Diamond pattern: b -> a, c -> a, d -> b, d -> c
-        // (b,c depend on a; d depends on b,c; execution: a then b,c then d)
+    fn test_concurrency_and_max_parallel_serialize() {
         let mut p = Pipeline::new();
-        p.task("a").run("echo a");
-        p.task("b").run("echo b").after(&["a"]);
-        p.task("c").run("echo c").after(&["a"]);
-        p.task("d").run("echo d").after(&["b", "c"]);
+        p.max_parallel(2);
+        let _ = p
+            .task("deploy-api")
+            .run("./deploy.sh api")
+            .concurrency_group("staging-deploy");
+        let _ = p
+            .task("deploy-web")
+            .run("./deploy.sh web")
+            .concurrency_group("staging-deploy")
+            .cancel_in_progress(true);
+        let _ = p.task("test").run("cargo test");
+
+        let json = emit_json(&p);
+        assert_eq!(json["max_parallel"], 2);
+        assert_eq!(
+            json["tasks"][0]["concurrency"],
+            serde_json::json!({"group": "staging-deploy"})
+        );
+        assert_eq!(
+            json["tasks"][1]["concurrency"],
+            serde_json::json!({"group": "staging-deploy", "cancel_in_progress": true})
+        );
+        assert!(json["tasks"][2].get("concurrency").is_none());
+        let to_json: serde_json::Value = serde_json::from_str(&p.to_json().unwrap()).unwrap();
+        assert_eq!(to_json, json);
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
 
         let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains("deploy-web [concurrency: staging-deploy]\n"),
+            "{}",
+            out
+        );
         assert!(
-            result.is_ok(),
-            "diamond pattern should not error: {:?}",
-            result
+            out.contains("3 tasks in 1 level, max parallelism 3, capped at 2 by max_parallel\n"),
+            "{}",
+            out
         );
     }
 
     #[test]
-    fn test_no_cycle_multiple_roots() {
-        // Multiple independent roots converging
+    fn test_max_parallel_omitted_by_default() {
         let mut p = Pipeline::new();
-        p.task("a").run("echo a");
-        p.task("b").run("echo b");
-        p.task("c").run("echo c");
-        p.task("final").run("echo final").after(&["a", "b", "c"]);
+        let _ = p.task("test").run("cargo test");
+        assert!(emit_json(&p).get("max_parallel").is_none());
+    }
 
-        let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(
-            result.is_ok(),
-            "multiple roots should not error: {:?}",
-            result
+    #[test]
+    fn test_max_parallel_zero_fails_emit() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        p.max_parallel(0);
+
+        assert!(matches!(emit_err(&p), EmitError::ZeroMaxParallel));
+        let issue = p
+            .validate()
+            .into_iter()
+            .find(|i| i.field == "max_parallel")
+            .unwrap();
+        assert_eq!(issue.task, None);
+        assert_eq!(
+            issue.message,
+            "max_parallel is 0, so no task could run (use at least 1)"
         );
     }
 
-    // =============================================================================
-    // TEMPLATE TESTS
-    // =============================================================================
-
     #[test]
-    fn test_template_basic() {
+    #[should_panic(expected = "concurrency group cannot be empty")]
+    fn test_empty_concurrency_group_panics() {
         let mut p = Pipeline::new();
-        let src = p.dir(".");
-
-        // Create template with common config
-        let tmpl = Template::new()
-            .container("rust:1.75")
-            .mount_dir(&src, "/src")
-            .workdir("/src");
-
-        // Task inherits from template
-        p.task("test").from(&tmpl).run("cargo test");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let _ = p.task("deploy").concurrency_group("");
+    }
 
-        assert_eq!(json["tasks"][0]["container"], "rust:1.75");
-        assert_eq!(json["tasks"][0]["workdir"], "/src");
-        assert_eq!(json["tasks"][0]["mounts"][0]["path"], "/src");
+    #[test]
+    #[should_panic(expected = "cancel_in_progress() must be called after concurrency_group()")]
+    fn test_cancel_in_progress_without_group_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").cancel_in_progress(true);
     }
 
     #[test]
-    fn test_template_with_cache() {
+    fn test_entrypoint_and_user_serialize() {
         let mut p = Pipeline::new();
-        let src = p.dir(".");
-        let cache = p.cache("cargo-registry");
+        let _ = p
+            .task("plan")
+            .container("hashicorp/terraform:1.7")
+            .mount_cwd()
+            .entrypoint("")
+            .user("1000:1000")
+            .run("terraform plan");
+        let _ = p.task("lint").container("rust:1.75").run("cargo clippy");
+
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["entrypoint"], "");
+        assert_eq!(json["tasks"][0]["user"], "1000:1000");
+        assert!(json["tasks"][1].get("entrypoint").is_none());
+        assert!(json["tasks"][1].get("user").is_none());
+        let loaded = Pipeline::from_json(&p.to_json().unwrap()).unwrap();
+        assert_eq!(emit_json(&loaded), json);
+
+        let view = p.iter_tasks().next().unwrap();
+        assert_eq!(view.entrypoint(), Some(""));
+        assert_eq!(view.user(), Some("1000:1000"));
+    }
 
-        let tmpl = Template::new()
-            .container("rust:1.75")
-            .mount_dir(&src, "/src")
-            .mount_cache(&cache, "/usr/local/cargo/registry")
-            .workdir("/src");
+    #[test]
+    #[should_panic(expected = "container user")]
+    fn test_user_rejects_empty() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").user("");
+    }
 
-        p.task("test").from(&tmpl).run("cargo test");
-        p.task("build").from(&tmpl).run("cargo build");
+    #[test]
+    fn test_always_run_task_after_manual_rejected() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh").manual();
+        let _ = p.task("smoke").run("./smoke.sh").after(&["deploy"]);
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let err = p.emit_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "task \"smoke\" always runs but depends on manual task \"deploy\" (make it manual or give it a condition)"
+        );
 
-        // Both tasks should have 2 mounts
-        assert_eq!(json["tasks"][0]["mounts"].as_array().unwrap().len(), 2);
-        assert_eq!(json["tasks"][1]["mounts"].as_array().unwrap().len(), 2);
+        // Conditional or manual dependents are fine
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh").manual();
+        let _ = p
+            .task("smoke")
+            .run("./smoke.sh")
+            .after(&["deploy"])
+            .when_cond(Condition::branch("main"));
+        let _ = p
+            .task("notify")
+            .run("./notify.sh")
+            .after(&["deploy"])
+            .manual();
+        assert!(p.emit_to(&mut Vec::new()).is_ok());
     }
 
+    // ====== PULL REQUEST CONDITION TESTS ======
+
     #[test]
-    fn test_template_with_env() {
-        let mut p = Pipeline::new();
+    fn test_pull_request_conditions() {
+        let push = ExplainContext {
+            branch: "main".to_string(),
+            event: "push".to_string(),
+            ..Default::default()
+        };
+        let pr = |target: &str| ExplainContext {
+            branch: "feature/login".to_string(),
+            event: "pull_request".to_string(),
+            pr_target: target.to_string(),
+            ..Default::default()
+        };
 
-        let tmpl = Template::new()
-            .container("rust:1.75")
-            .env("RUST_BACKTRACE", "1")
-            .env("CARGO_TERM_COLOR", "always");
+        let into_main = Condition::pull_request().and(Condition::pr_target("main"));
+        assert!(matches!(
+            into_main.evaluate(&push),
+            condition::Decision::Skip(_)
+        ));
+        assert_eq!(into_main.evaluate(&pr("main")), condition::Decision::Run);
+        assert!(matches!(
+            into_main.evaluate(&pr("develop")),
+            condition::Decision::Skip(_)
+        ));
+
+        let release = Condition::pr_target("release/*");
+        assert_eq!(release.to_string(), "pr_target matches 'release/*'");
+        assert_eq!(
+            release.evaluate(&pr("release/2.0")),
+            condition::Decision::Run
+        );
 
-        p.task("build").from(&tmpl).run("cargo build");
+        let main_or_pr = Condition::branch("main").or(Condition::pull_request());
+        assert_eq!(main_or_pr.evaluate(&push), condition::Decision::Run);
+        assert_eq!(main_or_pr.evaluate(&pr("main")), condition::Decision::Run);
 
+        let mut p = Pipeline::new();
+        let _ = p.task("check").run("make check").when_cond(into_main);
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        assert_eq!(json["tasks"][0]["env"]["RUST_BACKTRACE"], "1");
-        assert_eq!(json["tasks"][0]["env"]["CARGO_TERM_COLOR"], "always");
+        p.explain_to(&mut buf, Some(&pr("develop")));
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("[SKIPPED: pr_target is 'develop', not 'main']"));
     }
 
+    // ====== UNLESS TESTS ======
+
     #[test]
-    fn test_template_override() {
+    fn test_unless_alone() {
         let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .unless_cond(Condition::branch("wip/*"));
 
-        let tmpl = Template::new()
-            .container("rust:1.75")
-            .env("FOO", "from-template");
-
-        // Task overrides env
-        p.task("test")
-            .from(&tmpl)
-            .env("FOO", "from-task")
-            .run("echo $FOO");
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["when"], "!(branch matches 'wip/*')");
 
+        let ctx = ExplainContext {
+            branch: "wip/spike".to_string(),
+            ..Default::default()
+        };
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        // Task-level should override
-        assert_eq!(json["tasks"][0]["env"]["FOO"], "from-task");
+        p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("[SKIPPED: branch matches 'wip/*' holds]"));
+        assert!(out.contains("   Condition: !(branch matches 'wip/*')\n"));
     }
 
     #[test]
-    fn test_template_multiple_tasks() {
+    fn test_when_and_unless_combine() {
         let mut p = Pipeline::new();
-        let src = p.dir(".");
-
-        let rust = Template::new()
-            .container("rust:1.75")
-            .mount_dir(&src, "/src")
-            .workdir("/src");
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .when("branch matches 'release/*'")
+            .unless("tag == 'skip'");
 
-        p.task("lint").from(&rust).run("cargo clippy");
-        p.task("test").from(&rust).run("cargo test");
-        p.task("build")
-            .from(&rust)
-            .run("cargo build")
-            .after(&["lint", "test"]);
+        let json = emit_json(&p);
+        let cond = "(branch matches 'release/*') && (!(tag == 'skip'))";
+        assert_eq!(json["tasks"][0]["when"], cond);
+        assert_eq!(
+            p.iter_tasks()
+                .next()
+                .unwrap()
+                .effective_condition()
+                .as_deref(),
+            Some(cond)
+        );
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let ctx = |tag: &str| ExplainContext {
+            branch: "release/1.0".to_string(),
+            tag: tag.to_string(),
+            ..Default::default()
+        };
+        let explain = |tag: &str| {
+            let mut buf = Vec::new();
+            p.explain_to(&mut buf, Some(&ctx(tag)));
+            String::from_utf8(buf).unwrap()
+        };
+        assert!(!explain("v1.0").contains("SKIPPED"));
+        assert!(explain("skip").contains("[SKIPPED: tag == 'skip' holds]"));
+    }
 
-        assert_eq!(json["tasks"].as_array().unwrap().len(), 3);
-        // All should have same container
-        for i in 0..3 {
-            assert_eq!(json["tasks"][i]["container"], "rust:1.75");
+    #[test]
+    fn test_unless_string_is_validated() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test").unless("brnch == 'wip'");
+        match emit_err(&p) {
+            EmitError::InvalidCondition { field, message, .. } => {
+                assert_eq!(field, "unless");
+                assert!(message.contains("did you mean \"branch\"?"), "{}", message);
+            }
+            err => panic!("expected invalid condition, got: {}", err),
         }
     }
 
-    // =============================================================================
-    // CHAIN TESTS
-    // =============================================================================
+    // ====== SCHEDULE CONDITION TESTS ======
 
     #[test]
-    fn test_chain_basic() {
+    fn test_schedule_conditions_in_explain() {
         let mut p = Pipeline::new();
-        p.task("a").run("echo a");
-        p.task("b").run("echo b");
-        p.task("c").run("echo c");
+        let _ = p
+            .task("audit")
+            .run("cargo audit")
+            .when_cond(Condition::schedule().and(Condition::cron("0 3 * * *")));
+        let _ = p
+            .task("fuzz")
+            .run("cargo fuzz run parser")
+            .when_cond(Condition::cron("0 0 * * 0"));
 
-        // Chain creates: a → b → c
-        p.chain(&["a", "b", "c"]);
+        let json = emit_json(&p);
+        assert_eq!(
+            json["tasks"][0]["when"],
+            "(event == 'schedule') && (cron == '0 3 * * *')"
+        );
 
+        let nightly = ExplainContext {
+            event: "schedule".to_string(),
+            cron: "0 3 * * *".to_string(),
+            ..Default::default()
+        };
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.explain_to(&mut buf, Some(&nightly));
+        let out = String::from_utf8(buf).unwrap();
+        let header = |name: &str| {
+            out.lines()
+                .find(|l| l.contains(&format!(". {}", name)))
+                .unwrap()
+                .to_string()
+        };
+        assert!(!header("audit").contains("SKIPPED"));
+        assert!(header("fuzz").contains("[SKIPPED: cron is '0 3 * * *', not '0 0 * * 0']"));
+    }
 
-        // a has no deps
-        assert!(json["tasks"][0]["depends_on"].is_null());
-        // b depends on a
-        assert_eq!(json["tasks"][1]["depends_on"][0], "a");
-        // c depends on b
-        assert_eq!(json["tasks"][2]["depends_on"][0], "b");
+    #[test]
+    fn test_cron_validation() {
+        for ok in [
+            "0 3 * * *",
+            "*/15 * * * *",
+            "0 9-17/2 * * 1-5",
+            "0 0 1,15 * *",
+            "30 4 * 12 7",
+            "*/15 0-23 1,15 JAN-DEC MON-FRI",
+            "0 0 * jun,Dec sun",
+            "0 12 * FEB-NOV/3 SAT",
+            "@yearly",
+            "@monthly",
+            "@weekly",
+            "@daily",
+            "@hourly",
+        ] {
+            assert!(check_cron(ok).is_ok(), "{}", ok);
+        }
+        let cases = [
+            ("every day", "expected 5 fields, got 2"),
+            ("60 * * * *", "minute 60 is out of range 0-59"),
+            ("0 3 0 * *", "day of month 0 is out of range 1-31"),
+            ("0 3 * JANUARY *", "month \"JANUARY\" is not a number or name"),
+            ("0 3 MON * *", "day of month \"MON\" is not a number"),
+            ("0 3 * * FRI-MON", "day of week range \"FRI-MON\" is backwards"),
+            (
+                "@reboot",
+                "unknown macro \"@reboot\", expected one of @yearly, @monthly, @weekly, @daily, @hourly",
+            ),
+            ("*/0 * * * *", "minute step \"0\" is not a positive number"),
+            ("0 5-1 * * *", "hour range \"5-1\" is backwards"),
+        ];
+        for (expr, msg) in cases {
+            assert_eq!(check_cron(expr).unwrap_err(), msg, "{}", expr);
+        }
     }
 
     #[test]
-    fn test_chain_preserves_existing_deps() {
-        let mut p = Pipeline::new();
-        p.task("prereq").run("echo prereq");
-        p.task("a").run("echo a").after(&["prereq"]); // existing dep
-        p.task("b").run("echo b");
+    #[should_panic(expected = "invalid cron expression \"every day\": expected 5 fields, got 2")]
+    fn test_cron_panics_on_garbage() {
+        let _ = Condition::cron("every day");
+    }
 
-        p.chain(&["a", "b"]);
+    // ====== CONDITION PARSE TESTS ======
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    #[test]
+    fn test_condition_parse_round_trips_builder_output() {
+        let conditions = [
+            Condition::branch("main"),
+            Condition::tag("v*"),
+            Condition::has_tag(),
+            Condition::in_ci(),
+            Condition::paths_changed(&["web/**", "package.json"]),
+            Condition::env("ENVIRONMENT", "staging"),
+            Condition::var("release").ne("none"),
+            Condition::pr_target("release/*"),
+            Condition::cron("0 3 * * 1-5"),
+            Condition::branch("main")
+                .and(Condition::negate(Condition::event("pull_request")))
+                .or(Condition::tag("v*").and(Condition::in_ci())),
+            Condition::negate(Condition::negate(Condition::negate(Condition::branch(
+                "wip/*",
+            )))),
+            Condition::branch("ünïcode/*").or(Condition::env("GREETING", "héllo wörld 👋")),
+            Condition::branch("it's"),
+            Condition::tag(r"v\*"),
+            Condition::env("PATTERN", r"a\'b\\"),
+        ];
+        for c in conditions {
+            let s = c.to_string();
+            assert_eq!(Condition::parse(&s).unwrap().to_string(), s);
+        }
+    }
 
-        // a should still have prereq AND nothing from chain (it's first)
-        let a_deps = json["tasks"][1]["depends_on"].as_array().unwrap();
-        assert_eq!(a_deps.len(), 1);
-        assert_eq!(a_deps[0], "prereq");
+    #[test]
+    fn test_condition_quotes_are_escaped() {
+        let c = Condition::branch("it's");
+        assert_eq!(c.to_string(), r"branch == 'it\'s'");
+        let ctx = ExplainContext {
+            branch: "it's".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(c.evaluate(&ctx), condition::Decision::Run);
+        assert_eq!(
+            Condition::parse(&c.to_string()).unwrap().evaluate(&ctx),
+            condition::Decision::Run
+        );
 
-        // b should depend on a (from chain)
-        assert_eq!(json["tasks"][2]["depends_on"][0], "a");
+        let c = Condition::env("DIR", r"C:\build");
+        assert_eq!(c.to_string(), r"env.DIR == 'C:\\build'");
+        let mut ctx = ExplainContext::default();
+        ctx.vars.insert("DIR".to_string(), r"C:\build".to_string());
+        assert_eq!(c.evaluate(&ctx), condition::Decision::Run);
     }
 
     #[test]
-    fn test_chain_single_task() {
-        let mut p = Pipeline::new();
-        p.task("only").run("echo only");
-
-        p.chain(&["only"]); // Single task - no deps added
+    fn test_condition_parse_nested_and_unicode() {
+        let c = Condition::parse("((((branch == 'ü')))) && !((tag matches 'v*' || ci == false))")
+            .unwrap();
+        assert_eq!(
+            c.to_string(),
+            "(branch == 'ü') && (!((tag matches 'v*') || (ci == false)))"
+        );
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let deep = format!("{}branch == 'main'{}", "(".repeat(50), ")".repeat(50));
+        assert_eq!(
+            Condition::parse(&deep).unwrap().to_string(),
+            "branch == 'main'"
+        );
 
-        assert!(json["tasks"][0]["depends_on"].is_null());
+        // Offsets are in bytes, past the multi-byte characters
+        let err = Condition::parse("env.GRÜSS == 'x'").unwrap_err();
+        assert_eq!(
+            (err.offset, err.expected),
+            (6, &["==", "!=", "matches"][..])
+        );
+        let err = Condition::parse("branch == 'ünterminated").unwrap_err();
+        assert_eq!((err.offset, err.expected), (10, &["'"][..]));
+        let err = Condition::parse("(branch == 'é'").unwrap_err();
+        assert_eq!((err.offset, err.expected), (15, &[")"][..]));
+        let err = Condition::parse("branch == 'main' &&").unwrap_err();
+        assert_eq!(err.expected, ["identifier", "!", "("]);
     }
 
-    // =============================================================================
-    // PARALLEL GROUP TESTS
-    // =============================================================================
+    // ====== LEVEL TESTS ======
 
     #[test]
-    fn test_parallel_as_dependency() {
+    fn test_levels_diamond() {
         let mut p = Pipeline::new();
-        p.task("lint").run("cargo clippy");
-        p.task("test").run("cargo test");
-
-        // Parallel group: both have no deps themselves
-        // Build depends on the group
-        let checks = &["lint", "test"];
-        p.task("build").run("cargo build").after(checks);
+        let _ = p.task("checkout").run("git fetch");
+        let _ = p.task("build").run("make").after(&["checkout"]);
+        let _ = p.task("docs").run("make docs").after(&["checkout"]);
+        let _ = p.task("package").run("make dist").after(&["build", "docs"]);
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(
+            p.levels(),
+            vec![vec!["checkout"], vec!["build", "docs"], vec!["package"]]
+        );
+    }
 
-        // lint and test have no deps
-        assert!(json["tasks"][0]["depends_on"].is_null());
-        assert!(json["tasks"][1]["depends_on"].is_null());
+    #[test]
+    fn test_levels_multi_root_uses_longest_path() {
+        let mut p = Pipeline::new();
+        let _ = p.task("deploy").run("./deploy.sh").after(&["test", "fmt"]);
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p.task("fmt").run("cargo fmt --check");
+        let _ = p.task("test").run("cargo test").after(&["build"]);
+        let _ = p.task("build").run("cargo build");
+        let _ = p.task("audit").run("cargo audit");
 
-        // build depends on both
-        let build_deps = json["tasks"][2]["depends_on"].as_array().unwrap();
-        assert_eq!(build_deps.len(), 2);
+        assert_eq!(
+            p.levels(),
+            vec![
+                vec!["lint", "fmt", "build", "audit"],
+                vec!["test"],
+                vec!["deploy"],
+            ]
+        );
     }
 
     #[test]
-    fn test_chain_with_parallel_group() {
+    fn test_explain_prints_levels_and_summary() {
         let mut p = Pipeline::new();
-        // Parallel checks
-        p.task("lint").run("cargo clippy");
-        p.task("test").run("cargo test");
-        let checks = vec!["lint", "test"];
+        for name in ["lint", "fmt", "test"] {
+            let _ = p.task(name).run("true");
+        }
+        let _ = p.task("build").run("true").after(&["lint", "fmt", "test"]);
+        let _ = p.task("image").run("true").after(&["build"]);
+        let _ = p.task("sbom").run("true").after(&["build"]);
+        let _ = p.task("push").run("true").after(&["image", "sbom"]);
 
-        // Build after checks
-        p.task("build").run("cargo build").after(&checks);
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+
+        let levels: Vec<_> = out.lines().filter(|l| l.starts_with("Level ")).collect();
+        assert_eq!(
+            levels,
+            [
+                "Level 0 (parallel): lint, fmt, test",
+                "Level 1: build",
+                "Level 2 (parallel): image, sbom",
+                "Level 3: push",
+            ]
+        );
+        assert!(out.contains("\n4. build (after: lint, fmt, test)\n"));
+        assert!(out.contains("7 tasks in 4 levels, max parallelism 3\n"));
+    }
+
+    // ====== MATRIX PREVIEW TESTS ======
 
-        // Deploy after build
-        p.task("deploy").run("./deploy.sh").after(&["build"]);
+    #[test]
+    fn test_explain_lists_matrix_variants() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust_version", &["1.75", "1.80", "1.85"])
+            .matrix("os", &["linux", "macos"]);
+        let _ = p.task("build").run("cargo build").after(&["test"]);
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
 
-        // lint and test parallel (no deps)
-        assert!(json["tasks"][0]["depends_on"].is_null());
-        assert!(json["tasks"][1]["depends_on"].is_null());
+        assert!(out.contains(
+            "   Matrix: 6 variants\n     test [rust_version=1.75, os=linux]\n     test [rust_version=1.75, os=macos]\n"
+        ));
+        assert!(out.contains("     test [rust_version=1.85, os=macos]\n"));
+        assert!(!out.contains("more"));
+        assert!(out.contains("2 tasks (7 after matrix expansion) in 2 levels, max parallelism 1"));
+    }
 
-        // build depends on both
-        assert_eq!(json["tasks"][2]["depends_on"].as_array().unwrap().len(), 2);
+    #[test]
+    fn test_explain_caps_matrix_preview() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("shard", &["1", "2", "3", "4", "5"]);
 
-        // deploy depends on build
-        assert_eq!(json["tasks"][3]["depends_on"][0], "build");
-    }
+        let ctx = ExplainContext {
+            matrix_preview: Some(2),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
 
-    // =============================================================================
-    // TASK NAME METHOD TEST
-    // =============================================================================
+        assert!(out.contains("     test [shard=1]\n     test [shard=2]\n     … and 3 more\n"));
+        assert!(!out.contains("shard=3"));
+    }
 
     #[test]
-    fn test_task_name_method() {
+    fn test_expand_matrix() {
         let mut p = Pipeline::new();
-        let name = p.task("my-task").run("echo test").name();
-        assert_eq!(name, "my-task");
-    }
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.75", "1.80"])
+            .matrix("os", &["linux"]);
+        let _ = p.task("lint").run("cargo clippy");
 
-    // =============================================================================
-    // INPUT/OUTPUT BINDING TESTS
-    // =============================================================================
+        let variants = p.expand_matrix("test");
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[1]["rust"], "1.80");
+        assert_eq!(variants[1]["os"], "linux");
+        assert!(p.expand_matrix("lint").is_empty());
+    }
 
     #[test]
-    fn test_input_from_basic() {
+    fn test_expand_matrix_honors_exclude_and_include() {
         let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.70", "1.75", "1.80"])
+            .matrix("os", &["linux", "macos", "windows"])
+            .matrix_exclude(&[("os", "windows")])
+            .matrix_exclude(&[("rust", "1.70"), ("os", "macos")])
+            .matrix_include(&[("rust", "nightly"), ("os", "linux")]);
 
-        // Build produces output
-        p.task("build")
-            .run("cargo build --release")
-            .output("binary", "target/release/app");
-
-        // Package consumes it
-        p.task("package")
-            .input_from("build", "binary", "/app")
-            .run("docker build .");
+        let variants = p.expand_matrix("test");
+        assert_eq!(variants.len(), 6);
+        assert!(!variants.iter().any(|v| v["os"] == "windows"));
+        assert_eq!(variants[5]["rust"], "nightly");
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("   Matrix: 6 variants\n"), "{}", out);
+        assert!(out.contains("     test [rust=nightly, os=linux]\n"));
+        assert!(out.contains("1 task (6 after matrix expansion)"));
+    }
 
-        // Check task_inputs
-        let inputs = json["tasks"][1]["task_inputs"].as_array().unwrap();
-        assert_eq!(inputs.len(), 1);
-        assert_eq!(inputs[0]["from_task"], "build");
-        assert_eq!(inputs[0]["output"], "binary");
-        assert_eq!(inputs[0]["dest"], "/app");
+    #[test]
+    #[should_panic(expected = "expand_matrix: unknown task \"tset\" (did you mean \"test\"?)")]
+    fn test_expand_matrix_unknown_task_panics() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        let _ = p.expand_matrix("tset");
     }
 
     #[test]
-    fn test_input_from_auto_adds_dep() {
+    fn test_expand_matrices_rewires_dependents() {
         let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.75", "1.80"])
+            .matrix("os", &["linux", "macos"])
+            .matrix_exclude(&[("rust", "1.75"), ("os", "macos")])
+            .after(&["lint"]);
+        let _ = p.task("build").run("cargo build").after(&["test"]);
+        let _ = p.task("report").run("./report.sh").always_after(&["test"]);
+        p.barrier("tested");
+
+        p.expand_matrices();
+        let _ = p.task("deploy").run("./deploy.sh");
+        let variants = ["test-1.75-linux", "test-1.80-linux", "test-1.80-macos"];
+        let names: Vec<_> = p.iter_tasks().map(|t| t.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "lint",
+                variants[0],
+                variants[1],
+                variants[2],
+                "build",
+                "report",
+                "deploy"
+            ]
+        );
 
-        p.task("build").run("cargo build").output("binary", "./app");
-        p.task("package")
-            .input_from("build", "binary", "/app")
-            .run("docker build");
+        let json = emit_json(&p);
+        let tasks = json["tasks"].as_array().unwrap();
+        assert_eq!(tasks[1]["depends_on"], serde_json::json!(["lint"]));
+        assert_eq!(tasks[1]["env"]["os"], "linux");
+        assert!(tasks[1]["matrix"].is_null());
+        assert_eq!(tasks[4]["depends_on"], serde_json::json!(variants));
+        let report = &tasks[5]["depends_on_meta"];
+        assert_eq!(report.as_array().unwrap().len(), 3);
+        assert_eq!(report[2]["task"], variants[2]);
+        assert_eq!(report[2]["run_when"], "always");
+        assert_eq!(tasks[6]["depends_on"].as_array().unwrap().len(), 6);
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    #[test]
+    fn test_expand_matrices_follows_selectors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"])
+            .matrix("arch", &["x64", "arm64"])
+            .output("binary", "target/app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after_matrix("build", &[("os", "macos")])
+            .input_from_matrix(
+                "build",
+                &[("os", "linux"), ("arch", "arm64")],
+                "binary",
+                "./app",
+            );
 
-        // package should depend on build
-        let deps = json["tasks"][1]["depends_on"].as_array().unwrap();
-        assert_eq!(deps.len(), 1);
-        assert_eq!(deps[0], "build");
+        p.expand_matrices();
+        let json = emit_json(&p);
+        let package = &json["tasks"][4];
+        assert_eq!(
+            package["depends_on"],
+            serde_json::json!(["build-linux-arm64", "build-macos-x64", "build-macos-arm64",])
+        );
+        assert_eq!(package["task_inputs"][0]["from_task"], "build-linux-arm64");
+        assert!(package["task_inputs"][0]["matrix"].is_null());
     }
 
     #[test]
-    fn test_input_from_multiple() {
+    fn test_expand_matrices_substitutes_placeholders() {
         let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .container("rust:{{matrix.rust}}-{{matrix.os}}")
+            .run("cargo +{{matrix.rust}} test --target-dir /tmp/{{matrix.unknown}}")
+            .matrix("rust", &["1.75"])
+            .matrix("os", &["slim"]);
+
+        p.expand_matrices_named("{{task}}-{{matrix.rust}}");
+        let t = p.iter_tasks().next().unwrap();
+        assert_eq!(t.name(), "test-1.75");
+        assert_eq!(t.container(), Some("rust:1.75-slim"));
+        assert_eq!(
+            t.command(),
+            Some("cargo +1.75 test --target-dir /tmp/{{matrix.unknown}}")
+        );
+    }
 
-        p.task("build-linux")
-            .run("cargo build")
-            .output("binary", "./linux");
-        p.task("build-darwin")
-            .run("cargo build")
-            .output("binary", "./darwin");
-        p.task("package")
-            .input_from("build-linux", "binary", "/linux")
-            .input_from("build-darwin", "binary", "/darwin")
-            .run("tar czf release.tar.gz /linux /darwin");
+    #[test]
+    fn test_matrix_placeholders_emitted_verbatim() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .container("rust:{{matrix.rust}}")
+            .workdir("/src/{{matrix.os}}")
+            .env("TARGET_DIR", "target/{{matrix.rust}}-{{matrix.os}}")
+            .run("cargo +{{matrix.rust}} test")
+            .matrix("rust", &["1.75", "1.80"])
+            .matrix("os", &["linux", "macos"]);
+
+        let json = emit_json(&p);
+        let task = &json["tasks"][0];
+        assert_eq!(task["container"], "rust:{{matrix.rust}}");
+        assert_eq!(task["workdir"], "/src/{{matrix.os}}");
+        assert_eq!(
+            task["env"]["TARGET_DIR"],
+            "target/{{matrix.rust}}-{{matrix.os}}"
+        );
+        assert_eq!(task["command"], "cargo +{{matrix.rust}} test");
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    #[test]
+    fn test_expand_matrices_interpolates_surviving_variants() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .container("rust:{{matrix.rust}}")
+            .env("TARGET", "{{matrix.os}}")
+            .run("cargo test")
+            .matrix("rust", &["1.70", "1.80"])
+            .matrix("os", &["linux", "macos"])
+            .matrix_exclude(&[("rust", "1.70"), ("os", "macos")])
+            .matrix_include(&[("rust", "nightly"), ("os", "linux")]);
+
+        p.expand_matrices_named("{{task}}-{{matrix.rust}}-{{matrix.os}}");
+        let images: Vec<_> = p
+            .iter_tasks()
+            .map(|t| {
+                format!(
+                    "{}={}@{}",
+                    t.name(),
+                    t.container().unwrap(),
+                    t.env()["TARGET"]
+                )
+            })
+            .collect();
+        assert_eq!(
+            images,
+            [
+                "test-1.70-linux=rust:1.70@linux",
+                "test-1.80-linux=rust:1.80@linux",
+                "test-1.80-macos=rust:1.80@macos",
+                "test-nightly-linux=rust:nightly@linux",
+            ]
+        );
+        assert!(p.validate_for_emit().is_ok());
+    }
 
-        let inputs = json["tasks"][2]["task_inputs"].as_array().unwrap();
-        assert_eq!(inputs.len(), 2);
+    #[test]
+    fn test_expand_matrices_errors_leave_pipeline_unchanged() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux", "macos"])
+            .output("report", "report.xml");
+        let _ =
+            p.task("publish")
+                .run("./publish.sh")
+                .input_from("test", "report", "/in/report.xml");
 
-        let deps = json["tasks"][2]["depends_on"].as_array().unwrap();
-        assert_eq!(deps.len(), 2);
+        assert_eq!(
+            p.try_expand_matrices_named("{{task}}"),
+            Err(PipelineError::DuplicateTask {
+                name: "test".to_string()
+            })
+        );
+        assert_eq!(
+            p.try_expand_matrices_named("{{task}}-{{matrix.os}}")
+                .unwrap_err()
+                .to_string(),
+            "task \"publish\" takes input from matrix task \"test\", which has one set of outputs per variant once expanded"
+        );
+        assert_eq!(p.expand_matrix("test").len(), 2);
     }
 
     #[test]
-    fn test_input_from_no_duplicate_deps() {
+    fn test_validation_runs_after_expansion() {
         let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux", "macos"])
+            .after(&["build"]);
+        let _ = p.task("build").run("cargo build").after(&["test"]);
+        let _ = p.task("deploy").run("./deploy.sh").after(&["tset"]);
 
-        p.task("build").run("cargo build").output("binary", "./app");
-        // Explicit after AND input_from - should not duplicate
-        p.task("package")
-            .after(&["build"])
-            .input_from("build", "binary", "/app")
-            .run("docker build");
+        p.expand_matrices();
+        let messages: Vec<String> = p.validate().iter().map(|i| i.to_string()).collect();
+        assert!(messages[0].contains("\"tset\""), "{:?}", messages);
+        assert!(
+            messages[1].contains("test-linux") && messages[1].contains("build"),
+            "{:?}",
+            messages
+        );
 
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        // Should have only one dep, not duplicated
-        let deps = json["tasks"][1]["depends_on"].as_array().unwrap();
-        assert_eq!(deps.len(), 1);
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux", "macos"]);
+        p.expand_matrices();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(
+            out.contains("Level 0 (parallel): test-linux, test-macos"),
+            "{}",
+            out
+        );
+        assert!(!out.contains("Matrix:"));
     }
 
-    // =============================================================================
-    // K8S VALIDATION TESTS
-    // =============================================================================
+    // ====== SCOPED EXPLAIN TESTS ======
 
-    #[test]
-    fn test_k8s_validation_valid_memory_formats() {
-        let valid = ["512Mi", "4Gi", "1Ti", "256Ki", "1G", "500M", "100"];
-        for mem in valid {
-            let opts = K8sOptions {
-                memory: Some(mem.to_string()),
-                ..Default::default()
-            };
-            let errors = opts.validate();
-            assert!(errors.is_empty(), "expected {} to be valid", mem);
-        }
+    fn diamond_with_extras() -> Pipeline {
+        let mut p = Pipeline::new();
+        let _ = p.task("checkout").run("git fetch");
+        let _ = p.task("docs").run("cargo doc");
+        let _ = p.task("lint").run("cargo clippy").after(&["checkout"]);
+        let _ = p.task("test").run("cargo test").after(&["checkout"]);
+        let _ = p.task("deploy").run("./deploy.sh").after(&["lint", "test"]);
+        let _ = p.task("notify").run("./notify.sh").after(&["deploy"]);
+        p
+    }
+
+    fn explain_scope(
+        f: impl FnOnce(&mut Vec<u8>) -> Result<ExplainReport, subset::SubsetError>,
+    ) -> Vec<String> {
+        let mut buf = Vec::new();
+        f(&mut buf).unwrap();
+        String::from_utf8(buf)
+            .unwrap()
+            .lines()
+            .filter(|l| {
+                l.starts_with("Scope:")
+                    || l.starts_with("Level")
+                    || l.contains(" levels, max parallelism ")
+            })
+            .map(str::to_string)
+            .collect()
     }
 
     #[test]
-    fn test_k8s_validation_invalid_memory_formats() {
-        let cases = [
-            ("32gb", "did you mean 'Gi'"),
-            ("512mb", "did you mean 'Mi'"),
-            ("1kb", "did you mean 'Ki'"),
-            ("4GB", "did you mean 'Gi'"),
-            ("lots", "invalid memory format"),
-        ];
-        for (mem, expected_hint) in cases {
-            let mut p = Pipeline::new();
-            p.task("test").run("echo test").k8s(K8sOptions {
-                memory: Some(mem.to_string()),
-                ..Default::default()
-            });
-            let mut buf = Vec::new();
-            let result = p.emit_to(&mut buf);
-            assert!(result.is_err(), "expected {} to fail", mem);
-            let err_msg = result.unwrap_err().to_string();
-            assert!(
-                err_msg.contains(expected_hint),
-                "expected error for {} to contain '{}', got: {}",
-                mem,
-                expected_hint,
-                err_msg
-            );
-        }
+    fn test_explain_task_shows_dependency_closure() {
+        let p = diamond_with_extras();
+        let lines = explain_scope(|w| p.explain_task(w, "deploy", None));
+        assert_eq!(
+            lines,
+            [
+                "Scope: deploy and everything it depends on",
+                "Level 0: checkout",
+                "Level 1 (parallel): lint, test",
+                "Level 2: deploy",
+                "4 tasks in 3 levels, max parallelism 2",
+            ]
+        );
     }
 
     #[test]
-    fn test_k8s_validation_valid_cpu_formats() {
-        let valid = ["100m", "500m", "1", "2", "0.5", "1.5"];
-        for cpu in valid {
-            let opts = K8sOptions {
-                cpu: Some(cpu.to_string()),
-                ..Default::default()
-            };
-            let errors = opts.validate();
-            assert!(errors.is_empty(), "expected {} to be valid", cpu);
-        }
+    fn test_explain_dependents_shows_downstream() {
+        let p = diamond_with_extras();
+        let lines = explain_scope(|w| p.explain_dependents(w, "lint", None));
+        assert_eq!(
+            lines,
+            [
+                "Scope: lint and everything that depends on it",
+                "Level 0: lint",
+                "Level 1: deploy",
+                "Level 2: notify",
+                "3 tasks in 3 levels, max parallelism 1",
+            ]
+        );
     }
 
     #[test]
-    fn test_k8s_validation_invalid_cpu_formats() {
-        let cases = ["100cores", "2 cores", "fast"];
-        for cpu in cases {
-            let mut p = Pipeline::new();
-            p.task("test").run("echo test").k8s(K8sOptions {
-                cpu: Some(cpu.to_string()),
-                ..Default::default()
-            });
-            let mut buf = Vec::new();
-            let result = p.emit_to(&mut buf);
-            assert!(result.is_err(), "expected {} to fail", cpu);
-        }
+    fn test_explain_task_unknown_name_suggests() {
+        let p = diamond_with_extras();
+        let err = p.explain_task(&mut Vec::new(), "deplyo", None).unwrap_err();
+        assert_eq!(
+            err,
+            subset::SubsetError::UnknownTask {
+                name: "deplyo".to_string(),
+                suggestion: Some("deploy".to_string()),
+            }
+        );
+        assert!(p.explain_dependents(&mut Vec::new(), "nope", None).is_err());
     }
 
     #[test]
-    fn test_k8s_gpu() {
-        let mut p = Pipeline::new();
-        p.task("train").run("python train.py").k8s(K8sOptions {
-            memory: Some("32Gi".into()),
-            gpu: Some(2),
-            ..Default::default()
+    fn test_explain_filtered_pulls_in_dependencies() {
+        let mut p = diamond_with_extras();
+        let _ = p.task_or_get("test").tag("release");
+        let _ = p.task_or_get("docs").tag("ci");
+        let _ = p.task_or_get("notify").tags(&["release", "ci"]);
+
+        let release =
+            explain_scope(|w| p.explain_filtered(w, subset::TagFilter::Any(&["release"]), None));
+        assert_eq!(
+            release,
+            [
+                "Scope: tasks tagged release and everything they depend on",
+                "Level 0: checkout",
+                "Level 1 (parallel): lint, test",
+                "Level 2: deploy",
+                "Level 3: notify",
+                "5 tasks in 4 levels, max parallelism 2",
+            ]
+        );
+        let ci = explain_scope(|w| {
+            p.explain_filtered(w, subset::TagFilter::Any(&["nightly", "ci"]), None)
         });
+        assert_eq!(
+            ci[0],
+            "Scope: tasks tagged nightly or ci and everything they depend on"
+        );
+        assert_eq!(ci.len(), 6);
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        assert_eq!(json["tasks"][0]["k8s"]["memory"], "32Gi");
-        assert_eq!(json["tasks"][0]["k8s"]["gpu"], 2);
+        let err = p
+            .explain_filtered(&mut Vec::new(), subset::TagFilter::Any(&["nightly"]), None)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no tasks match tag=nightly");
     }
 
+    // ====== SECRET CHECK TESTS ======
+
     #[test]
-    fn test_k8s_raw_escape_hatch() {
-        // k8s_raw allows advanced options (tolerations, volumes, etc.)
+    fn test_explain_checks_secrets() {
+        std::env::set_var("SYKLI_TEST_PRESENT_SECRET", "x");
         let mut p = Pipeline::new();
-        p.task("gpu-train")
-            .run("python train.py")
-            .k8s(K8sOptions {
-                memory: Some("32Gi".into()),
-                gpu: Some(1),
-                ..Default::default()
-            })
-            .k8s_raw(r#"{"nodeSelector": {"gpu": "true"}, "tolerations": [{"key": "gpu", "effect": "NoSchedule"}]}"#);
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret_from("TOKEN", SecretRef::from_env("SYKLI_TEST_PRESENT_SECRET"))
+            .secret_from("KEY", SecretRef::from_file("Cargo.toml"))
+            .secret_from("CERT", SecretRef::from_file("/nonexistent/sykli/cert.pem"))
+            .secret_from("DB", SecretRef::from_vault("secret/db#password"))
+            .secret_from("REGISTRY", SecretRef::from_k8s("ci/registry#token"));
+        let _ = p
+            .task("publish")
+            .run("./publish.sh")
+            .secret("SYKLI_TEST_MISSING_SECRET");
 
+        let ctx = ExplainContext {
+            check_secrets: true,
+            ..Default::default()
+        };
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-
-        // Structured options
-        assert_eq!(json["tasks"][0]["k8s"]["memory"], "32Gi");
-        assert_eq!(json["tasks"][0]["k8s"]["gpu"], 1);
-        // Raw JSON passed through
-        assert!(json["tasks"][0]["k8s"]["raw"]
-            .as_str()
-            .unwrap()
-            .contains("nodeSelector"));
+        let report = p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
+
+        assert!(out.contains("     TOKEN (env:SYKLI_TEST_PRESENT_SECRET) \u{2713} present\n"));
+        assert!(out.contains("     KEY (file:Cargo.toml) \u{2713} present\n"));
+        assert!(out.contains("     CERT (file:/nonexistent/sykli/cert.pem) \u{2717} MISSING\n"));
+        assert!(out.contains("     DB (vault:secret/db#password) unchecked (vault)\n"));
+        assert!(out.contains("     REGISTRY (k8s:ci/registry#token) unchecked (k8s)\n"));
+        assert!(out.contains(
+            "     SYKLI_TEST_MISSING_SECRET (env:SYKLI_TEST_MISSING_SECRET) \u{2717} MISSING\n"
+        ));
+        assert!(out.contains("max parallelism 2\n2 secrets missing\n"));
+        assert_eq!(report.missing_secret_count(), 2);
+        assert_eq!(
+            report.missing_secrets,
+            [
+                ("deploy".to_string(), "CERT".to_string()),
+                (
+                    "publish".to_string(),
+                    "SYKLI_TEST_MISSING_SECRET".to_string()
+                ),
+            ]
+        );
     }
 
     #[test]
-    fn test_k8s_raw_only() {
-        // Can use k8s_raw without structured K8sOptions
+    fn test_explain_does_not_fail_on_optional_secrets() {
         let mut p = Pipeline::new();
-        p.task("custom")
-            .run("echo test")
-            .k8s_raw(r#"{"serviceAccount": "my-sa"}"#);
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .secret_opt("SYKLI_TEST_MISSING_TRACING_TOKEN")
+            .secret("SYKLI_TEST_MISSING_SECRET");
 
+        let ctx = ExplainContext {
+            check_secrets: true,
+            ..Default::default()
+        };
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        let report = p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains(
+            "     SYKLI_TEST_MISSING_TRACING_TOKEN (env:SYKLI_TEST_MISSING_TRACING_TOKEN) \u{2717} missing (optional)\n"
+        ));
+        assert!(out.contains("\n1 secret missing\n"));
+        assert_eq!(
+            report.missing_secrets,
+            [("test".to_string(), "SYKLI_TEST_MISSING_SECRET".to_string())]
+        );
 
-        assert!(json["tasks"][0]["k8s"]["raw"]
-            .as_str()
-            .unwrap()
-            .contains("serviceAccount"));
+        let mut buf = Vec::new();
+        let _ = p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains(
+            "   Secrets: SYKLI_TEST_MISSING_SECRET, SYKLI_TEST_MISSING_TRACING_TOKEN (env:SYKLI_TEST_MISSING_TRACING_TOKEN, optional)\n"
+        ));
     }
 
     #[test]
-    fn test_k8s_validation_with_defaults() {
-        // Validation should happen after merging with defaults
-        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
-            memory: Some("invalid_memory".to_string()),
-            ..Default::default()
-        });
-        p.task("test").run("echo test");
+    fn test_explain_without_secret_check_reports_nothing() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("publish")
+            .run("./publish.sh")
+            .secret("SYKLI_TEST_MISSING_SECRET");
 
         let mut buf = Vec::new();
-        let result = p.emit_to(&mut buf);
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("invalid memory format"));
+        let report = p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("   Secrets: SYKLI_TEST_MISSING_SECRET\n"));
+        assert!(!out.contains("missing"));
+        assert_eq!(report, ExplainReport::default());
     }
 
-    // =========================================================================
-    // MATRIX BUILD TESTS
-    // =========================================================================
+    // ====== DURATION ESTIMATE TESTS ======
 
-    #[test]
-    fn test_matrix_basic() {
+    fn estimated_diamond() -> Pipeline {
         let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy").estimated_duration(60);
+        let _ = p
+            .task("unit")
+            .run("cargo test --lib")
+            .after(&["lint"])
+            .estimated_duration(120);
+        let _ = p
+            .task("build")
+            .run("cargo build --release")
+            .after(&["lint"])
+            .estimated_duration(600);
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .after(&["unit", "build"])
+            .estimated_duration(180);
+        p
+    }
 
-        let group = p.matrix("go-versions", &["1.21", "1.22", "1.23"], |p, version| {
-            p.task(&format!("test-go-{}", version))
-                .container(&format!("golang:{}", version))
-                .run("go test ./...");
-        });
+    #[test]
+    fn test_critical_path_follows_longer_branch() {
+        let p = estimated_diamond();
+        assert_eq!(
+            p.critical_path(),
+            (
+                vec![
+                    "lint".to_string(),
+                    "build".to_string(),
+                    "deploy".to_string()
+                ],
+                840
+            )
+        );
+        assert_eq!(Pipeline::new().critical_path(), (Vec::new(), 0));
+    }
 
-        // Should have 3 task names
-        assert_eq!(group.task_names.len(), 3);
-        assert_eq!(group.name, "go-versions");
+    #[test]
+    fn test_estimated_duration_in_json() {
+        let p = estimated_diamond();
+        let json = emit_json(&p);
+        assert_eq!(json["tasks"][0]["estimated_duration"], 60);
 
-        // Verify task names
-        assert!(group.task_names.contains(&"test-go-1.21".to_string()));
-        assert!(group.task_names.contains(&"test-go-1.22".to_string()));
-        assert!(group.task_names.contains(&"test-go-1.23".to_string()));
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test");
+        assert!(emit_json(&p)["tasks"][0]["estimated_duration"].is_null());
+    }
+
+    #[test]
+    fn test_explain_shows_estimates() {
+        let mut p = estimated_diamond();
+        let _ = p.task("notify").run("./notify.sh").after(&["deploy"]);
 
-        // Should emit successfully
         let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-        let tasks = json["tasks"].as_array().unwrap();
-        assert_eq!(tasks.len(), 3);
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+
+        assert!(out.contains("Level 1 (parallel): unit, build (est. 10m)\n"));
+        assert!(out.contains("   Estimate: 2m\n"));
+        assert!(out.contains("   Estimate: none (counted as 0)\n"));
+        assert!(out.contains(
+            "estimated total: 14m, critical path: lint \u{2192} build \u{2192} deploy\n"
+        ));
+        assert!(out.contains("no estimate (counted as 0): notify\n"));
     }
 
     #[test]
-    #[should_panic(expected = "matrix: values must not be empty")]
-    fn test_matrix_empty_panics() {
+    fn test_explain_without_estimates_is_unchanged() {
         let mut p = Pipeline::new();
-        p.matrix("empty", &[], |p, v| {
-            p.task(&format!("test-{}", v)).run("test");
-        });
+        let _ = p.task("test").run("cargo test");
+
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("Level 0: test\n"));
+        assert!(!out.contains("(est.") && !out.contains("Estimate") && !out.contains("estimated"));
     }
 
     #[test]
-    fn test_matrix_as_dependency() {
-        let mut p = Pipeline::new();
+    fn test_format_duration() {
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(840), "14m");
+        assert_eq!(format_duration(150), "2m 30s");
+        assert_eq!(format_duration(3900), "1h 5m");
+        assert_eq!(format_duration(7200), "2h");
+    }
 
-        let tests = p.matrix("tests", &["1.21", "1.22"], |p, version| {
-            p.task(&format!("test-{}", version)).run("go test");
-        });
+    // ====== DETERMINISTIC JSON TESTS ======
 
-        // Another task depends on the entire matrix
-        p.task("deploy").after_group(&tests).run("deploy");
+    fn map_heavy_pipeline() -> Pipeline {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let caches: Vec<_> = ["npm", "cargo", "go-mod", "pip"]
+            .iter()
+            .map(|c| p.cache(c))
+            .collect();
+        let mut build = p.task("build").container("rust:1.75").mount(&src, "/src");
+        for (i, c) in caches.iter().enumerate() {
+            build = build.mount_cache(c, &format!("/cache/{}", i));
+        }
+        for key in ["ZETA", "ALPHA", "MIKE", "BRAVO", "YANKEE", "CHARLIE"] {
+            build = build.env(key, "1");
+        }
+        for name in ["sbom", "binary", "docs", "archive"] {
+            build = build.output(name, &format!("/out/{}", name));
+        }
+        let _ = build
+            .run("cargo build")
+            .matrix("rust", &["1.80", "1.75"])
+            .matrix("os", &["linux", "macos"]);
+        p
+    }
 
+    fn emit_bytes(p: &Pipeline) -> Vec<u8> {
         let mut buf = Vec::new();
         p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        buf
+    }
 
-        // Find deploy task
-        let tasks = json["tasks"].as_array().unwrap();
-        let deploy = tasks.iter().find(|t| t["name"] == "deploy").unwrap();
-        let deps = deploy["depends_on"].as_array().unwrap();
+    #[test]
+    fn test_emit_is_byte_for_byte_stable() {
+        let first = emit_bytes(&map_heavy_pipeline());
+        for _ in 0..50 {
+            assert_eq!(emit_bytes(&map_heavy_pipeline()), first);
+        }
+    }
 
-        // Should depend on both matrix tasks
-        assert_eq!(deps.len(), 2);
+    #[test]
+    fn test_emit_sorts_map_keys() {
+        let out = String::from_utf8(emit_bytes(&map_heavy_pipeline())).unwrap();
+        let position = |needle: &str| out.find(needle).unwrap();
+        assert!(position("\"ALPHA\"") < position("\"BRAVO\""));
+        assert!(position("\"BRAVO\"") < position("\"ZETA\""));
+        assert!(position("\"archive\"") < position("\"sbom\""));
+        assert!(position("\"cargo\":") < position("\"npm\":"));
+        // Matrix keeps declaration order, not sorted order
+        assert!(position("\"rust\"") < position("\"os\""));
     }
 
+    // ====== PRETTY EMIT TESTS ======
+
     #[test]
-    fn test_task_group_new() {
-        let group = TaskGroup::new(
-            "test-group",
-            vec!["task-a".to_string(), "task-b".to_string()],
-        );
+    fn test_pretty_emit_matches_compact() {
+        let p = map_heavy_pipeline();
+        let mut pretty = Vec::new();
+        p.emit_pretty_to(&mut pretty).unwrap();
+        let compact = emit_bytes(&p);
 
-        assert_eq!(group.name, "test-group");
-        assert_eq!(group.names(), &["task-a", "task-b"]);
+        assert!(pretty.len() > compact.len());
+        assert_eq!(compact.iter().filter(|&&b| b == b'\n').count(), 1);
+        let pretty: serde_json::Value = serde_json::from_slice(&pretty).unwrap();
+        let compact: serde_json::Value = serde_json::from_slice(&compact).unwrap();
+        assert_eq!(pretty, compact);
     }
 
     #[test]
-    fn test_after_group() {
+    fn test_pretty_emit_validates() {
         let mut p = Pipeline::new();
+        let _ = p.task("a").run("true").after(&["missing"]);
+        assert!(p.emit_pretty_to(&mut Vec::new()).is_err());
+    }
 
-        p.task("a").run("echo a");
-        p.task("b").run("echo b");
-
-        let group = TaskGroup::new("prereqs", vec!["a".to_string(), "b".to_string()]);
+    #[test]
+    fn test_pretty_requested() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(pretty_requested(&args(&["p", "--emit-pretty"]), None));
+        assert!(pretty_requested(&args(&["p", "--emit"]), Some("1")));
+        assert!(!pretty_requested(&args(&["p", "--emit"]), None));
+        assert!(!pretty_requested(&args(&["p", "--emit"]), Some("0")));
+    }
 
-        p.task("c").after_group(&group).run("echo c");
+    // ====== EMIT FILE TESTS ======
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("sykli-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("pipeline.json")
+    }
 
-        let tasks = json["tasks"].as_array().unwrap();
-        let task_c = tasks.iter().find(|t| t["name"] == "c").unwrap();
-        let deps = task_c["depends_on"].as_array().unwrap();
+    #[test]
+    fn test_emit_file_writes_same_bytes() {
+        let path = temp_path("emit-file");
+        let p = map_heavy_pipeline();
+        p.emit_file(&path).unwrap();
 
-        // Should depend on both a and b
-        assert_eq!(deps.len(), 2);
-        assert!(deps.contains(&serde_json::json!("a")));
-        assert!(deps.contains(&serde_json::json!("b")));
+        assert_eq!(std::fs::read(&path).unwrap(), emit_bytes(&p));
+        let entries: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["pipeline.json"]);
     }
 
-    // =============================================================================
-    // PARALLEL METHOD TESTS
-    // =============================================================================
-
     #[test]
-    fn test_parallel_creates_group() {
+    fn test_emit_file_failure_keeps_previous_file() {
+        let path = temp_path("emit-file-fail");
+        std::fs::write(&path, "previous").unwrap();
         let mut p = Pipeline::new();
-        p.task("lint").run("cargo clippy");
-        p.task("test").run("cargo test");
+        let _ = p.task("a").run("true").after(&["missing"]);
 
-        let checks = p.parallel("checks", &["lint", "test"]);
+        let err = p.emit_file(&path).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}: ", path.display())));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous");
+        assert_eq!(
+            std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+            1
+        );
 
-        assert_eq!(checks.name, "checks");
-        assert_eq!(checks.names(), &["lint", "test"]);
+        let missing_dir = path.parent().unwrap().join("nope").join("pipeline.json");
+        let err = map_heavy_pipeline().emit_file(&missing_dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(&missing_dir.display().to_string()));
     }
 
     #[test]
-    fn test_parallel_as_group_dependency() {
-        let mut p = Pipeline::new();
-        p.task("lint").run("cargo clippy");
-        p.task("test").run("cargo test");
-
-        let checks = p.parallel("checks", &["lint", "test"]);
-
-        p.task("build").after_group(&checks).run("cargo build");
+    fn test_emit_arg_parsing() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(emit_arg(&args(&["p", "--emit"])), Some(None));
+        assert_eq!(emit_arg(&args(&["p", "--emit-pretty"])), Some(None));
+        assert_eq!(
+            emit_arg(&args(&["p", "--emit=/tmp/pipeline.json"])),
+            Some(Some("/tmp/pipeline.json"))
+        );
+        assert_eq!(emit_arg(&args(&["p", "--emit-subset", "tag=a"])), None);
+        assert_eq!(emit_arg(&args(&["p"])), None);
+    }
 
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    // ====== TO_JSON TESTS ======
 
-        let tasks = json["tasks"].as_array().unwrap();
-        let build = tasks.iter().find(|t| t["name"] == "build").unwrap();
-        let deps = build["depends_on"].as_array().unwrap();
+    #[test]
+    fn test_to_json_matches_emit_to() {
+        let p = map_heavy_pipeline();
+        let json = p.to_json().unwrap();
+        assert_eq!(format!("{}\n", json).into_bytes(), emit_bytes(&p));
 
-        assert_eq!(deps.len(), 2);
-        assert!(deps.contains(&serde_json::json!("lint")));
-        assert!(deps.contains(&serde_json::json!("test")));
+        let value = p.to_json_value().unwrap();
+        assert_eq!(value, emit_json(&p));
+        assert_eq!(value["tasks"][0]["name"], "build");
+        assert_eq!(value["tasks"][0]["env"]["ALPHA"], "1");
+        assert_eq!(value["resources"]["npm"]["type"], "cache");
     }
 
     #[test]
-    #[should_panic(expected = "task \"unknown\" not found")]
-    fn test_parallel_unknown_task_panics() {
+    fn test_to_json_validates() {
         let mut p = Pipeline::new();
-        p.task("lint").run("cargo clippy");
+        let _ = p.task("a").run("true").after(&["missing"]);
 
-        // "unknown" doesn't exist
-        p.parallel("checks", &["lint", "unknown"]);
+        let err = p.to_json().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let inner = err.get_ref().and_then(|e| e.downcast_ref::<EmitError>());
+        assert!(matches!(inner, Some(EmitError::UnknownDependency { .. })));
+        assert_eq!(p.to_json_value().unwrap_err().to_string(), err.to_string());
+        assert_eq!(
+            p.emit_to(&mut Vec::new()).unwrap_err().to_string(),
+            err.to_string()
+        );
     }
 
+    // ====== YAML EMIT TESTS ======
+
+    #[cfg(feature = "yaml")]
     #[test]
-    fn test_gate_basic() {
-        let mut p = Pipeline::new();
-        p.task("build").run("make build");
-        p.gate("approve-deploy")
+    fn test_yaml_matches_json() {
+        let mut p = map_heavy_pipeline();
+        let _ = p
+            .task("deploy")
+            .run("echo \"deploying: main\"\n./deploy.sh")
             .after(&["build"])
-            .gate_strategy("env")
-            .gate_timeout(600)
-            .gate_message("Approve deployment to production?")
-            .gate_env_var("DEPLOY_APPROVED");
-        p.task("deploy")
-            .run("make deploy")
-            .after(&["approve-deploy"]);
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap();
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+            .when("branch == 'main'")
+            .retry(2);
 
-        // Gate task has no command
-        assert_eq!(json["tasks"][1]["name"], "approve-deploy");
-        assert!(json["tasks"][1]["command"].is_null());
-        // Gate config
-        assert_eq!(json["tasks"][1]["gate"]["strategy"], "env");
-        assert_eq!(json["tasks"][1]["gate"]["timeout"], 600);
-        assert_eq!(
-            json["tasks"][1]["gate"]["message"],
-            "Approve deployment to production?"
-        );
-        assert_eq!(json["tasks"][1]["gate"]["env_var"], "DEPLOY_APPROVED");
-        // Non-gate tasks still have commands
-        assert_eq!(json["tasks"][0]["command"], "make build");
-        assert_eq!(json["tasks"][2]["command"], "make deploy");
+        let yaml = p.to_yaml().unwrap();
+        let value: serde_json::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(value, emit_json(&p));
+        // Matrix keys keep declaration order, as in JSON.
+        assert!(yaml.find("rust:").unwrap() < yaml.find("os:").unwrap());
     }
 
+    #[cfg(feature = "yaml")]
     #[test]
-    #[should_panic(expected = "gate methods can only be used on gate tasks")]
-    fn test_gate_methods_on_non_gate_panics() {
+    fn test_yaml_validates() {
         let mut p = Pipeline::new();
-        p.task("build").run("make build").gate_strategy("env");
+        let _ = p.task("a").run("true").after(&["missing"]);
+        assert_eq!(
+            p.to_yaml().unwrap_err().to_string(),
+            p.to_json().unwrap_err().to_string()
+        );
     }
 
     #[test]
-    fn test_gate_no_command_validation() {
-        // Gates should not fail validation for missing command
-        let mut p = Pipeline::new();
-        p.gate("approve");
-
-        let mut buf = Vec::new();
-        p.emit_to(&mut buf).unwrap(); // should not error
-        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
-        assert_eq!(json["tasks"][0]["gate"]["strategy"], "prompt");
-        assert_eq!(json["tasks"][0]["gate"]["timeout"], 3600);
+    fn test_emit_yaml_is_not_a_json_emit_flag() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(emit_arg(&args(&["bin", "--emit-yaml"])), None);
+        assert!(!pretty_requested(&args(&["bin", "--emit-yaml"]), None));
     }
 }
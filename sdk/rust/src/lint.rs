@@ -0,0 +1,1338 @@
+//! Checks for pipeline shapes that are valid but usually mistakes.
+//!
+//! Unlike emit validation, lints never fail a build on their own, except
+//! unused resources under [`Pipeline::strict`]. Teams that want them to can
+//! inspect [`Pipeline::lint`] in a test, and silence a rule with
+//! [`Pipeline::allow_lint`]:
+//!
+//! ```rust
+//! use sykli::lint::LintCode;
+//! use sykli::Pipeline;
+//!
+//! let mut p = Pipeline::new();
+//! let target = p.cache("cargo-target");
+//! p.task("build")
+//!     .container("rust:1.75")
+//!     .mount_cache(&target, "/src/target")
+//!     .mount_cache(&target, "/build/target")
+//!     .run("cargo build");
+//!
+//! let codes: Vec<_> = p.lint().iter().map(|w| w.code).collect();
+//! assert!(codes.contains(&LintCode::CacheMountedTwice));
+//!
+//! p.allow_lint(LintCode::CacheMountedTwice);
+//! assert!(p.lint().iter().all(|w| w.code != LintCode::CacheMountedTwice));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::validate::{EmitError, Issues};
+use crate::{CacheSharing, Pipeline, SecretNameRule, SecretSource, TaskData};
+
+/// Identifies a lint rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LintCode {
+    /// One task mounts the same cache at more than one path.
+    CacheMountedTwice,
+    /// Tasks that can run at the same time both mount a cache read-write.
+    CacheSharedConcurrently,
+    /// Tasks at the same level share a cache, and one writes build outputs
+    /// to it under its workdir.
+    CacheWriteRace,
+    /// Where each cache is mounted across the pipeline (info).
+    CacheMountPoints,
+    /// A declared directory, cache or file is never mounted.
+    UnusedResource,
+    /// A task runs in a container but mounts nothing.
+    ContainerWithoutMounts,
+    /// A task has mounts but no container, so they don't apply.
+    MountsWithoutContainer,
+    /// A task sets an entrypoint or user but has no container.
+    ContainerOptionsWithoutContainer,
+    /// A containerized task reads an artifact whose output path is relative.
+    RelativeArtifactPath,
+    /// A task retries but has no timeout.
+    RetryWithoutTimeout,
+    /// A task's timeout is above one hour.
+    LongTimeout,
+    /// A task sets `PATH` or `HOME`, replacing the image's own.
+    OverriddenSystemEnv,
+    /// A task that must pass reads an output of a task allowed to fail.
+    InputFromAllowedFailure,
+    /// A concurrency group has a single task, so it never has to wait.
+    SingleTaskConcurrencyGroup,
+    /// A task reads an output that isn't required to exist.
+    InputFromOptionalOutput,
+    /// A task declares the same secret more than once; emit keeps one.
+    DuplicateSecret,
+    /// A file secret's path is neither absolute nor `./`/`../`-relative.
+    RelativeSecretPath,
+    /// A secret name isn't upper snake case and no name rule is set.
+    UnconventionalSecretName,
+}
+
+impl LintCode {
+    /// Stable kebab-case name, as shown in explain output.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintCode::CacheMountedTwice => "cache-mounted-twice",
+            LintCode::CacheSharedConcurrently => "cache-shared-concurrently",
+            LintCode::CacheWriteRace => "cache-write-race",
+            LintCode::CacheMountPoints => "cache-mount-points",
+            LintCode::UnusedResource => "unused-resource",
+            LintCode::ContainerWithoutMounts => "container-without-mounts",
+            LintCode::MountsWithoutContainer => "mounts-without-container",
+            LintCode::ContainerOptionsWithoutContainer => "container-options-without-container",
+            LintCode::RelativeArtifactPath => "relative-artifact-path",
+            LintCode::RetryWithoutTimeout => "retry-without-timeout",
+            LintCode::LongTimeout => "long-timeout",
+            LintCode::OverriddenSystemEnv => "overridden-system-env",
+            LintCode::InputFromAllowedFailure => "input-from-allowed-failure",
+            LintCode::SingleTaskConcurrencyGroup => "single-task-concurrency-group",
+            LintCode::InputFromOptionalOutput => "input-from-optional-output",
+            LintCode::DuplicateSecret => "duplicate-secret",
+            LintCode::RelativeSecretPath => "relative-secret-path",
+            LintCode::UnconventionalSecretName => "unconventional-secret-name",
+        }
+    }
+}
+
+/// How much a lint finding matters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Background information, shown in verbose explain only.
+    Info,
+    /// Likely a mistake.
+    Warning,
+}
+
+/// One lint finding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PipelineWarning {
+    /// Rule that produced it.
+    pub code: LintCode,
+    /// How much it matters.
+    pub severity: Severity,
+    /// Task it is about, if it concerns a single task.
+    pub task: Option<String>,
+    /// What's wrong and how to fix it.
+    pub message: String,
+}
+
+impl fmt::Display for PipelineWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}[{}]", level, self.code.as_str())?;
+        if let Some(task) = &self.task {
+            write!(f, " task {:?}", task)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Message for a directory or cache that is never mounted.
+pub(crate) fn unused_resource_message(kind: &str, id: &str) -> String {
+    let fix = match kind {
+        "cache" => "mount_cache()",
+        "file" => "mount_file_res()",
+        _ => "mount()",
+    };
+    format!(
+        "{} {:?} is never mounted; mount it with {} or remove it",
+        kind, id, fix
+    )
+}
+
+impl Pipeline {
+    /// Runs every lint rule that hasn't been allowed, in rule order.
+    pub fn lint(&self) -> Vec<PipelineWarning> {
+        let mut warnings = Vec::new();
+        self.lint_cache_mounted_twice(&mut warnings);
+        self.lint_cache_shared_concurrently(&mut warnings);
+        self.lint_cache_write_races(&mut warnings);
+        self.lint_cache_mount_points(&mut warnings);
+        self.lint_unused_resources(&mut warnings);
+        self.lint_container_mounts(&mut warnings);
+        self.lint_relative_artifact_paths(&mut warnings);
+        self.lint_inputs_from_allowed_failures(&mut warnings);
+        self.lint_timeouts(&mut warnings);
+        self.lint_system_env(&mut warnings);
+        self.lint_concurrency_groups(&mut warnings);
+        self.lint_inputs_from_optional_outputs(&mut warnings);
+        self.lint_secrets(&mut warnings);
+        warnings.retain(|w| !self.allowed_lints.contains(&w.code));
+        warnings
+    }
+
+    /// Makes declared directories, caches and files that no task mounts fail emit,
+    /// instead of only showing up as `unused-resource` warnings. A forgotten
+    /// `mount_cache()` otherwise silently loses caching.
+    ///
+    /// [`Pipeline::allow_lint`] with [`LintCode::UnusedResource`] still
+    /// silences them.
+    ///
+    /// Strict mode also requires a [`sha256`](crate::HttpFile::sha256) on
+    /// every downloaded file.
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Silences a lint rule for this pipeline.
+    pub fn allow_lint(&mut self, code: LintCode) -> &mut Self {
+        if !self.allowed_lints.contains(&code) {
+            self.allowed_lints.push(code);
+        }
+        self
+    }
+
+    /// Cache mounts as `(cache, path)` pairs, in declaration order.
+    fn cache_mounts(t: &TaskData) -> impl Iterator<Item = (&str, &str)> {
+        t.mounts
+            .iter()
+            .filter(|m| m.mount_type == "cache")
+            .map(|m| (m.resource.as_str(), m.path.as_str()))
+    }
+
+    fn lint_cache_mounted_twice(&self, out: &mut Vec<PipelineWarning>) {
+        for t in &self.tasks {
+            let mut paths: Vec<(&str, Vec<&str>)> = Vec::new();
+            for (cache, path) in Self::cache_mounts(t) {
+                match paths.iter_mut().find(|(c, _)| *c == cache) {
+                    Some((_, p)) => p.push(path),
+                    None => paths.push((cache, vec![path])),
+                }
+            }
+            for (cache, paths) in paths.into_iter().filter(|(_, p)| p.len() > 1) {
+                out.push(PipelineWarning {
+                    code: LintCode::CacheMountedTwice,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "cache {:?} is mounted at {}; mount it once or use a separate cache",
+                        cache,
+                        paths.join(" and ")
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_cache_shared_concurrently(&self, out: &mut Vec<PipelineWarning>) {
+        let ancestors = self.ancestors();
+        for (cache, users) in self.cache_users() {
+            let mut pairs = Vec::new();
+            for (i, a) in users.iter().enumerate() {
+                for b in &users[i + 1..] {
+                    let ordered = ancestors[a].contains(b) || ancestors[b].contains(a);
+                    if !ordered {
+                        pairs.push(format!("{}/{}", a, b));
+                    }
+                }
+            }
+            if pairs.is_empty() {
+                continue;
+            }
+            out.push(PipelineWarning {
+                code: LintCode::CacheSharedConcurrently,
+                severity: Severity::Warning,
+                task: None,
+                message: format!(
+                    "cache {:?} is mounted read-write by tasks that can run concurrently ({}); \
+                     order them with after() or give the mounts a Locked sharing mode with mount_cache_with()",
+                    cache,
+                    pairs.join(", ")
+                ),
+            });
+        }
+    }
+
+    /// Shared mounts under the workdir are where builds put their outputs,
+    /// so tasks at the same level writing there can corrupt each other.
+    fn lint_cache_write_races(&self, out: &mut Vec<PipelineWarning>) {
+        for level in self.task_levels() {
+            let mut users: Vec<(&str, Vec<&str>, Vec<&str>)> = Vec::new();
+            for t in level {
+                let workdir = t.workdir.as_deref().map(|w| w.trim_end_matches('/'));
+                for (cache, path) in Self::shared_cache_mounts(t) {
+                    let under_workdir = workdir.is_some_and(|w| {
+                        path.strip_prefix(w)
+                            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+                    });
+                    let i = match users.iter().position(|(c, _, _)| *c == cache) {
+                        Some(i) => i,
+                        None => {
+                            users.push((cache, Vec::new(), Vec::new()));
+                            users.len() - 1
+                        }
+                    };
+                    let (_, tasks, writers) = &mut users[i];
+                    if tasks.last() != Some(&t.name.as_str()) {
+                        tasks.push(&t.name);
+                    }
+                    if under_workdir && writers.last() != Some(&t.name.as_str()) {
+                        writers.push(&t.name);
+                    }
+                }
+            }
+            for (cache, tasks, writers) in users {
+                if tasks.len() < 2 || writers.is_empty() {
+                    continue;
+                }
+                out.push(PipelineWarning {
+                    code: LintCode::CacheWriteRace,
+                    severity: Severity::Warning,
+                    task: None,
+                    message: format!(
+                        "cache {:?} is shared by {}, which run at the same level, and {} \
+                         {} build outputs to it under the workdir; mount it with \
+                         CacheSharing::Locked or CacheSharing::Private",
+                        cache,
+                        tasks.join(", "),
+                        writers.join(", "),
+                        if writers.len() == 1 {
+                            "writes"
+                        } else {
+                            "write"
+                        }
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_cache_mount_points(&self, out: &mut Vec<PipelineWarning>) {
+        for c in &self.caches {
+            let mounts: Vec<_> = self
+                .tasks
+                .iter()
+                .flat_map(|t| {
+                    Self::cache_mounts(t)
+                        .filter(|(cache, _)| *cache == c.name)
+                        .map(move |(_, path)| format!("{} at {}", t.name, path))
+                })
+                .collect();
+            if mounts.is_empty() {
+                continue;
+            }
+            out.push(PipelineWarning {
+                code: LintCode::CacheMountPoints,
+                severity: Severity::Info,
+                task: None,
+                message: format!("cache {:?} is mounted by {}", c.name, mounts.join(", ")),
+            });
+        }
+    }
+
+    fn lint_unused_resources(&self, out: &mut Vec<PipelineWarning>) {
+        for (kind, id) in self.unused_resources() {
+            out.push(PipelineWarning {
+                code: LintCode::UnusedResource,
+                severity: Severity::Warning,
+                task: None,
+                message: unused_resource_message(kind, &id),
+            });
+        }
+    }
+
+    /// Declared directories, caches and files that no task mounts, as
+    /// `(kind, id)` pairs. Template mounts count once the template is used.
+    fn unused_resources(&self) -> Vec<(&'static str, String)> {
+        let mounted: HashSet<&str> = self
+            .tasks
+            .iter()
+            .flat_map(|t| t.mounts.iter().map(|m| m.resource.as_str()))
+            .collect();
+        let dirs = self
+            .directories()
+            .into_iter()
+            .map(|d| ("directory", d.id()));
+        let caches = self.caches.iter().map(|c| ("cache", c.name.clone()));
+        let files = self.files.iter().map(|f| ("file", f.id()));
+        dirs.chain(caches)
+            .chain(files)
+            .filter(|(_, id)| !mounted.contains(id.as_str()))
+            .collect()
+    }
+
+    /// In strict mode, unused resources fail emit unless the lint is allowed.
+    pub(crate) fn check_unused_resources(&self, issues: &mut Issues) {
+        if !self.strict || self.allowed_lints.contains(&LintCode::UnusedResource) {
+            return;
+        }
+        for (kind, id) in self.unused_resources() {
+            issues.error(EmitError::UnusedResource { kind, id });
+        }
+    }
+
+    fn lint_container_mounts(&self, out: &mut Vec<PipelineWarning>) {
+        for t in &self.tasks {
+            let containerized = t.container.is_some() || t.container_build.is_some();
+            if containerized && t.mounts.is_empty() {
+                out.push(PipelineWarning {
+                    code: LintCode::ContainerWithoutMounts,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: "runs in a container without mounts, so it can't see the workspace; \
+                              add mount_cwd() or mount()"
+                        .to_string(),
+                });
+            }
+            if !containerized && !t.mounts.is_empty() {
+                out.push(PipelineWarning {
+                    code: LintCode::MountsWithoutContainer,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: "has mounts but no container, and mounts only apply inside one; \
+                              set container() or drop the mounts"
+                        .to_string(),
+                });
+            }
+            let options: Vec<&str> = [
+                ("entrypoint()", t.entrypoint.is_some()),
+                ("user()", t.user.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(name, set)| set.then_some(name))
+            .collect();
+            if !containerized && !options.is_empty() {
+                out.push(PipelineWarning {
+                    code: LintCode::ContainerOptionsWithoutContainer,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "sets {} but has no container, and they only apply inside one; \
+                         set container() or drop them",
+                        options.join(" and ")
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_relative_artifact_paths(&self, out: &mut Vec<PipelineWarning>) {
+        for t in &self.tasks {
+            if t.container.is_none() && t.container_build.is_none() {
+                continue;
+            }
+            for input in &self.with_defaults(t).task_inputs {
+                let path = self
+                    .tasks
+                    .iter()
+                    .find(|p| p.name == input.from_task)
+                    .and_then(|p| p.outputs.get(&input.output));
+                let Some(path) = path.filter(|path| !path.starts_with('/')) else {
+                    continue;
+                };
+                out.push(PipelineWarning {
+                    code: LintCode::RelativeArtifactPath,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "reads output {:?} of {:?} at relative path {:?}, which the container \
+                         resolves against its workdir; give output() an absolute path",
+                        input.output, input.from_task, path
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_inputs_from_allowed_failures(&self, out: &mut Vec<PipelineWarning>) {
+        for t in self.tasks.iter().filter(|t| !t.allow_failure) {
+            for input in &self.with_defaults(t).task_inputs {
+                let allowed = self
+                    .tasks
+                    .iter()
+                    .any(|p| p.name == input.from_task && p.allow_failure);
+                if !allowed {
+                    continue;
+                }
+                out.push(PipelineWarning {
+                    code: LintCode::InputFromAllowedFailure,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "reads output {:?} of {:?}, which is allowed to fail, so the artifact \
+                         may be missing; allow this task to fail too or drop the input",
+                        input.output, input.from_task
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_timeouts(&self, out: &mut Vec<PipelineWarning>) {
+        for t in &self.tasks {
+            let t = &*self.with_defaults(t);
+            if let (Some(retry), None) = (t.retry.as_ref().map(|r| r.max), t.timeout) {
+                out.push(PipelineWarning {
+                    code: LintCode::RetryWithoutTimeout,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "retries {} times without a timeout, so a hung attempt is never retried; \
+                         set timeout()",
+                        retry
+                    ),
+                });
+            }
+            if let Some(timeout) = t.timeout.filter(|&secs| secs > 3600) {
+                out.push(PipelineWarning {
+                    code: LintCode::LongTimeout,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "timeout of {} is over one hour; split the task or lower the timeout",
+                        crate::format_duration(timeout)
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_system_env(&self, out: &mut Vec<PipelineWarning>) {
+        for t in &self.tasks {
+            let t = &*self.with_defaults(t);
+            for key in ["PATH", "HOME"]
+                .into_iter()
+                .filter(|k| t.env.contains_key(*k))
+            {
+                out.push(PipelineWarning {
+                    code: LintCode::OverriddenSystemEnv,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "sets {}, replacing the one the image or runner provides; \
+                         extend it in the command instead",
+                        key
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_secrets(&self, out: &mut Vec<PipelineWarning>) {
+        for t in &self.tasks {
+            for (name, count) in t.duplicate_secrets() {
+                out.push(PipelineWarning {
+                    code: LintCode::DuplicateSecret,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!("declares secret {:?} {} times; emit keeps one", name, count),
+                });
+            }
+            let t = &*self.with_defaults(t);
+            if self.secret_name_rule.is_none() {
+                let mut seen: Vec<String> = Vec::new();
+                for sr in t.secret_declarations() {
+                    let name = sr.name.as_str();
+                    if SecretNameRule::UpperSnake.allows(name) || seen.iter().any(|n| n == name) {
+                        continue;
+                    }
+                    seen.push(name.to_string());
+                    out.push(PipelineWarning {
+                        code: LintCode::UnconventionalSecretName,
+                        severity: Severity::Warning,
+                        task: Some(t.name.clone()),
+                        message: format!(
+                            "secret {:?} doesn't match {}; rename it, or set \
+                             Pipeline::secret_name_rule to pick the rule emit enforces",
+                            name,
+                            SecretNameRule::UpperSnake.pattern()
+                        ),
+                    });
+                }
+            }
+            for sr in t.secret_refs.iter().filter(|sr| {
+                matches!(sr.source, SecretSource::File)
+                    && !["/", "./", "../"].iter().any(|p| sr.key.starts_with(p))
+            }) {
+                out.push(PipelineWarning {
+                    code: LintCode::RelativeSecretPath,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "reads secret {:?} from {:?}; write \"./{}\" if it's relative to the \
+                         workdir, or use an absolute path",
+                        sr.name, sr.key, sr.key
+                    ),
+                });
+            }
+        }
+    }
+
+    fn lint_concurrency_groups(&self, out: &mut Vec<PipelineWarning>) {
+        // (group, first task, tasks after matrix expansion), in order of first use
+        let mut groups: Vec<(&str, &str, usize)> = Vec::new();
+        for t in &self.tasks {
+            let Some(c) = &t.concurrency else { continue };
+            let variants = t.matrix.variants().len().max(1);
+            match groups.iter_mut().find(|(g, _, _)| *g == c.group) {
+                Some((_, _, count)) => *count += variants,
+                None => groups.push((&c.group, &t.name, variants)),
+            }
+        }
+        for (group, task, _) in groups.into_iter().filter(|(_, _, n)| *n == 1) {
+            out.push(PipelineWarning {
+                code: LintCode::SingleTaskConcurrencyGroup,
+                severity: Severity::Warning,
+                task: Some(task.to_string()),
+                message: format!(
+                    "is the only task in concurrency group {:?}, so the group only keeps \
+                     runs of this task apart; check the group name for a typo",
+                    group
+                ),
+            });
+        }
+    }
+
+    fn lint_inputs_from_optional_outputs(&self, out: &mut Vec<PipelineWarning>) {
+        for t in &self.tasks {
+            for input in &self.with_defaults(t).task_inputs {
+                let optional = self
+                    .tasks
+                    .iter()
+                    .find(|p| p.name == input.from_task)
+                    .and_then(|p| p.output_options.get(&input.output))
+                    .is_some_and(|o| !o.required);
+                if !optional {
+                    continue;
+                }
+                out.push(PipelineWarning {
+                    code: LintCode::InputFromOptionalOutput,
+                    severity: Severity::Warning,
+                    task: Some(t.name.clone()),
+                    message: format!(
+                        "reads output {:?} of {:?}, which isn't required, so the artifact \
+                         may be missing; make it required or handle its absence",
+                        input.output, input.from_task
+                    ),
+                });
+            }
+        }
+    }
+
+    /// Shared cache mounts as `(cache, path)` pairs, in declaration order.
+    fn shared_cache_mounts(t: &TaskData) -> impl Iterator<Item = (&str, &str)> {
+        t.mounts
+            .iter()
+            .filter(|m| m.mount_type == "cache" && m.sharing == CacheSharing::Shared)
+            .map(|m| (m.resource.as_str(), m.path.as_str()))
+    }
+
+    /// Tasks mounting each cache in shared mode, in order of first use.
+    fn cache_users(&self) -> Vec<(&str, Vec<&str>)> {
+        let mut users: Vec<(&str, Vec<&str>)> = Vec::new();
+        for t in &self.tasks {
+            for (cache, _) in Self::shared_cache_mounts(t) {
+                match users.iter_mut().find(|(c, _)| *c == cache) {
+                    Some((_, tasks)) if tasks.last() == Some(&t.name.as_str()) => {}
+                    Some((_, tasks)) => tasks.push(&t.name),
+                    None => users.push((cache, vec![&t.name])),
+                }
+            }
+        }
+        users
+    }
+
+    /// Every task's transitive dependencies.
+    fn ancestors(&self) -> HashMap<&str, HashSet<&str>> {
+        let deps: HashMap<&str, Vec<&str>> = self
+            .tasks
+            .iter()
+            .map(|t| {
+                let deps = t.depends_on.iter().map(String::as_str).collect();
+                (t.name.as_str(), deps)
+            })
+            .collect();
+        let mut ancestors = HashMap::new();
+        for t in &self.tasks {
+            let mut seen = HashSet::new();
+            let mut stack = deps[t.name.as_str()].clone();
+            while let Some(dep) = stack.pop() {
+                if seen.insert(dep) {
+                    stack.extend(deps.get(dep).into_iter().flatten());
+                }
+            }
+            ancestors.insert(t.name.as_str(), seen);
+        }
+        ancestors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::Severity as ValidationSeverity;
+    use crate::{OutputOptions, SecretRef, Template};
+
+    fn codes(p: &Pipeline) -> Vec<LintCode> {
+        p.lint().iter().map(|w| w.code).collect()
+    }
+
+    #[test]
+    fn test_cache_mounted_twice() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let registry = p.cache("cargo-registry");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount_cache(&target, "/src/target")
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .mount_cache(&target, "/build/target")
+            .run("cargo build");
+
+        let warnings: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::CacheMountedTwice)
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].task.as_deref(), Some("build"));
+        assert_eq!(
+            warnings[0].to_string(),
+            "warning[cache-mounted-twice] task \"build\": cache \"cargo-target\" is mounted at \
+             /src/target and /build/target; mount it once or use a separate cache"
+        );
+    }
+
+    #[test]
+    fn test_cache_mounted_once_per_task_is_fine() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount_cache(&target, "/src/target")
+            .run("cargo build");
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .mount_cache(&target, "/work/target")
+            .run("cargo test")
+            .after(&["build"]);
+
+        assert_eq!(codes(&p), [LintCode::CacheMountPoints]);
+    }
+
+    #[test]
+    fn test_cache_shared_by_concurrent_tasks() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let _ = p.task("fmt").run("cargo fmt --check");
+        for name in ["build", "test", "doc"] {
+            let _ = p
+                .task(name)
+                .mount_cache(&target, "/src/target")
+                .run("cargo")
+                .after(&["fmt"]);
+        }
+        let _ = p
+            .task("package")
+            .mount_cache(&target, "/src/target")
+            .run("cargo package")
+            .after(&["build", "test", "doc"]);
+
+        let warning = p
+            .lint()
+            .into_iter()
+            .find(|w| w.code == LintCode::CacheSharedConcurrently)
+            .unwrap();
+        assert_eq!(warning.severity, Severity::Warning);
+        assert!(warning
+            .message
+            .contains("(build/test, build/doc, test/doc)"));
+        assert!(warning.message.contains("Locked sharing mode"));
+    }
+
+    #[test]
+    fn test_cache_shared_by_ordered_tasks_is_fine() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let _ = p
+            .task("build")
+            .mount_cache(&target, "/src/target")
+            .run("cargo build");
+        let _ = p.task("check").run("./check.sh").after(&["build"]);
+        let _ = p
+            .task("package")
+            .mount_cache(&target, "/src/target")
+            .run("cargo package")
+            .after(&["check"]);
+
+        assert!(!codes(&p).contains(&LintCode::CacheSharedConcurrently));
+    }
+
+    #[test]
+    fn test_cache_mount_points_info() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let _ = p.cache("unused");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount_cache(&target, "/src/target")
+            .run("cargo build");
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .mount_cache(&target, "/work/target")
+            .run("cargo test")
+            .after(&["build"]);
+
+        p.allow_lint(LintCode::UnusedResource);
+        let lints = p.lint();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].severity, Severity::Info);
+        assert_eq!(
+            lints[0].message,
+            "cache \"cargo-target\" is mounted by build at /src/target, test at /work/target"
+        );
+    }
+
+    #[test]
+    fn test_cache_written_under_workdir_at_same_level() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let _ = p
+            .task("build")
+            .workdir("/src")
+            .mount_cache(&target, "/src/target")
+            .run("cargo build");
+        let _ = p
+            .task("test")
+            .mount_cache(&target, "/cache/target")
+            .run("cargo test");
+        let _ = p
+            .task("package")
+            .workdir("/src")
+            .mount_cache(&target, "/src/target")
+            .run("cargo package")
+            .after(&["build", "test"]);
+
+        let warnings: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::CacheWriteRace)
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert_eq!(
+            warnings[0].message,
+            "cache \"cargo-target\" is shared by build, test, which run at the same level, \
+             and build writes build outputs to it under the workdir; mount it with \
+             CacheSharing::Locked or CacheSharing::Private"
+        );
+    }
+
+    #[test]
+    fn test_cache_write_race_needs_shared_mounts_under_workdir() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let registry = p.cache("cargo-registry");
+        let _ = p
+            .task("build")
+            .workdir("/src/")
+            .mount_cache_with(&target, "/src/target", CacheSharing::Locked)
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .run("cargo build");
+        let _ = p
+            .task("test")
+            .workdir("/src")
+            .mount_cache_with(&target, "/src/target", CacheSharing::Locked)
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .run("cargo test");
+
+        assert!(!codes(&p).contains(&LintCode::CacheWriteRace));
+        // Locked mounts are no longer concurrent; only the registry is
+        let shared: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::CacheSharedConcurrently)
+            .collect();
+        assert_eq!(shared.len(), 1);
+        assert!(shared[0].message.contains("\"cargo-registry\""));
+    }
+
+    #[test]
+    fn test_allow_lint_suppresses_by_code() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let _ = p
+            .task("a")
+            .container("alpine")
+            .mount_cache(&target, "/t")
+            .mount_cache(&target, "/u")
+            .run("true");
+        let _ = p
+            .task("b")
+            .container("alpine")
+            .mount_cache(&target, "/t")
+            .run("true");
+
+        assert_eq!(
+            codes(&p),
+            [
+                LintCode::CacheMountedTwice,
+                LintCode::CacheSharedConcurrently,
+                LintCode::CacheMountPoints
+            ]
+        );
+        p.allow_lint(LintCode::CacheMountedTwice)
+            .allow_lint(LintCode::CacheSharedConcurrently)
+            .allow_lint(LintCode::CacheMountPoints);
+        assert!(p.lint().is_empty());
+    }
+
+    #[test]
+    fn test_explain_shows_warnings_and_verbose_info() {
+        let mut p = Pipeline::new();
+        let target = p.cache("cargo-target");
+        let _ = p.task("a").mount_cache(&target, "/t").run("true");
+        let _ = p.task("b").mount_cache(&target, "/t").run("true");
+
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("Warnings\n--------\n"));
+        assert!(out.contains("warning[cache-shared-concurrently]"));
+        assert!(!out.contains("cache-mount-points"));
+
+        let ctx = crate::ExplainContext {
+            verbose: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, Some(&ctx));
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains(
+            "info[cache-mount-points]: cache \"cargo-target\" is mounted by a at /t, b at /t"
+        ));
+    }
+
+    #[test]
+    fn test_unused_resources() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let _ = p.dir("docs");
+        let _ = p.cache("npm");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .run("cargo build");
+
+        let messages: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::UnusedResource)
+            .map(|w| w.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "directory \"src:docs\" is never mounted; mount it with mount() or remove it",
+                "cache \"npm\" is never mounted; mount it with mount_cache() or remove it",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_used_through_template() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let registry = p.cache("cargo-registry");
+        let npm = p.cache("npm");
+        let rust = Template::new()
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount_cache(&registry, "/usr/local/cargo/registry");
+        let _node = Template::new()
+            .container("node:20")
+            .mount(&src, "/src")
+            .mount_cache(&npm, "/root/.npm");
+        let _ = p.task("test").from(&rust).workdir("/src").run("cargo test");
+
+        let unused: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::UnusedResource)
+            .map(|w| w.message)
+            .collect();
+        // A template only counts once a task uses it.
+        assert_eq!(
+            unused,
+            ["cache \"npm\" is never mounted; mount it with mount_cache() or remove it"]
+        );
+    }
+
+    #[test]
+    fn test_strict_fails_emit_on_unused_resources() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let _ = p.dir("docs");
+        let _ = p.cache("cargo-registry");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .run("cargo build");
+        assert!(p.emit_to(&mut Vec::new()).is_ok());
+
+        p.strict(true);
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        let EmitError::Multiple(errors) = err else {
+            panic!("expected two errors, got: {}", err);
+        };
+        assert!(matches!(
+            &errors[..],
+            [
+                EmitError::UnusedResource { kind: "directory", id: dir },
+                EmitError::UnusedResource { kind: "cache", id: cache },
+            ] if dir == "src:docs" && cache == "cargo-registry"
+        ));
+        // Reported once, as errors.
+        let issues = p.validate();
+        assert_eq!(issues.len(), 2);
+        assert!(issues
+            .iter()
+            .all(|i| i.severity == ValidationSeverity::Error));
+
+        // explain still lists them.
+        let mut out = Vec::new();
+        p.explain_to(&mut out, None);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("warning[unused-resource]: cache \"cargo-registry\" is never mounted"));
+
+        p.allow_lint(LintCode::UnusedResource);
+        assert!(p.emit_to(&mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_overridden_system_env() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("make")
+            .env("PATH", "/opt/bin")
+            .env("HOME", "/tmp")
+            .env("CC", "clang");
+
+        let lints: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::OverriddenSystemEnv)
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(
+            lints,
+            [
+                "warning[overridden-system-env] task \"build\": sets PATH, replacing the one the image or runner provides; extend it in the command instead",
+                "warning[overridden-system-env] task \"build\": sets HOME, replacing the one the image or runner provides; extend it in the command instead",
+            ]
+        );
+        // A warning, not an emit error.
+        assert!(p.emit_to(&mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_input_from_allowed_failure() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("audit")
+            .run("cargo audit --json > audit.json")
+            .output("report", "audit.json")
+            .allow_failure(true);
+        let _ = p
+            .task("summary")
+            .run("./summarize.sh")
+            .input_from("audit", "report", "/in/audit.json")
+            .allow_failure(true);
+        let _ =
+            p.task("publish")
+                .run("./publish.sh")
+                .input_from("audit", "report", "/in/audit.json");
+
+        let lints: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::InputFromAllowedFailure)
+            .collect();
+        // Only the task that must pass is flagged.
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].task.as_deref(), Some("publish"));
+        assert_eq!(
+            lints[0].message,
+            "reads output \"report\" of \"audit\", which is allowed to fail, so the artifact \
+             may be missing; allow this task to fail too or drop the input"
+        );
+    }
+
+    #[test]
+    fn test_input_from_optional_output() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("coverage")
+            .run("make coverage")
+            .output_with(
+                "html",
+                "coverage/",
+                OutputOptions {
+                    required: false,
+                    ..OutputOptions::default()
+                },
+            )
+            .output("lcov", "lcov.info");
+        let _ = p
+            .task("upload")
+            .run("./upload.sh")
+            .input_from("coverage", "html", "/in/html/")
+            .input_from("coverage", "lcov", "/in/lcov.info");
+
+        let lints: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::InputFromOptionalOutput)
+            .collect();
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].task.as_deref(), Some("upload"));
+        assert_eq!(
+            lints[0].message,
+            "reads output \"html\" of \"coverage\", which isn't required, so the artifact \
+             may be missing; make it required or handle its absence"
+        );
+    }
+
+    #[test]
+    fn test_container_and_mount_mismatch() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let _ = p
+            .task("fmt")
+            .container("rust:1.75")
+            .run("cargo fmt --check");
+        let _ = p.task("host").mount(&src, "/src").run("make");
+        let _ = p
+            .task("ok")
+            .container("rust:1.75")
+            .mount_cwd()
+            .run("cargo test");
+
+        let warnings: Vec<_> = p
+            .lint()
+            .into_iter()
+            .map(|w| (w.code, w.task.unwrap_or_default()))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (LintCode::ContainerWithoutMounts, "fmt".to_string()),
+                (LintCode::MountsWithoutContainer, "host".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_container_options_without_container() {
+        let mut p = Pipeline::new();
+        let _ = p.task("host").entrypoint("").user("1000:1000").run("make");
+        let _ = p.task("local").user("ci").run("make");
+        let _ = p
+            .task("ok")
+            .container("rust:1.75")
+            .mount_cwd()
+            .entrypoint("")
+            .user("1000:1000")
+            .run("cargo test");
+
+        let lints: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::ContainerOptionsWithoutContainer)
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(
+            lints,
+            [
+                "warning[container-options-without-container] task \"host\": sets entrypoint() \
+                 and user() but has no container, and they only apply inside one; set \
+                 container() or drop them",
+                "warning[container-options-without-container] task \"local\": sets user() but \
+                 has no container, and they only apply inside one; set container() or drop them",
+            ]
+        );
+        assert!(p.validate().iter().any(|i| {
+            i.task.as_deref() == Some("local")
+                && i.field == "container-options-without-container"
+                && i.severity == ValidationSeverity::Warning
+        }));
+    }
+
+    #[test]
+    fn test_single_task_concurrency_group() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy-api")
+            .run("./deploy.sh api")
+            .concurrency_group("staging");
+        let _ = p
+            .task("deploy-web")
+            .run("./deploy.sh web")
+            .concurrency_group("staging");
+        let _ = p
+            .task("deploy-prod")
+            .run("./deploy.sh")
+            .concurrency_group("prdo");
+        let _ = p
+            .task("migrate")
+            .run("./migrate.sh")
+            .matrix("db", &["users", "orders"])
+            .concurrency_group("migrations");
+
+        let lints: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::SingleTaskConcurrencyGroup)
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(
+            lints,
+            [
+                "warning[single-task-concurrency-group] task \"deploy-prod\": is the only task in \
+              concurrency group \"prdo\", so the group only keeps runs of this task apart; \
+              check the group name for a typo"
+            ]
+        );
+        assert!(p.validate().iter().any(|i| {
+            i.task.as_deref() == Some("deploy-prod")
+                && i.field == "single-task-concurrency-group"
+                && i.severity == ValidationSeverity::Warning
+        }));
+    }
+
+    #[test]
+    fn test_relative_artifact_path_in_container() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .output("binary", "target/release/app")
+            .output("sbom", "/out/sbom.json");
+        let _ = p
+            .task("package")
+            .container("alpine")
+            .mount_cwd()
+            .input_from("build", "binary", "/in/app")
+            .input_from("build", "sbom", "/in/sbom.json")
+            .run("./package.sh");
+        let _ = p
+            .task("host-package")
+            .input_from("build", "binary", "/tmp/app")
+            .run("./package.sh");
+
+        let warnings: Vec<_> = p
+            .lint()
+            .into_iter()
+            .filter(|w| w.code == LintCode::RelativeArtifactPath)
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "warning[relative-artifact-path] task \"package\": reads output \"binary\" of \"build\" \
+             at relative path \"target/release/app\", which the container resolves against its \
+             workdir; give output() an absolute path"
+        );
+    }
+
+    #[test]
+    fn test_retry_and_timeout_warnings() {
+        let mut p = Pipeline::new();
+        let _ = p.task("flaky").run("./e2e.sh").retry(3);
+        let _ = p.task("soak").run("./soak.sh").timeout(7200);
+        let _ = p.task("ok").run("./e2e.sh").retry(3).timeout(600);
+
+        let warnings: Vec<_> = p.lint().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "warning[retry-without-timeout] task \"flaky\": retries 3 times without a \
+                 timeout, so a hung attempt is never retried; set timeout()",
+                "warning[long-timeout] task \"soak\": timeout of 2h is over one hour; split the \
+                 task or lower the timeout",
+            ]
+        );
+    }
+    #[test]
+    fn test_unconventional_secret_names() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret("GH_TOKEN")
+            .secret("db_pass")
+            .secret_from("api_key", SecretRef::from_env("API_KEY"));
+
+        let warnings: Vec<_> = p.lint().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "warning[unconventional-secret-name] task \"deploy\": secret \"db_pass\" doesn't \
+                 match [A-Z_][A-Z0-9_]*; rename it, or set Pipeline::secret_name_rule to pick the \
+                 rule emit enforces",
+                "warning[unconventional-secret-name] task \"deploy\": secret \"api_key\" doesn't \
+                 match [A-Z_][A-Z0-9_]*; rename it, or set Pipeline::secret_name_rule to pick the \
+                 rule emit enforces",
+            ]
+        );
+        assert!(p.to_json().is_ok());
+
+        // With a rule, emit enforces it and the lint steps aside.
+        p.secret_name_rule(SecretNameRule::Identifier);
+        assert!(p.lint().is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_and_relative_secrets() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret("TOKEN")
+            .secret("TOKEN")
+            .secret_from("TOKEN", SecretRef::from_env("TOKEN"))
+            .secret_from("CA", SecretRef::from_file("certs/ca.pem"))
+            .secret_from("CA", SecretRef::from_file("certs/ca.pem"))
+            .secret_from("KEY", SecretRef::from_file("./certs/key.pem"));
+
+        let warnings: Vec<_> = p.lint().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            [
+                "warning[duplicate-secret] task \"deploy\": declares secret \"TOKEN\" 3 times; \
+                 emit keeps one",
+                "warning[duplicate-secret] task \"deploy\": declares secret \"CA\" 2 times; \
+                 emit keeps one",
+                "warning[relative-secret-path] task \"deploy\": reads secret \"CA\" from \
+                 \"certs/ca.pem\"; write \"./certs/ca.pem\" if it's relative to the workdir, \
+                 or use an absolute path",
+            ]
+        );
+
+        let json = p.to_json_value().unwrap();
+        assert_eq!(json["tasks"][0]["secrets"], serde_json::json!(["TOKEN"]));
+        assert_eq!(
+            json["tasks"][0]["secret_refs"],
+            serde_json::json!([
+                {"name": "CA", "source": "file", "key": "certs/ca.pem"},
+                {"name": "KEY", "source": "file", "key": "./certs/key.pem"},
+            ])
+        );
+    }
+}
@@ -0,0 +1,1602 @@
+//! Loading pipeline JSON back into a [`Pipeline`].
+//!
+//! Pipelines emitted by any sykli SDK can be read, extended and emitted
+//! again:
+//!
+//! ```rust
+//! use sykli::Pipeline;
+//!
+//! let json = r#"{"version":"1","tasks":[{"name":"test","command":"cargo test"}]}"#;
+//! let mut p = Pipeline::from_json(json).unwrap();
+//! p.task("build").run("cargo build").after(&["test"]);
+//!
+//! let out = p.to_json_value().unwrap();
+//! assert_eq!(out["tasks"][1]["depends_on"][0], "test");
+//! ```
+//!
+//! Loading is lossless for JSON this SDK emits: `from_json(to_json(p))`
+//! emits the same bytes again. Fields the loader doesn't know are dropped,
+//! and reported by [`Pipeline::from_json_with_warnings`].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{
+    matrix, AiHooks, Backoff, CacheScope, CacheSharing, CacheVolume, Concurrency, ContainerBuild,
+    Criticality, Directory, Eviction, FileSource, GateConfig, GitSource, HttpFile, K8sOptions,
+    Mount, NodeKind, OnFailAction, OutputKind, OutputOptions, Pipeline, PublishDest, ReadyProbe,
+    RetryPolicy, RunWhen, SecretRef, SecretSource, SecretTemplate, SelectMode, Semantic, Service,
+    SuccessCriterion, TaskData, TaskInput, TaskType, Template,
+};
+
+/// Error returned by [`Pipeline::from_json`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum PipelineParseError {
+    /// Not JSON, or a field has the wrong type.
+    Json(String),
+    /// The document's `version` isn't one this SDK reads.
+    UnsupportedVersion(String),
+    /// A field has a value the SDK can't represent, e.g. an unknown secret
+    /// source.
+    Invalid {
+        /// Where, e.g. `tasks[2].secret_refs[0].source`.
+        path: String,
+        /// What's wrong with it.
+        message: String,
+    },
+}
+
+impl fmt::Display for PipelineParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineParseError::Json(msg) => write!(f, "invalid pipeline JSON: {}", msg),
+            PipelineParseError::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "unsupported pipeline version {:?} (expected 1, 2 or 3)",
+                    v
+                )
+            }
+            PipelineParseError::Invalid { path, message } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for PipelineParseError {}
+
+impl From<serde_json::Error> for PipelineParseError {
+    fn from(e: serde_json::Error) -> Self {
+        PipelineParseError::Json(e.to_string())
+    }
+}
+
+type Extra = BTreeMap<String, Value>;
+
+#[derive(Deserialize)]
+struct RawPipeline {
+    version: String,
+    #[serde(default)]
+    resources: BTreeMap<String, RawResource>,
+    #[serde(default)]
+    templates: BTreeMap<String, RawTemplate>,
+    max_parallel: Option<usize>,
+    #[serde(default)]
+    tasks: Vec<RawTask>,
+    #[serde(flatten)]
+    extra: Extra,
+}
+
+#[derive(Deserialize)]
+struct RawResource {
+    #[serde(rename = "type")]
+    type_: String,
+    path: Option<String>,
+    name: Option<String>,
+    #[serde(default)]
+    globs: Vec<String>,
+    key: Option<String>,
+    #[serde(default)]
+    key_files: Vec<String>,
+    scope: Option<String>,
+    fallback: Option<String>,
+    max_size: Option<String>,
+    eviction: Option<String>,
+    url: Option<String>,
+    #[serde(rename = "ref")]
+    ref_: Option<String>,
+    depth: Option<u32>,
+    sha256: Option<String>,
+    #[serde(default)]
+    unpack: bool,
+    #[serde(flatten)]
+    extra: Extra,
+}
+
+#[derive(Deserialize)]
+struct RawTemplate {
+    container: Option<String>,
+    workdir: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    mounts: Vec<RawMount>,
+    #[serde(default)]
+    inputs: Vec<String>,
+    #[serde(default)]
+    secrets: Vec<String>,
+    retry: Option<RawRetry>,
+    timeout: Option<u32>,
+    k8s: Option<RawK8s>,
+    #[serde(flatten)]
+    extra: Extra,
+}
+
+#[derive(Deserialize)]
+struct RawMount {
+    resource: String,
+    path: String,
+    #[serde(rename = "type")]
+    type_: String,
+    sharing: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawK8s {
+    memory: Option<String>,
+    cpu: Option<String>,
+    gpu: Option<u32>,
+    raw: Option<String>,
+    #[serde(flatten)]
+    extra: Extra,
+}
+
+#[derive(Deserialize)]
+struct RawTaskInput {
+    from_task: String,
+    output: String,
+    dest: String,
+}
+
+#[derive(Deserialize)]
+struct RawConcurrency {
+    group: String,
+    cancel_in_progress: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RawDependency {
+    task: String,
+    kind: String,
+    run_when: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawOutput {
+    Path(String),
+    Rich {
+        path: String,
+        #[serde(rename = "type")]
+        type_: Option<String>,
+        retention_days: Option<u32>,
+        compress: Option<bool>,
+        required: Option<bool>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawRetry {
+    Count(u32),
+    Policy {
+        max: u32,
+        backoff: Option<RawBackoff>,
+        retry_on_exit_codes: Option<Vec<i32>>,
+        retry_on_timeout: Option<bool>,
+    },
+}
+
+#[derive(Deserialize)]
+struct RawBackoff {
+    #[serde(rename = "type")]
+    type_: String,
+    secs: Option<u32>,
+    base_secs: Option<u32>,
+    max_secs: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawSuccessCriterion {
+    #[serde(rename = "type")]
+    type_: String,
+    equals: Option<i32>,
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawSecretRef {
+    #[serde(default)]
+    name: String,
+    source: String,
+    key: String,
+    mount_path: Option<String>,
+    optional: Option<bool>,
+    mask: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct RawPublish {
+    output: String,
+    #[serde(rename = "type")]
+    type_: String,
+    repo: Option<String>,
+    tag: Option<String>,
+    url: Option<String>,
+    token: Option<RawSecretRef>,
+    auth: Option<RawSecretRef>,
+}
+
+#[derive(Deserialize)]
+struct RawContainerBuild {
+    context_resource: String,
+    dockerfile: String,
+}
+
+#[derive(Deserialize)]
+struct RawSecretTemplate {
+    dest: String,
+    template: String,
+    #[serde(default)]
+    refs: Vec<RawSecretRef>,
+}
+
+#[derive(Deserialize)]
+struct RawProvide {
+    name: String,
+    value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawSemantic {
+    #[serde(default)]
+    covers: Vec<String>,
+    intent: Option<String>,
+    criticality: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawAiHooks {
+    on_fail: Option<String>,
+    select: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawGate {
+    strategy: String,
+    timeout: Option<u32>,
+    message: Option<String>,
+    env_var: Option<String>,
+    file_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawService {
+    image: String,
+    name: String,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    ports: Vec<u16>,
+    command: Option<String>,
+    readiness: Option<RawReadiness>,
+}
+
+#[derive(Deserialize)]
+struct RawReadiness {
+    #[serde(rename = "type")]
+    type_: String,
+    command: Option<String>,
+    port: Option<u16>,
+    path: Option<String>,
+    timeout: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawTask {
+    name: String,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    kind: Option<String>,
+    template: Option<String>,
+    task_type: Option<String>,
+    #[serde(default)]
+    success_criteria: Vec<RawSuccessCriterion>,
+    command: Option<String>,
+    steps: Option<Vec<String>>,
+    args: Option<Vec<String>>,
+    primitive: Option<String>,
+    agent: Option<String>,
+    #[serde(default)]
+    context: Vec<String>,
+    deterministic: Option<bool>,
+    container: Option<String>,
+    container_build: Option<RawContainerBuild>,
+    workdir: Option<String>,
+    entrypoint: Option<String>,
+    user: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    env_files: Vec<String>,
+    #[serde(default)]
+    mounts: Vec<RawMount>,
+    #[serde(default)]
+    inputs: Vec<String>,
+    #[serde(default)]
+    task_inputs: Vec<RawTaskInput>,
+    #[serde(default)]
+    outputs: HashMap<String, RawOutput>,
+    #[serde(default)]
+    depends_on: Vec<String>,
+    depends_on_meta: Option<Vec<RawDependency>>,
+    when: Option<String>,
+    #[serde(default)]
+    secrets: Vec<String>,
+    #[serde(default)]
+    secret_refs: Vec<RawSecretRef>,
+    #[serde(default)]
+    secret_templates: Vec<RawSecretTemplate>,
+    #[serde(default)]
+    publish: Vec<RawPublish>,
+    matrix: Option<matrix::Matrix>,
+    #[serde(default)]
+    matrix_exclude: Vec<BTreeMap<String, String>>,
+    #[serde(default)]
+    matrix_include: Vec<BTreeMap<String, String>>,
+    #[serde(default)]
+    services: Vec<RawService>,
+    retry: Option<RawRetry>,
+    timeout: Option<u32>,
+    estimated_duration: Option<u32>,
+    k8s: Option<RawK8s>,
+    #[serde(default)]
+    requires: Vec<String>,
+    #[serde(default)]
+    provides: Vec<RawProvide>,
+    #[serde(default)]
+    needs: Vec<String>,
+    semantic: Option<RawSemantic>,
+    ai_hooks: Option<RawAiHooks>,
+    gate: Option<RawGate>,
+    manual: Option<bool>,
+    allow_failure: Option<bool>,
+    concurrency: Option<RawConcurrency>,
+    verify: Option<String>,
+    // Deprecated: read so old documents load, but never emitted
+    target: Option<String>,
+    #[serde(flatten)]
+    extra: Extra,
+}
+
+impl Pipeline {
+    /// Loads pipeline JSON emitted by any sykli SDK (versions 1 to 3).
+    ///
+    /// Unknown fields are dropped with a `tracing` warning; use
+    /// [`Pipeline::from_json_with_warnings`] to inspect them.
+    ///
+    /// # Errors
+    /// Returns [`PipelineParseError`] for malformed JSON, an unsupported version, or
+    /// a value the SDK can't represent.
+    pub fn from_json(json: &str) -> Result<Pipeline, PipelineParseError> {
+        let (pipeline, warnings) = Pipeline::from_json_with_warnings(json)?;
+        for w in &warnings {
+            warn!("{}", w);
+        }
+        Ok(pipeline)
+    }
+
+    /// Like [`Pipeline::from_json`], reading from `reader`.
+    ///
+    /// # Errors
+    /// As [`Pipeline::from_json`]; read errors are [`PipelineParseError::Json`].
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Pipeline, PipelineParseError> {
+        let mut json = String::new();
+        reader
+            .read_to_string(&mut json)
+            .map_err(|e| PipelineParseError::Json(e.to_string()))?;
+        Pipeline::from_json(&json)
+    }
+
+    /// Like [`Pipeline::from_json`], also returning one warning per dropped
+    /// field, e.g. `tasks[0] ("test"): unknown field "owner" dropped`.
+    ///
+    /// # Errors
+    /// As [`Pipeline::from_json`].
+    pub fn from_json_with_warnings(
+        json: &str,
+    ) -> Result<(Pipeline, Vec<String>), PipelineParseError> {
+        let raw: RawPipeline = serde_json::from_str(json)?;
+        if !matches!(raw.version.as_str(), "1" | "2" | "3") {
+            return Err(PipelineParseError::UnsupportedVersion(raw.version));
+        }
+
+        let mut warnings = Vec::new();
+        // Derived from the tasks on emit
+        let extra = raw.extra.into_iter().filter(|(k, _)| k != "requires");
+        unknown_fields(&mut warnings, "pipeline", extra);
+
+        let mut p = Pipeline::new();
+        p.max_parallel = raw.max_parallel;
+        for (id, r) in raw.resources {
+            let path = format!("resources[{:?}]", id);
+            // Derived on emit: file resources are always keyed by sha256
+            let file = r.type_ == "file";
+            let extra = r.extra.into_iter().filter(|(k, _)| !(file && k == "hash"));
+            unknown_fields(&mut warnings, &path, extra);
+            match r.type_.as_str() {
+                "directory" => {
+                    let mut dir = Directory {
+                        path: r
+                            .path
+                            .ok_or_else(|| invalid(&path, "directory without a path"))?,
+                        globs: r.globs,
+                        name: None,
+                        hash_globs: true,
+                    };
+                    // `src:<path>#<hash>` from hash_dir_globs, which later
+                    // dir() calls keep using
+                    if dir.id() == id && !dir.globs.is_empty() {
+                        p.hash_dir_globs = true;
+                    } else {
+                        dir.hash_globs = false;
+                    }
+                    // `src:<path>#<name>` from dir_named
+                    if let Some(name) = id
+                        .strip_prefix(&format!("src:{}#", dir.path))
+                        .filter(|name| !name.is_empty() && dir.id() != id)
+                    {
+                        dir.name = Some(name.to_string());
+                    }
+                    if dir.id() != id {
+                        warnings.push(format!(
+                            "{}: re-emitted as {:?}; mounts referencing {:?} will dangle",
+                            path,
+                            dir.id(),
+                            id
+                        ));
+                    }
+                    p.dirs.push(dir);
+                }
+                "cache" => {
+                    let scope = |field: &str, value: Option<String>| {
+                        value
+                            .map(|v| {
+                                CacheScope::parse(&v).ok_or_else(|| {
+                                    invalid(
+                                        &format!("{}.{}", path, field),
+                                        &format!(
+                                            "unknown cache scope {:?} (expected global, branch or default)",
+                                            v
+                                        ),
+                                    )
+                                })
+                            })
+                            .transpose()
+                    };
+                    p.caches.push(CacheVolume {
+                        name: r.name.unwrap_or(id),
+                        key: r.key,
+                        key_files: r.key_files,
+                        scope: scope("scope", r.scope)?,
+                        fallback: scope("fallback", r.fallback)?,
+                        max_size: r.max_size,
+                        eviction: r
+                            .eviction
+                            .map(|v| {
+                                Eviction::parse(&v).ok_or_else(|| {
+                                    invalid(
+                                        &format!("{}.eviction", path),
+                                        &format!(
+                                            "unknown eviction policy {:?} (expected lru, lfu or fifo)",
+                                            v
+                                        ),
+                                    )
+                                })
+                            })
+                            .transpose()?,
+                    });
+                }
+                "git" => {
+                    let url = r.url.ok_or_else(|| invalid(&path, "git without a url"))?;
+                    // The JSON doesn't say which kind of ref; a full SHA is a
+                    // commit, anything else clones like a branch.
+                    let is_sha =
+                        |r: &String| r.len() == 40 && r.bytes().all(|b| b.is_ascii_hexdigit());
+                    let (commit, branch) = match r.ref_ {
+                        Some(r) if is_sha(&r) => (Some(r), None),
+                        r => (None, r),
+                    };
+                    let git = GitSource {
+                        url,
+                        branch,
+                        tag: None,
+                        commit,
+                        depth: r.depth,
+                    };
+                    if git.id() != id {
+                        warnings.push(format!(
+                            "{}: re-emitted as {:?}; mounts referencing {:?} will dangle",
+                            path,
+                            git.id(),
+                            id
+                        ));
+                    }
+                    p.git_sources.push(git);
+                }
+                "http" => p.http_files.push(HttpFile {
+                    url: r.url.ok_or_else(|| invalid(&path, "http without a url"))?,
+                    sha256: r.sha256,
+                    unpack: r.unpack,
+                }),
+                "file" => {
+                    if !r.globs.is_empty() {
+                        return Err(invalid(
+                            &format!("{}.globs", path),
+                            "globs are not allowed on a file resource (use a directory)",
+                        ));
+                    }
+                    let mut file = FileSource {
+                        path: r
+                            .path
+                            .ok_or_else(|| invalid(&path, "file without a path"))?,
+                        name: None,
+                    };
+                    // `file:<path>#<name>` from file_named
+                    if let Some(name) = id
+                        .strip_prefix(&format!("file:{}#", file.path))
+                        .filter(|name| !name.is_empty())
+                    {
+                        file.name = Some(name.to_string());
+                    }
+                    if file.id() != id {
+                        warnings.push(format!(
+                            "{}: re-emitted as {:?}; mounts referencing {:?} will dangle",
+                            path,
+                            file.id(),
+                            id
+                        ));
+                    }
+                    p.files.push(file);
+                }
+                other => {
+                    return Err(invalid(
+                        &format!("{}.type", path),
+                        &format!("unknown resource type {:?}", other),
+                    ))
+                }
+            }
+        }
+
+        for (name, t) in raw.templates {
+            let path = format!("templates[{:?}]", name);
+            unknown_fields(&mut warnings, &path, t.extra);
+            let (k8s, raw_k8s) = k8s_options(&mut warnings, &path, t.k8s);
+            if raw_k8s.is_some() {
+                warnings.push(format!(
+                    "{}.k8s.raw: not supported on templates, dropped",
+                    path
+                ));
+            }
+            p.templates.push(Template {
+                name: Some(name),
+                container: t.container,
+                workdir: t.workdir,
+                env: t.env,
+                mounts: mounts(&path, t.mounts)?,
+                inputs: t.inputs,
+                secrets: t.secrets,
+                retry: retry_policy(&path, t.retry)?,
+                timeout: t.timeout,
+                k8s,
+            });
+        }
+
+        for (i, t) in raw.tasks.into_iter().enumerate() {
+            let path = format!("tasks[{}] ({:?})", i, t.name);
+            let task = task_data(&mut warnings, &path, t)?;
+            p.tasks.push(task);
+        }
+        link_resources(&mut p);
+        link_variants(&mut p);
+        Ok((p, warnings))
+    }
+}
+
+const TASK_TYPES: [TaskType; 12] = [
+    TaskType::Build,
+    TaskType::Test,
+    TaskType::Lint,
+    TaskType::Format,
+    TaskType::Scan,
+    TaskType::Package,
+    TaskType::Publish,
+    TaskType::Deploy,
+    TaskType::Migrate,
+    TaskType::Generate,
+    TaskType::Verify,
+    TaskType::Cleanup,
+];
+
+fn invalid(path: &str, message: &str) -> PipelineParseError {
+    PipelineParseError::Invalid {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn unknown_fields(
+    warnings: &mut Vec<String>,
+    path: &str,
+    extra: impl IntoIterator<Item = (String, Value)>,
+) {
+    for (key, _) in extra {
+        warnings.push(format!("{}: unknown field {:?} dropped", path, key));
+    }
+}
+
+/// A bare count of 0, as older SDKs emitted for `retry(0)`, means no retry.
+fn retry_policy(
+    path: &str,
+    r: Option<RawRetry>,
+) -> Result<Option<RetryPolicy>, PipelineParseError> {
+    let (max, backoff, retry_on_exit_codes, retry_on_timeout) = match r {
+        None | Some(RawRetry::Count(0)) => return Ok(None),
+        Some(RawRetry::Count(max)) => return Ok(Some(RetryPolicy::new(max))),
+        Some(RawRetry::Policy {
+            max,
+            backoff,
+            retry_on_exit_codes,
+            retry_on_timeout,
+        }) => (max, backoff, retry_on_exit_codes, retry_on_timeout),
+    };
+    let backoff = match backoff {
+        None => Backoff::Immediate,
+        Some(b) => match (b.type_.as_str(), b.secs, b.base_secs, b.max_secs) {
+            ("fixed", Some(secs), _, _) => Backoff::Fixed { secs },
+            ("exponential", _, Some(base_secs), Some(max_secs)) => Backoff::Exponential {
+                base_secs,
+                max_secs,
+            },
+            (other, ..) => {
+                return Err(invalid(
+                    &format!("{}.retry.backoff", path),
+                    &format!("unsupported or incomplete backoff type {:?}", other),
+                ))
+            }
+        },
+    };
+    Ok(Some(RetryPolicy {
+        max,
+        backoff,
+        retry_on_exit_codes,
+        retry_on_timeout: retry_on_timeout.unwrap_or(true),
+    }))
+}
+
+fn mounts(path: &str, raw: Vec<RawMount>) -> Result<Vec<Mount>, PipelineParseError> {
+    raw.into_iter()
+        .enumerate()
+        .map(|(i, m)| {
+            let sharing = match m.sharing {
+                None => CacheSharing::Shared,
+                Some(v) => CacheSharing::parse(&v).ok_or_else(|| {
+                    invalid(
+                        &format!("{}.mounts[{}].sharing", path, i),
+                        &format!(
+                            "unknown cache sharing mode {:?} (expected shared, locked or private)",
+                            v
+                        ),
+                    )
+                })?,
+            };
+            Ok(Mount {
+                resource: m.resource,
+                path: m.path,
+                mount_type: m.type_,
+                dir: None,
+                cache: None,
+                file: None,
+                sharing,
+            })
+        })
+        .collect()
+}
+
+/// Points directory and cache mounts and build contexts at the loaded
+/// resources they reference.
+fn link_resources(p: &mut Pipeline) {
+    let find = |dirs: &[Directory], id: &str| dirs.iter().find(|d| d.id() == id).cloned();
+    let mounts = p
+        .tasks
+        .iter_mut()
+        .flat_map(|t| &mut t.mounts)
+        .chain(p.templates.iter_mut().flat_map(|t| &mut t.mounts));
+    for m in mounts {
+        match m.mount_type.as_str() {
+            "directory" => m.dir = find(&p.dirs, &m.resource),
+            "cache" => m.cache = p.caches.iter().find(|c| c.name == m.resource).cloned(),
+            "file" => m.file = p.files.iter().find(|f| f.id() == m.resource).cloned(),
+            _ => {}
+        }
+    }
+    for cb in p
+        .tasks
+        .iter_mut()
+        .filter_map(|t| t.container_build.as_mut())
+    {
+        cb.context = find(&p.dirs, &cb.context_resource);
+    }
+}
+
+/// Turns dependencies and inputs naming a matrix variant, e.g. `build-linux`,
+/// back into the matrix task with a selector for that variant.
+fn link_variants(p: &mut Pipeline) {
+    let names: HashSet<&str> = p.tasks.iter().map(|t| t.name.as_str()).collect();
+    let mut variants: HashMap<String, (String, BTreeMap<String, String>)> = HashMap::new();
+    for t in &p.tasks {
+        for v in t.matrix.variants() {
+            let name = v.task_name(&t.name);
+            if !names.contains(name.as_str()) {
+                let selector = v.values().iter().cloned().collect();
+                variants.insert(name, (t.name.clone(), selector));
+            }
+        }
+    }
+    if variants.is_empty() {
+        return;
+    }
+    for t in &mut p.tasks {
+        let mut depends_on: Vec<String> = Vec::new();
+        let mut plain: Vec<String> = Vec::new();
+        for dep in std::mem::take(&mut t.depends_on) {
+            let task = match variants.get(&dep) {
+                Some((task, selector)) => {
+                    let selectors = t.dep_selectors.entry(task.clone()).or_default();
+                    if !selectors.contains(selector) {
+                        selectors.push(selector.clone());
+                    }
+                    if let Some(when) = t.run_when.remove(&dep) {
+                        t.run_when.insert(task.clone(), when);
+                    }
+                    task.clone()
+                }
+                None => {
+                    plain.push(dep.clone());
+                    dep
+                }
+            };
+            if !depends_on.contains(&task) {
+                depends_on.push(task);
+            }
+        }
+        // A plain entry already waits for every variant.
+        for task in &plain {
+            t.dep_selectors.remove(task);
+        }
+        t.depends_on = depends_on;
+        let mut order_deps: Vec<String> = Vec::new();
+        for dep in std::mem::take(&mut t.order_deps) {
+            let task = variants.get(&dep).map_or(dep, |(task, _)| task.clone());
+            if !order_deps.contains(&task) {
+                order_deps.push(task);
+            }
+        }
+        t.order_deps = order_deps;
+        for ti in &mut t.task_inputs {
+            if let Some((task, selector)) = variants.get(&ti.from_task) {
+                ti.from_task = task.clone();
+                ti.matrix = selector.clone();
+            }
+        }
+    }
+}
+
+/// Splits JSON k8s options into structured options and raw JSON.
+fn k8s_options(
+    warnings: &mut Vec<String>,
+    path: &str,
+    k8s: Option<RawK8s>,
+) -> (Option<K8sOptions>, Option<String>) {
+    let Some(k8s) = k8s else {
+        return (None, None);
+    };
+    unknown_fields(warnings, &format!("{}.k8s", path), k8s.extra);
+    let opts = K8sOptions {
+        memory: k8s.memory,
+        cpu: k8s.cpu,
+        gpu: k8s.gpu,
+    };
+    ((!opts.is_empty()).then_some(opts), k8s.raw)
+}
+
+fn secret_ref(path: &str, r: RawSecretRef) -> Result<SecretRef, PipelineParseError> {
+    let source = match r.source.as_str() {
+        "env" => SecretSource::Env,
+        "file" => SecretSource::File,
+        "vault" => SecretSource::Vault,
+        "k8s" => SecretSource::K8s,
+        "aws" => SecretSource::Aws,
+        "gcp" => SecretSource::Gcp,
+        "sops" => SecretSource::Sops,
+        other => {
+            return Err(invalid(
+                &format!("{}.source", path),
+                &format!(
+                    "unknown secret source {:?} (expected env, file, vault, k8s, aws, gcp or sops)",
+                    other
+                ),
+            ))
+        }
+    };
+    if let Err(format) = source.check_key(&r.key) {
+        return Err(invalid(
+            &format!("{}.key", path),
+            &format!("expected {}, got {:?}", format, r.key),
+        ));
+    }
+    Ok(SecretRef {
+        name: r.name,
+        source,
+        key: r.key,
+        mount_path: r.mount_path,
+        optional: r.optional.unwrap_or(false),
+        mask: r.mask.unwrap_or(true),
+    })
+}
+
+fn task_data(
+    warnings: &mut Vec<String>,
+    path: &str,
+    t: RawTask,
+) -> Result<TaskData, PipelineParseError> {
+    unknown_fields(warnings, path, t.extra);
+    if t.target.is_some() {
+        warnings.push(format!(
+            "{}: deprecated field \"target\" is not re-emitted",
+            path
+        ));
+    }
+
+    let kind = match t.kind.as_deref() {
+        None | Some("task") => NodeKind::Task,
+        Some("review") => NodeKind::Review,
+        Some(other) => {
+            return Err(invalid(
+                &format!("{}.kind", path),
+                &format!("unknown kind {:?}", other),
+            ))
+        }
+    };
+    let task_type = t
+        .task_type
+        .map(|tt| {
+            TASK_TYPES
+                .iter()
+                .find(|known| known.as_str() == tt)
+                .cloned()
+                .ok_or_else(|| {
+                    invalid(
+                        &format!("{}.task_type", path),
+                        &format!("unknown task type {:?}", tt),
+                    )
+                })
+        })
+        .transpose()?;
+    let success_criteria = t
+        .success_criteria
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let at = format!("{}.success_criteria[{}]", path, i);
+            match (c.type_.as_str(), c.equals, c.path) {
+                ("exit_code", Some(code), _) => Ok(SuccessCriterion::ExitCode(code)),
+                ("file_exists", _, Some(p)) => Ok(SuccessCriterion::FileExists(p)),
+                ("file_non_empty", _, Some(p)) => Ok(SuccessCriterion::FileNonEmpty(p)),
+                (other, _, _) => Err(invalid(&at, &format!("unsupported criterion {:?}", other))),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut depends_on: Vec<String> = Vec::new();
+    for task in t.depends_on {
+        if !depends_on.contains(&task) {
+            depends_on.push(task);
+        }
+    }
+
+    // Ordering deps are the ones not implied by an artifact input, unless
+    // the document says otherwise.
+    let mut run_when = HashMap::new();
+    for (i, d) in t.depends_on_meta.iter().flatten().enumerate() {
+        let when = match d.run_when.as_deref() {
+            None => continue,
+            Some("success") => RunWhen::Success,
+            Some("failure") => RunWhen::Failure,
+            Some("always") => RunWhen::Always,
+            Some(other) => {
+                return Err(invalid(
+                    &format!("{}.depends_on_meta[{}].run_when", path, i),
+                    &format!("unknown run_when {:?}", other),
+                ))
+            }
+        };
+        run_when.insert(d.task.clone(), when);
+    }
+    let order_deps = match t.depends_on_meta {
+        Some(meta) => meta
+            .into_iter()
+            .filter(|d| d.kind != "artifact")
+            .map(|d| d.task)
+            .collect(),
+        None => depends_on
+            .iter()
+            .filter(|d| !t.task_inputs.iter().any(|ti| &ti.from_task == *d))
+            .cloned()
+            .collect(),
+    };
+
+    let secret_refs = t
+        .secret_refs
+        .into_iter()
+        .enumerate()
+        .map(|(i, r)| secret_ref(&format!("{}.secret_refs[{}]", path, i), r))
+        .collect::<Result<_, _>>()?;
+    let secret_templates = t
+        .secret_templates
+        .into_iter()
+        .enumerate()
+        .map(|(i, st)| {
+            let refs = st
+                .refs
+                .into_iter()
+                .enumerate()
+                .map(|(j, r)| {
+                    secret_ref(&format!("{}.secret_templates[{}].refs[{}]", path, i, j), r)
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(SecretTemplate {
+                dest: st.dest,
+                template: st.template,
+                refs,
+            })
+        })
+        .collect::<Result<_, PipelineParseError>>()?;
+    let publish = t
+        .publish
+        .into_iter()
+        .enumerate()
+        .map(|(i, pb)| {
+            let at = format!("{}.publish[{}]", path, i);
+            let dest = match (pb.type_.as_str(), pb.repo, pb.tag, pb.token, pb.url) {
+                ("github_release", Some(repo), Some(tag_expr), Some(token), _) => {
+                    PublishDest::GithubRelease {
+                        repo,
+                        tag_expr,
+                        token: secret_ref(&format!("{}.token", at), token)?,
+                    }
+                }
+                ("http_put", _, _, _, Some(url_template)) => PublishDest::HttpPut {
+                    url_template,
+                    auth: pb
+                        .auth
+                        .map(|a| secret_ref(&format!("{}.auth", at), a))
+                        .transpose()?,
+                },
+                (other, ..) => {
+                    return Err(invalid(
+                        &at,
+                        &format!("unsupported or incomplete publish type {:?}", other),
+                    ))
+                }
+            };
+            Ok((pb.output, dest))
+        })
+        .collect::<Result<_, PipelineParseError>>()?;
+
+    let mut matrix = t.matrix.unwrap_or_default();
+    for (list, entries) in [
+        ("matrix_exclude", t.matrix_exclude),
+        ("matrix_include", t.matrix_include),
+    ] {
+        for (i, entry) in entries.into_iter().enumerate() {
+            if entry.is_empty() {
+                return Err(invalid(
+                    &format!("{}.{}[{}]", path, list, i),
+                    "entry cannot be empty",
+                ));
+            }
+            if list == "matrix_exclude" {
+                matrix.exclude(entry);
+            } else {
+                matrix.include(entry);
+            }
+        }
+    }
+
+    let services = t
+        .services
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let (ready_probes, ready_timeout) = match s.readiness {
+                None => (Vec::new(), None),
+                Some(r) => {
+                    let probe = match (r.type_.as_str(), r.command, r.port, r.path) {
+                        ("cmd", Some(command), _, _) => ReadyProbe::Cmd(command),
+                        ("tcp", _, Some(port), _) => ReadyProbe::Tcp(port),
+                        ("http", _, Some(port), Some(path)) => ReadyProbe::Http { path, port },
+                        (other, ..) => {
+                            return Err(invalid(
+                                &format!("{}.services[{}].readiness", path, i),
+                                &format!("unsupported or incomplete readiness type {:?}", other),
+                            ))
+                        }
+                    };
+                    (vec![probe], r.timeout)
+                }
+            };
+            Ok(Service {
+                image: s.image,
+                name: s.name,
+                env: s.env,
+                ports: s.ports,
+                command: s.command,
+                ready_probes,
+                ready_timeout,
+            })
+        })
+        .collect::<Result<_, PipelineParseError>>()?;
+
+    let semantic = match t.semantic {
+        None => Semantic::default(),
+        Some(s) => Semantic {
+            covers: s.covers,
+            intent: s.intent,
+            criticality: s
+                .criticality
+                .map(|c| match c.as_str() {
+                    "high" => Ok(Criticality::High),
+                    "medium" => Ok(Criticality::Medium),
+                    "low" => Ok(Criticality::Low),
+                    other => Err(invalid(
+                        &format!("{}.semantic.criticality", path),
+                        &format!("unknown criticality {:?}", other),
+                    )),
+                })
+                .transpose()?,
+        },
+    };
+    let ai_hooks = match t.ai_hooks {
+        None => AiHooks::default(),
+        Some(h) => AiHooks {
+            on_fail: h
+                .on_fail
+                .map(|a| match a.as_str() {
+                    "analyze" => Ok(OnFailAction::Analyze),
+                    "retry" => Ok(OnFailAction::Retry),
+                    "skip" => Ok(OnFailAction::Skip),
+                    other => Err(invalid(
+                        &format!("{}.ai_hooks.on_fail", path),
+                        &format!("unknown action {:?}", other),
+                    )),
+                })
+                .transpose()?,
+            select: h
+                .select
+                .map(|s| match s.as_str() {
+                    "smart" => Ok(SelectMode::Smart),
+                    "always" => Ok(SelectMode::Always),
+                    "manual" => Ok(SelectMode::Manual),
+                    other => Err(invalid(
+                        &format!("{}.ai_hooks.select", path),
+                        &format!("unknown select mode {:?}", other),
+                    )),
+                })
+                .transpose()?,
+        },
+    };
+    let (k8s_options, k8s_raw) = k8s_options(warnings, path, t.k8s);
+    let mut outputs = HashMap::new();
+    let mut output_kinds = HashMap::new();
+    let mut output_options = HashMap::new();
+    for (name, o) in t.outputs {
+        let file = match o {
+            RawOutput::Path(file) => file,
+            RawOutput::Rich {
+                path: file,
+                type_,
+                retention_days,
+                compress,
+                required,
+            } => {
+                let kind = match type_.as_deref().unwrap_or("file") {
+                    "file" => None,
+                    "glob" => Some(OutputKind::Glob),
+                    "directory" => Some(OutputKind::Dir),
+                    other => {
+                        return Err(invalid(
+                            &format!("{}.outputs.{}.type", path, name),
+                            &format!("unknown output type {:?}", other),
+                        ))
+                    }
+                };
+                if let Some(kind) = kind {
+                    output_kinds.insert(name.clone(), kind);
+                }
+                let options = OutputOptions {
+                    retention_days,
+                    compress: compress.unwrap_or(false),
+                    required: required.unwrap_or(true),
+                };
+                if options != OutputOptions::default() {
+                    output_options.insert(name.clone(), options);
+                }
+                file
+            }
+        };
+        outputs.insert(name, file);
+    }
+
+    Ok(TaskData {
+        kind,
+        name: t.name,
+        task_type,
+        success_criteria,
+        // With steps or args, `command` is only their joined form for older
+        // runners
+        command: match (&t.steps, &t.args) {
+            (None, None) => t.command.unwrap_or_default(),
+            _ => String::new(),
+        },
+        steps: t.steps.unwrap_or_default(),
+        args: t.args.unwrap_or_default(),
+        primitive: t.primitive,
+        agent: t.agent,
+        context: t.context,
+        deterministic: t.deterministic.unwrap_or(false),
+        container: t.container,
+        container_build: t.container_build.map(|cb| ContainerBuild {
+            context_resource: cb.context_resource,
+            context: None,
+            dockerfile: cb.dockerfile,
+        }),
+        workdir: t.workdir,
+        entrypoint: t.entrypoint,
+        user: t.user,
+        env: t.env,
+        runtime_env_files: t.env_files,
+        mounts: mounts(path, t.mounts)?,
+        inputs: t.inputs,
+        task_inputs: t
+            .task_inputs
+            .into_iter()
+            .map(|ti| TaskInput {
+                from_task: ti.from_task,
+                output: ti.output,
+                dest_path: ti.dest,
+                matrix: BTreeMap::new(),
+            })
+            .collect(),
+        outputs,
+        output_kinds,
+        output_options,
+        depends_on,
+        dep_selectors: HashMap::new(),
+        order_deps,
+        run_when,
+        condition: t.when,
+        secrets: t.secrets,
+        secret_refs,
+        secret_templates,
+        publish,
+        matrix,
+        services,
+        retry: retry_policy(path, t.retry)?,
+        timeout: t.timeout,
+        estimated_duration: t.estimated_duration,
+        k8s_options,
+        k8s_raw,
+        target_name: t.target,
+        requires: t.requires,
+        semantic,
+        ai_hooks,
+        provides: t.provides.into_iter().map(|p| (p.name, p.value)).collect(),
+        needs: t.needs,
+        gate: t.gate.map(|g| GateConfig {
+            strategy: g.strategy,
+            timeout: g.timeout.unwrap_or(0),
+            message: g.message,
+            env_var: g.env_var,
+            file_path: g.file_path,
+        }),
+        manual: t.manual.unwrap_or(false),
+        allow_failure: t.allow_failure.unwrap_or(false),
+        concurrency: t.concurrency.map(|c| Concurrency {
+            group: c.group,
+            cancel_in_progress: c.cancel_in_progress.unwrap_or(false),
+        }),
+        verify: t.verify,
+        tags: t.tags,
+        description: t.description,
+        template: t.template,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EdgeKind, K8sOptions, TaskType};
+
+    fn parse_err(json: &str) -> PipelineParseError {
+        Pipeline::from_json(json)
+            .err()
+            .expect("expected a parse error")
+    }
+
+    fn round_trip(p: &Pipeline) {
+        let json = p.to_json().unwrap();
+        let loaded = Pipeline::from_json(&json).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), json);
+    }
+
+    #[test]
+    fn test_round_trip_conformance_cases() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/conformance/cases");
+        let mut cases: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|x| x == "json"))
+            .collect();
+        cases.sort();
+        assert!(!cases.is_empty(), "no cases in {}", dir);
+        for case in cases {
+            let json = std::fs::read_to_string(&case).unwrap();
+            let p =
+                Pipeline::from_json(&json).unwrap_or_else(|e| panic!("{}: {}", case.display(), e));
+            let out = p
+                .to_json_value()
+                .unwrap_or_else(|e| panic!("{}: {}", case.display(), e));
+            let expected: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(out, expected, "{}", case.display());
+        }
+    }
+
+    #[test]
+    fn test_round_trip_v2() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".").glob(&["**/*.rs"]);
+        let registry = p.cache_with_key("cargo-registry", "Cargo.lock");
+        let _ = p
+            .template("rust")
+            .container("rust:1.80")
+            .mount(&src, "/src")
+            .workdir("/src")
+            .env("CARGO_TERM_COLOR", "always")
+            .timeout(600)
+            .k8s(K8sOptions {
+                memory: Some("2Gi".to_string()),
+                ..Default::default()
+            });
+        let rust = p.get_template("rust").unwrap().clone();
+        let _ = p
+            .task("build")
+            .from(&rust)
+            .run("cargo build --release")
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .output("binary", "target/release/app")
+            .output_glob("debs", "target/debian/*.deb")
+            .output_dir("docs", "target/doc/")
+            .output_with(
+                "log",
+                "build.log",
+                OutputOptions {
+                    retention_days: Some(3),
+                    compress: true,
+                    required: false,
+                },
+            )
+            .retry(2)
+            .k8s_raw(r#"{"nodeSelector":{"pool":"fast"}}"#);
+        let _ = p
+            .task("test")
+            .from(&rust)
+            .run("cargo test")
+            .matrix("rust", &["1.80", "stable"])
+            .matrix("os", &["linux", "macos"])
+            .matrix_exclude(&[("rust", "1.80"), ("os", "macos")])
+            .matrix_include(&[("rust", "nightly"), ("os", "linux")])
+            .service("postgres:16", "db")
+            .output("report", "report.xml")
+            .after(&["build"])
+            .covers(&["src/**"])
+            .on_fail(OnFailAction::Analyze);
+        let _ = p
+            .task("deploy")
+            .container("alpine:3")
+            .run("./deploy.sh")
+            .input_from("build", "binary", "/in/app")
+            .input_from_matrix(
+                "test",
+                &[("rust", "stable"), ("os", "linux")],
+                "report",
+                "/in/report.xml",
+            )
+            .after(&["test"])
+            .when("branch == 'main'")
+            .secret("KUBECONFIG")
+            .env_file_lazy("/etc/deploy.env")
+            .secret_from("TOKEN", SecretRef::from_vault("secret/data/ci#token"))
+            .secret_from(
+                "KUBECONFIG_DATA",
+                SecretRef::from_k8s("ci/deployer#kubeconfig"),
+            )
+            .secret_opt("TRACING_TOKEN")
+            .secret_from(
+                "DEPLOY_KEY",
+                SecretRef::from_sops("ci/secrets.enc.yaml#deploy.key"),
+            )
+            .secret_from(
+                "ACCOUNT_ID",
+                SecretRef::from_gcp("projects/ci/secrets/account").no_mask(),
+            )
+            .secret_file(
+                "TLS_KEY",
+                SecretRef::from_file("/etc/ci/tls.key"),
+                "/run/secrets/tls.key",
+            )
+            .output("manifest", "out/manifest.yaml")
+            .publish_output(
+                "manifest",
+                PublishDest::GithubRelease {
+                    repo: "acme/app".to_string(),
+                    tag_expr: "{tag}".to_string(),
+                    token: SecretRef::from_env("GITHUB_TOKEN"),
+                },
+            )
+            .provides("app", Some("v1"))
+            .estimated_duration(90);
+        let _ = p
+            .task("bench")
+            .container("rust:1.80")
+            .run("cargo bench")
+            .after_matrix("test", &[("os", "linux")])
+            .after_matrix("test", &[("rust", "nightly")]);
+        let _ = p
+            .gate("approve")
+            .gate_message("Ship it?")
+            .after(&["deploy"]);
+
+        round_trip(&p);
+    }
+
+    #[test]
+    fn test_round_trip_v3_and_review() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("lint")
+            .run("cargo clippy")
+            .task_type(TaskType::Lint)
+            .success_criteria(&[
+                SuccessCriterion::ExitCode(0),
+                SuccessCriterion::FileExists("clippy.txt".to_string()),
+            ]);
+        let _ = p
+            .review("review-diff")
+            .primitive("diff")
+            .agent("reviewer")
+            .context(&["src/"])
+            .deterministic(true)
+            .after(&["lint"]);
+
+        round_trip(&p);
+    }
+
+    #[test]
+    fn test_load_v1_and_extend() {
+        let json = r#"{
+            "version": "1",
+            "tasks": [
+                {"name": "test", "command": "go test ./...", "inputs": ["**/*.go"],
+                 "secrets": ["NPM_TOKEN"], "retry": 3, "timeout": 300},
+                {"name": "build", "command": "go build", "depends_on": ["test"],
+                 "when": "branch == 'main'", "target": "local"}
+            ]
+        }"#;
+        let (mut p, warnings) = Pipeline::from_json_with_warnings(json).unwrap();
+        assert_eq!(
+            warnings,
+            [r#"tasks[1] ("build"): deprecated field "target" is not re-emitted"#]
+        );
+
+        let _ = p.task("package").run("./package.sh").after(&["build"]);
+        let out = p.to_json_value().unwrap();
+        assert_eq!(out["version"], "1");
+        assert_eq!(out["tasks"][0]["retry"], 3);
+        assert_eq!(out["tasks"][1]["when"], "branch == 'main'");
+        assert!(out["tasks"][1].get("target").is_none());
+        assert_eq!(out["tasks"][2]["depends_on"][0], "build");
+    }
+
+    #[test]
+    fn test_artifact_deps_without_meta() {
+        // Older documents have depends_on but no depends_on_meta.
+        let json = r#"{"version": "2", "tasks": [
+            {"name": "build", "container": "rust", "command": "cargo build",
+             "outputs": {"bin": "target/app"}},
+            {"name": "lint", "container": "rust", "command": "cargo clippy"},
+            {"name": "package", "container": "alpine", "command": "./pkg.sh",
+             "depends_on": ["build", "lint"],
+             "task_inputs": [{"from_task": "build", "output": "bin", "dest": "/in/app"}]}
+        ]}"#;
+        let p = Pipeline::from_json(json).unwrap();
+        assert_eq!(
+            p.dependency_kinds("package"),
+            [
+                ("build".to_string(), EdgeKind::Artifact),
+                ("lint".to_string(), EdgeKind::Order),
+            ]
+        );
+        let out = p.to_json_value().unwrap();
+        assert!(out["tasks"][2].get("depends_on_meta").is_none());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_reported() {
+        let json = r#"{"version": "1", "owner": "ci-team",
+            "tasks": [{"name": "test", "command": "make test", "labels": {"team": "core"}}]}"#;
+        let (_, warnings) = Pipeline::from_json_with_warnings(json).unwrap();
+        assert_eq!(
+            warnings,
+            [
+                r#"pipeline: unknown field "owner" dropped"#,
+                r#"tasks[0] ("test"): unknown field "labels" dropped"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse_err(r#"{"version": "9", "tasks": []}"#),
+            PipelineParseError::UnsupportedVersion("9".to_string())
+        );
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [{"name": "deploy",
+                "secret_refs": [{"name": "T", "source": "ssm", "key": "t"}]}]}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"tasks[0] ("deploy").secret_refs[0].source: unknown secret source "ssm" (expected env, file, vault, k8s, aws, gcp or sops)"#
+        );
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [{"name": "deploy",
+                "secret_refs": [{"name": "T", "source": "gcp", "key": "deployer"}]}]}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"tasks[0] ("deploy").secret_refs[0].key: expected 'projects/PROJECT/secrets/NAME' format, optionally with '/versions/VERSION', got "deployer""#
+        );
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [{"name": "test", "command": "true",
+                "matrix": {"os": ["linux"]}, "matrix_exclude": [{}]}]}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"tasks[0] ("test").matrix_exclude[0]: entry cannot be empty"#
+        );
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [],
+                "resources": {"npm": {"type": "cache", "name": "npm", "scope": "Branch"}}}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"resources["npm"].scope: unknown cache scope "Branch" (expected global, branch or default)"#
+        );
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [],
+                "resources": {"file:ci": {"type": "file", "path": "ci", "globs": ["*.toml"]}}}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"resources["file:ci"].globs: globs are not allowed on a file resource (use a directory)"#
+        );
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [{"name": "build", "command": "true",
+                "mounts": [{"resource": "target", "path": "/t", "type": "cache",
+                            "sharing": "exclusive"}]}]}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"tasks[0] ("build").mounts[0].sharing: unknown cache sharing mode "exclusive" (expected shared, locked or private)"#
+        );
+        assert!(matches!(
+            parse_err("{not json"),
+            PipelineParseError::Json(_)
+        ));
+    }
+
+    #[test]
+    fn test_output_forms() {
+        let p = Pipeline::from_json(
+            r#"{"version": "2", "tasks": [{"name": "a", "command": "true", "outputs": {
+                "bin": "target/app",
+                "log": {"path": "build.log", "type": "file"},
+                "site": {"path": "public/", "type": "directory"},
+                "cov": {"path": "cov/", "retention_days": 7}}}]}"#,
+        )
+        .unwrap();
+        let a = p.iter_tasks().next().unwrap();
+        assert_eq!(a.outputs()["log"], "build.log");
+        assert_eq!(a.output_type("bin"), Some("file"));
+        assert_eq!(a.output_type("log"), Some("file"));
+        assert_eq!(a.output_type("site"), Some("directory"));
+        assert_eq!(a.output_type("cov"), Some("file"));
+        assert_eq!(
+            a.output_options("cov"),
+            Some(OutputOptions {
+                retention_days: Some(7),
+                ..OutputOptions::default()
+            })
+        );
+
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [{"name": "a", "command": "true",
+                "outputs": {"x": {"path": "x", "type": "socket"}}}]}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"tasks[0] ("a").outputs.x.type: unknown output type "socket""#
+        );
+    }
+
+    #[test]
+    fn test_retry_forms() {
+        let p = Pipeline::from_json(
+            r#"{"version": "2", "tasks": [
+                {"name": "a", "command": "true", "retry": 0},
+                {"name": "b", "command": "true", "retry": 2},
+                {"name": "c", "command": "true",
+                 "retry": {"max": 3, "backoff": {"type": "fixed", "secs": 5}}}]}"#,
+        )
+        .unwrap();
+        let retries: Vec<_> = p.iter_tasks().map(|t| t.retry_policy().cloned()).collect();
+        assert_eq!(
+            retries,
+            [
+                None,
+                Some(RetryPolicy::new(2)),
+                Some(RetryPolicy {
+                    max: 3,
+                    backoff: Backoff::Fixed { secs: 5 },
+                    ..RetryPolicy::default()
+                }),
+            ]
+        );
+
+        let err = parse_err(
+            r#"{"version": "2", "tasks": [{"name": "a", "command": "true",
+                "retry": {"max": 3, "backoff": {"type": "linear"}}}]}"#,
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"tasks[0] ("a").retry.backoff: unsupported or incomplete backoff type "linear""#
+        );
+    }
+}
@@ -0,0 +1,507 @@
+//! Matrix dimensions and how they expand into variants.
+//!
+//! Runners that expand a [`Task::matrix`](crate::Task::matrix) must follow
+//! this contract so every runner produces the same variants:
+//!
+//! - Dimensions iterate in the order they were declared, values in the order
+//!   they were listed. Re-declaring a key replaces its values in place.
+//! - Variants are the cartesian product in nested-loop order: the first
+//!   dimension is the outermost loop, the last varies fastest.
+//! - Each variant sees every dimension as an env var under its raw key and
+//!   under `SYKLI_MATRIX_<KEY>` (see [`env_alias`]).
+//! - A variant's task name is the base name followed by each value, joined
+//!   with `-` (see [`Variant::task_name`]).
+//! - [`Task::matrix_exclude`](crate::Task::matrix_exclude) entries then drop
+//!   every variant whose values match all of the entry's pairs.
+//! - [`Task::matrix_include`](crate::Task::matrix_include) entries are
+//!   appended last, in declaration order, unless an identical variant is
+//!   already there. An include sets every dimension, and its values needn't
+//!   be in the dimension's list.
+//! - `{{matrix.<key>}}` in a task's command, steps, exec args, container
+//!   image, workdir or env values is emitted verbatim; runners replace it
+//!   with the variant's value (see [`Variant::interpolate`]). Emit fails if
+//!   `<key>` isn't one of the task's dimensions.
+//!
+//! ```rust
+//! use sykli::Pipeline;
+//!
+//! let mut p = Pipeline::new();
+//! p.task("test")
+//!     .run("cargo test")
+//!     .matrix("rust", &["1.75", "1.80"])
+//!     .matrix("os", &["linux", "macos"]);
+//!
+//! let task = p.iter_tasks().next().unwrap();
+//! let names: Vec<_> = task
+//!     .matrix()
+//!     .variants()
+//!     .iter()
+//!     .map(|v| v.task_name("test"))
+//!     .collect();
+//! assert_eq!(
+//!     names,
+//!     ["test-1.75-linux", "test-1.75-macos", "test-1.80-linux", "test-1.80-macos"]
+//! );
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+
+/// Matrix dimensions of a task, in declaration order, with its exclude and
+/// include combinations.
+///
+/// Serializes as a JSON object whose keys keep the dimension order; the
+/// combinations are emitted separately as `matrix_exclude` and
+/// `matrix_include`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Matrix {
+    dims: Vec<(String, Vec<String>)>,
+    exclude: Vec<BTreeMap<String, String>>,
+    include: Vec<BTreeMap<String, String>>,
+}
+
+impl Matrix {
+    /// Sets the values for `key`, keeping its position if already declared.
+    pub(crate) fn insert(&mut self, key: String, values: Vec<String>) {
+        match self.dims.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = values,
+            None => self.dims.push((key, values)),
+        }
+    }
+
+    /// Adds a combination to drop from the expansion.
+    pub(crate) fn exclude(&mut self, entry: BTreeMap<String, String>) {
+        self.exclude.push(entry);
+    }
+
+    /// Adds a combination to append to the expansion.
+    pub(crate) fn include(&mut self, entry: BTreeMap<String, String>) {
+        self.include.push(entry);
+    }
+
+    /// Exclude combinations, in declaration order.
+    pub fn excludes(&self) -> &[BTreeMap<String, String>] {
+        &self.exclude
+    }
+
+    /// Include combinations, in declaration order.
+    pub fn includes(&self) -> &[BTreeMap<String, String>] {
+        &self.include
+    }
+
+    /// Returns the values of a dimension.
+    pub fn get(&self, key: &str) -> Option<&[String]> {
+        self.dims
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Iterates over `(key, values)` in declaration order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.dims.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    /// Dimension keys in declaration order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.dims.iter().map(|(k, _)| k.as_str())
+    }
+
+    /// Number of dimensions.
+    pub fn len(&self) -> usize {
+        self.dims.len()
+    }
+
+    /// Returns true if no dimensions are declared.
+    pub fn is_empty(&self) -> bool {
+        self.dims.is_empty()
+    }
+
+    /// Expands the dimensions into variants, in the order described in the
+    /// [module docs](self). Returns no variants if there are no dimensions.
+    ///
+    /// Includes that don't set every dimension are skipped; validation
+    /// rejects them.
+    pub fn variants(&self) -> Vec<Variant> {
+        let mut variants: Vec<Variant> = self
+            .product()
+            .into_iter()
+            .filter(|v| !self.exclude.iter().any(|e| v.matches(e)))
+            .collect();
+        for entry in &self.include {
+            let values: Option<Vec<_>> = self
+                .keys()
+                .map(|k| entry.get(k).map(|v| (k.to_string(), v.clone())))
+                .collect();
+            if let Some(values) = values {
+                let variant = Variant { values };
+                if !variants.contains(&variant) {
+                    variants.push(variant);
+                }
+            }
+        }
+        variants
+    }
+
+    /// The cartesian product of the dimensions.
+    fn product(&self) -> Vec<Variant> {
+        if self.dims.is_empty() {
+            return Vec::new();
+        }
+        let mut variants = vec![Variant { values: Vec::new() }];
+        for (key, values) in &self.dims {
+            variants = variants
+                .iter()
+                .flat_map(|v| {
+                    values.iter().map(move |value| {
+                        let mut next = v.clone();
+                        next.values.push((key.clone(), value.clone()));
+                        next
+                    })
+                })
+                .collect();
+        }
+        variants
+    }
+}
+
+impl std::ops::Index<&str> for Matrix {
+    type Output = [String];
+
+    fn index(&self, key: &str) -> &[String] {
+        self.get(key)
+            .unwrap_or_else(|| panic!("no matrix dimension {:?}", key))
+    }
+}
+
+impl Serialize for Matrix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.dims.len()))?;
+        for (key, values) in &self.dims {
+            map.serialize_entry(key, values)?;
+        }
+        map.end()
+    }
+}
+
+/// Reads the JSON object back, keeping its key order.
+impl<'de> Deserialize<'de> for Matrix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MatrixVisitor;
+
+        impl<'de> Visitor<'de> for MatrixVisitor {
+            type Value = Matrix;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map of matrix keys to value lists")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Matrix, A::Error> {
+                let mut matrix = Matrix::default();
+                while let Some((key, values)) = map.next_entry::<String, Vec<String>>()? {
+                    matrix.insert(key, values);
+                }
+                Ok(matrix)
+            }
+        }
+
+        deserializer.deserialize_map(MatrixVisitor)
+    }
+}
+
+/// One combination of matrix values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variant {
+    values: Vec<(String, String)>,
+}
+
+impl Variant {
+    /// `(key, value)` pairs in dimension order.
+    pub fn values(&self) -> &[(String, String)] {
+        &self.values
+    }
+
+    /// The value of dimension `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Replaces each `{{matrix.<key>}}` in `s` with this variant's value.
+    /// Placeholders for other keys are left as they are.
+    ///
+    /// ```rust
+    /// use sykli::Pipeline;
+    ///
+    /// let mut p = Pipeline::new();
+    /// p.task("test").run("cargo test").matrix("rust", &["1.80"]);
+    ///
+    /// let variant = &p.iter_tasks().next().unwrap().matrix().variants()[0];
+    /// assert_eq!(variant.interpolate("rust:{{matrix.rust}}"), "rust:1.80");
+    /// ```
+    pub fn interpolate(&self, s: &str) -> String {
+        replace_placeholders(s, |key| self.get(key).map(str::to_string))
+    }
+
+    /// Returns true if the variant has every `(key, value)` pair of `entry`.
+    pub fn matches(&self, entry: &BTreeMap<String, String>) -> bool {
+        entry.iter().all(|(k, v)| {
+            self.values
+                .iter()
+                .any(|(key, value)| key == k && value == v)
+        })
+    }
+
+    /// Env vars for this variant: each raw key followed by its
+    /// `SYKLI_MATRIX_<KEY>` alias, in dimension order.
+    pub fn env(&self) -> Vec<(String, String)> {
+        self.values
+            .iter()
+            .flat_map(|(k, v)| [(k.clone(), v.clone()), (env_alias(k), v.clone())])
+            .collect()
+    }
+
+    /// Name of the task running this variant: `base` followed by each value,
+    /// joined with `-`.
+    ///
+    /// Characters other than ASCII letters, digits, `.`, `_` and `-` in a
+    /// value become `_`, so `"ubuntu 24.04"` contributes `ubuntu_24.04`.
+    pub fn task_name(&self, base: &str) -> String {
+        let mut name = base.to_string();
+        for (_, value) in &self.values {
+            name.push('-');
+            name.extend(value.chars().map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                    c
+                } else {
+                    '_'
+                }
+            }));
+        }
+        name
+    }
+}
+
+/// Keys of the `{{matrix.<key>}}` placeholders in `s`, in order.
+pub(crate) fn placeholders(s: &str) -> Vec<&str> {
+    let mut keys = Vec::new();
+    let mut rest = s;
+    while let Some((key, after)) = next_placeholder(rest) {
+        keys.push(key);
+        rest = after;
+    }
+    keys
+}
+
+/// Replaces each `{{matrix.<key>}}` in `s` with `value(key)`, leaving it as
+/// it is where that's `None`.
+pub(crate) fn replace_placeholders(s: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find(PLACEHOLDER) {
+        let Some((key, after)) = next_placeholder(&rest[start..]) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        match value(key) {
+            Some(v) => out.push_str(&v),
+            None => out.push_str(&rest[start..rest.len() - after.len()]),
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+const PLACEHOLDER: &str = "{{matrix.";
+
+/// The key of the first placeholder in `s`, and what follows it.
+fn next_placeholder(s: &str) -> Option<(&str, &str)> {
+    let start = s.find(PLACEHOLDER)? + PLACEHOLDER.len();
+    let len = s[start..].find("}}")?;
+    Some((&s[start..start + len], &s[start + len + 2..]))
+}
+
+/// The `SYKLI_MATRIX_<KEY>` alias for a matrix key: the key upper-cased, with
+/// anything other than ASCII letters and digits replaced by `_`.
+///
+/// ```rust
+/// assert_eq!(sykli::matrix::env_alias("rust-version"), "SYKLI_MATRIX_RUST_VERSION");
+/// ```
+pub fn env_alias(key: &str) -> String {
+    let key: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("SYKLI_MATRIX_{}", key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pipeline;
+
+    fn build() -> Pipeline {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust_version", &["1.80", "1.70", "1.75"])
+            .matrix("os", &["ubuntu", "macos"])
+            .matrix("arch", &["x86_64", "aarch64"]);
+        p
+    }
+
+    fn matrix(p: &Pipeline) -> Matrix {
+        p.iter_tasks().next().unwrap().matrix().clone()
+    }
+
+    #[test]
+    fn test_variants_nested_loop_order() {
+        let p = build();
+        let names: Vec<_> = matrix(&p)
+            .variants()
+            .iter()
+            .map(|v| v.task_name("test"))
+            .collect();
+        assert_eq!(names.len(), 12);
+        assert_eq!(
+            &names[..4],
+            [
+                "test-1.80-ubuntu-x86_64",
+                "test-1.80-ubuntu-aarch64",
+                "test-1.80-macos-x86_64",
+                "test-1.80-macos-aarch64",
+            ]
+        );
+        assert_eq!(names[4], "test-1.70-ubuntu-x86_64");
+        assert_eq!(names[11], "test-1.75-macos-aarch64");
+    }
+
+    #[test]
+    fn test_expansion_is_stable_across_runs() {
+        let expand = || {
+            let m = matrix(&build());
+            m.variants()
+                .iter()
+                .map(|v| (v.task_name("test"), v.env()))
+                .collect::<Vec<_>>()
+        };
+        let first = expand();
+        for _ in 0..20 {
+            assert_eq!(expand(), first);
+        }
+    }
+
+    #[test]
+    fn test_variant_env_has_raw_key_and_alias() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("go test ./...")
+            .matrix("go-version", &["1.22"])
+            .matrix("os", &["linux"]);
+        let variants = matrix(&p).variants();
+
+        assert_eq!(
+            variants[0].env(),
+            [
+                ("go-version".to_string(), "1.22".to_string()),
+                ("SYKLI_MATRIX_GO_VERSION".to_string(), "1.22".to_string()),
+                ("os".to_string(), "linux".to_string()),
+                ("SYKLI_MATRIX_OS".to_string(), "linux".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redeclared_key_keeps_position() {
+        let mut m = Matrix::default();
+        m.insert("a".to_string(), vec!["1".to_string()]);
+        m.insert("b".to_string(), vec!["2".to_string()]);
+        m.insert("a".to_string(), vec!["3".to_string()]);
+
+        assert_eq!(m.keys().collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(m["a"], ["3"]);
+    }
+
+    #[test]
+    fn test_exclude_and_include_counts() {
+        let mut p = build();
+        let _ = p
+            .task_or_get("test")
+            .matrix_exclude(&[("os", "macos"), ("arch", "aarch64")])
+            .matrix_exclude(&[("rust_version", "1.70")]);
+        // 12 - 3 macOS/aarch64 - 4 for 1.70 + the 1.70/macOS/aarch64 counted twice
+        assert_eq!(matrix(&p).variants().len(), 6);
+
+        let _ = p
+            .task_or_get("test")
+            .matrix_include(&[
+                ("rust_version", "nightly"),
+                ("os", "ubuntu"),
+                ("arch", "x86_64"),
+            ])
+            // Already in the product, so it adds nothing.
+            .matrix_include(&[
+                ("rust_version", "1.80"),
+                ("os", "ubuntu"),
+                ("arch", "x86_64"),
+            ])
+            // Excluded above, but includes come last.
+            .matrix_include(&[
+                ("arch", "aarch64"),
+                ("os", "macos"),
+                ("rust_version", "1.80"),
+            ]);
+        let names: Vec<_> = matrix(&p)
+            .variants()
+            .iter()
+            .map(|v| v.task_name("test"))
+            .collect();
+        assert_eq!(names.len(), 8);
+        assert_eq!(
+            &names[6..],
+            ["test-nightly-ubuntu-x86_64", "test-1.80-macos-aarch64"]
+        );
+    }
+
+    #[test]
+    fn test_incomplete_include_is_skipped() {
+        let mut m = Matrix::default();
+        m.insert("os".to_string(), vec!["linux".to_string()]);
+        m.insert("rust".to_string(), vec!["1.80".to_string()]);
+        m.include([("os".to_string(), "windows".to_string())].into());
+        assert_eq!(m.variants().len(), 1);
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let s = "rust:{{matrix.rust}}-{{matrix.os}} {{matrix.rust}} {{env.X}} {{matrix.open";
+        assert_eq!(placeholders(s), ["rust", "os", "rust"]);
+        assert_eq!(
+            replace_placeholders(s, |k| (k == "rust").then(|| "1.80".to_string())),
+            "rust:1.80-{{matrix.os}} 1.80 {{env.X}} {{matrix.open"
+        );
+    }
+
+    #[test]
+    fn test_task_name_sanitizes_values() {
+        let mut m = Matrix::default();
+        m.insert("image".to_string(), vec!["ubuntu 24.04/arm".to_string()]);
+        assert_eq!(m.variants()[0].task_name("e2e"), "e2e-ubuntu_24.04_arm");
+        assert!(Matrix::default().variants().is_empty());
+    }
+}
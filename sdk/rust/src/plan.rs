@@ -0,0 +1,287 @@
+//! Machine-readable execution plan, the structured form of
+//! [`Pipeline::explain`].
+//!
+//! Text and JSON explain share [`SkipDecision`], so they never disagree on
+//! whether a task runs:
+//!
+//! ```rust
+//! use sykli::plan::SkipDecision;
+//! use sykli::{ExplainContext, Pipeline};
+//!
+//! let mut p = Pipeline::new();
+//! p.task("test").run("cargo test");
+//! p.task("deploy")
+//!     .run("./deploy.sh")
+//!     .after(&["test"])
+//!     .when("branch == 'main'");
+//!
+//! let ctx = ExplainContext::default().with_branch("dev");
+//! let plan = p.execution_plan(Some(&ctx));
+//! assert_eq!(plan.tasks[1].name, "deploy");
+//! assert_eq!(
+//!     plan.tasks[1].decision,
+//!     SkipDecision::Skip {
+//!         reason: "branch is 'dev', not 'main'".to_string()
+//!     }
+//! );
+//! ```
+
+use serde::Serialize;
+
+use crate::condition::{self, Decision};
+use crate::{ExplainContext, K8sOptions, Pipeline, TaskData};
+
+/// Whether a task would run in the explain context.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum SkipDecision {
+    /// No condition, or the condition holds.
+    Run,
+    /// The condition doesn't hold.
+    Skip {
+        /// Why, e.g. `branch is 'dev', not 'main'`.
+        reason: String,
+    },
+    /// The condition can't be decided from the context.
+    Unknown {
+        /// Why, e.g. `env.FOO not set in context`.
+        reason: String,
+    },
+    /// The condition doesn't parse, so it wasn't evaluated.
+    Invalid {
+        /// The parse error.
+        error: String,
+    },
+}
+
+/// The plan for a whole pipeline, in execution order.
+#[derive(Clone, Debug, Serialize)]
+pub struct ExecutionPlan {
+    /// Tasks in the order explain lists them.
+    pub tasks: Vec<PlannedTask>,
+}
+
+/// One task in an [`ExecutionPlan`].
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedTask {
+    /// Position in the plan, from 1, as numbered by text explain.
+    pub order: usize,
+    /// Task name.
+    pub name: String,
+    /// Shell command (empty for gates and reviews), with steps joined by
+    /// `&&`.
+    pub command: String,
+    /// Target override, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Names of the tasks this one waits for.
+    pub depends_on: Vec<String>,
+    /// Condition as emitted in `when`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// Whether the task would run in the context.
+    #[serde(flatten)]
+    pub decision: SkipDecision,
+    /// Waits for approval instead of starting automatically.
+    pub manual: bool,
+    /// Can fail without failing the pipeline.
+    pub allow_failure: bool,
+    /// Number of matrix variants, if the task has a matrix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matrix_variants: Option<usize>,
+    /// Secrets the task reads.
+    pub secrets: Vec<PlannedSecret>,
+    /// K8s options after merging pipeline defaults and templates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k8s: Option<K8sOptions>,
+}
+
+/// A secret in a [`PlannedTask`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PlannedSecret {
+    /// Env var name inside the task.
+    pub name: String,
+    /// `env`, `file` or `vault`; None for secrets declared with `secret()`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Where the source reads it from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl Pipeline {
+    /// Builds the execution plan that [`Pipeline::explain`] prints.
+    pub fn execution_plan(&self, ctx: Option<&ExplainContext>) -> ExecutionPlan {
+        let default_ctx = ExplainContext::default();
+        let ctx = ctx.unwrap_or(&default_ctx);
+        let tasks = self
+            .topological_sort()
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| self.planned_task(i + 1, t, ctx))
+            .collect();
+        ExecutionPlan { tasks }
+    }
+
+    /// [`Pipeline::execution_plan`] as JSON, for tools that render the plan.
+    pub fn explain_json(&self, ctx: Option<&ExplainContext>) -> serde_json::Value {
+        serde_json::to_value(self.execution_plan(ctx)).expect("execution plan serializes")
+    }
+
+    fn planned_task(&self, order: usize, t: &TaskData, ctx: &ExplainContext) -> PlannedTask {
+        let secrets = if t.secret_refs.is_empty() {
+            t.secrets
+                .iter()
+                .map(|name| PlannedSecret {
+                    name: name.clone(),
+                    source: None,
+                    key: None,
+                })
+                .collect()
+        } else {
+            t.secret_refs
+                .iter()
+                .map(|sr| PlannedSecret {
+                    name: sr.name.clone(),
+                    source: Some(sr.source.as_str().to_string()),
+                    key: Some(sr.key.clone()),
+                })
+                .collect()
+        };
+        let variants = t.matrix.variants().len();
+        PlannedTask {
+            order,
+            name: t.name.clone(),
+            command: t.script(),
+            target: t.target_name.clone(),
+            depends_on: t.depends_on.clone(),
+            condition: t.effective_condition(),
+            decision: skip_decision(t, ctx),
+            manual: t.manual,
+            allow_failure: t.allow_failure,
+            matrix_variants: (variants > 0).then_some(variants),
+            secrets,
+            k8s: self.merged_k8s(t).filter(|k| !k.is_empty()),
+        }
+    }
+}
+
+/// Decides whether `t` would run in `ctx`; shared by text and JSON explain.
+pub(crate) fn skip_decision(t: &TaskData, ctx: &ExplainContext) -> SkipDecision {
+    let Some(cond) = t.effective_condition() else {
+        return SkipDecision::Run;
+    };
+    match condition::parse(&cond).map(|expr| expr.evaluate(ctx)) {
+        Ok(Decision::Run) => SkipDecision::Run,
+        Ok(Decision::Skip(reason)) => SkipDecision::Skip { reason },
+        Ok(Decision::Unknown(reason)) => SkipDecision::Unknown { reason },
+        Err(err) => SkipDecision::Invalid {
+            error: err.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SecretRef;
+
+    #[test]
+    fn test_explain_json_shape() {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("2Gi".to_string()),
+            ..Default::default()
+        });
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.75", "1.80"])
+            .matrix("os", &["linux", "macos", "windows"]);
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .after(&["test"])
+            .when_cond(crate::Condition::branch("main"))
+            .secret_from("TOKEN", SecretRef::from_env("DEPLOY_TOKEN"))
+            .k8s(K8sOptions {
+                cpu: Some("2".to_string()),
+                ..Default::default()
+            });
+
+        let ctx = ExplainContext {
+            branch: "dev".to_string(),
+            ..Default::default()
+        };
+        let json = p.explain_json(Some(&ctx));
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "tasks": [
+                    {
+                        "order": 1,
+                        "name": "test",
+                        "command": "cargo test",
+                        "depends_on": [],
+                        "decision": "run",
+                        "manual": false,
+                        "allow_failure": false,
+                        "matrix_variants": 6,
+                        "secrets": [],
+                        "k8s": {"memory": "2Gi"}
+                    },
+                    {
+                        "order": 2,
+                        "name": "deploy",
+                        "command": "./deploy.sh",
+                        "depends_on": ["test"],
+                        "condition": "branch == 'main'",
+                        "decision": "skip",
+                        "reason": "branch is 'dev', not 'main'",
+                        "manual": false,
+                        "allow_failure": false,
+                        "secrets": [{"name": "TOKEN", "source": "env", "key": "DEPLOY_TOKEN"}],
+                        "k8s": {"memory": "2Gi", "cpu": "2"}
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_text_and_json_explain_agree() {
+        let mut p = Pipeline::new();
+        p.allow_unchecked_conditions();
+        let _ = p.task("a").run("true").when("branch == 'main'");
+        let _ = p.task("b").run("true").when("env.MISSING == 'x'");
+        let _ = p.task("c").run("true").when("branch = 'main'");
+        let _ = p.task("d").run("true");
+
+        let plan = p.execution_plan(None);
+        let mut buf = Vec::new();
+        p.explain_to(&mut buf, None);
+        let out = String::from_utf8(buf).unwrap();
+
+        for task in &plan.tasks {
+            let header = out
+                .lines()
+                .find(|l| {
+                    l.ends_with(&format!(". {}", task.name))
+                        || l.contains(&format!(". {} ", task.name))
+                })
+                .unwrap();
+            match &task.decision {
+                SkipDecision::Run | SkipDecision::Invalid { .. } => {
+                    assert!(!header.contains('['), "{}", header)
+                }
+                SkipDecision::Skip { reason } => {
+                    assert!(header.ends_with(&format!("[SKIPPED: {}]", reason)))
+                }
+                SkipDecision::Unknown { reason } => {
+                    assert!(header.ends_with(&format!("[UNKNOWN: {}]", reason)))
+                }
+            }
+        }
+        let c = plan.tasks.iter().find(|t| t.name == "c").unwrap();
+        assert!(matches!(c.decision, SkipDecision::Invalid { .. }));
+    }
+}
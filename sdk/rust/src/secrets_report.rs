@@ -0,0 +1,311 @@
+//! Which secrets a pipeline touches, where they come from and who uses them.
+//!
+//! ```rust
+//! use sykli::{Pipeline, SecretRef};
+//!
+//! let mut p = Pipeline::new();
+//! p.task("test").run("cargo test").secret("CODECOV_TOKEN");
+//! p.task("deploy")
+//!     .container("alpine")
+//!     .run("./deploy.sh")
+//!     .secret_from("DB_PASS", SecretRef::from_vault("secret/data/db#password"));
+//!
+//! let report = p.secrets_report();
+//! assert_eq!(report.secrets.len(), 2);
+//! println!("{}", report);
+//! ```
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{Pipeline, PublishDest, SecretRef, SecretSource, TaskData};
+
+/// Every distinct secret used by a pipeline. See [`Pipeline::secrets_report`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SecretsReport {
+    /// Secrets sorted by name, then source and key.
+    pub secrets: Vec<SecretUsage>,
+}
+
+/// One distinct secret (name + source + key) and the tasks that use it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SecretUsage {
+    /// Name the task sees the secret under.
+    pub name: String,
+    /// `env`, `file` or `vault`.
+    pub source: String,
+    /// Env var name, file path or vault path.
+    pub key: String,
+    /// Tasks using the secret, in declaration order.
+    pub tasks: Vec<String>,
+    /// True if any of those tasks runs in a container.
+    pub in_container: bool,
+    /// True if any of those tasks runs on the host.
+    pub on_host: bool,
+}
+
+impl SecretUsage {
+    fn location(&self) -> &'static str {
+        match (self.in_container, self.on_host) {
+            (true, true) => "container+host",
+            (true, false) => "container",
+            _ => "host",
+        }
+    }
+}
+
+impl fmt::Display for SecretsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.secrets.is_empty() {
+            return writeln!(f, "No secrets used.");
+        }
+        let rows: Vec<[String; 5]> = self
+            .secrets
+            .iter()
+            .map(|s| {
+                [
+                    s.name.clone(),
+                    s.source.clone(),
+                    s.key.clone(),
+                    s.location().to_string(),
+                    s.tasks.join(", "),
+                ]
+            })
+            .collect();
+        let header = ["SECRET", "SOURCE", "KEY", "RUNS IN", "TASKS"];
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(cell.len());
+            }
+        }
+
+        let write_row = |f: &mut fmt::Formatter<'_>, cells: [&str; 5]| {
+            for (i, cell) in cells.iter().enumerate() {
+                if i == cells.len() - 1 {
+                    writeln!(f, "{}", cell)?;
+                } else {
+                    write!(f, "{:width$}  ", cell, width = widths[i])?;
+                }
+            }
+            Ok(())
+        };
+        write_row(f, header)?;
+        for row in &rows {
+            write_row(f, [&row[0], &row[1], &row[2], &row[3], &row[4]])?;
+        }
+        Ok(())
+    }
+}
+
+impl Pipeline {
+    /// Aggregates the secrets used across all tasks.
+    ///
+    /// Plain `secret("X")` names are reported as env secrets read from `X`,
+    /// so they merge with an equivalent `secret_from("X", SecretRef::from_env("X"))`.
+    /// Refs used by `secret_template` are included too, as are
+    /// `publish_output` credentials, which the runner uses on the host.
+    pub fn secrets_report(&self) -> SecretsReport {
+        let mut report = SecretsReport::default();
+        for t in &self.tasks {
+            let t = &*self.with_defaults(t);
+            let in_container = t.container.is_some() || t.container_build.is_some();
+            for name in &t.secrets {
+                report.record(t, name, &SecretSource::Env, name, in_container);
+            }
+            let template_refs = t.secret_templates.iter().flat_map(|st| &st.refs);
+            for sr in t.secret_refs.iter().chain(template_refs) {
+                let SecretRef {
+                    name, source, key, ..
+                } = sr;
+                report.record(t, name, source, key, in_container);
+            }
+            for (_, dest) in &t.publish {
+                let credential = match dest {
+                    PublishDest::GithubRelease { token, .. } => Some(token),
+                    PublishDest::HttpPut { auth, .. } => auth.as_ref(),
+                };
+                // Publish credentials have no task-visible name; report them by key.
+                if let Some(SecretRef { source, key, .. }) = credential {
+                    report.record(t, key, source, key, false);
+                }
+            }
+        }
+        report
+            .secrets
+            .sort_by(|a, b| (&a.name, &a.source, &a.key).cmp(&(&b.name, &b.source, &b.key)));
+        report
+    }
+}
+
+impl SecretsReport {
+    fn record(
+        &mut self,
+        task: &TaskData,
+        name: &str,
+        source: &SecretSource,
+        key: &str,
+        in_container: bool,
+    ) {
+        let source = source.as_str();
+        let usage = match self
+            .secrets
+            .iter_mut()
+            .find(|s| s.name == name && s.source == source && s.key == key)
+        {
+            Some(usage) => usage,
+            None => {
+                self.secrets.push(SecretUsage {
+                    name: name.to_string(),
+                    source: source.to_string(),
+                    key: key.to_string(),
+                    tasks: Vec::new(),
+                    in_container: false,
+                    on_host: false,
+                });
+                self.secrets.last_mut().unwrap()
+            }
+        };
+        if !usage.tasks.contains(&task.name) {
+            usage.tasks.push(task.name.clone());
+        }
+        if in_container {
+            usage.in_container = true;
+        } else {
+            usage.on_host = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed_pipeline() -> Pipeline {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .secret("GITHUB_TOKEN")
+            .secret("CODECOV_TOKEN");
+        let _ = p
+            .task("release")
+            .container("rust:1.75")
+            .run("cargo publish")
+            .secret_from("GITHUB_TOKEN", SecretRef::from_env("GITHUB_TOKEN"))
+            .secret_from(
+                "CARGO_TOKEN",
+                SecretRef::from_vault("secret/data/crates#token"),
+            );
+        let _ = p
+            .task("deploy")
+            .container("alpine")
+            .run("./deploy.sh")
+            .secret_from("GITHUB_TOKEN", SecretRef::from_env("DEPLOY_GITHUB_TOKEN"))
+            .secret_template(
+                "/root/.kube/config",
+                "{{kubeconfig}}",
+                &[("kubeconfig", SecretRef::from_file("/etc/ci/kubeconfig"))],
+            );
+        p
+    }
+
+    fn usage<'a>(report: &'a SecretsReport, name: &str, key: &str) -> &'a SecretUsage {
+        report
+            .secrets
+            .iter()
+            .find(|s| s.name == name && s.key == key)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_secrets_report_groups_plain_and_typed() {
+        let report = mixed_pipeline().secrets_report();
+
+        let names: Vec<_> = report
+            .secrets
+            .iter()
+            .map(|s| (s.name.as_str(), s.source.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("CARGO_TOKEN", "vault"),
+                ("CODECOV_TOKEN", "env"),
+                ("GITHUB_TOKEN", "env"),
+                ("GITHUB_TOKEN", "env"),
+                ("kubeconfig", "file"),
+            ]
+        );
+
+        // secret("GITHUB_TOKEN") and from_env("GITHUB_TOKEN") are the same secret.
+        let github = usage(&report, "GITHUB_TOKEN", "GITHUB_TOKEN");
+        assert_eq!(github.tasks, ["test", "release"]);
+        assert!(github.in_container);
+        assert!(github.on_host);
+
+        // Same name from a different env var is reported separately.
+        let deploy = usage(&report, "GITHUB_TOKEN", "DEPLOY_GITHUB_TOKEN");
+        assert_eq!(deploy.tasks, ["deploy"]);
+        assert!(deploy.in_container);
+        assert!(!deploy.on_host);
+
+        let kubeconfig = usage(&report, "kubeconfig", "/etc/ci/kubeconfig");
+        assert_eq!(kubeconfig.tasks, ["deploy"]);
+    }
+
+    #[test]
+    fn test_secrets_report_includes_publish_credentials() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("release")
+            .container("rust:1.75")
+            .run("cargo build --release")
+            .output("binary", "target/release/app")
+            .publish_output(
+                "binary",
+                PublishDest::GithubRelease {
+                    repo: "acme/app".to_string(),
+                    tag_expr: "{tag}".to_string(),
+                    token: SecretRef::from_env("GITHUB_TOKEN"),
+                },
+            );
+        let report = p.secrets_report();
+
+        let token = usage(&report, "GITHUB_TOKEN", "GITHUB_TOKEN");
+        assert_eq!(token.tasks, ["release"]);
+        assert!(token.on_host);
+        assert!(!token.in_container);
+    }
+
+    #[test]
+    fn test_secrets_report_display_table() {
+        let out = mixed_pipeline().secrets_report().to_string();
+        let lines: Vec<_> = out.lines().collect();
+
+        assert!(lines[0].starts_with("SECRET "));
+        assert!(lines[0].ends_with("TASKS"));
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].starts_with("CARGO_TOKEN "));
+        assert!(lines[4].contains("container+host"));
+        assert!(lines[4].ends_with("test, release"));
+
+        assert_eq!(
+            Pipeline::new().secrets_report().to_string(),
+            "No secrets used.\n"
+        );
+    }
+
+    #[test]
+    fn test_secrets_report_serialize() {
+        let report = mixed_pipeline().secrets_report();
+        let json = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(json["secrets"][0]["name"], "CARGO_TOKEN");
+        assert_eq!(json["secrets"][0]["source"], "vault");
+        assert_eq!(json["secrets"][0]["key"], "secret/data/crates#token");
+        assert_eq!(json["secrets"][0]["tasks"][0], "release");
+        assert_eq!(json["secrets"][0]["in_container"], true);
+    }
+}
@@ -0,0 +1,497 @@
+//! Partial pipelines: selected tasks plus everything they depend on.
+//!
+//! A merge queue can run only the `required` tasks while the full pipeline
+//! runs after merge:
+//!
+//! ```rust
+//! use sykli::Pipeline;
+//! use sykli::subset::SubsetFilter;
+//!
+//! let mut p = Pipeline::new();
+//! p.task("build").run("cargo build");
+//! p.task("test").run("cargo test").tag("required").after(&["build"]);
+//! p.task("bench").run("cargo bench").after(&["build"]);
+//!
+//! let required = p.subset(SubsetFilter::tag("required")).unwrap();
+//! let names: Vec<_> = required.iter_tasks().map(|t| t.name()).collect();
+//! assert_eq!(names, ["build", "test"]);
+//! ```
+//!
+//! The same selection is available from the command line as
+//! `--emit-subset tag=required` (see [`Pipeline::emit`]).
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::{suggest_task_name, Pipeline, TaskData};
+
+/// Selects tasks by tag for [`Pipeline::explain_filtered`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum TagFilter<'a> {
+    /// Tasks carrying any of these tags.
+    Any(&'a [&'a str]),
+}
+
+impl fmt::Display for TagFilter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagFilter::Any(tags) => write!(f, "tasks tagged {}", tags.join(" or ")),
+        }
+    }
+}
+
+/// Selects the tasks to keep in a [`Pipeline::subset`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubsetFilter {
+    /// Tasks carrying any of these tags.
+    Tags(Vec<String>),
+    /// Tasks with exactly these names.
+    Names(Vec<String>),
+}
+
+impl SubsetFilter {
+    /// Selects tasks tagged with `name`.
+    pub fn tag(name: &str) -> Self {
+        SubsetFilter::Tags(vec![name.to_string()])
+    }
+
+    /// Selects tasks by name.
+    pub fn names(names: &[&str]) -> Self {
+        SubsetFilter::Names(names.iter().map(|s| s.to_string()).collect())
+    }
+
+    /// Parses a CLI filter: `tag=a,b` or `name=a,b`.
+    ///
+    /// # Errors
+    /// Returns [`SubsetError::InvalidFilter`] for any other form.
+    pub fn parse(spec: &str) -> Result<Self, SubsetError> {
+        let (kind, values) = spec.split_once('=').ok_or_else(|| {
+            SubsetError::InvalidFilter(format!("{:?} (expected tag=<tags> or name=<tasks>)", spec))
+        })?;
+        let values: Vec<String> = values
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect();
+        if values.is_empty() {
+            return Err(SubsetError::InvalidFilter(format!(
+                "{:?} selects nothing",
+                spec
+            )));
+        }
+        match kind.trim() {
+            "tag" | "tags" => Ok(SubsetFilter::Tags(values)),
+            "name" | "names" | "task" => Ok(SubsetFilter::Names(values)),
+            other => Err(SubsetError::InvalidFilter(format!(
+                "unknown filter {:?} (expected tag or name)",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for SubsetFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubsetFilter::Tags(tags) => write!(f, "tag={}", tags.join(",")),
+            SubsetFilter::Names(names) => write!(f, "name={}", names.join(",")),
+        }
+    }
+}
+
+/// Error returned by [`Pipeline::subset`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubsetError {
+    /// The filter couldn't be parsed.
+    InvalidFilter(String),
+    /// A name filter referenced a task that doesn't exist.
+    UnknownTask {
+        /// The requested name.
+        name: String,
+        /// Closest existing task name, if any.
+        suggestion: Option<String>,
+    },
+    /// No task matched the filter.
+    NoMatch(SubsetFilter),
+    /// A kept task depends on a task the subset dropped.
+    MissingDependency {
+        /// The kept task.
+        task: String,
+        /// The dropped dependency.
+        dependency: String,
+    },
+}
+
+impl fmt::Display for SubsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubsetError::InvalidFilter(msg) => write!(f, "invalid subset filter: {}", msg),
+            SubsetError::UnknownTask {
+                name,
+                suggestion: Some(s),
+            } => write!(f, "unknown task {:?} (did you mean {:?}?)", name, s),
+            SubsetError::UnknownTask { name, .. } => write!(f, "unknown task {:?}", name),
+            SubsetError::NoMatch(filter) => write!(f, "no tasks match {}", filter),
+            SubsetError::MissingDependency { task, dependency } => write!(
+                f,
+                "task {:?} depends on {:?}, which is not in the subset",
+                task, dependency
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubsetError {}
+
+impl Pipeline {
+    /// Returns a pipeline containing only the selected tasks and their
+    /// transitive dependencies.
+    ///
+    /// Dependencies include `after`/`input_from` edges and the providers of
+    /// any capability a kept task `needs`. Declaration order is preserved,
+    /// and resources not mounted by a kept task are dropped.
+    ///
+    /// # Errors
+    /// Returns [`SubsetError::UnknownTask`] if a name filter references a
+    /// missing task, and [`SubsetError::NoMatch`] if nothing was selected.
+    pub fn subset(&self, filter: SubsetFilter) -> Result<Pipeline, SubsetError> {
+        let pending: Vec<&str> = match &filter {
+            SubsetFilter::Tags(tags) => self
+                .tasks
+                .iter()
+                .filter(|t| t.tags.iter().any(|tag| tags.contains(tag)))
+                .map(|t| t.name.as_str())
+                .collect(),
+            SubsetFilter::Names(names) => {
+                let known: Vec<_> = self.tasks.iter().map(|t| t.name.as_str()).collect();
+                for name in names {
+                    if !known.contains(&name.as_str()) {
+                        return Err(SubsetError::UnknownTask {
+                            name: name.clone(),
+                            suggestion: suggest_task_name(name, &known).map(str::to_string),
+                        });
+                    }
+                }
+                names.iter().map(String::as_str).collect()
+            }
+        };
+        if pending.is_empty() {
+            return Err(SubsetError::NoMatch(filter));
+        }
+
+        let keep = self.closure(pending, Direction::Dependencies);
+
+        let tasks: Vec<_> = self
+            .tasks
+            .iter()
+            .filter(|t| keep.contains(t.name.as_str()))
+            .cloned()
+            .collect();
+        for t in &tasks {
+            let deps = t
+                .depends_on
+                .iter()
+                .chain(t.task_inputs.iter().map(|ti| &ti.from_task));
+            for dep in deps {
+                if self.tasks.iter().any(|o| &o.name == dep) && !keep.contains(dep.as_str()) {
+                    return Err(SubsetError::MissingDependency {
+                        task: t.name.clone(),
+                        dependency: dep.clone(),
+                    });
+                }
+            }
+        }
+
+        let mounted: HashSet<&str> = tasks
+            .iter()
+            .flat_map(|t| t.mounts.iter().map(|m| m.resource.as_str()))
+            .collect();
+        let dirs = self
+            .directories()
+            .into_iter()
+            .filter(|d| mounted.contains(d.id().as_str()))
+            .cloned()
+            .collect();
+        let caches = self
+            .caches()
+            .into_iter()
+            .filter(|c| mounted.contains(c.name.as_str()))
+            .cloned()
+            .collect();
+        let git_sources = self
+            .git_sources
+            .iter()
+            .filter(|g| mounted.contains(g.id().as_str()))
+            .cloned()
+            .collect();
+        let http_files = self
+            .http_files
+            .iter()
+            .filter(|h| mounted.contains(h.id().as_str()))
+            .cloned()
+            .collect();
+        let files = self
+            .files
+            .iter()
+            .filter(|f| mounted.contains(f.id().as_str()))
+            .cloned()
+            .collect();
+
+        Ok(Pipeline {
+            id: self.id,
+            tasks,
+            templates: self.templates.clone(),
+            dirs,
+            caches,
+            git_sources,
+            http_files,
+            files,
+            k8s_defaults: self.k8s_defaults.clone(),
+            defaults: self.defaults.clone(),
+            max_tasks: self.max_tasks,
+            max_parallel: self.max_parallel,
+            // Barrier edges are already on the kept tasks
+            barriers: Vec::new(),
+            normalize_resource_ids: self.normalize_resource_ids,
+            hash_dir_globs: self.hash_dir_globs,
+            allowed_lints: self.allowed_lints.clone(),
+            max_runner_version: self.max_runner_version,
+            unchecked_conditions: self.unchecked_conditions,
+            strict: self.strict,
+            reserved_env_prefixes: self.reserved_env_prefixes.clone(),
+            secret_name_rule: self.secret_name_rule,
+        })
+    }
+
+    /// `task_name` plus every task reachable from it in `direction`.
+    pub(crate) fn related_tasks(
+        &self,
+        task_name: &str,
+        direction: Direction,
+    ) -> Result<HashSet<&str>, SubsetError> {
+        let Some(t) = self.tasks.iter().find(|t| t.name == task_name) else {
+            let known: Vec<_> = self.tasks.iter().map(|t| t.name.as_str()).collect();
+            return Err(SubsetError::UnknownTask {
+                name: task_name.to_string(),
+                suggestion: suggest_task_name(task_name, &known).map(str::to_string),
+            });
+        };
+        Ok(self.closure(vec![t.name.as_str()], direction))
+    }
+
+    /// Tasks matching `filter` and everything they depend on.
+    pub(crate) fn tagged_tasks(&self, filter: TagFilter<'_>) -> Result<HashSet<&str>, SubsetError> {
+        let TagFilter::Any(tags) = filter;
+        let roots: Vec<&str> = self
+            .tasks
+            .iter()
+            .filter(|t| t.tags.iter().any(|tag| tags.contains(&tag.as_str())))
+            .map(|t| t.name.as_str())
+            .collect();
+        if roots.is_empty() {
+            let tags = tags.iter().map(|t| t.to_string()).collect();
+            return Err(SubsetError::NoMatch(SubsetFilter::Tags(tags)));
+        }
+        Ok(self.closure(roots, Direction::Dependencies))
+    }
+
+    /// The `roots` plus every task reachable from them in `direction`.
+    ///
+    /// A task depends on its `after`/`input_from` dependencies and on the
+    /// providers of any capability it `needs`.
+    fn closure<'a>(&'a self, mut pending: Vec<&'a str>, direction: Direction) -> HashSet<&'a str> {
+        let depends = |t: &TaskData, dep: &TaskData| {
+            t.depends_on.contains(&dep.name)
+                || t.needs
+                    .iter()
+                    .any(|cap| dep.provides.iter().any(|(c, _)| c == cap))
+        };
+        let mut seen: HashSet<&str> = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name) {
+                continue;
+            }
+            let Some(t) = self.tasks.iter().find(|t| t.name == name) else {
+                continue;
+            };
+            pending.extend(
+                self.tasks
+                    .iter()
+                    .filter(|o| match direction {
+                        Direction::Dependencies => depends(t, o),
+                        Direction::Dependents => depends(o, t),
+                    })
+                    .map(|o| o.name.as_str()),
+            );
+        }
+        seen
+    }
+}
+
+/// Which way [`Pipeline::related_tasks`] walks the dependency graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// Towards the tasks a task waits for.
+    Dependencies,
+    /// Towards the tasks waiting for it.
+    Dependents,
+}
+
+/// Returns the value of `--emit-subset <filter>` or `--emit-subset=<filter>`.
+pub(crate) fn emit_subset_arg(args: &[String]) -> Option<&str> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--emit-subset" {
+            return Some(iter.next().map(String::as_str).unwrap_or(""));
+        }
+        if let Some(spec) = arg.strip_prefix("--emit-subset=") {
+            return Some(spec);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(p: &Pipeline) -> Vec<&str> {
+        p.iter_tasks().map(|t| t.name()).collect()
+    }
+
+    fn merge_queue_pipeline() -> Pipeline {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let docs = p.dir("docs");
+        let registry = p.cache("cargo-registry");
+        let npm = p.cache("npm");
+
+        let _ = p
+            .task("setup")
+            .run("make setup")
+            .provides("toolchain", None);
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .mount(&src, "/src")
+            .mount_cache(&registry, "/cargo")
+            .output("bin", "target/app")
+            .needs(&["toolchain"]);
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .tag("required")
+            .input_from("build", "bin", "/app");
+        let _ = p
+            .task("lint")
+            .run("cargo clippy")
+            .tags(&["required", "fast"]);
+        let _ = p
+            .task("docs")
+            .run("mdbook build")
+            .mount(&docs, "/docs")
+            .mount_cache(&npm, "/npm")
+            .after(&["build"]);
+        let _ = p.task("deploy").run("./deploy.sh").after(&["test", "docs"]);
+        p
+    }
+
+    #[test]
+    fn test_subset_includes_transitive_closure() {
+        let p = merge_queue_pipeline();
+        let sub = p.subset(SubsetFilter::tag("required")).unwrap();
+
+        // setup comes in through needs(), build through input_from.
+        assert_eq!(names(&sub), ["setup", "build", "test", "lint"]);
+
+        let sub = p.subset(SubsetFilter::names(&["deploy"])).unwrap();
+        assert_eq!(names(&sub), ["setup", "build", "test", "docs", "deploy"]);
+    }
+
+    #[test]
+    fn test_subset_prunes_resources() {
+        let p = merge_queue_pipeline();
+        let sub = p.subset(SubsetFilter::tag("required")).unwrap();
+
+        let ids: Vec<_> = sub.resources().map(|r| r.id()).collect();
+        assert_eq!(ids, ["src:.", "cargo-registry"]);
+    }
+
+    #[test]
+    fn test_subset_has_no_dangling_references() {
+        let p = merge_queue_pipeline();
+        for filter in ["tag=required", "tag=fast", "name=test", "name=docs,lint"] {
+            let sub = p.subset(SubsetFilter::parse(filter).unwrap()).unwrap();
+            let kept = names(&sub);
+            for t in sub.iter_tasks() {
+                for dep in t.depends_on() {
+                    assert!(
+                        kept.contains(&dep.as_str()),
+                        "{}: {} -> {}",
+                        filter,
+                        t.name(),
+                        dep
+                    );
+                }
+            }
+            let mut buf = Vec::new();
+            sub.emit_to(&mut buf).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_subset_errors() {
+        let p = merge_queue_pipeline();
+
+        assert_eq!(
+            p.subset(SubsetFilter::names(&["tset"])).err().unwrap(),
+            SubsetError::UnknownTask {
+                name: "tset".to_string(),
+                suggestion: Some("test".to_string()),
+            }
+        );
+        assert!(matches!(
+            p.subset(SubsetFilter::tag("nightly")),
+            Err(SubsetError::NoMatch(_))
+        ));
+        assert!(matches!(
+            SubsetFilter::parse("required"),
+            Err(SubsetError::InvalidFilter(_))
+        ));
+        assert!(matches!(
+            SubsetFilter::parse("target=local"),
+            Err(SubsetError::InvalidFilter(_))
+        ));
+    }
+
+    #[test]
+    fn test_emit_subset_cli_args() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            emit_subset_arg(&args(&["pipeline", "--emit-subset", "tag=required"])),
+            Some("tag=required")
+        );
+        assert_eq!(
+            emit_subset_arg(&args(&["pipeline", "--emit-subset=name=test"])),
+            Some("name=test")
+        );
+        assert_eq!(
+            emit_subset_arg(&args(&["pipeline", "--emit-subset"])),
+            Some("")
+        );
+        assert_eq!(emit_subset_arg(&args(&["pipeline", "--emit"])), None);
+
+        let p = merge_queue_pipeline();
+        let cli = args(&["--emit-subset", "tag=fast"]);
+        let spec = emit_subset_arg(&cli).unwrap();
+        let sub = p.subset(SubsetFilter::parse(spec).unwrap()).unwrap();
+        let mut buf = Vec::new();
+        sub.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["tasks"].as_array().unwrap().len(), 1);
+        assert_eq!(json["tasks"][0]["name"], "lint");
+    }
+}
@@ -18,6 +18,9 @@
 //! ```
 //!
 //! That's all you need. One method.
+//! Targets that depend on something outside the process (a Docker daemon,
+//! an SSH host) can also override [`Target::health_check`] so runners can
+//! find out before scheduling.
 //!
 //! # Optional Capabilities
 //!
@@ -28,6 +31,7 @@
 //! - [`Secrets`] - resolve secrets by name
 //! - [`Storage`] - manage volumes and artifacts
 //! - [`Services`] - start/stop service containers
+//! - [`Publisher`] - upload published outputs
 //!
 //! # Examples
 //!
@@ -70,11 +74,15 @@
 //! }
 //! ```
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error as StdError;
 use std::fmt;
+use std::process::Command;
+use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::{CacheSharing, Eviction, PublishDest, SecretRef, SecretSource, SECRET_PLACEHOLDER};
+
 // =============================================================================
 // ERROR TYPE
 // =============================================================================
@@ -124,16 +132,31 @@ impl StdError for Error {
 // =============================================================================
 
 /// Task specification passed to [`Target::run_task`].
+///
+/// Build one with [`TaskSpec::new`] and set fields on it; struct literals
+/// outside this crate don't compile, so fields can be added without a break.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct TaskSpec {
     /// Task name.
     pub name: String,
-    /// Command to execute.
+    /// Command to execute, as a shell string. With `args` set, only a
+    /// display form of them.
     pub command: String,
+    /// Program and arguments to execute directly, without a shell, from
+    /// `Task::exec`. Empty for shell commands; see [`TaskSpec::argv`].
+    pub args: Vec<String>,
     /// Container image (empty = shell execution).
     pub image: Option<String>,
-    /// Working directory inside container.
+    /// Image to build before running, instead of pulling `image`.
+    pub image_build: Option<ImageBuildSpec>,
+    /// Working directory: inside the container when `image` is set,
+    /// otherwise on the host, where it may be relative to the project root.
     pub workdir: Option<String>,
+    /// Container entrypoint override (`Some("")` = clear the image's).
+    pub entrypoint: Option<String>,
+    /// User to run the container as, e.g. `1000:1000`.
+    pub user: Option<String>,
     /// Environment variables.
     pub env: HashMap<String, String>,
     /// Volume mounts.
@@ -142,6 +165,12 @@ pub struct TaskSpec {
     pub timeout: Option<u32>,
     /// Service containers for this task.
     pub services: Vec<ServiceSpec>,
+    /// Files to render from secrets before the task runs.
+    pub secret_templates: Vec<SecretTemplateSpec>,
+    /// Secrets to write to files rather than export as env vars.
+    pub secret_files: Vec<SecretFileSpec>,
+    /// Outputs to upload after the task succeeds.
+    pub publish: Vec<PublishSpec>,
 }
 
 impl TaskSpec {
@@ -150,18 +179,79 @@ impl TaskSpec {
         Self {
             name: name.into(),
             command: command.into(),
+            args: Vec::new(),
             image: None,
+            image_build: None,
             workdir: None,
+            entrypoint: None,
+            user: None,
             env: HashMap::new(),
             mounts: Vec::new(),
             timeout: None,
             services: Vec::new(),
+            secret_templates: Vec::new(),
+            secret_files: Vec::new(),
+            publish: Vec::new(),
+        }
+    }
+
+    /// What to execute: `args` when set, otherwise `sh -c command`.
+    ///
+    /// ```rust
+    /// use sykli::target::TaskSpec;
+    ///
+    /// let mut task = TaskSpec::new("test", "cargo test");
+    /// assert_eq!(task.argv(), ["sh", "-c", "cargo test"]);
+    ///
+    /// task.args = vec!["cargo".into(), "test".into()];
+    /// assert_eq!(task.argv(), ["cargo", "test"]);
+    /// ```
+    pub fn argv(&self) -> Vec<String> {
+        if self.args.is_empty() {
+            vec!["sh".to_string(), "-c".to_string(), self.command.clone()]
+        } else {
+            self.args.clone()
         }
     }
 }
 
+/// Image built from a Dockerfile, declared with `Task::container_from_dockerfile`.
+///
+/// Targets that can't build images should return an error from `run_task`
+/// naming the task rather than falling back to a shell.
+#[derive(Debug, Clone)]
+pub struct ImageBuildSpec {
+    /// Host path of the build context.
+    pub context: String,
+    /// Dockerfile path, relative to `context`.
+    pub dockerfile: String,
+}
+
+impl ImageBuildSpec {
+    /// Arguments for `docker build` that print only the image ID.
+    ///
+    /// `tag` should be derived from the content of the Dockerfile and context
+    /// so unchanged images are reused between runs.
+    pub fn docker_build_args(&self, tag: &str) -> Vec<String> {
+        let dockerfile = std::path::Path::new(&self.context).join(&self.dockerfile);
+        vec![
+            "build".to_string(),
+            "-q".to_string(),
+            "-f".to_string(),
+            dockerfile.to_string_lossy().into_owned(),
+            "-t".to_string(),
+            tag.to_string(),
+            self.context.clone(),
+        ]
+    }
+}
+
 /// Volume mount specification.
+///
+/// Build one with [`MountSpec::new`]; struct literals outside this crate
+/// don't compile.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct MountSpec {
     /// Host path or volume reference.
     pub source: String,
@@ -169,6 +259,40 @@ pub struct MountSpec {
     pub target: String,
     /// Mount type (directory, cache).
     pub mount_type: MountType,
+    /// How concurrent tasks share a cache mount.
+    pub sharing: CacheSharing,
+}
+
+impl MountSpec {
+    /// Creates a mount of `source` at `target`, shared with concurrent tasks.
+    ///
+    /// ```rust
+    /// use sykli::target::{MountSpec, MountType};
+    /// use sykli::CacheSharing;
+    ///
+    /// let mount = MountSpec::new("cargo", "/usr/local/cargo/registry", MountType::Cache)
+    ///     .with_sharing(CacheSharing::Locked);
+    /// assert_eq!(mount.sharing, CacheSharing::Locked);
+    /// ```
+    pub fn new(
+        source: impl Into<String>,
+        target: impl Into<String>,
+        mount_type: MountType,
+    ) -> Self {
+        Self {
+            source: source.into(),
+            target: target.into(),
+            mount_type,
+            sharing: CacheSharing::Shared,
+        }
+    }
+
+    /// Sets how concurrent tasks share a cache mount.
+    #[must_use]
+    pub fn with_sharing(mut self, sharing: CacheSharing) -> Self {
+        self.sharing = sharing;
+        self
+    }
 }
 
 /// Type of mount.
@@ -180,13 +304,174 @@ pub enum MountType {
     Cache,
 }
 
+/// A file rendered from secrets, declared with `Task::secret_template`.
+///
+/// Targets render it with [`SecretTemplateSpec::render`], write the result to
+/// `dest` inside the task's environment and remove it once the task finishes.
+#[derive(Debug, Clone)]
+pub struct SecretTemplateSpec {
+    /// Absolute path the rendered file is written to.
+    pub dest: String,
+    /// Template with `{{name}}` placeholders.
+    pub template: String,
+    /// One ref per placeholder; `SecretRef::name` is the placeholder name.
+    pub refs: Vec<SecretRef>,
+}
+
+impl SecretTemplateSpec {
+    /// Renders the template, resolving each placeholder's ref key through `secrets`.
+    pub fn render(&self, secrets: &dyn Secrets) -> std::result::Result<String, Error> {
+        let mut out = String::with_capacity(self.template.len());
+        let mut last = 0;
+        for caps in SECRET_PLACEHOLDER.captures_iter(&self.template) {
+            let whole = caps.get(0).unwrap();
+            let name = &caps[1];
+            let secret_ref = self.refs.iter().find(|r| r.name == name).ok_or_else(|| {
+                Error::new(format!(
+                    "secret template {}: no ref for placeholder {:?}",
+                    self.dest, name
+                ))
+            })?;
+            out.push_str(&self.template[last..whole.start()]);
+            out.push_str(&secrets.resolve_secret(&secret_ref.key)?);
+            last = whole.end();
+        }
+        out.push_str(&self.template[last..]);
+        Ok(out)
+    }
+}
+
+/// A secret written to a file, declared with `Task::secret_file`.
+///
+/// Targets resolve `secret` and write its value to `path` inside the task's
+/// environment, without exporting it as an env var, and remove the file once
+/// the task finishes.
+#[derive(Debug, Clone)]
+pub struct SecretFileSpec {
+    /// Absolute path the value is written to.
+    pub path: String,
+    /// Where the value comes from; `SecretRef::name` identifies it in logs.
+    pub secret: SecretRef,
+}
+
+impl SecretFileSpec {
+    /// Builds the spec for a ref declared with `Task::secret_file`, or None
+    /// for an env-style ref.
+    pub fn from_ref(secret: &SecretRef) -> Option<Self> {
+        Some(SecretFileSpec {
+            path: secret.mount_path.clone()?,
+            secret: secret.clone(),
+        })
+    }
+}
+
+/// An output to upload, declared with `Task::publish_output`.
+#[derive(Debug, Clone)]
+pub struct PublishSpec {
+    /// Output name.
+    pub output: String,
+    /// Path of the output on the host.
+    pub path: String,
+    /// Where to upload it.
+    pub dest: PublishDest,
+}
+
+impl PublishSpec {
+    /// Fills `{name}` placeholders in a destination template from `vars`.
+    ///
+    /// `{output}` and `{file}` (the output's file name) are always available.
+    pub fn expand(
+        &self,
+        template: &str,
+        vars: &HashMap<String, String>,
+    ) -> std::result::Result<String, Error> {
+        let file = std::path::Path::new(&self.path)
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| Error::new(format!("unclosed '{{' in {:?}", template)))?;
+            let name = &rest[open + 1..open + close];
+            let value = match name {
+                "output" => self.output.as_str(),
+                "file" => file.as_str(),
+                _ => vars.get(name).map(String::as_str).ok_or_else(|| {
+                    Error::new(format!("publish {}: {{{}}} not set", self.output, name))
+                })?,
+            };
+            out.push_str(&rest[..open]);
+            out.push_str(value);
+            rest = &rest[open + close + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
 /// Service container specification.
+///
+/// Build one with [`ServiceSpec::new`] and set fields on it; struct literals
+/// outside this crate don't compile.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct ServiceSpec {
     /// Service name (used as hostname).
     pub name: String,
     /// Container image.
     pub image: String,
+    /// Environment variables.
+    pub env: HashMap<String, String>,
+    /// Ports the service listens on, when not the image's default.
+    pub ports: Vec<u16>,
+    /// Replacement for the image's default command, split like a shell
+    /// would but not run through one.
+    pub command: Option<String>,
+    /// What to wait for before starting the task.
+    pub readiness: Option<ReadinessSpec>,
+}
+
+impl ServiceSpec {
+    /// Creates a service running `image` as hostname `name`, with the
+    /// image's defaults and no readiness probe.
+    pub fn new(name: impl Into<String>, image: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            image: image.into(),
+            env: HashMap::new(),
+            ports: Vec::new(),
+            command: None,
+            readiness: None,
+        }
+    }
+}
+
+/// When a service counts as ready.
+#[derive(Debug, Clone)]
+pub struct ReadinessSpec {
+    /// Check to repeat until it passes.
+    pub probe: ReadinessProbe,
+    /// Seconds to wait before failing the task (`None` = target default).
+    pub timeout: Option<u32>,
+}
+
+/// Readiness check for a service container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadinessProbe {
+    /// Command run inside the service container; ready when it exits 0.
+    Cmd(String),
+    /// Ready when the port accepts TCP connections.
+    Tcp(u16),
+    /// Ready when `GET path` on the port answers with a 2xx status.
+    Http {
+        /// Request path, starting with `/`.
+        path: String,
+        /// Port to connect to.
+        port: u16,
+    },
 }
 
 // =============================================================================
@@ -194,7 +479,13 @@ pub struct ServiceSpec {
 // =============================================================================
 
 /// Result from task execution.
+///
+/// Build one with [`Result::success`], [`Result::failure`] or
+/// [`Result::error`] and the `with_*` methods. The struct is
+/// `#[non_exhaustive]` so fields like `image_digest` can be added without
+/// another break; struct literals outside this crate no longer compile.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct Result {
     /// Whether the task succeeded.
     pub success: bool,
@@ -206,6 +497,8 @@ pub struct Result {
     pub duration: Duration,
     /// Error message if failed.
     pub error: Option<String>,
+    /// Digest of the container image actually used, if the target can tell.
+    pub image_digest: Option<String>,
 }
 
 impl Result {
@@ -217,6 +510,7 @@ impl Result {
             output: String::new(),
             duration: Duration::ZERO,
             error: None,
+            image_digest: None,
         }
     }
 
@@ -228,6 +522,7 @@ impl Result {
             output: output.into(),
             duration,
             error: None,
+            image_digest: None,
         }
     }
 
@@ -239,6 +534,7 @@ impl Result {
             output: output.into(),
             duration: Duration::ZERO,
             error: None,
+            image_digest: None,
         }
     }
 
@@ -250,8 +546,78 @@ impl Result {
             output: String::new(),
             duration: Duration::ZERO,
             error: Some(message.into()),
+            image_digest: None,
         }
     }
+
+    /// Records the digest of the image the task ran in (e.g. `sha256:...`).
+    #[must_use]
+    pub fn with_image_digest(mut self, digest: impl Into<String>) -> Self {
+        self.image_digest = Some(digest.into());
+        self
+    }
+}
+
+/// The last lines of a task's output, for failure summaries.
+///
+/// Keeps at most `limit` complete lines plus any unterminated last line,
+/// so executors can feed it every chunk while streaming output elsewhere.
+#[derive(Debug, Clone)]
+pub struct OutputTail {
+    limit: usize,
+    lines: VecDeque<String>,
+    partial: String,
+}
+
+impl OutputTail {
+    /// Default number of lines kept for a failed task.
+    pub const DEFAULT_LINES: usize = 50;
+
+    /// Creates an empty tail keeping the last `limit` lines.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            lines: VecDeque::with_capacity(limit.min(1024)),
+            partial: String::new(),
+        }
+    }
+
+    /// Appends a chunk of output, which may end mid-line.
+    pub fn push(&mut self, chunk: &str) {
+        self.partial.push_str(chunk);
+        while let Some(nl) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=nl).collect();
+            self.push_line(line.trim_end_matches(['\n', '\r']).to_string());
+        }
+    }
+
+    fn push_line(&mut self, line: String) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.lines.len() == self.limit {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// The kept lines, oldest first, with every occurrence of a secret value
+    /// replaced by `***`.
+    pub fn lines(&self, secrets: &[&str]) -> Vec<String> {
+        let mut lines: Vec<String> = self.lines.iter().cloned().collect();
+        if !self.partial.is_empty() && self.limit > 0 {
+            if lines.len() == self.limit {
+                lines.remove(0);
+            }
+            lines.push(self.partial.clone());
+        }
+        for line in &mut lines {
+            for secret in secrets.iter().filter(|s| !s.is_empty()) {
+                *line = line.replace(secret, "***");
+            }
+        }
+        lines
+    }
 }
 
 // =============================================================================
@@ -271,10 +637,10 @@ impl Result {
 ///
 /// impl Target for ShellTarget {
 ///     fn run_task(&self, task: &TaskSpec) -> Result {
-///         // Execute via shell
-///         let output = std::process::Command::new("sh")
-///             .arg("-c")
-///             .arg(&task.command)
+///         // Execute via shell, or directly for exec() tasks
+///         let argv = task.argv();
+///         let output = std::process::Command::new(&argv[0])
+///             .args(&argv[1..])
 ///             .output()
 ///             .expect("failed to execute");
 ///
@@ -297,6 +663,16 @@ pub trait Target {
     ///
     /// This is the ONLY required method. Everything else is optional.
     fn run_task(&self, task: &TaskSpec) -> Result;
+
+    /// Checks that the target can run tasks right now, e.g. that the Docker
+    /// daemon answers or an SSH host accepts connections.
+    ///
+    /// Runners call this before scheduling and decide whether to fail the
+    /// run or skip the tasks routed to an unavailable target. The default
+    /// assumes the target is available.
+    fn health_check(&self) -> std::result::Result<(), Error> {
+        Ok(())
+    }
 }
 
 // =============================================================================
@@ -359,6 +735,32 @@ pub trait Secrets {
     fn resolve_secret(&self, name: &str) -> std::result::Result<String, Error>;
 }
 
+/// Optional capability: Upload published outputs.
+///
+/// Targets without a publisher leave `TaskSpec::publish` to the runner.
+/// Credentials arrive as [`SecretRef`]s and should be resolved through
+/// [`Secrets`] at upload time, never written into commands.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// impl Publisher for MyTarget {
+///     fn publish(&self, task: &str, spec: &PublishSpec) -> std::result::Result<(), Error> {
+///         match &spec.dest {
+///             PublishDest::HttpPut { url_template, auth } => {
+///                 let url = spec.expand(url_template, &self.vars)?;
+///                 self.http.put(&url, &spec.path, auth.as_ref())
+///             }
+///             PublishDest::GithubRelease { .. } => Err(Error::new("unsupported")),
+///         }
+///     }
+/// }
+/// ```
+pub trait Publisher {
+    /// Upload one output of a task that succeeded.
+    fn publish(&self, task: &str, spec: &PublishSpec) -> std::result::Result<(), Error>;
+}
+
 /// Volume reference returned by [`Storage::create_volume`].
 #[derive(Debug, Clone)]
 pub struct Volume {
@@ -371,10 +773,42 @@ pub struct Volume {
 }
 
 /// Options for volume creation.
+///
+/// For a cache, [`ResourceView::volume_options`](crate::view::ResourceView::volume_options)
+/// fills in its limits. Build one from [`VolumeOptions::default`] and the
+/// `with_*` methods; struct literals outside this crate don't compile.
 #[derive(Debug, Clone, Default)]
+#[non_exhaustive]
 pub struct VolumeOptions {
     /// Volume size (e.g., "1Gi").
     pub size: Option<String>,
+    /// Size to keep the contents under by evicting entries (e.g., "10Gi").
+    pub max_size: Option<String>,
+    /// Which entries to evict first.
+    pub eviction: Option<Eviction>,
+}
+
+impl VolumeOptions {
+    /// Sets the volume size.
+    #[must_use]
+    pub fn with_size(mut self, size: impl Into<String>) -> Self {
+        self.size = Some(size.into());
+        self
+    }
+
+    /// Sets the size to keep the contents under.
+    #[must_use]
+    pub fn with_max_size(mut self, max_size: impl Into<String>) -> Self {
+        self.max_size = Some(max_size.into());
+        self
+    }
+
+    /// Sets which entries to evict first.
+    #[must_use]
+    pub fn with_eviction(mut self, eviction: Eviction) -> Self {
+        self.eviction = Some(eviction);
+        self
+    }
 }
 
 /// Optional capability: Manage volumes and artifacts.
@@ -460,6 +894,175 @@ impl Secrets for EnvSecrets {
     }
 }
 
+/// Resolves secrets by reading the file named by the secret key.
+///
+/// A single trailing newline is stripped, matching how most secret
+/// managers write files.
+#[derive(Debug, Clone, Default)]
+pub struct FileSecrets;
+
+impl Secrets for FileSecrets {
+    fn resolve_secret(&self, name: &str) -> std::result::Result<String, Error> {
+        let mut value = std::fs::read_to_string(name)
+            .map_err(|e| Error::with_source(format!("secret file not readable: {}", name), e))?;
+        if value.ends_with('\n') {
+            value.pop();
+            if value.ends_with('\r') {
+                value.pop();
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Why [`SopsSecrets`] couldn't resolve a secret. It is the
+/// [`source`](StdError::source) of the [`Error`] `resolve_secret` returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SopsError {
+    /// The encrypted file doesn't exist.
+    FileNotFound {
+        /// Path of the file.
+        path: String,
+    },
+    /// `sops -d` couldn't be started, exited with an error or printed
+    /// something other than JSON.
+    SopsFailed {
+        /// Path of the file.
+        path: String,
+        /// What went wrong, e.g. sops' stderr.
+        message: String,
+    },
+    /// The decrypted document has no value at the key.
+    KeyMissing {
+        /// Path of the file.
+        path: String,
+        /// The dotted key.
+        key: String,
+    },
+}
+
+impl fmt::Display for SopsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SopsError::FileNotFound { path } => write!(f, "sops file not found: {}", path),
+            SopsError::SopsFailed { path, message } => {
+                write!(f, "sops failed to decrypt {}: {}", path, message)
+            }
+            SopsError::KeyMissing { path, key } => write!(f, "key {:?} missing in {}", key, path),
+        }
+    }
+}
+
+impl StdError for SopsError {}
+
+/// Resolves [`SecretRef::from_sops`] keys (`path#dotted.key`) by running
+/// `sops -d` on the file.
+///
+/// Each file is decrypted once per `SopsSecrets`, so keep one for the whole
+/// run. Strings are returned as is, other scalars as JSON text.
+#[derive(Debug)]
+pub struct SopsSecrets {
+    binary: String,
+    documents: Mutex<HashMap<String, serde_json::Value>>,
+}
+
+impl Default for SopsSecrets {
+    fn default() -> Self {
+        Self::with_binary("sops")
+    }
+}
+
+impl SopsSecrets {
+    /// Uses the `sops` binary on `PATH`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses the given `sops` binary instead of the one on `PATH`.
+    pub fn with_binary(binary: &str) -> Self {
+        Self {
+            binary: binary.to_string(),
+            documents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Looks up `key` in the decrypted `path`, decrypting it on first use.
+    ///
+    /// # Errors
+    /// Returns which of the [`SopsError`] cases applies.
+    pub fn resolve(&self, path: &str, key: &str) -> std::result::Result<String, SopsError> {
+        let mut documents = self.documents.lock().unwrap_or_else(|e| e.into_inner());
+        if !documents.contains_key(path) {
+            let document = self.decrypt(path)?;
+            documents.insert(path.to_string(), document);
+        }
+        let mut value = &documents[path];
+        for segment in key.split('.') {
+            let next = match value {
+                serde_json::Value::Object(map) => map.get(segment),
+                serde_json::Value::Array(items) => {
+                    segment.parse::<usize>().ok().and_then(|i| items.get(i))
+                }
+                _ => None,
+            };
+            value = match next {
+                Some(v) if !v.is_null() => v,
+                _ => {
+                    return Err(SopsError::KeyMissing {
+                        path: path.to_string(),
+                        key: key.to_string(),
+                    })
+                }
+            };
+        }
+        Ok(match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+    }
+
+    fn decrypt(&self, path: &str) -> std::result::Result<serde_json::Value, SopsError> {
+        if !std::path::Path::new(path).is_file() {
+            return Err(SopsError::FileNotFound {
+                path: path.to_string(),
+            });
+        }
+        let failed = |message: String| SopsError::SopsFailed {
+            path: path.to_string(),
+            message,
+        };
+        let output = Command::new(&self.binary)
+            .args(["-d", "--output-type", "json", path])
+            .output()
+            .map_err(|e| failed(format!("can't run {}: {}", self.binary, e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(failed(match stderr.trim() {
+                "" => output.status.to_string(),
+                stderr => stderr.to_string(),
+            }));
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| failed(format!("output isn't JSON: {}", e)))
+    }
+}
+
+impl Secrets for SopsSecrets {
+    fn resolve_secret(&self, name: &str) -> std::result::Result<String, Error> {
+        let Some((path, key)) = name
+            .split_once('#')
+            .filter(|_| SecretSource::Sops.check_key(name).is_ok())
+        else {
+            return Err(Error::new(format!(
+                "invalid sops secret reference {:?} (expected path#key)",
+                name
+            )));
+        };
+        self.resolve(path, key)
+            .map_err(|e| Error::with_source(e.to_string(), e))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -480,6 +1083,51 @@ mod tests {
         assert!(result.success);
     }
 
+    #[test]
+    fn test_task_spec_argv_from_exec() {
+        let mut p = crate::Pipeline::new();
+        let _ = p
+            .task("test")
+            .exec(&["cargo", "test", "--features", "foo bar"]);
+        let _ = p.task("lint").run("cargo clippy");
+
+        let specs: Vec<TaskSpec> = p
+            .iter_tasks()
+            .map(|t| {
+                let mut spec = TaskSpec::new(t.name(), t.command().unwrap_or_default());
+                spec.args = t.args().to_vec();
+                spec
+            })
+            .collect();
+        assert_eq!(specs[0].argv(), ["cargo", "test", "--features", "foo bar"]);
+        assert_eq!(specs[1].argv(), ["sh", "-c", "cargo clippy"]);
+    }
+
+    struct NoDaemonTarget;
+
+    impl Target for NoDaemonTarget {
+        fn run_task(&self, _task: &TaskSpec) -> Result {
+            Result::error("unreachable")
+        }
+
+        fn health_check(&self) -> std::result::Result<(), Error> {
+            Err(Error::new("cannot connect to the Docker daemon"))
+        }
+    }
+
+    #[test]
+    fn test_health_check() {
+        assert!(TestTarget.health_check().is_ok());
+
+        let targets: Vec<Box<dyn Target>> = vec![Box::new(TestTarget), Box::new(NoDaemonTarget)];
+        let unavailable: Vec<_> = targets
+            .iter()
+            .filter_map(|t| t.health_check().err())
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(unavailable, ["cannot connect to the Docker daemon"]);
+    }
+
     #[test]
     fn test_env_secrets() {
         std::env::set_var("TEST_SECRET", "secret_value");
@@ -510,4 +1158,358 @@ mod tests {
         assert_eq!(result.exit_code, 1);
         assert_eq!(result.output, "error output");
     }
+
+    /// Renders secret templates the way an executor would: into a scratch
+    /// directory that's removed once the task has run.
+    struct TemplateTarget {
+        scratch: std::path::PathBuf,
+    }
+
+    impl Target for TemplateTarget {
+        fn run_task(&self, task: &TaskSpec) -> Result {
+            std::fs::create_dir_all(&self.scratch).unwrap();
+            for st in &task.secret_templates {
+                let rendered = match st.render(&FileSecrets) {
+                    Ok(r) => r,
+                    Err(e) => return Result::error(e.to_string()),
+                };
+                let file = self
+                    .scratch
+                    .join(st.dest.trim_start_matches('/').replace('/', "_"));
+                std::fs::write(&file, rendered).unwrap();
+            }
+            let output = std::fs::read_dir(&self.scratch)
+                .unwrap()
+                .map(|e| std::fs::read_to_string(e.unwrap().path()).unwrap())
+                .collect::<String>();
+            std::fs::remove_dir_all(&self.scratch).unwrap();
+            Result::success_with_output(output, Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn test_secret_template_round_trip_with_file_secrets() {
+        let dir = std::env::temp_dir().join(format!("sykli-secrets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("key_id");
+        let secret_file = dir.join("secret");
+        std::fs::write(&key_file, "AKIA123\n").unwrap();
+        std::fs::write(&secret_file, "s3cr3t").unwrap();
+
+        let mut task = TaskSpec::new("plan", "terraform plan");
+        task.secret_templates.push(SecretTemplateSpec {
+            dest: "/run/secrets/aws".to_string(),
+            template: "[default]\nid = {{key_id}}\nkey = {{ secret }}\n".to_string(),
+            refs: vec![
+                SecretRef {
+                    name: "key_id".to_string(),
+                    ..SecretRef::from_file(key_file.to_str().unwrap())
+                },
+                SecretRef {
+                    name: "secret".to_string(),
+                    ..SecretRef::from_file(secret_file.to_str().unwrap())
+                },
+            ],
+        });
+
+        let target = TemplateTarget {
+            scratch: dir.join("scratch"),
+        };
+        let result = target.run_task(&task);
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.output, "[default]\nid = AKIA123\nkey = s3cr3t\n");
+        assert!(!target.scratch.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sops_secrets_with_fake_binary() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("sykli-sops-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // Prints the file as JSON (the fixtures are already JSON) and counts
+        // the calls; files named bad* fail like a missing key would.
+        let sops = dir.join("sops");
+        std::fs::write(
+            &sops,
+            "#!/bin/sh\n\
+             for f; do :; done\n\
+             echo \"$f\" >> \"$(dirname \"$0\")/calls\"\n\
+             case \"$f\" in */bad*) echo 'Failed to get the data key' >&2; exit 128 ;; esac\n\
+             cat \"$f\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&sops, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let secrets_file = dir.join("secrets.enc.yaml");
+        std::fs::write(
+            &secrets_file,
+            r#"{"deploy": {"token": "t0k3n", "port": 5432, "hosts": ["a", "b"]}}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("bad.enc.yaml"), "{}").unwrap();
+        let file = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        let secrets = SopsSecrets::with_binary(sops.to_str().unwrap());
+        let resolve =
+            |key: &str| secrets.resolve_secret(&format!("{}#{}", file("secrets.enc.yaml"), key));
+        assert_eq!(resolve("deploy.token").unwrap(), "t0k3n");
+        assert_eq!(resolve("deploy.port").unwrap(), "5432");
+        assert_eq!(resolve("deploy.hosts.1").unwrap(), "b");
+
+        // Decrypted once, however many keys are read.
+        let calls = std::fs::read_to_string(dir.join("calls")).unwrap();
+        assert_eq!(calls.lines().count(), 1);
+
+        let sops_error = |e: Error| e.source().unwrap().downcast_ref::<SopsError>().cloned();
+        assert_eq!(
+            sops_error(resolve("deploy.password").unwrap_err()),
+            Some(SopsError::KeyMissing {
+                path: file("secrets.enc.yaml"),
+                key: "deploy.password".to_string(),
+            })
+        );
+        assert_eq!(
+            sops_error(
+                secrets
+                    .resolve_secret(&format!("{}#token", file("missing.yaml")))
+                    .unwrap_err()
+            ),
+            Some(SopsError::FileNotFound {
+                path: file("missing.yaml"),
+            })
+        );
+        let err = secrets
+            .resolve_secret(&format!("{}#token", file("bad.enc.yaml")))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "sops failed to decrypt {}: Failed to get the data key",
+                file("bad.enc.yaml")
+            )
+        );
+        assert!(matches!(
+            sops_error(err),
+            Some(SopsError::SopsFailed { .. })
+        ));
+        assert!(secrets.resolve_secret("no-key").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_secret_template_missing_secret() {
+        let st = SecretTemplateSpec {
+            dest: "/run/secrets/token".to_string(),
+            template: "{{token}}".to_string(),
+            refs: vec![SecretRef {
+                name: "token".to_string(),
+                ..SecretRef::from_file("/nonexistent/sykli/token")
+            }],
+        };
+        let err = st.render(&FileSecrets).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/sykli/token"));
+    }
+
+    #[test]
+    fn test_secret_files_from_view() {
+        let mut p = crate::Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("kubectl apply -f k8s/")
+            .secret_from("TOKEN", SecretRef::from_env("DEPLOY_TOKEN"))
+            .secret_file(
+                "KUBECONFIG_CONTENT",
+                SecretRef::from_vault("secret/ci#kubeconfig"),
+                "/secrets/kubeconfig",
+            );
+
+        let t = p.iter_tasks().next().unwrap();
+        let mut spec = TaskSpec::new(t.name(), t.command().unwrap_or_default());
+        spec.secret_files = t
+            .secret_refs()
+            .iter()
+            .filter_map(SecretFileSpec::from_ref)
+            .collect();
+        assert_eq!(spec.secret_files.len(), 1);
+        assert_eq!(spec.secret_files[0].path, "/secrets/kubeconfig");
+        assert_eq!(spec.secret_files[0].secret.name, "KUBECONFIG_CONTENT");
+        assert_eq!(spec.secret_files[0].secret.key, "secret/ci#kubeconfig");
+    }
+
+    #[test]
+    fn test_image_build_docker_args() {
+        let build = ImageBuildSpec {
+            context: "ci".to_string(),
+            dockerfile: "e2e.Dockerfile".to_string(),
+        };
+        assert_eq!(
+            build.docker_build_args("sykli-e2e:3f2a"),
+            [
+                "build",
+                "-q",
+                "-f",
+                "ci/e2e.Dockerfile",
+                "-t",
+                "sykli-e2e:3f2a",
+                "ci"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_result_image_digest() {
+        assert_eq!(Result::success().image_digest, None);
+
+        let result = Result::success().with_image_digest("sha256:abc123");
+        assert_eq!(result.image_digest.as_deref(), Some("sha256:abc123"));
+        assert!(result.success);
+    }
+
+    /// Uploads with a plain HTTP/1.1 PUT, the way a runner-side publisher would.
+    struct HttpPutPublisher {
+        vars: HashMap<String, String>,
+    }
+
+    impl Publisher for HttpPutPublisher {
+        fn publish(&self, _task: &str, spec: &PublishSpec) -> std::result::Result<(), Error> {
+            use std::io::{Read, Write};
+
+            let PublishDest::HttpPut { url_template, auth } = &spec.dest else {
+                return Err(Error::new("only http_put is supported"));
+            };
+            let url = spec.expand(url_template, &self.vars)?;
+            let rest = url.strip_prefix("http://").unwrap();
+            let (host, path) = rest.split_at(rest.find('/').unwrap());
+            let body = std::fs::read(&spec.path).map_err(|e| Error::with_source("read", e))?;
+
+            let mut req = format!(
+                "PUT {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+                path,
+                host,
+                body.len()
+            );
+            if let Some(auth) = auth {
+                req += &format!(
+                    "Authorization: Bearer {}\r\n",
+                    FileSecrets.resolve_secret(&auth.key)?
+                );
+            }
+            req += "\r\n";
+
+            let mut stream =
+                std::net::TcpStream::connect(host).map_err(|e| Error::with_source("connect", e))?;
+            stream.write_all(req.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            if response.starts_with("HTTP/1.1 2") {
+                Ok(())
+            } else {
+                Err(Error::new(format!(
+                    "upload failed: {}",
+                    response.lines().next().unwrap_or("")
+                )))
+            }
+        }
+    }
+
+    #[test]
+    fn test_http_put_publisher_against_local_server() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !String::from_utf8_lossy(&request).ends_with("hello") {
+                let n = conn.read(&mut buf).unwrap();
+                assert!(n > 0, "connection closed early");
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let dir = std::env::temp_dir().join(format!("sykli-publish-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let artifact = dir.join("app.tar.gz");
+        let token = dir.join("token");
+        std::fs::write(&artifact, "hello").unwrap();
+        std::fs::write(&token, "t0ken\n").unwrap();
+
+        let spec = PublishSpec {
+            output: "bundle".to_string(),
+            path: artifact.to_str().unwrap().to_string(),
+            dest: PublishDest::HttpPut {
+                url_template: format!("http://{}/{{branch}}/{{file}}", addr),
+                auth: Some(SecretRef::from_file(token.to_str().unwrap())),
+            },
+        };
+        let publisher = HttpPutPublisher {
+            vars: HashMap::from([("branch".to_string(), "main".to_string())]),
+        };
+        publisher.publish("package", &spec).unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("PUT /main/app.tar.gz HTTP/1.1\r\n"));
+        assert!(request.contains("Authorization: Bearer t0ken\r\n"));
+        assert!(request.ends_with("\r\n\r\nhello"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_publish_spec_expand() {
+        let spec = PublishSpec {
+            output: "binary".to_string(),
+            path: "target/release/app".to_string(),
+            dest: PublishDest::HttpPut {
+                url_template: String::new(),
+                auth: None,
+            },
+        };
+        let vars = HashMap::from([("tag".to_string(), "v1.2.0".to_string())]);
+        assert_eq!(
+            spec.expand("https://dl.example.com/{tag}/{output}/{file}", &vars)
+                .unwrap(),
+            "https://dl.example.com/v1.2.0/binary/app"
+        );
+        let err = spec.expand("{commit}", &vars).unwrap_err();
+        assert_eq!(err.to_string(), "publish binary: {commit} not set");
+    }
+
+    #[test]
+    fn test_output_tail_keeps_last_lines_and_redacts() {
+        let mut tail = OutputTail::new(OutputTail::DEFAULT_LINES);
+        for i in 0..200 {
+            // Arrive in uneven chunks, like a streamed pipe
+            tail.push(&format!("line {} token=hunter2", i));
+            tail.push("\n");
+        }
+
+        let lines = tail.lines(&["hunter2"]);
+        assert_eq!(lines.len(), 50);
+        assert_eq!(lines[0], "line 150 token=***");
+        assert_eq!(lines[49], "line 199 token=***");
+        assert!(lines.iter().all(|l| !l.contains("hunter2")));
+    }
+
+    #[test]
+    fn test_output_tail_includes_unterminated_line() {
+        let mut tail = OutputTail::new(2);
+        tail.push("a\r\nb\nerror: c");
+        assert_eq!(tail.lines(&[]), ["b", "error: c"]);
+
+        let mut none = OutputTail::new(0);
+        none.push("x\ny");
+        assert!(none.lines(&[]).is_empty());
+    }
 }
@@ -0,0 +1,2859 @@
+//! Emit-time validation, reporting every problem at once.
+//!
+//! [`Pipeline::validate`] runs the same checks as `emit_to` without stopping
+//! at the first failure, so a broken pipeline can be fixed in one pass.
+//! [`Pipeline::emit_to_checked`] reports them as a typed [`EmitError`]:
+//!
+//! ```rust
+//! use sykli::validate::Severity;
+//! use sykli::Pipeline;
+//!
+//! let mut p = Pipeline::new();
+//! p.task("lint");
+//! p.task("test").run("cargo test").after(&["biuld"]);
+//!
+//! let errors: Vec<_> = p
+//!     .validate()
+//!     .into_iter()
+//!     .filter(|i| i.severity == Severity::Error)
+//!     .map(|i| i.message)
+//!     .collect();
+//! assert_eq!(
+//!     errors,
+//!     [
+//!         r#"task "lint" has no command"#,
+//!         r#"task "test" depends on unknown task "biuld""#,
+//!     ]
+//! );
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::io;
+
+use crate::features::Feature;
+use crate::{
+    dotenv, has_glob_chars, is_git_url, lint, matrix, memory_quantity_error, suggest_task_name,
+    Backoff, K8sOptions, K8sValidationError, NodeKind, OutputKind, Pipeline, SecretNameRule,
+    SecretRef, SecretSource, TaskData,
+};
+
+/// How much a validation issue matters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Likely a mistake, but the pipeline still emits. These are the
+    /// pipeline's [lints](crate::lint) at warning level.
+    Warning,
+    /// The pipeline can't be emitted.
+    Error,
+}
+
+/// One problem found by [`Pipeline::validate`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    /// Whether it blocks emit.
+    pub severity: Severity,
+    /// Task it is about, if it concerns a single task.
+    pub task: Option<String>,
+    /// Setting at fault, e.g. `"depends_on"`, `"when"` or `"k8s.memory"`.
+    pub field: String,
+    /// What's wrong, naming the task.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Why a pipeline can't be emitted.
+///
+/// `Display` gives the same messages `emit_to` reports in its `io::Error`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EmitError {
+    /// More tasks than [`Pipeline::max_tasks`](crate::Pipeline::max_tasks) allows.
+    TooManyTasks {
+        /// Number of tasks.
+        count: usize,
+        /// The limit.
+        limit: usize,
+    },
+    /// [`Pipeline::max_parallel`](crate::Pipeline::max_parallel) is 0, so
+    /// no task could start.
+    ZeroMaxParallel,
+    /// A review node without a primitive.
+    MissingPrimitive {
+        /// Review name.
+        review: String,
+    },
+    /// A task without a command.
+    MissingCommand {
+        /// Task name.
+        task: String,
+    },
+    /// A task depends on a handle from another pipeline.
+    ForeignDependency {
+        /// Task name.
+        task: String,
+        /// Name of the task the handle points to.
+        dep: String,
+    },
+    /// A task depends on a task that doesn't exist.
+    UnknownDependency {
+        /// Task name.
+        task: String,
+        /// The missing dependency.
+        dep: String,
+        /// Closest existing task name, if any is close.
+        suggestion: Option<String>,
+    },
+    /// A task has both a `run()` command and steps.
+    CommandAndSteps {
+        /// Task name.
+        task: String,
+    },
+    /// A task has `exec()` args and also a `run()` command or steps.
+    ExecAndCommand {
+        /// Task name.
+        task: String,
+        /// `"run()"` or `"step()"`.
+        other: &'static str,
+    },
+    /// `input_from` names an output the producing task doesn't declare.
+    UnknownInput {
+        /// Task name.
+        task: String,
+        /// Producing task.
+        from_task: String,
+        /// The missing output.
+        output: String,
+        /// Outputs the producer does declare, sorted.
+        declared: Vec<String>,
+        /// Closest output the producer declares, if any is close.
+        suggestion: Option<String>,
+    },
+    /// `input_from` names a task that doesn't exist.
+    UnknownInputTask {
+        /// Task name.
+        task: String,
+        /// The missing producer.
+        from_task: String,
+        /// Output read from it.
+        output: String,
+        /// Closest existing task name, if any is close.
+        suggestion: Option<String>,
+    },
+    /// `inputs_from_all` names a task that declares no outputs.
+    NoOutputs {
+        /// Task name.
+        task: String,
+        /// The producer without outputs.
+        from_task: String,
+    },
+    /// A task running in a container reads an artifact into a relative
+    /// path, which has no fixed place in the container.
+    RelativeInputPath {
+        /// Task name.
+        task: String,
+        /// Producing task.
+        from_task: String,
+        /// Output read from it.
+        output: String,
+        /// The relative destination.
+        path: String,
+    },
+    /// `input_from` reads a glob output into a path without a trailing `/`;
+    /// the matches can only land in a directory.
+    GlobInputNotDirectory {
+        /// Task name.
+        task: String,
+        /// Producing task.
+        from_task: String,
+        /// The glob output read.
+        output: String,
+        /// The destination.
+        path: String,
+    },
+    /// An output's `retention_days` is 0, so it would be deleted at once.
+    ZeroRetention {
+        /// Task name.
+        task: String,
+        /// The output.
+        output: String,
+    },
+    /// Two mounts of a task share a container path, so one hides the other.
+    MountConflict {
+        /// Task name.
+        task: String,
+        /// The shared container path.
+        path: String,
+        /// Resource ID mounted there first.
+        first: String,
+        /// Resource ID mounted there again.
+        second: String,
+    },
+    /// A task runs in a container but its workdir is relative, which only
+    /// tasks running on the host may use.
+    RelativeContainerWorkdir {
+        /// Task name.
+        task: String,
+        /// The workdir.
+        workdir: String,
+    },
+    /// A task runs in a container with directory mounts, but its workdir is
+    /// outside all of its mounts.
+    UnmountedWorkdir {
+        /// Task name.
+        task: String,
+        /// The workdir.
+        workdir: String,
+    },
+    /// A secret file's path is relative.
+    RelativeSecretFile {
+        /// Task name.
+        task: String,
+        /// The secret's name.
+        name: String,
+        /// The path.
+        path: String,
+    },
+    /// A secret file's path is already used by another secret file, secret
+    /// template or mount of the task.
+    SecretFileConflict {
+        /// Task name.
+        task: String,
+        /// The secret's name.
+        name: String,
+        /// The shared path.
+        path: String,
+        /// What uses it first, e.g. `secret file "TLS_KEY"`.
+        other: String,
+    },
+    /// A declared directory or cache is never mounted, in
+    /// [strict](Pipeline::strict) mode.
+    UnusedResource {
+        /// `"directory"`, `"cache"` or `"file"`.
+        kind: &'static str,
+        /// Its resource ID.
+        id: String,
+    },
+    /// A git resource doesn't set exactly one of branch, tag or commit.
+    InvalidGitRef {
+        /// Resource ID.
+        id: String,
+        /// The ones it sets, e.g. `["branch", "tag"]`.
+        set: Vec<&'static str>,
+    },
+    /// A git resource's URL is neither https nor ssh.
+    InvalidGitUrl {
+        /// Resource ID.
+        id: String,
+        /// The URL.
+        url: String,
+    },
+    /// An http resource's URL isn't `http://` or `https://`.
+    InvalidHttpUrl {
+        /// Resource ID.
+        id: String,
+        /// The URL.
+        url: String,
+    },
+    /// An http resource has no sha256, in [strict](Pipeline::strict) mode.
+    MissingSha256 {
+        /// Resource ID.
+        id: String,
+    },
+    /// A file resource's path has glob characters.
+    FileGlob {
+        /// Resource ID.
+        id: String,
+        /// The path.
+        path: String,
+    },
+    /// A cache's `max_size` isn't a quantity like `10Gi`.
+    InvalidCacheSize {
+        /// Cache name.
+        cache: String,
+        /// The size given.
+        value: String,
+        /// What's wrong, with a suggestion for common unit mistakes.
+        message: String,
+    },
+    /// A cache's `key_files` has an empty pattern.
+    EmptyCacheKeyFile {
+        /// Cache name.
+        cache: String,
+    },
+    /// A cache is mounted with different key files, scopes or limits.
+    CacheSettingsConflict {
+        /// Cache name.
+        cache: String,
+        /// The settings it was first mounted with.
+        first: String,
+        /// The conflicting one.
+        second: String,
+    },
+    /// Tasks mount filtered views of one directory under one ID, so only one
+    /// set of globs could be emitted for it.
+    DirectoryGlobConflict {
+        /// Resource ID, e.g. `src:.`.
+        id: String,
+        /// The globs it was first mounted with.
+        first: Vec<String>,
+        /// The conflicting ones.
+        second: Vec<String>,
+    },
+    /// A secret name doesn't follow the pipeline's
+    /// [`SecretNameRule`](crate::SecretNameRule), when one is set.
+    InvalidSecretName {
+        /// Task name.
+        task: String,
+        /// The secret name.
+        name: String,
+        /// Pattern it should match.
+        pattern: &'static str,
+    },
+    /// A secret's key isn't in its source's format.
+    InvalidSecretKey {
+        /// Task name.
+        task: String,
+        /// The secret name.
+        name: String,
+        /// Source, e.g. `vault`.
+        source: &'static str,
+        /// The key.
+        key: String,
+        /// The format expected.
+        expected: String,
+    },
+    /// A task declares the same secret name twice with different sources,
+    /// keys or options.
+    SecretSourceConflict {
+        /// Task name.
+        task: String,
+        /// The secret name.
+        name: String,
+        /// The first declaration, e.g. `env:GITHUB_TOKEN`.
+        first: String,
+        /// The conflicting one.
+        second: String,
+    },
+    /// A task's env key is also one of its matrix dimensions, which the
+    /// engine sets for each variant.
+    EnvMatrixCollision {
+        /// Task name.
+        task: String,
+        /// The env key.
+        key: String,
+    },
+    /// A task's env key is also the name of one of its secrets.
+    EnvSecretCollision {
+        /// Task name.
+        task: String,
+        /// The env key.
+        key: String,
+    },
+    /// A task's env key starts with a prefix reserved for the engine, see
+    /// [`Pipeline::reserve_env_prefix`].
+    ReservedEnvPrefix {
+        /// Task name.
+        task: String,
+        /// The env key.
+        key: String,
+        /// The reserved prefix it starts with.
+        prefix: String,
+    },
+    /// A file from [`Task::env_file`](crate::Task::env_file) is missing or
+    /// malformed.
+    EnvFile {
+        /// Task name.
+        task: String,
+        /// The file's path.
+        path: String,
+        /// Why it couldn't be read, e.g. `line 3: expected KEY=value`.
+        error: String,
+    },
+    /// A [`matrix_exclude`](crate::Task::matrix_exclude) or
+    /// [`matrix_include`](crate::Task::matrix_include) entry names a key that
+    /// isn't a matrix dimension.
+    UnknownMatrixKey {
+        /// Task name.
+        task: String,
+        /// `"matrix_exclude"` or `"matrix_include"`.
+        list: &'static str,
+        /// The key.
+        key: String,
+    },
+    /// A [`matrix_exclude`](crate::Task::matrix_exclude) entry has a value
+    /// that isn't in its dimension's list, so it excludes nothing.
+    UnknownMatrixValue {
+        /// Task name.
+        task: String,
+        /// The dimension.
+        key: String,
+        /// The value.
+        value: String,
+    },
+    /// A [`matrix_include`](crate::Task::matrix_include) entry doesn't set
+    /// one of the matrix dimensions.
+    IncompleteMatrixInclude {
+        /// Task name.
+        task: String,
+        /// The dimension it leaves out.
+        missing: String,
+    },
+    /// A `{{matrix.<key>}}` placeholder names a key that isn't one of the
+    /// task's matrix dimensions.
+    UnknownMatrixPlaceholder {
+        /// Task name.
+        task: String,
+        /// Where it is: `"command"`, `"container"`, `"workdir"` or `"env"`.
+        field: &'static str,
+        /// The key.
+        key: String,
+        /// The task's dimensions.
+        dimensions: Vec<String>,
+    },
+    /// A matrix variant selector from [`Task::after_matrix`](crate::Task::after_matrix)
+    /// or [`Task::input_from_matrix`](crate::Task::input_from_matrix) names a
+    /// key that isn't one of the producer's dimensions.
+    UnknownSelectorKey {
+        /// Task name.
+        task: String,
+        /// The matrix task it selects variants of.
+        dep: String,
+        /// The key.
+        key: String,
+        /// The producer's dimensions.
+        dimensions: Vec<String>,
+    },
+    /// A matrix variant selector has a value the producer's dimension
+    /// doesn't list.
+    UnknownSelectorValue {
+        /// Task name.
+        task: String,
+        /// The matrix task it selects variants of.
+        dep: String,
+        /// The dimension.
+        key: String,
+        /// The value.
+        value: String,
+        /// The dimension's values, including ones from includes.
+        values: Vec<String>,
+    },
+    /// A matrix variant selector matches no variant, e.g. because they were
+    /// excluded.
+    EmptyMatrixSelector {
+        /// Task name.
+        task: String,
+        /// The matrix task it selects variants of.
+        dep: String,
+        /// The selector, e.g. `os=linux`.
+        selector: String,
+    },
+    /// [`Task::input_from_matrix`](crate::Task::input_from_matrix) matches
+    /// more than one variant, so it's unclear whose output to read.
+    AmbiguousMatrixInput {
+        /// Task name.
+        task: String,
+        /// The matrix task.
+        from_task: String,
+        /// The selector, e.g. `os=linux`.
+        selector: String,
+        /// How many variants it matches.
+        matches: usize,
+    },
+    /// A service has more than one readiness probe.
+    MultipleReadinessProbes {
+        /// Task name.
+        task: String,
+        /// Service name.
+        service: String,
+        /// The probes, e.g. `tcp:5432`.
+        probes: Vec<String>,
+    },
+    /// A service's readiness timeout is zero, or set without a probe.
+    InvalidReadyTimeout {
+        /// Task name.
+        task: String,
+        /// Service name.
+        service: String,
+        /// The timeout in seconds.
+        secs: u32,
+    },
+    /// A retry policy with a `max` of 0.
+    ZeroRetries {
+        /// Task name.
+        task: String,
+    },
+    /// An exponential backoff whose `max_secs` is below its `base_secs`.
+    InvalidBackoff {
+        /// Task name.
+        task: String,
+        /// Wait before the first retry, in seconds.
+        base_secs: u32,
+        /// Longest wait, in seconds.
+        max_secs: u32,
+    },
+    /// `publish_output` names an output the task doesn't declare.
+    UnknownPublishedOutput {
+        /// Task name.
+        task: String,
+        /// The missing output.
+        output: String,
+    },
+    /// A `when()` or `unless()` string that doesn't parse or uses an
+    /// unknown identifier.
+    InvalidCondition {
+        /// Task name.
+        task: String,
+        /// `"when"` or `"unless"`.
+        field: &'static str,
+        /// The condition as written.
+        condition: String,
+        /// What's wrong with it.
+        message: String,
+    },
+    /// A task that always runs depends on a manual task.
+    ManualDependency {
+        /// Task name.
+        task: String,
+        /// The manual task.
+        dep: String,
+    },
+    /// The dependency graph has a cycle.
+    Cycle {
+        /// Task names around the cycle, ending with the first.
+        path: Vec<String>,
+    },
+    /// A task's merged K8s options are invalid.
+    K8s {
+        /// Task name.
+        task: String,
+        /// The first problem found.
+        error: K8sValidationError,
+    },
+    /// Pipeline K8s defaults are invalid.
+    K8sDefaults {
+        /// The problem.
+        error: K8sValidationError,
+    },
+    /// A feature needs a newer runner than
+    /// [`Pipeline::max_runner_version`](crate::Pipeline::max_runner_version).
+    RunnerVersion {
+        /// Task using it, or `None` for pipeline-level features.
+        task: Option<String>,
+        /// The feature.
+        feature: Feature,
+        /// The configured maximum runner version.
+        max: (u32, u32, u32),
+    },
+    /// More than one of the above, in check order.
+    Multiple(Vec<EmitError>),
+    /// Serializing or writing the JSON failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmitError::TooManyTasks { count, limit } => write!(
+                f,
+                "pipeline has {} tasks, exceeding max_tasks limit of {}",
+                count, limit
+            ),
+            EmitError::ZeroMaxParallel => {
+                write!(f, "max_parallel is 0, so no task could run (use at least 1)")
+            }
+            EmitError::MissingPrimitive { review } => {
+                write!(f, "review {:?} has no primitive", review)
+            }
+            EmitError::MissingCommand { task } => write!(f, "task {:?} has no command", task),
+            EmitError::ExecAndCommand { task, other } => write!(
+                f,
+                "task {:?} uses both exec() and {} (use one of them)",
+                task, other
+            ),
+            EmitError::CommandAndSteps { task } => write!(
+                f,
+                "task {:?} has both a run() command and steps (use one of them)",
+                task
+            ),
+            EmitError::ForeignDependency { task, dep } => write!(
+                f,
+                "task {:?} depends on a handle to {:?} from another pipeline",
+                task, dep
+            ),
+            EmitError::UnknownDependency {
+                task,
+                dep,
+                suggestion,
+            } => {
+                write!(f, "task {:?} depends on unknown task {:?}", task, dep)?;
+                if let Some(s) = suggestion {
+                    write!(f, " (did you mean {:?}?)", s)?;
+                }
+                Ok(())
+            }
+            EmitError::UnknownInput {
+                task,
+                from_task,
+                output,
+                declared,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "task {:?} reads unknown output {:?} of task {:?}, which declares ",
+                    task, output, from_task
+                )?;
+                if declared.is_empty() {
+                    write!(f, "no outputs")?;
+                } else {
+                    let declared: Vec<String> =
+                        declared.iter().map(|o| format!("{:?}", o)).collect();
+                    write!(f, "{}", declared.join(", "))?;
+                }
+                match suggestion {
+                    Some(s) => write!(f, " (did you mean {:?}?)", s),
+                    None => write!(f, " (declare it with output() on {:?})", from_task),
+                }
+            }
+            EmitError::UnknownInputTask {
+                task,
+                from_task,
+                output,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "task {:?} reads output {:?} of unknown task {:?}",
+                    task, output, from_task
+                )?;
+                if let Some(s) = suggestion {
+                    write!(f, " (did you mean {:?}?)", s)?;
+                }
+                Ok(())
+            }
+            EmitError::NoOutputs { task, from_task } => write!(
+                f,
+                "task {:?} reads all outputs of task {:?}, which declares none (declare them with output() or drop inputs_from_all())",
+                task, from_task
+            ),
+            EmitError::RelativeInputPath {
+                task,
+                from_task,
+                output,
+                path,
+            } => write!(
+                f,
+                "task {:?} runs in a container but reads output {:?} of task {:?} into relative path {:?} (use an absolute path)",
+                task, output, from_task, path
+            ),
+            EmitError::GlobInputNotDirectory {
+                task,
+                from_task,
+                output,
+                path,
+            } => write!(
+                f,
+                "task {:?} reads glob output {:?} of task {:?} into {:?}, which must be a directory (end it with '/')",
+                task, output, from_task, path
+            ),
+            EmitError::ZeroRetention { task, output } => write!(
+                f,
+                "output {:?} of task {:?} has retention_days 0, so it would be deleted at once (use None for the store's default)",
+                output, task
+            ),
+            EmitError::MountConflict {
+                task,
+                path,
+                first,
+                second,
+            } if first == second => {
+                write!(f, "task {:?} mounts {:?} twice at {}", task, first, path)
+            }
+            EmitError::MountConflict {
+                task,
+                path,
+                first,
+                second,
+            } => write!(
+                f,
+                "task {:?} mounts both {:?} and {:?} at {}",
+                task, first, second, path
+            ),
+            EmitError::RelativeContainerWorkdir { task, workdir } => write!(
+                f,
+                "task {:?} runs in a container but has relative workdir {:?} (container workdirs must be absolute; relative ones are for tasks on the host)",
+                task, workdir
+            ),
+            EmitError::UnmountedWorkdir { task, workdir } => write!(
+                f,
+                "task {:?} has workdir {} outside its mounts (mount a directory there or change workdir())",
+                task, workdir
+            ),
+            EmitError::RelativeSecretFile { task, name, path } => write!(
+                f,
+                "task {:?} writes secret {:?} to relative path {:?} (secret files must be absolute)",
+                task, name, path
+            ),
+            EmitError::SecretFileConflict {
+                task,
+                name,
+                path,
+                other,
+            } => write!(
+                f,
+                "task {:?} writes secret {:?} to {}, which its {} already uses",
+                task, name, path, other
+            ),
+            EmitError::UnusedResource { kind, id } => {
+                f.write_str(&lint::unused_resource_message(kind, id))
+            }
+            EmitError::InvalidGitRef { id, set } if set.is_empty() => write!(
+                f,
+                "git resource {:?} has no ref (set one of branch, tag or commit)",
+                id
+            ),
+            EmitError::InvalidGitRef { id, set } => write!(
+                f,
+                "git resource {:?} sets more than one ref ({}); keep one of branch, tag or commit",
+                id,
+                set.join(", ")
+            ),
+            EmitError::InvalidGitUrl { id, url } => write!(
+                f,
+                "git resource {:?} has URL {:?}, expected https://host/path, ssh://host/path or user@host:path",
+                id, url
+            ),
+            EmitError::InvalidHttpUrl { id, url } => write!(
+                f,
+                "http resource {:?} has URL {:?}, expected http:// or https://",
+                id, url
+            ),
+            EmitError::EmptyCacheKeyFile { cache } => write!(
+                f,
+                "cache {:?} has an empty key_files pattern",
+                cache
+            ),
+            EmitError::CacheSettingsConflict {
+                cache,
+                first,
+                second,
+            } => write!(
+                f,
+                "cache {:?} is mounted with {} and with {} (set key files, scopes and limits once)",
+                cache, first, second
+            ),
+            EmitError::DirectoryGlobConflict { id, first, second } => write!(
+                f,
+                "directory {:?} is mounted with globs {:?} and with {:?} (use dir_named() or hash_dir_globs() to give each view its own ID)",
+                id, first, second
+            ),
+            EmitError::FileGlob { id, path } => write!(
+                f,
+                "file resource {:?} has glob characters in {:?} (mount a directory with glob() for several files)",
+                id, path
+            ),
+            EmitError::InvalidCacheSize {
+                cache,
+                value,
+                message,
+            } => write!(f, "cache {:?} max_size: {} (got {:?})", cache, message, value),
+            EmitError::MissingSha256 { id } => write!(
+                f,
+                "http resource {:?} has no sha256, which strict mode requires (set it with sha256())",
+                id
+            ),
+            EmitError::InvalidSecretName {
+                task,
+                name,
+                pattern,
+            } => write!(
+                f,
+                "task {:?} declares secret {:?}, which doesn't match {} (see Pipeline::secret_name_rule)",
+                task, name, pattern
+            ),
+            EmitError::InvalidSecretKey {
+                task,
+                name,
+                source,
+                key,
+                expected,
+            } => write!(
+                f,
+                "task {:?} reads secret {:?} from {} key {:?}, expected {}",
+                task, name, source, key, expected
+            ),
+            EmitError::SecretSourceConflict {
+                task,
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "task {:?} declares secret {:?} as both {} and {} (keep one)",
+                task, name, first, second
+            ),
+            EmitError::EnvMatrixCollision { task, key } => write!(
+                f,
+                "task {:?} sets env {:?}, which is also a matrix dimension (rename one of them)",
+                task, key
+            ),
+            EmitError::EnvSecretCollision { task, key } => write!(
+                f,
+                "task {:?} sets env {:?}, which is also a secret name (rename one of them)",
+                task, key
+            ),
+            EmitError::ReservedEnvPrefix { task, key, prefix } => write!(
+                f,
+                "task {:?} sets env {:?}, but the {:?} prefix is reserved",
+                task, key, prefix
+            ),
+            EmitError::EnvFile { task, path, error } => {
+                write!(f, "task {:?} can't read env file {:?}: {}", task, path, error)
+            }
+            EmitError::UnknownMatrixKey { task, list, key } => write!(
+                f,
+                "task {:?} has {} key {:?}, which isn't a matrix dimension",
+                task, list, key
+            ),
+            EmitError::UnknownMatrixValue { task, key, value } => write!(
+                f,
+                "task {:?} excludes {}={:?}, which isn't one of the matrix values for {:?}",
+                task, key, value, key
+            ),
+            EmitError::IncompleteMatrixInclude { task, missing } => write!(
+                f,
+                "task {:?} has a matrix_include entry without {:?} (includes must set every dimension)",
+                task, missing
+            ),
+            EmitError::UnknownMatrixPlaceholder {
+                task,
+                field,
+                key,
+                dimensions,
+            } => {
+                write!(
+                    f,
+                    "task {:?} uses {{{{matrix.{}}}}} in its {}, but ",
+                    task, key, field
+                )?;
+                if dimensions.is_empty() {
+                    write!(f, "it has no matrix")
+                } else {
+                    write!(f, "its matrix keys are {}", dimensions.join(", "))
+                }
+            }
+            EmitError::UnknownSelectorKey {
+                task,
+                dep,
+                key,
+                dimensions,
+            } => {
+                write!(
+                    f,
+                    "task {:?} selects variants of {:?} by {:?}, but ",
+                    task, dep, key
+                )?;
+                if dimensions.is_empty() {
+                    write!(f, "{:?} has no matrix", dep)
+                } else {
+                    write!(f, "its matrix keys are {}", dimensions.join(", "))
+                }
+            }
+            EmitError::UnknownSelectorValue {
+                task,
+                dep,
+                key,
+                value,
+                values,
+            } => write!(
+                f,
+                "task {:?} selects {}={:?} of {:?}, which isn't one of its values ({})",
+                task,
+                key,
+                value,
+                dep,
+                values.join(", ")
+            ),
+            EmitError::EmptyMatrixSelector {
+                task,
+                dep,
+                selector,
+            } => write!(
+                f,
+                "task {:?} selects {} of {:?}, but no variant matches",
+                task, selector, dep
+            ),
+            EmitError::AmbiguousMatrixInput {
+                task,
+                from_task,
+                selector,
+                matches,
+            } => write!(
+                f,
+                "task {:?} reads an input from {} of {:?}, which matches {} variants (select exactly one)",
+                task, selector, from_task, matches
+            ),
+            EmitError::MultipleReadinessProbes {
+                task,
+                service,
+                probes,
+            } => write!(
+                f,
+                "task {:?} service {:?} has several readiness probes: {} (keep one)",
+                task,
+                service,
+                probes.join(", ")
+            ),
+            EmitError::InvalidReadyTimeout {
+                task,
+                service,
+                secs: 0,
+            } => write!(
+                f,
+                "task {:?} service {:?} has a readiness timeout of 0 (use at least 1 second)",
+                task, service
+            ),
+            EmitError::InvalidReadyTimeout { task, service, .. } => write!(
+                f,
+                "task {:?} service {:?} sets ready_timeout() without a readiness probe \
+                 (add ready_cmd(), ready_tcp() or ready_http())",
+                task, service
+            ),
+            EmitError::ZeroRetries { task } => write!(
+                f,
+                "task {:?} has a retry policy with max 0 (use at least 1, or drop the policy)",
+                task
+            ),
+            EmitError::InvalidBackoff {
+                task,
+                base_secs,
+                max_secs,
+            } => write!(
+                f,
+                "task {:?} has a backoff capped at {}s, below its {}s base (raise max_secs)",
+                task, max_secs, base_secs
+            ),
+            EmitError::UnknownPublishedOutput { task, output } => write!(
+                f,
+                "task {:?} publishes unknown output {:?} (declare it with output())",
+                task, output
+            ),
+            EmitError::InvalidCondition {
+                task,
+                condition,
+                message,
+                ..
+            } => write!(
+                f,
+                "task {:?}: invalid condition {:?}: {}",
+                task, condition, message
+            ),
+            EmitError::ManualDependency { task, dep } => write!(
+                f,
+                "task {:?} always runs but depends on manual task {:?} (make it manual or give it a condition)",
+                task, dep
+            ),
+            EmitError::Cycle { path } => {
+                write!(f, "dependency cycle detected: {}", path.join(" -> "))
+            }
+            EmitError::K8s { task, error } => write!(f, "task {:?}: {}", task, error),
+            EmitError::K8sDefaults { error } => write!(f, "k8s defaults: {}", error),
+            EmitError::RunnerVersion { task, feature, max } => {
+                match task {
+                    Some(t) => write!(f, "task {:?}", t)?,
+                    None => write!(f, "pipeline")?,
+                }
+                write!(
+                    f,
+                    " uses {}, which requires runner >={} (max_runner_version is {})",
+                    feature.name(),
+                    feature.min_runner_version(),
+                    crate::features::format_version(*max)
+                )
+            }
+            EmitError::Multiple(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+            EmitError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmitError::K8s { error, .. } | EmitError::K8sDefaults { error } => Some(error),
+            EmitError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmitError {
+    fn from(e: io::Error) -> Self {
+        EmitError::Io(e)
+    }
+}
+
+/// Validation failures become `InvalidData` errors carrying the
+/// [`EmitError`], so `io::Error::get_ref` can recover it.
+impl From<EmitError> for io::Error {
+    fn from(e: EmitError) -> Self {
+        match e {
+            EmitError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+impl EmitError {
+    /// The task the error is about, if it concerns a single task.
+    fn task(&self) -> Option<&str> {
+        match self {
+            EmitError::MissingPrimitive { review: task }
+            | EmitError::MissingCommand { task }
+            | EmitError::CommandAndSteps { task }
+            | EmitError::ExecAndCommand { task, .. }
+            | EmitError::ForeignDependency { task, .. }
+            | EmitError::UnknownDependency { task, .. }
+            | EmitError::UnknownInput { task, .. }
+            | EmitError::UnknownInputTask { task, .. }
+            | EmitError::NoOutputs { task, .. }
+            | EmitError::RelativeInputPath { task, .. }
+            | EmitError::GlobInputNotDirectory { task, .. }
+            | EmitError::ZeroRetention { task, .. }
+            | EmitError::MountConflict { task, .. }
+            | EmitError::RelativeContainerWorkdir { task, .. }
+            | EmitError::UnmountedWorkdir { task, .. }
+            | EmitError::RelativeSecretFile { task, .. }
+            | EmitError::SecretFileConflict { task, .. }
+            | EmitError::InvalidSecretName { task, .. }
+            | EmitError::InvalidSecretKey { task, .. }
+            | EmitError::SecretSourceConflict { task, .. }
+            | EmitError::EnvMatrixCollision { task, .. }
+            | EmitError::EnvSecretCollision { task, .. }
+            | EmitError::ReservedEnvPrefix { task, .. }
+            | EmitError::EnvFile { task, .. }
+            | EmitError::UnknownMatrixKey { task, .. }
+            | EmitError::UnknownMatrixValue { task, .. }
+            | EmitError::IncompleteMatrixInclude { task, .. }
+            | EmitError::UnknownMatrixPlaceholder { task, .. }
+            | EmitError::UnknownSelectorKey { task, .. }
+            | EmitError::UnknownSelectorValue { task, .. }
+            | EmitError::EmptyMatrixSelector { task, .. }
+            | EmitError::AmbiguousMatrixInput { task, .. }
+            | EmitError::MultipleReadinessProbes { task, .. }
+            | EmitError::InvalidReadyTimeout { task, .. }
+            | EmitError::ZeroRetries { task }
+            | EmitError::InvalidBackoff { task, .. }
+            | EmitError::UnknownPublishedOutput { task, .. }
+            | EmitError::InvalidCondition { task, .. }
+            | EmitError::ManualDependency { task, .. }
+            | EmitError::K8s { task, .. } => Some(task),
+            EmitError::RunnerVersion { task, .. } => task.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The setting at fault, as reported in [`ValidationIssue::field`].
+    fn field(&self) -> String {
+        match self {
+            EmitError::TooManyTasks { .. } => "max_tasks".to_string(),
+            EmitError::ZeroMaxParallel => "max_parallel".to_string(),
+            EmitError::MissingPrimitive { .. } => "primitive".to_string(),
+            EmitError::MissingCommand { .. }
+            | EmitError::CommandAndSteps { .. }
+            | EmitError::ExecAndCommand { .. } => "command".to_string(),
+            EmitError::UnknownInput { .. }
+            | EmitError::UnknownInputTask { .. }
+            | EmitError::NoOutputs { .. }
+            | EmitError::RelativeInputPath { .. }
+            | EmitError::GlobInputNotDirectory { .. } => "input_from".to_string(),
+            EmitError::ZeroRetention { .. } => "outputs".to_string(),
+            EmitError::MountConflict { .. } => "mounts".to_string(),
+            EmitError::RelativeContainerWorkdir { .. } | EmitError::UnmountedWorkdir { .. } => {
+                "workdir".to_string()
+            }
+            EmitError::InvalidSecretName { .. } => "secrets".to_string(),
+            EmitError::RelativeSecretFile { .. }
+            | EmitError::SecretFileConflict { .. }
+            | EmitError::InvalidSecretKey { .. }
+            | EmitError::SecretSourceConflict { .. } => "secret_refs".to_string(),
+            EmitError::EnvMatrixCollision { .. }
+            | EmitError::EnvSecretCollision { .. }
+            | EmitError::ReservedEnvPrefix { .. } => "env".to_string(),
+            EmitError::EnvFile { .. } => "env_file".to_string(),
+            EmitError::UnknownMatrixKey { list, .. } => list.to_string(),
+            EmitError::UnknownMatrixValue { .. } => "matrix_exclude".to_string(),
+            EmitError::IncompleteMatrixInclude { .. } => "matrix_include".to_string(),
+            EmitError::UnknownMatrixPlaceholder { field, .. } => field.to_string(),
+            EmitError::AmbiguousMatrixInput { .. } => "input_from".to_string(),
+            EmitError::UnusedResource { .. } => lint::LintCode::UnusedResource.as_str().to_string(),
+            EmitError::InvalidGitRef { .. } => "ref".to_string(),
+            EmitError::InvalidGitUrl { .. } | EmitError::InvalidHttpUrl { .. } => "url".to_string(),
+            EmitError::MissingSha256 { .. } => "sha256".to_string(),
+            EmitError::FileGlob { .. } => "path".to_string(),
+            EmitError::EmptyCacheKeyFile { .. } => "key_files".to_string(),
+            EmitError::CacheSettingsConflict { .. } => "resources".to_string(),
+            EmitError::DirectoryGlobConflict { .. } => "globs".to_string(),
+            EmitError::InvalidCacheSize { .. } => "max_size".to_string(),
+            EmitError::MultipleReadinessProbes { .. } | EmitError::InvalidReadyTimeout { .. } => {
+                "services".to_string()
+            }
+            EmitError::ZeroRetries { .. } | EmitError::InvalidBackoff { .. } => "retry".to_string(),
+            EmitError::UnknownPublishedOutput { .. } => "publish".to_string(),
+            EmitError::InvalidCondition { field, .. } => field.to_string(),
+            EmitError::K8s { error, .. } | EmitError::K8sDefaults { error } => {
+                format!("k8s.{}", error.field)
+            }
+            EmitError::RunnerVersion { .. } => "requires".to_string(),
+            _ => "depends_on".to_string(),
+        }
+    }
+}
+
+/// Collects errors from the individual checks.
+#[derive(Default)]
+pub(crate) struct Issues(Vec<EmitError>);
+
+impl Issues {
+    pub(crate) fn error(&mut self, e: EmitError) {
+        self.0.push(e);
+    }
+}
+
+impl Pipeline {
+    /// Runs every emit-time check and returns all issues found, in check
+    /// order: missing commands, unknown dependencies, artifacts and published
+    /// outputs that aren't declared, conflicting mounts, unused resources in
+    /// [strict](Pipeline::strict) mode, env keys that shadow matrix values,
+    /// secrets or reserved prefixes, malformed or conflicting secret
+    /// declarations, matrix excludes and includes that don't
+    /// fit the dimensions, invalid conditions, dependencies on
+    /// manual tasks, cycles, K8s options and runner version limits, then
+    /// warning-level lints.
+    ///
+    /// `emit_to` fails if any issue is an [`Severity::Error`], listing all of
+    /// them one per line.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues: Vec<ValidationIssue> = self
+            .emit_errors()
+            .into_iter()
+            .map(|e| ValidationIssue {
+                severity: Severity::Error,
+                task: e.task().map(str::to_string),
+                field: e.field(),
+                message: e.to_string(),
+            })
+            .collect();
+        issues.extend(
+            self.lint()
+                .into_iter()
+                .filter(|w| w.severity == lint::Severity::Warning)
+                // Strict mode already reported these as errors.
+                .filter(|w| !(self.strict && w.code == lint::LintCode::UnusedResource))
+                .map(|w| ValidationIssue {
+                    severity: Severity::Warning,
+                    task: w.task,
+                    field: w.code.as_str().to_string(),
+                    message: w.message,
+                }),
+        );
+        issues
+    }
+
+    /// Fails with every error from [`Pipeline::validate`]:
+    /// [`EmitError::Multiple`] if there is more than one.
+    pub(crate) fn validate_for_emit(&self) -> Result<(), EmitError> {
+        let mut errors = self.emit_errors();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(EmitError::Multiple(errors)),
+        }
+    }
+
+    /// Runs the checks that block emit, in order.
+    fn emit_errors(&self) -> Vec<EmitError> {
+        let mut issues = Issues::default();
+        self.check_graph(&mut issues);
+        self.check_mounts(&mut issues);
+        self.check_unused_resources(&mut issues);
+        self.check_git_sources(&mut issues);
+        self.check_http_files(&mut issues);
+        self.check_files(&mut issues);
+        self.check_directories(&mut issues);
+        self.check_caches(&mut issues);
+        self.check_env(&mut issues);
+        self.check_secrets(&mut issues);
+        self.check_matrix(&mut issues);
+        self.check_services(&mut issues);
+        self.check_retries(&mut issues);
+        self.check_conditions(&mut issues);
+        self.check_manual_deps(&mut issues);
+        if let Some(path) = self.detect_cycle() {
+            issues.error(EmitError::Cycle { path });
+        }
+        self.check_k8s(&mut issues);
+        self.check_runner_version(&mut issues);
+        issues.0
+    }
+
+    /// Task count, commands, dependencies and the outputs other tasks use.
+    fn check_graph(&self, issues: &mut Issues) {
+        if let Some(limit) = self.max_tasks {
+            if self.tasks.len() > limit {
+                issues.error(EmitError::TooManyTasks {
+                    count: self.tasks.len(),
+                    limit,
+                });
+            }
+        }
+        if self.max_parallel == Some(0) {
+            issues.error(EmitError::ZeroMaxParallel);
+        }
+
+        let task_names: Vec<_> = self.tasks.iter().map(|t| t.name.as_str()).collect();
+        for t in &self.tasks {
+            if t.kind == NodeKind::Review {
+                if t.primitive.as_deref().unwrap_or("").is_empty() {
+                    issues.error(EmitError::MissingPrimitive {
+                        review: t.name.clone(),
+                    });
+                }
+            } else if t.command.is_empty()
+                && t.steps.is_empty()
+                && t.args.is_empty()
+                && t.gate.is_none()
+            {
+                issues.error(EmitError::MissingCommand {
+                    task: t.name.clone(),
+                });
+            } else if !t.args.is_empty() && (!t.command.is_empty() || !t.steps.is_empty()) {
+                let other = if t.command.is_empty() {
+                    "step()"
+                } else {
+                    "run()"
+                };
+                issues.error(EmitError::ExecAndCommand {
+                    task: t.name.clone(),
+                    other,
+                });
+            } else if !t.command.is_empty() && !t.steps.is_empty() {
+                issues.error(EmitError::CommandAndSteps {
+                    task: t.name.clone(),
+                });
+            }
+            for dep in &t.foreign_deps {
+                issues.error(EmitError::ForeignDependency {
+                    task: t.name.clone(),
+                    dep: dep.clone(),
+                });
+            }
+            for dep in &t.depends_on {
+                // Missing producers of inputs are reported with the input.
+                if task_names.contains(&dep.as_str())
+                    || t.task_inputs.iter().any(|ti| ti.from_task == *dep)
+                {
+                    continue;
+                }
+                issues.error(EmitError::UnknownDependency {
+                    task: t.name.clone(),
+                    dep: dep.clone(),
+                    suggestion: suggest_task_name(dep, &task_names).map(str::to_string),
+                });
+            }
+            for (from, _) in &t.inputs_from_all {
+                if self
+                    .tasks
+                    .iter()
+                    .any(|d| d.name == *from && d.outputs.is_empty())
+                {
+                    issues.error(EmitError::NoOutputs {
+                        task: t.name.clone(),
+                        from_task: from.clone(),
+                    });
+                }
+            }
+            let in_container = t.container.is_some() || t.container_build.is_some();
+            for ti in &self.with_defaults(t).task_inputs {
+                match self.tasks.iter().find(|d| d.name == ti.from_task) {
+                    None => issues.error(EmitError::UnknownInputTask {
+                        task: t.name.clone(),
+                        from_task: ti.from_task.clone(),
+                        output: ti.output.clone(),
+                        suggestion: suggest_task_name(&ti.from_task, &task_names)
+                            .map(str::to_string),
+                    }),
+                    Some(from) if !from.outputs.contains_key(&ti.output) => {
+                        let mut outputs: Vec<&str> =
+                            from.outputs.keys().map(String::as_str).collect();
+                        outputs.sort_unstable();
+                        issues.error(EmitError::UnknownInput {
+                            task: t.name.clone(),
+                            from_task: from.name.clone(),
+                            output: ti.output.clone(),
+                            suggestion: suggest_task_name(&ti.output, &outputs).map(str::to_string),
+                            declared: outputs.into_iter().map(str::to_string).collect(),
+                        });
+                    }
+                    Some(from)
+                        if from.output_kinds.get(&ti.output) == Some(&OutputKind::Glob)
+                            && !ti.dest_path.ends_with('/') =>
+                    {
+                        issues.error(EmitError::GlobInputNotDirectory {
+                            task: t.name.clone(),
+                            from_task: from.name.clone(),
+                            output: ti.output.clone(),
+                            path: ti.dest_path.clone(),
+                        });
+                    }
+                    Some(_) => {}
+                }
+                if in_container && !ti.dest_path.starts_with('/') {
+                    issues.error(EmitError::RelativeInputPath {
+                        task: t.name.clone(),
+                        from_task: ti.from_task.clone(),
+                        output: ti.output.clone(),
+                        path: ti.dest_path.clone(),
+                    });
+                }
+            }
+            for (output, _) in &t.publish {
+                if !t.outputs.contains_key(output) {
+                    issues.error(EmitError::UnknownPublishedOutput {
+                        task: t.name.clone(),
+                        output: output.clone(),
+                    });
+                }
+            }
+            let mut retained: Vec<_> = t.output_options.iter().collect();
+            retained.sort_by_key(|(name, _)| *name);
+            for (output, options) in retained {
+                if options.retention_days == Some(0) {
+                    issues.error(EmitError::ZeroRetention {
+                        task: t.name.clone(),
+                        output: output.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Env files must be readable, and env keys must not shadow what the
+    /// engine sets: matrix values, secrets and variables under a reserved
+    /// prefix.
+    fn check_env(&self, issues: &mut Issues) {
+        for t in &self.tasks {
+            for path in &t.env_files {
+                if let Err(error) = dotenv::read(path) {
+                    issues.error(EmitError::EnvFile {
+                        task: t.name.clone(),
+                        path: path.clone(),
+                        error,
+                    });
+                }
+            }
+            let t = &*self.with_defaults(t);
+            let mut keys: Vec<&String> = t.env.keys().collect();
+            keys.sort();
+            for key in keys {
+                let task = t.name.clone();
+                let key = key.clone();
+                if t.matrix.keys().any(|k| k == key) {
+                    issues.error(EmitError::EnvMatrixCollision { task, key });
+                } else if t.secrets.contains(&key)
+                    || t.secret_refs
+                        .iter()
+                        .any(|s| s.name == key && s.mount_path.is_none())
+                {
+                    issues.error(EmitError::EnvSecretCollision { task, key });
+                } else if let Some(prefix) = self
+                    .reserved_env_prefixes
+                    .iter()
+                    .find(|p| key.starts_with(p.as_str()))
+                {
+                    let prefix = prefix.clone();
+                    issues.error(EmitError::ReservedEnvPrefix { task, key, prefix });
+                }
+            }
+        }
+    }
+
+    /// Secret names follow the name rule if one is set, keys their source's
+    /// format (env keys the name rule too), and no name is declared twice
+    /// differently.
+    fn check_secrets(&self, issues: &mut Issues) {
+        let rule = self.secret_name_rule.unwrap_or(SecretNameRule::Unchecked);
+        for t in &self.tasks {
+            let decls: Vec<_> = t.secret_declarations().collect();
+            let mut conflicts: Vec<&str> = Vec::new();
+            for (i, sr) in decls.iter().enumerate() {
+                let Some(first) = decls[..i].iter().find(|o| o.name == sr.name && *o != sr) else {
+                    continue;
+                };
+                if conflicts.contains(&sr.name.as_str()) {
+                    continue;
+                }
+                conflicts.push(&sr.name);
+                issues.error(EmitError::SecretSourceConflict {
+                    task: t.name.clone(),
+                    name: sr.name.clone(),
+                    first: describe_secret(first),
+                    second: describe_secret(sr),
+                });
+            }
+
+            // Inherited pipeline secrets are checked for each task they reach.
+            let resolved = self.with_defaults(t);
+            for sr in resolved.secret_declarations() {
+                if !rule.allows(&sr.name) {
+                    issues.error(EmitError::InvalidSecretName {
+                        task: t.name.clone(),
+                        name: sr.name.clone(),
+                        pattern: rule.pattern(),
+                    });
+                    // A plain secret's key is its name.
+                    if resolved.secrets.contains(&sr.name) {
+                        continue;
+                    }
+                }
+                let expected = match sr.source.check_key(&sr.key) {
+                    Err(format) => format.to_string(),
+                    Ok(()) if matches!(sr.source, SecretSource::Env) && !rule.allows(&sr.key) => {
+                        format!("an environment variable name matching {}", rule.pattern())
+                    }
+                    Ok(()) => continue,
+                };
+                issues.error(EmitError::InvalidSecretKey {
+                    task: t.name.clone(),
+                    name: sr.name.clone(),
+                    source: sr.source.as_str(),
+                    key: sr.key.clone(),
+                    expected,
+                });
+            }
+        }
+    }
+
+    /// Matrix excludes name existing dimensions and values; includes set
+    /// every dimension and nothing else; placeholders name dimensions;
+    /// variant selectors name the producer's dimensions and values and
+    /// match a variant, exactly one for inputs.
+    fn check_matrix(&self, issues: &mut Issues) {
+        for t in &self.tasks {
+            self.check_selectors(t, issues);
+            let m = &t.matrix;
+            for (field, value) in self.with_defaults(t).interpolated_fields() {
+                for key in matrix::placeholders(value) {
+                    if m.get(key).is_none() {
+                        issues.error(EmitError::UnknownMatrixPlaceholder {
+                            task: t.name.clone(),
+                            field,
+                            key: key.to_string(),
+                            dimensions: m.keys().map(str::to_string).collect(),
+                        });
+                    }
+                }
+            }
+            for entry in m.excludes() {
+                for (key, value) in entry {
+                    let task = t.name.clone();
+                    match m.get(key) {
+                        None => issues.error(EmitError::UnknownMatrixKey {
+                            task,
+                            list: "matrix_exclude",
+                            key: key.clone(),
+                        }),
+                        Some(values) if !values.contains(value) => {
+                            issues.error(EmitError::UnknownMatrixValue {
+                                task,
+                                key: key.clone(),
+                                value: value.clone(),
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            for entry in m.includes() {
+                for key in entry.keys().filter(|k| m.get(k).is_none()) {
+                    issues.error(EmitError::UnknownMatrixKey {
+                        task: t.name.clone(),
+                        list: "matrix_include",
+                        key: key.clone(),
+                    });
+                }
+                for key in m.keys().filter(|k| !entry.contains_key(*k)) {
+                    issues.error(EmitError::IncompleteMatrixInclude {
+                        task: t.name.clone(),
+                        missing: key.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Checks the variant selectors of `t`'s dependencies and inputs.
+    fn check_selectors(&self, t: &TaskData, issues: &mut Issues) {
+        let mut selectors: Vec<(&String, &BTreeMap<String, String>)> = Vec::new();
+        for dep in &t.depends_on {
+            for selector in t.dep_selectors.get(dep).into_iter().flatten() {
+                selectors.push((dep, selector));
+            }
+        }
+        for ti in t.task_inputs.iter().filter(|ti| !ti.matrix.is_empty()) {
+            if !selectors.contains(&(&ti.from_task, &ti.matrix)) {
+                selectors.push((&ti.from_task, &ti.matrix));
+            }
+        }
+        for (dep, selector) in selectors {
+            // Unknown tasks are reported by check_graph.
+            let Some(producer) = self.tasks.iter().find(|p| p.name == *dep) else {
+                continue;
+            };
+            let m = &producer.matrix;
+            let mut valid = true;
+            for (key, value) in selector {
+                let Some(listed) = m.get(key) else {
+                    valid = false;
+                    issues.error(EmitError::UnknownSelectorKey {
+                        task: t.name.clone(),
+                        dep: dep.clone(),
+                        key: key.clone(),
+                        dimensions: m.keys().map(str::to_string).collect(),
+                    });
+                    continue;
+                };
+                let mut values = listed.to_vec();
+                for v in m.includes().iter().filter_map(|e| e.get(key)) {
+                    if !values.contains(v) {
+                        values.push(v.clone());
+                    }
+                }
+                if !values.contains(value) {
+                    valid = false;
+                    issues.error(EmitError::UnknownSelectorValue {
+                        task: t.name.clone(),
+                        dep: dep.clone(),
+                        key: key.clone(),
+                        value: value.clone(),
+                        values,
+                    });
+                }
+            }
+            if !valid {
+                continue;
+            }
+            let matches = m.variants().iter().filter(|v| v.matches(selector)).count();
+            let described = selector
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join(",");
+            if matches == 0 {
+                issues.error(EmitError::EmptyMatrixSelector {
+                    task: t.name.clone(),
+                    dep: dep.clone(),
+                    selector: described,
+                });
+            } else if matches > 1
+                && t.task_inputs
+                    .iter()
+                    .any(|ti| ti.from_task == *dep && ti.matrix == *selector)
+            {
+                issues.error(EmitError::AmbiguousMatrixInput {
+                    task: t.name.clone(),
+                    from_task: dep.clone(),
+                    selector: described,
+                    matches,
+                });
+            }
+        }
+    }
+
+    /// A service waits on at most one readiness probe, for a nonzero time.
+    fn check_services(&self, issues: &mut Issues) {
+        for t in &self.tasks {
+            for s in &t.services {
+                if s.ready_probes.len() > 1 {
+                    issues.error(EmitError::MultipleReadinessProbes {
+                        task: t.name.clone(),
+                        service: s.name.clone(),
+                        probes: s.ready_probes.iter().map(|p| p.describe()).collect(),
+                    });
+                }
+                if let Some(secs) = s.ready_timeout {
+                    if secs == 0 || s.ready_probes.is_empty() {
+                        issues.error(EmitError::InvalidReadyTimeout {
+                            task: t.name.clone(),
+                            service: s.name.clone(),
+                            secs,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// A retry policy retries at least once, with a backoff that can reach
+    /// its own base.
+    fn check_retries(&self, issues: &mut Issues) {
+        for t in &self.tasks {
+            let Some(ref retry) = t.retry else { continue };
+            if retry.max == 0 {
+                issues.error(EmitError::ZeroRetries {
+                    task: t.name.clone(),
+                });
+            }
+            if let Backoff::Exponential {
+                base_secs,
+                max_secs,
+            } = retry.backoff
+            {
+                if max_secs < base_secs {
+                    issues.error(EmitError::InvalidBackoff {
+                        task: t.name.clone(),
+                        base_secs,
+                        max_secs,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Mount paths must be distinct, and a container's workdir must be
+    /// absolute and inside one of its mounts once it mounts any directory.
+    /// Nesting is fine: a cache at `/src/target` under a directory at `/src`.
+    /// Tasks on the host may use any workdir.
+    fn check_mounts(&self, issues: &mut Issues) {
+        for t in &self.tasks {
+            for (i, m) in t.mounts.iter().enumerate() {
+                let path = mount_path(&m.path);
+                if let Some(first) = t.mounts[..i].iter().find(|o| mount_path(&o.path) == path) {
+                    issues.error(EmitError::MountConflict {
+                        task: t.name.clone(),
+                        path: path.to_string(),
+                        first: first.resource.clone(),
+                        second: m.resource.clone(),
+                    });
+                }
+            }
+            check_secret_files(&self.with_defaults(t), issues);
+
+            let in_container = t.container.is_some() || t.container_build.is_some();
+            let Some(ref workdir) = t.workdir else {
+                continue;
+            };
+            if in_container && !workdir.starts_with('/') {
+                issues.error(EmitError::RelativeContainerWorkdir {
+                    task: t.name.clone(),
+                    workdir: workdir.clone(),
+                });
+                continue;
+            }
+            if !in_container || !t.mounts.iter().any(|m| m.mount_type == "directory") {
+                continue;
+            }
+            let workdir = mount_path(workdir);
+            let covered = t.mounts.iter().any(|m| {
+                let path = mount_path(&m.path);
+                path == "/"
+                    || workdir
+                        .strip_prefix(path)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            });
+            if !covered {
+                issues.error(EmitError::UnmountedWorkdir {
+                    task: t.name.clone(),
+                    workdir: workdir.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Git resources need one ref and an https or ssh URL.
+    fn check_git_sources(&self, issues: &mut Issues) {
+        for g in &self.git_sources {
+            let set = g.ref_kinds();
+            if set.len() != 1 {
+                issues.error(EmitError::InvalidGitRef { id: g.id(), set });
+            }
+            if !is_git_url(&g.url) {
+                issues.error(EmitError::InvalidGitUrl {
+                    id: g.id(),
+                    url: g.url.clone(),
+                });
+            }
+        }
+    }
+
+    /// Http resources need an http(s) URL, and a digest in strict mode.
+    fn check_http_files(&self, issues: &mut Issues) {
+        for h in &self.http_files {
+            let rest = h
+                .url
+                .strip_prefix("https://")
+                .or_else(|| h.url.strip_prefix("http://"));
+            if rest.map_or(true, str::is_empty) {
+                issues.error(EmitError::InvalidHttpUrl {
+                    id: h.id(),
+                    url: h.url.clone(),
+                });
+            }
+            if self.strict && h.sha256.is_none() {
+                issues.error(EmitError::MissingSha256 { id: h.id() });
+            }
+        }
+    }
+
+    /// A file resource names one file, so its path can't be a glob.
+    fn check_files(&self, issues: &mut Issues) {
+        for file in &self.files {
+            if has_glob_chars(&file.path) {
+                issues.error(EmitError::FileGlob {
+                    id: file.id(),
+                    path: file.path.clone(),
+                });
+            }
+        }
+    }
+
+    /// Key file patterns must be non-empty, sizes must be quantities, and
+    /// every mount of a cache that sets key files, scopes or limits must set
+    /// the same ones.
+    /// Without a name or a hash, filtered views of one path share an ID.
+    fn check_directories(&self, issues: &mut Issues) {
+        let used = self.used_directories();
+        let mut reported: Vec<String> = Vec::new();
+        for (i, d) in used.iter().enumerate() {
+            let id = d.id();
+            if reported.contains(&id) {
+                continue;
+            }
+            if let Some(first) = used[..i]
+                .iter()
+                .find(|o| o.id() == id && o.glob_set() != d.glob_set())
+            {
+                issues.error(EmitError::DirectoryGlobConflict {
+                    id: id.clone(),
+                    first: first.globs.clone(),
+                    second: d.globs.clone(),
+                });
+                reported.push(id);
+            }
+        }
+    }
+
+    fn check_caches(&self, issues: &mut Issues) {
+        for c in self.caches() {
+            if c.key_files.iter().any(|f| f.trim().is_empty()) {
+                issues.error(EmitError::EmptyCacheKeyFile {
+                    cache: c.name.clone(),
+                });
+            }
+            if let Some(ref size) = c.max_size {
+                if let Some(message) = memory_quantity_error("size", size) {
+                    issues.error(EmitError::InvalidCacheSize {
+                        cache: c.name.clone(),
+                        value: size.clone(),
+                        message,
+                    });
+                }
+            }
+            let mut seen: Vec<String> = Vec::new();
+            for m in self.mounted_caches() {
+                if m.name != c.name || !m.is_configured() || *m == *c {
+                    continue;
+                }
+                let settings = m.settings();
+                if !seen.contains(&settings) {
+                    issues.error(EmitError::CacheSettingsConflict {
+                        cache: c.name.clone(),
+                        first: c.settings(),
+                        second: settings.clone(),
+                    });
+                    seen.push(settings);
+                }
+            }
+        }
+    }
+
+    /// A task that always runs would wait forever on a manual one.
+    fn check_manual_deps(&self, issues: &mut Issues) {
+        for t in &self.tasks {
+            if t.manual || t.effective_condition().is_some() {
+                continue;
+            }
+            for dep in self
+                .tasks
+                .iter()
+                .filter(|d| d.manual && t.depends_on.contains(&d.name))
+            {
+                issues.error(EmitError::ManualDependency {
+                    task: t.name.clone(),
+                    dep: dep.name.clone(),
+                });
+            }
+        }
+    }
+
+    /// Validates K8s options after merging defaults and templates. Tasks
+    /// without their own options share the defaults, so those are reported
+    /// once rather than per task.
+    fn check_k8s(&self, issues: &mut Issues) {
+        let mut uses_defaults = false;
+        for t in &self.tasks {
+            if t.k8s_options.is_none() && t.template_k8s.is_none() {
+                uses_defaults |= !t.bare;
+                continue;
+            }
+            let errors = self
+                .merged_k8s(t)
+                .map(|opts| opts.validate())
+                .unwrap_or_default();
+            for error in errors {
+                tracing::error!(task = %t.name, error = %error, "K8s validation failed");
+                issues.error(EmitError::K8s {
+                    task: t.name.clone(),
+                    error,
+                });
+            }
+        }
+        if !uses_defaults {
+            return;
+        }
+        for error in self
+            .k8s_defaults
+            .as_ref()
+            .map(K8sOptions::validate)
+            .unwrap_or_default()
+        {
+            tracing::error!(error = %error, "K8s defaults validation failed");
+            issues.error(EmitError::K8sDefaults { error });
+        }
+    }
+}
+
+/// A container path without trailing slashes, so `/src/` and `/src` match.
+/// Secret files need an absolute path of their own.
+fn check_secret_files(t: &TaskData, issues: &mut Issues) {
+    let files: Vec<(&String, &str)> = t
+        .secret_refs
+        .iter()
+        .filter_map(|sr| Some((&sr.name, sr.mount_path.as_deref()?)))
+        .collect();
+    for (i, &(name, path)) in files.iter().enumerate() {
+        if !path.starts_with('/') {
+            issues.error(EmitError::RelativeSecretFile {
+                task: t.name.clone(),
+                name: name.clone(),
+                path: path.to_string(),
+            });
+            continue;
+        }
+        let path = mount_path(path);
+        let other = files[..i]
+            .iter()
+            .find(|(_, p)| mount_path(p) == path)
+            .map(|(n, _)| format!("secret file {:?}", n))
+            .or_else(|| {
+                t.secret_templates
+                    .iter()
+                    .find(|st| mount_path(&st.dest) == path)
+                    .map(|_| "secret template".to_string())
+            })
+            .or_else(|| {
+                t.mounts
+                    .iter()
+                    .find(|m| mount_path(&m.path) == path)
+                    .map(|m| format!("mount of {:?}", m.resource))
+            });
+        if let Some(other) = other {
+            issues.error(EmitError::SecretFileConflict {
+                task: t.name.clone(),
+                name: name.clone(),
+                path: path.to_string(),
+                other,
+            });
+        }
+    }
+}
+
+/// A secret declaration for conflict messages, e.g.
+/// `env:GITHUB_TOKEN (optional)`.
+fn describe_secret(sr: &SecretRef) -> String {
+    let mut s = format!("{}:{}", sr.source.as_str(), sr.key);
+    if let Some(path) = &sr.mount_path {
+        write!(s, " at {}", path).unwrap();
+    }
+    let options: Vec<&str> = [(sr.optional, "optional"), (!sr.mask, "unmasked")]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect();
+    if !options.is_empty() {
+        write!(s, " ({})", options.join(", ")).unwrap();
+    }
+    s
+}
+
+fn mount_path(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        p => p,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::LintCode;
+    use crate::{Backoff, CacheScope, OutputOptions, RetryPolicy, SecretNameRule, SecretRef};
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint");
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .output("binary", "target/release/app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .input_from("build", "binry", "/in/app")
+            .after(&["tset"]);
+        let _ = p.task("deploy").run("./deploy.sh").k8s(K8sOptions {
+            memory: Some("4GB".into()),
+            ..Default::default()
+        });
+
+        let issues = p.validate();
+        let errors: Vec<(Option<&str>, &str)> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| (i.task.as_deref(), i.field.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (Some("lint"), "command"),
+                (Some("package"), "depends_on"),
+                (Some("package"), "input_from"),
+                (Some("deploy"), "k8s.memory"),
+            ]
+        );
+        assert_eq!(
+            issues[2].message,
+            r#"task "package" reads unknown output "binry" of task "build", which declares "binary" (did you mean "binary"?)"#
+        );
+
+        let err = p.emit_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let msg = err.to_string();
+        let lines: Vec<&str> = msg.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", err);
+        assert_eq!(lines[0], r#"task "lint" has no command"#);
+        assert_eq!(lines[1], r#"task "package" depends on unknown task "tset""#);
+        assert!(
+            lines[3].starts_with(r#"task "deploy": k8s.memory"#),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_input_from_unknown_task() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build").output("binary", "app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .input_from("biuld", "binary", "/in/app");
+
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            &err,
+            EmitError::UnknownInputTask { task, from_task, suggestion: Some(s), .. }
+                if task == "package" && from_task == "biuld" && s == "build"
+        ));
+        assert_eq!(
+            err.to_string(),
+            r#"task "package" reads output "binary" of unknown task "biuld" (did you mean "build"?)"#
+        );
+    }
+
+    #[test]
+    fn test_input_from_undeclared_output() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .output("sbom", "sbom.json")
+            .output("binary", "app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .input_from("lint", "report", "/in/report")
+            .input_from("build", "docs", "/in/docs");
+
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        let EmitError::Multiple(errors) = err else {
+            panic!("expected two errors, got: {}", err);
+        };
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "package" reads unknown output "report" of task "lint", which declares no outputs (declare it with output() on "lint")"#,
+                r#"task "package" reads unknown output "docs" of task "build", which declares "binary", "sbom" (declare it with output() on "build")"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_input_from_near_miss_output() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build").output("binary", "app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .input_from("build", "binaries", "/in/app");
+
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        match err {
+            EmitError::UnknownInput {
+                declared,
+                suggestion,
+                ..
+            } => {
+                assert_eq!(declared, ["binary"]);
+                assert_eq!(suggestion.as_deref(), Some("binary"));
+            }
+            err => panic!("expected unknown input, got: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_input_from_relative_path_in_container() {
+        let mut p = Pipeline::new();
+        let _ = p.task("build").run("cargo build").output("binary", "app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .input_from("build", "binary", "./app");
+        // Without a container the path is relative to the workspace.
+        assert!(p.emit_to_checked(&mut Vec::new()).is_ok());
+
+        let _ = p.task_or_get("package").container("docker:24");
+        assert!(matches!(
+            p.emit_to_checked(&mut Vec::new()).unwrap_err(),
+            EmitError::RelativeInputPath { path, .. } if path == "./app"
+        ));
+    }
+
+    #[test]
+    fn test_glob_input_needs_directory_dest() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("package")
+            .run("make dist")
+            .output_glob("bundles", "dist/*.tar.gz");
+        let _ = p.task("upload").run("./upload.sh").input_from(
+            "package",
+            "bundles",
+            "/in/bundle.tar.gz",
+        );
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            EmitError::GlobInputNotDirectory { ref path, .. } if path == "/in/bundle.tar.gz"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "task \"upload\" reads glob output \"bundles\" of task \"package\" into \"/in/bundle.tar.gz\", which must be a directory (end it with '/')"
+        );
+
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("package")
+            .run("make dist")
+            .output_glob("bundles", "dist/*.tar.gz");
+        let _ =
+            p.task("upload")
+                .run("./upload.sh")
+                .input_from("package", "bundles", "/in/bundles/");
+        assert!(p.emit_to_checked(&mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_zero_output_retention() {
+        let mut p = Pipeline::new();
+        let _ = p.task("coverage").run("make coverage").output_with(
+            "report",
+            "coverage/",
+            OutputOptions {
+                retention_days: Some(0),
+                ..OutputOptions::default()
+            },
+        );
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            EmitError::ZeroRetention { ref task, ref output } if task == "coverage" && output == "report"
+        ));
+        let issues = p.validate();
+        assert_eq!(issues[0].field, "outputs");
+    }
+
+    #[test]
+    fn test_duplicate_mount_paths() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let cargo = p.cache("cargo");
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount(&src, "/src")
+            .mount_cache(&cargo, "/src/")
+            .workdir("/src")
+            .run("cargo test");
+
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        let EmitError::Multiple(errors) = err else {
+            panic!("expected two errors, got: {}", err);
+        };
+        assert!(matches!(
+            &errors[1],
+            EmitError::MountConflict { path, first, second, .. }
+                if path == "/src" && first == "src:." && second == "cargo"
+        ));
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "test" mounts "src:." twice at /src"#,
+                r#"task "test" mounts both "src:." and "cargo" at /src"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_mounts_are_allowed() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let target = p.cache("target");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .mount_cache(&target, "/src/target")
+            .workdir("/src/crates/app")
+            .run("cargo build");
+
+        assert!(p.validate().is_empty());
+    }
+
+    #[test]
+    fn test_relative_workdir_needs_host_task() {
+        let mut p = Pipeline::new();
+        let _ = p.task("web-test").workdir("web").run("npm test");
+        assert!(p.emit_to_checked(&mut Vec::new()).is_ok());
+
+        // The container comes after the workdir, so only emit can tell.
+        let _ = p.task_or_get("web-test").container("node:20");
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "task \"web-test\" runs in a container but has relative workdir \"web\" (container workdirs must be absolute; relative ones are for tasks on the host)"
+        );
+        assert_eq!(p.validate()[0].field, "workdir");
+    }
+
+    #[test]
+    fn test_workdir_outside_mounts() {
+        let mut p = Pipeline::new();
+        let src = p.dir(".");
+        let _ = p
+            .task("build")
+            .container("rust:1.75")
+            .mount(&src, "/src")
+            .workdir("/srcs")
+            .run("cargo build");
+        // Without directory mounts the workdir is the image's own.
+        let _ = p
+            .task("lint")
+            .container("rust:1.75")
+            .workdir("/app")
+            .run("cargo clippy");
+
+        let issues: Vec<ValidationIssue> = p
+            .validate()
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "workdir");
+        assert_eq!(
+            issues[0].message,
+            r#"task "build" has workdir /srcs outside its mounts (mount a directory there or change workdir())"#
+        );
+    }
+
+    #[test]
+    fn test_env_collides_with_matrix_and_secrets() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("os", &["linux", "macos"])
+            .env("os", "linux");
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret("DEPLOY_TOKEN")
+            .secret_from("KUBECONFIG", SecretRef::from_env("PROD_KUBECONFIG"))
+            .env("DEPLOY_TOKEN", "dummy")
+            .env("KUBECONFIG", "/tmp/kube");
+
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        let EmitError::Multiple(errors) = err else {
+            panic!("expected three errors, got: {}", err);
+        };
+        assert!(matches!(
+            &errors[..],
+            [
+                EmitError::EnvMatrixCollision { task: t, key: k },
+                EmitError::EnvSecretCollision { key: token, .. },
+                EmitError::EnvSecretCollision { key: kube, .. },
+            ] if t == "test" && k == "os" && token == "DEPLOY_TOKEN" && kube == "KUBECONFIG"
+        ));
+        assert_eq!(
+            errors[0].to_string(),
+            r#"task "test" sets env "os", which is also a matrix dimension (rename one of them)"#
+        );
+    }
+
+    #[test]
+    fn test_env_reserved_prefixes() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .env("SYKLI_MATRIX_OS", "linux")
+            .env("CI_JOB_ID", "1");
+
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            &err,
+            EmitError::ReservedEnvPrefix { key, prefix, .. }
+                if key == "SYKLI_MATRIX_OS" && prefix == "SYKLI_"
+        ));
+
+        p.reserve_env_prefix("CI_").reserve_env_prefix("CI_");
+        let err = p.emit_to_checked(&mut Vec::new()).unwrap_err();
+        let EmitError::Multiple(errors) = err else {
+            panic!("expected two errors, got: {}", err);
+        };
+        assert_eq!(
+            errors[0].to_string(),
+            r#"task "test" sets env "CI_JOB_ID", but the "CI_" prefix is reserved"#
+        );
+    }
+
+    #[test]
+    fn test_default_env_is_checked_per_task() {
+        let mut p = Pipeline::new();
+        p.default_env("SYKLI_TOKEN", "x");
+        let _ = p.task("test").run("cargo test");
+        let _ = p.task("notify").run("./notify.sh").bare();
+
+        let messages: Vec<String> = p.validate().iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            messages,
+            [r#"task "test" sets env "SYKLI_TOKEN", but the "SYKLI_" prefix is reserved"#]
+        );
+    }
+
+    #[test]
+    fn test_unknown_matrix_placeholders() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .container("rust:{{matrix.version}}")
+            .env("OS", "{{matrix.os}}")
+            .run("cargo +{{matrix.rust}} test")
+            .matrix("rust", &["1.75", "1.80"])
+            .matrix("os", &["linux"]);
+        let _ = p.task("lint").run("echo {{matrix.rust}}");
+
+        let issues: Vec<_> = p
+            .validate()
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        let fields: Vec<&str> = issues.iter().map(|i| i.field.as_str()).collect();
+        assert_eq!(fields, ["container", "command"]);
+        assert_eq!(
+            issues[0].message,
+            r#"task "test" uses {{matrix.version}} in its container, but its matrix keys are rust, os"#
+        );
+        assert_eq!(
+            issues[1].message,
+            r#"task "lint" uses {{matrix.rust}} in its command, but it has no matrix"#
+        );
+    }
+
+    #[test]
+    fn test_matrix_exclude_and_include_errors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .matrix("rust", &["1.70", "1.80"])
+            .matrix("os", &["linux", "macos"])
+            .matrix_exclude(&[("rust", "1.70"), ("os", "macos")])
+            .matrix_exclude(&[("arch", "arm64")])
+            .matrix_exclude(&[("os", "bsd")])
+            .matrix_include(&[("rust", "nightly"), ("os", "linux")])
+            .matrix_include(&[("rust", "nightly")])
+            .matrix_include(&[("rust", "beta"), ("os", "linux"), ("arch", "x86")]);
+
+        let issues = p.validate();
+        let fields: Vec<&str> = issues.iter().map(|i| i.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            [
+                "matrix_exclude",
+                "matrix_exclude",
+                "matrix_include",
+                "matrix_include"
+            ]
+        );
+        let messages: Vec<String> = issues.iter().map(|i| i.message.clone()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "test" has matrix_exclude key "arch", which isn't a matrix dimension"#,
+                r#"task "test" excludes os="bsd", which isn't one of the matrix values for "os""#,
+                r#"task "test" has a matrix_include entry without "os" (includes must set every dimension)"#,
+                r#"task "test" has matrix_include key "arch", which isn't a matrix dimension"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matrix_selector_errors() {
+        let mut p = Pipeline::new();
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"])
+            .matrix("arch", &["x64", "arm64"])
+            .matrix_exclude(&[("os", "macos"), ("arch", "x64")])
+            .output("binary", "target/app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after_matrix("build", &[("platform", "linux")])
+            .after_matrix("build", &[("os", "windows")])
+            .after_matrix("build", &[("os", "macos"), ("arch", "x64")])
+            .after_matrix("lint", &[("os", "linux")])
+            .input_from_matrix("build", &[("os", "linux")], "binary", "./app");
+
+        let issues: Vec<_> = p
+            .validate()
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        let fields: Vec<&str> = issues.iter().map(|i| i.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            [
+                "depends_on",
+                "depends_on",
+                "depends_on",
+                "input_from",
+                "depends_on"
+            ]
+        );
+        let messages: Vec<String> = issues.iter().map(|i| i.message.clone()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "package" selects variants of "build" by "platform", but its matrix keys are os, arch"#,
+                r#"task "package" selects os="windows" of "build", which isn't one of its values (linux, macos)"#,
+                r#"task "package" selects arch=x64,os=macos of "build", but no variant matches"#,
+                r#"task "package" reads an input from os=linux of "build", which matches 2 variants (select exactly one)"#,
+                r#"task "package" selects variants of "lint" by "os", but "lint" has no matrix"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matrix_selectors_valid() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("build")
+            .run("cargo build")
+            .matrix("os", &["linux", "macos"])
+            .matrix_include(&[("os", "windows")])
+            .output("binary", "target/app");
+        let _ = p
+            .task("package")
+            .run("./package.sh")
+            .after_matrix("build", &[("os", "windows")])
+            .input_from_matrix("build", &[("os", "linux")], "binary", "./app");
+
+        assert!(p.validate_for_emit().is_ok());
+    }
+
+    #[test]
+    fn test_secret_file_path_errors() {
+        let mut p = Pipeline::new();
+        let certs = p.dir("certs");
+        let _ = p
+            .task("deploy")
+            .container("alpine:3")
+            .mount(&certs, "/certs")
+            .run("./deploy.sh")
+            .env("KUBECONFIG_CONTENT", "unused")
+            .secret_file(
+                "KUBECONFIG_CONTENT",
+                SecretRef::from_vault("secret/ci#kubeconfig"),
+                "/secrets/kubeconfig",
+            )
+            .secret_file(
+                "KUBECONFIG_BACKUP",
+                SecretRef::from_env("KUBECONFIG_B64"),
+                "/secrets/kubeconfig",
+            )
+            .secret_file("CA", SecretRef::from_file("ca.pem"), "/certs/")
+            .secret_file("NPMRC", SecretRef::from_env("NPMRC"), ".npmrc");
+
+        let issues: Vec<_> = p
+            .validate()
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        let messages: Vec<&str> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "deploy" writes secret "KUBECONFIG_BACKUP" to /secrets/kubeconfig, which its secret file "KUBECONFIG_CONTENT" already uses"#,
+                r#"task "deploy" writes secret "CA" to /certs, which its mount of "src:certs" already uses"#,
+                r#"task "deploy" writes secret "NPMRC" to relative path ".npmrc" (secret files must be absolute)"#,
+            ]
+        );
+        assert!(issues.iter().all(|i| i.field == "secret_refs"));
+    }
+
+    #[test]
+    fn test_secret_name_and_key_errors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret("GITHUB TOKEN")
+            .secret("npm_token")
+            .secret_from("TOKEN", SecretRef::from_env("gh-token"))
+            .secret_from("DB", SecretRef::from_vault("secret/db#password"));
+        p.tasks[0].secret_refs[1].key = "secret/db#pass#word".to_string();
+
+        let errors = |p: &Pipeline| -> Vec<(String, String)> {
+            p.validate()
+                .into_iter()
+                .filter(|i| i.severity == Severity::Error)
+                .map(|i| (i.field, i.message))
+                .collect()
+        };
+        // Without a rule only the vault key's format is checked.
+        assert_eq!(errors(&p).len(), 1);
+
+        p.secret_name_rule(SecretNameRule::UpperSnake);
+        let pair = |field: &str, message: &str| (field.to_string(), message.to_string());
+        assert_eq!(
+            errors(&p),
+            [
+                pair(
+                    "secrets",
+                    r#"task "deploy" declares secret "GITHUB TOKEN", which doesn't match [A-Z_][A-Z0-9_]* (see Pipeline::secret_name_rule)"#
+                ),
+                pair(
+                    "secrets",
+                    r#"task "deploy" declares secret "npm_token", which doesn't match [A-Z_][A-Z0-9_]* (see Pipeline::secret_name_rule)"#
+                ),
+                pair(
+                    "secret_refs",
+                    r#"task "deploy" reads secret "TOKEN" from env key "gh-token", expected an environment variable name matching [A-Z_][A-Z0-9_]*"#
+                ),
+                pair(
+                    "secret_refs",
+                    r#"task "deploy" reads secret "DB" from vault key "secret/db#pass#word", expected 'path#field' format with a single '#' (e.g., 'secret/data/db#password')"#
+                ),
+            ]
+        );
+
+        p.secret_name_rule(SecretNameRule::Identifier);
+        assert_eq!(errors(&p).len(), 3);
+        p.secret_name_rule(SecretNameRule::Unchecked);
+        assert_eq!(errors(&p).len(), 1);
+    }
+
+    #[test]
+    fn test_secret_source_conflict() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("deploy")
+            .run("./deploy.sh")
+            .secret("TOKEN")
+            .secret_from("TOKEN", SecretRef::from_vault("secret/ci#token"))
+            .secret_from("TOKEN", SecretRef::from_env("GITHUB_TOKEN"))
+            .secret_from("NPM_TOKEN", SecretRef::from_env("NPM_TOKEN"))
+            .secret_from("NPM_TOKEN", SecretRef::from_env("NPM_TOKEN").optional());
+
+        let issues: Vec<_> = p
+            .validate()
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        let messages: Vec<&str> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "deploy" declares secret "TOKEN" as both env:TOKEN and vault:secret/ci#token (keep one)"#,
+                r#"task "deploy" declares secret "NPM_TOKEN" as both env:NPM_TOKEN and env:NPM_TOKEN (optional) (keep one)"#,
+            ]
+        );
+        assert!(issues.iter().all(|i| i.field == "secret_refs"));
+    }
+
+    #[test]
+    fn test_service_readiness_errors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("test")
+            .run("cargo test")
+            .service_with("postgres:15", "db", |s| {
+                s.ready_tcp(5432).ready_cmd("pg_isready -U postgres")
+            })
+            .service_with("redis:7", "cache", |s| s.ready_tcp(6379).ready_timeout(0))
+            .service_with("minio/minio", "s3", |s| s.ready_timeout(30));
+
+        let messages: Vec<String> = p.validate().iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "test" service "db" has several readiness probes: tcp:5432, cmd:pg_isready -U postgres (keep one)"#,
+                r#"task "test" service "cache" has a readiness timeout of 0 (use at least 1 second)"#,
+                r#"task "test" service "s3" sets ready_timeout() without a readiness probe (add ready_cmd(), ready_tcp() or ready_http())"#,
+            ]
+        );
+        assert!(p
+            .validate()
+            .iter()
+            .all(|i| i.field == "services" && i.task.as_deref() == Some("test")));
+    }
+
+    #[test]
+    fn test_retry_policy_errors() {
+        let mut p = Pipeline::new();
+        let _ = p
+            .task("push")
+            .run("docker push app")
+            .timeout(60)
+            .retry_policy(RetryPolicy {
+                max: 0,
+                ..RetryPolicy::default()
+            });
+        let _ = p
+            .task("fetch")
+            .run("./fetch.sh")
+            .timeout(60)
+            .retry_policy(RetryPolicy {
+                max: 3,
+                backoff: Backoff::Exponential {
+                    base_secs: 60,
+                    max_secs: 30,
+                },
+                ..RetryPolicy::default()
+            });
+
+        let messages: Vec<String> = p.validate().iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"task "push" has a retry policy with max 0 (use at least 1, or drop the policy)"#,
+                r#"task "fetch" has a backoff capped at 30s, below its 60s base (raise max_secs)"#,
+            ]
+        );
+        assert!(p.validate().iter().all(|i| i.field == "retry"));
+        assert!(matches!(
+            p.emit_to_checked(&mut Vec::new()),
+            Err(EmitError::Multiple(ref errors)) if matches!(errors[0], EmitError::ZeroRetries { .. })
+        ));
+    }
+
+    #[test]
+    fn test_git_resource_needs_one_ref() {
+        let mut p = Pipeline::new();
+        let url = "https://github.com/org/charts.git";
+        let none = p.git(url);
+        let both = p.git(url).branch("main").tag("v1");
+        let one = p.git(url).commit("4b825dc6");
+        let _ = p
+            .task("a")
+            .container("alpine")
+            .mount_git(&none, "/charts")
+            .run("ls /charts");
+        let _ = p
+            .task("b")
+            .container("alpine")
+            .mount_git(&both, "/charts")
+            .run("ls /charts");
+        let _ = p
+            .task("c")
+            .container("alpine")
+            .mount_git(&one, "/charts")
+            .run("ls /charts");
+
+        let issues = p.validate();
+        let messages: Vec<String> = issues.iter().map(|i| i.message.clone()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"git resource "git:https://github.com/org/charts.git" has no ref (set one of branch, tag or commit)"#,
+                r#"git resource "git:https://github.com/org/charts.git#main" sets more than one ref (branch, tag); keep one of branch, tag or commit"#,
+            ]
+        );
+        assert!(issues.iter().all(|i| i.field == "ref" && i.task.is_none()));
+        assert!(matches!(
+            p.emit_to_checked(&mut Vec::new()),
+            Err(EmitError::Multiple(ref errors)) if matches!(
+                errors[1],
+                EmitError::InvalidGitRef { ref set, .. } if set == &["branch", "tag"]
+            )
+        ));
+    }
+
+    #[test]
+    fn test_git_resource_url_forms() {
+        for (url, ok) in [
+            ("https://github.com/org/charts.git", true),
+            ("ssh://git@github.com/org/charts.git", true),
+            ("git@github.com:org/charts.git", true),
+            ("http://github.com/org/charts.git", false),
+            ("file:///srv/charts.git", false),
+            ("github.com/org/charts.git", false),
+            ("https://github.com", false),
+            ("git@github.com", false),
+        ] {
+            let mut p = Pipeline::new();
+            let charts = p.git(url).branch("main");
+            let _ = p
+                .task("deploy")
+                .container("alpine")
+                .mount_git(&charts, "/charts")
+                .run("ls /charts");
+            let errors: Vec<_> = p
+                .validate()
+                .into_iter()
+                .filter(|i| i.severity == Severity::Error)
+                .collect();
+            assert_eq!(errors.is_empty(), ok, "{}", url);
+            if !ok {
+                assert_eq!(errors[0].field, "url");
+            }
+        }
+    }
+
+    #[test]
+    fn test_file_resource_rejects_globs() {
+        let mut p = Pipeline::new();
+        let configs = p.file("config/*.toml");
+        let _ = p
+            .task("check")
+            .container("alpine")
+            .mount_file_res(&configs, "/etc/app")
+            .run("true");
+
+        let errors: Vec<_> = p
+            .validate()
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "path");
+        assert_eq!(
+            errors[0].message,
+            r#"file resource "file:config/*.toml" has glob characters in "config/*.toml" (mount a directory with glob() for several files)"#
+        );
+        assert!(matches!(
+            p.emit_to_checked(&mut Vec::new()),
+            Err(EmitError::FileGlob { .. })
+        ));
+    }
+
+    #[test]
+    fn test_http_resource_checks() {
+        let mut p = Pipeline::new();
+        let tool = p.http("https://example.com/tool.tar.gz").unpack(true);
+        let ftp = p.http("ftp://example.com/tool.tar.gz");
+        let _ = p
+            .task("build")
+            .container("debian:12")
+            .mount_file(&tool, "/opt/tool")
+            .mount_file(&ftp, "/opt/tool.tar.gz")
+            .run("/opt/tool/bin/tool");
+
+        let errors = |p: &Pipeline| -> Vec<String> {
+            p.validate()
+                .into_iter()
+                .filter(|i| i.severity == Severity::Error)
+                .map(|i| i.message)
+                .collect()
+        };
+        assert_eq!(
+            errors(&p),
+            [
+                r#"http resource "http:ftp://example.com/tool.tar.gz" has URL "ftp://example.com/tool.tar.gz", expected http:// or https://"#
+            ]
+        );
+
+        // Strict mode also requires digests.
+        p.strict(true);
+        let messages = errors(&p);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0],
+            r#"http resource "http:https://example.com/tool.tar.gz" has no sha256, which strict mode requires (set it with sha256())"#
+        );
+        assert!(matches!(
+            p.emit_to_checked(&mut Vec::new()),
+            Err(EmitError::Multiple(ref errors)) if matches!(errors[0], EmitError::MissingSha256 { .. })
+        ));
+        assert!(p
+            .validate()
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .all(|i| i.field == "url" || i.field == "sha256"));
+    }
+
+    #[test]
+    fn test_cache_key_checks() {
+        let mut p = Pipeline::new();
+        let cargo = p.cache("cargo").key_files(&["Cargo.lock", " "]);
+        let npm = p.cache("npm");
+        let _ = p
+            .task("build")
+            .container("rust:1.80")
+            .mount_cache(&cargo, "/usr/local/cargo/registry")
+            .mount_cache(&npm.clone().scope(CacheScope::Branch), "/root/.npm")
+            .run("cargo build");
+        let _ = p
+            .task("web")
+            .container("node:20")
+            .mount_cache(&npm.scope(CacheScope::Global), "/root/.npm")
+            .run("npm ci");
+
+        let errors: Vec<_> = p
+            .validate()
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        let messages: Vec<_> = errors.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                r#"cache "cargo" has an empty key_files pattern"#,
+                r#"cache "npm" is mounted with key npm per branch and with key npm shared by all branches (set key files, scopes and limits once)"#,
+            ]
+        );
+        assert_eq!(errors[0].field, "key_files");
+        assert_eq!(errors[1].field, "resources");
+    }
+
+    #[test]
+    fn test_cache_size_uses_k8s_quantity_check() {
+        for (size, hint) in [
+            ("10gb", "did you mean 'Gi'"),
+            ("512MB", "did you mean 'Mi'"),
+            ("lots", "use Ki/Mi/Gi/Ti"),
+        ] {
+            let mut p = Pipeline::new();
+            let target = p.cache("target").max_size(size);
+            let _ = p
+                .task("build")
+                .container("rust:1.80")
+                .mount_cache(&target, "/src/target")
+                .run("cargo build");
+
+            let issues = p.validate();
+            assert_eq!(issues.len(), 1, "{:?}", issues);
+            assert_eq!(issues[0].field, "max_size");
+            assert!(issues[0]
+                .message
+                .starts_with(r#"cache "target" max_size: invalid size format"#));
+            assert!(issues[0].message.contains(hint), "{}", issues[0].message);
+            // The same check as k8s memory, with the same suggestion.
+            let k8s = K8sOptions {
+                memory: Some(size.to_string()),
+                ..Default::default()
+            };
+            assert!(k8s.validate()[0].message.contains(hint));
+        }
+
+        let mut p = Pipeline::new();
+        let target = p.cache("target").max_size("1.5Gi");
+        let _ = p
+            .task("build")
+            .container("rust:1.80")
+            .mount_cache(&target, "/src/target")
+            .run("cargo build");
+        assert!(p.validate().is_empty());
+    }
+
+    #[test]
+    fn test_validate_includes_lint_warnings() {
+        let mut p = Pipeline::new();
+        let _ = p.task("test").run("cargo test").retry(3);
+
+        let issues = p.validate();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(issues[0].field, LintCode::RetryWithoutTimeout.as_str());
+        // Warnings don't block emit.
+        assert!(p.emit_to(&mut Vec::new()).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_k8s_defaults_reported_once() {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            cpu: Some("lots".into()),
+            ..Default::default()
+        });
+        let _ = p.task("a").run("true");
+        let _ = p.task("b").run("true");
+        let _ = p.task("c").run("true").bare();
+
+        let issues = p.validate();
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].task.is_none());
+        assert!(issues[0].message.starts_with("k8s defaults: k8s.cpu"));
+    }
+}
@@ -0,0 +1,589 @@
+//! Read-only views of a constructed pipeline.
+//!
+//! Tools such as policy checkers can inspect a [`Pipeline`] in Rust instead
+//! of parsing its JSON:
+//!
+//! ```rust
+//! use sykli::Pipeline;
+//!
+//! let mut p = Pipeline::new();
+//! p.task("build").container("rust:1.75").run("cargo build");
+//!
+//! for task in p.iter_tasks() {
+//!     if let Some(image) = task.container() {
+//!         assert!(image.contains(':'), "{} uses an unpinned image", task.name());
+//!     }
+//! }
+//! ```
+//!
+//! Views borrow from the pipeline, so they reflect exactly what `emit` would
+//! serialize at the time they are taken.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::matrix::Matrix;
+use crate::target::VolumeOptions;
+use crate::{
+    CacheScope, CacheSharing, Directory, Eviction, FileSource, GitSource, K8sOptions, Mount,
+    NodeKind, OutputOptions, Pipeline, RetryPolicy, SecretRef, TaskData,
+};
+
+/// A borrowed view of a task.
+#[derive(Clone, Copy)]
+pub struct TaskView<'a> {
+    pipeline: &'a Pipeline,
+    data: &'a TaskData,
+}
+
+impl<'a> TaskView<'a> {
+    /// Task name.
+    pub fn name(&self) -> &'a str {
+        &self.data.name
+    }
+
+    /// Description set with [`Task::describe`](crate::Task::describe).
+    pub fn description(&self) -> Option<&'a str> {
+        self.data.description.as_deref()
+    }
+
+    /// Tags, in the order they were added.
+    pub fn tags(&self) -> &'a [String] {
+        &self.data.tags
+    }
+
+    /// Shell command, or `None` for review nodes, gates and tasks made of
+    /// [`steps`](Self::steps) or [`args`](Self::args).
+    pub fn command(&self) -> Option<&'a str> {
+        if self.data.command.is_empty() {
+            None
+        } else {
+            Some(&self.data.command)
+        }
+    }
+
+    /// Program and arguments set with [`Task::exec`](crate::Task::exec).
+    pub fn args(&self) -> &'a [String] {
+        &self.data.args
+    }
+
+    /// Commands added with [`Task::step`](crate::Task::step), in order.
+    pub fn steps(&self) -> &'a [String] {
+        &self.data.steps
+    }
+
+    /// Container image.
+    pub fn container(&self) -> Option<&'a str> {
+        self.data.container.as_deref()
+    }
+
+    /// Image built from a Dockerfile, as `(context resource id, dockerfile)`.
+    pub fn container_build(&self) -> Option<(&'a str, &'a str)> {
+        self.data
+            .container_build
+            .as_ref()
+            .map(|cb| (cb.context_resource.as_str(), cb.dockerfile.as_str()))
+    }
+
+    /// Working directory inside the container.
+    pub fn workdir(&self) -> Option<&'a str> {
+        self.data.workdir.as_deref()
+    }
+
+    /// Entrypoint override; `Some("")` clears the image's entrypoint.
+    pub fn entrypoint(&self) -> Option<&'a str> {
+        self.data.entrypoint.as_deref()
+    }
+
+    /// User the container runs as.
+    pub fn user(&self) -> Option<&'a str> {
+        self.data.user.as_deref()
+    }
+
+    /// Environment variables set on the task or its template, without
+    /// [`Pipeline::default_env`](crate::Pipeline::default_env) values.
+    pub fn env(&self) -> &'a HashMap<String, String> {
+        &self.data.env
+    }
+
+    /// Env files left for the engine to read, from
+    /// [`Task::env_file_lazy`](crate::Task::env_file_lazy). Files from
+    /// `env_file` are read at emit and aren't listed.
+    pub fn runtime_env_files(&self) -> &'a [String] {
+        &self.data.runtime_env_files
+    }
+
+    /// Mounted resources, in declaration order.
+    pub fn mounts(&self) -> impl Iterator<Item = MountView<'a>> {
+        self.data.mounts.iter().map(|m| MountView { mount: m })
+    }
+
+    /// File patterns used for caching.
+    pub fn inputs(&self) -> &'a [String] {
+        &self.data.inputs
+    }
+
+    /// Named outputs (name -> path).
+    pub fn outputs(&self) -> &'a HashMap<String, String> {
+        &self.data.outputs
+    }
+
+    /// What the output `name` declares: `"file"`, `"glob"` or `"directory"`,
+    /// or `None` if the task has no such output.
+    pub fn output_type(&self, name: &str) -> Option<&'static str> {
+        self.data.outputs.get(name)?;
+        Some(
+            self.data
+                .output_kinds
+                .get(name)
+                .map_or("file", |k| k.as_str()),
+        )
+    }
+
+    /// Retention, compression and whether the output `name` must exist, or
+    /// `None` if the task has no such output.
+    pub fn output_options(&self, name: &str) -> Option<OutputOptions> {
+        self.data.outputs.get(name)?;
+        Some(
+            self.data
+                .output_options
+                .get(name)
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Names of the tasks this task depends on.
+    pub fn depends_on(&self) -> &'a [String] {
+        &self.data.depends_on
+    }
+
+    /// Variant selectors on the dependency on `dep`, set with
+    /// [`crate::Task::after_matrix`]. Empty if it waits for every variant.
+    pub fn matrix_selectors(&self, dep: &str) -> &'a [BTreeMap<String, String>] {
+        self.data
+            .dep_selectors
+            .get(dep)
+            .map_or(&[], |s| s.as_slice())
+    }
+
+    /// Condition expression, whether set with `when` or `when_cond`.
+    ///
+    /// Doesn't include `unless`; see [`TaskView::effective_condition`].
+    pub fn condition(&self) -> Option<&'a str> {
+        self.data
+            .when_cond
+            .as_ref()
+            .map(|c| c.expr.as_str())
+            .or(self.data.condition.as_deref())
+    }
+
+    /// The condition as emitted in `when`, including a negated `unless`.
+    pub fn effective_condition(&self) -> Option<String> {
+        self.data.effective_condition()
+    }
+
+    /// Secret names declared with `secret`/`secrets`.
+    pub fn secrets(&self) -> &'a [String] {
+        &self.data.secrets
+    }
+
+    /// Typed secret references declared with `secret_from`.
+    pub fn secret_refs(&self) -> &'a [SecretRef] {
+        &self.data.secret_refs
+    }
+
+    /// Service containers as `(image, name)` pairs.
+    pub fn services(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.data
+            .services
+            .iter()
+            .map(|s| (s.image.as_str(), s.name.as_str()))
+    }
+
+    /// Number of retries on failure, including
+    /// [`Pipeline::default_retry`](crate::Pipeline::default_retry).
+    pub fn retry(&self) -> Option<u32> {
+        self.retry_policy().map(|r| r.max)
+    }
+
+    /// Full retry policy, with backoff and exit code filter.
+    pub fn retry_policy(&self) -> Option<&'a RetryPolicy> {
+        let default = self.pipeline.defaults.retry.as_ref();
+        self.data
+            .retry
+            .as_ref()
+            .or(default.filter(|_| self.data.takes_defaults()))
+    }
+
+    /// Timeout in seconds, including
+    /// [`Pipeline::default_timeout`](crate::Pipeline::default_timeout).
+    pub fn timeout(&self) -> Option<u32> {
+        let default = self.pipeline.defaults.timeout;
+        self.data
+            .timeout
+            .or(default.filter(|_| self.data.takes_defaults()))
+    }
+
+    /// K8s options with pipeline defaults merged in, as emitted.
+    pub fn k8s(&self) -> Option<K8sOptions> {
+        self.pipeline.merged_k8s(self.data)
+    }
+
+    /// Raw K8s JSON set with `k8s_raw`.
+    pub fn k8s_raw(&self) -> Option<&'a str> {
+        self.data.k8s_raw.as_deref()
+    }
+
+    /// Matrix dimensions, in declaration order.
+    pub fn matrix(&self) -> &'a Matrix {
+        &self.data.matrix
+    }
+
+    /// Returns true if this is a gate.
+    pub fn is_gate(&self) -> bool {
+        self.data.gate.is_some()
+    }
+
+    /// Returns true if this is a review node.
+    pub fn is_review(&self) -> bool {
+        self.data.kind == NodeKind::Review
+    }
+
+    /// Returns true if the task opted out of pipeline defaults with `bare`.
+    pub fn is_bare(&self) -> bool {
+        self.data.bare
+    }
+}
+
+/// A borrowed view of a task's mount.
+#[derive(Clone, Copy)]
+pub struct MountView<'a> {
+    mount: &'a Mount,
+}
+
+impl<'a> MountView<'a> {
+    /// Resource ID (e.g. `src:.` or a cache name).
+    pub fn resource(&self) -> &'a str {
+        &self.mount.resource
+    }
+
+    /// Path inside the container.
+    pub fn path(&self) -> &'a str {
+        &self.mount.path
+    }
+
+    /// Mount type: `"directory"`, `"cache"`, `"git"` or `"file"`.
+    pub fn mount_type(&self) -> &'a str {
+        &self.mount.mount_type
+    }
+    /// How concurrent tasks share a cache mount; `Shared` for other mounts.
+    pub fn sharing(&self) -> CacheSharing {
+        self.mount.sharing
+    }
+}
+
+/// A borrowed view of a pipeline resource.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResourceView<'a> {
+    /// A directory created with `dir()` or `dir_named()`.
+    Directory {
+        /// Host path.
+        path: &'a str,
+        /// Glob filters.
+        globs: &'a [String],
+        /// Name given with `dir_named()`.
+        name: Option<&'a str>,
+        /// Whether the ID hashes the globs, from `hash_dir_globs()`.
+        hash_globs: bool,
+    },
+    /// A cache volume created with `cache()`.
+    Cache {
+        /// Cache name.
+        name: &'a str,
+        /// Sharing key set with `cache_with_key`.
+        key: Option<&'a str>,
+        /// Files whose contents the key is derived from.
+        key_files: &'a [String],
+        /// Which runs share the cache.
+        scope: Option<CacheScope>,
+        /// Where a run without a cache of its own scope starts from.
+        fallback: Option<CacheScope>,
+        /// Size limit, e.g. `10Gi`.
+        max_size: Option<&'a str>,
+        /// Which entries to evict first.
+        eviction: Option<Eviction>,
+    },
+    /// A git repository created with `git()` and mounted with `mount_git()`.
+    Git {
+        /// Repository URL.
+        url: &'a str,
+        /// Branch, tag or commit checked out.
+        reference: Option<&'a str>,
+        /// Clone depth, if shallow.
+        depth: Option<u32>,
+    },
+    /// A downloaded file created with `http()` and mounted with
+    /// `mount_file()`.
+    Http {
+        /// Download URL.
+        url: &'a str,
+        /// Expected SHA-256 digest.
+        sha256: Option<&'a str>,
+        /// Whether the archive is unpacked.
+        unpack: bool,
+    },
+    /// A workspace file created with `file()` or `file_named()`.
+    File {
+        /// Host path.
+        path: &'a str,
+        /// Name given with `file_named()`.
+        name: Option<&'a str>,
+    },
+}
+
+impl ResourceView<'_> {
+    /// Options for [`Storage::create_volume`](crate::target::Storage::create_volume)
+    /// when creating a cache's volume, or None for other resources.
+    pub fn volume_options(&self) -> Option<VolumeOptions> {
+        match self {
+            ResourceView::Cache {
+                max_size, eviction, ..
+            } => Some(VolumeOptions {
+                max_size: max_size.map(str::to_string),
+                eviction: *eviction,
+                ..Default::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Resource ID, as referenced by [`MountView::resource`].
+    pub fn id(&self) -> String {
+        match self {
+            ResourceView::Directory {
+                path,
+                globs,
+                name,
+                hash_globs,
+            } => Directory {
+                path: path.to_string(),
+                globs: globs.to_vec(),
+                name: name.map(str::to_string),
+                hash_globs: *hash_globs,
+            }
+            .id(),
+            ResourceView::Cache { name, .. } => name.to_string(),
+            ResourceView::Git {
+                url,
+                reference,
+                depth,
+            } => GitSource {
+                url: url.to_string(),
+                branch: reference.map(str::to_string),
+                tag: None,
+                commit: None,
+                depth: *depth,
+            }
+            .id(),
+            ResourceView::Http { url, .. } => format!("http:{}", url),
+            ResourceView::File { path, name } => FileSource {
+                path: path.to_string(),
+                name: name.map(str::to_string),
+            }
+            .id(),
+        }
+    }
+}
+
+impl Pipeline {
+    /// Iterates over the tasks, gates and review nodes in declaration order.
+    pub fn iter_tasks(&self) -> impl Iterator<Item = TaskView<'_>> {
+        self.tasks.iter().map(move |data| TaskView {
+            pipeline: self,
+            data,
+        })
+    }
+
+    /// Iterates over directories, caches, git sources, http files, then
+    /// workspace files, in declaration order, as emitted under `resources`.
+    pub fn resources(&self) -> impl Iterator<Item = ResourceView<'_>> {
+        let dirs = self
+            .directories()
+            .into_iter()
+            .map(|d| ResourceView::Directory {
+                path: &d.path,
+                globs: &d.globs,
+                name: d.name.as_deref(),
+                hash_globs: d.hash_globs,
+            });
+        let caches = self.caches().into_iter().map(|c| ResourceView::Cache {
+            name: &c.name,
+            key: c.key.as_deref(),
+            key_files: &c.key_files,
+            scope: c.scope,
+            fallback: c.fallback,
+            max_size: c.max_size.as_deref(),
+            eviction: c.eviction,
+        });
+        let git = self.git_sources.iter().map(|g| ResourceView::Git {
+            url: &g.url,
+            reference: g.reference(),
+            depth: g.depth,
+        });
+        let http = self.http_files.iter().map(|h| ResourceView::Http {
+            url: &h.url,
+            sha256: h.sha256.as_deref(),
+            unpack: h.unpack,
+        });
+        let files = self.files.iter().map(|f| ResourceView::File {
+            path: &f.path,
+            name: f.name.as_deref(),
+        });
+        dirs.chain(caches).chain(git).chain(http).chain(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Condition;
+
+    fn configured() -> Pipeline {
+        let mut p = Pipeline::with_k8s_defaults(K8sOptions {
+            memory: Some("2Gi".to_string()),
+            cpu: Some("1".to_string()),
+            ..Default::default()
+        });
+        let src = p.dir(".");
+        let registry = p.cache("cargo-registry");
+        let _ = p.task("lint").run("cargo clippy");
+        let _ = p
+            .task("test")
+            .container("rust:1.75")
+            .run("cargo test")
+            .mount(&src, "/src")
+            .mount_cache(&registry, "/usr/local/cargo/registry")
+            .workdir("/src")
+            .env("RUST_LOG", "debug")
+            .inputs(&["**/*.rs"])
+            .output("report", "target/report.xml")
+            .when_cond(Condition::branch("main"))
+            .secret("TOKEN")
+            .secret_from("DB", SecretRef::from_env("DATABASE_URL"))
+            .service("postgres:15", "db")
+            .retry(2)
+            .timeout(300)
+            .k8s(K8sOptions {
+                memory: Some("8Gi".to_string()),
+                ..Default::default()
+            })
+            .matrix("os", &["linux", "macos"])
+            .after(&["lint"]);
+        p
+    }
+
+    #[test]
+    fn test_task_view_getters() {
+        let p = configured();
+        let t = p.iter_tasks().find(|t| t.name() == "test").unwrap();
+
+        assert_eq!(t.command(), Some("cargo test"));
+        assert_eq!(t.container(), Some("rust:1.75"));
+        assert_eq!(t.workdir(), Some("/src"));
+        assert_eq!(t.env().get("RUST_LOG").map(String::as_str), Some("debug"));
+        assert_eq!(t.inputs(), ["**/*.rs"]);
+        assert_eq!(
+            t.outputs().get("report").map(String::as_str),
+            Some("target/report.xml")
+        );
+        assert_eq!(t.depends_on(), ["lint"]);
+        assert_eq!(t.condition(), Some("branch == 'main'"));
+        assert_eq!(t.secrets(), ["TOKEN"]);
+        assert_eq!(t.secret_refs()[0].name, "DB");
+        assert_eq!(t.secret_refs()[0].key, "DATABASE_URL");
+        assert_eq!(t.services().collect::<Vec<_>>(), [("postgres:15", "db")]);
+        assert_eq!(t.retry(), Some(2));
+        assert_eq!(t.timeout(), Some(300));
+        assert_eq!(t.matrix()["os"], ["linux", "macos"]);
+        assert!(!t.is_gate());
+        assert!(!t.is_review());
+
+        let mounts: Vec<_> = t
+            .mounts()
+            .map(|m| (m.resource(), m.path(), m.mount_type()))
+            .collect();
+        assert_eq!(
+            mounts,
+            [
+                ("src:.", "/src", "directory"),
+                ("cargo-registry", "/usr/local/cargo/registry", "cache"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_task_view_k8s_matches_emit() {
+        let p = configured();
+        let mut buf = Vec::new();
+        p.emit_to(&mut buf).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        for t in p.iter_tasks() {
+            let emitted = json["tasks"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|e| e["name"] == t.name())
+                .unwrap();
+            let k8s = t.k8s().unwrap();
+            assert_eq!(emitted["k8s"]["memory"].as_str(), k8s.memory.as_deref());
+            assert_eq!(emitted["k8s"]["cpu"].as_str(), k8s.cpu.as_deref());
+        }
+        let test = p.iter_tasks().find(|t| t.name() == "test").unwrap();
+        assert_eq!(test.k8s().unwrap().memory.as_deref(), Some("8Gi"));
+        assert_eq!(test.k8s().unwrap().cpu.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn test_resources_view() {
+        let p = configured();
+        let resources: Vec<_> = p.resources().collect();
+
+        assert_eq!(resources.len(), 2);
+        assert_eq!(
+            resources[0],
+            ResourceView::Directory {
+                path: ".",
+                globs: &[],
+                name: None,
+                hash_globs: false,
+            }
+        );
+        assert_eq!(
+            resources[1],
+            ResourceView::Cache {
+                name: "cargo-registry",
+                key: None,
+                key_files: &[],
+                scope: None,
+                fallback: None,
+                max_size: None,
+                eviction: None,
+            }
+        );
+        let ids: Vec<_> = resources.iter().map(ResourceView::id).collect();
+        assert_eq!(ids, ["src:.", "cargo-registry"]);
+    }
+
+    #[test]
+    fn test_task_view_gate_and_review() {
+        let mut p = Pipeline::new();
+        let _ = p.gate("approve");
+        let _ = p.review("audit").agent("claude");
+
+        let views: Vec<_> = p.iter_tasks().collect();
+        assert!(views[0].is_gate());
+        assert_eq!(views[0].command(), None);
+        assert!(views[1].is_review());
+    }
+}
@@ -0,0 +1,144 @@
+use sykli::{
+    Backoff, CacheScope, CacheSharing, Condition, Eviction, K8sOptions, OutputOptions, Pipeline,
+    PublishDest, RetryPolicy, SecretRef,
+};
+
+fn main() {
+    let mut p = Pipeline::new();
+    p.max_parallel(4);
+
+    let src = p.dir(".");
+    let registry = p.cache_with_key("cargo-registry", "cargo-{branch}")
+        .key_files(&["Cargo.lock"])
+        .scope(CacheScope::Branch)
+        .fallback_scope(CacheScope::Global)
+        .max_size("2Gi")
+        .eviction(Eviction::Lru);
+    let fixtures = p.git("https://github.com/acme/fixtures.git").branch("main").depth(1);
+    let toolchain = p
+        .http("https://example.com/tool.tar.gz")
+        .sha256("0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef")
+        .unpack(true);
+    let config = p.file("ci/config.toml");
+
+    p.template("rust")
+        .container("rust:1.80")
+        .mount(&src, "/src")
+        .workdir("/src")
+        .env("CARGO_TERM_COLOR", "always")
+        .retry_policy(RetryPolicy {
+            max: 2,
+            backoff: Backoff::Fixed { secs: 5 },
+            retry_on_exit_codes: None,
+            retry_on_timeout: true,
+        })
+        .k8s(K8sOptions {
+            memory: Some("4Gi".to_string()),
+            ..Default::default()
+        });
+    let rust = p.get_template("rust").unwrap().clone();
+
+    p.task("lint").from(&rust).run("cargo clippy").describe("Run clippy").tags(&["check"]);
+
+    p.task("image")
+        .container_from_dockerfile(&src, "Dockerfile")
+        .entrypoint("/bin/sh")
+        .user("1000:1000")
+        .env_file_lazy(".env")
+        .step("make deps")
+        .step("make image");
+
+    p.task("build")
+        .container("rust:1.80")
+        .workdir("/src")
+        .mount(&src, "/src")
+        .mount_cache_with(&registry, "/usr/local/cargo/registry", CacheSharing::Locked)
+        .mount_git(&fixtures, "/fixtures")
+        .mount_file(&toolchain, "/opt/tool")
+        .mount_file_res(&config, "/etc/config.toml")
+        .exec(&["cargo", "build", "--release"])
+        .matrix("os", &["linux", "macos"])
+        .matrix("arch", &["x64", "arm64"])
+        .matrix_exclude(&[("os", "linux"), ("arch", "arm64")])
+        .matrix_include(&[("os", "windows"), ("arch", "x64")])
+        .output_glob("logs", "target/*.log")
+        .output_dir("docs", "target/doc")
+        .output_with(
+            "binary",
+            "target/release/app",
+            OutputOptions {
+                retention_days: Some(7),
+                compress: true,
+                required: true,
+            },
+        )
+        .publish_output(
+            "binary",
+            PublishDest::GithubRelease {
+                repo: "acme/app".to_string(),
+                tag_expr: "{tag}".to_string(),
+                token: SecretRef::from_env("GITHUB_TOKEN"),
+            },
+        )
+        .estimated_duration(300)
+        .concurrency_group("build-{branch}")
+        .cancel_in_progress(true)
+        .after(&["lint"]);
+
+    p.task("package")
+        .run("tar czf app.tgz app")
+        .input_from_matrix("build", &[("os", "macos"), ("arch", "x64")], "binary", "app")
+        .after_matrix("build", &[("os", "linux")])
+        .publish_output(
+            "bundle",
+            PublishDest::HttpPut {
+                url_template: "https://artifacts.example.com/{branch}/{file}".to_string(),
+                auth: Some(SecretRef::from_env("ARTIFACT_TOKEN")),
+            },
+        )
+        .output("bundle", "app.tgz");
+
+    p.task("test")
+        .run("cargo test")
+        .service_with("postgres:15", "db", |s| {
+            s.env("POSTGRES_PASSWORD", "test")
+                .port(5432)
+                .command("postgres -c fsync=off")
+                .ready_tcp(5432)
+                .ready_timeout(30)
+        })
+        .secret_opt("CODECOV_TOKEN")
+        .secret_file(
+            "KUBECONFIG",
+            SecretRef::from_k8s("ci/kubeconfig#config").no_mask(),
+            "/run/secrets/kubeconfig",
+        )
+        .secret_from("AWS_KEY", SecretRef::from_aws("ci/deploy#key"))
+        .secret_from("GCP_KEY", SecretRef::from_gcp("projects/acme/secrets/deploy"))
+        .secret_from("SOPS_KEY", SecretRef::from_sops("ci/secrets.enc.yaml#deploy.token").optional())
+        .secret_template(
+            "/run/secrets/aws",
+            "[default]\naws_access_key_id = {{key_id}}\n",
+            &[("key_id", SecretRef::from_vault("secret/data/aws#key_id"))],
+        )
+        .allow_failure(true)
+        .after(&["build"]);
+
+    p.task("deploy")
+        .run("./deploy.sh")
+        .retry_policy(RetryPolicy {
+            max: 3,
+            backoff: Backoff::Exponential {
+                base_secs: 2,
+                max_secs: 30,
+            },
+            retry_on_exit_codes: Some(vec![75]),
+            retry_on_timeout: false,
+        })
+        .when_cond(Condition::manual())
+        .after(&["package", "test"]);
+
+    p.task("notify").run("./notify.sh").on_failure_of(&["build"]);
+
+    p.emit();
+}